@@ -0,0 +1,92 @@
+// A bare-bones browser frontend for the emulator core. It's built as a wasm
+// module that exports a handful of plain functions, and index.html drives it
+// from JavaScript, copying the ROM in and the finished frames out through the
+// module's memory. No bindings crate needed.
+//
+// Build it with:
+//   cargo build --release --example web --target wasm32-unknown-unknown --no-default-features
+//
+// See README.md in this folder for how to run it.
+
+use std::cell::RefCell;
+
+use nes_emulator::core::{Input, Nes, NES_SCREEN_BUF_SIZE};
+
+thread_local! {
+    static NES: RefCell<Option<Nes>> = const { RefCell::new(None) };
+    static ROM_BUF: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Makes room for a ROM of the given size and returns where JavaScript should
+/// copy it to before calling nes_load
+#[no_mangle]
+pub extern "C" fn nes_rom_buffer(len: usize) -> *mut u8 {
+    ROM_BUF.with_borrow_mut(|buf| {
+        buf.clear();
+        buf.resize(len, 0);
+        buf.as_mut_ptr()
+    })
+}
+
+/// Loads the ROM that was copied into the ROM buffer. Returns whether it
+/// could be loaded.
+#[no_mangle]
+pub extern "C" fn nes_load() -> bool {
+    let mut nes = Nes::default();
+    // Audio isn't hooked up in this example, so the samples are thrown away
+    nes.set_block_audio_samples(true);
+
+    let loaded = ROM_BUF.with_borrow(|rom| nes.load_cart_bytes(rom, "web")).is_ok();
+
+    if loaded {
+        NES.with_borrow_mut(|slot| *slot = Some(nes));
+    }
+
+    loaded
+}
+
+/// Runs the NES for one frame
+#[no_mangle]
+pub extern "C" fn nes_frame() {
+    NES.with_borrow_mut(|nes| {
+        if let Some(nes) = nes {
+            nes.cycle_until_frame();
+            nes.swap_screen_buffers();
+        }
+    });
+}
+
+/// Sets which buttons player 1 or 2 (0 or 1) is holding. Bits from lowest to
+/// highest are A, B, Select, Start, Up, Down, Left, Right.
+#[no_mangle]
+pub extern "C" fn nes_set_buttons(player: usize, buttons: u8) {
+    NES.with_borrow_mut(|nes| {
+        if let Some(nes) = nes {
+            nes.set_controller_state(player, Input::from_bits(buttons));
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn nes_reset() {
+    NES.with_borrow_mut(|nes| {
+        if let Some(nes) = nes {
+            nes.reset();
+        }
+    });
+}
+
+/// Pointer to the last finished frame, 256x240 RGBA. Only valid until the next
+/// call into the module.
+#[no_mangle]
+pub extern "C" fn nes_screen() -> *const u8 {
+    NES.with_borrow(|nes| match nes {
+        Some(nes) => nes.frame().pixels().as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn nes_screen_len() -> usize {
+    NES_SCREEN_BUF_SIZE
+}
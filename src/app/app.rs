@@ -1,101 +1,116 @@
 use gilrs::{GamepadId, Gilrs};
-use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
 use rodio::Sink;
 use winit::dpi::PhysicalSize;
-use winit::event::{ElementState, KeyEvent, WindowEvent};
+use winit::event::{ElementState, KeyEvent, Modifiers, MouseButton, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
 use winit::keyboard::{KeyCode, PhysicalKey};
-use winit::window::Window;
+use winit::window::{Fullscreen, Window};
 use winit::{application::ApplicationHandler, window::WindowId};
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
-
-use crate::app::draw::DEFAULT_DEBUG_PAL;
-use crate::system::controller::{ControllerButton, ControllerReadState, ControllerUpdate, NesController};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::app::draw::DebugPalette;
+use crate::cartridge::cartridge::Cartridge;
+use crate::cartridge::compat::{CompatDatabase, CompatEntry};
+use crate::cartridge::hash::{self, RomHashes};
+use crate::cartridge::patch;
+use crate::cli::{CaptureView, Command, CropMode, Region, Rotation, SyncMode};
+use crate::system::apu::DEFAULT_TARGET_QUEUE_DEPTH;
+use crate::system::apu_util::NesChannel;
+use crate::system::controller::{ControllerButton, ControllerReadState, ControllerUpdate, ExpansionDevice, NesController};
+use crate::system::breakpoints::WriteBreak;
+use crate::system::events::{EventKind, NesEvent, Subscription};
+use crate::system::memory_region::MemoryRegion;
+use crate::system::movie::Movie;
 use crate::system::nes::Nes;
+use crate::system::sample_ring::SampleQueue;
+use crate::system::savestate::SaveState;
+use crate::system::video_filter::{FilterChain, VideoFilter};
 use crate::RuntimeConfig;
 
+use super::assets::{Asset, AssetStore};
+use super::capture::{self, CaptureSettings};
+use super::config::{ConfigFile, GameProfile, SavedMapping, SkipIntro, CONFIG_PATH};
+use super::crash::{self, CrashNotice};
+use super::rom_hashes::{RomHashCache, ROM_HASH_CACHE_PATH};
+use super::presenter::{Presenter, WindowPresenter};
+use super::shader::GpuShader;
+use super::battery::{BatterySaves, BATTERY_SAVE_DIR};
+use super::save_slots::{SaveSlots, SAVE_STATE_DIR};
+use super::shutdown;
+use super::thumbnails::{ThumbnailCache, THUMBNAIL_DIR};
+use super::title::{TitleInfo, WindowTitle};
 use super::util::{ControllerMapping, ControllerSprite, MenuSound, SliderSprite};
+use super::debug_cache::DebugViewCache;
 use super::draw::{self, draw_paused_menu_bg};
+use super::ghost::Ghost;
+use super::menu::MenuScreen;
+use super::overlay::{OsdMessage, OverlayLayers, OverlayTarget};
+use super::perf::PerfHud;
+use super::text::{fill, text, wrap, Text};
+use super::widgets::{Widget, WidgetEvent, WidgetId, WidgetList};
 
 const MICROS_PER_FRAME: u128 = 1_000_000 / 60;
-const MIN_SAMPLES_THRESH: usize = 600;
+// Where memory dumps are written to and loaded back from (see memory_region)
+const DUMP_DIR: &str = "dumps";
+// Most frames run in one go when audio sync is catching up after a hitch
+const AUDIO_SYNC_MAX_FRAMES: usize = 4;
 const VOLUME_CONTROL_SPEED: f32 = 0.05;
+// How much the volume moves with shift held, in percent
+const VOLUME_FINE_STEP_PERCENT: usize = 1;
 const MAX_VOLUME: f32 = 2.0;
-
-#[derive(Default, Clone, Copy, PartialEq)]
-pub enum PauseMenuItem {
-    #[default]
-    Continue,
-    ControllerMap,
-    Volume,
-    NoLimit,
-    Quit,
-}
-
-impl PauseMenuItem {
-    /// Selects the next menu item, wrapping if the last one is selected and wrap
-    /// is true. Returns a bool reporting whether a new item was selected.
-    fn go_next(&mut self, wrap: bool) -> bool {
-        let new_val = match self {
-            Self::Continue => Self::ControllerMap,
-            Self::ControllerMap => Self::Volume,
-            Self::Volume => Self::NoLimit,
-            Self::NoLimit => Self::Quit,
-            Self::Quit => {
-                if wrap {
-                    Self::Continue
-                } else {
-                    Self::Quit
-                }
-            },
-        };
-
-        let new_val_chosen = new_val != *self;
-        *self = new_val;
-
-        new_val_chosen
-    }
-
-    /// Selects the previous menu item, wrapping if the first one is selected and wrap
-    /// is true. Returns a bool reporting whether a new item was selected.
-    fn go_prev(&mut self, wrap: bool) -> bool {
-        let new_val = match self {
-            Self::Continue => {
-                if wrap {
-                    Self::Quit
-                } else {
-                    Self::Continue
-                }
-            },
-            Self::ControllerMap => Self::Continue,
-            Self::Volume => Self::ControllerMap,
-            Self::NoLimit => Self::Volume,
-            Self::Quit => Self::NoLimit,
-        };
-
-        let new_val_chosen = new_val != *self;
-        *self = new_val;
-
-        new_val_chosen
-    }
-}
+const DEFAULT_VOLUME_PERCENT: f32 = 0.25;
+// Emulation speed limits, in percent of normal speed
+const MIN_SPEED_PERCENT: usize = 25;
+const MAX_SPEED_PERCENT: usize = 400;
+const SPEED_STEP_PERCENT: usize = 25;
+// Left stick settings, in percent (see ControllerMapping::stick_directions)
+const MAX_STICK_DEADZONE_PERCENT: usize = 90;
+const STICK_STEP_PERCENT: usize = 5;
+const FILTER_SETTING_STEP_PERCENT: usize = 5;
+// How long a game's known issues stay up when it's loaded, and how many
+// characters fit on a line of them
+const KNOWN_ISSUES_DURATION: Duration = Duration::from_secs(8);
+const KNOWN_ISSUES_WIDTH: usize = 34;
 
 pub struct PauseMenu {
-    pub selected: PauseMenuItem,
-
-    pub mapping_controller: bool,
-    pub map_controller1: bool,
-    pub map_controller2: bool,
-    pub player1_map_selected: bool,
+    pub screen: MenuScreen,
+
+    // The pages of the menu (see widgets.rs)
+    pub main: WidgetList,
+    pub controller_select: WidgetList,
+    pub controller_mapping: WidgetList,
+    pub volume: WidgetList,
+    pub channels: WidgetList,
+    pub cart_info: WidgetList,
+    pub video_filters: WidgetList,
+    pub filter_settings: WidgetList,
+
+    // Which video filter the settings page is for
+    pub settings_filter: usize,
+
+    // Which player's controller is being mapped
+    pub mapping_player: usize,
     pub controller_state: NesController,
-    pub controller_read: ControllerReadState,
     pub controller_sprite: ControllerSprite,
 
-    pub setting_volume: bool,
     pub volume_percent: f32,
+    // Silences everything without losing the volume setting
+    pub muted: bool,
     pub slider_sprite: SliderSprite,
 
+    pub speed_percent: usize,
+
+    // Menu buttons held down on each player's gamepad, so holding one down
+    // only counts as one press
+    pub gamepad_held: [NesController; 2],
+
+    // Whether the current settings are saved as a profile for this game
+    pub has_game_profile: bool,
+
     pub move_sound: MenuSound,
     pub select_sound: MenuSound,
     pub reject_sound: MenuSound,
@@ -103,28 +118,305 @@ pub struct PauseMenu {
 }
 
 impl PauseMenu {
-    fn new() -> Self {
+    fn new(assets: &AssetStore, filters: &FilterChain) -> Self {
+        let main = WidgetList::new(text(Text::PausedTitle))
+            .with(Widget::button(WidgetId::Continue, text(Text::Continue)))
+            .with(Widget::button(WidgetId::ControllerMap, text(Text::MapControllerInputs)))
+            .with(Widget::button(WidgetId::Volume, text(Text::Volume)))
+            .with(Widget::button(WidgetId::AudioChannels, text(Text::AudioChannels)))
+            .with(Widget::slider(WidgetId::Speed, text(Text::Speed), MIN_SPEED_PERCENT, MAX_SPEED_PERCENT, SPEED_STEP_PERCENT, "%"))
+            .with(Widget::choice(WidgetId::Expansion, text(Text::ExpansionPort), &ExpansionDevice::ALL.map(|device| text(Text::Expansion(device)))))
+            .with(Widget::button(WidgetId::CartInfo, text(Text::CartInfo)))
+            .with(Widget::button(WidgetId::VideoFilters, text(Text::VideoFilters)))
+            .with(Widget::toggle(WidgetId::GameProfile, text(Text::GameProfile)))
+            .with(Widget::toggle(WidgetId::NoLimit, text(Text::NoLimit)))
+            .with(Widget::toggle(WidgetId::AudioSync, text(Text::AudioSync)))
+            .with(Widget::button(WidgetId::RetryFromState, text(Text::RetryFromState)))
+            .with(Widget::button(WidgetId::Quit, text(Text::Quit)));
+
+        let mut controller_select = WidgetList::new(text(Text::ControllerMappingsTitle))
+            .with(Widget::button(WidgetId::Player(0), &fill(Text::Player, &["1"])))
+            .with(Widget::button(WidgetId::Player(1), &fill(Text::Player, &["2"])));
+        for (player_id, number) in ["1", "2"].into_iter().enumerate() {
+            controller_select = controller_select
+                .with(Widget::slider(WidgetId::StickDeadzone(player_id), &fill(Text::StickDeadzone, &[number]),
+                    0, MAX_STICK_DEADZONE_PERCENT, STICK_STEP_PERCENT, "%"))
+                .with(Widget::slider(WidgetId::DiagonalBias(player_id), &fill(Text::DiagonalBias, &[number]),
+                    0, 100, STICK_STEP_PERCENT, "%"));
+        }
+
+        // In the same order the buttons are read from the controller
+        let mut controller_mapping = WidgetList::new(&fill(Text::Player, &["1"]));
+        let mut read_state = ControllerReadState::new();
+        while !read_state.finished() {
+            let button = read_state.button();
+            controller_mapping = controller_mapping.with(Widget::key_capture(WidgetId::MapButton(button), text(Text::Button(button))));
+            read_state = read_state.next();
+        }
+
+        let volume = WidgetList::new(text(Text::SettingVolumeTitle))
+            .with(Widget::slider(WidgetId::Volume, text(Text::Volume), 0, 100, (VOLUME_CONTROL_SPEED * 100.0) as usize, "%")
+                .with_fine_step(VOLUME_FINE_STEP_PERCENT));
+
+        let mut channels = WidgetList::new(text(Text::AudioChannels))
+            .with_hint(text(Text::AudioChannelsHint));
+        for (i, channel) in NesChannel::ALL.iter().enumerate() {
+            channels = channels.with(Widget::toggle(WidgetId::Channel(i), text(Text::Channel(*channel))));
+        }
+
+        // The info itself is the hint, filled in by sync_menu
+        let cart_info = WidgetList::new(text(Text::CartInfo))
+            .with(Widget::button(WidgetId::Back, text(Text::Back)));
+
+        let mut video_filters = WidgetList::new(text(Text::VideoFilters))
+            .with_hint(text(Text::VideoFiltersHint));
+        for i in 0..filters.len() {
+            video_filters = video_filters.with(Widget::toggle(WidgetId::VideoFilter(i), filters.filter(i).name()));
+        }
+        video_filters = video_filters.with(Widget::choice(WidgetId::GpuShader, text(Text::GpuShaderLabel),
+            &GpuShader::ALL.map(|shader| text(Text::GpuShader(shader)))));
+
         Self {
-            selected: PauseMenuItem::Continue,
+            screen: MenuScreen::Main,
+
+            main,
+            controller_select,
+            controller_mapping,
+            volume,
+            channels,
+            cart_info,
+            video_filters,
+            // Made for a filter when it's opened
+            filter_settings: WidgetList::new(""),
+
+            settings_filter: 0,
 
-            mapping_controller: false,
-            map_controller1: false,
-            map_controller2: false,
-            player1_map_selected: true,
+            mapping_player: 0,
             controller_state: NesController::default(),
-            controller_read: ControllerReadState::new(),
-            controller_sprite: ControllerSprite::new(),
+            controller_sprite: ControllerSprite::new(assets),
+
+            volume_percent: DEFAULT_VOLUME_PERCENT,
+            muted: false,
+            slider_sprite: SliderSprite::new(assets, 20, 200, 26),
+
+            speed_percent: 100,
+
+            gamepad_held: [NesController::new(); 2],
+
+            has_game_profile: false,
+
+            move_sound: MenuSound::load(assets, Asset::MoveSound),
+            select_sound: MenuSound::load(assets, Asset::SelectSound),
+            reject_sound: MenuSound::load(assets, Asset::RejectSound),
+            pause_sound: MenuSound::load(assets, Asset::PauseSound),
+        }
+    }
+
+    /// The page of the menu that's showing
+    pub fn page(&self) -> &WidgetList {
+        match self.screen {
+            MenuScreen::Main => &self.main,
+            MenuScreen::ControllerSelect => &self.controller_select,
+            MenuScreen::ControllerMapping => &self.controller_mapping,
+            MenuScreen::Volume => &self.volume,
+            MenuScreen::AudioChannels => &self.channels,
+            MenuScreen::CartInfo => &self.cart_info,
+            MenuScreen::VideoFilters => &self.video_filters,
+            MenuScreen::FilterSettings => &self.filter_settings,
+        }
+    }
+
+    pub fn page_mut(&mut self) -> &mut WidgetList {
+        match self.screen {
+            MenuScreen::Main => &mut self.main,
+            MenuScreen::ControllerSelect => &mut self.controller_select,
+            MenuScreen::ControllerMapping => &mut self.controller_mapping,
+            MenuScreen::Volume => &mut self.volume,
+            MenuScreen::AudioChannels => &mut self.channels,
+            MenuScreen::CartInfo => &mut self.cart_info,
+            MenuScreen::VideoFilters => &mut self.video_filters,
+            MenuScreen::FilterSettings => &mut self.filter_settings,
+        }
+    }
+
+    /// Shows another page of the menu, with the cursor at the top
+    pub fn open(&mut self, screen: MenuScreen) {
+        self.screen = screen;
+        self.page_mut().reset_focus();
+    }
+
+    /// Shows the settings page for a video filter
+    fn open_filter_settings(&mut self, index: usize, filter: &dyn VideoFilter) {
+        let mut page = WidgetList::new(filter.name());
+        for (i, setting) in filter.settings().iter().enumerate() {
+            page = page.with(Widget::slider(WidgetId::FilterSetting(i), setting.name, 0, 100, FILTER_SETTING_STEP_PERCENT, "%")
+                .with_fine_step(1));
+        }
+
+        self.filter_settings = page;
+        self.settings_filter = index;
+        self.screen = MenuScreen::FilterSettings;
+    }
+
+    /// The NES button a gamepad button is being asked for, while mapping
+    pub fn mapping_button(&self) -> Option<ControllerButton> {
+        match self.controller_mapping.focused().id {
+            WidgetId::MapButton(button) if self.controller_mapping.capturing() => Some(button),
+            _ => None,
+        }
+    }
+}
+
+/// How much the sink amplifies the sound at a volume setting
+fn output_gain(volume_percent: f32, muted: bool) -> f32 {
+    if muted {
+        0.0
+    } else {
+        MAX_VOLUME * volume_percent
+    }
+}
+
+// The text under the volume slider, saying how loud that actually is
+fn volume_hint(volume_percent: f32, muted: bool) -> String {
+    let gain = output_gain(volume_percent, muted);
 
-            setting_volume: false,
-            volume_percent: 0.25,
-            slider_sprite: SliderSprite::new(20, 200, 26),
+    if muted {
+        String::from(text(Text::Muted))
+    } else if gain <= 0.0 {
+        fill(Text::OutputDecibels, &[text(Text::Silent)])
+    } else {
+        fill(Text::OutputDecibels, &[&format!("{:+.1}", 20.0 * gain.log10())])
+    }
+}
+
+// The text on the cartridge info page: the header's details, then the ROM's
+// hashes, for pasting into a bug report
+fn cart_info_hint(nes: &Nes) -> String {
+    let Some(info) = nes.cart_info() else {
+        return String::new();
+    };
+
+    let size = |bytes: usize| if bytes.is_multiple_of(1024) { format!("{} KiB", bytes / 1024) } else { format!("{bytes} B") };
+    let yes_no = |val: bool| String::from(text(if val { Text::Yes } else { Text::No }));
+
+    let (_, mapper_name) = nes.mapper_info();
+    let mapper = match info.submapper_num {
+        Some(submapper) if submapper > 0 => format!("{}.{submapper} ({mapper_name})", info.mapper_num),
+        _ => format!("{} ({mapper_name})", info.mapper_num),
+    };
+
+    let mut rows = vec![
+        (text(Text::CartFormat), String::from(if info.nes2 { "NES 2.0" } else { "iNES" })),
+        (text(Text::MapperPanel), mapper),
+        ("PRG ROM", size(info.prg_rom_size)),
+        ("CHR ROM", if info.chr_rom_size > 0 { size(info.chr_rom_size) } else { String::from(text(Text::ChrRam)) }),
+    ];
+    if let Some(prg_ram_size) = info.prg_ram_size {
+        rows.push(("PRG RAM", size(prg_ram_size)));
+    }
+    rows.extend([
+        (text(Text::CartMirroring), String::from(text(Text::Mirror(info.mirroring)))),
+        (text(Text::CartBattery), yes_no(info.battery)),
+        (text(Text::CartRegionLabel), String::from(text(info.region.map_or(Text::Unknown, Text::CartRegion)))),
+        ("CRC32", format!("{:08X}", nes.rom_crc32())),
+    ]);
+
+    let label_width = rows.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0) + 1;
+
+    let mut hint = String::new();
+    for (label, value) in rows {
+        hint.push_str(&format!("{label:<label_width$}{value}\n"));
+    }
+
+    // Too long to go after a label
+    hint.push_str("SHA-1\n");
+    match nes.rom_hashes() {
+        Some(hashes) => hint.push_str(&RomHashes::sha1_hex(&hashes.rom_sha1)),
+        None => hint.push_str(text(Text::RomHashing)),
+    }
+
+    hint
+}
+
+// The on screen message with a game's known issues, one note after another
+// with each wrapped to fit across the game
+fn known_issues_notice(entry: &CompatEntry) -> String {
+    let mut notice = fill(Text::KnownIssues, &[text(Text::CompatStatus(entry.status))]);
 
-            move_sound: MenuSound::new("src/app/assets/sounds/move.wav"),
-            select_sound: MenuSound::new("src/app/assets/sounds/select.wav"),
-            reject_sound: MenuSound::new("src/app/assets/sounds/reject.wav"),
-            pause_sound: MenuSound::new("src/app/assets/sounds/pause.wav"),
+    for note in &entry.notes {
+        for (i, line) in wrap(note, KNOWN_ISSUES_WIDTH - 2).iter().enumerate() {
+            notice.push_str(if i == 0 { "\n- " } else { "\n  " });
+            notice.push_str(line);
         }
     }
+
+    notice
+}
+
+fn print_known_issues(entry: &CompatEntry) {
+    println!("Known issues with this game ({}):", text(Text::CompatStatus(entry.status)));
+    for note in &entry.notes {
+        println!("  {note}");
+    }
+}
+
+/// The NES button a key stands for, both in game and in the pause menu
+fn keyboard_button(key: PhysicalKey) -> Option<ControllerButton> {
+    match key {
+        PhysicalKey::Code(KeyCode::KeyZ) => Some(ControllerButton::A),
+        PhysicalKey::Code(KeyCode::KeyX) => Some(ControllerButton::B),
+        PhysicalKey::Code(KeyCode::ShiftRight) => Some(ControllerButton::Select),
+        PhysicalKey::Code(KeyCode::Enter) => Some(ControllerButton::Start),
+        PhysicalKey::Code(KeyCode::ArrowUp) => Some(ControllerButton::Up),
+        PhysicalKey::Code(KeyCode::ArrowDown) => Some(ControllerButton::Down),
+        PhysicalKey::Code(KeyCode::ArrowLeft) => Some(ControllerButton::Left),
+        PhysicalKey::Code(KeyCode::ArrowRight) => Some(ControllerButton::Right),
+        _ => None,
+    }
+}
+
+// The host keys standing in for the Family BASIC keyboard's, in the order the
+// keyboard puts them out (see "ON HOW EXPANSION PORT DEVICES WORK"). Keys are
+// placed where they are on the Family BASIC keyboard where there's a match,
+// so ESC is left of 1, @ is right of P and so on.
+const FAMILY_KEYBOARD_LAYOUT: [[KeyCode; 8]; 9] = [
+    // ] [ RETURN F8 | STOP YEN RSHIFT KANA
+    [KeyCode::Backslash, KeyCode::BracketRight, KeyCode::Enter, KeyCode::F8,
+        KeyCode::End, KeyCode::IntlYen, KeyCode::ShiftRight, KeyCode::AltRight],
+    // ; : @ F7 | ^ - / _
+    [KeyCode::Semicolon, KeyCode::Quote, KeyCode::BracketLeft, KeyCode::F7,
+        KeyCode::Equal, KeyCode::Minus, KeyCode::Slash, KeyCode::IntlRo],
+    // K L O F6 | 0 P , .
+    [KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyO, KeyCode::F6,
+        KeyCode::Digit0, KeyCode::KeyP, KeyCode::Comma, KeyCode::Period],
+    // J U I F5 | 8 9 N M
+    [KeyCode::KeyJ, KeyCode::KeyU, KeyCode::KeyI, KeyCode::F5,
+        KeyCode::Digit8, KeyCode::Digit9, KeyCode::KeyN, KeyCode::KeyM],
+    // H G Y F4 | 6 7 V B
+    [KeyCode::KeyH, KeyCode::KeyG, KeyCode::KeyY, KeyCode::F4,
+        KeyCode::Digit6, KeyCode::Digit7, KeyCode::KeyV, KeyCode::KeyB],
+    // D R T F3 | 4 5 C F
+    [KeyCode::KeyD, KeyCode::KeyR, KeyCode::KeyT, KeyCode::F3,
+        KeyCode::Digit4, KeyCode::Digit5, KeyCode::KeyC, KeyCode::KeyF],
+    // A S W F2 | 3 E Z X
+    [KeyCode::KeyA, KeyCode::KeyS, KeyCode::KeyW, KeyCode::F2,
+        KeyCode::Digit3, KeyCode::KeyE, KeyCode::KeyZ, KeyCode::KeyX],
+    // CTR Q ESC F1 | 2 1 GRPH LSHIFT
+    [KeyCode::ControlLeft, KeyCode::KeyQ, KeyCode::Backquote, KeyCode::F1,
+        KeyCode::Digit2, KeyCode::Digit1, KeyCode::AltLeft, KeyCode::ShiftLeft],
+    // LEFT RIGHT UP CLR-HOME | INS DEL SPACE DOWN
+    [KeyCode::ArrowLeft, KeyCode::ArrowRight, KeyCode::ArrowUp, KeyCode::Home,
+        KeyCode::Insert, KeyCode::Backspace, KeyCode::Space, KeyCode::ArrowDown],
+];
+
+/// The Family BASIC key a host key stands in for, numbered the way
+/// ExpansionInput::keys is
+fn family_keyboard_key(key: PhysicalKey) -> Option<usize> {
+    let PhysicalKey::Code(code) = key else {
+        return None;
+    };
+
+    FAMILY_KEYBOARD_LAYOUT.as_flattened().iter().position(|&family_key| family_key == code)
 }
 
 #[derive(Default, Clone, Copy, PartialEq)]
@@ -134,29 +426,156 @@ pub enum ViewMode {
     Debug,
 }
 
+/// What's shown in the big panel on the right side of the debug view
+#[derive(Clone, Copy, PartialEq, Hash)]
+pub enum DebugPanel {
+    // A 256 byte page of CPU memory, by the high byte of its address
+    Memory(u8),
+    ApuLog,
+    PpuShifters,
+    Mapper,
+    Interrupts,
+    RomInfo,
+    Unsupported,
+}
+
+impl Default for DebugPanel {
+    fn default() -> Self {
+        Self::Memory(0)
+    }
+}
+
+/// Developer overlays drawn over the NES picture
+#[derive(Default, Clone, Copy, PartialEq, Hash)]
+pub struct ScreenOverlays {
+    // Tint the debug view's screen by how recently each tile was written
+    pub write_heatmap: bool,
+    // Mark the scanlines where PPU registers were written mid-frame (needs
+    // the register write assert mode)
+    pub write_markers: bool,
+    // Draw lines where the scroll or nametable changed partway down the frame
+    pub scroll_splits: bool,
+}
+
 pub struct NesApp {
     window: Option<Window>,
-    pixel_buf: Option<Pixels>,
+    // Takes the finished frames to the window, or wherever a host embedding
+    // the app wants them (see presenter.rs)
+    presenter: Option<Box<dyn Presenter>>,
+
+    // Optional second window showing the debug view, so the game can stay
+    // visible at full size while debugging
+    debug_window: Option<Window>,
+    debug_presenter: Option<WindowPresenter>,
+    // What's been drawn into each debug view, so unchanged parts are skipped
+    debug_cache: DebugViewCache,
+    debug_window_cache: DebugViewCache,
     
     audio_sink: Option<Sink>,
-    audio_stream_queue: Option<Arc<Mutex<VecDeque<f32>>>>,
+    audio_stream_queue: Option<SampleQueue>,
+    // Emulation runs ahead of the frame timer if fewer samples than this are queued
+    audio_queue_depth: usize,
+    sync_mode: SyncMode,
 
     nes: Nes,
+    // The app's subscription, for WriteBreak (see events)
+    nes_events: Subscription,
+    // The buttons each player is holding, handed to the NES at the start of
+    // each frame (see Nes::advance_frame)
+    held_input: [NesController; 2],
     paused: bool,
     view_mode: ViewMode,
+    debug_panel: DebugPanel,
+    overlays: ScreenOverlays,
+    // Drawn over the game in the normal view (see overlay.rs)
+    overlay_layers: OverlayLayers,
+    osd: OsdMessage,
+    // Up once emulation has panicked, which stops the game until it's reset
+    // (see "ON HOW CRASH REPORTS WORK")
+    crash_notice: CrashNotice,
+    // Which palette (0-7) the debug view draws the pagetables in, or None
+    // for greyscale
+    pgtbl_palette: Option<u8>,
     pause_menu: PauseMenu,
+    perf_hud: PerfHud,
+    // The audio underrun count as of the last frame, to tell when the sound
+    // card has run dry since (see "ON AUDIO UNDERRUNS" in apu_util.rs)
+    audio_underruns_seen: u64,
+    // What the game view goes through before it's shown (see video_filter.rs)
+    video_filters: FilterChain,
+    // The game view drawn for the filters to work on
+    filter_input: Vec<u8>,
+    // The size of the pixel buffer while the game view is shown, which is
+    // bigger than the game view when a filter scales it up
+    game_view_size: (usize, usize),
+    // Draws the game view to the window through a GPU shader (see shader.rs),
+    // if the presenter supports them
+    gpu_shader: GpuShader,
 
     controller_handler: Gilrs,
     controller1_map: ControllerMapping,
     controller2_map: ControllerMapping,
     p1_controller_id: Option<gilrs::GamepadId>,
     p2_controller_id: Option<gilrs::GamepadId>,
+    // Where each player's left stick is, and the D-pad directions it's
+    // pressing because of it
+    stick_positions: [(f32, f32); 2],
+    stick_held: [NesController; 2],
 
     limit_fps: bool,
     can_debug: bool,
+    modifiers: Modifiers,
+
+    // Which windows have focus. The app is in the background when neither
+    // does, and depending on the config pauses and/or mutes until it's back.
+    main_focused: bool,
+    debug_focused: bool,
+    pause_in_background: bool,
+    mute_in_background: bool,
+    // Whether the game was paused by going into the background (rather than
+    // by the player), so it should carry on when focus comes back
+    paused_in_background: bool,
+
+    // Window size as a multiple of the NES resolution, and fullscreen setting
+    scale: Option<usize>,
+    fullscreen: bool,
+    // Which edges of the picture are hidden in the game view
+    crop: CropMode,
+    // How the game view is turned and flipped (see cli::Rotation)
+    rotation: Rotation,
+    mirror: bool,
+    palette_path: Option<String>,
+    // Colors the menus, debug view and HUDs are drawn in
+    ui_palette: DebugPalette,
+    region: Region,
+
+    // Config file holding the per-game settings profiles
+    config: ConfigFile,
+    // How the game's profile has its intro skipped, if it does
+    skip_intro: Option<SkipIntro>,
+    // Pictures of each game for picking ROMs
+    thumbnails: ThumbnailCache,
+    // Each game's save states (see save_slots.rs)
+    save_slots: SaveSlots,
+    // Each game's battery backed RAM (see battery.rs)
+    battery_saves: BatterySaves,
+    // Hashes of each game, worked out in the background
+    rom_hashes: RomHashCache,
+    // Known issues of games (see "ON THE COMPATIBILITY DATABASE")
+    compat_db: CompatDatabase,
+
+    // Movie being recorded or played back, and where to save a recording
+    movie: Option<Movie>,
+    movie_command: Command,
+    movie_path: Option<String>,
+    movie_frame: usize,
+    // A past run being raced, drawn over the game (see ghost.rs)
+    ghost: Option<Ghost>,
+
     last_frame: std::time::Instant,
     fps: usize,
     frame_count: u64,
+    window_title: WindowTitle,
 
     // Flag keeping track of if the NES was just reset.
     reset: bool,
@@ -164,15 +583,26 @@ pub struct NesApp {
 
 impl ApplicationHandler for NesApp {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let (view_width, view_height) = match self.rotation.swaps_sides() {
+            true => (draw::GAME_FRAME_HEIGHT, draw::GAME_FRAME_WIDTH),
+            false => (draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT),
+        };
+        let window_size = match self.scale {
+            Some(scale) => PhysicalSize::new((view_width * scale) as u32, (view_height * scale) as u32),
+            None => PhysicalSize::new(1920, 1080),
+        };
+        let fullscreen = if self.fullscreen { Some(Fullscreen::Borderless(None)) } else { None };
+
         let window_attributes = Window::default_attributes()
             .with_title("NEmulator")
-            .with_inner_size(PhysicalSize::new(1920, 1080));
+            .with_inner_size(window_size)
+            .with_fullscreen(fullscreen);
         let window = event_loop.create_window(window_attributes).unwrap();
-        let size = window.inner_size();
 
         self.window = Some(window);
+        self.update_window_title(true);
 
-        let bg_col = draw::DEFAULT_DEBUG_PAL.bg_col;
+        let bg_col = self.ui_palette.bg_col;
         let wgpu_bg_col = pixels::wgpu::Color {
             r: (bg_col.r as f64) / 255.0,
             g: (bg_col.g as f64) / 255.0,
@@ -180,45 +610,36 @@ impl ApplicationHandler for NesApp {
             a: 1.0,
         };
 
-        let pixel_surface =
-            SurfaceTexture::new(size.width, size.height, self.window.as_ref().unwrap());
+        let (width, height) = match self.view_mode {
+            ViewMode::Debug => (draw::DEBUG_FRAME_WIDTH, draw::DEBUG_FRAME_HEIGHT),
+            ViewMode::Normal => (draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT),
+        };
 
-        match self.view_mode {
-            ViewMode::Debug => {
-                let pixels_builder = PixelsBuilder::new(
-                    draw::DEBUG_FRAME_WIDTH as u32,
-                    draw::DEBUG_FRAME_HEIGHT as u32,
-                    pixel_surface,
-                )
-                .enable_vsync(false)
-                .clear_color(wgpu_bg_col);
+        // A host embedding the app may have given its own presenter already
+        if self.presenter.is_none() {
+            let window = self.window.as_ref().unwrap();
+            let presenter = WindowPresenter::new(window, width as u32, height as u32, wgpu_bg_col, true).unwrap();
 
-                self.pixel_buf = Some(pixels_builder.build().unwrap());
+            self.presenter = Some(Box::new(presenter));
+        }
+        let presenter = self.presenter.as_mut().unwrap();
+        presenter.resize_buffer(width as u32, height as u32).unwrap();
 
-                draw::draw_debug_bg(
-                    self.pixel_buf.as_mut().unwrap().frame_mut(),
-                    DEFAULT_DEBUG_PAL,
-                    &self.nes,
-                );
+        match self.view_mode {
+            ViewMode::Debug => {
+                draw::draw_debug_bg(presenter.frame_mut(), self.ui_palette, self.debug_panel, &mut self.debug_cache);
             }
             ViewMode::Normal => {
-                let pixels_builder = PixelsBuilder::new(
-                    draw::GAME_FRAME_WIDTH as u32,
-                    draw::GAME_FRAME_HEIGHT as u32,
-                    pixel_surface,
-                )
-                .enable_vsync(false)
-                .clear_color(wgpu_bg_col);
+                self.game_view_size = (draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT);
 
-                self.pixel_buf = Some(pixels_builder.build().unwrap());
-
-                draw::draw_game_view_bg(
-                    self.pixel_buf.as_mut().unwrap().frame_mut(),
-                    DEFAULT_DEBUG_PAL,
-                );
+                draw::draw_game_view_bg(presenter.frame_mut(), self.ui_palette);
             }
         }
 
+        if !presenter.supports_shaders() {
+            self.use_cpu_shader_fallback();
+        }
+
         self.window.as_ref().unwrap().request_redraw();
 
         self.last_frame = std::time::Instant::now();
@@ -227,10 +648,15 @@ impl ApplicationHandler for NesApp {
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _window_id: WindowId,
+        window_id: WindowId,
         win_event: WindowEvent,
     ) {
-        self.handle_controller_input();
+        self.handle_controller_input(event_loop);
+
+        if self.debug_window.as_ref().is_some_and(|window| window.id() == window_id) {
+            self.debug_window_event(event_loop, win_event);
+            return;
+        }
 
         match win_event {
             WindowEvent::CloseRequested => {
@@ -242,68 +668,195 @@ impl ApplicationHandler for NesApp {
                 self.handle_keyboard_input(event, event_loop);
             }
 
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+            }
+
+            WindowEvent::Focused(focused) => {
+                self.main_focused = focused;
+                self.update_background();
+            }
+
+            // The mouse turns the Arkanoid controller's knob, from the left
+            // edge of the window to the right
+            WindowEvent::CursorMoved { position, .. } if self.nes.expansion_device() == ExpansionDevice::ArkanoidPaddle => {
+                let width = self.window.as_ref().map_or(1, |window| window.inner_size().width.max(1));
+
+                let mut input = self.nes.expansion_input();
+                input.paddle_position = (position.x / width as f64 * 255.0).clamp(0.0, 255.0) as u8;
+                self.nes.set_expansion_input(input);
+            }
+
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } if self.nes.expansion_device() == ExpansionDevice::ArkanoidPaddle => {
+                let mut input = self.nes.expansion_input();
+                input.paddle_button = state == ElementState::Pressed;
+                self.nes.set_expansion_input(input);
+                self.perf_hud.input_arrived();
+            }
+
             WindowEvent::Resized(new_size) => {
-                if let Some(buf) = self.pixel_buf.as_mut() {
-                    let _ = buf.resize_surface(new_size.width, new_size.height);
+                if let Some(presenter) = self.presenter.as_mut() {
+                    let _ = presenter.resize_surface(new_size.width, new_size.height);
                 }
             }
 
             WindowEvent::RedrawRequested => {
 
                 let micros_since_frame = self.last_frame.elapsed().as_micros();
+                let micros_per_frame = MICROS_PER_FRAME * 100 / self.pause_menu.speed_percent as u128;
 
-                if !self.limit_fps || 
-                    micros_since_frame > MICROS_PER_FRAME || 
-                    (!self.paused && self.nes.audio_samples_queued() < MIN_SAMPLES_THRESH) {
+                if self.frame_due(micros_since_frame, micros_per_frame) {
 
                     self.last_frame = std::time::Instant::now();
 
                     self.fps = (1_000_000 / micros_since_frame) as usize;
                     
-                    if let Some(buf) = self.pixel_buf.as_mut() {
-                        let frame = buf.frame_mut();
-    
-                        if self.can_debug {
-                            match self.view_mode {
-                                ViewMode::Debug => {
-                                    draw::draw_debug(frame, draw::DEFAULT_DEBUG_PAL, &mut self.nes, self.fps);
-                                }
-                                ViewMode::Normal => {
-                                    draw::draw_game_view(frame, &mut self.nes);
-                                }
+                    if self.paused {
+                        self.sync_menu();
+                    }
+
+                    let mut shader_failed = false;
+                    if let Some(presenter) = self.presenter.as_mut() {
+                        let draw_start = Instant::now();
+
+                        // The pause menu only takes over the window when
+                        // there's no debugging
+                        let debug_shown = self.can_debug && self.view_mode == ViewMode::Debug;
+                        let game_shown = !debug_shown && (self.can_debug || !self.paused);
+                        let filtered = game_shown && self.video_filters.is_active();
+
+                        // The filters can change the size of the picture,
+                        // and turning it can put it on its side (the menu
+                        // is always drawn at the game's size, the right way up)
+                        if !debug_shown {
+                            let (width, height) = if filtered {
+                                self.video_filters.output_size(draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT)
+                            } else {
+                                (draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT)
+                            };
+                            let size = match game_shown && self.rotation.swaps_sides() {
+                                true => (height, width),
+                                false => (width, height),
+                            };
+
+                            if size != self.game_view_size {
+                                presenter.resize_buffer(size.0 as u32, size.1 as u32).unwrap();
+                                self.game_view_size = size;
                             }
+                        }
+
+                        let frame = presenter.frame_mut();
+    
+                        if let Some(ghost) = &mut self.ghost {
+                            ghost.catch_up(self.nes.frames_run());
+                        }
+                        let ghost_screen = self.ghost.as_ref().and_then(|ghost| ghost.screen());
+
+                        if debug_shown {
+                            draw::draw_debug(frame, self.ui_palette, &mut self.nes, ghost_screen, self.fps, self.debug_panel, self.overlays, self.pgtbl_palette, &mut self.debug_cache);
+                        } else if game_shown {
+                            draw::draw_game_view(&mut self.filter_input, &mut self.nes, ghost_screen, self.crop, self.overlays);
+
+                            let filtered_frame = filtered.then(|| {
+                                self.video_filters.process(&self.filter_input, draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT)
+                            });
+                            let (picture, width, height) = match &filtered_frame {
+                                Some(filtered) => (&filtered.pixels[..], filtered.width, filtered.height),
+                                None => (&self.filter_input[..], draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT),
+                            };
+                            draw::orient_game_view(frame, picture, width, height, self.rotation, self.mirror);
+
+                            // Drawn last, so they're the right way up
+                            let (width, height) = self.game_view_size;
+                            let mut target = OverlayTarget { frame, width, height, palette: self.ui_palette };
+                            self.overlay_layers.compose(&mut target, &self.nes);
                         } else {
-                            if !self.paused {
-                                draw::draw_game_view(frame, &mut self.nes);
-                            } else {
-                                if self.pause_menu.mapping_controller && self.frame_count % 32 == 0 {
-                                    let new_state = (self.frame_count >> 5) & 1 == 1; // Alternates about every 1/2 second
-
-                                    self.pause_menu.controller_state = match self.pause_menu.controller_read.button() {
-                                        ControllerButton::A => NesController::new().with_a(new_state),
-                                        ControllerButton::B => NesController::new().with_b(new_state),
-                                        ControllerButton::Up => NesController::new().with_up(new_state),
-                                        ControllerButton::Down => NesController::new().with_down(new_state),
-                                        ControllerButton::Left => NesController::new().with_left(new_state),
-                                        ControllerButton::Right => NesController::new().with_right(new_state),
-                                        ControllerButton::Select => NesController::new().with_select(new_state),
-                                        ControllerButton::Start => NesController::new().with_start(new_state),
-                                    };
-                                }
+                            let mapping_controller = matches!(self.pause_menu.screen, 
+                                MenuScreen::ControllerSelect | MenuScreen::ControllerMapping);
 
-                                draw::draw_menu(frame, &self.pause_menu, self.limit_fps);
+                            if mapping_controller && self.frame_count % 32 == 0 {
+                                let new_state = (self.frame_count >> 5) & 1 == 1; // Alternates about every 1/2 second
+
+                                let mut controller_state = NesController::new();
+                                controller_state.set_button(self.pause_menu.mapping_button().unwrap_or_default(), new_state);
+                                self.pause_menu.controller_state = controller_state;
                             }
+
+                            draw::draw_menu(frame, self.ui_palette, &self.pause_menu);
+                        }
+
+                        // Not over the pause menu, which has the whole frame to itself
+                        if self.perf_hud.shown && (debug_shown || game_shown) {
+                            let (width, height) = if debug_shown {
+                                (draw::DEBUG_FRAME_WIDTH, draw::DEBUG_FRAME_HEIGHT)
+                            } else {
+                                self.game_view_size
+                            };
+                            draw::draw_perf_hud(frame, width, height, self.ui_palette, &self.perf_hud, &self.nes, self.audio_queue_depth);
+                        }
+                        self.perf_hud.frame_drawn(draw_start.elapsed());
+
+                        let present_start = Instant::now();
+                        let shader = if game_shown { self.gpu_shader } else { GpuShader::Off };
+                        if let Err(e) = presenter.present(shader) {
+                            println!("{e}");
+                            shader_failed = !presenter.supports_shaders();
+                        }
+                        self.perf_hud.frame_presented(present_start.elapsed());
+                    }
+                    if shader_failed {
+                        self.use_cpu_shader_fallback();
+                    }
+
+                    if let Some(presenter) = self.debug_presenter.as_mut() {
+                        let ghost_screen = self.ghost.as_ref().and_then(|ghost| ghost.screen());
+                        draw::draw_debug(presenter.frame_mut(), self.ui_palette, &mut self.nes, ghost_screen, self.fps, self.debug_panel, self.overlays, self.pgtbl_palette, &mut self.debug_window_cache);
+                        if let Err(e) = presenter.present(GpuShader::Off) {
+                            println!("{e}");
                         }
-    
-                        buf.render().unwrap();
                     }
 
                     if !self.paused {
-                        self.nes.cycle_until_frame();
-                        self.nes.swap_screen_buffers();
+                        let mut frames_run = 1;
+                        self.emulate_frame();
+
+                        // If the sound card drained the queue (say the window
+                        // was being dragged), catch back up without waiting
+                        // on more redraws
+                        if self.limit_fps && self.sync_mode == SyncMode::Audio {
+                            while frames_run < AUDIO_SYNC_MAX_FRAMES {
+                                if self.nes.audio_samples_queued() >= self.audio_queue_depth {
+                                    break;
+                                }
+                                self.emulate_frame();
+                                frames_run += 1;
+                            }
+                        }
+
+                        // The sound card ran out of samples since the last
+                        // frame, so run another one now to refill the queue
+                        // rather than waiting on the next redraw
+                        let underruns = self.nes.audio_underruns();
+                        if underruns > self.audio_underruns_seen && frames_run < AUDIO_SYNC_MAX_FRAMES {
+                            self.emulate_frame();
+                            frames_run += 1;
+                            self.perf_hud.frame_caught_up();
+                        }
+
+                        if self.limit_fps {
+                            self.perf_hud.count_dropped(micros_since_frame, micros_per_frame, frames_run);
+                        }
                     }
 
+                    // Taken after the frames run (and while paused, when the
+                    // queue is left to run dry) so only new underruns count
+                    self.audio_underruns_seen = self.nes.audio_underruns();
                     self.frame_count += 1;
+                    self.update_window_title(false);
+                    self.thumbnails.poll();
+                    if let Some(hashes) = self.rom_hashes.poll() {
+                        self.nes.set_rom_hashes(hashes);
+                    }
                 }
 
                 self.window.as_ref().unwrap().request_redraw();
@@ -311,177 +864,949 @@ impl ApplicationHandler for NesApp {
             _ => (),
         }
     }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, _event: ()) {
+        // The only user event is the interrupt handler waking the event loop
+        if shutdown::interrupted() {
+            println!("Interrupted; stopping");
+            event_loop.exit();
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        self.shutdown();
+    }
 }
 
 impl NesApp {
     pub fn new() -> Self {
+        let mut overlay_layers = OverlayLayers::default();
+        overlay_layers.add(Box::new(draw::draw_cpu_jammed));
+        let osd = OsdMessage::default();
+        overlay_layers.add(osd.layer());
+        let crash_notice = CrashNotice::default();
+        overlay_layers.add(crash_notice.layer());
+
+        let config = ConfigFile::load(CONFIG_PATH);
+        let save_slots = SaveSlots::new(SAVE_STATE_DIR, config.retry_slot());
+
+        let video_filters = FilterChain::standard();
+
+        let mut nes = Nes::default();
+        let nes_events = nes.subscribe(&[EventKind::WriteBreak]);
+
         Self {
             window: None,
-            pixel_buf: None,
+            presenter: None,
+
+            debug_window: None,
+            debug_presenter: None,
+            debug_cache: DebugViewCache::default(),
+            debug_window_cache: DebugViewCache::default(),
 
             audio_sink: None,
             audio_stream_queue: None,
+            audio_queue_depth: DEFAULT_TARGET_QUEUE_DEPTH,
+            sync_mode: SyncMode::Timer,
 
-            nes: Nes::default(),
+            nes,
+            nes_events,
+            held_input: [NesController::new(); 2],
             paused: false,
             view_mode: ViewMode::default(),
-            pause_menu: PauseMenu::new(),
+            debug_panel: DebugPanel::default(),
+            overlays: ScreenOverlays::default(),
+            overlay_layers,
+            osd,
+            crash_notice,
+            pgtbl_palette: None,
+            perf_hud: PerfHud::default(),
+            audio_underruns_seen: 0,
+            pause_menu: PauseMenu::new(&AssetStore::default(), &video_filters),
+            video_filters,
+            filter_input: vec![0; draw::GAME_FRAME_WIDTH * draw::GAME_FRAME_HEIGHT * 4],
+            game_view_size: (draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT),
+            gpu_shader: GpuShader::Off,
 
             controller_handler: Gilrs::new().unwrap(),
             controller1_map: ControllerMapping::default(),
             controller2_map: ControllerMapping::default(),
             p1_controller_id: None,
+            stick_positions: [(0.0, 0.0); 2],
+            stick_held: [NesController::new(); 2],
             p2_controller_id: None,
 
             limit_fps: false,
             can_debug: true,
+            modifiers: Modifiers::default(),
+
+            main_focused: true,
+            debug_focused: false,
+            pause_in_background: true,
+            mute_in_background: true,
+            paused_in_background: false,
+
+            scale: None,
+            fullscreen: false,
+            crop: CropMode::Full,
+            rotation: Rotation::None,
+            mirror: false,
+            palette_path: None,
+            ui_palette: draw::DEFAULT_DEBUG_PAL,
+            region: Region::Ntsc,
+
+            config,
+            skip_intro: None,
+            thumbnails: ThumbnailCache::new(THUMBNAIL_DIR),
+            save_slots,
+            battery_saves: BatterySaves::new(BATTERY_SAVE_DIR),
+            rom_hashes: RomHashCache::load(ROM_HASH_CACHE_PATH),
+            compat_db: CompatDatabase::default(),
+
+            movie: None,
+            movie_command: Command::Run,
+            movie_path: None,
+            movie_frame: 0,
+            ghost: None,
+
             last_frame: std::time::Instant::now(),
             fps: 0,
             frame_count: 0,
+            window_title: WindowTitle::new(),
 
             reset: false,
         }
     }
 
-    pub fn init(&mut self, config: RuntimeConfig, sample_queue: Arc<Mutex<VecDeque<f32>>>) {
+    /// Has the app present its frames through the given presenter instead of
+    /// drawing them to its window, for hosts embedding it in their own wgpu
+    /// app (see "ON HOW FRAMES ARE PRESENTED"). Has to be given before the
+    /// event loop starts.
+    pub fn set_presenter(&mut self, presenter: Box<dyn Presenter>) {
+        self.presenter = Some(presenter);
+    }
+
+    pub fn init(&mut self, config: RuntimeConfig, sample_queue: SampleQueue) -> Result<(), String> {
+        // Nothing's been changed in the menu yet, so it can just be made again
+        // with the asset overrides
+        let assets = AssetStore::new(config.assets_dir.as_deref());
+        if config.assets_dir.is_some() {
+            self.pause_menu = PauseMenu::new(&assets, &self.video_filters);
+        }
+        self.compat_db = assets.decode(Asset::CompatDatabase, |bytes| {
+            CompatDatabase::parse(&String::from_utf8_lossy(bytes))
+        });
+
         self.audio_stream_queue = Some(Arc::clone(&sample_queue));
-        self.nes.load_cart(&config.cart_path, sample_queue);
+        self.nes.set_sample_queue(sample_queue);
+        self.nes.set_ram_fill(config.ram_fill);
+        self.nes.set_ppu_warmup(config.ppu_warmup);
+        if config.test_pattern {
+            self.nes.load_test_pattern()?;
+        } else if let Err(e) = self.nes.load_cart_patched(&config.cart_path, config.patch_path.as_deref()) {
+            return Err(self.with_known_issues(e, &config));
+        }
+        for &freeze in &config.cheats {
+            self.nes.add_freeze(freeze);
+        }
+        for &breakpoint in &config.write_breakpoints {
+            self.nes.add_write_breakpoint(breakpoint);
+        }
+        self.nes.set_audio_batch_config(config.audio_batch_size, config.audio_queue_depth, config.adaptive_audio);
+        self.nes.set_audio_sample_rate(config.sample_rate);
+        self.nes.set_audio_synthesis(config.synthesis);
+        self.nes.set_audio_mixer(config.mixer);
+        self.nes.set_duty_cycles_swapped(config.swap_duty_cycles);
+        self.nes.set_dmc_input_glitch(config.dmc_input_glitch);
+        self.nes.set_sprite_evaluation(config.sprite_evaluation);
+        self.nes.set_cpu_mode(config.cpu_mode);
+        self.nes.set_register_write_assert(config.register_write_assert);
+        self.nes.set_bg_hidden(config.hide_bg);
+        self.nes.set_sprites_hidden(config.hide_sprites);
+        self.audio_queue_depth = config.audio_queue_depth;
+        self.sync_mode = config.sync_mode;
         self.limit_fps = config.limit_fps;
+        self.nes.set_audio_ducked(!self.limit_fps);
         self.can_debug = config.can_debug;
+        self.scale = config.scale;
+        self.fullscreen = config.fullscreen;
+        self.crop = config.crop;
+        self.rotation = config.rotation;
+        self.mirror = config.mirror;
+        self.palette_path = config.palette_path.clone();
+        self.ui_palette = draw::debug_palette(config.ui_palette);
+        self.nes.set_color_filter(config.color_filter);
+        self.region = config.region;
+
+        self.nes.set_expansion_device(config.expansion_device);
+
+        if let Some(profile) = self.config.game_profile(self.nes.rom_crc32()) {
+            self.apply_game_profile(profile);
+        }
 
-        self.find_gamepads();
-    }
+        // Set up before the sink is attached so the first frame is already at
+        // the right volume
+        if let Some(volume) = self.config.volume_percent() {
+            self.pause_menu.volume_percent = volume.min(100) as f32 / 100.0;
+        }
+        self.pause_menu.muted = self.config.muted().unwrap_or(false);
+        self.pause_in_background = self.config.pause_in_background().unwrap_or(true);
+        self.mute_in_background = self.config.mute_in_background().unwrap_or(true);
+        self.config.load_video_filters(&mut self.video_filters);
+        self.gpu_shader = self.config.gpu_shader().unwrap_or_default();
+
+        if let Some(path) = &self.palette_path {
+            self.nes.load_palette(path)?;
+        }
 
-    // Find the first two connected gamepads
-    fn find_gamepads(&mut self) {
-        let mut player1: Option<GamepadId> = None;
-        let mut player2: Option<GamepadId> = None;
+        if !config.test_pattern {
+            self.thumbnails.generate_if_missing(&config.cart_path, self.nes.rom_crc32());
 
-        for (_id, gamepad) in self.controller_handler.gamepads() {
-            if gamepad.is_connected() {
-                if player1.is_none() {
-                    player1 = Some(gamepad.id());
-                } else if player2.is_none() {
-                    player2 = Some(gamepad.id());
-                    break;
-                }
+            if let Some(hashes) = self.rom_hashes.hash(&config.cart_path, config.patch_path.as_deref(), self.nes.rom_crc32()) {
+                self.nes.set_rom_hashes(hashes);
             }
         }
 
-        self.p1_controller_id = player1;
-        self.p2_controller_id = player2;
-    }
+        if let Some(path) = &config.ghost_path {
+            self.ghost = Some(Ghost::new(&config, path)?);
+        }
 
-    pub fn switch_view_mode(&mut self) {
-        match self.view_mode {
-            ViewMode::Debug => {
-                let buf = self.pixel_buf.as_mut().unwrap();
+        self.movie_command = config.command;
+        self.movie_path = config.movie_path;
 
-                buf.resize_buffer(
-                    draw::GAME_FRAME_WIDTH as u32,
-                    draw::GAME_FRAME_HEIGHT as u32,
-                )
-                .unwrap();
+        if !self.in_movie() {
+            self.load_battery_ram();
+        }
 
-                let frame = buf.frame_mut();
+        // Movies and ghosts go frame for frame from power on, so the intro
+        // can't be skipped under them
+        if !self.in_movie() && self.ghost.is_none() {
+            self.skip_intro();
+        }
 
-                self.view_mode = ViewMode::Normal;
+        // After the intro's skipped, so it isn't hidden by that message
+        if let Some(entry) = self.compat_db.get(self.nes.rom_crc32()) {
+            print_known_issues(entry);
+            self.osd.show_for(&known_issues_notice(entry), KNOWN_ISSUES_DURATION);
+        }
 
-                draw::draw_game_view_bg(frame, draw::DEFAULT_DEBUG_PAL);
-            }
-            ViewMode::Normal => {
-                let buf = self.pixel_buf.as_mut().unwrap();
+        match config.command {
+            Command::Record => {
+                let rom_name = std::path::Path::new(&config.cart_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
 
-                buf.resize_buffer(
-                    draw::DEBUG_FRAME_WIDTH as u32,
-                    draw::DEBUG_FRAME_HEIGHT as u32,
-                )
-                .unwrap();
+                let mut movie = Movie::new(&rom_name);
 
-                let frame = buf.frame_mut();
+                if let Some(path) = &config.movie_anchor_path {
+                    let bytes = std::fs::read(path).map_err(|e| format!("Couldn't read save state '{path}': {e}"))?;
+                    let state = SaveState::from_bytes(bytes);
 
-                frame.fill(0);
+                    self.nes.load_state(&state)?;
+                    movie.set_anchor(state);
+                }
 
-                self.view_mode = ViewMode::Debug;
+                self.movie = Some(movie);
+            }
+            Command::Playback => {
+                let path = self.movie_path.as_ref().ok_or("No movie file given")?;
+                let movie = Movie::load(path)?;
 
-                draw::draw_debug_bg(frame, DEFAULT_DEBUG_PAL, &self.nes);
+                movie.start(&mut self.nes)?;
+                self.movie = Some(movie);
             }
+            _ => {}
         }
+
+        self.find_gamepads();
+
+        Ok(())
     }
 
-    /// If the input should go to the NES, then
-    /// this function creates the controller state
-    fn handle_nes_input(&mut self, event: KeyEvent) -> bool {
-        if event.repeat {
-            return false;
+    /// Applies a game's settings profile on top of the settings from the
+    /// command line
+    fn apply_game_profile(&mut self, profile: GameProfile) {
+        println!("Using settings profile for {}", profile.name);
+
+        self.pause_menu.has_game_profile = true;
+
+        if profile.palette_path.is_some() {
+            self.palette_path = profile.palette_path;
         }
+        if let Some(crop) = profile.crop {
+            self.crop = crop;
+        }
+        if let Some(rotation) = profile.rotation {
+            self.rotation = rotation;
+        }
+        if let Some(mirror) = profile.mirror {
+            self.mirror = mirror;
+        }
+        if let Some(region) = profile.region {
+            if region != Region::Ntsc {
+                println!("Warning: {} timing isn't emulated yet, running as NTSC", region.name());
+            }
+            self.region = region;
+        }
+        if let Some(speed) = profile.speed_percent {
+            let speed = speed.clamp(MIN_SPEED_PERCENT, MAX_SPEED_PERCENT);
 
-        let new_state = event.state == ElementState::Pressed;
+            self.pause_menu.speed_percent = speed;
+            self.nes.set_emulation_speed(speed as f64 / 100.0);
+        }
+        if let Some(device) = profile.expansion {
+            self.nes.set_expansion_device(device);
+        }
+        if let Some(mixer) = profile.mixer {
+            self.nes.set_audio_mixer(mixer);
+        }
+        if let Some(swap) = profile.swap_duty_cycles {
+            self.nes.set_duty_cycles_swapped(swap);
+        }
 
-        const PLAYER1_ID: usize = 0;
-        // const PLAYER2_ID: usize = 1; // Keyboard controls player 1 controller
+        self.skip_intro = profile.skip_intro;
 
-        let controller_update: Option<ControllerUpdate> = match event.physical_key {
-            // Player 1 keys
-            PhysicalKey::Code(KeyCode::KeyZ) => Some(ControllerUpdate {
-                button: ControllerButton::A,
-                player_id: PLAYER1_ID,
-                pressed: new_state,
-            }),
-            PhysicalKey::Code(KeyCode::KeyX) => Some(ControllerUpdate {
-                button: ControllerButton::B,
-                player_id: PLAYER1_ID,
-                pressed: new_state,
-            }),
-            PhysicalKey::Code(KeyCode::ShiftRight) => Some(ControllerUpdate {
-                button: ControllerButton::Select,
-                player_id: PLAYER1_ID,
-                pressed: new_state,
-            }),
-            PhysicalKey::Code(KeyCode::Enter) => Some(ControllerUpdate {
-                button: ControllerButton::Start,
-                player_id: PLAYER1_ID,
-                pressed: new_state,
-            }),
-            PhysicalKey::Code(KeyCode::ArrowUp) => Some(ControllerUpdate {
-                button: ControllerButton::Up,
-                player_id: PLAYER1_ID,
-                pressed: new_state,
-            }),
-            PhysicalKey::Code(KeyCode::ArrowDown) => Some(ControllerUpdate {
-                button: ControllerButton::Down,
-                player_id: PLAYER1_ID,
-                pressed: new_state,
-            }),
-            PhysicalKey::Code(KeyCode::ArrowLeft) => Some(ControllerUpdate {
-                button: ControllerButton::Left,
-                player_id: PLAYER1_ID,
-                pressed: new_state,
-            }),
-            PhysicalKey::Code(KeyCode::ArrowRight) => Some(ControllerUpdate {
-                button: ControllerButton::Right,
-                player_id: PLAYER1_ID,
-                pressed: new_state,
-            }),
-            _ => None,
-        };
+        let [map1, map2] = profile.controller_maps;
+        if let Some(mapping) = map1 {
+            self.controller1_map = Self::controller_map_from_saved(&mapping);
+        }
+        if let Some(mapping) = map2 {
+            self.controller2_map = Self::controller_map_from_saved(&mapping);
+        }
+    }
 
-        if let Some(update) = controller_update {
-            self.nes.update_controllers(update);
-            return true;
+    fn controller_map_from_saved(mapping: &SavedMapping) -> ControllerMapping {
+        let mut controller_map = ControllerMapping::default();
+
+        for (nes_button, gamepad_button, positive) in &mapping.buttons {
+            controller_map.set_button_mapping(*nes_button, *gamepad_button, if *positive { 1.0 } else { -1.0 });
+        }
+        if let Some(deadzone) = mapping.stick_deadzone {
+            controller_map.set_stick_deadzone(deadzone as f32 / 100.0);
+        }
+        if let Some(bias) = mapping.diagonal_bias {
+            controller_map.set_diagonal_bias(bias as f32 / 100.0);
         }
 
-        false
+        controller_map
     }
 
-    fn handle_nes_controller_input(&mut self, button: gilrs::Button, val: f32, gamepad_id: GamepadId) {
-        if self.controller_handler.connected_gamepad(gamepad_id).is_some() {
-            
-            let mut button_map = None;
-            let mut player_id = 0;
+    fn saved_from_controller_map(controller_map: &ControllerMapping) -> SavedMapping {
+        SavedMapping {
+            buttons: controller_map.button_mappings(),
+            stick_deadzone: Some((controller_map.stick_deadzone() * 100.0).round() as usize),
+            diagonal_bias: Some((controller_map.diagonal_bias() * 100.0).round() as usize),
+        }
+    }
 
-            if let Some(p1_id) = self.p1_controller_id {
-                if p1_id == gamepad_id {
+    /// Writes the volume to the config file if it's changed since it was loaded
+    fn save_volume(&mut self) {
+        let volume = (self.pause_menu.volume_percent * 100.0).round() as usize;
+        let muted = self.pause_menu.muted;
+
+        let default_volume = (DEFAULT_VOLUME_PERCENT * 100.0).round() as usize;
+        if self.config.volume_percent().unwrap_or(default_volume) == volume && self.config.muted().unwrap_or(false) == muted {
+            return;
+        }
+
+        self.config.set_volume(volume, muted);
+
+        if let Err(e) = self.config.save() {
+            println!("{e}");
+        }
+    }
+
+    /// Turns on the CPU filters closest to the selected GPU shader, for when
+    /// the GPU can't run it
+    fn use_cpu_shader_fallback(&mut self) {
+        if self.gpu_shader == GpuShader::Off {
+            return;
+        }
+
+        println!("Using the CPU filters instead of the {} shader", self.gpu_shader.name());
+
+        for id in self.gpu_shader.cpu_fallback() {
+            if let Some(i) = (0..self.video_filters.len()).find(|&i| self.video_filters.filter(i).id() == *id) {
+                self.video_filters.set_enabled(i, true);
+            }
+        }
+        self.gpu_shader = GpuShader::Off;
+    }
+
+    /// Writes the video filters and GPU shader to the config file if they've
+    /// changed since they were loaded
+    fn save_video_filters(&mut self) {
+        if !self.config.set_video_filters(&self.video_filters, self.gpu_shader) {
+            return;
+        }
+
+        if let Err(e) = self.config.save() {
+            println!("{e}");
+        }
+    }
+
+    /// Saves the current settings as this game's profile, or deletes its
+    /// profile if save is false
+    fn save_game_profile(&mut self, save: bool) {
+        let crc = self.nes.rom_crc32();
+
+        if save {
+            let profile = GameProfile {
+                name: String::from(self.nes.rom_name()),
+                palette_path: self.palette_path.clone(),
+                crop: Some(self.crop),
+                rotation: Some(self.rotation),
+                mirror: Some(self.mirror),
+                region: Some(self.region),
+                speed_percent: Some(self.pause_menu.speed_percent),
+                expansion: Some(self.nes.expansion_device()),
+                mixer: Some(self.nes.audio_mixer()),
+                swap_duty_cycles: Some(self.nes.duty_cycles_swapped()),
+                controller_maps: [
+                    Some(Self::saved_from_controller_map(&self.controller1_map)),
+                    Some(Self::saved_from_controller_map(&self.controller2_map)),
+                ],
+                skip_intro: self.skip_intro.clone(),
+            };
+
+            self.config.set_game_profile(crc, Some(&profile));
+        } else {
+            self.config.set_game_profile(crc, None);
+        }
+
+        if let Err(e) = self.config.save() {
+            println!("{e}");
+        }
+    }
+
+    /// Adds the known issues of the game that failed to load, if the
+    /// compatibility database has any, to why it failed. The ROM has to be
+    /// read again for its CRC-32, since the NES never got it.
+    fn with_known_issues(&self, error: String, config: &RuntimeConfig) -> String {
+        let entry = patch::read_patched_rom(&config.cart_path, config.patch_path.as_deref()).ok()
+            .and_then(|(data, _)| data.get(Cartridge::HEADER_LEN..).map(hash::crc32))
+            .and_then(|crc32| self.compat_db.get(crc32));
+
+        match entry {
+            Some(entry) => format!("{error}\nKnown issues ({}): {}", text(Text::CompatStatus(entry.status)), entry.notes.join("; ")),
+            None => error,
+        }
+    }
+
+    /// Runs the game past its intro as fast as it can, without showing or
+    /// playing any of it, if its profile says to (see SkipIntro)
+    fn skip_intro(&mut self) {
+        let Some(skip) = self.skip_intro.clone() else {
+            return;
+        };
+
+        let movie = match skip.movie_path.as_deref().map(Movie::load) {
+            Some(Ok(movie)) => match movie.start(&mut self.nes) {
+                Ok(()) => Some(movie),
+                Err(e) => {
+                    println!("Warning: {e}, not skipping the intro");
+                    return;
+                }
+            },
+            Some(Err(e)) => {
+                println!("Warning: {e}, not skipping the intro");
+                return;
+            }
+            None => None,
+        };
+
+        let frames = skip.frames.max(movie.as_ref().map_or(0, Movie::len));
+        for frame in 0..frames {
+            let inputs = movie.as_ref()
+                .and_then(|movie| movie.frame(frame))
+                .unwrap_or([NesController::new(); 2]);
+
+            self.nes.advance_frame(inputs);
+        }
+        self.handle_nes_events();
+
+        // Only the picture and sound from after the intro are kept
+        self.nes.swap_screen_buffers();
+        if let Some(stream) = &self.audio_stream_queue {
+            stream.clear();
+        }
+
+        println!("Skipped {frames} frames of intro");
+        self.osd.show(&fill(Text::IntroSkipped, &[&frames.to_string()]));
+    }
+
+    /// Whether it's time to draw and run the next frame, given how long it's
+    /// been since the last one and how long a frame should take
+    fn frame_due(&self, micros_since_frame: u128, micros_per_frame: u128) -> bool {
+        let audio_low = self.nes.audio_samples_queued() < self.audio_queue_depth;
+
+        if !self.limit_fps {
+            return true;
+        }
+
+        match self.sync_mode {
+            SyncMode::Timer => micros_since_frame > micros_per_frame || (!self.paused && audio_low),
+            // Nothing's filling the audio queue while paused, so the menu
+            // goes by the timer
+            SyncMode::Audio if self.paused => micros_since_frame > MICROS_PER_FRAME,
+            // The timer is still a backstop in case the audio stops being
+            // played altogether
+            SyncMode::Audio => audio_low || micros_since_frame > micros_per_frame * AUDIO_SYNC_MAX_FRAMES as u128,
+        }
+    }
+
+    /// Runs the NES for one frame, feeding it movie input if a movie is playing
+    /// and recording the input used if one is being recorded.
+    fn emulate_frame(&mut self) {
+        // Whatever went wrong would only go wrong again
+        if self.crash_notice.shown() {
+            return;
+        }
+
+        let mut inputs = self.held_input;
+
+        if let (Command::Playback, Some(movie)) = (self.movie_command, &self.movie) {
+            if let Some(movie_inputs) = movie.frame(self.movie_frame) {
+                inputs = movie_inputs;
+            }
+        }
+
+        let emulate_start = Instant::now();
+        let polls_before = self.nes.controller_polls();
+
+        let ran = panic::catch_unwind(AssertUnwindSafe(|| self.nes.advance_frame(inputs)));
+        let Ok(finished) = ran else {
+            self.report_crash();
+            return;
+        };
+        self.handle_nes_events();
+
+        self.perf_hud.frame_emulated(emulate_start.elapsed(), self.nes.controller_polls() != polls_before);
+
+        // Only show a new picture if the PPU finished one, which it doesn't
+        // with no cart in or when a breakpoint stops it partway. The rest of
+        // a frame that was stopped is run next time, with the same input.
+        if finished {
+            self.nes.swap_screen_buffers();
+
+            if let (Command::Record, Some(movie)) = (self.movie_command, &mut self.movie) {
+                movie.push_frame(inputs);
+            }
+            self.movie_frame += 1;
+        }
+    }
+
+    /// Runs one frame while paused and plays the sound from just that frame,
+    /// so the music can be followed frame by frame. Anything left of the last
+    /// frame's sound is dropped first, so the sound heard always goes with
+    /// the picture, and the whole frame's worth is queued rather than waiting
+    /// on a batch that won't fill until the game carries on.
+    fn advance_paused_frame(&mut self) {
+        if let Some(stream) = &self.audio_stream_queue {
+            stream.clear();
+        }
+
+        self.emulate_frame();
+        self.nes.flush_audio();
+    }
+
+    // Presses or releases a button, for the frames run from now on
+    fn update_held_input(&mut self, update: ControllerUpdate) {
+        if let Some(held) = self.held_input.get_mut(update.player_id) {
+            held.set_button(update.button, update.pressed);
+        }
+    }
+
+    /// Handles the events published since the last call, pausing if a write
+    /// breakpoint was hit
+    fn handle_nes_events(&mut self) {
+        for event in self.nes.take_events(self.nes_events) {
+            if let NesEvent::WriteBreak(hit) = event {
+                self.break_on_write(hit);
+            }
+        }
+    }
+
+    fn break_on_write(&mut self, hit: WriteBreak) {
+        println!("Breakpoint {}: ${:04X} <- ${:02X} at scanline {}, dot {} (PC ${:04X})",
+            hit.breakpoint.code(), hit.address, hit.data, hit.scanline, hit.dot, hit.pc);
+
+        if !self.paused {
+            self.pause();
+        }
+    }
+
+    /// Writes a crash report after the emulation panicked and puts up the
+    /// box saying where it went (see "ON HOW CRASH REPORTS WORK")
+    fn report_crash(&mut self) {
+        let details = crash::take_panic().unwrap_or_default();
+
+        let notice = match crash::write_report(&mut self.nes, &details) {
+            Ok(dir) => {
+                println!("The emulator crashed; a crash report was saved in '{}'", dir.display());
+                fill(Text::EmulatorCrashed, &[&dir.display().to_string()])
+            }
+            Err(e) => {
+                println!("The emulator crashed, and the crash report couldn't be saved: {e}");
+                String::from(text(Text::CrashReportFailed))
+            }
+        };
+        self.crash_notice.show(&notice);
+    }
+
+    /// Resets the NES, and starts the ghost over with it
+    fn reset_nes(&mut self) {
+        self.nes.reset();
+        self.crash_notice.clear();
+
+        if let Some(ghost) = &mut self.ghost {
+            ghost.restart(self.nes.frames_run());
+        }
+    }
+
+    /// Saves everything that has to outlive the app: the movie being
+    /// recorded, the game's battery backed RAM and the settings in the config
+    /// file (see "ON HOW SHUTTING DOWN WORKS")
+    fn shutdown(&mut self) {
+        self.save_movie();
+        self.save_battery_ram();
+
+        self.save_volume();
+        self.save_video_filters();
+
+        // Keep any settings changed while playing
+        if self.pause_menu.has_game_profile {
+            self.save_game_profile(true);
+        }
+
+        if let Some(sink) = &self.audio_sink {
+            sink.stop();
+        }
+    }
+
+    /// Whether a movie is being recorded or played back
+    fn in_movie(&self) -> bool {
+        matches!(self.movie_command, Command::Record | Command::Playback)
+    }
+
+    /// Puts back the game's battery backed RAM from the last time it was
+    /// played (see "ON HOW BATTERY SAVES WORK")
+    fn load_battery_ram(&mut self) {
+        if self.nes.battery_ram().is_none() {
+            return;
+        }
+
+        let loaded = self.battery_saves.load(self.nes.rom_crc32())
+            .and_then(|ram| match ram {
+                Some(ram) => self.nes.load_battery_ram(&ram).map(|_| true),
+                None => Ok(false),
+            });
+
+        match loaded {
+            Ok(true) => println!("Loaded battery save"),
+            Ok(false) => {}
+            Err(e) => println!("Warning: {e}"),
+        }
+    }
+
+    /// Writes out the game's battery backed RAM, if it has any
+    fn save_battery_ram(&self) {
+        if self.in_movie() {
+            return;
+        }
+        let Some(ram) = self.nes.battery_ram() else {
+            return;
+        };
+
+        match self.battery_saves.save(self.nes.rom_crc32(), &ram) {
+            Ok(()) => println!("Saved battery RAM"),
+            Err(e) => println!("{e}"),
+        }
+    }
+
+    /// Saves the NES's state to the selected slot (see "ON HOW SAVE SLOTS
+    /// WORK")
+    fn save_to_slot(&mut self) {
+        let slot = self.save_slots.selected();
+        let number = slot.to_string();
+
+        match self.save_slots.save(self.nes.rom_crc32(), slot, &self.nes.save_state()) {
+            Ok(()) => self.osd.show(&fill(Text::StateSaved, &[&number])),
+            Err(e) => {
+                println!("{e}");
+                self.osd.show(&fill(Text::StateSaveFailed, &[&number]));
+            }
+        }
+    }
+
+    /// Loads the state in a slot. Gives back whether it was loaded.
+    fn load_from_slot(&mut self, slot: usize) -> bool {
+        // A movie only plays back right from where it started, and a
+        // recording has to be made of every frame in order
+        if self.in_movie() {
+            self.osd.show(text(Text::NoStatesDuringMovie));
+            return false;
+        }
+
+        let number = slot.to_string();
+        let loaded = self.save_slots.load(self.nes.rom_crc32(), slot)
+            .and_then(|state| self.nes.load_state(&state));
+
+        match loaded {
+            Ok(()) => {
+                self.crash_notice.clear();
+                self.osd.show(&fill(Text::StateLoaded, &[&number]));
+                true
+            }
+            Err(e) => {
+                println!("{e}");
+                self.osd.show(&fill(Text::StateLoadFailed, &[&number]));
+                false
+            }
+        }
+    }
+
+    /// Loads the retry anchor: the configured retry slot, or the one saved
+    /// to last. Gives back whether a state was loaded.
+    fn retry_from_state(&mut self) -> bool {
+        match self.save_slots.retry_slot(self.nes.rom_crc32()) {
+            Some(slot) => self.load_from_slot(slot),
+            None => {
+                self.osd.show(text(Text::NoRetryState));
+                false
+            }
+        }
+    }
+
+    /// Switches a movie being played back to read+write: the rest of it is
+    /// dropped, and recording carries on from the frame it's on (see "ON HOW
+    /// MOVIES WORK")
+    fn resume_movie_recording(&mut self) {
+        if let (Command::Playback, Some(movie)) = (self.movie_command, &mut self.movie) {
+            movie.resume_recording(self.movie_frame);
+            self.movie_command = Command::Record;
+
+            println!("Recording from frame {} of the movie (rerecord {})", self.movie_frame, movie.rerecords());
+        }
+    }
+
+    /// Saves the movie being recorded, if there is one
+    fn save_movie(&self) {
+        if let (Command::Record, Some(movie), Some(path)) = (self.movie_command, &self.movie, &self.movie_path) {
+            match movie.save(path) {
+                Ok(()) => println!("Saved {} frame movie to {path}", movie.len()),
+                Err(e) => println!("{e}"),
+            }
+        }
+    }
+
+    // Find the first two connected gamepads
+    fn find_gamepads(&mut self) {
+        let mut player1: Option<GamepadId> = None;
+        let mut player2: Option<GamepadId> = None;
+
+        for (_id, gamepad) in self.controller_handler.gamepads() {
+            if gamepad.is_connected() {
+                if player1.is_none() {
+                    player1 = Some(gamepad.id());
+                } else if player2.is_none() {
+                    player2 = Some(gamepad.id());
+                    break;
+                }
+            }
+        }
+
+        self.p1_controller_id = player1;
+        self.p2_controller_id = player2;
+    }
+
+    pub fn switch_view_mode(&mut self) {
+        match self.view_mode {
+            ViewMode::Debug => {
+                let presenter = self.presenter.as_mut().unwrap();
+
+                presenter.resize_buffer(
+                    draw::GAME_FRAME_WIDTH as u32,
+                    draw::GAME_FRAME_HEIGHT as u32,
+                )
+                .unwrap();
+                self.game_view_size = (draw::GAME_FRAME_WIDTH, draw::GAME_FRAME_HEIGHT);
+
+                let frame = presenter.frame_mut();
+
+                self.view_mode = ViewMode::Normal;
+
+                draw::draw_game_view_bg(frame, self.ui_palette);
+            }
+            ViewMode::Normal => {
+                let presenter = self.presenter.as_mut().unwrap();
+
+                presenter.resize_buffer(
+                    draw::DEBUG_FRAME_WIDTH as u32,
+                    draw::DEBUG_FRAME_HEIGHT as u32,
+                )
+                .unwrap();
+
+                let frame = presenter.frame_mut();
+
+                frame.fill(0);
+
+                self.view_mode = ViewMode::Debug;
+
+                draw::draw_debug_bg(frame, self.ui_palette, self.debug_panel, &mut self.debug_cache);
+            }
+        }
+    }
+
+    /// Opens the debug view in its own window, or closes it if it's already open
+    fn toggle_debug_window(&mut self, event_loop: &ActiveEventLoop) {
+        if self.debug_window.is_some() {
+            // The presenter has to go before the window it draws to
+            self.debug_presenter = None;
+            self.debug_window = None;
+            return;
+        }
+
+        let window_attributes = Window::default_attributes()
+            .with_title("NEmulator Debugger")
+            .with_inner_size(PhysicalSize::new(draw::DEBUG_FRAME_WIDTH as u32, draw::DEBUG_FRAME_HEIGHT as u32));
+
+        let window = match event_loop.create_window(window_attributes) {
+            Ok(window) => window,
+            Err(e) => {
+                println!("Couldn't open debug window: {e}");
+                return;
+            }
+        };
+        let presenter = WindowPresenter::new(&window, draw::DEBUG_FRAME_WIDTH as u32, draw::DEBUG_FRAME_HEIGHT as u32,
+            pixels::wgpu::Color::BLACK, false);
+        let mut presenter = match presenter {
+            Ok(presenter) => presenter,
+            Err(e) => {
+                println!("Couldn't open debug window: {e}");
+                return;
+            }
+        };
+
+        draw::draw_debug_bg(presenter.frame_mut(), self.ui_palette, self.debug_panel, &mut self.debug_window_cache);
+
+        self.debug_window = Some(window);
+        self.debug_presenter = Some(presenter);
+    }
+
+    /// Shows the given panel in the debug view, or goes back to the zero page
+    /// of the memory panel if it's already showing
+    fn toggle_debug_panel(&mut self, panel: DebugPanel) {
+        self.debug_panel = if self.debug_panel == panel { DebugPanel::default() } else { panel };
+
+        // The APU and interrupt logs are only kept while someone is looking
+        self.nes.set_apu_debug(self.debug_panel == DebugPanel::ApuLog);
+        self.nes.set_interrupt_logging(self.debug_panel == DebugPanel::Interrupts);
+
+        self.redraw_debug_bgs();
+    }
+
+    /// Clears the debug views and draws their backgrounds again, so the whole
+    /// of each view gets drawn on the next redraw
+    fn redraw_debug_bgs(&mut self) {
+        if self.view_mode == ViewMode::Debug {
+            if let Some(presenter) = self.presenter.as_mut() {
+                let frame = presenter.frame_mut();
+
+                frame.fill(0);
+                draw::draw_debug_bg(frame, self.ui_palette, self.debug_panel, &mut self.debug_cache);
+            }
+        }
+        if let Some(presenter) = self.debug_presenter.as_mut() {
+            let frame = presenter.frame_mut();
+
+            frame.fill(0);
+            draw::draw_debug_bg(frame, self.ui_palette, self.debug_panel, &mut self.debug_window_cache);
+        }
+    }
+
+    /// Handles events for the debug window. Drawing happens alongside the
+    /// main window's redraws, so only closing, resizing, and input matter here.
+    fn debug_window_event(&mut self, event_loop: &ActiveEventLoop, win_event: WindowEvent) {
+        match win_event {
+            WindowEvent::CloseRequested => {
+                self.debug_presenter = None;
+                self.debug_window = None;
+                self.debug_focused = false;
+            }
+
+            WindowEvent::Focused(focused) => {
+                self.debug_focused = focused;
+                self.update_background();
+            }
+
+            WindowEvent::KeyboardInput { event, .. } => {
+                self.handle_keyboard_input(event, event_loop);
+            }
+
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+            }
+
+            WindowEvent::Resized(new_size) => {
+                if let Some(presenter) = self.debug_presenter.as_mut() {
+                    let _ = presenter.resize_surface(new_size.width, new_size.height);
+                }
+            }
+
+            _ => (),
+        }
+    }
+
+    /// If the input should go to the NES, then
+    /// this function creates the controller state
+    fn handle_nes_input(&mut self, event: KeyEvent) -> bool {
+        // The Family BASIC keyboard takes every key it has a stand-in for,
+        // before the controller and the hotkeys get them
+        if self.nes.expansion_device() == ExpansionDevice::FamilyKeyboard {
+            if let Some(key) = family_keyboard_key(event.physical_key) {
+                let mut input = self.nes.expansion_input();
+                input.set_key(key, event.state == ElementState::Pressed);
+                self.nes.set_expansion_input(input);
+                self.perf_hud.input_arrived();
+
+                return true;
+            }
+        }
+
+        if event.repeat {
+            return false;
+        }
+
+        let new_state = event.state == ElementState::Pressed;
+
+        const PLAYER1_ID: usize = 0;
+        // const PLAYER2_ID: usize = 1; // Keyboard controls player 1 controller
+
+        let controller_update = keyboard_button(event.physical_key).map(|button| ControllerUpdate {
+            button,
+            player_id: PLAYER1_ID,
+            pressed: new_state,
+        });
+
+        if let Some(update) = controller_update {
+            self.update_held_input(update);
+            self.perf_hud.input_arrived();
+            return true;
+        }
+
+        false
+    }
+
+    fn handle_nes_controller_input(&mut self, button: gilrs::Button, val: f32, gamepad_id: GamepadId) {
+        if self.controller_handler.connected_gamepad(gamepad_id).is_some() {
+            
+            let mut button_map = None;
+            let mut player_id = 0;
+
+            if let Some(p1_id) = self.p1_controller_id {
+                if p1_id == gamepad_id {
                     button_map = Some(&self.controller1_map);
                     player_id = 0;
                 }
@@ -508,7 +1833,8 @@ impl NesApp {
                     pressed: pressed,
                 };
 
-                self.nes.update_controllers(controller_update);
+                self.update_held_input(controller_update);
+                self.perf_hud.input_arrived();
 
                 if !pressed {
                     let extra_update = match nes_button {
@@ -540,14 +1866,51 @@ impl NesApp {
                     };
 
                     if let Some(update) = extra_update {
-                        self.nes.update_controllers(update);
+                        self.update_held_input(update);
                     }
                 }
             }
         }
     }
 
-    fn handle_controller_input(&mut self) {
+    /// Presses and releases D-pad directions as the left stick moves (see
+    /// ControllerMapping::stick_directions). In the pause menu, pushing the
+    /// stick a new way counts as pressing that direction.
+    fn handle_stick_input(&mut self, axis: gilrs::Axis, val: f32, gamepad_id: GamepadId, event_loop: &ActiveEventLoop) {
+        let player_id = if self.p1_controller_id == Some(gamepad_id) {
+            0
+        } else if self.p2_controller_id == Some(gamepad_id) {
+            1
+        } else {
+            return;
+        };
+
+        let position = &mut self.stick_positions[player_id];
+        match axis {
+            gilrs::Axis::LeftStickX => position.0 = val,
+            gilrs::Axis::LeftStickY => position.1 = val,
+            _ => return,
+        }
+
+        let button_map = if player_id == 0 { &self.controller1_map } else { &self.controller2_map };
+        let (x, y) = *position;
+
+        for (button, pressed) in button_map.stick_directions(x, y) {
+            if self.stick_held[player_id].is_pressed(button) == pressed {
+                continue;
+            }
+            self.stick_held[player_id].set_button(button, pressed);
+
+            if !self.paused {
+                self.update_held_input(ControllerUpdate { button, player_id, pressed });
+                self.perf_hud.input_arrived();
+            } else if pressed && self.pause_menu.screen != MenuScreen::ControllerMapping {
+                self.handle_menu_button(button, false, event_loop);
+            }
+        }
+    }
+
+    fn handle_controller_input(&mut self, event_loop: &ActiveEventLoop) {
         // Handle controller input
         if let Some(controller_event) = self.controller_handler.next_event() {
 
@@ -570,349 +1933,801 @@ impl NesApp {
                         _ => { val }
                     };
 
-                    if !self.paused {
+                    // The guide/home button opens and closes the pause menu,
+                    // since there's no NES button for it
+                    let mapping = self.paused && self.pause_menu.screen == MenuScreen::ControllerMapping;
+
+                    if button == gilrs::Button::Mode && !mapping {
+                        if val > 0.5 {
+                            if self.paused { self.unpause(); } else { self.pause(); }
+                        }
+                    } else if !self.paused {
                         self.handle_nes_controller_input(button, val, controller_event.id);
                     } else {
-                        self.handle_menu_controller_input(button, val, controller_event.id);
+                        self.handle_menu_controller_input(button, val, controller_event.id, event_loop);
                     }
                 }
 
+                gilrs::EventType::AxisChanged(axis, val, _) => {
+                    self.handle_stick_input(axis, val, controller_event.id, event_loop);
+                }
+
                 _ => {}
             };
         }
     }
 
     fn handle_menu_input(&mut self, event: KeyEvent, event_loop: &ActiveEventLoop) -> bool {
+        // Releasing a key never does anything in the menu
+        if event.state == ElementState::Released {
+            return false;
+        }
+
+        // Escape also backs out of mapping a controller, the same as X
+        let button = match event.physical_key {
+            PhysicalKey::Code(KeyCode::Escape) if self.pause_menu.screen == MenuScreen::ControllerMapping => Some(ControllerButton::B),
+            key => keyboard_button(key),
+        };
 
-        if self.pause_menu.mapping_controller {
+        match button {
+            Some(button) => self.handle_menu_button(button, event.repeat, event_loop),
+            None => false,
+        }
+    }
+
+    fn handle_menu_controller_input(&mut self, button: gilrs::Button, val: f32, gamepad_id: GamepadId, event_loop: &ActiveEventLoop) {
+        if self.pause_menu.screen == MenuScreen::ControllerMapping {
+            self.map_controller_button(button, val, gamepad_id);
+            return;
+        }
 
-            if !self.pause_menu.map_controller1 && !self.pause_menu.map_controller2 {
-                if event.repeat || event.state == ElementState::Released {
-                    return false;
+        let player_id = if self.p1_controller_id == Some(gamepad_id) {
+            0
+        } else if self.p2_controller_id == Some(gamepad_id) {
+            1
+        } else {
+            return;
+        };
+        let button_map = if player_id == 0 { &self.controller1_map } else { &self.controller2_map };
+
+        // Gamepads send an event every time an analog value changes, so only
+        // the moment a button goes from released to pressed counts
+        if val.abs() < 0.5 {
+            for direction in [1.0, -1.0] {
+                if let Some(released) = button_map.get_mapped_button(button, direction) {
+                    self.pause_menu.gamepad_held[player_id].set_button(released, false);
                 }
+            }
+            return;
+        }
 
-                match event.physical_key {
-                    PhysicalKey::Code(KeyCode::KeyZ) |
-                    PhysicalKey::Code(KeyCode::Enter) => {
-                        self.pause_menu.controller_read = ControllerReadState::new();
+        let Some(nes_button) = button_map.get_mapped_button(button, val) else {
+            return;
+        };
 
-                        if self.pause_menu.player1_map_selected {
-                            self.controller1_map = ControllerMapping::default();
-                            self.pause_menu.map_controller1 = true;
-                        } else {
-                            self.controller2_map = ControllerMapping::default();
-                            self.pause_menu.map_controller2 = true;
-                        }
+        if self.pause_menu.gamepad_held[player_id].is_pressed(nes_button) {
+            return;
+        }
+        self.pause_menu.gamepad_held[player_id].set_button(nes_button, true);
 
-                        self.play_menu_sound(&self.pause_menu.select_sound);
+        self.handle_menu_button(nes_button, false, event_loop);
+    }
 
-                        true
-                    },
-                    PhysicalKey::Code(KeyCode::KeyX) => {
-                        self.pause_menu.mapping_controller = false;
+    // Maps the gamepad button to whichever NES button is being asked for, then
+    // moves on to the next one
+    fn map_controller_button(&mut self, button: gilrs::Button, val: f32, gamepad_id: GamepadId) {
+        if val.abs() < 0.5 {
+            return;
+        }
 
-                        self.play_menu_sound(&self.pause_menu.reject_sound);
+        let Some(nes_button) = self.pause_menu.mapping_button() else {
+            return;
+        };
 
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::ArrowUp) |
-                    PhysicalKey::Code(KeyCode::ArrowDown) |
-                    PhysicalKey::Code(KeyCode::ShiftRight) => {
-                        self.pause_menu.player1_map_selected = !self.pause_menu.player1_map_selected;
+        // A gamepad can only belong to one player
+        let player_id = self.pause_menu.mapping_player;
+        if player_id == 0 {
+            if self.p2_controller_id == Some(gamepad_id) {
+                self.p2_controller_id = None;
+            }
+            self.p1_controller_id = Some(gamepad_id);
+        } else {
+            if self.p1_controller_id == Some(gamepad_id) {
+                self.p1_controller_id = None;
+            }
+            self.p2_controller_id = Some(gamepad_id);
+        }
 
-                        self.play_menu_sound(&self.pause_menu.move_sound);
-    
-                        true
-                    },
-                    PhysicalKey::Code(KeyCode::ArrowLeft) |
-                    PhysicalKey::Code(KeyCode::ArrowRight) => {
-                        self.play_menu_sound(&self.pause_menu.reject_sound);
-    
-                        true
-                    },
-                    
-                    _ => { false }
+        let button_map = if player_id == 0 { &mut self.controller1_map } else { &mut self.controller2_map };
+        button_map.set_button_mapping(nes_button, button, val);
+
+        let binding = if val > 0.0 { format!("{button:?}") } else { format!("{button:?} -") };
+
+        if !self.pause_menu.controller_mapping.capture(Some(binding)) {
+            self.pause_menu.screen = MenuScreen::Main;
+
+            // Releases aren't tracked while mapping, so start over with just
+            // the last button mapped held down (it shouldn't count as a press
+            // once it's back to navigating the menu)
+            let button_map = if player_id == 0 { &self.controller1_map } else { &self.controller2_map };
+
+            self.pause_menu.gamepad_held = [NesController::new(); 2];
+            if let Some(held) = button_map.get_mapped_button(button, val) {
+                self.pause_menu.gamepad_held[player_id].set_button(held, true);
+            }
+        }
+    }
+
+    /// Does whatever the NES button does on the menu page that's showing (see
+    /// menu.rs and widgets.rs). Returns a bool reporting whether the menu
+    /// used it.
+    fn handle_menu_button(&mut self, button: ControllerButton, repeat: bool, event_loop: &ActiveEventLoop) -> bool {
+        let screen = self.pause_menu.screen;
+
+        if repeat && !screen.repeats() {
+            return false;
+        }
+
+        let Some(action) = screen.action(button) else {
+            return false;
+        };
+
+        // Shift moves sliders in finer steps
+        let event = if self.modifiers.state().shift_key() {
+            self.pause_menu.page_mut().handle_fine(action)
+        } else {
+            self.pause_menu.page_mut().handle(action)
+        };
+
+        match event {
+            WidgetEvent::FocusMoved => self.play_menu_sound(&self.pause_menu.move_sound),
+            // Hitting the end of something only complains once, not on every
+            // repeat of a held key
+            WidgetEvent::Rejected => {
+                if !repeat {
+                    self.play_menu_sound(&self.pause_menu.reject_sound);
                 }
-            } else {
-                match event.physical_key {
-                    PhysicalKey::Code(KeyCode::Escape) |
-                    PhysicalKey::Code(KeyCode::KeyX) => {
-                        self.pause_menu.mapping_controller = false;
-                        self.pause_menu.map_controller1 = false;
-                        self.pause_menu.map_controller2 = false;
-
-                        self.pause_menu.controller_read = ControllerReadState::new();
-                        
-                        self.play_menu_sound(&self.pause_menu.reject_sound);
-
-                        true
-                    }
+            }
+            WidgetEvent::Back => self.leave_menu_page(),
+            WidgetEvent::Pressed(id) => self.menu_widget_pressed(id, event_loop),
+            WidgetEvent::Toggled(id, on) => self.menu_widget_toggled(id, on),
+            WidgetEvent::Changed(id, value) => self.menu_widget_changed(id, value),
+            WidgetEvent::Alternate(WidgetId::Channel(i)) => {
+                self.nes.solo_audio_channel(NesChannel::ALL[i]);
+
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
+            WidgetEvent::Alternate(WidgetId::VideoFilter(i)) if !self.video_filters.filter(i).settings().is_empty() => {
+                self.pause_menu.open_filter_settings(i, self.video_filters.filter(i));
+
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
+            WidgetEvent::Alternate(_) | WidgetEvent::Capturing(_) => {
+                self.play_menu_sound(&self.pause_menu.reject_sound);
+            }
+        }
+
+        true
+    }
 
-                    _ => { false }
+    // B on a page of the pause menu
+    fn leave_menu_page(&mut self) {
+        match self.pause_menu.screen {
+            MenuScreen::Main => self.unpause(),
+            screen => {
+                if screen == MenuScreen::ControllerMapping {
+                    self.pause_menu.controller_mapping.cancel_capture();
                 }
+                self.pause_menu.screen = match screen {
+                    MenuScreen::FilterSettings => MenuScreen::VideoFilters,
+                    _ => MenuScreen::Main,
+                };
+
+                self.play_menu_sound(&self.pause_menu.reject_sound);
             }
         }
-        // Using volume slider
-        else if self.pause_menu.setting_volume {
+    }
 
-            // Ignore when keys are released
-            if event.state == ElementState::Released {
-                return false;
+    fn menu_widget_pressed(&mut self, id: WidgetId, event_loop: &ActiveEventLoop) {
+        match (self.pause_menu.screen, id) {
+            (_, WidgetId::Continue) => {
+                self.unpause();
             }
 
-            match event.physical_key {
-                PhysicalKey::Code(KeyCode::KeyZ) |
-                PhysicalKey::Code(KeyCode::Enter) => {
-                    self.update_audio_volume(self.pause_menu.volume_percent);
-                    self.pause_menu.setting_volume = false;
+            (_, WidgetId::RetryFromState) => {
+                if self.retry_from_state() {
+                    self.unpause();
+                }
+            }
 
-                    self.play_menu_sound(&self.pause_menu.select_sound);
+            (_, WidgetId::ControllerMap) => {
+                self.pause_menu.open(MenuScreen::ControllerSelect);
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::ArrowUp) => {
-                    self.play_menu_sound(&self.pause_menu.reject_sound);
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::ArrowDown) => {
-                    self.play_menu_sound(&self.pause_menu.reject_sound);
+            (MenuScreen::Main, WidgetId::Volume) => {
+                self.pause_menu.open(MenuScreen::Volume);
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::ShiftRight) => {
-                    self.play_menu_sound(&self.pause_menu.reject_sound);
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::ArrowLeft) => {
-                    if self.pause_menu.volume_percent > 1e-3 {
-                        self.pause_menu.volume_percent -= VOLUME_CONTROL_SPEED;
-    
-                        if self.pause_menu.volume_percent < 0.0 {
-                            self.pause_menu.volume_percent = 0.0;
-                            self.play_menu_sound(&self.pause_menu.reject_sound);
-                        } else {
-                            self.play_menu_sound(&self.pause_menu.move_sound);
-                        }
-    
-                        self.update_audio_volume(self.pause_menu.volume_percent);
-                        
-                    } else if !event.repeat {
-                        self.play_menu_sound(&self.pause_menu.reject_sound);
-                    }
+            // Confirming the volume slider itself
+            (_, WidgetId::Volume) => {
+                self.update_audio_volume(self.pause_menu.volume_percent);
+                self.pause_menu.screen = MenuScreen::Main;
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::ArrowRight) => {
-                    if self.pause_menu.volume_percent < 0.999 {
-                        self.pause_menu.volume_percent += VOLUME_CONTROL_SPEED;
-    
-                        if self.pause_menu.volume_percent > 1.0 {
-                            self.pause_menu.volume_percent = 1.0;
-                            self.play_menu_sound(&self.pause_menu.reject_sound);
-                        } else {
-                            self.play_menu_sound(&self.pause_menu.move_sound);
-                        }
-    
-                        self.update_audio_volume(self.pause_menu.volume_percent);
-                        
-                    } else if !event.repeat {
-                        self.play_menu_sound(&self.pause_menu.reject_sound);
-                    }
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
 
-                    true
-                },
-                
-                _ => { false }
+            (_, WidgetId::AudioChannels) => {
+                self.pause_menu.open(MenuScreen::AudioChannels);
+
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
+
+            (_, WidgetId::CartInfo) => {
+                self.pause_menu.open(MenuScreen::CartInfo);
+
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
+
+            (_, WidgetId::VideoFilters) => {
+                self.pause_menu.open(MenuScreen::VideoFilters);
+
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
+
+            // Confirming a filter setting goes back to the list of filters
+            (_, WidgetId::FilterSetting(_)) => {
+                self.pause_menu.screen = MenuScreen::VideoFilters;
+
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
+
+            (_, WidgetId::Back) => {
+                self.pause_menu.screen = MenuScreen::Main;
+
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
+
+            (_, WidgetId::Player(player_id)) => {
+                // Only the buttons get mapped again, the stick settings stay
+                let button_map = if player_id == 0 { &mut self.controller1_map } else { &mut self.controller2_map };
+                let mut new_map = ControllerMapping::default();
+                new_map.set_stick_deadzone(button_map.stick_deadzone());
+                new_map.set_diagonal_bias(button_map.diagonal_bias());
+                *button_map = new_map;
+
+                let mapping = &mut self.pause_menu.controller_mapping;
+                mapping.title = fill(Text::Player, &[&(player_id + 1).to_string()]);
+                for widget_id in mapping.widgets().iter().map(|widget| widget.id).collect::<Vec<_>>() {
+                    mapping.set_binding(widget_id, None);
+                }
+                mapping.start_capture();
+
+                self.pause_menu.mapping_player = player_id;
+                self.pause_menu.screen = MenuScreen::ControllerMapping;
+
+                self.play_menu_sound(&self.pause_menu.select_sound);
+            }
+
+            (_, WidgetId::Quit) => { 
+                println!("Quit button pressed, exiting.");
+                event_loop.exit();
+            }
+
+            _ => {
+                self.play_menu_sound(&self.pause_menu.reject_sound);
             }
         }
-        // Selecting menu option
-        else {
-            // Ignore when keys are released
-            if event.state == ElementState::Released || event.repeat {
-                return false;
+    }
+
+    fn menu_widget_toggled(&mut self, id: WidgetId, on: bool) {
+        match id {
+            WidgetId::GameProfile => {
+                self.pause_menu.has_game_profile = on;
+                self.save_game_profile(on);
             }
 
-            let handled = match event.physical_key {
-                PhysicalKey::Code(KeyCode::KeyZ) |
-                PhysicalKey::Code(KeyCode::Enter) => {
-                    match self.pause_menu.selected {
-                        PauseMenuItem::Continue => {
-                            self.unpause();
-                        }
+            WidgetId::NoLimit => {
+                self.limit_fps = !on;
+                self.nes.set_audio_ducked(!self.limit_fps);
+                self.update_window_title(true);
+            }
+
+            WidgetId::AudioSync => {
+                self.sync_mode = if on { SyncMode::Audio } else { SyncMode::Timer };
+            }
 
-                        PauseMenuItem::ControllerMap => {
-                            self.pause_menu.mapping_controller = true;
-                            self.pause_menu.controller_read = ControllerReadState::new();
+            // Channels are shown as on when they're not muted
+            WidgetId::Channel(i) => {
+                let channel = NesChannel::ALL[i];
 
-                            self.play_menu_sound(&self.pause_menu.select_sound);
-                        }
+                if self.nes.muted_audio_channels().is_muted(channel) == on {
+                    self.nes.toggle_audio_channel(channel);
+                }
+            }
 
-                        PauseMenuItem::Volume => {
-                            self.pause_menu.setting_volume = true;
+            WidgetId::VideoFilter(i) => {
+                self.video_filters.set_enabled(i, on);
+                println!("{} filter {}", self.video_filters.filter(i).name(), if on { "on" } else { "off" });
+            }
 
-                            self.play_menu_sound(&self.pause_menu.select_sound);
-                        }
+            _ => {}
+        }
 
-                        PauseMenuItem::NoLimit => {
-                            self.limit_fps = !self.limit_fps;
-                            self.nes.set_block_audio_samples(!self.limit_fps);
+        self.play_menu_sound(&self.pause_menu.select_sound);
+    }
 
-                            self.play_menu_sound(&self.pause_menu.select_sound);
-                        }
+    fn menu_widget_changed(&mut self, id: WidgetId, value: usize) {
+        match id {
+            WidgetId::Speed => {
+                self.set_speed(value);
+            }
+
+            WidgetId::Volume => {
+                self.pause_menu.volume_percent = value as f32 / 100.0;
+                self.update_audio_volume(self.pause_menu.volume_percent);
+            }
+
+            WidgetId::StickDeadzone(player_id) => {
+                let button_map = if player_id == 0 { &mut self.controller1_map } else { &mut self.controller2_map };
+                button_map.set_stick_deadzone(value as f32 / 100.0);
+            }
+
+            WidgetId::DiagonalBias(player_id) => {
+                let button_map = if player_id == 0 { &mut self.controller1_map } else { &mut self.controller2_map };
+                button_map.set_diagonal_bias(value as f32 / 100.0);
+            }
+
+            WidgetId::GpuShader => {
+                self.gpu_shader = GpuShader::ALL[value];
+                println!("GPU shader: {}", self.gpu_shader.name());
+
+                if !self.presenter.as_ref().is_some_and(|presenter| presenter.supports_shaders()) {
+                    self.use_cpu_shader_fallback();
+                }
+            }
+
+            WidgetId::FilterSetting(i) => {
+                self.video_filters.filter_mut(self.pause_menu.settings_filter).set_setting(i, value);
+            }
+
+            WidgetId::Expansion => {
+                let device = ExpansionDevice::ALL[value];
+
+                self.nes.set_expansion_device(device);
+                println!("Expansion port: {}", device.name());
+            }
+
+            _ => {}
+        }
+
+        self.play_menu_sound(&self.pause_menu.move_sound);
+    }
+
+    // Copies the settings shown in the pause menu into its widgets, since
+    // some of them can also be changed with hotkeys
+    fn sync_menu(&mut self) {
+        let menu = &mut self.pause_menu;
+
+        menu.main.set_value(WidgetId::Speed, menu.speed_percent);
+        let expansion = self.nes.expansion_device();
+        menu.main.set_value(WidgetId::Expansion, ExpansionDevice::ALL.iter().position(|&device| device == expansion).unwrap_or(0));
+        menu.main.set_toggle(WidgetId::GameProfile, menu.has_game_profile);
+        menu.main.set_toggle(WidgetId::NoLimit, !self.limit_fps);
+        menu.main.set_toggle(WidgetId::AudioSync, self.sync_mode == SyncMode::Audio);
+
+        for (player_id, button_map) in [&self.controller1_map, &self.controller2_map].into_iter().enumerate() {
+            menu.controller_select.set_value(WidgetId::StickDeadzone(player_id), (button_map.stick_deadzone() * 100.0).round() as usize);
+            menu.controller_select.set_value(WidgetId::DiagonalBias(player_id), (button_map.diagonal_bias() * 100.0).round() as usize);
+        }
+
+        menu.volume.set_value(WidgetId::Volume, (menu.volume_percent * 100.0).round() as usize);
+        menu.volume.hint = Some(volume_hint(menu.volume_percent, menu.muted));
+
+        let muted_channels = self.nes.muted_audio_channels();
+        for (i, channel) in NesChannel::ALL.iter().enumerate() {
+            menu.channels.set_toggle(WidgetId::Channel(i), !muted_channels.is_muted(*channel));
+        }
+
+        menu.cart_info.hint = Some(cart_info_hint(&self.nes));
+
+        for i in 0..self.video_filters.len() {
+            menu.video_filters.set_toggle(WidgetId::VideoFilter(i), self.video_filters.enabled(i));
+        }
+        menu.video_filters.set_value(WidgetId::GpuShader, GpuShader::ALL.iter().position(|&shader| shader == self.gpu_shader).unwrap_or(0));
+        if menu.screen == MenuScreen::FilterSettings {
+            let settings = self.video_filters.filter(menu.settings_filter).settings();
+            for (i, setting) in settings.iter().enumerate() {
+                menu.filter_settings.set_value(WidgetId::FilterSetting(i), setting.value);
+            }
+        }
+    }
+
+    fn handle_keyboard_input(&mut self, event: KeyEvent, event_loop: &ActiveEventLoop) {
+        let handled = if !self.paused {
+            self.handle_nes_input(event.clone())
+        } else {
+            self.handle_menu_input(event.clone(), event_loop)
+        };
 
-                        PauseMenuItem::Quit => { 
-                            println!("Quit button pressed, exiting.");
-                            event_loop.exit();
+        if !handled {
+            match event {
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyV),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    if self.can_debug {
+                        // Shift + V pops the debug view out into its own window
+                        if self.modifiers.state().shift_key() {
+                            self.toggle_debug_window(event_loop);
+                        } else {
+                            self.switch_view_mode();
                         }
                     }
+                },
+
+                // Y turns the picture another quarter turn, and Shift + Y
+                // flips it left to right
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyY),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    if self.modifiers.state().shift_key() {
+                        self.mirror = !self.mirror;
+                        println!("Mirrored: {}", self.mirror);
+                    } else {
+                        self.rotation = self.rotation.next();
+                        println!("Rotation: {} degrees", self.rotation.name());
+                    }
+                }
+
+                // O cycles through the picture crop modes
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyO),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    self.crop = self.crop.next();
+                    println!("Crop mode: {:?}", self.crop);
+                }
+
+                // Minus and equals slow down and speed up the emulation
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(key @ (KeyCode::Minus | KeyCode::Equal)),
+                    state: ElementState::Pressed,
+                    ..
+                } => {
+                    self.change_speed(key == KeyCode::Equal);
+                }
+
+                // Pages through CPU memory in the debug view's memory panel
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(key @ (KeyCode::PageUp | KeyCode::PageDown)),
+                    state: ElementState::Pressed,
+                    ..
+                } if self.can_debug => {
+                    if let DebugPanel::Memory(page) = self.debug_panel {
+                        let page = if key == KeyCode::PageDown { page.wrapping_add(1) } else { page.wrapping_sub(1) };
+
+                        self.debug_panel = DebugPanel::Memory(page);
+                    }
+                }
+
+                // Swaps the memory panel of the debug view for the APU log
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyA),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    if self.can_debug {
+                        self.toggle_debug_panel(DebugPanel::ApuLog);
+                    }
+                }
+
+                // Swaps the memory panel of the debug view for the PPU
+                // background shift registers
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyS),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    if self.can_debug {
+                        self.toggle_debug_panel(DebugPanel::PpuShifters);
+                    }
+                }
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::KeyX) => {
-                    self.unpause();
+                // Swaps the memory panel of the debug view for the mapper's
+                // registers
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyK),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    self.toggle_debug_panel(DebugPanel::Mapper);
+                }
 
-                    true
+                // Swaps the memory panel of the debug view for the
+                // interrupt log
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyI),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    self.toggle_debug_panel(DebugPanel::Interrupts);
                 }
-                PhysicalKey::Code(KeyCode::ArrowUp) => {
-                    let cursor_moved = self.pause_menu.selected.go_prev(false);
 
-                    self.play_menu_sound(
-                        if cursor_moved { &self.pause_menu.move_sound }
-                        else { &self.pause_menu.reject_sound }
-                    );
+                // Swaps the memory panel of the debug view for the loaded
+                // ROM's hashes
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F9),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    self.toggle_debug_panel(DebugPanel::RomInfo);
+                }
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::ArrowDown) => {
-                    let cursor_moved = self.pause_menu.selected.go_next(false);
+                // Swaps the memory panel of the debug view for the report
+                // of unsupported things the game has done
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyU),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    self.toggle_debug_panel(DebugPanel::Unsupported);
+                }
 
-                    self.play_menu_sound(
-                        if cursor_moved { &self.pause_menu.move_sound }
-                        else { &self.pause_menu.reject_sound }
-                    );
+                // Tints the debug view's screen by how recently the game wrote
+                // to the nametable/attribute bytes behind each tile
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyH),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    self.overlays.write_heatmap = !self.overlays.write_heatmap;
+                }
 
-                    true
+                // Marks the scanlines the game wrote PPU registers on last
+                // frame, when it was started with --writeassert
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyM),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.nes.register_write_assert() => {
+                    self.overlays.write_markers = !self.overlays.write_markers;
                 }
-                PhysicalKey::Code(KeyCode::ShiftRight) => {
-                    let cursor_moved = self.pause_menu.selected.go_next(true);
 
-                    self.play_menu_sound(
-                        if cursor_moved { &self.pause_menu.move_sound }
-                        else { &self.pause_menu.reject_sound }
-                    );
+                // Lines across the picture where the game changed the scroll
+                // or nametable mid-frame (status bars, parallax)
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyL),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    self.overlays.scroll_splits = !self.overlays.scroll_splits;
+                }
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::ArrowLeft) => {
-                    self.play_menu_sound(&self.pause_menu.reject_sound);
+                // Cycles the palette the pagetables are drawn in, from
+                // greyscale through palettes 0-7 and back to greyscale
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyP),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    self.pgtbl_palette = match self.pgtbl_palette {
+                        None => Some(0),
+                        Some(7) => None,
+                        Some(palette) => Some(palette + 1),
+                    };
+                }
 
-                    true
-                },
-                PhysicalKey::Code(KeyCode::ArrowRight) => {
-                    self.play_menu_sound(&self.pause_menu.reject_sound);
+                // C steps an instruction, or a single CPU cycle with shift
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyC),
+                    state: ElementState::Pressed,
+                    ..
+                } => {
+                    if self.paused && self.can_debug {
+                        if self.modifiers.state().shift_key() {
+                            self.nes.cycle_cpu(1);
+                        } else {
+                            self.nes.cycle_instr();
+                        }
+                        self.handle_nes_events();
+                    }
+                }
 
-                    true
-                },
-                
-                _ => { false }
-            };
+                // N runs to the start of the next scanline, or to the start of
+                // vblank with shift
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyN),
+                    state: ElementState::Pressed,
+                    ..
+                } if self.paused && self.can_debug => {
+                    let (_, scanline) = self.nes.ppu_position();
 
-            handled
-        }
-    }
+                    if self.modifiers.state().shift_key() {
+                        self.nes.cycle_until_scanline(241, 1);
+                    } else {
+                        self.nes.cycle_until_scanline((scanline + 1) % 262, 0);
+                    }
+                    self.handle_nes_events();
+                }
 
-    fn handle_menu_controller_input(&mut self, button: gilrs::Button, val: f32, gamepad_id: GamepadId) {
-        if val.abs() < 0.5 || !self.pause_menu.mapping_controller {
-            return;
-        }
+                // G runs until the instruction about to run comes around again
+                // (the next time through a loop, say)
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyG),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.paused && self.can_debug => {
+                    const MAX_FRAMES: u64 = 60 * 10;
 
-        let mut mapped_button = false;
-                
-        if self.pause_menu.map_controller1 {
-            if let Some(p2_id) = self.p2_controller_id {
-                if p2_id == gamepad_id {
-                    self.p2_controller_id = None;
+                    let pc = self.nes.get_cpu_state().pc;
+                    let give_up_at = self.nes.frames_run() + MAX_FRAMES;
+
+                    // A breakpoint can stop it before it gets there
+                    if !self.nes.cycle_until_pc(pc, MAX_FRAMES) && self.nes.frames_run() >= give_up_at {
+                        println!("${pc:04X} didn't run again in {MAX_FRAMES} frames");
+                    }
+                    self.handle_nes_events();
                 }
-            }
 
-            self.p1_controller_id = Some(gamepad_id);
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyF),
+                    state: ElementState::Pressed,
+                    ..
+                } => {
+                    if self.paused && self.can_debug {
+                        self.advance_paused_frame();
+                    }
+                }
 
-            self.controller1_map.set_button_mapping(
-                self.pause_menu.controller_read.button(),
-                button,
-                val,
-            );
-            mapped_button = true;
+                // F3 shows/hides the performance HUD
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F3),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    self.perf_hud.shown = !self.perf_hud.shown;
 
-        } else if self.pause_menu.map_controller2 {
-            if let Some(p1_id) = self.p1_controller_id {
-                if p1_id == gamepad_id {
-                    self.p1_controller_id = None;
+                    // The HUD is drawn over the debug view, which otherwise
+                    // only draws over the parts that change
+                    if !self.perf_hud.shown {
+                        self.redraw_debug_bgs();
+                    }
                 }
-            }
-
-            self.p2_controller_id = Some(gamepad_id);
 
-            self.controller2_map.set_button_mapping(
-                self.pause_menu.controller_read.button(),
-                button,
-                val,
-            );
-            mapped_button = true;
-        }
+                // F4 shows/hides the ghost run
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F4),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.ghost.is_some() => {
+                    if let Some(ghost) = &mut self.ghost {
+                        ghost.shown = !ghost.shown;
+                    }
+                }
 
-        if mapped_button {
-            self.pause_menu.controller_read = self.pause_menu.controller_read.next();
-        
-            if self.pause_menu.controller_read.finished() {
-                self.pause_menu.mapping_controller = false;
-                self.pause_menu.map_controller1 = false;
-                self.pause_menu.map_controller2 = false;
-            }
-        }
-    }
+                // F12 saves a picture of the debug view, or with shift, one
+                // each of the pagetables, nametables and OAM
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F12),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    if self.modifiers.state().shift_key() {
+                        for view in CaptureView::PANELS {
+                            self.save_capture(view);
+                        }
+                    } else {
+                        self.save_capture(CaptureView::Debug);
+                    }
+                }
 
-    fn handle_keyboard_input(&mut self, event: KeyEvent, event_loop: &ActiveEventLoop) {
-        let handled = if !self.paused {
-            self.handle_nes_input(event.clone())
-        } else {
-            self.handle_menu_input(event.clone(), event_loop)
-        };
+                // F10 dumps the game's memory (PRG RAM, CHR RAM, VRAM, OAM
+                // and palettes) to files, or with shift, loads them back
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F10),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.can_debug => {
+                    if self.modifiers.state().shift_key() {
+                        self.restore_memory_dumps();
+                    } else {
+                        self.save_memory_dumps();
+                    }
+                }
 
-        if !handled {
-            match event {
+                // 0 mutes/unmutes all sound
                 KeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::KeyV),
+                    physical_key: PhysicalKey::Code(KeyCode::Digit0),
                     state: ElementState::Pressed,
                     repeat: false,
                     ..
                 } => {
-                    if self.can_debug {
-                        self.switch_view_mode();
-                    }
-                },
+                    self.pause_menu.muted = !self.pause_menu.muted;
+                    self.update_audio_volume(self.pause_menu.volume_percent);
+                }
 
+                // Number keys 1-6 mute/unmute the audio channels, and
+                // shift + number solos the channel
                 KeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::KeyC),
+                    physical_key: PhysicalKey::Code(
+                        key @ (KeyCode::Digit1 | KeyCode::Digit2 | KeyCode::Digit3 |
+                               KeyCode::Digit4 | KeyCode::Digit5 | KeyCode::Digit6)
+                    ),
                     state: ElementState::Pressed,
+                    repeat: false,
                     ..
                 } => {
-                    if self.paused && self.can_debug {
-                        self.nes.cycle_instr();
+                    let channel = match key {
+                        KeyCode::Digit1 => NesChannel::Pulse1,
+                        KeyCode::Digit2 => NesChannel::Pulse2,
+                        KeyCode::Digit3 => NesChannel::Triangle,
+                        KeyCode::Digit4 => NesChannel::Noise,
+                        KeyCode::Digit5 => NesChannel::DMC,
+                        _ => NesChannel::Expansion,
+                    };
+
+                    if self.modifiers.state().shift_key() {
+                        self.nes.solo_audio_channel(channel);
+                    } else {
+                        self.nes.toggle_audio_channel(channel);
                     }
                 }
 
+                // 7 and 8 hide/show the background and sprites
                 KeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::KeyF),
+                    physical_key: PhysicalKey::Code(KeyCode::Digit7),
                     state: ElementState::Pressed,
+                    repeat: false,
                     ..
                 } => {
-                    if self.paused && self.can_debug {
-                        self.nes.cycle_until_frame();
-                        self.nes.swap_screen_buffers();
-                    }
+                    self.nes.set_bg_hidden(!self.nes.bg_hidden());
+                }
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::Digit8),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    self.nes.set_sprites_hidden(!self.nes.sprites_hidden());
+                }
+
+                // Normally R has to be held to reset (so it isn't hit by
+                // accident), but a jammed or crashed game can't be hurt by a
+                // stray reset
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyR),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } if self.nes.cpu_jammed_at().is_some() || self.crash_notice.shown() => {
+                    self.reset_nes();
+                    self.reset = true;
                 }
 
                 KeyEvent {
@@ -922,7 +2737,7 @@ impl NesApp {
                     ..
                 } => {
                     if !self.reset {
-                        self.nes.reset();
+                        self.reset_nes();
                         self.reset = true;
                     }
                 }
@@ -936,6 +2751,70 @@ impl NesApp {
                     self.reset = false;
                 }
 
+                // Q picks recording back up partway through a movie
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyQ),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    self.resume_movie_recording();
+                }
+
+                // F5 saves to the selected slot, F6 picks the next slot and
+                // F7 loads it (see "ON HOW SAVE SLOTS WORK")
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F5),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    self.save_to_slot();
+                }
+
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F6),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    self.save_slots.select_next();
+                    self.osd.show(&fill(Text::SlotSelected, &[&self.save_slots.selected().to_string()]));
+                    self.update_window_title(true);
+                }
+
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F7),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    self.load_from_slot(self.save_slots.selected());
+                }
+
+                // F8 retries from the retry anchor
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::F8),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    self.retry_from_state();
+                }
+
+                // T replaces the game's thumbnail with what's on screen
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(KeyCode::KeyT),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                } => {
+                    match self.thumbnails.capture(self.nes.rom_crc32(), self.nes.screen_buf_slice()) {
+                        Ok(()) => println!("Saved thumbnail"),
+                        Err(e) => println!("{e}"),
+                    }
+                }
+
                 KeyEvent {
                     physical_key: PhysicalKey::Code(KeyCode::Escape),
                     state: ElementState::Pressed,
@@ -949,8 +2828,8 @@ impl NesApp {
 
                         // Pause menu gui can't be opened if debug is enabled
                         if !self.can_debug {
-                            if let Some(buf) = self.pixel_buf.as_mut() {
-                                let frame = buf.frame_mut();
+                            if let Some(presenter) = self.presenter.as_mut() {
+                                let frame = presenter.frame_mut();
         
                                 draw_paused_menu_bg(frame);
                             }
@@ -963,30 +2842,161 @@ impl NesApp {
         }
     }
 
+    /// Takes over the audio output. The sink should be paused, it's started
+    /// once its volume is set so the first samples aren't played too loud.
     pub fn attatch_sound_sink(&mut self, sink: Sink) {
         self.audio_sink = Some(sink);
         self.update_audio_volume(self.pause_menu.volume_percent);
+
+        if let Some(sink) = &self.audio_sink {
+            sink.play();
+        }
     }
 
     fn update_audio_volume(&mut self, max_volume_percent: f32) {
+        let muted = self.pause_menu.muted || (self.mute_in_background && self.in_background());
+
         if let Some(sink) = &mut self.audio_sink {
-            sink.set_volume(MAX_VOLUME * max_volume_percent);
+            sink.set_volume(output_gain(max_volume_percent, muted));
+        }
+    }
+
+    // Whether neither of the emulator's windows has focus
+    fn in_background(&self) -> bool {
+        !self.main_focused && !self.debug_focused
+    }
+
+    // Pauses and mutes (if the config says to) when the app goes into the
+    // background, and undoes it when the app comes back. Moving focus
+    // between the game and debug windows doesn't count.
+    fn update_background(&mut self) {
+        let in_background = self.in_background();
+
+        // Muted first, so the pause sound isn't heard
+        self.update_audio_volume(self.pause_menu.volume_percent);
+
+        if in_background && self.pause_in_background && !self.paused {
+            self.paused_in_background = true;
+            self.pause();
+        } else if !in_background && self.paused_in_background {
+            self.unpause();
         }
     }
 
     fn play_menu_sound(&self, sound: &MenuSound) {
         if let Some(stream) = self.audio_stream_queue.clone() {
 
-            stream.lock().unwrap().clear();
+            stream.clear();
 
             sound.play_to_stream(stream);
         }
     }
 
+    /// Steps the emulation speed up or down. Returns a bool reporting whether
+    /// the speed changed (it won't past the min/max speeds).
+    fn change_speed(&mut self, faster: bool) -> bool {
+        let old_speed = self.pause_menu.speed_percent;
+
+        let new_speed = if faster {
+            std::cmp::min(old_speed + SPEED_STEP_PERCENT, MAX_SPEED_PERCENT)
+        } else {
+            std::cmp::max(old_speed.saturating_sub(SPEED_STEP_PERCENT), MIN_SPEED_PERCENT)
+        };
+
+        self.set_speed(new_speed);
+
+        new_speed != old_speed
+    }
+
+    /// Sets the emulation speed, in percent of normal speed
+    fn set_speed(&mut self, speed_percent: usize) {
+        self.pause_menu.speed_percent = speed_percent;
+        self.nes.set_emulation_speed(speed_percent as f64 / 100.0);
+        self.update_window_title(true);
+    }
+
+    /// Counts a frame towards the window title's FPS, or updates the title
+    /// immediately if refresh_now is set
+    fn update_window_title(&mut self, refresh_now: bool) {
+        if let Some(window) = &self.window {
+            let info = TitleInfo {
+                game_name: self.nes.rom_name(),
+                paused: self.paused,
+                fast_forward: !self.limit_fps,
+                speed_percent: self.pause_menu.speed_percent,
+                save_slot: self.save_slots.selected(),
+            };
+
+            if refresh_now {
+                self.window_title.refresh(window, info);
+            } else {
+                self.window_title.tick(window, info);
+            }
+        }
+    }
+
+    /// Saves a picture of one of the debugger's views to the captures folder
+    fn save_capture(&mut self, view: CaptureView) {
+        let settings = CaptureSettings {
+            palette: self.ui_palette,
+            panel: self.debug_panel,
+            overlays: self.overlays,
+            pgtbl_palette: self.pgtbl_palette,
+            fps: self.fps,
+        };
+        let path = capture::capture_path(&self.nes, view);
+
+        match capture::capture(&mut self.nes, view, settings).save(Path::new(&path)) {
+            Ok(()) => println!("Saved {} capture to {path}", view.name()),
+            Err(e) => println!("{e}"),
+        }
+    }
+
+    /// Writes each region of memory the cart has to the dumps folder
+    fn save_memory_dumps(&self) {
+        if let Err(e) = fs::create_dir_all(DUMP_DIR) {
+            println!("Couldn't create dump folder '{DUMP_DIR}': {e}");
+            return;
+        }
+
+        for region in MemoryRegion::ALL {
+            let path = self.memory_dump_path(region);
+
+            let result = self.nes.dump_memory(region)
+                .and_then(|bytes| fs::write(&path, bytes).map_err(|e| format!("Couldn't write '{path}': {e}")));
+
+            match result {
+                Ok(()) => println!("Dumped {} to {path}", region.name()),
+                Err(e) => println!("Didn't dump {}: {e}", region.name()),
+            }
+        }
+    }
+
+    /// Loads back whichever of the game's dumps are in the dumps folder
+    fn restore_memory_dumps(&mut self) {
+        for region in MemoryRegion::ALL {
+            let path = self.memory_dump_path(region);
+
+            let Ok(bytes) = fs::read(&path) else {
+                continue;
+            };
+
+            match self.nes.restore_memory(region, &bytes) {
+                Ok(()) => println!("Loaded {} from {path}", region.name()),
+                Err(e) => println!("Couldn't load {path}: {e}"),
+            }
+        }
+    }
+
+    fn memory_dump_path(&self, region: MemoryRegion) -> String {
+        format!("{DUMP_DIR}/{}-{}.bin", self.nes.rom_name(), region.name())
+    }
+
     fn pause(&mut self) {
         self.paused = true;
+        self.update_window_title(true);
         if let Some(stream) = self.audio_stream_queue.clone() {
-            stream.lock().unwrap().clear();
+            stream.clear();
 
             self.play_menu_sound(&self.pause_menu.pause_sound);
         }
@@ -994,8 +3004,10 @@ impl NesApp {
 
     fn unpause(&mut self) {
         self.paused = false;
+        self.paused_in_background = false;
+        self.update_window_title(true);
         if let Some(stream) = self.audio_stream_queue.clone() {
-            stream.lock().unwrap().clear();
+            stream.clear();
         }
     }
 }
@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::PathBuf;
+
+/*
+ON HOW ASSETS ARE FOUND:
+
+The menu's sprites and sounds, and the compatibility database (see "ON THE
+COMPATIBILITY DATABASE" in compat), are built into the executable (with
+include_bytes!), so the app works the same no matter where it's installed or
+what folder it's started from. Sprites are pngs and sounds are wavs, both
+decoded once when the menu is made, and the database is read once when the app
+starts.
+
+An asset folder can be given with --assets to swap any of them out. It's laid
+out the same way as src/app/assets (sprites/slider.png, sounds/move.wav...), and
+only needs the files being replaced: anything missing, or that fails to load,
+falls back to the built-in copy.
+*/
+
+/// Every file the app loads from its assets
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Asset {
+    MoveSound,
+    SelectSound,
+    RejectSound,
+    PauseSound,
+
+    ControllerBase,
+    AButton,
+    BButton,
+    SelectButton,
+    StartButton,
+    UpArrow,
+    DownArrow,
+    LeftArrow,
+    RightArrow,
+    SliderBase,
+    SliderDot,
+
+    CompatDatabase,
+}
+
+impl Asset {
+    /// Where the asset is in the assets folder
+    pub fn path(&self) -> &'static str {
+        match self {
+            Self::MoveSound => "sounds/move.wav",
+            Self::SelectSound => "sounds/select.wav",
+            Self::RejectSound => "sounds/reject.wav",
+            Self::PauseSound => "sounds/pause.wav",
+
+            Self::ControllerBase => "sprites/NemulatorController.png",
+            Self::AButton => "sprites/a_button.png",
+            Self::BButton => "sprites/b_button.png",
+            Self::SelectButton => "sprites/select_button.png",
+            Self::StartButton => "sprites/start_button.png",
+            Self::UpArrow => "sprites/up_arrow.png",
+            Self::DownArrow => "sprites/down_arrow.png",
+            Self::LeftArrow => "sprites/left_arrow.png",
+            Self::RightArrow => "sprites/right_arrow.png",
+            Self::SliderBase => "sprites/slider.png",
+            Self::SliderDot => "sprites/slider_dot.png",
+
+            Self::CompatDatabase => "compatibility.toml",
+        }
+    }
+
+    /// The copy of the asset built into the executable
+    pub fn embedded(&self) -> &'static [u8] {
+        match self {
+            Self::MoveSound => include_bytes!("assets/sounds/move.wav"),
+            Self::SelectSound => include_bytes!("assets/sounds/select.wav"),
+            Self::RejectSound => include_bytes!("assets/sounds/reject.wav"),
+            Self::PauseSound => include_bytes!("assets/sounds/pause.wav"),
+
+            Self::ControllerBase => include_bytes!("assets/sprites/NemulatorController.png"),
+            Self::AButton => include_bytes!("assets/sprites/a_button.png"),
+            Self::BButton => include_bytes!("assets/sprites/b_button.png"),
+            Self::SelectButton => include_bytes!("assets/sprites/select_button.png"),
+            Self::StartButton => include_bytes!("assets/sprites/start_button.png"),
+            Self::UpArrow => include_bytes!("assets/sprites/up_arrow.png"),
+            Self::DownArrow => include_bytes!("assets/sprites/down_arrow.png"),
+            Self::LeftArrow => include_bytes!("assets/sprites/left_arrow.png"),
+            Self::RightArrow => include_bytes!("assets/sprites/right_arrow.png"),
+            Self::SliderBase => include_bytes!("assets/sprites/slider.png"),
+            Self::SliderDot => include_bytes!("assets/sprites/slider_dot.png"),
+
+            Self::CompatDatabase => include_bytes!("assets/compatibility.toml"),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct AssetStore {
+    // Folder with assets to use in place of the built-in ones
+    override_dir: Option<PathBuf>,
+}
+
+impl AssetStore {
+    pub fn new(override_dir: Option<&str>) -> Self {
+        Self {
+            override_dir: override_dir.map(PathBuf::from),
+        }
+    }
+
+    /// Decodes the asset with the given function. If the override can't be
+    /// decoded, the built-in copy is used instead.
+    pub fn decode<T>(&self, asset: Asset, decoder: impl Fn(&[u8]) -> Result<T, String>) -> T {
+        if let Some(bytes) = self.override_bytes(asset) {
+            match decoder(&bytes) {
+                Ok(decoded) => return decoded,
+                Err(e) => println!("Couldn't load asset '{}', using the built-in one: {e}", asset.path()),
+            }
+        }
+
+        decoder(asset.embedded())
+            .unwrap_or_else(|e| panic!("Built-in asset '{}' is broken: {e}", asset.path()))
+    }
+
+    fn override_bytes(&self, asset: Asset) -> Option<Vec<u8>> {
+        let path = self.override_dir.as_ref()?.join(asset.path());
+
+        fs::read(path).ok()
+    }
+}
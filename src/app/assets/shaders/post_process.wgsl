@@ -0,0 +1,118 @@
+// The GPU shaders the game view can be drawn with (see shader.rs). Each one is
+// a fragment entry point, all sharing the same vertex shader and bindings.
+
+struct Locals {
+    // The size of the pixel buffer, in pixels
+    source_size: vec2<f32>,
+    // The size it's drawn at on the window, in pixels
+    output_size: vec2<f32>,
+}
+
+@group(0) @binding(0) var r_tex_color: texture_2d<f32>;
+@group(0) @binding(1) var r_tex_sampler: sampler;
+@group(0) @binding(2) var<uniform> r_locals: Locals;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+}
+
+// One triangle covering the whole viewport, made from the vertex index so no
+// vertex buffer is needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let position = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.tex_coord = fma(position, vec2<f32>(0.5, -0.5), vec2<f32>(0.5, 0.5));
+    return out;
+}
+
+fn sample(tex_coord: vec2<f32>) -> vec3<f32> {
+    return textureSample(r_tex_color, r_tex_sampler, tex_coord).rgb;
+}
+
+// Darkens the gap between each line of the picture, by more the bigger the
+// picture is drawn (at 1x there's no room for a gap)
+fn scanline(tex_coord: vec2<f32>) -> f32 {
+    let scale = r_locals.output_size.y / r_locals.source_size.y;
+    let strength = clamp((scale - 1.0) / 3.0, 0.0, 1.0) * 0.5;
+
+    let distance = abs(fract(tex_coord.y * r_locals.source_size.y) - 0.5) * 2.0;
+    return 1.0 - strength * distance * distance;
+}
+
+// Bends the picture outwards like the glass of a CRT
+fn curve(tex_coord: vec2<f32>) -> vec2<f32> {
+    var centered = tex_coord * 2.0 - 1.0;
+    centered *= 1.0 + 0.08 * centered.yx * centered.yx;
+    centered /= 1.08;
+    return centered * 0.5 + 0.5;
+}
+
+fn outside(tex_coord: vec2<f32>) -> bool {
+    return any(tex_coord < vec2<f32>(0.0)) || any(tex_coord > vec2<f32>(1.0));
+}
+
+// The glow around bright pixels, from the bright parts of the pixels around
+fn glow(tex_coord: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / r_locals.source_size;
+
+    var total = vec3<f32>(0.0);
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let offset = vec2<f32>(f32(x), f32(y));
+            let weight = exp(-dot(offset, offset) / 4.0);
+            total += max(sample(tex_coord + offset * texel) - 0.5, vec3<f32>(0.0)) * weight;
+        }
+    }
+
+    // The weights add up to about 12.6
+    return total / 12.6;
+}
+
+@fragment
+fn fs_scanlines(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
+    return vec4<f32>(sample(tex_coord) * scanline(tex_coord), 1.0);
+}
+
+@fragment
+fn fs_curvature(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
+    let curved = curve(tex_coord);
+    // Sampled before the check, since textureSample has to run on every pixel
+    let color = sample(curved);
+
+    if outside(curved) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_bloom(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
+    return vec4<f32>(sample(tex_coord) + glow(tex_coord) * 1.5, 1.0);
+}
+
+// All of the above, with the edges darkened and the red, green and blue
+// stripes of an aperture grille
+@fragment
+fn fs_crt(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
+    let curved = curve(tex_coord);
+    var color = sample(curved) + glow(curved);
+
+    color *= scanline(curved);
+
+    let stripe = u32(curved.x * r_locals.output_size.x) % 3u;
+    var mask = vec3<f32>(0.85);
+    mask[stripe] = 1.0;
+    color *= mask;
+
+    let centered = curved * 2.0 - 1.0;
+    color *= 1.0 - 0.25 * dot(centered * centered, centered * centered);
+
+    if outside(curved) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Where battery saves are kept, next to the config file
+pub const BATTERY_SAVE_DIR: &str = "saves";
+
+/*
+ON HOW BATTERY SAVES WORK:
+
+Cartridges with a battery (the flag in the iNES header) keep their PRG RAM
+while the console is off, which is how games like Zelda remember save files.
+The RAM is written out when the emulator shuts down, and put back when the game
+is loaded, as a raw copy of the RAM named after the ROM's CRC32 (3FE272FB.sav).
+
+Movies start from a freshly powered on console with empty RAM, so while one is
+recording or playing back the battery save is neither loaded nor written.
+
+Saves are written to a temporary file and then renamed, so being killed while
+one is being written never leaves half a save behind.
+*/
+pub struct BatterySaves {
+    dir: PathBuf,
+}
+
+impl BatterySaves {
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    /// Where the battery save for the ROM with the given hash is (or would be)
+    pub fn path(&self, rom_crc32: u32) -> PathBuf {
+        self.dir.join(format!("{rom_crc32:08X}.sav"))
+    }
+
+    /// The battery save for the ROM with the given hash, or None if it
+    /// hasn't been saved before
+    pub fn load(&self, rom_crc32: u32) -> Result<Option<Vec<u8>>, String> {
+        let path = self.path(rom_crc32);
+
+        match fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Couldn't read battery save '{}': {e}", path.display())),
+        }
+    }
+
+    /// Saves the battery backed RAM of the ROM with the given hash, replacing
+    /// the old save
+    pub fn save(&self, rom_crc32: u32, ram: &[u8]) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Couldn't create battery save folder '{}': {e}", self.dir.display()))?;
+
+        let path = self.path(rom_crc32);
+        let temp_path = path.with_extension("sav.tmp");
+
+        fs::write(&temp_path, ram)
+            .map_err(|e| format!("Couldn't write battery save '{}': {e}", temp_path.display()))?;
+        fs::rename(&temp_path, &path)
+            .map_err(|e| format!("Couldn't write battery save '{}': {e}", path.display()))
+    }
+}
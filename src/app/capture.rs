@@ -0,0 +1,219 @@
+use std::fs;
+use std::path::Path;
+
+use crate::cli::CaptureView;
+use crate::system::nes::{Nes, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
+
+use super::app::{DebugPanel, ScreenOverlays};
+use super::debug_cache::DebugViewCache;
+use super::draw::{self, Color, DebugPalette, DEBUG_FRAME_HEIGHT, DEBUG_FRAME_WIDTH, DEFAULT_DEBUG_PAL};
+
+/*
+ON HOW DEBUG CAPTURES ARE TAKEN:
+
+A capture is a picture of one of the debugger's views, saved as a png for bug
+reports or for showing off what a homebrew game's graphics look like. Every
+capture is drawn fresh from the NES into its own buffer rather than copied off
+the screen, so it comes out the same whether or not the view is being shown,
+and works headless too.
+
+The debug view is drawn exactly like it is in the window (at 960x540, with an
+empty cache so every part of it gets drawn). The other views are only pictures
+of the PPU's memory, at one pixel per NES pixel:
+
+    Pagetables  both pattern tables side by side (256x128), in the palette
+                picked for the debug view's pagetables
+    Nametables  all four nametables as the PPU sees them through the mirroring
+                (512x480), using the background pattern table and palettes the
+                game has set up right now
+    Oam         the 64 sprites in OAM in an 8x8 grid, in OAM order, with
+                their own palettes and flips. Each cell is 8x8, or 8x16 when
+                the game uses large sprites. Transparent pixels stay
+                transparent in the png.
+
+None of the reads have side effects (see Nes::peek_ppu), so taking a capture
+doesn't change what the game does next.
+https://www.nesdev.org/wiki/PPU_nametables
+https://www.nesdev.org/wiki/PPU_OAM
+*/
+
+// Where the app saves captures
+pub const CAPTURE_DIR: &str = "screenshots";
+
+/// What the debugger views are drawn with, besides the NES itself
+#[derive(Clone, Copy)]
+pub struct CaptureSettings {
+    pub palette: DebugPalette,
+    pub panel: DebugPanel,
+    pub overlays: ScreenOverlays,
+    // The palette the pagetables are drawn in, or None for greyscale
+    pub pgtbl_palette: Option<u8>,
+    pub fps: usize,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            palette: DEFAULT_DEBUG_PAL,
+            panel: DebugPanel::default(),
+            overlays: ScreenOverlays::default(),
+            pgtbl_palette: None,
+            fps: 0,
+        }
+    }
+}
+
+/// An RGBA picture, row by row from the top left
+pub struct Capture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Capture {
+    fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![0; width * height * 4] }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Color) {
+        draw::dot(&mut self.pixels, self.width, self.height, x, y, 1, color);
+    }
+
+    /// Saves the picture as a png, making the folder it goes in if needed
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Couldn't create screenshot folder '{}': {e}", dir.display()))?;
+        }
+
+        image::save_buffer(path, &self.pixels, self.width as u32, self.height as u32, image::ExtendedColorType::Rgba8)
+            .map_err(|e| format!("Couldn't save screenshot '{}': {e}", path.display()))
+    }
+}
+
+/// Draws one of the views into a new picture
+pub fn capture(nes: &mut Nes, view: CaptureView, settings: CaptureSettings) -> Capture {
+    match view {
+        CaptureView::Game => {
+            let mut capture = Capture::new(NES_SCREEN_WIDTH, NES_SCREEN_HEIGHT);
+            capture.pixels.copy_from_slice(nes.screen_buf_slice());
+            capture
+        }
+        CaptureView::Debug => capture_debug_view(nes, settings),
+        CaptureView::Pagetables => capture_pagetables(nes, settings.pgtbl_palette),
+        CaptureView::Nametables => capture_nametables(nes),
+        CaptureView::Oam => capture_oam(nes),
+    }
+}
+
+/// Where the app saves a capture of the given view: the captures folder, named
+/// after the game, the view and the frame it was taken on
+pub fn capture_path(nes: &Nes, view: CaptureView) -> String {
+    format!("{CAPTURE_DIR}/{}-{}-{}.png", nes.rom_name(), view.name(), nes.frames_run())
+}
+
+fn capture_debug_view(nes: &mut Nes, settings: CaptureSettings) -> Capture {
+    let mut capture = Capture::new(DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT);
+    let mut cache = DebugViewCache::default();
+
+    draw::draw_debug_bg(&mut capture.pixels, settings.palette, settings.panel, &mut cache);
+    draw::draw_debug(&mut capture.pixels, settings.palette, nes, None, settings.fps,
+        settings.panel, settings.overlays, settings.pgtbl_palette, &mut cache);
+
+    capture
+}
+
+fn capture_pagetables(nes: &Nes, pgtbl_palette: Option<u8>) -> Capture {
+    let mut capture = Capture::new(256, 128);
+    let colors = draw::pagetable_colors(nes, pgtbl_palette);
+
+    draw::draw_nes_pagetable_8x8(&mut capture.pixels, 256, 128, nes.get_pgtbl1(), colors, 0, 0);
+    draw::draw_nes_pagetable_8x8(&mut capture.pixels, 256, 128, nes.get_pgtbl2(), colors, 128, 0);
+
+    capture
+}
+
+fn capture_nametables(nes: &Nes) -> Capture {
+    let mut capture = Capture::new(NES_SCREEN_WIDTH * 2, NES_SCREEN_HEIGHT * 2);
+
+    // PPUCTRL bit 4 picks the background pattern table
+    let pattern_table = if nes.ppu_ctrl() & 0x10 != 0 { 0x1000 } else { 0x0000 };
+    let palettes: [[Color; 4]; 4] = std::array::from_fn(|palette| draw::pagetable_colors(nes, Some(palette as u8)));
+    let backdrop = palettes[0][0];
+
+    for nametable in 0..4 {
+        let base = 0x2000 + nametable * 0x400;
+        let (origin_x, origin_y) = ((nametable as usize % 2) * NES_SCREEN_WIDTH, (nametable as usize / 2) * NES_SCREEN_HEIGHT);
+
+        for tile_y in 0..30 {
+            for tile_x in 0..32 {
+                let tile = nes.peek_ppu(base + tile_y * 32 + tile_x) as u16;
+
+                // Each attribute byte covers 4x4 tiles, 2 bits per 2x2 quadrant
+                let attribute = nes.peek_ppu(base + 0x3C0 + (tile_y / 4) * 8 + tile_x / 4);
+                let shift = ((tile_y % 4) / 2) * 4 + ((tile_x % 4) / 2) * 2;
+                let colors = palettes[((attribute >> shift) & 3) as usize];
+
+                for row in 0..8 {
+                    let lo = nes.peek_ppu(pattern_table + tile * 16 + row);
+                    let hi = nes.peek_ppu(pattern_table + tile * 16 + row + 8);
+
+                    for col in 0..8 {
+                        let pixel = (((hi >> (7 - col)) & 1) << 1) | ((lo >> (7 - col)) & 1);
+                        let color = if pixel == 0 { backdrop } else { colors[pixel as usize] };
+
+                        capture.set(origin_x + tile_x as usize * 8 + col,
+                            origin_y + tile_y as usize * 8 + row as usize, color);
+                    }
+                }
+            }
+        }
+    }
+
+    capture
+}
+
+fn capture_oam(nes: &Nes) -> Capture {
+    const GRID_WIDTH: usize = 8;
+
+    let large_sprites = nes.large_sprites();
+    let sprite_height = if large_sprites { 16 } else { 8 };
+    let mut capture = Capture::new(GRID_WIDTH * 8, GRID_WIDTH * sprite_height);
+
+    // PPUCTRL bit 3 picks the pattern table for 8x8 sprites. 8x16 sprites
+    // pick their own with bit 0 of the tile number.
+    let small_pattern_table = if nes.ppu_ctrl() & 0x08 != 0 { 0x1000 } else { 0x0000 };
+    let oam = nes.oam();
+
+    for (i, sprite) in oam.chunks_exact(4).enumerate() {
+        let (tile, attributes) = (sprite[1] as u16, sprite[2]);
+        let colors = draw::pagetable_colors(nes, Some(4 + (attributes & 3)));
+        let flip_h = attributes & 0x40 != 0;
+        let flip_v = attributes & 0x80 != 0;
+
+        let (cell_x, cell_y) = ((i % GRID_WIDTH) * 8, (i / GRID_WIDTH) * sprite_height);
+
+        for y in 0..sprite_height {
+            let sprite_row = if flip_v { sprite_height - 1 - y } else { y } as u16;
+
+            let tile_address = if large_sprites {
+                ((tile & 1) * 0x1000) + ((tile & 0xFE) + sprite_row / 8) * 16
+            } else {
+                small_pattern_table + tile * 16
+            };
+            let lo = nes.peek_ppu(tile_address + sprite_row % 8);
+            let hi = nes.peek_ppu(tile_address + sprite_row % 8 + 8);
+
+            for x in 0..8 {
+                let bit = if flip_h { x } else { 7 - x };
+                let pixel = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+
+                if pixel != 0 {
+                    capture.set(cell_x + x, cell_y + y, colors[pixel as usize]);
+                }
+            }
+        }
+    }
+
+    capture
+}
@@ -0,0 +1,364 @@
+use std::fs;
+
+use serde_json::{json, Map, Value};
+
+use crate::cli::{CropMode, Region, Rotation};
+use crate::system::apu_util::Mixer;
+use crate::system::controller::{ControllerButton, ExpansionDevice};
+use crate::system::video_filter::FilterChain;
+
+use super::shader::GpuShader;
+use super::util::gamepad_button_from_name;
+
+pub const CONFIG_PATH: &str = "nemulator.json";
+
+// NES buttons in the order they're written to the config
+const NES_BUTTONS: [ControllerButton; 8] = [
+    ControllerButton::A, ControllerButton::B, ControllerButton::Select, ControllerButton::Start,
+    ControllerButton::Up, ControllerButton::Down, ControllerButton::Left, ControllerButton::Right,
+];
+
+/// A gamepad mapping as saved in a profile: which gamepad button (and direction)
+/// is mapped to each NES button, and the left stick's settings (in percent)
+#[derive(Clone, Debug, Default)]
+pub struct SavedMapping {
+    pub buttons: Vec<(ControllerButton, gilrs::Button, bool)>,
+    pub stick_deadzone: Option<usize>,
+    pub diagonal_bias: Option<usize>,
+}
+
+/// How a game's intro is skipped right after it's loaded: the frames are run
+/// as fast as they can be, without being shown or heard. The input comes from
+/// a movie if there is one (see movie.rs), and is left alone after it ends or
+/// without one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SkipIntro {
+    // Frames run in total. The whole movie is always played, even if it's
+    // longer than this.
+    pub frames: usize,
+    pub movie_path: Option<String>,
+}
+
+/// Settings that override the defaults whenever a particular game is loaded.
+/// Anything left as None uses whatever was given on the command line.
+#[derive(Clone, Debug, Default)]
+pub struct GameProfile {
+    // Only there to make the config file readable, games are looked up by hash
+    pub name: String,
+    pub palette_path: Option<String>,
+    pub crop: Option<CropMode>,
+    pub rotation: Option<Rotation>,
+    pub mirror: Option<bool>,
+    pub region: Option<Region>,
+    pub speed_percent: Option<usize>,
+    pub expansion: Option<ExpansionDevice>,
+    // For games made for Famicom clones, which can mix differently or have
+    // the pulse duty cycles swapped
+    pub mixer: Option<Mixer>,
+    pub swap_duty_cycles: Option<bool>,
+    pub controller_maps: [Option<SavedMapping>; 2],
+    pub skip_intro: Option<SkipIntro>,
+}
+
+/// The emulator's config file. It's plain JSON so it can be edited by hand:
+///
+/// {
+///   "volume": 25,
+///   "muted": false,
+///   "pause_in_background": true,
+///   "mute_in_background": true,
+///   "retry_slot": 0,
+///   "video_filters": {
+///     "scanlines": { "enabled": true, "intensity": 40 }, ...
+///   },
+///   "gpu_shader": "crt",
+///   "profiles": {
+///     "3FE272FB": { "name": "Super Mario Bros", "crop": "left", "speed": 100, ... },
+///     "D445F698": { "name": "Zelda", "skip_intro": { "frames": 400, "movie": "intros/zelda.fm2" } }
+///   }
+/// }
+///
+/// Unknown keys are left alone, so hand-written settings survive being saved.
+pub struct ConfigFile {
+    path: String,
+    json: Value,
+}
+
+impl ConfigFile {
+    /// Loads the config file, or starts an empty one if it doesn't exist or
+    /// can't be read.
+    pub fn load(path: &str) -> Self {
+        let json = match fs::read_to_string(path) {
+            Ok(text) => match serde_json::from_str::<Value>(&text) {
+                Ok(json) if json.is_object() => json,
+                _ => {
+                    println!("Couldn't parse config file '{path}', ignoring it");
+                    json!({})
+                }
+            },
+            Err(_) => json!({}),
+        };
+
+        Self { path: String::from(path), json }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(&self.json)
+            .map_err(|e| format!("Couldn't serialize config: {e}"))?;
+
+        fs::write(&self.path, text)
+            .map_err(|e| format!("Couldn't write config file '{}': {e}", self.path))
+    }
+
+    /// The volume (in percent of the slider) last used, if it's been saved
+    pub fn volume_percent(&self) -> Option<usize> {
+        self.json.get("volume").and_then(Value::as_u64).map(|volume| volume as usize)
+    }
+
+    pub fn muted(&self) -> Option<bool> {
+        self.json.get("muted").and_then(Value::as_bool)
+    }
+
+    /// Whether the game pauses while the emulator's windows aren't focused.
+    /// Only ever set by hand, for players who want the game to keep running.
+    pub fn pause_in_background(&self) -> Option<bool> {
+        self.json.get("pause_in_background").and_then(Value::as_bool)
+    }
+
+    /// The save slot retrying loads, instead of the one last saved to. Only
+    /// ever set by hand.
+    pub fn retry_slot(&self) -> Option<usize> {
+        self.json.get("retry_slot").and_then(Value::as_u64).map(|slot| slot as usize)
+    }
+
+    /// Whether the sound is muted while the emulator's windows aren't focused
+    pub fn mute_in_background(&self) -> Option<bool> {
+        self.json.get("mute_in_background").and_then(Value::as_bool)
+    }
+
+    /// Sets the volume for next time. The file itself isn't written until save
+    /// is called.
+    pub fn set_volume(&mut self, volume_percent: usize, muted: bool) {
+        let root = self.json.as_object_mut().unwrap();
+
+        root.insert(String::from("volume"), json!(volume_percent));
+        root.insert(String::from("muted"), json!(muted));
+    }
+
+    /// Turns on the video filters that were on last time, with their settings.
+    /// Filters that aren't in the file are left as they are.
+    pub fn load_video_filters(&self, chain: &mut FilterChain) {
+        let Some(saved) = self.json.get("video_filters") else {
+            return;
+        };
+
+        for i in 0..chain.len() {
+            let Some(entry) = saved.get(chain.filter(i).id()) else {
+                continue;
+            };
+
+            if let Some(enabled) = entry.get("enabled").and_then(Value::as_bool) {
+                chain.set_enabled(i, enabled);
+            }
+            for (j, setting) in chain.filter(i).settings().iter().enumerate() {
+                if let Some(value) = entry.get(setting.id).and_then(Value::as_u64) {
+                    chain.filter_mut(i).set_setting(j, value.min(100) as usize);
+                }
+            }
+        }
+    }
+
+    /// The GPU shader last used, if it's been saved
+    pub fn gpu_shader(&self) -> Option<GpuShader> {
+        self.json.get("gpu_shader").and_then(Value::as_str).and_then(GpuShader::from_name)
+    }
+
+    /// Sets the video filters and GPU shader for next time. Returns whether
+    /// that changed anything, so the file is only written when it needs to be
+    /// (nothing saved counts the same as the defaults). The file itself isn't
+    /// written until save is called.
+    pub fn set_video_filters(&mut self, chain: &FilterChain, shader: GpuShader) -> bool {
+        let filters = Self::video_filters_to_json(chain);
+        let saved = self.json.get("video_filters").cloned()
+            .unwrap_or_else(|| Self::video_filters_to_json(&FilterChain::standard()));
+
+        if saved == filters && self.gpu_shader().unwrap_or_default() == shader {
+            return false;
+        }
+
+        let root = self.json.as_object_mut().unwrap();
+
+        root.insert(String::from("video_filters"), filters);
+        root.insert(String::from("gpu_shader"), json!(shader.name()));
+        true
+    }
+
+    fn video_filters_to_json(chain: &FilterChain) -> Value {
+        let mut filters = Map::new();
+
+        for i in 0..chain.len() {
+            let mut entry = Map::new();
+            entry.insert(String::from("enabled"), json!(chain.enabled(i)));
+            for setting in chain.filter(i).settings() {
+                entry.insert(String::from(setting.id), json!(setting.value));
+            }
+
+            filters.insert(String::from(chain.filter(i).id()), Value::Object(entry));
+        }
+
+        Value::Object(filters)
+    }
+
+    fn profile_key(rom_crc32: u32) -> String {
+        format!("{rom_crc32:08X}")
+    }
+
+    /// Gets the profile for the game with the given ROM hash, if it has one
+    pub fn game_profile(&self, rom_crc32: u32) -> Option<GameProfile> {
+        let profile = self.json.get("profiles")?.get(Self::profile_key(rom_crc32))?;
+
+        let string = |key: &str| profile.get(key).and_then(Value::as_str).map(String::from);
+
+        Some(GameProfile {
+            name: string("name").unwrap_or_default(),
+            palette_path: string("palette"),
+            crop: string("crop").and_then(|name| CropMode::from_name(&name)),
+            rotation: string("rotate").and_then(|name| Rotation::from_name(&name)),
+            mirror: profile.get("mirror").and_then(Value::as_bool),
+            region: string("region").and_then(|name| Region::from_name(&name)),
+            speed_percent: profile.get("speed").and_then(Value::as_u64).map(|speed| speed as usize),
+            expansion: string("expansion").and_then(|name| ExpansionDevice::from_name(&name)),
+            mixer: string("mixer").and_then(|name| Mixer::from_name(&name)),
+            swap_duty_cycles: profile.get("swap_duty").and_then(Value::as_bool),
+            controller_maps: [
+                profile.get("controller1").and_then(Self::mapping_from_json),
+                profile.get("controller2").and_then(Self::mapping_from_json),
+            ],
+            skip_intro: profile.get("skip_intro").and_then(Self::skip_intro_from_json),
+        })
+    }
+
+    /// Saves (or with None, deletes) the profile for the game with the given
+    /// ROM hash. The file itself isn't written until save is called.
+    pub fn set_game_profile(&mut self, rom_crc32: u32, profile: Option<&GameProfile>) {
+        let root = self.json.as_object_mut().unwrap();
+        let profiles = root.entry("profiles").or_insert(json!({}));
+
+        if !profiles.is_object() {
+            *profiles = json!({});
+        }
+        let profiles = profiles.as_object_mut().unwrap();
+
+        let Some(profile) = profile else {
+            profiles.remove(&Self::profile_key(rom_crc32));
+            return;
+        };
+
+        let mut entry = Map::new();
+        entry.insert(String::from("name"), json!(profile.name));
+
+        if let Some(path) = &profile.palette_path {
+            entry.insert(String::from("palette"), json!(path));
+        }
+        if let Some(crop) = profile.crop {
+            entry.insert(String::from("crop"), json!(crop.name()));
+        }
+        if let Some(rotation) = profile.rotation {
+            entry.insert(String::from("rotate"), json!(rotation.name()));
+        }
+        if let Some(mirror) = profile.mirror {
+            entry.insert(String::from("mirror"), json!(mirror));
+        }
+        if let Some(region) = profile.region {
+            entry.insert(String::from("region"), json!(region.name()));
+        }
+        if let Some(speed) = profile.speed_percent {
+            entry.insert(String::from("speed"), json!(speed));
+        }
+        if let Some(expansion) = profile.expansion {
+            entry.insert(String::from("expansion"), json!(expansion.name()));
+        }
+        if let Some(mixer) = profile.mixer {
+            entry.insert(String::from("mixer"), json!(mixer.name()));
+        }
+        if let Some(swap) = profile.swap_duty_cycles {
+            entry.insert(String::from("swap_duty"), json!(swap));
+        }
+        for (i, mapping) in profile.controller_maps.iter().enumerate() {
+            if let Some(mapping) = mapping {
+                entry.insert(format!("controller{}", i + 1), Self::mapping_to_json(mapping));
+            }
+        }
+        if let Some(skip) = &profile.skip_intro {
+            entry.insert(String::from("skip_intro"), Self::skip_intro_to_json(skip));
+        }
+
+        profiles.insert(Self::profile_key(rom_crc32), Value::Object(entry));
+    }
+
+    // Skipping an intro is stored like { "frames": 300, "movie": "intro.fm2" },
+    // where either can be left out
+    fn skip_intro_to_json(skip: &SkipIntro) -> Value {
+        let mut map = Map::new();
+
+        map.insert(String::from("frames"), json!(skip.frames));
+        if let Some(path) = &skip.movie_path {
+            map.insert(String::from("movie"), json!(path));
+        }
+
+        Value::Object(map)
+    }
+
+    fn skip_intro_from_json(json: &Value) -> Option<SkipIntro> {
+        let map = json.as_object()?;
+
+        Some(SkipIntro {
+            frames: map.get("frames").and_then(Value::as_u64).unwrap_or(0) as usize,
+            movie_path: map.get("movie").and_then(Value::as_str).map(String::from),
+        })
+    }
+
+    // Mappings are stored like { "A": "South", "Up": "-DPadUp" }, where a minus
+    // means the button is pressed in the negative direction, along with the
+    // stick settings like "stick_deadzone": 25
+    fn mapping_to_json(mapping: &SavedMapping) -> Value {
+        let mut map = Map::new();
+
+        for (nes_button, gamepad_button, positive) in &mapping.buttons {
+            let sign = if *positive { "" } else { "-" };
+            map.insert(format!("{nes_button:?}"), json!(format!("{sign}{gamepad_button:?}")));
+        }
+        if let Some(deadzone) = mapping.stick_deadzone {
+            map.insert(String::from("stick_deadzone"), json!(deadzone));
+        }
+        if let Some(bias) = mapping.diagonal_bias {
+            map.insert(String::from("diagonal_bias"), json!(bias));
+        }
+
+        Value::Object(map)
+    }
+
+    fn mapping_from_json(json: &Value) -> Option<SavedMapping> {
+        let map = json.as_object()?;
+
+        let buttons = NES_BUTTONS.iter()
+            .filter_map(|nes_button| {
+                let name = map.get(&format!("{nes_button:?}"))?.as_str()?;
+                let (positive, name) = match name.strip_prefix('-') {
+                    Some(name) => (false, name),
+                    None => (true, name),
+                };
+
+                Some((*nes_button, gamepad_button_from_name(name)?, positive))
+            })
+            .collect();
+
+        let percent = |key: &str| map.get(key).and_then(Value::as_u64).map(|percent| percent.min(100) as usize);
+
+        Some(SavedMapping {
+            buttons,
+            stick_deadzone: percent("stick_deadzone"),
+            diagonal_bias: percent("diagonal_bias"),
+        })
+    }
+}
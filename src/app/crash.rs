@@ -0,0 +1,178 @@
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cartridge::hash::RomHashes;
+use crate::cli::CaptureView;
+use crate::system::nes::Nes;
+
+use super::capture::{self, CaptureSettings};
+use super::draw;
+use super::overlay::OverlayCallback;
+
+// Where the app saves crash reports, one folder per crash
+pub const CRASH_DIR: &str = "crashes";
+
+// The panic the hook saw last, until the app takes it for a report
+static LAST_PANIC: Mutex<Option<PanicDetails>> = Mutex::new(None);
+
+/*
+ON HOW CRASH REPORTS WORK:
+
+The emulator has a few spots that panic when it ends up somewhere it never
+should (the unreachable!() arms in the PPU and APU, mostly). Before, that took
+the whole app down with nothing but a message in a terminal the player
+probably didn't have open.
+
+Now the app runs each frame of emulation inside catch_unwind. When a frame
+panics, the app writes a crash report to its own folder in crashes/, named
+after when it happened:
+
+    report.txt  what the panic said and where, the game (name, hashes and
+                mapper, with the mapper's registers), the CPU's registers, the
+                PPU's position, the last instructions the CPU ran (see "ON THE
+                INSTRUCTION TRACE" in trace) and a backtrace
+    screen.png  the picture the PPU was drawing when it happened
+
+Then it stops running the game and puts up a box over it saying where the
+report went. Resetting (or loading a save state) starts the game going again,
+though whatever went wrong may well happen again.
+
+The panic message and backtrace only exist inside the panic hook, so the hook
+the app installs saves them for the report before handing the panic on to the
+one that was there before (which prints it like always). The NES can't be
+reached from the hook (it isn't Sync, and may be halfway through a frame), so
+the rest of the report is written after the frame has unwound. Only the
+emulation is guarded this way. A panic anywhere else in the app still takes it
+down, since there's no telling what state the app is left in.
+*/
+
+/// What a panic said, where it happened and how it got there
+#[derive(Clone, Debug, Default)]
+pub struct PanicDetails {
+    // Like "panicked at src/system/ppu.rs:866:19:\nBy Becquerel's Ghost!"
+    pub message: String,
+    pub backtrace: String,
+}
+
+/// Saves the details of every panic for take_panic, then lets the panic go on
+/// to whatever hook was installed before (see "ON HOW CRASH REPORTS WORK")
+pub fn install_panic_hook() {
+    let previous_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        let details = PanicDetails {
+            message: info.to_string(),
+            backtrace: Backtrace::force_capture().to_string(),
+        };
+        // A panic while the lock was held doesn't make the details any less good
+        *LAST_PANIC.lock().unwrap_or_else(|e| e.into_inner()) = Some(details);
+
+        previous_hook(info);
+    }));
+}
+
+/// Takes the details of the last panic, if the hook has seen one since this
+/// was last called
+pub fn take_panic() -> Option<PanicDetails> {
+    LAST_PANIC.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/// Writes a crash report for the NES as it was left by a panic, returning the
+/// folder it went in
+pub fn write_report(nes: &mut Nes, panic: &PanicDetails) -> Result<PathBuf, String> {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let dir = Path::new(CRASH_DIR).join(seconds.to_string());
+
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Couldn't create crash report folder '{}': {e}", dir.display()))?;
+
+    let report_path = dir.join("report.txt");
+    fs::write(&report_path, report_text(nes, panic))
+        .map_err(|e| format!("Couldn't save crash report '{}': {e}", report_path.display()))?;
+
+    capture::capture(nes, CaptureView::Game, CaptureSettings::default()).save(&dir.join("screen.png"))?;
+
+    Ok(dir)
+}
+
+/// The text of a crash report (see "ON HOW CRASH REPORTS WORK")
+pub fn report_text(nes: &Nes, panic: &PanicDetails) -> String {
+    let mut text = String::from("NEmulator crash report\n\n");
+
+    let _ = writeln!(text, "{}\n", panic.message);
+
+    let _ = writeln!(text, "Game: {}", nes.rom_name());
+    let _ = writeln!(text, "ROM CRC-32: {:08X}", nes.rom_crc32());
+    match nes.rom_hashes() {
+        Some(hashes) => {
+            let _ = writeln!(text, "ROM SHA-1: {}", RomHashes::sha1_hex(&hashes.rom_sha1));
+        }
+        None => text.push_str("ROM SHA-1: not worked out yet\n"),
+    }
+
+    let (mapper_num, mapper_name) = nes.mapper_info();
+    let _ = writeln!(text, "Mapper: {mapper_num} ({mapper_name})");
+    for (name, value) in nes.mapper_debug_state() {
+        let _ = writeln!(text, "    {name}: {value}");
+    }
+
+    let cpu = nes.get_cpu_state();
+    let (dot, scanline) = nes.ppu_position();
+    let _ = writeln!(text, "\nFrame: {}", nes.frames_run());
+    let _ = writeln!(text, "CPU: A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PC:{:04X} CYC:{}",
+        cpu.acc, cpu.x, cpu.y, cpu.status.into_bits(), cpu.sp, cpu.pc, cpu.total_clocks);
+    let _ = writeln!(text, "PPU: scanline {scanline}, dot {dot}, PPUCTRL ${:02X}", nes.ppu_ctrl());
+    if let Some(address) = nes.cpu_jammed_at() {
+        let _ = writeln!(text, "The CPU was jammed at ${address:04X}");
+    }
+
+    let trace = nes.cpu_trace();
+    let _ = writeln!(text, "\nLast {} instructions, oldest first:", trace.len());
+    for entry in trace {
+        let _ = writeln!(text, "{}", entry.line());
+    }
+
+    let _ = write!(text, "\nBacktrace:\n{}", panic.backtrace);
+
+    text
+}
+
+/// The box put up over the game once it's crashed, saying where the report
+/// went. Cloning it gives another handle to the same box, like OsdMessage.
+#[derive(Clone, Default)]
+pub struct CrashNotice {
+    current: Rc<RefCell<Option<String>>>,
+}
+
+impl CrashNotice {
+    /// Puts up the box with the given text, until it's cleared
+    pub fn show(&self, text: &str) {
+        *self.current.borrow_mut() = Some(String::from(text));
+    }
+
+    pub fn clear(&self) {
+        *self.current.borrow_mut() = None;
+    }
+
+    pub fn shown(&self) -> bool {
+        self.current.borrow().is_some()
+    }
+
+    /// The overlay layer that draws the box while it's up
+    pub fn layer(&self) -> OverlayCallback {
+        let current = self.current.clone();
+
+        Box::new(move |target, _nes| {
+            if let Some(text) = current.borrow().as_ref() {
+                draw::draw_crash_notice(target, text);
+            }
+        })
+    }
+}
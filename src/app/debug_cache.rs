@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/*
+ON HOW THE DEBUG VIEW AVOIDS REDRAWING:
+
+The debug view is a 960x540 frame, and most of it doesn't change from one frame
+to the next. The pixel buffer keeps whatever was drawn into it last time, so a
+panel only needs drawing again when what it shows has changed.
+
+Each part of the view (a DebugRegion) is drawn from a handful of values: the
+CPU registers, CPU memory, the pattern tables... Before drawing a region,
+those values are hashed and checked against the hash from the last time it was
+drawn. If they match, the pixels already there are still right and the region
+is skipped. While the NES is paused nothing changes, so almost nothing gets
+drawn at all.
+
+The pattern tables used to be decoded only when the background was drawn, so
+they went stale whenever the game switched CHR banks or wrote to CHR RAM. Now
+they're read every frame (8KiB through the PPU bus, which is cheap) but only
+decoded and drawn when the bytes change, which for most games is rare. That's
+cheap enough that it isn't worth handing them to another thread, which would
+also need its own copy of the NES to read from.
+
+Anything drawing over the view (like the performance HUD) or clearing it has to
+redraw the background, which forgets everything so the whole view gets drawn
+again. Each pixel buffer the view is drawn into needs its own cache.
+*/
+
+/// The parts of the debug view that are drawn separately
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DebugRegion {
+    NesScreen,
+    Pagetables,
+    CpuState,
+    // The memory view, APU log or PPU shifters
+    Panel,
+    PpuInfo,
+    Fps,
+    AudioStats,
+}
+
+#[derive(Default)]
+pub struct DebugViewCache {
+    // The hash of what each region showed when it was last drawn
+    drawn: HashMap<DebugRegion, u64>,
+}
+
+impl DebugViewCache {
+    /// Forgets what's been drawn, so every region is drawn next time
+    pub fn invalidate(&mut self) {
+        self.drawn.clear();
+    }
+
+    /// Whether the region needs drawing to show the given contents. The
+    /// contents are remembered as drawn, so only call this right before
+    /// drawing.
+    pub fn needs_redraw(&mut self, region: DebugRegion, contents: impl Hash) -> bool {
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        self.drawn.insert(region, hash) != Some(hash)
+    }
+}
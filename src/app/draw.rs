@@ -1,7 +1,13 @@
 
-use crate::{cartridge::mapper::NametableMirror, system::{controller::ControllerButton, nes::Nes}};
+use crate::{cartridge::hash::RomHashes, cli::{CropMode, Rotation, UiPalette}, system::{apu::PCM_LOG_SIZE, interrupt_log::{InterruptEvent, InterruptOutcome}, memory_region::CpuRegion, nes::{Nes, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH}, telemetry::UnsupportedUse}};
 
-use super::app::{PauseMenu, PauseMenuItem};
+use super::app::{DebugPanel, PauseMenu, ScreenOverlays};
+use super::debug_cache::{DebugRegion, DebugViewCache};
+use super::menu::MenuScreen;
+use super::overlay::OverlayTarget;
+use super::perf::PerfHud;
+use super::text::{fill, text, Text};
+use super::widgets::{WidgetKind, WidgetList};
 
 pub const DEBUG_FRAME_WIDTH: usize = 960;
 pub const DEBUG_FRAME_HEIGHT: usize = 540;
@@ -474,6 +480,407 @@ pub mod chars {
         0b1101_0100 << 2*8 |
         0b1001_1100 << 1*8 |
         0b0000_0000 << 0*8;
+    pub const QUESTION: u64 = 
+        0b0111_0000 << 7*8 |
+        0b1000_1000 << 6*8 |
+        0b0000_1000 << 5*8 |
+        0b0011_0000 << 4*8 |
+        0b0010_0000 << 3*8 |
+        0b0000_0000 << 2*8 |
+        0b0010_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+
+    // Lowercase letters sit on the same baseline as the capitals, with g, j,
+    // p, q and y hanging down into the bottom row
+    pub const LOWER_A: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0111_0000 << 5*8 |
+        0b0000_1000 << 4*8 |
+        0b0111_1000 << 3*8 |
+        0b1000_1000 << 2*8 |
+        0b0111_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_B: u64 = 
+        0b1000_0000 << 7*8 |
+        0b1000_0000 << 6*8 |
+        0b1111_0000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b1000_1000 << 2*8 |
+        0b1111_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_C: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0111_0000 << 5*8 |
+        0b1000_0000 << 4*8 |
+        0b1000_0000 << 3*8 |
+        0b1000_0000 << 2*8 |
+        0b0111_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_D: u64 = 
+        0b0000_1000 << 7*8 |
+        0b0000_1000 << 6*8 |
+        0b0111_1000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b1000_1000 << 2*8 |
+        0b0111_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_E: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0111_0000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1111_1000 << 3*8 |
+        0b1000_0000 << 2*8 |
+        0b0111_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_F: u64 = 
+        0b0011_0000 << 7*8 |
+        0b0100_0000 << 6*8 |
+        0b1110_0000 << 5*8 |
+        0b0100_0000 << 4*8 |
+        0b0100_0000 << 3*8 |
+        0b0100_0000 << 2*8 |
+        0b0100_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_G: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0111_1000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b0111_1000 << 2*8 |
+        0b0000_1000 << 1*8 |
+        0b0111_0000 << 0*8;
+    pub const LOWER_H: u64 = 
+        0b1000_0000 << 7*8 |
+        0b1000_0000 << 6*8 |
+        0b1111_0000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b1000_1000 << 2*8 |
+        0b1000_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_I: u64 = 
+        0b0010_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0110_0000 << 5*8 |
+        0b0010_0000 << 4*8 |
+        0b0010_0000 << 3*8 |
+        0b0010_0000 << 2*8 |
+        0b0111_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_J: u64 = 
+        0b0001_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0011_0000 << 5*8 |
+        0b0001_0000 << 4*8 |
+        0b0001_0000 << 3*8 |
+        0b0001_0000 << 2*8 |
+        0b1001_0000 << 1*8 |
+        0b0110_0000 << 0*8;
+    pub const LOWER_K: u64 = 
+        0b1000_0000 << 7*8 |
+        0b1000_0000 << 6*8 |
+        0b1001_0000 << 5*8 |
+        0b1010_0000 << 4*8 |
+        0b1100_0000 << 3*8 |
+        0b1010_0000 << 2*8 |
+        0b1001_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_L: u64 = 
+        0b0110_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b0010_0000 << 5*8 |
+        0b0010_0000 << 4*8 |
+        0b0010_0000 << 3*8 |
+        0b0010_0000 << 2*8 |
+        0b0111_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_M: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1101_0000 << 5*8 |
+        0b1010_1000 << 4*8 |
+        0b1010_1000 << 3*8 |
+        0b1010_1000 << 2*8 |
+        0b1000_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_N: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1111_0000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b1000_1000 << 2*8 |
+        0b1000_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_O: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0111_0000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b1000_1000 << 2*8 |
+        0b0111_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_P: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1111_0000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b1111_0000 << 2*8 |
+        0b1000_0000 << 1*8 |
+        0b1000_0000 << 0*8;
+    pub const LOWER_Q: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0111_1000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b0111_1000 << 2*8 |
+        0b0000_1000 << 1*8 |
+        0b0000_1000 << 0*8;
+    pub const LOWER_R: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1011_0000 << 5*8 |
+        0b1100_1000 << 4*8 |
+        0b1000_0000 << 3*8 |
+        0b1000_0000 << 2*8 |
+        0b1000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_S: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0111_1000 << 5*8 |
+        0b1000_0000 << 4*8 |
+        0b0111_0000 << 3*8 |
+        0b0000_1000 << 2*8 |
+        0b1111_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_T: u64 = 
+        0b0100_0000 << 7*8 |
+        0b0100_0000 << 6*8 |
+        0b1110_0000 << 5*8 |
+        0b0100_0000 << 4*8 |
+        0b0100_0000 << 3*8 |
+        0b0100_1000 << 2*8 |
+        0b0011_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_U: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1000_1000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b1001_1000 << 2*8 |
+        0b0110_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_V: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1000_1000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b0101_0000 << 2*8 |
+        0b0010_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_W: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1000_1000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1010_1000 << 3*8 |
+        0b1010_1000 << 2*8 |
+        0b0101_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_X: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1000_1000 << 5*8 |
+        0b0101_0000 << 4*8 |
+        0b0010_0000 << 3*8 |
+        0b0101_0000 << 2*8 |
+        0b1000_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LOWER_Y: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1000_1000 << 5*8 |
+        0b1000_1000 << 4*8 |
+        0b1000_1000 << 3*8 |
+        0b0111_1000 << 2*8 |
+        0b0000_1000 << 1*8 |
+        0b0111_0000 << 0*8;
+    pub const LOWER_Z: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b1111_1000 << 5*8 |
+        0b0001_0000 << 4*8 |
+        0b0010_0000 << 3*8 |
+        0b0100_0000 << 2*8 |
+        0b1111_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+
+    // The rest of printable ASCII
+    pub const EXCLAMATION: u64 = 
+        0b0010_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b0010_0000 << 5*8 |
+        0b0010_0000 << 4*8 |
+        0b0010_0000 << 3*8 |
+        0b0000_0000 << 2*8 |
+        0b0010_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const DOUBLE_QUOTE: u64 = 
+        0b0101_0000 << 7*8 |
+        0b0101_0000 << 6*8 |
+        0b0101_0000 << 5*8 |
+        0b0000_0000 << 4*8 |
+        0b0000_0000 << 3*8 |
+        0b0000_0000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const AMPERSAND: u64 = 
+        0b0110_0000 << 7*8 |
+        0b1001_0000 << 6*8 |
+        0b1010_0000 << 5*8 |
+        0b0100_0000 << 4*8 |
+        0b1010_1000 << 3*8 |
+        0b1001_0000 << 2*8 |
+        0b0110_1000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const APOSTROPHE: u64 = 
+        0b0010_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b0100_0000 << 5*8 |
+        0b0000_0000 << 4*8 |
+        0b0000_0000 << 3*8 |
+        0b0000_0000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const LPAREN: u64 = 
+        0b0001_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b0100_0000 << 5*8 |
+        0b0100_0000 << 4*8 |
+        0b0100_0000 << 3*8 |
+        0b0010_0000 << 2*8 |
+        0b0001_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const RPAREN: u64 = 
+        0b0100_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b0001_0000 << 5*8 |
+        0b0001_0000 << 4*8 |
+        0b0001_0000 << 3*8 |
+        0b0010_0000 << 2*8 |
+        0b0100_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const ASTERISK: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b1010_1000 << 5*8 |
+        0b0111_0000 << 4*8 |
+        0b1010_1000 << 3*8 |
+        0b0010_0000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const PLUS: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b0010_0000 << 5*8 |
+        0b1111_1000 << 4*8 |
+        0b0010_0000 << 3*8 |
+        0b0010_0000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const PERIOD: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0000_0000 << 5*8 |
+        0b0000_0000 << 4*8 |
+        0b0000_0000 << 3*8 |
+        0b0110_0000 << 2*8 |
+        0b0110_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const SEMICOLON: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0110_0000 << 6*8 |
+        0b0110_0000 << 5*8 |
+        0b0000_0000 << 4*8 |
+        0b0110_0000 << 3*8 |
+        0b0110_0000 << 2*8 |
+        0b0010_0000 << 1*8 |
+        0b0100_0000 << 0*8;
+    pub const AT: u64 = 
+        0b0111_0000 << 7*8 |
+        0b1000_1000 << 6*8 |
+        0b1011_1000 << 5*8 |
+        0b1010_1000 << 4*8 |
+        0b1011_1000 << 3*8 |
+        0b1000_0000 << 2*8 |
+        0b0111_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const BACKSLASH: u64 = 
+        0b0000_0000 << 7*8 |
+        0b1000_0000 << 6*8 |
+        0b0100_0000 << 5*8 |
+        0b0010_0000 << 4*8 |
+        0b0001_0000 << 3*8 |
+        0b0000_1000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const CARET: u64 = 
+        0b0010_0000 << 7*8 |
+        0b0101_0000 << 6*8 |
+        0b1000_1000 << 5*8 |
+        0b0000_0000 << 4*8 |
+        0b0000_0000 << 3*8 |
+        0b0000_0000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const UNDERSCORE: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0000_0000 << 5*8 |
+        0b0000_0000 << 4*8 |
+        0b0000_0000 << 3*8 |
+        0b0000_0000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b1111_1000 << 0*8;
+    pub const PIPE: u64 = 
+        0b0010_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b0010_0000 << 5*8 |
+        0b0010_0000 << 4*8 |
+        0b0010_0000 << 3*8 |
+        0b0010_0000 << 2*8 |
+        0b0010_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const TILDE: u64 = 
+        0b0000_0000 << 7*8 |
+        0b0000_0000 << 6*8 |
+        0b0100_0000 << 5*8 |
+        0b1010_1000 << 4*8 |
+        0b0001_0000 << 3*8 |
+        0b0000_0000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
+    pub const GRAVE: u64 = 
+        0b0100_0000 << 7*8 |
+        0b0010_0000 << 6*8 |
+        0b0000_0000 << 5*8 |
+        0b0000_0000 << 4*8 |
+        0b0000_0000 << 3*8 |
+        0b0000_0000 << 2*8 |
+        0b0000_0000 << 1*8 |
+        0b0000_0000 << 0*8;
 
     // Characters wrapped in `backticks` in a string will be considered special.
     // These characters map to their special counterpart if one exists, else
@@ -509,7 +916,7 @@ pub mod chars {
         0b1111_1111 << 0*8;
 
     pub fn get_letter_from_char(letter_char: char, special_char: bool) -> u64 {
-        match letter_char.to_ascii_uppercase() {
+        match letter_char {
             'A' => A,
             'B' => B,
             'C' => C,
@@ -536,6 +943,32 @@ pub mod chars {
             'X' => X,
             'Y' => Y,
             'Z' => Z,
+            'a' => LOWER_A,
+            'b' => LOWER_B,
+            'c' => LOWER_C,
+            'd' => LOWER_D,
+            'e' => LOWER_E,
+            'f' => LOWER_F,
+            'g' => LOWER_G,
+            'h' => LOWER_H,
+            'i' => LOWER_I,
+            'j' => LOWER_J,
+            'k' => LOWER_K,
+            'l' => LOWER_L,
+            'm' => LOWER_M,
+            'n' => LOWER_N,
+            'o' => LOWER_O,
+            'p' => LOWER_P,
+            'q' => LOWER_Q,
+            'r' => LOWER_R,
+            's' => LOWER_S,
+            't' => LOWER_T,
+            'u' => LOWER_U,
+            'v' => LOWER_V,
+            'w' => LOWER_W,
+            'x' => LOWER_X,
+            'y' => LOWER_Y,
+            'z' => LOWER_Z,
             '0' => ZERO,
             '1' => ONE,
             '2' => TWO,
@@ -560,11 +993,72 @@ pub mod chars {
             ',' => COMMA,
             '/' => FORWARD_SLASH,
             '%' => PERCENT,
+            '?' => QUESTION,
+            '!' => EXCLAMATION,
+            '"' => DOUBLE_QUOTE,
+            '&' => AMPERSAND,
+            '\'' => APOSTROPHE,
+            '(' => LPAREN,
+            ')' => RPAREN,
+            '*' => ASTERISK,
+            '+' => PLUS,
+            '.' => PERIOD,
+            ';' => SEMICOLON,
+            '@' => AT,
+            '\\' => BACKSLASH,
+            '^' => CARET,
+            '_' => UNDERSCORE,
+            '|' => PIPE,
+            '~' => TILDE,
+            '`' => GRAVE,
             ' ' => SPACE,
 
-            _ => UNKNOWN,
+            _ => match fallback_char(letter_char) {
+                Some(fallback) => get_letter_from_char(fallback, special_char),
+                None => UNKNOWN,
+            },
         }
     }
+
+    /// The closest character the font has for one it doesn't: accented
+    /// letters lose their accent, and fancy quotes and dashes become plain
+    /// ones. Anything else gets drawn as UNKNOWN.
+    pub fn fallback_char(letter_char: char) -> Option<char> {
+        let fallback = match letter_char {
+            'À'..='Å' => 'A',
+            'à'..='å' => 'a',
+            'Æ' => 'A',
+            'æ' => 'a',
+            'Ç' => 'C',
+            'ç' => 'c',
+            'È'..='Ë' => 'E',
+            'è'..='ë' => 'e',
+            'Ì'..='Ï' => 'I',
+            'ì'..='ï' => 'i',
+            'Ð' => 'D',
+            'ð' => 'd',
+            'Ñ' => 'N',
+            'ñ' => 'n',
+            'Ò'..='Ö' | 'Ø' => 'O',
+            'ò'..='ö' | 'ø' => 'o',
+            'Ù'..='Ü' => 'U',
+            'ù'..='ü' => 'u',
+            'Ý' | 'Ÿ' => 'Y',
+            'ý' | 'ÿ' => 'y',
+            'ß' => 's',
+            '¡' => '!',
+            '¿' => '?',
+            '×' => '*',
+            '‘' | '’' | '´' => '\'',
+            '“' | '”' | '«' | '»' => '"',
+            '‐'..='—' | '−' => '-',
+            '…' => '.',
+            '\u{a0}' => ' ',
+            _ => return None,
+        };
+
+        Some(fallback)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -603,13 +1097,19 @@ pub const BLACK: Color = Color{r: 0, g: 0, b: 0};
 pub const WHITE: Color = Color{r: 255, g: 255, b: 255};
 pub const GREY: Color = Color{r: 128, g: 128, b: 128};
 
+/// The colors the debug view, menus and HUDs are drawn in
 #[derive(Clone, Copy)]
 pub struct DebugPalette {
     pub bg_col: Color,
     pub txt_col: Color,
     pub border_col: Color,
+    // Things that are on, set or fine (and off, clear or wrong)
     pub ok_col: Color,
     pub err_col: Color,
+    // The menu cursor and whatever it's pointing at
+    pub highlight_col: Color,
+    // Things that are there but don't apply right now
+    pub dim_col: Color,
 }
 
 pub const DEFAULT_DEBUG_PAL: DebugPalette = DebugPalette {
@@ -619,8 +1119,42 @@ pub const DEFAULT_DEBUG_PAL: DebugPalette = DebugPalette {
     border_col: Color{r: 0xF4, g: 0xA2, b: 0x59},
     ok_col: Color{r: 0x8C, g: 0xB3, b: 0x69},
     err_col: Color{r: 0xBC, g: 0x4B, b: 0x51},
+    highlight_col: WHITE,
+    dim_col: GREY,
+};
+
+pub const HIGH_CONTRAST_DEBUG_PAL: DebugPalette = DebugPalette {
+    bg_col: BLACK,
+    txt_col: WHITE,
+    border_col: Color{r: 0xFF, g: 0xFF, b: 0x00},
+    ok_col: Color{r: 0x00, g: 0xFF, b: 0xFF},
+    err_col: Color{r: 0xFF, g: 0x40, b: 0xFF},
+    highlight_col: Color{r: 0xFF, g: 0xFF, b: 0x00},
+    dim_col: Color{r: 0xA0, g: 0xA0, b: 0xA0},
 };
 
+// From the Okabe-Ito palette, which was picked to stay distinguishable with
+// every common kind of color blindness. Ok and error are blue and orange
+// rather than green and red.
+pub const COLOR_BLIND_DEBUG_PAL: DebugPalette = DebugPalette {
+    bg_col: BLACK,
+    txt_col: Color{r: 0xF0, g: 0xE4, b: 0x42},
+    border_col: Color{r: 0xE6, g: 0x9F, b: 0x00},
+    ok_col: Color{r: 0x56, g: 0xB4, b: 0xE9},
+    err_col: Color{r: 0xD5, g: 0x5E, b: 0x00},
+    highlight_col: WHITE,
+    dim_col: GREY,
+};
+
+/// The debug palette for a UI palette setting
+pub fn debug_palette(ui_palette: UiPalette) -> DebugPalette {
+    match ui_palette {
+        UiPalette::Default => DEFAULT_DEBUG_PAL,
+        UiPalette::HighContrast => HIGH_CONTRAST_DEBUG_PAL,
+        UiPalette::ColorBlind => COLOR_BLIND_DEBUG_PAL,
+    }
+}
+
 
 const DEBUG_NES_SCREEN_X: usize = 9;
 const DEBUG_NES_SCREEN_Y: usize = 38;
@@ -634,59 +1168,40 @@ const DEBUG_PGTBL2_VIEW_X: usize = 690;
 const DEBUG_PGTBL2_VIEW_Y: usize = 368;
 const DEBUG_FPS_COUNTER_X: usize = 835;
 const DEBUG_FPS_COUNTER_Y: usize = 520;
+const DEBUG_AUDIO_LATENCY_X: usize = 835;
+const DEBUG_AUDIO_LATENCY_Y: usize = 478;
 
 const MENU_CONTROLLER_X: usize = 27;
 const MENU_CONTROLLER_Y: usize = 145;
 const MENU_VOLUME_SLIDER_X: usize = 9;
 const MENU_VOLUME_SLIDER_Y: usize = 154;
 
-/// Function to draw a dot to the frame at some (x,y) pair. Doesn't check for
-/// out of bounds. Draws the dot with the top-left pixel at (x,y), not centered.
-pub fn dot(frame: &mut [u8], frame_width: usize, frame_height: usize, x: usize, 
-        y: usize, size: usize, color: Color) {
-    
-    // Handle sizes < 3 manually for speed
-    if size == 0 {
-        return;
-    } else if size == 1 {
-        let pix_idx = (y * frame_width + x)*4;
-        frame[pix_idx + 0] = color.r;
-        frame[pix_idx + 1] = color.g;
-        frame[pix_idx + 2] = color.b;
-        frame[pix_idx + 3] = 0xFF;
-    } else if size == 2 {
-        let top_left_idx = (y * frame_width + x)*4;
-        frame[top_left_idx + 0] = color.r;
-        frame[top_left_idx + 1] = color.g;
-        frame[top_left_idx + 2] = color.b;
-        frame[top_left_idx + 3] = 0xFF;
-
-        let top_right_idx = (y * frame_width + x + 1)*4;
-        frame[top_right_idx + 0] = color.r;
-        frame[top_right_idx + 1] = color.g;
-        frame[top_right_idx + 2] = color.b;
-        frame[top_right_idx + 3] = 0xFF;
-
-        let bottom_left_idx = ((y+1) * frame_width + x)*4;
-        frame[bottom_left_idx + 0] = color.r;
-        frame[bottom_left_idx + 1] = color.g;
-        frame[bottom_left_idx + 2] = color.b;
-        frame[bottom_left_idx + 3] = 0xFF;
-
-        let bottom_right_idx = ((y+1) * frame_width + x + 1)*4;
-        frame[bottom_right_idx + 0] = color.r;
-        frame[bottom_right_idx + 1] = color.g;
-        frame[bottom_right_idx + 2] = color.b;
-        frame[bottom_right_idx + 3] = 0xFF;
-    } else {
-        for i in 0..size {
-            for j in 0..size {
-                let pix_idx = ((y+i) * frame_width + x + j)*4;
-                frame[pix_idx + 0] = color.r;
-                frame[pix_idx + 1] = color.g;
-                frame[pix_idx + 2] = color.b;
-                frame[pix_idx + 3] = 0xFF;
-            }
+/// Function to draw a dot to the frame at some (x,y) pair. Draws the dot with
+/// the top-left pixel at (x,y), not centered. Whatever part of the dot is past
+/// the edge of the frame gets clipped off, so it's safe to draw partly (or
+/// completely) off-screen.
+pub fn dot(frame: &mut [u8], frame_width: usize, frame_height: usize, x: usize, 
+        y: usize, size: usize, color: Color) {
+    
+    // Clip the dot to the frame
+    let x_end = x.saturating_add(size).min(frame_width);
+    let y_end = y.saturating_add(size).min(frame_height);
+
+    if x >= x_end || y >= y_end {
+        return;
+    }
+
+    for py in y..y_end {
+        let row_start = (py * frame_width + x) * 4;
+        let row_end = (py * frame_width + x_end) * 4;
+
+        // A frame smaller than it says it is shouldn't take the app down either
+        let Some(row) = frame.get_mut(row_start..row_end) else {
+            return;
+        };
+
+        for pix in row.chunks_exact_mut(4) {
+            pix.copy_from_slice(&[color.r, color.g, color.b, 0xFF]);
         }
     }
 }
@@ -738,15 +1253,17 @@ pub fn draw_string(frame: &mut [u8], frame_width: usize, frame_height: usize,
     let vertical_step = text_size * chars::CHAR_HEIGHT + chars::NEWLINE_PADDING;
 
     let mut special = false;
-    for character in text.chars().into_iter() {
+    let mut characters = text.chars().peekable();
+    while let Some(character) = characters.next() {
         if character == '\n' {
             curr_x = x_pos;
             curr_y += vertical_step;
             continue;
         }
 
-        // backtick toggles the "special" flag
-        if character == '`' {
+        // backtick toggles the "special" flag, and two of them in a row draw
+        // a backtick
+        if character == '`' && characters.next_if_eq(&'`').is_none() {
             special = !special;
             continue;
         }
@@ -784,11 +1301,8 @@ pub fn horizontal_line(frame: &mut [u8], frame_width: usize, frame_height: usize
     if x_start >= frame_width {
         return;
     }
-    // clamp the line to on the screen
-    if x_end + thickness > frame_width {
-        x_end = frame_width - thickness;
-        x_start = std::cmp::min(x_start, x_end);
-    }
+    // clamp the line to on the screen (dot clips the last bit of thickness)
+    x_end = std::cmp::min(x_end, frame_width);
 
     for x in (x_start..x_end).step_by(thickness) {
         dot(frame, frame_width, frame_height, x, y, thickness, color);
@@ -818,11 +1332,8 @@ pub fn vertical_line(frame: &mut [u8], frame_width: usize, frame_height: usize,
     if y_start >= frame_height {
         return;
     }
-    // clamp the line to on the screen
-    if y_end + thickness > frame_height {
-        y_end = frame_height - thickness;
-        y_start = std::cmp::min(y_start, y_end);
-    }
+    // clamp the line to on the screen (dot clips the last bit of thickness)
+    y_end = std::cmp::min(y_end, frame_height);
 
     for y in (y_start..y_end).step_by(thickness) {
         dot(frame, frame_width, frame_height, x, y, thickness, color);
@@ -839,11 +1350,13 @@ pub fn draw_box(frame: &mut [u8], frame_width: usize, frame_height: usize,
         const TITLE_PADDING: usize = 5;
 
         let title_x = x + (width as f64 * TITLE_OFFSET) as usize;
-        let title_y = y - chars::CHAR_HEIGHT; // Titles use large chars, so 1/2 large char height is just char height
+        // Titles use large chars, so 1/2 large char height is just char height.
+        // A box right at the top of the frame has its title cut off instead.
+        let title_y = y.saturating_sub(chars::CHAR_HEIGHT);
 
-        let title_width = text.len() * (chars::CHAR_WIDTH * 2 - 1) - 5;
+        let title_width = text.chars().count() * (chars::CHAR_WIDTH * 2 - 1) - 5;
 
-        horizontal_line(frame, frame_width, frame_height, x, title_x - TITLE_PADDING, y, thickness, palette.border_col);
+        horizontal_line(frame, frame_width, frame_height, x, title_x.saturating_sub(TITLE_PADDING), y, thickness, palette.border_col);
         horizontal_line(frame, frame_width, frame_height, title_x + title_width + TITLE_PADDING, x + width, y, thickness, palette.border_col);
 
         draw_string(frame, frame_width, frame_height, text, title_x, title_y, palette.border_col, palette.bg_col, 2);
@@ -861,28 +1374,173 @@ pub fn draw_box(frame: &mut [u8], frame_width: usize, frame_height: usize,
 }
 
 
+/// Draws the NES screen with its top left corner at (x, y). Whatever doesn't
+/// fit in the frame is cut off.
 pub fn draw_nes_screen(frame: &mut [u8], frame_width: usize, frame_height: usize, 
                     screen_buf: &[u8], x: usize, y: usize, double_size: bool) {
     
+    const ROW_BYTES: usize = 256 * 4;
+
+    let s = if double_size { 2 } else { 1 };
+
+    // How much of the (scaled) screen lands on the frame, in frame pixels
+    let width = (256 * s).min(frame_width.saturating_sub(x));
+    let height = (screen_buf.len() / ROW_BYTES * s).min(frame_height.saturating_sub(y));
+
+    // The PPU only writes opaque pixels, so rows can be copied over as they
+    // are instead of going through dot()
+    for fy in 0..height {
+        let start = ((y + fy) * frame_width + x) * 4;
+
+        // A frame smaller than it says it is shouldn't take the app down
+        if start + width * 4 > frame.len() {
+            return;
+        }
+
+        // The second row of each doubled pair is the same as the first
+        if s == 2 && fy % 2 == 1 {
+            let above = start - frame_width * 4;
+            frame.copy_within(above..above + width * 4, start);
+            continue;
+        }
+
+        let src = &screen_buf[fy / s * ROW_BYTES..(fy / s + 1) * ROW_BYTES];
+        let dest = &mut frame[start..start + width * 4];
+
+        if s == 1 {
+            dest.copy_from_slice(&src[..width * 4]);
+        } else {
+            for (pair, pix) in dest.chunks_mut(8).zip(src.chunks_exact(4)) {
+                for out in pair.chunks_mut(4) {
+                    out.copy_from_slice(pix);
+                }
+            }
+        }
+    }
+}
+
+/// Draws a ghost run's screen see-through over whatever's already been drawn,
+/// with its top left corner at (x, y)
+pub fn draw_ghost(frame: &mut [u8], frame_width: usize, frame_height: usize,
+                    ghost_buf: &[u8], x: usize, y: usize, double_size: bool) {
+    // How much of the ghost shows through, out of 255
+    const GHOST_OPACITY: u16 = 96;
+
     let s = if double_size { 2 } else { 1 };
 
-    for (py, row) in screen_buf.chunks(256*4).enumerate() {
+    for (py, row) in ghost_buf.chunks(256*4).enumerate() {
+        for (px, pix) in row.chunks(4).enumerate() {
+            for (fx, fy) in (0..s).flat_map(|dy| (0..s).map(move |dx| (x + s*px + dx, y + s*py + dy))) {
+                if fx >= frame_width || fy >= frame_height {
+                    continue;
+                }
+
+                let i = (fy * frame_width + fx) * 4;
+                for c in 0..3 {
+                    frame[i + c] = ((frame[i + c] as u16 * (255 - GHOST_OPACITY) + pix[c] as u16 * GHOST_OPACITY) / 255) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Draws the NES screen (double size) with each pixel tinted red by how
+/// recently the game wrote to the nametable or attribute byte behind it. Bright
+/// red was written this frame, and the tint fades out over about a second.
+pub fn draw_write_heatmap(frame: &mut [u8], frame_width: usize, frame_height: usize,
+                        nes: &Nes, x: usize, y: usize) {
+    const FADE_FRAMES: u64 = 60;
+
+    let heat = nes.bg_write_heat(FADE_FRAMES);
+
+    for (py, row) in nes.screen_buf_slice().chunks(256*4).enumerate() {
         for (px, pix) in row.chunks(4).enumerate() {
-            dot(frame, frame_width, frame_height, x+s*px, y+s*py, s, Color::from(pix));
+            // Darken the picture a bit so the tint stands out
+            let amount = heat[py * 256 + px] as u16;
+            let blend = |channel: u8, tint: u8| ((channel as u16 / 2 * (255 - amount) + tint as u16 * amount) / 255) as u8;
+
+            let color = Color {
+                r: blend(pix[0], RED.r),
+                g: blend(pix[1], RED.g),
+                b: blend(pix[2], RED.b),
+            };
+
+            dot(frame, frame_width, frame_height, x+2*px, y+2*py, 2, color);
+        }
+    }
+}
+
+/// Draws a dotted line across each scanline the game wrote PPUCTRL (red),
+/// PPUSCROLL (green) or PPUADDR (cyan) on last frame, with a solid tick at the
+/// dot the write happened on. Writes on the pre-render line aren't drawn since
+/// they're not on any visible row.
+pub fn draw_raster_write_markers(frame: &mut [u8], frame_width: usize, frame_height: usize,
+                        nes: &Nes, x: usize, y: usize, scale: usize) {
+    const CYAN: Color = Color{r: 0, g: 255, b: 255};
+    const TICK_WIDTH: usize = 4;
+
+    for write in nes.raster_writes().iter().filter(|write| write.scanline < NES_SCREEN_HEIGHT) {
+        let color = match write.address & 0x0007 {
+            0 => RED,
+            5 => GREEN,
+            _ => CYAN,
+        };
+        let row = y + write.scanline * scale;
+
+        for px in (0..NES_SCREEN_WIDTH).step_by(2) {
+            dot(frame, frame_width, frame_height, x + px * scale, row, scale, color);
+        }
+
+        // Dot 0 is idle and dots past 256 are off the right edge of the picture
+        let tick_x = write.dot.saturating_sub(1).min(NES_SCREEN_WIDTH - TICK_WIDTH);
+        for px in tick_x..tick_x + TICK_WIDTH {
+            dot(frame, frame_width, frame_height, x + px * scale, row, scale, color);
         }
     }
 }
 
+/// Draws a line across each scanline where the game changed the scroll
+/// position last frame. The color says what changed: red for the X scroll,
+/// green for the Y scroll and blue for the nametable, mixed together when more
+/// than one changed (so an X and Y change is yellow).
+pub fn draw_scroll_splits(frame: &mut [u8], frame_width: usize, frame_height: usize,
+                        nes: &Nes, x: usize, y: usize, scale: usize) {
+    // Channels that didn't change aren't completely off so a blue line is
+    // still visible on a dark background
+    let channel = |changed: bool| if changed { 255 } else { 64 };
+
+    for (scanline, split) in nes.scroll_splits().iter().enumerate().filter(|(_, split)| split.any()) {
+        let color = Color {
+            r: channel(split.scroll_x()),
+            g: channel(split.scroll_y()),
+            b: channel(split.nametable()),
+        };
+
+        horizontal_line(frame, frame_width, frame_height, x, x + NES_SCREEN_WIDTH * scale, 
+                        y + scanline * scale, scale, color);
+    }
+}
+
+/// The colors pagetables are drawn in when no palette is picked for them
+const PGTBL_GREYSCALE: [Color; 4] = [
+    Color{r: 0x40, g: 0x40, b: 0x40},
+    Color{r: 0x80, g: 0x80, b: 0x80},
+    Color{r: 0xC0, g: 0xC0, b: 0xC0},
+    Color{r: 0xF0, g: 0xF0, b: 0xF0},
+];
+
+/// The colors to draw the pagetables in: one of the palettes (0-7) currently
+/// in palette RAM, or greyscale
+pub fn pagetable_colors(nes: &Nes, palette: Option<u8>) -> [Color; 4] {
+    match palette {
+        Some(palette) => nes.palette_colors(palette).map(|col| Color{r: col.r, g: col.g, b: col.b}),
+        None => PGTBL_GREYSCALE,
+    }
+}
+
 pub fn draw_nes_pagetable_8x8(frame: &mut [u8], frame_width: usize, frame_height: usize, 
-                        pagetable: Box<[u8; 0x1000]>, x: usize, y: usize) {
+                        pagetable: Box<[u8; 0x1000]>, colors: [Color; 4], x: usize, y: usize) {
     
-    const GREYSCALE: [Color; 4] = [
-        Color{r: 0x40, g: 0x40, b: 0x40},
-        Color{r: 0x80, g: 0x80, b: 0x80},
-        Color{r: 0xC0, g: 0xC0, b: 0xC0},
-        Color{r: 0xF0, g: 0xF0, b: 0xF0},
-    ];
-
     const SPRITE_WIDTH: usize = 8; // Num pixels per side of sprite
     const PGTBL_WIDTH: usize = 16; // Num sprites per side of pagetable
     
@@ -897,7 +1555,7 @@ pub fn draw_nes_pagetable_8x8(frame: &mut [u8], frame_width: usize, frame_height
 
                 for c in 0..8 {
                     let col_idx = (((msb_byte >> (7 - c)) & 1) << 1) | ((lsb_byte >> (7 - c)) & 1);
-                    let col = GREYSCALE[col_idx as usize];
+                    let col = colors[col_idx as usize];
 
                     let pixel_x = x + spr_x*SPRITE_WIDTH + c;
                     let pixel_y = y + spr_y*SPRITE_WIDTH + r;
@@ -910,14 +1568,7 @@ pub fn draw_nes_pagetable_8x8(frame: &mut [u8], frame_width: usize, frame_height
 }
 
 pub fn draw_nes_pagetable_8x16(frame: &mut [u8], frame_width: usize, frame_height: usize, 
-                        pagetable: Box<[u8; 0x1000]>, x: usize, y: usize) {
-
-    const GREYSCALE: [Color; 4] = [
-        Color{r: 0x40, g: 0x40, b: 0x40},
-        Color{r: 0x80, g: 0x80, b: 0x80},
-        Color{r: 0xC0, g: 0xC0, b: 0xC0},
-        Color{r: 0xF0, g: 0xF0, b: 0xF0},
-    ];
+                        pagetable: Box<[u8; 0x1000]>, colors: [Color; 4], x: usize, y: usize) {
 
     const SPRITE_WIDTH: usize = 8; // Num pixels per side of sprite
     const PGTBL_WIDTH: usize = 16; // Num sprites per side of pagetable
@@ -935,7 +1586,7 @@ pub fn draw_nes_pagetable_8x16(frame: &mut [u8], frame_width: usize, frame_heigh
 
                 for c in 0..8 {
                     let col_idx = (((msb_byte >> (7 - c)) & 1) << 1) | ((lsb_byte >> (7 - c)) & 1);
-                    let col = GREYSCALE[col_idx as usize];
+                    let col = colors[col_idx as usize];
 
                     let pixel_x = x + spr_x*SPRITE_WIDTH + c;
                     let pixel_y = y + spr_y*SPRITE_WIDTH + r;
@@ -952,12 +1603,12 @@ pub fn draw_cpu_state(frame: &mut [u8], frame_width: usize, frame_height: usize,
     
     let cpu_state = nes.get_cpu_state();
 
-    let mut text = String::with_capacity(200);
-    text.push_str(&format!("A:{:02X}  X:{:02X}  Y:{:02X}\n", cpu_state.acc, cpu_state.x, cpu_state.y));
-    text.push_str(&format!("SP:{:02X}  PC:{:04X}\n", cpu_state.sp, cpu_state.pc));
-    text.push_str(&format!("Total Clks:{}\nStatus:", cpu_state.total_clocks));
+    let mut state_text = String::with_capacity(200);
+    state_text.push_str(&format!("A:{:02X}  X:{:02X}  Y:{:02X}\n", cpu_state.acc, cpu_state.x, cpu_state.y));
+    state_text.push_str(&format!("SP:{:02X}  PC:{:04X}\n", cpu_state.sp, cpu_state.pc));
+    state_text.push_str(&fill(Text::CpuClocks, &[&cpu_state.total_clocks.to_string()]));
 
-    let (next_x, next_y) = draw_string(frame, frame_width, frame_height, &text, x, y, palette.txt_col, palette.bg_col, 2);
+    let (next_x, next_y) = draw_string(frame, frame_width, frame_height, &state_text, x, y, palette.txt_col, palette.bg_col, 2);
     // Flags
     let (next_x, next_y) = draw_string(frame, frame_width, frame_height, 
         "N", next_x, next_y, 
@@ -995,25 +1646,269 @@ pub fn draw_cpu_state(frame: &mut [u8], frame_width: usize, frame_height: usize,
     let instr_str = format!("{: <34}", nes.get_cpu().current_instr_str());
 
     let (next_x, next_y) = draw_string(frame, frame_width, frame_height, 
-        "Last Instr:", x, next_y, 
+        text(Text::LastInstruction), x, next_y, 
         palette.txt_col, palette.bg_col, 1);
     draw_string(frame, frame_width, frame_height, 
         &instr_str, next_x, next_y, 
         palette.txt_col, palette.bg_col, 1);
 }
 
-fn draw_zpage(frame: &mut [u8], frame_width: usize, frame_height: usize,
-            nes: &mut Nes, x: usize, y: usize, palette: DebugPalette) {
+/// Draws a page of CPU memory, 16 bytes to a line, with the lines that are
+/// only mirrors of memory lower down greyed out. What's in the page is named
+/// on the right of the panel's top border (see "ON THE CPU MEMORY MAP").
+fn draw_memory_page(frame: &mut [u8], frame_width: usize, frame_height: usize,
+            rows: &[(CpuRegion, String)], label: &str, x: usize, y: usize, palette: DebugPalette) {
+    
+    // The label sits in a gap at the right end of the panel's top border
+    const LABEL_LEN: usize = 30;
+    let border_x = x - 7;
+    let border_y = y - 11;
+    let border_end = border_x + 390;
+    let label_x = border_end - 6 - (LABEL_LEN + 2) * chars::CHAR_WIDTH;
+
+    horizontal_line(frame, frame_width, frame_height, label_x, border_end, border_y, 2, palette.border_col);
+
+    let label: String = label.chars().take(LABEL_LEN).collect();
+    let label = format!(" {label} ");
+    let label_width = label.chars().count() * chars::CHAR_WIDTH;
+    draw_string(frame, frame_width, frame_height, &label, border_end - 6 - label_width, border_y - 3, 
+        palette.border_col, palette.bg_col, 1);
+
+    let mut row_y = y;
+    for (region, row) in rows {
+        let col = if region.mirror { palette.dim_col } else { palette.txt_col };
+        let (_, next_y) = draw_string(frame, frame_width, frame_height, &format!("{row}\n"), x, row_y, col, palette.bg_col, 1);
+
+        row_y = next_y;
+    }
+}
+
+/// Draws the mapper's number and name, then its registers one to a line. Used
+/// in place of the memory view.
+fn draw_mapper_state(frame: &mut [u8], frame_width: usize, frame_height: usize,
+            nes: &Nes, x: usize, y: usize, palette: DebugPalette) {
+    
+    let (mapper_num, mapper_name) = nes.mapper_info();
+    let registers = nes.mapper_debug_state();
+    let name_width = registers.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+    let mut state_str = format!("{}\n\n", fill(Text::MapperLabel, &[&mapper_num.to_string(), mapper_name]));
+    for (name, value) in &registers {
+        state_str.push_str(&format!("{name:<name_width$}  {value}\n"));
+    }
+
+    draw_string(frame, frame_width, frame_height, &state_str, x, y, palette.txt_col, palette.bg_col, 1);
+}
+
+/// Draws the loaded ROM's name and hashes: the CRC-32 and SHA-1 of the whole
+/// ROM and of the PRG and CHR ROM on their own. Until they've been worked out
+/// only the CRC-32 of the whole ROM is known. Used in place of the memory
+/// view.
+fn draw_rom_info(frame: &mut [u8], frame_width: usize, frame_height: usize,
+            nes: &Nes, x: usize, y: usize, palette: DebugPalette) {
+    
+    // As long as a SHA-1 line
+    const LINE_LEN: usize = 52;
+
+    let mut lines = vec![String::from(nes.rom_name()), String::new()];
+    lines.push(format!("ROM CRC-32  {:08X}", nes.rom_crc32()));
+
+    match nes.rom_hashes() {
+        Some(hashes) => {
+            lines.push(format!("    SHA-1   {}", RomHashes::sha1_hex(&hashes.rom_sha1)));
+            lines.push(format!("PRG CRC-32  {:08X}", hashes.prg_crc32));
+            lines.push(format!("    SHA-1   {}", RomHashes::sha1_hex(&hashes.prg_sha1)));
+
+            match (hashes.chr_crc32, &hashes.chr_sha1) {
+                (Some(chr_crc32), Some(chr_sha1)) => {
+                    lines.push(format!("CHR CRC-32  {chr_crc32:08X}"));
+                    lines.push(format!("    SHA-1   {}", RomHashes::sha1_hex(chr_sha1)));
+                }
+                _ => lines.push(format!("CHR         {}", text(Text::ChrRam))),
+            }
+        }
+        None => lines.extend([String::new(), String::from(text(Text::RomHashing))]),
+    }
+
+    // Padded out so nothing from the last ROM (or the last panel) is left
+    let mut info_str = String::new();
+    lines.resize(10, String::new());
+    for line in &lines {
+        info_str.push_str(&format!("{line:<LINE_LEN$}\n"));
+    }
+
+    draw_string(frame, frame_width, frame_height, &info_str, x, y, palette.txt_col, palette.bg_col, 1);
+}
+
+/// Draws everything the game has done that isn't emulated (see telemetry),
+/// one to a line, with how many times and the frame it first happened on.
+/// Used in place of the memory view.
+fn draw_unsupported_uses(frame: &mut [u8], frame_width: usize, frame_height: usize,
+            uses: &[UnsupportedUse], x: usize, y: usize, palette: DebugPalette) {
+    
+    const LINES: usize = 15;
+    const LINE_LEN: usize = 48;
+
+    let (_, mut line_y) = draw_string(frame, frame_width, frame_height, 
+        &format!("{:<26} {:>10} {:>9}\n", "Feature", "Count", "First"), 
+        x, y, palette.txt_col, palette.bg_col, 1);
+
+    for i in 0..LINES {
+        let line = match uses.get(i) {
+            // The last line says how many more there are that didn't fit
+            Some(_) if i == LINES - 1 && uses.len() > LINES => format!("(+{} more)", uses.len() - i),
+            Some(used) => format!("{:<26} {:>10} {:>9}", used.feature.describe(), used.count, used.first_frame),
+            None if i == 0 => String::from(text(Text::NothingUnsupported)),
+            None => String::new(),
+        };
+
+        (_, line_y) = draw_string(frame, frame_width, frame_height, &format!("{line:<LINE_LEN$}\n"), x, line_y, 
+            palette.txt_col, palette.bg_col, 1);
+    }
+}
+
+/// Draws the most recent interrupts, one to a line, with the frame, scanline
+/// and dot each one started on. Serviced interrupts are drawn in the text
+/// color, ignored ones in the error color and pending ones in the ok color.
+/// Used in place of the memory view.
+fn draw_interrupt_log(frame: &mut [u8], frame_width: usize, frame_height: usize,
+            interrupt_log: &[InterruptEvent], x: usize, y: usize, palette: DebugPalette) {
+    
+    const LOG_LINES: usize = 15;
+    const LINE_LEN: usize = 38;
+
+    let (_, mut line_y) = draw_string(frame, frame_width, frame_height, 
+        &format!("{:<10} {:>7} {:>4} {:>4}  {:<8}\n", "Source", "Frame", "Line", "Dot", "Outcome"), 
+        x, y, palette.txt_col, palette.bg_col, 1);
+
+    let first_shown = interrupt_log.len().saturating_sub(LOG_LINES);
+    for i in 0..LOG_LINES {
+        let (line, color) = match interrupt_log.get(first_shown + i) {
+            Some(event) => (
+                format!("{:<10} {:>7} {:>4} {:>4}  {:<8}\n", event.source.name(), event.frame, 
+                    event.scanline, event.dot, event.outcome.name()),
+                match event.outcome {
+                    InterruptOutcome::Serviced => palette.txt_col,
+                    InterruptOutcome::Ignored => palette.err_col,
+                    InterruptOutcome::Pending => palette.ok_col,
+                },
+            ),
+            None => (format!("{: <LINE_LEN$}\n", ""), palette.txt_col),
+        };
+
+        (_, line_y) = draw_string(frame, frame_width, frame_height, &line, x, line_y, 
+            color, palette.bg_col, 1);
+    }
+}
+
+/// Draws the most recent APU register writes on the left and a plot of the
+/// recent values written to $4011 (DMC direct load) on the right. Used in place
+/// of the memory view when APU debugging is enabled.
+fn draw_apu_log(frame: &mut [u8], frame_width: usize, frame_height: usize,
+            nes: &Nes, x: usize, y: usize, palette: DebugPalette) {
+    
+    const LOG_LINES: usize = 15;
+    const PLOT_OFFSET_X: usize = 176;
+    const PLOT_WIDTH: usize = PCM_LOG_SIZE;
+    const PLOT_HEIGHT: usize = 128; // $4011 takes 7 bit values
+
+    let write_log = nes.apu_write_log();
+
+    let mut log_str = String::with_capacity(LOG_LINES * 24);
+    let first_shown = write_log.len().saturating_sub(LOG_LINES);
+    for i in 0..LOG_LINES {
+        match write_log.get(first_shown + i) {
+            Some(write) => log_str.push_str(&format!("{:>10} ${:04X}<-${:02X}\n", 
+                write.clock, write.address, write.data)),
+            None => log_str.push_str(&format!("{: <22}\n", "")),
+        }
+    }
+
+    draw_string(frame, frame_width, frame_height, &log_str, x, y, palette.txt_col, palette.bg_col, 1);
+
+    // PCM plot
+    let plot_x = x + PLOT_OFFSET_X;
+    let (_, plot_y) = draw_string(frame, frame_width, frame_height, "$4011 PCM\n", 
+        plot_x, y, palette.txt_col, palette.bg_col, 1);
+
+    for py in plot_y..plot_y + PLOT_HEIGHT {
+        for px in plot_x..plot_x + PLOT_WIDTH {
+            dot(frame, frame_width, frame_height, px, py, 1, palette.bg_col);
+        }
+    }
+    horizontal_line(frame, frame_width, frame_height, plot_x, plot_x + PLOT_WIDTH, 
+        plot_y + PLOT_HEIGHT / 2, 1, GREY);
+
+    for (i, level) in nes.apu_pcm_log().iter().enumerate() {
+        let py = plot_y + PLOT_HEIGHT - 1 - *level as usize;
+
+        dot(frame, frame_width, frame_height, plot_x + i, py, 1, palette.ok_col);
+    }
+}
+
+/// Draws the PPU's background rendering pipeline: the four 16 bit shift
+/// registers as strips of bits (the bit picked out by fine x is outlined), the
+/// latches holding the next tile, and the pixel the shifters are producing.
+/// The upper 8 bits of each shift register are the tile being drawn and the
+/// lower 8 bits are the next tile, which get shifted in one bit per dot.
+/// https://www.nesdev.org/wiki/PPU_rendering
+fn draw_ppu_shifters(frame: &mut [u8], frame_width: usize, frame_height: usize,
+            nes: &Nes, x: usize, y: usize, palette: DebugPalette) {
     
-    let zpage_str = nes.zpage_str();
+    const LABEL_WIDTH: usize = 8 * (chars::CHAR_WIDTH - 1);
+    const CELL_PITCH: usize = 12;
+    const CELL_SIZE: usize = 8;
+
+    let shift_regs = nes.ppu_bg_shift_regs();
+    let latches = nes.ppu_bg_next_tile_latches();
+    let fine_x = nes.ppu_fine_x() as usize;
+    let (dot_num, scanline) = nes.ppu_position();
+
+    let header = format!("Fine X: {fine_x}  Dot: {dot_num:>3}  Line: {scanline:>3}\n\n        Current     Next\n");
+    let (_, mut row_y) = draw_string(frame, frame_width, frame_height, &header, 
+        x, y, palette.txt_col, palette.bg_col, 1);
+
+    const REG_NAMES: [&str; 4] = ["Pat Lo", "Pat Hi", "Att Lo", "Att Hi"];
+
+    for (name, reg) in REG_NAMES.iter().zip(shift_regs) {
+        draw_string(frame, frame_width, frame_height, name, 
+            x, row_y, palette.txt_col, palette.bg_col, 1);
+
+        for bit in 0..16 {
+            let cell_x = x + LABEL_WIDTH + bit * CELL_PITCH;
+            let set = (reg >> (15 - bit)) & 1 == 1;
+
+            // Outline the bit that's being drawn this dot
+            let outline_col = if bit == fine_x { palette.err_col } else { palette.bg_col };
+            dot(frame, frame_width, frame_height, cell_x, row_y, CELL_SIZE + 2, outline_col);
+
+            let bit_col = if set { palette.txt_col } else { palette.dim_col };
+            dot(frame, frame_width, frame_height, cell_x + 1, row_y + 1, CELL_SIZE, bit_col);
+        }
+
+        row_y += CELL_PITCH + 2;
+    }
 
-    draw_string(frame, frame_width, frame_height, &zpage_str, x, y, palette.txt_col, palette.bg_col, 1);
+    // The pixel the shifters are outputting right now
+    let bit = |reg: u16| ((reg >> (15 - fine_x)) & 1) as u8;
+    let pixel = (bit(shift_regs[1]) << 1) | bit(shift_regs[0]);
+    let bg_palette = (bit(shift_regs[3]) << 1) | bit(shift_regs[2]);
+
+    let latch_str = format!(
+        "\nNext tile latches:\n NT: ${:02X}  AT: {}  Lo: ${:02X}  Hi: ${:02X}\n\nOutput: pixel {}, palette {}",
+        latches[0], latches[1] & 3, latches[2], latches[3], pixel, bg_palette,
+    );
+    draw_string(frame, frame_width, frame_height, &latch_str, 
+        x, row_y, palette.txt_col, palette.bg_col, 1);
 }
 
 /// Draw the background of the debug view to the frame buffer. This renders the
-/// title, outlines, and pagetables (i.e. everything that doesn't change)
-/// This function should only be called once.
-pub fn draw_debug_bg(frame: &mut [u8], palette: DebugPalette, nes: &Nes) {
+/// title and outlines (i.e. everything that doesn't change), and forgets what
+/// draw_debug has drawn so it all gets drawn again over the new background.
+pub fn draw_debug_bg(frame: &mut [u8], palette: DebugPalette, panel: DebugPanel, cache: &mut DebugViewCache) {
+    cache.invalidate();
+
+
     // TITLE DECOR
     horizontal_line(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 5, 255, 4, 2, palette.border_col);
     horizontal_line(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 10, 250, 10, 2, palette.border_col);
@@ -1031,7 +1926,7 @@ pub fn draw_debug_bg(frame: &mut [u8], palette: DebugPalette, nes: &Nes) {
     // PAGETABLE VIEWS
     draw_box(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
         DEBUG_PGTBL1_VIEW_X - 10, DEBUG_PGTBL1_VIEW_Y - 14,
-         290, 175, 2, palette, Some("Pagetables"));
+         290, 175, 2, palette, Some(text(Text::Pagetables)));
     draw_box(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
         DEBUG_PGTBL1_VIEW_X - 4, DEBUG_PGTBL1_VIEW_Y - 4,
         134, 134, 2, palette, None);
@@ -1039,66 +1934,219 @@ pub fn draw_debug_bg(frame: &mut [u8], palette: DebugPalette, nes: &Nes) {
         DEBUG_PGTBL2_VIEW_X - 4, DEBUG_PGTBL2_VIEW_Y - 4,
         134, 134, 2, palette, None);
 
-    let pgtbl1 = nes.get_pgtbl1();
-    let pgtbl2 = nes.get_pgtbl2();
-
-    if nes.large_sprites() {
-        draw_nes_pagetable_8x16(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, pgtbl1, DEBUG_PGTBL1_VIEW_X, DEBUG_PGTBL1_VIEW_Y);
-        draw_nes_pagetable_8x16(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, pgtbl2, DEBUG_PGTBL2_VIEW_X, DEBUG_PGTBL2_VIEW_Y);
-    } else {
-        draw_nes_pagetable_8x8(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, pgtbl1, DEBUG_PGTBL1_VIEW_X, DEBUG_PGTBL1_VIEW_Y);
-        draw_nes_pagetable_8x8(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, pgtbl2, DEBUG_PGTBL2_VIEW_X, DEBUG_PGTBL2_VIEW_Y);
-    }
-
     // CPU INFO DECOR
     draw_box(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
         DEBUG_CPU_STATE_X - 7, DEBUG_CPU_STATE_Y - 11, 
-        331, 100, 2, palette, Some("CPU Info"));
+        331, 100, 2, palette, Some(text(Text::CpuInfo)));
+
+    // ZPAGE DECOR (or whichever panel is being shown in its place)
+    let zpage_title = match panel {
+        DebugPanel::Memory(_) => text(Text::MemoryPanel),
+        DebugPanel::ApuLog => text(Text::ApuLog),
+        DebugPanel::PpuShifters => text(Text::PpuShifters),
+        DebugPanel::Mapper => text(Text::MapperPanel),
+        DebugPanel::Interrupts => text(Text::InterruptsPanel),
+        DebugPanel::RomInfo => text(Text::RomInfoPanel),
+        DebugPanel::Unsupported => text(Text::UnsupportedPanel),
+    };
 
-    // ZPAGE DECOR
     draw_box(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
         DEBUG_ZPAGE_STATE_X - 7, DEBUG_ZPAGE_STATE_Y - 11, 
-        390, 188, 2, palette, Some("Zero-Page"))
+        390, 188, 2, palette, Some(zpage_title))
 }
 
-pub fn draw_debug(frame: &mut [u8], palette: DebugPalette, nes: &mut Nes, fps: usize) {
-    draw_nes_screen(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes.screen_buf_slice(), 
-                        DEBUG_NES_SCREEN_X, DEBUG_NES_SCREEN_Y, true);
+/// Draws everything in the debug view that can change, skipping the parts
+/// that would come out the same as last time (see debug_cache.rs)
+pub fn draw_debug(frame: &mut [u8], palette: DebugPalette, nes: &mut Nes, ghost: Option<&[u8]>, fps: usize, 
+                panel: DebugPanel, overlays: ScreenOverlays, pgtbl_palette: Option<u8>, cache: &mut DebugViewCache) {
+    let cpu_state = nes.get_cpu_state();
+
+    // The picture only changes when a frame finishes, but the overlays are
+    // read from the PPU as it goes, so any clocks run count as a change
+    let screen_key = (nes.rom_crc32(), nes.frames_run(), cpu_state.total_clocks, overlays, ghost.is_some());
+    if cache.needs_redraw(DebugRegion::NesScreen, screen_key) {
+        draw_debug_screen(frame, nes, ghost, overlays);
+    }
+
+    let pgtbl1 = nes.get_pgtbl1();
+    let pgtbl2 = nes.get_pgtbl2();
+    let large_sprites = nes.large_sprites();
+    let pgtbl_colors = pagetable_colors(nes, pgtbl_palette);
+    let colors_key = pgtbl_colors.map(|col| [col.r, col.g, col.b]);
+
+    // Redrawn whenever the bytes change, which covers CHR bank switches and
+    // CHR RAM writes without the mappers having to say when they happen
+    if cache.needs_redraw(DebugRegion::Pagetables, (&pgtbl1, &pgtbl2, large_sprites, colors_key)) {
+        if large_sprites {
+            draw_nes_pagetable_8x16(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, pgtbl1, pgtbl_colors, DEBUG_PGTBL1_VIEW_X, DEBUG_PGTBL1_VIEW_Y);
+            draw_nes_pagetable_8x16(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, pgtbl2, pgtbl_colors, DEBUG_PGTBL2_VIEW_X, DEBUG_PGTBL2_VIEW_Y);
+        } else {
+            draw_nes_pagetable_8x8(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, pgtbl1, pgtbl_colors, DEBUG_PGTBL1_VIEW_X, DEBUG_PGTBL1_VIEW_Y);
+            draw_nes_pagetable_8x8(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, pgtbl2, pgtbl_colors, DEBUG_PGTBL2_VIEW_X, DEBUG_PGTBL2_VIEW_Y);
+        }
+    }
 
-    draw_cpu_state(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
-                    DEBUG_CPU_STATE_X, DEBUG_CPU_STATE_Y, palette);
+    let cpu_key = (
+        [cpu_state.acc, cpu_state.x, cpu_state.y, cpu_state.sp, cpu_state.status.into_bits()],
+        cpu_state.pc, cpu_state.total_clocks, nes.get_cpu().current_instr_str(),
+    );
+    if cache.needs_redraw(DebugRegion::CpuState, cpu_key) {
+        draw_cpu_state(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
+                        DEBUG_CPU_STATE_X, DEBUG_CPU_STATE_Y, palette);
+    }
 
-    draw_zpage(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
-                DEBUG_ZPAGE_STATE_X, DEBUG_ZPAGE_STATE_Y, palette);
+    match panel {
+        DebugPanel::Memory(page) => {
+            let start = (page as u16) << 8;
+            let rows: Vec<(CpuRegion, String)> = (start..=start | 0xFF).step_by(16)
+                .map(|row_start| {
+                    let mut row = format!("${row_start:04X}:");
+                    for address in row_start..row_start + 16 {
+                        row.push_str(&format!(" {:02X}", nes.peek_cpu(address)));
+                    }
+
+                    (nes.cpu_region(row_start), row)
+                })
+                .collect();
+
+            // Every region boundary is on a line boundary except the PPU
+            // register mirrors at $2008, which the next line covers anyway
+            let mut names: Vec<&str> = Vec::new();
+            for (region, _) in &rows {
+                if !names.contains(&region.name.as_str()) {
+                    names.push(&region.name);
+                }
+            }
+            let label = format!("${start:04X} {}", names.join(", "));
 
-    let mirror_text = match nes.current_mirror_type() {
-        NametableMirror::Horizontal =>        "Horizontal    ",
-        NametableMirror::Vertical =>          "Vertical      ",
-        NametableMirror::SingleScreenLower => "1-Screen Lower",
-        NametableMirror::SingleScreenUpper => "1-Screen Upper",
-        NametableMirror::FourScreen =>        "4-Screen      ",
-    };
-    let mirror_text = format!("Mirror: {mirror_text}\n");
+            if cache.needs_redraw(DebugRegion::Panel, (panel, &rows, &label)) {
+                draw_memory_page(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, &rows, &label, 
+                    DEBUG_ZPAGE_STATE_X, DEBUG_ZPAGE_STATE_Y, palette);
+            }
+        }
+        DebugPanel::ApuLog => {
+            if cache.needs_redraw(DebugRegion::Panel, (panel, nes.apu_write_log(), nes.apu_pcm_log())) {
+                draw_apu_log(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
+                    DEBUG_ZPAGE_STATE_X, DEBUG_ZPAGE_STATE_Y, palette);
+            }
+        }
+        DebugPanel::PpuShifters => {
+            let shifters_key = (panel, nes.ppu_bg_shift_regs(), nes.ppu_bg_next_tile_latches(), 
+                nes.ppu_fine_x(), nes.ppu_position());
 
-    let (new_x, new_y) = draw_string(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
-        &mirror_text, DEBUG_PGTBL1_VIEW_X - 4, DEBUG_PGTBL1_VIEW_Y + 128 + 9, 
-        palette.txt_col, palette.bg_col, 1);
-    
-    let spr_size_text = if nes.large_sprites() {
-        "8x16 - Large"
-    } else {
-        "8x8 - Small "
-    };
-    let spr_size_text = format!("Spr Size: {spr_size_text}");
+            if cache.needs_redraw(DebugRegion::Panel, shifters_key) {
+                draw_ppu_shifters(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
+                    DEBUG_ZPAGE_STATE_X, DEBUG_ZPAGE_STATE_Y, palette);
+            }
+        }
+        DebugPanel::Mapper => {
+            let registers = nes.mapper_debug_state();
 
-    draw_string(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
-        &spr_size_text, new_x, new_y, 
-        palette.txt_col, palette.bg_col, 1);
+            if cache.needs_redraw(DebugRegion::Panel, (panel, nes.mapper_info(), &registers)) {
+                draw_mapper_state(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
+                    DEBUG_ZPAGE_STATE_X, DEBUG_ZPAGE_STATE_Y, palette);
+            }
+        }
+        DebugPanel::Interrupts => {
+            let interrupt_log = nes.interrupt_log();
+
+            if cache.needs_redraw(DebugRegion::Panel, (panel, &interrupt_log)) {
+                draw_interrupt_log(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, &interrupt_log, 
+                    DEBUG_ZPAGE_STATE_X, DEBUG_ZPAGE_STATE_Y, palette);
+            }
+        }
+        DebugPanel::RomInfo => {
+            if cache.needs_redraw(DebugRegion::Panel, (panel, nes.rom_name(), nes.rom_hashes())) {
+                draw_rom_info(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
+                    DEBUG_ZPAGE_STATE_X, DEBUG_ZPAGE_STATE_Y, palette);
+            }
+        }
+        DebugPanel::Unsupported => {
+            let uses = nes.unsupported_uses();
+
+            if cache.needs_redraw(DebugRegion::Panel, (panel, &uses)) {
+                draw_unsupported_uses(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, &uses, 
+                    DEBUG_ZPAGE_STATE_X, DEBUG_ZPAGE_STATE_Y, palette);
+            }
+        }
+    }
+
+    let mirror = nes.current_mirror_type();
+    if cache.needs_redraw(DebugRegion::PpuInfo, (mirror.to_u8(), large_sprites, pgtbl_palette)) {
+        // Padded so a shorter name covers up a longer one from the last frame
+        let mirror_text = format!("{:<14}", text(Text::Mirror(mirror)));
+        let mirror_text = format!("{}\n", fill(Text::MirrorLabel, &[&mirror_text]));
+
+        let (new_x, new_y) = draw_string(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
+            &mirror_text, DEBUG_PGTBL1_VIEW_X - 4, DEBUG_PGTBL1_VIEW_Y + 128 + 9, 
+            palette.txt_col, palette.bg_col, 1);
+        
+        let spr_size_text = if large_sprites {
+            text(Text::LargeSprites)
+        } else {
+            text(Text::SmallSprites)
+        };
+        let spr_size_text = fill(Text::SpriteSizeLabel, &[&format!("{spr_size_text:<12}")]);
+
+        draw_string(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
+            &spr_size_text, new_x, new_y, 
+            palette.txt_col, palette.bg_col, 1);
+
+        let pgtbl_palette_text = match pgtbl_palette {
+            Some(pgtbl_palette) => pgtbl_palette.to_string(),
+            None => String::from(text(Text::Greyscale)),
+        };
+        let pgtbl_palette_text = fill(Text::PgtblPaletteLabel, &[&format!("{pgtbl_palette_text:<4}")]);
+
+        draw_string(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, 
+            &pgtbl_palette_text, DEBUG_PGTBL2_VIEW_X + 20, DEBUG_PGTBL1_VIEW_Y + 128 + 9, 
+            palette.txt_col, palette.bg_col, 1);
+    }
+
+    if cache.needs_redraw(DebugRegion::Fps, fps) {
+        let fps_str = format!("{} ", fill(Text::Fps, &[&fps.to_string()]));
+
+        draw_string(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, &fps_str, 
+            DEBUG_FPS_COUNTER_X, DEBUG_FPS_COUNTER_Y, palette.txt_col, palette.bg_col, 2);
+    }
+
+    let audio_key = (nes.audio_latency_ms() as usize, nes.audio_batch_size(), nes.audio_underruns());
+    if cache.needs_redraw(DebugRegion::AudioStats, audio_key) {
+        let latency_str = fill(Text::AudioStats, &[
+            &format!("{:>3}", audio_key.0),
+            &format!("{:<5}", audio_key.1),
+            &format!("{:<6}", audio_key.2),
+        ]);
+
+        draw_string(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, &latency_str, 
+            DEBUG_AUDIO_LATENCY_X, DEBUG_AUDIO_LATENCY_Y, palette.txt_col, palette.bg_col, 1);
+    }
+}
+
+/// Draws the NES picture at double size in the debug view, along with the
+/// ghost and any overlays on top of it
+fn draw_debug_screen(frame: &mut [u8], nes: &Nes, ghost: Option<&[u8]>, overlays: ScreenOverlays) {
+    if overlays.write_heatmap {
+        draw_write_heatmap(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
+                        DEBUG_NES_SCREEN_X, DEBUG_NES_SCREEN_Y);
+    } else {
+        draw_nes_screen(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes.screen_buf_slice(), 
+                        DEBUG_NES_SCREEN_X, DEBUG_NES_SCREEN_Y, true);
+    }
 
-    let fps_str = format!("FPS: {fps} ");
+    if let Some(ghost) = ghost {
+        draw_ghost(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, ghost, 
+                        DEBUG_NES_SCREEN_X, DEBUG_NES_SCREEN_Y, true);
+    }
 
-    draw_string(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, &fps_str, 
-        DEBUG_FPS_COUNTER_X, DEBUG_FPS_COUNTER_Y, palette.txt_col, palette.bg_col, 2);
+    if overlays.scroll_splits {
+        draw_scroll_splits(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
+                        DEBUG_NES_SCREEN_X, DEBUG_NES_SCREEN_Y, 2);
+    }
+    if overlays.write_markers {
+        draw_raster_write_markers(frame, DEBUG_FRAME_WIDTH, DEBUG_FRAME_HEIGHT, nes, 
+                        DEBUG_NES_SCREEN_X, DEBUG_NES_SCREEN_Y, 2);
+    }
 }
 
 
@@ -1119,142 +2167,243 @@ pub fn draw_game_view_bg(frame: &mut [u8], palette: DebugPalette) {
     // draw_box(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, 30, 30, 259, 243, 2, palette, None);
 }
 
-pub fn draw_game_view(frame: &mut [u8], nes: &mut Nes) {
+/// Draws the game's picture, with the ghost and developer overlays over it.
+/// The overlay layers go on after it's been filtered and turned (see
+/// orient_game_view).
+pub fn draw_game_view(frame: &mut [u8], nes: &mut Nes, ghost: Option<&[u8]>, crop: CropMode, overlays: ScreenOverlays) {
     draw_nes_screen(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, nes.screen_buf_slice(), 0, 0, false);
-}
 
+    if let Some(ghost) = ghost {
+        draw_ghost(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, ghost, 0, 0, false);
+    }
 
-pub fn draw_paused_menu_bg(frame: &mut [u8]) {
-    // No background. Will leave this here in case I want to add something later
+    if overlays.scroll_splits {
+        draw_scroll_splits(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, nes, 0, 0, 1);
+    }
+    if overlays.write_markers {
+        draw_raster_write_markers(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, nes, 0, 0, 1);
+    }
+
+    // Cropped areas are blacked out rather than resizing the frame, like a TV
+    // with the edges hidden behind the bezel
+    if crop.hides_left_column() {
+        for y in 0..GAME_FRAME_HEIGHT {
+            for x in 0..8 {
+                dot(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, x, y, 1, BLACK);
+            }
+        }
+    }
+    if crop.hides_overscan() {
+        for y in (0..8).chain(GAME_FRAME_HEIGHT - 8..GAME_FRAME_HEIGHT) {
+            for x in 0..GAME_FRAME_WIDTH {
+                dot(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, x, y, 1, BLACK);
+            }
+        }
+    }
 }
 
-fn draw_menu_options(frame: &mut [u8], menu: &PauseMenu, limit_fps: bool) {
-    let w = GAME_FRAME_WIDTH;
-    let h = GAME_FRAME_HEIGHT;
-    let x = 10;
-    let y = 10;
-    let chr_col = DEFAULT_DEBUG_PAL.txt_col;
-    let bg_col = DEFAULT_DEBUG_PAL.bg_col;
-    let s = 1;
+/// Copies the game's picture into the frame turned clockwise by the rotation,
+/// then flipped left to right if mirrored. The frame has to be the picture's
+/// size, with the width and height swapped if the rotation puts it on its side.
+pub fn orient_game_view(frame: &mut [u8], picture: &[u8], width: usize, height: usize, rotation: Rotation, mirror: bool) {
+    if rotation == Rotation::None && !mirror {
+        frame.copy_from_slice(picture);
+        return;
+    }
 
-    let (next_x, next_y) = draw_string(frame, w, h, "Emulator Paused\n\n ", 
-        x, y, chr_col, bg_col, s+1);
+    let out_width = if rotation.swaps_sides() { height } else { width };
 
-    let (next_x, next_y) = draw_string(frame, w, h, "Continue [ESC]\n", 
-        next_x, next_y, chr_col, bg_col, s);
+    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % out_width, i / out_width);
+        let x = if mirror { out_width - 1 - x } else { x };
 
-    let (next_x, next_y) = draw_string(frame, w, h, "Map Controller Inputs\n", 
-        next_x, next_y, chr_col, bg_col, s);
-    
-    let (next_x, next_y) = draw_string(frame, w, h, "Volume\n", 
-        next_x, next_y, chr_col, bg_col, s);
+        // Where the pixel came from, undoing the turn
+        let (src_x, src_y) = match rotation {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (y, height - 1 - x),
+            Rotation::Half => (width - 1 - x, height - 1 - y),
+            Rotation::ThreeQuarters => (width - 1 - y, x),
+        };
+
+        let src = (src_y * width + src_x) * 4;
+        pixel.copy_from_slice(&picture[src..src + 4]);
+    }
+}
 
-    let (next_x, next_y) = draw_string(frame, w, h, "NoLimit\n", 
-        next_x, next_y, chr_col, bg_col, s);
+/// Draws the performance HUD in the top left corner of the frame: the average
+/// time taken by each part of a frame, how full the audio queue is, the input
+/// lag estimate, the number of dropped frames and the audio underrun counters
+/// (see perf.rs)
+pub fn draw_perf_hud(frame: &mut [u8], frame_width: usize, frame_height: usize,
+                    palette: DebugPalette, hud: &PerfHud, nes: &Nes, audio_queue_depth: usize) {
+    let timings = hud.timings();
+    let millis = |micros: f64| format!("{:5.2}", micros / 1000.0);
+
+    let input_lag = match hud.input_latency() {
+        Some(latency) => format!("{:5.1}", latency.as_secs_f64() * 1000.0),
+        None => String::from("    -"),
+    };
 
-    draw_string(frame, w, h, "Quit\n", 
-        next_x, next_y, chr_col, bg_col, s);
+    let hud_text = fill(Text::PerfHud, &[
+        &millis(timings.emulate),
+        &millis(timings.draw),
+        &millis(timings.present),
+        &format!("{:>5}", nes.audio_samples_queued()),
+        &audio_queue_depth.to_string(),
+        &input_lag,
+        &hud.dropped_frames().to_string(),
+        &nes.audio_underruns().to_string(),
+        &nes.audio_missed_samples().to_string(),
+        &hud.catch_up_frames().to_string(),
+    ]);
+
+    draw_string(frame, frame_width, frame_height, &hud_text, 4, 4, palette.txt_col, palette.bg_col, 1);
+}
 
-    let select_str = match menu.selected {
-        PauseMenuItem::Continue =>      "`>`\n \n \n \n ",
-        PauseMenuItem::ControllerMap => " \n`>`\n \n \n ",
-        PauseMenuItem::Volume =>        " \n \n`>`\n \n ",
-        PauseMenuItem::NoLimit =>       " \n \n \n`>`\n ",
-        PauseMenuItem::Quit =>          " \n \n \n \n`>`",
+/// Overlay layer that draws a box over the middle of the game view telling the
+/// player the CPU hit a JAM opcode, since otherwise the game just freezes for
+/// no apparent reason
+pub fn draw_cpu_jammed(target: &mut OverlayTarget, nes: &Nes) {
+    let Some(jammed_at) = nes.cpu_jammed_at() else {
+        return;
     };
-    let (next_x, next_y) = draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, "\n\n", 10, 10, WHITE, DEFAULT_DEBUG_PAL.bg_col, 2);
-    draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, select_str, next_x, next_y, WHITE, DEFAULT_DEBUG_PAL.bg_col, 1);
-    
-    let (nolimit_str_x, nolimit_str_y) = draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, "` `", next_x, next_y, DEFAULT_DEBUG_PAL.txt_col, DEFAULT_DEBUG_PAL.bg_col, 2);
-    let (nolimit_str_x, nolimit_str_y) = draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, "\n\n\n`        `", nolimit_str_x, nolimit_str_y, DEFAULT_DEBUG_PAL.txt_col, DEFAULT_DEBUG_PAL.bg_col, 1);
-    let (nolimit_str_x, nolimit_str_y) = draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, "[", nolimit_str_x, nolimit_str_y, WHITE, DEFAULT_DEBUG_PAL.bg_col, 1);
-    let (nolimit_str_x, nolimit_str_y) = draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, "ON", nolimit_str_x, nolimit_str_y, if limit_fps { GREY } else { GREEN }, DEFAULT_DEBUG_PAL.bg_col, 1);
-    let (nolimit_str_x, nolimit_str_y) = draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, "/", nolimit_str_x, nolimit_str_y, WHITE, DEFAULT_DEBUG_PAL.bg_col, 1);
-    let (nolimit_str_x, nolimit_str_y) = draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, "OFF", nolimit_str_x, nolimit_str_y, if limit_fps { RED } else { GREY }, DEFAULT_DEBUG_PAL.bg_col, 1);
-    draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, "]", nolimit_str_x, nolimit_str_y, WHITE, DEFAULT_DEBUG_PAL.bg_col, 1);
+
+    let (frame, w, h, palette) = (&mut *target.frame, target.width, target.height, target.palette);
+
+    let (box_x, box_y, box_w, box_h) = (40, 96, 176, 48);
+
+    for y in box_y..box_y + box_h {
+        for x in box_x..box_x + box_w {
+            dot(frame, w, h, x, y, 1, palette.bg_col);
+        }
+    }
+    draw_box(frame, w, h, box_x, box_y, box_w, box_h, 2, palette, None);
+
+    draw_string(frame, w, h, &fill(Text::CpuJammed, &[&format!("{jammed_at:04X}")]),
+        box_x + 12, box_y + 12, palette.err_col, palette.bg_col, 1);
 }
 
-fn draw_volume_menu(frame: &mut [u8], menu: &PauseMenu) {
-    let w = GAME_FRAME_WIDTH;
-    let h = GAME_FRAME_HEIGHT;
-    let x = 10;
-    let y = 10;
-    let chr_col = DEFAULT_DEBUG_PAL.txt_col;
-    let bg_col = DEFAULT_DEBUG_PAL.bg_col;
-    let s = 1;
+/// Draws the box put up over the middle of the game view after the emulator
+/// crashes (see crash)
+pub fn draw_crash_notice(target: &mut OverlayTarget, text: &str) {
+    let (frame, w, h, palette) = (&mut *target.frame, target.width, target.height, target.palette);
 
-    let (next_x, next_y) = draw_string(frame, w, h, "Setting Volume\n\n ", 
-        x, y, chr_col, bg_col, s+1);
+    let (box_x, box_y, box_w, box_h) = (36, 75, 184, 90);
 
-    let volume_text = format!("Volume: {}%", (menu.volume_percent * 100.0) as usize);
+    for y in box_y..box_y + box_h {
+        for x in box_x..box_x + box_w {
+            dot(frame, w, h, x, y, 1, palette.bg_col);
+        }
+    }
+    draw_box(frame, w, h, box_x, box_y, box_w, box_h, 2, palette, None);
 
-    draw_string(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, &volume_text, 
-        next_x, next_y, chr_col, bg_col, s);
-    
-        menu.slider_sprite.draw(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, MENU_VOLUME_SLIDER_X, MENU_VOLUME_SLIDER_Y, menu.volume_percent);
+    draw_string(frame, w, h, text, box_x + 12, box_y + 12, palette.err_col, palette.bg_col, 1);
+}
+
+/// Draws an on screen message in the bottom left corner of the game view.
+/// Messages with more than one line grow upwards.
+pub fn draw_osd_message(target: &mut OverlayTarget, text: &str) {
+    let (frame, w, h, palette) = (&mut *target.frame, target.width, target.height, target.palette);
+
+    let extra_lines = text.lines().count().saturating_sub(1);
+    let y = h.saturating_sub(16 + extra_lines * (chars::CHAR_HEIGHT + chars::NEWLINE_PADDING));
+
+    draw_string(frame, w, h, text, 8, y, palette.txt_col, palette.bg_col, 1);
 }
 
-fn draw_controller_mapping_menu(frame: &mut [u8], menu: &PauseMenu) {
-    let w = GAME_FRAME_WIDTH;
-    let h = GAME_FRAME_HEIGHT;
-    let x = 10;
-    let y = 10;
-    let chr_col = DEFAULT_DEBUG_PAL.txt_col;
-    let bg_col = DEFAULT_DEBUG_PAL.bg_col;
+pub fn draw_paused_menu_bg(frame: &mut [u8]) {
+    // No background. Will leave this here in case I want to add something later
+}
+
+/// Draws a page of the menu: the title, then a row for each widget with the
+/// cursor next to the focused one, then the hint. Values (toggles, sliders,
+/// bindings) line up in a column after the longest label that has one.
+/// Returns the position under the last line drawn.
+pub fn draw_widget_list(frame: &mut [u8], frame_width: usize, frame_height: usize,
+                        palette: DebugPalette, list: &WidgetList, x: usize, y: usize) -> (usize, usize) {
+    let w = frame_width;
+    let h = frame_height;
+    let chr_col = palette.txt_col;
+    let bg_col = palette.bg_col;
+    let highlight_col = palette.highlight_col;
     let s = 1;
 
-    menu.controller_sprite.draw(frame, w, h, MENU_CONTROLLER_X, MENU_CONTROLLER_Y, menu.controller_state);
-    
-    if menu.map_controller1 || menu.map_controller2 {
-        if menu.map_controller1 {
-            draw_string(frame, w, h, "Player 1",
-                x, y, chr_col, bg_col, 2);
-        } else {
-            draw_string(frame, w, h, "Player 2",
-                x, y, chr_col, bg_col, 2);
+    let (_, mut line_y) = draw_string(frame, w, h, &format!("{}\n\n", list.title), 
+        x, y, chr_col, bg_col, s+1);
+
+    let value_column = list.widgets().iter()
+        .filter(|widget| widget.has_value())
+        .map(|widget| widget.label.chars().count() + 1)
+        .max()
+        .unwrap_or(0);
+
+    for (i, widget) in list.widgets().iter().enumerate() {
+        let focused = i == list.focus();
+
+        if focused {
+            draw_string(frame, w, h, "`>`", x, line_y, highlight_col, bg_col, s);
         }
 
-        let button_str = match menu.controller_read.button() {
-            ControllerButton::A =>      "A Button     ",
-            ControllerButton::B =>      "B Button     ",
-            ControllerButton::Up =>     "Up Arrow     ",
-            ControllerButton::Down =>   "Down Arrow   ",
-            ControllerButton::Left =>   "Left Arrow   ",
-            ControllerButton::Right =>  "Right Arrow  ",
-            ControllerButton::Select => "Select Button",
-            ControllerButton::Start =>  "Start Button ",
-        };
-        let button_str = format!("Press the {}", button_str);
+        let label = format!("`  `{:<value_column$}", widget.label);
+        let (value_x, value_y) = draw_string(frame, w, h, &label, x, line_y, chr_col, bg_col, s);
 
-        draw_string(frame, w, h, &button_str, 
-            MENU_CONTROLLER_X + 20, MENU_CONTROLLER_Y - 16, chr_col, bg_col, 1);
+        match &widget.kind {
+            WidgetKind::Button => {}
 
-    } else {
-        let(next_x, next_y) = draw_string(frame, w, h, "Controller Mappings\n\n ",
-            x, y, chr_col, bg_col, 2);
-        
-        if menu.player1_map_selected {
-            draw_string(frame, w, h,
-                "`> `", next_x, next_y, WHITE, bg_col, 1);
-        } else {
-            draw_string(frame, w, h,
-                "\n`> `", next_x, next_y, WHITE, bg_col, 1);
+            WidgetKind::Toggle(on) => {
+                let (status_x, status_y) = draw_string(frame, w, h, "[", value_x, value_y, highlight_col, bg_col, s);
+                let (status_x, status_y) = draw_string(frame, w, h, text(Text::On), status_x, status_y, if *on { palette.ok_col } else { palette.dim_col }, bg_col, s);
+                let (status_x, status_y) = draw_string(frame, w, h, "/", status_x, status_y, highlight_col, bg_col, s);
+                let (status_x, status_y) = draw_string(frame, w, h, text(Text::Off), status_x, status_y, if *on { palette.dim_col } else { palette.err_col }, bg_col, s);
+                draw_string(frame, w, h, "]", status_x, status_y, highlight_col, bg_col, s);
+            }
+
+            WidgetKind::Slider { value, suffix, .. } => {
+                draw_string(frame, w, h, &format!("<{value:>3}{suffix}>"), value_x, value_y, chr_col, bg_col, s);
+            }
+
+            WidgetKind::Choice { options, selected } => {
+                draw_string(frame, w, h, &format!("<{}>", options[*selected]), value_x, value_y, chr_col, bg_col, s);
+            }
+
+            WidgetKind::KeyCapture(binding) => {
+                let binding_str = match binding {
+                    _ if focused && list.capturing() => text(Text::WaitingForBinding),
+                    Some(binding) => binding.as_str(),
+                    None => text(Text::Unbound),
+                };
+                draw_string(frame, w, h, binding_str, value_x, value_y, 
+                    if focused && list.capturing() { highlight_col } else { chr_col }, bg_col, s);
+            }
         }
 
-        draw_string(frame, w, h, "`  `Player 1\n`  `Player 2", 
-            next_x, next_y, chr_col, bg_col, 1);
-        
+        line_y += s * chars::CHAR_HEIGHT + chars::NEWLINE_PADDING;
+    }
+
+    if let Some(hint) = &list.hint {
+        let (_, hint_y) = draw_string(frame, w, h, &format!("\n{hint}"), x, line_y, chr_col, bg_col, s);
+        line_y = hint_y + s * chars::CHAR_HEIGHT + chars::NEWLINE_PADDING;
     }
+
+    (x, line_y)
 }
 
-pub fn draw_menu(frame: &mut [u8], menu: &PauseMenu, limit_fps: bool) {
+pub fn draw_menu(frame: &mut [u8], palette: DebugPalette, menu: &PauseMenu) {
     frame.fill(0);
 
-    if menu.mapping_controller {
-        draw_controller_mapping_menu(frame, menu);
-    } else if menu.setting_volume {
-        draw_volume_menu(frame, menu);
-    } else {
-        draw_menu_options(frame, menu, limit_fps);
+    draw_widget_list(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, palette, menu.page(), 10, 10);
+
+    match menu.screen {
+        MenuScreen::Volume => {
+            menu.slider_sprite.draw(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, 
+                MENU_VOLUME_SLIDER_X, MENU_VOLUME_SLIDER_Y, menu.volume_percent);
+        }
+
+        MenuScreen::ControllerSelect | MenuScreen::ControllerMapping => {
+            menu.controller_sprite.draw(frame, GAME_FRAME_WIDTH, GAME_FRAME_HEIGHT, 
+                MENU_CONTROLLER_X, MENU_CONTROLLER_Y, menu.controller_state);
+        }
+
+        MenuScreen::Main | MenuScreen::AudioChannels | MenuScreen::CartInfo | MenuScreen::VideoFilters | MenuScreen::FilterSettings => {}
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,109 @@
+use crate::system::movie::Movie;
+use crate::system::nes::Nes;
+use crate::RuntimeConfig;
+
+/*
+ON HOW GHOST RUNS WORK:
+
+A ghost is a past attempt at a game raced against the current one. It's a
+second Nes with the same game in it, fed the input from a recorded movie
+instead of a controller, and drawn see-through on top of the real picture.
+
+Movies start from power on (or from the save state they were recorded from),
+so the ghost is powered on with the same settings as the real NES (RAM fill,
+PPU warm-up, DMC glitch), started where the movie starts, and kept on the same
+frame number as it: whenever the real NES has finished more frames than the
+ghost, the ghost runs until it catches up. This keeps them in lockstep however the
+real NES got there (playing, frame stepping, the debug stepping keys...).
+Resetting the game starts the ghost over, since the run it's racing does too.
+
+The ghost's audio is thrown away, and it disappears once the movie runs out.
+*/
+
+// Most frames the ghost runs to catch up in one go, so a long stretch of
+// stepping in the debugger doesn't hang the app
+const MAX_CATCH_UP_FRAMES: u64 = 10;
+
+pub struct Ghost {
+    pub shown: bool,
+
+    nes: Nes,
+    movie: Movie,
+    cart_path: String,
+    patch_path: Option<String>,
+
+    // The real NES's frame count when the ghost was powered on, so the two
+    // can be lined up
+    start_frame: u64,
+}
+
+impl Ghost {
+    /// Loads the movie and powers on a second NES to play it back on
+    pub fn new(config: &RuntimeConfig, movie_path: &str) -> Result<Self, String> {
+        let movie = Movie::load(movie_path)?;
+
+        let mut nes = Nes::default();
+        nes.set_block_audio_samples(true);
+        nes.set_ram_fill(config.ram_fill);
+        nes.set_ppu_warmup(config.ppu_warmup);
+        nes.set_dmc_input_glitch(config.dmc_input_glitch);
+        nes.set_sprite_evaluation(config.sprite_evaluation);
+        nes.set_cpu_mode(config.cpu_mode);
+        nes.set_color_filter(config.color_filter);
+        nes.load_cart_patched(&config.cart_path, config.patch_path.as_deref())?;
+        movie.start(&mut nes)?;
+
+        println!("Racing a {} frame ghost from {movie_path}", movie.len());
+
+        Ok(Self {
+            shown: true,
+            nes,
+            movie,
+            cart_path: config.cart_path.clone(),
+            patch_path: config.patch_path.clone(),
+            start_frame: 0,
+        })
+    }
+
+    /// Powers the ghost back on, to line up with the real NES being reset
+    /// after it had run the given number of frames
+    pub fn restart(&mut self, real_frames: u64) {
+        let restarted = self.nes.load_cart_patched(&self.cart_path, self.patch_path.as_deref())
+            .and_then(|()| self.movie.start(&mut self.nes));
+        if let Err(e) = restarted {
+            println!("Couldn't restart the ghost: {e}");
+        }
+        self.start_frame = real_frames;
+    }
+
+    /// Runs the ghost until it's on the same frame as the real NES
+    pub fn catch_up(&mut self, real_frames: u64) {
+        let target = real_frames.saturating_sub(self.start_frame);
+        let behind = target.saturating_sub(self.nes.frames_run());
+
+        for _ in 0..behind.min(MAX_CATCH_UP_FRAMES) {
+            if self.finished() {
+                return;
+            }
+
+            let inputs = self.movie.frame(self.nes.frames_run() as usize).unwrap_or_default();
+
+            self.nes.advance_frame(inputs);
+            self.nes.swap_screen_buffers();
+        }
+    }
+
+    /// Whether the movie has run out
+    pub fn finished(&self) -> bool {
+        self.nes.frames_run() as usize >= self.movie.len()
+    }
+
+    /// The ghost's picture, if it should be drawn
+    pub fn screen(&self) -> Option<&[u8]> {
+        if self.shown && !self.finished() {
+            Some(self.nes.screen_buf_slice())
+        } else {
+            None
+        }
+    }
+}
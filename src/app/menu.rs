@@ -0,0 +1,177 @@
+use crate::system::controller::ControllerButton;
+
+use ControllerButton as Button;
+
+/*
+ON HOW THE PAUSE MENU IS DRIVEN:
+
+Every input to the pause menu is turned into an NES controller button first,
+whether it came from the keyboard (using the same keys that play the game) or
+from a gamepad (through that player's button mapping). So anything that can
+play the game can also drive every screen of the menu, and remapping a gamepad
+remaps its menu buttons too.
+
+Each screen of the menu then has a table of what each button does on it. A
+button that isn't in a screen's table isn't used by the menu, so the keyboard
+hotkeys still get a chance at it. Adding a new settings page means adding a
+screen, its table, and the code for any new actions.
+*/
+
+/// The screens of the pause menu
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuScreen {
+    // The list of menu items
+    Main,
+    // Choosing which player's controller to map
+    ControllerSelect,
+    // Waiting for gamepad buttons to be pressed for each NES button
+    ControllerMapping,
+    // Moving the volume slider
+    Volume,
+    // Muting/soloing the audio channels
+    AudioChannels,
+    // What the cartridge's header says, and the ROM's hashes
+    CartInfo,
+    // Turning the video filters on and off
+    VideoFilters,
+    // The sliders for one video filter's settings
+    FilterSettings,
+}
+
+/// Something the pause menu can do in response to a button
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuAction {
+    // Moves the cursor up/down on screens with a list, optionally wrapping
+    // around at the ends
+    CursorPrev,
+    CursorNext,
+    CursorNextWrap,
+    // Left/right on a setting that's a value (speed, volume)
+    Decrease,
+    Increase,
+    // Activates whatever the cursor is on
+    Confirm,
+    // Select's own use on a page (soloing an audio channel, opening a video
+    // filter's settings)
+    Alternate,
+    // Leaves the current screen (the main screen leaves the menu)
+    Back,
+    // The button does nothing here, and gets the reject sound
+    Reject,
+}
+
+const MAIN_BINDINGS: &[(Button, MenuAction)] = &[
+    (Button::A, MenuAction::Confirm),
+    (Button::Start, MenuAction::Confirm),
+    (Button::B, MenuAction::Back),
+    (Button::Up, MenuAction::CursorPrev),
+    (Button::Down, MenuAction::CursorNext),
+    (Button::Select, MenuAction::CursorNextWrap),
+    (Button::Left, MenuAction::Decrease),
+    (Button::Right, MenuAction::Increase),
+];
+
+// The players to map, then the sliders for each player's stick settings
+const CONTROLLER_SELECT_BINDINGS: &[(Button, MenuAction)] = &[
+    (Button::A, MenuAction::Confirm),
+    (Button::Start, MenuAction::Confirm),
+    (Button::B, MenuAction::Back),
+    (Button::Up, MenuAction::CursorPrev),
+    (Button::Down, MenuAction::CursorNext),
+    (Button::Select, MenuAction::CursorNextWrap),
+    (Button::Left, MenuAction::Decrease),
+    (Button::Right, MenuAction::Increase),
+];
+
+// Every gamepad button goes to the mapping on this screen, so only the
+// keyboard's B (or escape) reaches this table
+const CONTROLLER_MAPPING_BINDINGS: &[(Button, MenuAction)] = &[
+    (Button::B, MenuAction::Back),
+];
+
+const VOLUME_BINDINGS: &[(Button, MenuAction)] = &[
+    (Button::A, MenuAction::Confirm),
+    (Button::Start, MenuAction::Confirm),
+    (Button::B, MenuAction::Back),
+    (Button::Up, MenuAction::Reject),
+    (Button::Down, MenuAction::Reject),
+    (Button::Select, MenuAction::Reject),
+    (Button::Left, MenuAction::Decrease),
+    (Button::Right, MenuAction::Increase),
+];
+
+const AUDIO_CHANNELS_BINDINGS: &[(Button, MenuAction)] = &[
+    (Button::A, MenuAction::Confirm),
+    (Button::Start, MenuAction::Confirm),
+    (Button::Select, MenuAction::Alternate),
+    (Button::B, MenuAction::Back),
+    (Button::Up, MenuAction::CursorPrev),
+    (Button::Down, MenuAction::CursorNext),
+    (Button::Left, MenuAction::Reject),
+    (Button::Right, MenuAction::Reject),
+];
+
+// The page is all text and a back button
+const CART_INFO_BINDINGS: &[(Button, MenuAction)] = &[
+    (Button::A, MenuAction::Confirm),
+    (Button::Start, MenuAction::Confirm),
+    (Button::B, MenuAction::Back),
+    (Button::Up, MenuAction::Reject),
+    (Button::Down, MenuAction::Reject),
+    (Button::Select, MenuAction::Reject),
+    (Button::Left, MenuAction::Reject),
+    (Button::Right, MenuAction::Reject),
+];
+
+// The filter toggles, then the choice of GPU shader
+const VIDEO_FILTERS_BINDINGS: &[(Button, MenuAction)] = &[
+    (Button::A, MenuAction::Confirm),
+    (Button::Start, MenuAction::Confirm),
+    (Button::Select, MenuAction::Alternate),
+    (Button::B, MenuAction::Back),
+    (Button::Up, MenuAction::CursorPrev),
+    (Button::Down, MenuAction::CursorNext),
+    (Button::Left, MenuAction::Decrease),
+    (Button::Right, MenuAction::Increase),
+];
+
+const FILTER_SETTINGS_BINDINGS: &[(Button, MenuAction)] = &[
+    (Button::A, MenuAction::Confirm),
+    (Button::Start, MenuAction::Confirm),
+    (Button::B, MenuAction::Back),
+    (Button::Up, MenuAction::CursorPrev),
+    (Button::Down, MenuAction::CursorNext),
+    (Button::Select, MenuAction::CursorNextWrap),
+    (Button::Left, MenuAction::Decrease),
+    (Button::Right, MenuAction::Increase),
+];
+
+impl MenuScreen {
+    /// What each button does on this screen
+    pub fn bindings(&self) -> &'static [(ControllerButton, MenuAction)] {
+        match self {
+            Self::Main => MAIN_BINDINGS,
+            Self::ControllerSelect => CONTROLLER_SELECT_BINDINGS,
+            Self::ControllerMapping => CONTROLLER_MAPPING_BINDINGS,
+            Self::Volume => VOLUME_BINDINGS,
+            Self::AudioChannels => AUDIO_CHANNELS_BINDINGS,
+            Self::CartInfo => CART_INFO_BINDINGS,
+            Self::VideoFilters => VIDEO_FILTERS_BINDINGS,
+            Self::FilterSettings => FILTER_SETTINGS_BINDINGS,
+        }
+    }
+
+    /// What the button does on this screen, if the screen uses it
+    pub fn action(&self, button: ControllerButton) -> Option<MenuAction> {
+        self.bindings()
+            .iter()
+            .find(|(bound, _)| *bound == button)
+            .map(|(_, action)| *action)
+    }
+
+    /// Whether holding a key down repeats its action. Only the volume slider
+    /// does this, everywhere else a held key would just fly past the items.
+    pub fn repeats(&self) -> bool {
+        *self == Self::Volume
+    }
+}
@@ -1,5 +1,27 @@
 mod app;
+mod assets;
+mod battery;
+pub(crate) mod capture;
+mod config;
+pub(crate) mod crash;
+mod debug_cache;
 mod draw;
+mod ghost;
+mod menu;
+mod overlay;
+mod perf;
+mod presenter;
+mod rom_hashes;
+mod save_slots;
+mod shader;
+pub(crate) mod shutdown;
+mod text;
+mod thumbnails;
+mod title;
+mod widgets;
 mod util;
 
-pub use app::NesApp;
\ No newline at end of file
+pub use app::NesApp;
+pub use presenter::{Presenter, TexturePresenter, WindowPresenter};
+// The wgpu the presenters use, so hosts can make matching devices and textures
+pub use pixels::wgpu;
\ No newline at end of file
@@ -0,0 +1,95 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::system::nes::Nes;
+
+use super::draw::{self, DebugPalette};
+
+// How long an on screen message stays up
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+/*
+ON HOW OVERLAYS ARE COMPOSED:
+
+Anything drawn over the game in the normal view (on screen messages, input
+displays, timers, script drawings...) is an overlay layer: a callback given to
+OverlayLayers::add. After the game view is drawn (the NES picture, the ghost,
+the developer overlays and cropping), filtered, and turned to the way the
+monitor's set up, compose runs every layer in the order they were added, so
+later layers end up on top and always the right way up. The frame can be
+bigger than the NES picture (when a filter scales it up) or on its side, so
+layers go by the target's width and height. Each one gets the frame being
+presented and the NES, and draws whatever it wants with the functions in draw.
+
+This way a new overlay doesn't need its own spot in draw_game_view, just a
+call to add when the app starts up.
+*/
+
+/// The frame an overlay layer draws onto, with the UI colors to draw with
+pub struct OverlayTarget<'a> {
+    pub frame: &'a mut [u8],
+    pub width: usize,
+    pub height: usize,
+    pub palette: DebugPalette,
+}
+
+pub type OverlayCallback = Box<dyn FnMut(&mut OverlayTarget, &Nes)>;
+
+/// The overlay layers drawn over the game view (see "ON HOW OVERLAYS ARE
+/// COMPOSED")
+#[derive(Default)]
+pub struct OverlayLayers {
+    layers: Vec<OverlayCallback>,
+}
+
+impl OverlayLayers {
+    /// Adds a layer on top of the ones already added
+    pub fn add(&mut self, layer: OverlayCallback) {
+        self.layers.push(layer);
+    }
+
+    /// Draws every layer onto the frame, bottom to top
+    pub fn compose(&mut self, target: &mut OverlayTarget, nes: &Nes) {
+        for layer in self.layers.iter_mut() {
+            layer(target, nes);
+        }
+    }
+}
+
+/// A short message shown over the game for a couple of seconds, like "Saved
+/// to slot 3". Cloning it gives another handle to the same message, so the
+/// app can show messages through one while the overlay layer draws them.
+#[derive(Clone, Default)]
+pub struct OsdMessage {
+    // The message, when it was shown and how long it stays up
+    current: Rc<RefCell<Option<(String, Instant, Duration)>>>,
+}
+
+impl OsdMessage {
+    /// Shows a message, replacing the one up now
+    pub fn show(&self, text: &str) {
+        self.show_for(text, MESSAGE_DURATION);
+    }
+
+    /// Shows a message for longer (or shorter) than usual, for ones that take
+    /// a while to read
+    pub fn show_for(&self, text: &str, duration: Duration) {
+        *self.current.borrow_mut() = Some((String::from(text), Instant::now(), duration));
+    }
+
+    /// The overlay layer that draws the message while it's up
+    pub fn layer(&self) -> OverlayCallback {
+        let current = self.current.clone();
+
+        Box::new(move |target, _nes| {
+            let mut current = current.borrow_mut();
+
+            match current.as_ref() {
+                Some((text, shown_at, duration)) if shown_at.elapsed() < *duration => draw::draw_osd_message(target, text),
+                Some(_) => *current = None,
+                None => {}
+            }
+        })
+    }
+}
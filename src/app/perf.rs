@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+/*
+ON HOW THE PERFORMANCE HUD MEASURES THINGS:
+
+Each frame the app does three things, and each gets timed: running the NES
+for a frame (emulate), drawing the screen into the pixel buffer (draw), and
+handing the pixel buffer to the GPU (present). The times shown are averaged
+over the last few frames so they're readable.
+
+Input lag is estimated from when a key or button event reaches the app to
+when a frame that saw it is shown. The emulator can't tell which button press
+a game reacted to, so "saw it" means the game latched the controllers (see
+Nes::controller_polls) during a frame run after the event. Since the app draws
+the last frame before running the next one, that frame only shows up on the
+redraw after. This leaves out the time the OS and the display take, so the
+real lag is a bit more.
+
+A frame is dropped when the app falls far enough behind that a frame's whole
+time slot goes by without it being shown, either because redraws came late or
+because audio sync ran extra frames to catch up.
+
+The audio lines show how often the sound card ran out of samples (underruns),
+how many samples had to be made up to cover the gaps, and how many extra frames
+the app ran to refill the queue after one (see "ON AUDIO UNDERRUNS" in
+apu_util.rs).
+*/
+
+// How much each new frame time counts towards the average
+const SMOOTHING: f64 = 0.1;
+
+/// Running averages of how long each part of a frame takes, in microseconds
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimings {
+    pub emulate: f64,
+    pub draw: f64,
+    pub present: f64,
+}
+
+#[derive(Default)]
+pub struct PerfHud {
+    pub shown: bool,
+
+    timings: FrameTimings,
+    dropped_frames: u64,
+    catch_up_frames: u64,
+
+    // When the oldest input not yet seen by the game arrived
+    pending_input: Option<Instant>,
+    // When the input seen by the last frame run arrived. The frame is shown
+    // on the next present.
+    input_in_frame: Option<Instant>,
+    input_latency: Option<Duration>,
+}
+
+impl PerfHud {
+    pub fn timings(&self) -> FrameTimings {
+        self.timings
+    }
+
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames
+    }
+
+    /// Number of extra frames run to refill the audio queue after underruns
+    pub fn catch_up_frames(&self) -> u64 {
+        self.catch_up_frames
+    }
+
+    /// Call after running an extra frame because the audio queue ran dry
+    pub fn frame_caught_up(&mut self) {
+        self.catch_up_frames += 1;
+    }
+
+    /// The latest estimate of input lag, once there's been some input
+    pub fn input_latency(&self) -> Option<Duration> {
+        self.input_latency
+    }
+
+    /// Call when a key or button event arrives for the game
+    pub fn input_arrived(&mut self) {
+        self.pending_input.get_or_insert_with(Instant::now);
+    }
+
+    /// Call after running a frame, with how long it took and whether the game
+    /// latched the controllers during it
+    pub fn frame_emulated(&mut self, time: Duration, polled_input: bool) {
+        average(&mut self.timings.emulate, time);
+
+        if polled_input {
+            if let Some(arrived) = self.pending_input.take() {
+                self.input_in_frame = Some(arrived);
+            }
+        }
+    }
+
+    pub fn frame_drawn(&mut self, time: Duration) {
+        average(&mut self.timings.draw, time);
+    }
+
+    /// Call after presenting a frame, with how long it took
+    pub fn frame_presented(&mut self, time: Duration) {
+        average(&mut self.timings.present, time);
+
+        if let Some(arrived) = self.input_in_frame.take() {
+            self.input_latency = Some(arrived.elapsed());
+        }
+    }
+
+    /// Counts the frames that went by unseen. Takes how long it's been since
+    /// the last frame was shown, how long a frame should take, and how many
+    /// frames were run for this one redraw.
+    pub fn count_dropped(&mut self, micros_since_frame: u128, micros_per_frame: u128, frames_run: usize) {
+        let late_frames = (micros_since_frame / micros_per_frame.max(1)).saturating_sub(1) as u64;
+        let unshown_frames = frames_run.saturating_sub(1) as u64;
+
+        self.dropped_frames += late_frames.max(unshown_frames);
+    }
+}
+
+fn average(average: &mut f64, time: Duration) {
+    let micros = time.as_secs_f64() * 1_000_000.0;
+
+    if *average == 0.0 {
+        *average = micros;
+    } else {
+        *average += (micros - *average) * SMOOTHING;
+    }
+}
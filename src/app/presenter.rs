@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use pixels::wgpu;
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
+use winit::window::Window;
+
+use super::shader::{GpuShader, ShaderRenderer};
+
+/*
+ON HOW FRAMES ARE PRESENTED:
+
+Every view the app shows (the game, the debugger, the pause menu) is drawn on
+the CPU into an RGBA buffer, and a Presenter takes it from there. Drawing into
+frame_mut and then calling present is all the app does with it, so what
+happens to the picture afterwards is up to the presenter:
+
+    WindowPresenter   pixels drawing the buffer to a window, scaled to fit,
+                      through one of the GPU shaders when one's picked (see
+                      shader.rs). It's what the app uses unless it's given
+                      something else.
+    TexturePresenter  copies the buffer into a wgpu texture on someone else's
+                      device, for programs embedding the emulator in their own
+                      wgpu app. They draw the texture however they like, and
+                      do their own post-processing (so GPU shaders picked in
+                      the app fall back to the CPU filters).
+
+A presenter of your own can be given to NesApp::set_presenter before the app
+starts. The texture presenter can also be used without the app at all, with
+Nes::frame's pixels copied into frame_mut.
+
+The wgpu types come from pixels' copy of wgpu, which is re-exported so the
+texture is the same type as the host's.
+*/
+
+/// Where the app's finished frames go (see "ON HOW FRAMES ARE PRESENTED")
+pub trait Presenter {
+    /// The RGBA buffer the next frame is drawn into, at the size last given
+    /// to resize_buffer
+    fn frame_mut(&mut self) -> &mut [u8];
+    /// Changes the size of the buffer, in pixels
+    fn resize_buffer(&mut self, width: u32, height: u32) -> Result<(), String>;
+    /// Called when whatever the buffer is shown on changes size. Presenters
+    /// that don't draw to a window don't need to do anything.
+    fn resize_surface(&mut self, _width: u32, _height: u32) -> Result<(), String> {
+        Ok(())
+    }
+    /// Whether the presenter can draw through GPU shaders right now
+    fn supports_shaders(&self) -> bool {
+        false
+    }
+    /// Shows the buffer, through the given GPU shader if the presenter
+    /// supports them. A presenter that stops being able to use the shaders
+    /// gives an error, and says it doesn't support them from then on.
+    fn present(&mut self, shader: GpuShader) -> Result<(), String>;
+}
+
+/// Draws frames to a window through pixels (see "ON HOW FRAMES ARE
+/// PRESENTED"). It has to be dropped before the window it draws to.
+pub struct WindowPresenter {
+    pixels: Pixels,
+    // None if the GPU couldn't make the shaders, or they weren't wanted
+    shader_renderer: Option<ShaderRenderer>,
+}
+
+impl WindowPresenter {
+    /// Makes a presenter drawing a buffer of the given size to the window,
+    /// with the clear color around it. With shaders, the GPU shaders are made
+    /// too, if the GPU can make them.
+    pub fn new(window: &Window, width: u32, height: u32, clear_color: wgpu::Color, shaders: bool) -> Result<Self, String> {
+        let size = window.inner_size();
+        let surface = SurfaceTexture::new(size.width, size.height, window);
+
+        let pixels = PixelsBuilder::new(width, height, surface)
+            .enable_vsync(false)
+            .clear_color(clear_color)
+            .build()
+            .map_err(|e| format!("Couldn't draw to the window: {e}"))?;
+
+        let shader_renderer = match shaders {
+            true => ShaderRenderer::new(&pixels, clear_color)
+                .map_err(|e| println!("Couldn't make the GPU shaders: {e}"))
+                .ok(),
+            false => None,
+        };
+
+        Ok(Self { pixels, shader_renderer })
+    }
+}
+
+impl Presenter for WindowPresenter {
+    fn frame_mut(&mut self) -> &mut [u8] {
+        self.pixels.frame_mut()
+    }
+
+    fn resize_buffer(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.pixels.resize_buffer(width, height).map_err(|e| e.to_string())
+    }
+
+    fn resize_surface(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.pixels.resize_surface(width, height).map_err(|e| e.to_string())
+    }
+
+    fn supports_shaders(&self) -> bool {
+        self.shader_renderer.is_some()
+    }
+
+    fn present(&mut self, shader: GpuShader) -> Result<(), String> {
+        match &self.shader_renderer {
+            Some(renderer) if shader != GpuShader::Off => {
+                let drawn = renderer.render(&self.pixels, shader);
+
+                if let Err(e) = drawn {
+                    self.shader_renderer = None;
+                    return Err(format!("Couldn't draw with the GPU shader: {e}"));
+                }
+                Ok(())
+            }
+            _ => self.pixels.render().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Copies frames into a wgpu texture on the host's device, for embedding the
+/// emulator in another wgpu app (see "ON HOW FRAMES ARE PRESENTED")
+pub struct TexturePresenter {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    buffer: Vec<u8>,
+    width: u32,
+    height: u32,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl TexturePresenter {
+    // The same format pixels draws from, so colors come out the same
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Makes a presenter with a texture of the given size on the device
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, width: u32, height: u32) -> Self {
+        let (texture, view) = Self::make_texture(&device, width, height);
+
+        Self {
+            device,
+            queue,
+            buffer: vec![0; width as usize * height as usize * 4],
+            width,
+            height,
+            texture,
+            view,
+        }
+    }
+
+    /// The texture the last presented frame is in. It's made again when the
+    /// buffer changes size, so don't hold on to it (or its view) between
+    /// frames.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// The texture's width and height, in pixels
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn make_texture(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("nes_frame_texture"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        (texture, view)
+    }
+}
+
+impl Presenter for TexturePresenter {
+    fn frame_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+
+    fn resize_buffer(&mut self, width: u32, height: u32) -> Result<(), String> {
+        if (width, height) == (self.width, self.height) {
+            return Ok(());
+        }
+
+        (self.texture, self.view) = Self::make_texture(&self.device, width, height);
+        self.buffer = vec![0; width as usize * height as usize * 4];
+        self.width = width;
+        self.height = height;
+
+        Ok(())
+    }
+
+    fn present(&mut self, _shader: GpuShader) -> Result<(), String> {
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.buffer,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * 4),
+                rows_per_image: Some(self.height),
+            },
+            wgpu::Extent3d { width: self.width, height: self.height, depth_or_array_layers: 1 },
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::thread::{self, JoinHandle};
+
+use serde_json::{json, Map, Value};
+
+use crate::cartridge::{hash::RomHashes, patch};
+
+/// Where ROM hashes are cached, next to the config file
+pub const ROM_HASH_CACHE_PATH: &str = "rom_hashes.json";
+
+/*
+ON HOW ROMS ARE HASHED:
+
+The CRC-32 of a ROM is cheap, so the NES works it out itself when a cart is
+loaded, and it's what per-game settings and thumbnails are filed under. The
+rest of the hashes shown on the ROM info panel (SHA-1s, and the PRG and CHR
+ROM hashed on their own) take a lot longer on big ROMs, so they're done on a
+background thread after the game has started, and handed to the NES once
+they're ready.
+
+Since a ROM's hashes never change, they're saved to a cache file keyed by the
+ROM's CRC-32, and a game that's been loaded before gets them straight away:
+
+{
+  "3FE272FB": { "sha1": "...", "prg_crc32": "...", "prg_sha1": "...", ... }
+}
+*/
+pub struct RomHashCache {
+    path: String,
+    json: Value,
+    // The CRC-32 of the ROM being hashed, and the thread hashing it
+    hashing: Option<(u32, JoinHandle<Result<RomHashes, String>>)>,
+}
+
+impl RomHashCache {
+    /// Loads the cache file, or starts an empty cache if it doesn't exist or
+    /// can't be read
+    pub fn load(path: &str) -> Self {
+        let json = fs::read_to_string(path).ok()
+            .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+            .filter(Value::is_object)
+            .unwrap_or_else(|| json!({}));
+
+        Self { path: String::from(path), json, hashing: None }
+    }
+
+    /// Gets the hashes of the ROM with the given CRC-32, if they're cached
+    pub fn get(&self, rom_crc32: u32) -> Option<RomHashes> {
+        let entry = self.json.get(Self::key(rom_crc32))?;
+
+        let crc32 = |key: &str| entry.get(key)?.as_str().and_then(|hex| u32::from_str_radix(hex, 16).ok());
+        let sha1 = |key: &str| entry.get(key)?.as_str().and_then(sha1_from_hex);
+
+        Some(RomHashes {
+            rom_crc32,
+            rom_sha1: sha1("sha1")?,
+            prg_crc32: crc32("prg_crc32")?,
+            prg_sha1: sha1("prg_sha1")?,
+            chr_crc32: crc32("chr_crc32"),
+            chr_sha1: sha1("chr_sha1"),
+        })
+    }
+
+    /// Starts hashing the ROM at the given path (patched the same way it was
+    /// when loaded) in the background, unless its hashes are already cached,
+    /// in which case they're returned
+    pub fn hash(&mut self, cart_path: &str, patch_path: Option<&str>, rom_crc32: u32) -> Option<RomHashes> {
+        if let Some(hashes) = self.get(rom_crc32) {
+            return Some(hashes);
+        }
+
+        let cart_path = String::from(cart_path);
+        let patch_path = patch_path.map(String::from);
+
+        // A ROM that was still being hashed is left to finish on its own, and
+        // what it finds is thrown away
+        self.hashing = Some((rom_crc32, thread::spawn(move || {
+            let (data, _) = patch::read_patched_rom(&cart_path, patch_path.as_deref())?;
+
+            RomHashes::of_rom(&data)
+        })));
+
+        None
+    }
+
+    /// Returns the hashes from the background thread once it's done, saving
+    /// them to the cache file. Cheap enough to call every frame.
+    pub fn poll(&mut self) -> Option<RomHashes> {
+        if !self.hashing.as_ref().is_some_and(|(_, handle)| handle.is_finished()) {
+            return None;
+        }
+
+        let (rom_crc32, handle) = self.hashing.take().unwrap();
+
+        let hashes = match handle.join() {
+            // The file could have been changed since it was loaded
+            Ok(Ok(hashes)) if hashes.rom_crc32 == rom_crc32 => hashes,
+            Ok(Ok(_)) => {
+                println!("Couldn't hash ROM: the file changed since it was loaded");
+                return None;
+            }
+            Ok(Err(e)) => {
+                println!("Couldn't hash ROM: {e}");
+                return None;
+            }
+            Err(_) => {
+                println!("Couldn't hash ROM: the hashing thread crashed");
+                return None;
+            }
+        };
+
+        self.insert(&hashes);
+        if let Err(e) = self.save() {
+            println!("{e}");
+        }
+
+        Some(hashes)
+    }
+
+    fn key(rom_crc32: u32) -> String {
+        format!("{rom_crc32:08X}")
+    }
+
+    fn insert(&mut self, hashes: &RomHashes) {
+        let mut entry = Map::new();
+
+        entry.insert(String::from("sha1"), json!(RomHashes::sha1_hex(&hashes.rom_sha1)));
+        entry.insert(String::from("prg_crc32"), json!(format!("{:08X}", hashes.prg_crc32)));
+        entry.insert(String::from("prg_sha1"), json!(RomHashes::sha1_hex(&hashes.prg_sha1)));
+        if let Some(chr_crc32) = hashes.chr_crc32 {
+            entry.insert(String::from("chr_crc32"), json!(format!("{chr_crc32:08X}")));
+        }
+        if let Some(chr_sha1) = &hashes.chr_sha1 {
+            entry.insert(String::from("chr_sha1"), json!(RomHashes::sha1_hex(chr_sha1)));
+        }
+
+        self.json.as_object_mut().unwrap().insert(Self::key(hashes.rom_crc32), Value::Object(entry));
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(&self.json)
+            .map_err(|e| format!("Couldn't serialize ROM hashes: {e}"))?;
+
+        fs::write(&self.path, text)
+            .map_err(|e| format!("Couldn't write ROM hash cache '{}': {e}", self.path))
+    }
+}
+
+fn sha1_from_hex(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+
+    let mut sha1 = [0u8; 20];
+    for (i, byte) in sha1.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(sha1)
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::system::savestate::SaveState;
+
+/// Where save states are kept, next to the config file
+pub const SAVE_STATE_DIR: &str = "states";
+
+/// Number of save slots each game has
+pub const SAVE_SLOTS: usize = 10;
+
+/*
+ON HOW SAVE SLOTS WORK:
+
+Each game has ten save slots, kept as files named after the ROM's CRC32 and
+the slot (3FE272FB.4.state), so renaming the ROM doesn't lose them. F5 saves to
+the selected slot, F6 picks the next slot and F7 loads the selected one.
+
+Retrying (F8, or "Retry from save state" in the pause menu) loads the retry
+anchor in one press, for practicing a hard part of a game over and over. The
+anchor is the slot set with "retry_slot" in the config file, or if none is set,
+the slot last saved to while the game's been running, or failing that the slot
+with the newest state on disk for the game.
+
+States are written to a temporary file and then renamed, so quitting while one
+is being saved never leaves half a state behind.
+*/
+pub struct SaveSlots {
+    dir: PathBuf,
+    selected: usize,
+    // The game and slot last saved to, if it's been saved this session
+    last_saved: Option<(u32, usize)>,
+    // The slot set as the retry anchor in the config, if any
+    retry_slot: Option<usize>,
+}
+
+impl SaveSlots {
+    pub fn new(dir: &str, retry_slot: Option<usize>) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            selected: 0,
+            last_saved: None,
+            retry_slot: retry_slot.filter(|&slot| slot < SAVE_SLOTS),
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Picks the next slot, going back to the first after the last
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % SAVE_SLOTS;
+    }
+
+    /// Where the state for the ROM with the given hash is (or would be) kept
+    pub fn path(&self, rom_crc32: u32, slot: usize) -> PathBuf {
+        self.dir.join(format!("{rom_crc32:08X}.{slot}.state"))
+    }
+
+    /// Saves a state to a slot, replacing what was there
+    pub fn save(&mut self, rom_crc32: u32, slot: usize, state: &SaveState) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Couldn't create save state folder '{}': {e}", self.dir.display()))?;
+
+        let path = self.path(rom_crc32, slot);
+        let temp_path = path.with_extension("state.tmp");
+
+        fs::write(&temp_path, state.as_bytes())
+            .map_err(|e| format!("Couldn't save state '{}': {e}", temp_path.display()))?;
+        fs::rename(&temp_path, &path)
+            .map_err(|e| format!("Couldn't save state '{}': {e}", path.display()))?;
+
+        self.last_saved = Some((rom_crc32, slot));
+
+        Ok(())
+    }
+
+    /// Reads the state in a slot
+    pub fn load(&self, rom_crc32: u32, slot: usize) -> Result<SaveState, String> {
+        let path = self.path(rom_crc32, slot);
+
+        match fs::read(&path) {
+            Ok(bytes) => Ok(SaveState::from_bytes(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(format!("Slot {slot} is empty")),
+            Err(e) => Err(format!("Couldn't read state '{}': {e}", path.display())),
+        }
+    }
+
+    /// The slot retrying loads for the ROM with the given hash, if there's
+    /// one to load (see "ON HOW SAVE SLOTS WORK")
+    pub fn retry_slot(&self, rom_crc32: u32) -> Option<usize> {
+        if self.retry_slot.is_some() {
+            return self.retry_slot;
+        }
+        if let Some((crc32, slot)) = self.last_saved {
+            if crc32 == rom_crc32 {
+                return Some(slot);
+            }
+        }
+
+        (0..SAVE_SLOTS)
+            .filter_map(|slot| {
+                let modified = fs::metadata(self.path(rom_crc32, slot)).and_then(|meta| meta.modified()).ok()?;
+                Some((slot, modified))
+            })
+            .max_by_key(|&(_, modified): &(usize, SystemTime)| modified)
+            .map(|(slot, _)| slot)
+    }
+}
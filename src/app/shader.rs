@@ -0,0 +1,257 @@
+use pixels::wgpu::{self, util::DeviceExt};
+use pixels::Pixels;
+
+/*
+ON HOW THE GPU SHADERS WORK:
+
+pixels normally draws the pixel buffer to the window with its scaling
+renderer: one textured triangle, scaled by a whole number and centered. A GPU
+shader takes its place, drawing the same triangle into the same spot but with
+a fragment shader of its own (assets/shaders/post_process.wgsl), which gets
+the whole scaled-up picture to work with. That's what makes these different
+from the CPU filters in video_filter.rs, which only have the NES's 256x240
+pixels (or twice that): a scanline can be a soft gradient across the 4 or 5
+window pixels a line of the picture takes up, and the curve bends the picture
+smoothly instead of a pixel at a time.
+
+    Scanlines   darkens the gaps between the lines
+    Curvature   bends the picture like the glass of a CRT
+    Bloom       makes bright colors glow into their neighbors
+    CRT         all of them at once, with an aperture grille and dark edges
+
+The shaders only draw the game view, not the debugger or the pause menu. They
+run after the CPU filters, so the two can be mixed.
+
+Every pipeline is made when the window is, inside a wgpu error scope. If the
+GPU or driver can't make them (or drawing with one fails later on), the app
+says so and falls back to the closest CPU filters instead.
+*/
+
+const SHADER_SOURCE: &str = include_str!("assets/shaders/post_process.wgsl");
+
+/// The shaders the game view can be drawn with
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GpuShader {
+    #[default]
+    Off,
+    Scanlines,
+    Curvature,
+    Bloom,
+    Crt,
+}
+
+impl GpuShader {
+    pub const ALL: [GpuShader; 5] = [Self::Off, Self::Scanlines, Self::Curvature, Self::Bloom, Self::Crt];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shader| shader.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Scanlines => "scanlines",
+            Self::Curvature => "curvature",
+            Self::Bloom => "bloom",
+            Self::Crt => "crt",
+        }
+    }
+
+    // The shader's fragment entry point in post_process.wgsl
+    fn entry_point(&self) -> Option<&'static str> {
+        match self {
+            Self::Off => None,
+            Self::Scanlines => Some("fs_scanlines"),
+            Self::Curvature => Some("fs_curvature"),
+            Self::Bloom => Some("fs_bloom"),
+            Self::Crt => Some("fs_crt"),
+        }
+    }
+
+    /// The ids of the CPU filters (see video_filter.rs) to use instead when
+    /// the shader can't run
+    pub fn cpu_fallback(&self) -> &'static [&'static str] {
+        match self {
+            Self::Off | Self::Bloom => &[],
+            Self::Scanlines => &["scanlines"],
+            Self::Curvature => &["crt_curve"],
+            Self::Crt => &["scanlines", "crt_curve"],
+        }
+    }
+}
+
+/// Draws a Pixels' buffer to its window through one of the GPU shaders
+pub struct ShaderRenderer {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    pipelines: Vec<(GpuShader, wgpu::RenderPipeline)>,
+    clear_color: wgpu::Color,
+}
+
+impl ShaderRenderer {
+    /// Makes every shader's pipeline for drawing to the given Pixels' window.
+    /// Returns an error if the GPU wouldn't make them.
+    pub fn new(pixels: &Pixels, clear_color: wgpu::Color) -> Result<Self, String> {
+        let device = pixels.device();
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let renderer = Self::build(device, pixels.render_texture_format(), clear_color);
+
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(e) => Err(e.to_string()),
+            None => Ok(renderer),
+        }
+    }
+
+    fn build(device: &wgpu::Device, format: wgpu::TextureFormat, clear_color: wgpu::Color) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("post_process_shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER_SOURCE.into()),
+        });
+
+        // Nearest neighbor, the shaders do any smoothing themselves
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post_process_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        // The source and output sizes, see Locals in the shader
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("post_process_uniform_buffer"),
+            contents: &[0; 16],
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_process_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(16),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post_process_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = GpuShader::ALL.iter()
+            .filter_map(|shader| Some((*shader, shader.entry_point()?)))
+            .map(|(shader, entry_point)| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &module,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &module,
+                        entry_point,
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                });
+
+                (shader, pipeline)
+            })
+            .collect();
+
+        Self { bind_group_layout, sampler, uniform_buffer, pipelines, clear_color }
+    }
+
+    /// Draws the pixel buffer to the window through a shader, in place of
+    /// Pixels::render
+    pub fn render(&self, pixels: &Pixels, shader: GpuShader) -> Result<(), String> {
+        let Some((_, pipeline)) = self.pipelines.iter().find(|(made, _)| *made == shader) else {
+            return pixels.render().map_err(|e| e.to_string());
+        };
+
+        pixels.render_with(|encoder, render_target, context| {
+            // Where the scaling renderer would have drawn the picture
+            let (x, y, width, height) = context.scaling_renderer.clip_rect();
+
+            let locals: Vec<u8> = [
+                context.texture_extent.width as f32,
+                context.texture_extent.height as f32,
+                width as f32,
+                height as f32,
+            ].iter().flat_map(|n| n.to_ne_bytes()).collect();
+            context.queue.write_buffer(&self.uniform_buffer, 0, &locals);
+
+            // The pixel buffer's texture is made again whenever the buffer is
+            // resized, so the bind group can't be kept
+            let texture_view = context.texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("post_process_bind_group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&texture_view) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                    wgpu::BindGroupEntry { binding: 2, resource: self.uniform_buffer.as_entire_binding() },
+                ],
+            });
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("post_process_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: render_target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.clear_color),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            rpass.set_pipeline(pipeline);
+            rpass.set_bind_group(0, &bind_group, &[]);
+            rpass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            rpass.set_scissor_rect(x, y, width, height);
+            rpass.draw(0..3, 0..1);
+
+            Ok(())
+        })
+        .map_err(|e| e.to_string())
+    }
+}
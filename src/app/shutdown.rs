@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use winit::event_loop::EventLoopProxy;
+
+// How often the watcher thread looks for an interrupt
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Exit status for being stopped by a second Ctrl+C, the way shells report
+// SIGINT (128 + 2)
+const FORCED_EXIT_STATUS: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/*
+ON HOW SHUTTING DOWN WORKS:
+
+Everything the app has to do before it goes (saving battery RAM and the movie
+being recorded, writing settings to the config file) happens in one place,
+NesApp::shutdown, whichever way the app is closed: the close button, the Quit
+menu item, or Ctrl+C (or SIGTERM) in the terminal. It runs from the event
+loop's exiting callback, not from anything's Drop, since the order things are
+dropped in when the event loop ends isn't something to depend on.
+
+A signal handler can't safely do much more than set a flag, so the one here
+only does that. A small thread watches the flag and wakes the event loop with
+a user event, and the app exits the event loop from there like it does for the
+close button. If the app is stuck and a second Ctrl+C comes in, the handler
+gives up on shutting down cleanly and exits right away.
+*/
+
+/// Has Ctrl+C (and SIGTERM) wake the event loop through the proxy instead of
+/// killing the process (see "ON HOW SHUTTING DOWN WORKS")
+pub fn install_interrupt_handler(proxy: EventLoopProxy<()>) {
+    // SAFETY: the handler only touches an atomic, or exits the process
+    unsafe {
+        libc::signal(libc::SIGINT, handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+
+    thread::spawn(move || {
+        while !INTERRUPTED.load(Ordering::Relaxed) {
+            thread::sleep(INTERRUPT_POLL_INTERVAL);
+        }
+
+        // Fails only if the event loop's already gone
+        let _ = proxy.send_event(());
+    });
+}
+
+/// Whether Ctrl+C (or SIGTERM) has asked the app to stop
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+extern "C" fn handle_interrupt(_signal: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        // SAFETY: _exit is safe to call from a signal handler
+        unsafe { libc::_exit(FORCED_EXIT_STATUS) };
+    }
+}
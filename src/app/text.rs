@@ -0,0 +1,323 @@
+use crate::cartridge::cartridge::CartRegion;
+use crate::cartridge::compat::CompatStatus;
+use crate::cartridge::mapper::NametableMirror;
+use crate::system::apu_util::NesChannel;
+use crate::system::controller::{ControllerButton, ExpansionDevice};
+
+use super::shader::GpuShader;
+
+/*
+ON HOW UI TEXT IS LOOKED UP:
+
+None of the words the app shows are written where they're drawn. Instead, each
+piece of text has a name in the Text enum, and the words for it come out of a
+table (one match for each language). Text that has numbers or names in the
+middle of it marks where they go with {0}, {1}... so a translation can put them
+wherever its grammar wants, and fill() puts them in.
+
+Only English exists for now. Another language would be another table with
+the same entries, and a setting to choose between them. The font falls back to
+the closest plain letter for accented ones it can't draw (see
+chars::fallback_char), so a translation doesn't need to avoid them.
+
+Things that aren't words, like register names, hex numbers and the `special`
+characters, stay where they're drawn.
+*/
+
+/// Every piece of text shown by the app
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Text {
+    // Pause menu
+    PausedTitle,
+    Continue,
+    MapControllerInputs,
+    Volume,
+    AudioChannels,
+    Speed,
+    ExpansionPort,
+    Expansion(ExpansionDevice),
+    CartInfo,
+    VideoFilters,
+    GameProfile,
+    NoLimit,
+    AudioSync,
+    RetryFromState,
+    Quit,
+    ControllerMappingsTitle,
+    // "Player {0}", with the player number (starting at 1)
+    Player,
+    // "P{0} Stick Deadzone" and "P{0} Stick Diagonals", with the player number
+    StickDeadzone,
+    DiagonalBias,
+    SettingVolumeTitle,
+    AudioChannelsHint,
+    VideoFiltersHint,
+    GpuShaderLabel,
+    GpuShader(GpuShader),
+    Back,
+    // Labels on the cartridge info page
+    CartFormat,
+    CartMirroring,
+    CartBattery,
+    CartRegionLabel,
+    CartRegion(CartRegion),
+    Yes,
+    No,
+    Unknown,
+    Button(ControllerButton),
+    Channel(NesChannel),
+    On,
+    Off,
+    // Shown in place of a binding while waiting for one
+    WaitingForBinding,
+    Unbound,
+    // "Output: {0} dB", with the gain in decibels
+    OutputDecibels,
+    // In place of a number of decibels when the volume is all the way down
+    Silent,
+    Muted,
+
+    // "CPU jammed at ${0}", with the address in hex
+    CpuJammed,
+    // The box over the game after the emulator panics, with the folder the
+    // crash report went in
+    EmulatorCrashed,
+    CrashReportFailed,
+
+    // On screen messages about save slots, with the slot number
+    SlotSelected,
+    StateSaved,
+    StateLoaded,
+    StateSaveFailed,
+    StateLoadFailed,
+    NoRetryState,
+    // Loading a state would put a movie out of sync with the game
+    NoStatesDuringMovie,
+    // "Skipped {0} frames of intro", when a game's profile skips its intro
+    IntroSkipped,
+    // "Known issues ({0}):", with the game's status, over its notes from the
+    // compatibility database
+    KnownIssues,
+    CompatStatus(CompatStatus),
+
+    // Debug view
+    Pagetables,
+    CpuInfo,
+    MemoryPanel,
+    ApuLog,
+    PpuShifters,
+    MapperPanel,
+    // "Mapper {0} ({1})", with the mapper's number and name
+    MapperLabel,
+    InterruptsPanel,
+    RomInfoPanel,
+    // Shown on the ROM panel until the ROM's hashes are ready
+    RomHashing,
+    // In place of the CHR hashes for games without CHR ROM
+    ChrRam,
+    UnsupportedPanel,
+    // On the unsupported panel when the game hasn't done anything unsupported
+    NothingUnsupported,
+    // "Mirror: {0}"
+    MirrorLabel,
+    Mirror(NametableMirror),
+    // "Spr Size: {0}"
+    SpriteSizeLabel,
+    LargeSprites,
+    SmallSprites,
+    // "Palette: {0}", with the palette the pagetables are drawn in
+    PgtblPaletteLabel,
+    Greyscale,
+    // "FPS: {0}"
+    Fps,
+    // "Audio: {0}ms\nBatch: {1}\nUnderruns: {2}"
+    AudioStats,
+    // "Total Clks:{0}\nStatus:"
+    CpuClocks,
+    LastInstruction,
+
+    // "Emulate: {0}ms\nDraw: {1}ms\n..." with each of the numbers on the
+    // performance HUD in order
+    PerfHud,
+
+    // Window title
+    WindowPaused,
+    // "{0} FPS"
+    WindowFps,
+    // "{0}% speed"
+    WindowSpeed,
+    // "Slot {0}", the save slot F5 and F7 use
+    WindowSlot,
+}
+
+/// The words for a piece of text
+pub fn text(id: Text) -> &'static str {
+    english(id)
+}
+
+/// The words for a piece of text, with each {n} replaced by args[n]
+pub fn fill(id: Text, args: &[&str]) -> String {
+    let mut filled = String::from(text(id));
+
+    for (i, arg) in args.iter().enumerate() {
+        filled = filled.replace(&format!("{{{i}}}"), arg);
+    }
+
+    filled
+}
+
+/// Breaks text into lines of at most the given number of characters, between
+/// words where it can
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word = word;
+
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        // Words too long for a line of their own are split wherever they hit the edge
+        while word.chars().count() > width {
+            let split = word.char_indices().nth(width).map_or(word.len(), |(i, _)| i);
+            lines.push(String::from(&word[..split]));
+            word = &word[split..];
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn english(id: Text) -> &'static str {
+    match id {
+        Text::PausedTitle => "Emulator Paused",
+        Text::Continue => "Continue [ESC]",
+        Text::MapControllerInputs => "Map Controller Inputs",
+        Text::Volume => "Volume",
+        Text::AudioChannels => "Audio Channels",
+        Text::Speed => "Speed",
+        Text::ExpansionPort => "Expansion",
+        Text::Expansion(ExpansionDevice::None) => "None",
+        Text::Expansion(ExpansionDevice::FamilyKeyboard) => "Keyboard",
+        Text::Expansion(ExpansionDevice::ArkanoidPaddle) => "Arkanoid",
+        Text::CartInfo => "Cartridge Info",
+        Text::VideoFilters => "Video Filters",
+        Text::GameProfile => "Game Profile",
+        Text::NoLimit => "NoLimit",
+        Text::AudioSync => "Audio Sync",
+        Text::RetryFromState => "Retry From Save",
+        Text::Quit => "Quit",
+        Text::ControllerMappingsTitle => "Controller Mappings",
+        Text::Player => "Player {0}",
+        Text::StickDeadzone => "P{0} Stick Deadzone",
+        Text::DiagonalBias => "P{0} Stick Diagonals",
+        Text::SettingVolumeTitle => "Setting Volume",
+        Text::AudioChannelsHint => "A: Toggle  Select: Solo\nB: Back",
+        Text::VideoFiltersHint => "A: Toggle  Select: Settings\nB: Back",
+        Text::GpuShaderLabel => "GPU Shader",
+        Text::GpuShader(GpuShader::Off) => "Off",
+        Text::GpuShader(GpuShader::Scanlines) => "Scanlines",
+        Text::GpuShader(GpuShader::Curvature) => "Curvature",
+        Text::GpuShader(GpuShader::Bloom) => "Bloom",
+        Text::GpuShader(GpuShader::Crt) => "CRT",
+        Text::Back => "Back",
+        Text::CartFormat => "Format",
+        Text::CartMirroring => "Mirroring",
+        Text::CartBattery => "Battery",
+        Text::CartRegionLabel => "Region",
+        Text::CartRegion(CartRegion::Ntsc) => "NTSC",
+        Text::CartRegion(CartRegion::Pal) => "PAL",
+        Text::CartRegion(CartRegion::MultiRegion) => "Any",
+        Text::CartRegion(CartRegion::Dendy) => "Dendy",
+        Text::Yes => "Yes",
+        Text::No => "No",
+        Text::Unknown => "Unknown",
+
+        Text::Button(ControllerButton::A) => "A Button",
+        Text::Button(ControllerButton::B) => "B Button",
+        Text::Button(ControllerButton::Select) => "Select",
+        Text::Button(ControllerButton::Start) => "Start",
+        Text::Button(ControllerButton::Up) => "Up",
+        Text::Button(ControllerButton::Down) => "Down",
+        Text::Button(ControllerButton::Left) => "Left",
+        Text::Button(ControllerButton::Right) => "Right",
+
+        Text::Channel(NesChannel::Pulse1) => "Pulse 1",
+        Text::Channel(NesChannel::Pulse2) => "Pulse 2",
+        Text::Channel(NesChannel::Triangle) => "Triangle",
+        Text::Channel(NesChannel::Noise) => "Noise",
+        Text::Channel(NesChannel::DMC) => "DMC",
+        Text::Channel(NesChannel::Expansion) => "Expansion",
+
+        Text::On => "ON",
+        Text::Off => "OFF",
+        Text::WaitingForBinding => "...",
+        Text::Unbound => "-",
+        Text::OutputDecibels => "Output: {0} dB",
+        Text::Silent => "-inf",
+        Text::Muted => "Muted [0]",
+
+        Text::CpuJammed => "CPU jammed at ${0}\n\n     Reset? [R]",
+        Text::EmulatorCrashed => "The emulator crashed!\n\nA report was saved in\n{0}\n\n      Reset? [R]",
+        Text::CrashReportFailed => "The emulator crashed!\n\nThe report couldn't be\nsaved\n\n      Reset? [R]",
+
+        Text::SlotSelected => "Slot {0}",
+        Text::StateSaved => "Saved to slot {0}",
+        Text::StateLoaded => "Loaded slot {0}",
+        Text::StateSaveFailed => "Couldn't save to slot {0}",
+        Text::StateLoadFailed => "Couldn't load slot {0}",
+        Text::NoRetryState => "No save state to retry from",
+        Text::NoStatesDuringMovie => "Can't load states during a movie",
+        Text::IntroSkipped => "Skipped {0} frames of intro",
+        Text::KnownIssues => "Known issues ({0}):",
+        Text::CompatStatus(CompatStatus::Playable) => "playable",
+        Text::CompatStatus(CompatStatus::MinorIssues) => "minor issues",
+        Text::CompatStatus(CompatStatus::MajorIssues) => "major issues",
+        Text::CompatStatus(CompatStatus::Unplayable) => "unplayable",
+
+        Text::Pagetables => "Pagetables",
+        Text::CpuInfo => "CPU Info",
+        Text::MemoryPanel => "Memory",
+        Text::ApuLog => "APU Log",
+        Text::PpuShifters => "PPU Shifters",
+        Text::MapperPanel => "Mapper",
+        Text::MapperLabel => "Mapper {0} ({1})",
+        Text::InterruptsPanel => "Interrupts",
+        Text::RomInfoPanel => "ROM",
+        Text::RomHashing => "Hashing...",
+        Text::ChrRam => "None (CHR RAM)",
+        Text::UnsupportedPanel => "Unsupported",
+        Text::NothingUnsupported => "Nothing unsupported used yet",
+        Text::MirrorLabel => "Mirror: {0}",
+        Text::Mirror(NametableMirror::Horizontal) => "Horizontal",
+        Text::Mirror(NametableMirror::Vertical) => "Vertical",
+        Text::Mirror(NametableMirror::SingleScreenLower) => "1-Screen Lower",
+        Text::Mirror(NametableMirror::SingleScreenUpper) => "1-Screen Upper",
+        Text::Mirror(NametableMirror::FourScreen) => "4-Screen",
+        Text::SpriteSizeLabel => "Spr Size: {0}",
+        Text::LargeSprites => "8x16 - Large",
+        Text::SmallSprites => "8x8 - Small",
+        Text::PgtblPaletteLabel => "Palette: {0}",
+        Text::Greyscale => "Grey",
+        Text::Fps => "FPS: {0}",
+        Text::AudioStats => "Audio: {0}ms\nBatch: {1}\nUnderruns: {2}",
+        Text::CpuClocks => "Total Clks:{0}\nStatus:",
+        Text::LastInstruction => "Last Instr:",
+
+        Text::PerfHud => "Emulate: {0}ms\nDraw:    {1}ms\nPresent: {2}ms\nAudio queue: {3}/{4}\nInput lag: {5}ms\nDropped: {6}\nUnderruns: {7}\nFilled in: {8}\nCatch-up: {9}",
+
+        Text::WindowPaused => "Paused",
+        Text::WindowFps => "{0} FPS",
+        Text::WindowSpeed => "{0}% speed",
+        Text::WindowSlot => "Slot {0}",
+    }
+}
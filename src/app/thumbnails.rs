@@ -0,0 +1,116 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use image::{imageops, RgbaImage};
+
+use crate::headless;
+use crate::system::nes::{NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
+
+/// Where thumbnails are kept, next to the config file
+pub const THUMBNAIL_DIR: &str = "thumbnails";
+
+// Thumbnails are half the size of the NES picture
+const THUMBNAIL_WIDTH: u32 = NES_SCREEN_WIDTH as u32 / 2;
+const THUMBNAIL_HEIGHT: u32 = NES_SCREEN_HEIGHT as u32 / 2;
+
+// How long generated thumbnails wait for the game to get past its intro
+// screens (5 seconds), and the most they'll wait for the picture to settle
+const THUMBNAIL_MIN_FRAMES: usize = 60 * 5;
+const THUMBNAIL_MAX_FRAMES: usize = 60 * 10;
+
+/*
+ON HOW THUMBNAILS ARE MADE:
+
+Every ROM gets a small png picture, named after the ROM's CRC32 so renaming the
+file doesn't lose it. If a game doesn't have one when it's loaded, a background
+thread boots a second, headless copy of the game, lets it run for a few seconds
+with no input, and saves the first frame that's the same as the one before it
+(so we don't catch the screen halfway through fading in). The player can also
+replace it with whatever's on screen at any time.
+
+Thumbnails are written to a temporary file and then renamed, so quitting while
+one is being saved never leaves a broken png behind.
+*/
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    generating: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            generating: None,
+        }
+    }
+
+    /// Where the thumbnail for the ROM with the given hash is (or would be)
+    pub fn path(&self, rom_crc32: u32) -> PathBuf {
+        self.dir.join(format!("{rom_crc32:08X}.png"))
+    }
+
+    /// The thumbnail for the ROM with the given hash, if one has been made
+    pub fn get(&self, rom_crc32: u32) -> Option<PathBuf> {
+        let path = self.path(rom_crc32);
+
+        path.exists().then_some(path)
+    }
+
+    /// Starts making a thumbnail for the ROM in the background, unless it
+    /// already has one or another one is still being made
+    pub fn generate_if_missing(&mut self, cart_path: &str, rom_crc32: u32) {
+        self.poll();
+
+        if self.generating.is_some() || self.get(rom_crc32).is_some() {
+            return;
+        }
+
+        let cart_path = String::from(cart_path);
+        let dest = self.path(rom_crc32);
+
+        self.generating = Some(thread::spawn(move || {
+            let frame = headless::render_stable_frame(&cart_path, THUMBNAIL_MIN_FRAMES, THUMBNAIL_MAX_FRAMES)?;
+
+            save_thumbnail(&frame, &dest)
+        }));
+    }
+
+    /// Saves the given frame (RGBA, the size of the NES screen) as the ROM's
+    /// thumbnail, replacing the old one
+    pub fn capture(&self, rom_crc32: u32, frame: &[u8]) -> Result<(), String> {
+        save_thumbnail(frame, &self.path(rom_crc32))
+    }
+
+    /// Reports how the background thumbnail went once it's done. Cheap enough
+    /// to call every frame.
+    pub fn poll(&mut self) {
+        if !self.generating.as_ref().is_some_and(|handle| handle.is_finished()) {
+            return;
+        }
+
+        match self.generating.take().unwrap().join() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => println!("Couldn't make thumbnail: {e}"),
+            Err(_) => println!("Couldn't make thumbnail: the game crashed"),
+        }
+    }
+}
+
+fn save_thumbnail(frame: &[u8], path: &Path) -> Result<(), String> {
+    let image = RgbaImage::from_raw(NES_SCREEN_WIDTH as u32, NES_SCREEN_HEIGHT as u32, frame.to_vec())
+        .ok_or("Frame is the wrong size for a thumbnail")?;
+    let thumbnail = imageops::thumbnail(&image, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Couldn't create thumbnail folder '{}': {e}", dir.display()))?;
+    }
+
+    let temp_path = path.with_extension("png.tmp");
+
+    thumbnail.save_with_format(&temp_path, image::ImageFormat::Png)
+        .map_err(|e| format!("Couldn't save thumbnail '{}': {e}", temp_path.display()))?;
+    fs::rename(&temp_path, path)
+        .map_err(|e| format!("Couldn't save thumbnail '{}': {e}", path.display()))
+}
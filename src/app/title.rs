@@ -0,0 +1,88 @@
+use std::time::Instant;
+
+use winit::window::Window;
+
+use super::text::{fill, text, Text};
+
+// How many frames go by between window title updates. Changing the title every
+// frame is surprisingly slow on some platforms, and an FPS number that changes
+// 60 times a second is unreadable anyway.
+const TITLE_UPDATE_FRAMES: u64 = 30;
+
+/// Everything that gets shown in the window title
+pub struct TitleInfo<'a> {
+    pub game_name: &'a str,
+    pub paused: bool,
+    pub fast_forward: bool,
+    pub speed_percent: usize,
+    pub save_slot: usize,
+}
+
+/// Keeps the window title up to date with the game being played, the save
+/// slot, the measured FPS, and whether the emulator is paused or running
+/// uncapped.
+pub struct WindowTitle {
+    current: String,
+    frames: u64,
+    last_update: Instant,
+    fps: f64,
+}
+
+impl WindowTitle {
+    pub fn new() -> Self {
+        Self {
+            current: String::new(),
+            frames: 0,
+            last_update: Instant::now(),
+            fps: 0.0,
+        }
+    }
+
+    /// Counts a frame, and updates the title if it's been long enough since
+    /// the last update.
+    pub fn tick(&mut self, window: &Window, info: TitleInfo) {
+        self.frames += 1;
+
+        if self.frames >= TITLE_UPDATE_FRAMES {
+            self.fps = self.frames as f64 / self.last_update.elapsed().as_secs_f64();
+            self.frames = 0;
+            self.last_update = Instant::now();
+
+            self.refresh(window, info);
+        }
+    }
+
+    /// Updates the title right away (for changes like pausing that should
+    /// show up immediately). The FPS shown is the last one measured.
+    pub fn refresh(&mut self, window: &Window, info: TitleInfo) {
+        let title = self.format(info);
+
+        if title != self.current {
+            window.set_title(&title);
+            self.current = title;
+        }
+    }
+
+    fn format(&self, info: TitleInfo) -> String {
+        let mut title = String::from("NEmulator");
+
+        if !info.game_name.is_empty() {
+            title.push_str(&format!(" - {}", info.game_name));
+            title.push_str(&format!(" | {}", fill(Text::WindowSlot, &[&info.save_slot.to_string()])));
+        }
+
+        if info.paused {
+            title.push_str(&format!(" | {}", text(Text::WindowPaused)));
+        } else {
+            title.push_str(&format!(" | {}", fill(Text::WindowFps, &[&format!("{:.0}", self.fps)])));
+
+            if info.fast_forward {
+                title.push_str(" | >>");
+            } else if info.speed_percent != 100 {
+                title.push_str(&format!(" | {}", fill(Text::WindowSpeed, &[&info.speed_percent.to_string()])));
+            }
+        }
+
+        title
+    }
+}
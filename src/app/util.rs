@@ -1,6 +1,8 @@
-use std::{collections::VecDeque, path::Path, sync::{Arc, Mutex}};
+use std::io::Cursor;
 
-use crate::system::controller::{ControllerButton, NesController};
+use crate::system::{controller::{ControllerButton, NesController}, sample_ring::SampleQueue};
+
+use super::assets::{Asset, AssetStore};
 
 pub struct Sprite {
     sprite_rgba: Vec<u8>,
@@ -9,24 +11,40 @@ pub struct Sprite {
 }
 
 impl Sprite {
-    pub fn new(path: &str) -> Self {
-        let img = image::open(path).expect("Failed to open image");
+    pub fn load(assets: &AssetStore, asset: Asset) -> Self {
+        assets.decode(asset, Self::from_png)
+    }
+
+    /// Decodes a sprite from the bytes of a png file
+    pub fn from_png(bytes: &[u8]) -> Result<Self, String> {
+        let img = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to decode image: {e}"))?;
         let rgba_image = img.to_rgba8();
 
-        Self {
+        Ok(Self {
             sprite_rgba: rgba_image.into_raw(),
             width: img.width() as usize,
             height: img.height() as usize,
-        }
+        })
     }
 
+    /// Copies the sprite to the frame with its top left corner at (x, y),
+    /// cutting off whatever's past the edge of the frame
     fn draw(&self, frame: &mut [u8], frame_width: usize, frame_height: usize, x: usize, y: usize) {
+        let visible_width = self.width.min(frame_width.saturating_sub(x));
+
         for (row, row_pixels) in self.sprite_rgba.chunks(self.width * 4).enumerate() {
+            if y + row >= frame_height || visible_width == 0 {
+                break;
+            }
+
             let row_start = ((y + row)*frame_width + x) * 4;
-            let row_end = row_start + self.width * 4;
-            let dest_row = &mut frame[row_start..row_end];
+            let row_end = row_start + visible_width * 4;
+            let Some(dest_row) = frame.get_mut(row_start..row_end) else {
+                break;
+            };
 
-            dest_row.copy_from_slice(row_pixels);
+            dest_row.copy_from_slice(&row_pixels[..visible_width * 4]);
         }
     }
 }
@@ -45,17 +63,17 @@ pub struct ControllerSprite {
 }
 
 impl ControllerSprite {
-    pub fn new() -> Self {
+    pub fn new(assets: &AssetStore) -> Self {
         Self {
-            base: Sprite::new("src/app/assets/sprites/NemulatorController.png"),
-            a_button: Sprite::new("src/app/assets/sprites/a_button.png"),
-            b_button: Sprite::new("src/app/assets/sprites/b_button.png"),
-            select: Sprite::new("src/app/assets/sprites/select_button.png"),
-            start: Sprite::new("src/app/assets/sprites/start_button.png"),
-            up_arrow: Sprite::new("src/app/assets/sprites/up_arrow.png"),
-            down_arrow: Sprite::new("src/app/assets/sprites/down_arrow.png"),
-            left_arrow: Sprite::new("src/app/assets/sprites/left_arrow.png"),
-            right_arrow: Sprite::new("src/app/assets/sprites/right_arrow.png"),
+            base: Sprite::load(assets, Asset::ControllerBase),
+            a_button: Sprite::load(assets, Asset::AButton),
+            b_button: Sprite::load(assets, Asset::BButton),
+            select: Sprite::load(assets, Asset::SelectButton),
+            start: Sprite::load(assets, Asset::StartButton),
+            up_arrow: Sprite::load(assets, Asset::UpArrow),
+            down_arrow: Sprite::load(assets, Asset::DownArrow),
+            left_arrow: Sprite::load(assets, Asset::LeftArrow),
+            right_arrow: Sprite::load(assets, Asset::RightArrow),
         }
     }
 
@@ -105,10 +123,10 @@ pub struct SliderSprite {
 }
 
 impl SliderSprite {
-    pub fn new(dot_min: usize, dot_max: usize, dot_y: usize,) -> Self {
+    pub fn new(assets: &AssetStore, dot_min: usize, dot_max: usize, dot_y: usize) -> Self {
         Self {
-            base: Sprite::new("src/app/assets/sprites/slider.png"),
-            dot: Sprite::new("src/app/assets/sprites/slider_dot.png"),
+            base: Sprite::load(assets, Asset::SliderBase),
+            dot: Sprite::load(assets, Asset::SliderDot),
             dot_min,
             dot_max,
             dot_y,
@@ -129,12 +147,14 @@ pub struct MenuSound {
 }
 
 impl MenuSound {
-    pub fn new(path: &str) -> Self {
-        // Path to the WAV file
-        let path = Path::new(path);
+    pub fn load(assets: &AssetStore, asset: Asset) -> Self {
+        assets.decode(asset, Self::from_wav)
+    }
 
-        // Open the WAV file
-        let mut reader = hound::WavReader::open(path).expect("Failed to open WAV file");
+    /// Decodes a sound from the bytes of a WAV file
+    pub fn from_wav(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = hound::WavReader::new(Cursor::new(bytes))
+            .map_err(|e| format!("Failed to read WAV file: {e}"))?;
 
         // Create a vector to hold the samples
         let mut raw_samples: Vec<f32> = Vec::new();
@@ -144,25 +164,25 @@ impl MenuSound {
             hound::SampleFormat::Float => {
                 // Read samples as f32 directly
                 for sample in reader.samples::<f32>() {
-                    raw_samples.push(sample.expect("Failed to read sample"));
+                    raw_samples.push(sample.map_err(|e| format!("Failed to read sample: {e}"))?);
                 }
             }
             hound::SampleFormat::Int => {
                 // Read samples as i16 and convert to f32
                 let max_amplitude = 2_i32.pow(reader.spec().bits_per_sample as u32 - 1) as f32;
                 for sample in reader.samples::<i16>() {
-                    raw_samples.push(sample.expect("Failed to read sample") as f32 / max_amplitude);
+                    raw_samples.push(sample.map_err(|e| format!("Failed to read sample: {e}"))? as f32 / max_amplitude);
                 }
             }
         }
 
-        Self {
+        Ok(Self {
             raw_samples
-        }
+        })
     }
 
-    pub fn play_to_stream(&self, stream: Arc<Mutex<VecDeque<f32>>>) {
-        stream.lock().unwrap().extend(self.raw_samples.clone());
+    pub fn play_to_stream(&self, stream: SampleQueue) {
+        stream.push_slice(&self.raw_samples);
     }
 }
 
@@ -271,6 +291,12 @@ impl ButtonMapping {
     }
 }
 
+/// How far (as a fraction of full tilt) the left stick has to be pushed
+/// before it counts as pressing the D-pad, and how wide the diagonals are
+/// (see ControllerMapping::stick_directions)
+pub const DEFAULT_STICK_DEADZONE: f32 = 0.25;
+pub const DEFAULT_DIAGONAL_BIAS: f32 = 0.5;
+
 pub struct ControllerMapping {
     a_map: ButtonMapping,
     b_map: ButtonMapping,
@@ -280,6 +306,10 @@ pub struct ControllerMapping {
     right_map: ButtonMapping,
     select_map: ButtonMapping,
     start_map: ButtonMapping,
+
+    // The left stick always works as a D-pad, with these settings
+    stick_deadzone: f32,
+    diagonal_bias: f32,
 }
 
 impl Default for ControllerMapping {
@@ -293,6 +323,9 @@ impl Default for ControllerMapping {
             left_map: ButtonMapping::new_from_button(ControllerButton::Left, gilrs::Button::DPadRight, false),
             select_map: ButtonMapping::new_from_button(ControllerButton::Select, gilrs::Button::Select, true),
             start_map: ButtonMapping::new_from_button(ControllerButton::Start, gilrs::Button::Start, true),
+
+            stick_deadzone: DEFAULT_STICK_DEADZONE,
+            diagonal_bias: DEFAULT_DIAGONAL_BIAS,
         }
     }
 }
@@ -334,4 +367,83 @@ impl ControllerMapping {
             None
         }
     }
-}
\ No newline at end of file
+
+    pub fn stick_deadzone(&self) -> f32 {
+        self.stick_deadzone
+    }
+
+    pub fn set_stick_deadzone(&mut self, deadzone: f32) {
+        self.stick_deadzone = deadzone.clamp(0.0, 1.0);
+    }
+
+    pub fn diagonal_bias(&self) -> f32 {
+        self.diagonal_bias
+    }
+
+    pub fn set_diagonal_bias(&mut self, bias: f32) {
+        self.diagonal_bias = bias.clamp(0.0, 1.0);
+    }
+
+    /// Turns a left stick position (each axis from -1 to 1, with up being
+    /// positive Y like gilrs has it) into the D-pad directions it presses, in
+    /// the order up, down, left, right.
+    ///
+    /// Nothing is pressed until the stick is pushed further than the
+    /// deadzone. Past that, the axis the stick is pushed furthest along is
+    /// always pressed, and the other one is pressed too when the stick is
+    /// close enough to a diagonal. The diagonal bias decides how close: at 0
+    /// diagonals are never pressed (a 4-way stick), at 0.5 the stick is split
+    /// into 8 equal directions, and at 1 anything off the axes is a diagonal.
+    pub fn stick_directions(&self, x: f32, y: f32) -> [(ControllerButton, bool); 4] {
+        let tilt = x.hypot(y);
+
+        let (vertical, horizontal) = if tilt <= self.stick_deadzone {
+            (false, false)
+        } else {
+            // How far off an axis the stick can be, as a fraction of how far
+            // it's pushed, before the other axis gets pressed too
+            let threshold = (std::f32::consts::FRAC_PI_4 * (1.0 - self.diagonal_bias)).sin() * tilt;
+
+            let vertical = y.abs() >= x.abs() || y.abs() > threshold;
+            let horizontal = x.abs() >= y.abs() || x.abs() > threshold;
+
+            (vertical, horizontal)
+        };
+
+        [
+            (ControllerButton::Up, vertical && y > 0.0),
+            (ControllerButton::Down, vertical && y < 0.0),
+            (ControllerButton::Left, horizontal && x < 0.0),
+            (ControllerButton::Right, horizontal && x > 0.0),
+        ]
+    }
+
+    /// Lists the gamepad button (and the direction it's pressed in) mapped to
+    /// each NES button, so the mapping can be saved
+    pub fn button_mappings(&self) -> Vec<(ControllerButton, gilrs::Button, bool)> {
+        [&self.a_map, &self.b_map, &self.up_map, &self.down_map,
+         &self.left_map, &self.right_map, &self.select_map, &self.start_map]
+            .iter()
+            .filter_map(|map| match (map.from_button, map.button_dir_positive) {
+                (Some(button), Some(positive)) => Some((map.to_button, button, positive)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// Every gamepad button gilrs knows about, for turning saved names back into buttons
+const GAMEPAD_BUTTONS: [gilrs::Button; 19] = [
+    gilrs::Button::South, gilrs::Button::East, gilrs::Button::North, gilrs::Button::West,
+    gilrs::Button::C, gilrs::Button::Z,
+    gilrs::Button::LeftTrigger, gilrs::Button::LeftTrigger2,
+    gilrs::Button::RightTrigger, gilrs::Button::RightTrigger2,
+    gilrs::Button::Select, gilrs::Button::Start, gilrs::Button::Mode,
+    gilrs::Button::LeftThumb, gilrs::Button::RightThumb,
+    gilrs::Button::DPadUp, gilrs::Button::DPadDown, gilrs::Button::DPadLeft, gilrs::Button::DPadRight,
+];
+
+/// Looks up a gamepad button by its debug name (e.g. "DPadUp")
+pub fn gamepad_button_from_name(name: &str) -> Option<gilrs::Button> {
+    GAMEPAD_BUTTONS.iter().copied().find(|button| format!("{button:?}") == name)
+}
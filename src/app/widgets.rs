@@ -0,0 +1,343 @@
+use crate::system::controller::ControllerButton;
+
+use super::menu::MenuAction;
+
+/*
+ON HOW MENU WIDGETS WORK:
+
+Each page of the pause menu is a WidgetList: a title, a column of widgets, and
+the focus (the widget with the cursor next to it). The page is built once out
+of widgets, and everything about moving around it is handled by the list. The
+menu actions from menu.rs go in, and a WidgetEvent saying what happened comes
+out for the app to act on (and pick a sound for).
+
+Widgets only hold what's needed to draw them. The settings they stand for
+(volume, speed, muted channels...) belong to the app, which copies them into
+the widgets whenever they might have changed, so a setting changed some other
+way (like a hotkey) never shows a stale value in the menu.
+
+A new settings page is a new list of widgets, a screen for it in menu.rs, and
+whatever the app should do with its events.
+*/
+
+/// Names every widget in the menu, so the app can tell what an event is about
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WidgetId {
+    // Main page
+    Continue,
+    ControllerMap,
+    Volume,
+    AudioChannels,
+    Speed,
+    Expansion,
+    CartInfo,
+    VideoFilters,
+    GameProfile,
+    NoLimit,
+    AudioSync,
+    RetryFromState,
+    Quit,
+
+    // Choosing which player's controller to map (0 or 1)
+    Player(usize),
+    // The left stick settings of each player's controller (0 or 1)
+    StickDeadzone(usize),
+    DiagonalBias(usize),
+    // The NES button a gamepad button is being mapped to
+    MapButton(ControllerButton),
+    // An audio channel, as an index into NesChannel::ALL
+    Channel(usize),
+    // A video filter, as an index into the app's FilterChain
+    VideoFilter(usize),
+    GpuShader,
+    // One of the settings of the video filter being changed
+    FilterSetting(usize),
+    // Leaves a page that's only there to be looked at
+    Back,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum WidgetKind {
+    // Does something when confirmed
+    Button,
+    // Flips between on and off when confirmed
+    Toggle(bool),
+    // A number moved with left and right, a step at a time (or a fine step
+    // at a time, for finer control)
+    Slider { value: usize, min: usize, max: usize, step: usize, fine_step: usize, suffix: &'static str },
+    // Shows what's bound to it, and takes a new binding when capturing
+    KeyCapture(Option<String>),
+    // One of a list of options, moved through with left and right
+    Choice { options: Vec<String>, selected: usize },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Widget {
+    pub id: WidgetId,
+    pub label: String,
+    pub kind: WidgetKind,
+}
+
+impl Widget {
+    pub fn button(id: WidgetId, label: &str) -> Self {
+        Self { id, label: String::from(label), kind: WidgetKind::Button }
+    }
+
+    pub fn toggle(id: WidgetId, label: &str) -> Self {
+        Self { id, label: String::from(label), kind: WidgetKind::Toggle(false) }
+    }
+
+    pub fn slider(id: WidgetId, label: &str, min: usize, max: usize, step: usize, suffix: &'static str) -> Self {
+        Self { id, label: String::from(label), kind: WidgetKind::Slider { value: min, min, max, step, fine_step: step, suffix } }
+    }
+
+    /// Sets how far a slider moves with handle_fine
+    pub fn with_fine_step(mut self, new_fine_step: usize) -> Self {
+        if let WidgetKind::Slider { fine_step, .. } = &mut self.kind {
+            *fine_step = new_fine_step;
+        }
+        self
+    }
+
+    pub fn choice(id: WidgetId, label: &str, options: &[&str]) -> Self {
+        let options = options.iter().map(|option| String::from(*option)).collect();
+        Self { id, label: String::from(label), kind: WidgetKind::Choice { options, selected: 0 } }
+    }
+
+    pub fn key_capture(id: WidgetId, label: &str) -> Self {
+        Self { id, label: String::from(label), kind: WidgetKind::KeyCapture(None) }
+    }
+
+    /// Whether the widget shows a value after its label
+    pub fn has_value(&self) -> bool {
+        !matches!(self.kind, WidgetKind::Button)
+    }
+}
+
+/// What happened to a WidgetList after a menu action
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WidgetEvent {
+    // The focus moved to another widget
+    FocusMoved,
+    // The action didn't do anything (the end of the list, the end of a
+    // slider, or an action the focused widget doesn't take)
+    Rejected,
+    // A button was confirmed, or a widget that doesn't react to being
+    // confirmed on its own
+    Pressed(WidgetId),
+    // A toggle was flipped to the new state
+    Toggled(WidgetId, bool),
+    // A slider moved to the new value, or a choice to the option with the
+    // new index
+    Changed(WidgetId, usize),
+    // Select was pressed on a widget
+    Alternate(WidgetId),
+    // A key capture widget started waiting for a binding
+    Capturing(WidgetId),
+    // The player wants to leave the page
+    Back,
+}
+
+pub struct WidgetList {
+    pub title: String,
+    // Help text drawn under the widgets
+    pub hint: Option<String>,
+    widgets: Vec<Widget>,
+    focus: usize,
+    // Whether the focused key capture widget is waiting for a binding
+    capturing: bool,
+}
+
+impl WidgetList {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: String::from(title),
+            hint: None,
+            widgets: Vec::new(),
+            focus: 0,
+            capturing: false,
+        }
+    }
+
+    pub fn with(mut self, widget: Widget) -> Self {
+        self.widgets.push(widget);
+        self
+    }
+
+    pub fn with_hint(mut self, hint: &str) -> Self {
+        self.hint = Some(String::from(hint));
+        self
+    }
+
+    pub fn widgets(&self) -> &[Widget] {
+        &self.widgets
+    }
+
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    pub fn focused(&self) -> &Widget {
+        &self.widgets[self.focus]
+    }
+
+    /// Moves the focus to the first widget
+    pub fn reset_focus(&mut self) {
+        self.focus = 0;
+        self.capturing = false;
+    }
+
+    pub fn capturing(&self) -> bool {
+        self.capturing
+    }
+
+    /// Makes the list take a binding for every key capture widget, one after
+    /// the other, starting from the first widget
+    pub fn start_capture(&mut self) {
+        self.reset_focus();
+        self.capturing = true;
+    }
+
+    /// Gives the focused widget its binding and moves on to the next one.
+    /// Returns false once there's nothing left to capture.
+    pub fn capture(&mut self, binding: Option<String>) -> bool {
+        if !self.capturing {
+            return false;
+        }
+
+        if let WidgetKind::KeyCapture(bound) = &mut self.widgets[self.focus].kind {
+            *bound = binding;
+        }
+
+        if self.focus + 1 < self.widgets.len() {
+            self.focus += 1;
+        } else {
+            self.capturing = false;
+        }
+
+        self.capturing
+    }
+
+    pub fn cancel_capture(&mut self) {
+        self.capturing = false;
+    }
+
+    pub fn set_toggle(&mut self, id: WidgetId, on: bool) {
+        if let Some(WidgetKind::Toggle(state)) = self.kind_mut(id) {
+            *state = on;
+        }
+    }
+
+    pub fn set_value(&mut self, id: WidgetId, new_value: usize) {
+        match self.kind_mut(id) {
+            Some(WidgetKind::Slider { value, min, max, .. }) => *value = new_value.clamp(*min, *max),
+            Some(WidgetKind::Choice { options, selected }) => *selected = new_value.min(options.len() - 1),
+            _ => {}
+        }
+    }
+
+    pub fn set_binding(&mut self, id: WidgetId, binding: Option<String>) {
+        if let Some(WidgetKind::KeyCapture(bound)) = self.kind_mut(id) {
+            *bound = binding;
+        }
+    }
+
+    /// Applies a menu action to the list
+    pub fn handle(&mut self, action: MenuAction) -> WidgetEvent {
+        self.handle_action(action, false)
+    }
+
+    /// Applies a menu action to the list, moving sliders by their fine step
+    pub fn handle_fine(&mut self, action: MenuAction) -> WidgetEvent {
+        self.handle_action(action, true)
+    }
+
+    fn handle_action(&mut self, action: MenuAction, fine: bool) -> WidgetEvent {
+        match action {
+            MenuAction::CursorPrev => self.move_focus(false, false),
+            MenuAction::CursorNext => self.move_focus(true, false),
+            MenuAction::CursorNextWrap => self.move_focus(true, true),
+
+            MenuAction::Decrease => self.step_slider(false, fine),
+            MenuAction::Increase => self.step_slider(true, fine),
+
+            MenuAction::Confirm => {
+                let id = self.focused().id;
+
+                match &mut self.widgets[self.focus].kind {
+                    WidgetKind::Toggle(on) => {
+                        *on = !*on;
+                        WidgetEvent::Toggled(id, *on)
+                    }
+                    WidgetKind::KeyCapture(_) => {
+                        self.capturing = true;
+                        WidgetEvent::Capturing(id)
+                    }
+                    WidgetKind::Button | WidgetKind::Slider { .. } | WidgetKind::Choice { .. } => WidgetEvent::Pressed(id),
+                }
+            }
+
+            MenuAction::Alternate => WidgetEvent::Alternate(self.focused().id),
+            MenuAction::Back => WidgetEvent::Back,
+            MenuAction::Reject => WidgetEvent::Rejected,
+        }
+    }
+
+    fn move_focus(&mut self, forward: bool, wrap: bool) -> WidgetEvent {
+        let last = self.widgets.len().saturating_sub(1);
+
+        let new_focus = match (forward, self.focus) {
+            (true, focus) if focus < last => focus + 1,
+            (true, _) if wrap => 0,
+            (false, focus) if focus > 0 => focus - 1,
+            (false, _) if wrap => last,
+            (_, focus) => focus,
+        };
+
+        if new_focus == self.focus {
+            return WidgetEvent::Rejected;
+        }
+
+        self.focus = new_focus;
+        WidgetEvent::FocusMoved
+    }
+
+    fn step_slider(&mut self, up: bool, fine: bool) -> WidgetEvent {
+        let id = self.focused().id;
+
+        if let WidgetKind::Choice { options, selected } = &mut self.widgets[self.focus].kind {
+            let new_selected = if up { (*selected + 1).min(options.len() - 1) } else { selected.saturating_sub(1) };
+
+            if new_selected == *selected {
+                return WidgetEvent::Rejected;
+            }
+
+            *selected = new_selected;
+            return WidgetEvent::Changed(id, new_selected);
+        }
+
+        let WidgetKind::Slider { value, min, max, step, fine_step, .. } = &mut self.widgets[self.focus].kind else {
+            return WidgetEvent::Rejected;
+        };
+        let step = if fine { fine_step } else { step };
+
+        let new_value = if up {
+            (*value + *step).min(*max)
+        } else {
+            value.saturating_sub(*step).max(*min)
+        };
+
+        if new_value == *value {
+            return WidgetEvent::Rejected;
+        }
+
+        *value = new_value;
+        WidgetEvent::Changed(id, new_value)
+    }
+
+    fn kind_mut(&mut self, id: WidgetId) -> Option<&mut WidgetKind> {
+        self.widgets.iter_mut()
+            .find(|widget| widget.id == id)
+            .map(|widget| &mut widget.kind)
+    }
+}
@@ -0,0 +1,319 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    cartridge::{cartridge::Cartridge, hash, patch},
+    headless::headless_nes,
+    system::nes::Nes,
+    RuntimeConfig,
+};
+
+/*
+ON HOW BATCH COMPATIBILITY REPORTS WORK:
+
+The batch command takes a folder instead of a ROM, and runs every .nes file (or
+zipped one) in it headlessly (no input, the same options as headless) for a
+few hundred frames, sorting each one into one of these:
+
+    ok           ran the whole time and put something on screen
+    blank        ran the whole time, but the last frame is one flat color
+    jammed       the CPU hit a JAM opcode
+    crashed      the emulator panicked
+    unsupported  the ROM needs a mapper that isn't implemented
+    unloadable   the file isn't a ROM we can read at all
+
+A blank screen is spotted by how much the brightness of the last frame varies:
+a game that's running shows a title screen or a level by then, while one that
+has silently gone wrong tends to sit on black (or on whatever color it cleared
+the palette to). Some games really do fade out on their own, so blank is a
+hint worth a look rather than a sure failure.
+
+The results go to stdout, with how many games of each mapper came out ok, and
+to a report file with --report: a CSV (one row per ROM) or an HTML page with
+the same table colored by result and the mapper coverage underneath, picked by
+the file's extension.
+*/
+
+// How long each ROM runs when no frame count is given (5 seconds of NES time)
+const DEFAULT_BATCH_FRAMES: usize = 300;
+
+// The most the brightness (0-255) of a frame's pixels can vary by for it to
+// count as blank
+const BLANK_VARIANCE: f64 = 4.0;
+
+/// How running a ROM went (see "ON HOW BATCH COMPATIBILITY REPORTS WORK")
+#[derive(Clone, PartialEq, Debug)]
+pub enum Outcome {
+    Ok,
+    Blank,
+    // The frame and address it jammed at
+    Jammed(usize, u16),
+    // The frame it panicked on, and the panic message
+    Crashed(usize, String),
+    UnsupportedMapper,
+    Unloadable(String),
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Blank => "blank",
+            Outcome::Jammed(..) => "jammed",
+            Outcome::Crashed(..) => "crashed",
+            Outcome::UnsupportedMapper => "unsupported",
+            Outcome::Unloadable(_) => "unloadable",
+        }
+    }
+
+    fn details(&self) -> String {
+        match self {
+            Outcome::Ok | Outcome::Blank | Outcome::UnsupportedMapper => String::new(),
+            Outcome::Jammed(frame, address) => format!("CPU jammed at ${address:04X} on frame {frame}"),
+            Outcome::Crashed(frame, message) => format!("panicked on frame {frame}: {message}"),
+            Outcome::Unloadable(e) => e.clone(),
+        }
+    }
+
+    // The colors a result is shown in on the HTML report
+    fn html_color(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "#c8f0c8",
+            Outcome::Blank => "#f0e8b0",
+            Outcome::UnsupportedMapper => "#d8d8d8",
+            Outcome::Jammed(..) | Outcome::Crashed(..) | Outcome::Unloadable(_) => "#f0c0c0",
+        }
+    }
+}
+
+/// One row of the report
+pub struct RomResult {
+    pub file: String,
+    // None when the header couldn't be read
+    pub mapper: Option<u16>,
+    pub crc32: u32,
+    pub outcome: Outcome,
+}
+
+/// Runs every ROM in the configured folder and reports how each one did.
+/// Returns an error only if the folder or report couldn't be read or written,
+/// not if games failed.
+pub fn run_batch(config: &RuntimeConfig) -> Result<(), String> {
+    let roms = rom_files(Path::new(&config.cart_path))?;
+    let frames = config.frames.unwrap_or(DEFAULT_BATCH_FRAMES);
+
+    println!("Running {} ROMs for {frames} frames each", roms.len());
+
+    let results: Vec<RomResult> = roms.iter()
+        .map(|path| {
+            let result = run_rom(config, path, frames);
+            println!("{:<12}{}", result.outcome.name(), result.file);
+            result
+        })
+        .collect();
+
+    print_coverage(&results);
+
+    match &config.report_path {
+        Some(path) if path.ends_with(".csv") => save_report(path, &csv_report(&results))?,
+        Some(path) if path.ends_with(".html") || path.ends_with(".htm") => save_report(path, &html_report(&results))?,
+        Some(path) => return Err(format!("Unknown report type '{path}' (expected a .csv or .html file)")),
+        None => {}
+    }
+
+    Ok(())
+}
+
+// The .nes files (and zips) in a folder, sorted by name
+fn rom_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("Couldn't read ROM folder '{}': {e}", dir.display()))?;
+
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("nes") || ext.eq_ignore_ascii_case("zip")))
+        .collect();
+    roms.sort();
+
+    Ok(roms)
+}
+
+fn run_rom(config: &RuntimeConfig, path: &Path, frames: usize) -> RomResult {
+    let file = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut result = RomResult { file, mapper: None, crc32: 0, outcome: Outcome::Ok };
+
+    let cart_path = path.to_string_lossy().into_owned();
+
+    // Read the same way the NES will, out of a zip and with any patch
+    // next to it applied
+    let data = match patch::read_patched_rom(&cart_path, None) {
+        Ok((data, _)) => data,
+        Err(e) => {
+            result.outcome = Outcome::Unloadable(e);
+            return result;
+        }
+    };
+    match Cartridge::from_bytes(&data) {
+        Ok(cart) => {
+            result.mapper = Some(cart.info().mapper_num);
+            result.crc32 = hash::crc32(&data[Cartridge::HEADER_LEN..]);
+        }
+        Err(e) => {
+            result.outcome = Outcome::Unloadable(e);
+            return result;
+        }
+    }
+
+    let rom_config = RuntimeConfig { cart_path, ..config.clone() };
+    let mut nes = match headless_nes(&rom_config) {
+        Ok(nes) => nes,
+        Err(e) if e.starts_with("Unimplemented mapper") => {
+            result.outcome = Outcome::UnsupportedMapper;
+            return result;
+        }
+        Err(e) => {
+            result.outcome = Outcome::Unloadable(e);
+            return result;
+        }
+    };
+
+    result.outcome = run_frames(&mut nes, frames);
+
+    result
+}
+
+// Runs the NES until it's done all its frames, jams or panics
+fn run_frames(nes: &mut Nes, frames: usize) -> Outcome {
+    for frame in 0..frames {
+        let ran = panic::catch_unwind(AssertUnwindSafe(|| {
+            nes.cycle_until_frame();
+            nes.swap_screen_buffers();
+        }));
+
+        if let Err(payload) = ran {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+
+            return Outcome::Crashed(frame, message);
+        }
+
+        if let Some(address) = nes.cpu_jammed_at() {
+            return Outcome::Jammed(frame, address);
+        }
+    }
+
+    if brightness_variance(nes.screen_buf_slice()) < BLANK_VARIANCE {
+        Outcome::Blank
+    } else {
+        Outcome::Ok
+    }
+}
+
+// How much the brightness of an RGBA frame's pixels varies
+fn brightness_variance(frame: &[u8]) -> f64 {
+    let brightness: Vec<f64> = frame.chunks_exact(4)
+        .map(|px| 0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64)
+        .collect();
+
+    let count = brightness.len().max(1) as f64;
+    let mean = brightness.iter().sum::<f64>() / count;
+
+    brightness.iter().map(|b| (b - mean) * (b - mean)).sum::<f64>() / count
+}
+
+// Each mapper, with how many of its games came out ok and how many there were
+fn mapper_coverage(results: &[RomResult]) -> Vec<(u16, usize, usize)> {
+    let mut coverage: Vec<(u16, usize, usize)> = Vec::new();
+
+    for result in results {
+        let Some(mapper) = result.mapper else {
+            continue;
+        };
+
+        let ok = (result.outcome == Outcome::Ok) as usize;
+        match coverage.iter_mut().find(|(num, _, _)| *num == mapper) {
+            Some((_, ok_count, total)) => {
+                *ok_count += ok;
+                *total += 1;
+            }
+            None => coverage.push((mapper, ok, 1)),
+        }
+    }
+
+    coverage.sort();
+    coverage
+}
+
+fn print_coverage(results: &[RomResult]) {
+    let ok = results.iter().filter(|result| result.outcome == Outcome::Ok).count();
+    println!("{ok}/{} ROMs ok", results.len());
+
+    for (mapper, ok, total) in mapper_coverage(results) {
+        println!("  mapper {mapper:>3}: {ok}/{total} ok");
+    }
+}
+
+fn csv_report(results: &[RomResult]) -> String {
+    let mut csv = String::from("rom,mapper,crc32,result,details\n");
+
+    for result in results {
+        csv.push_str(&format!("{},{},{:08X},{},{}\n",
+            csv_field(&result.file),
+            result.mapper.map(|num| num.to_string()).unwrap_or_default(),
+            result.crc32,
+            result.outcome.name(),
+            csv_field(&result.outcome.details())));
+    }
+
+    csv
+}
+
+// Quotes a CSV field if it has anything in it that would break the row
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        String::from(s)
+    }
+}
+
+fn html_report(results: &[RomResult]) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+        <title>Nemulator compatibility report</title>\n\
+        <style>body { font-family: sans-serif; } td, th { padding: 2px 8px; text-align: left; }</style>\n\
+        </head>\n<body>\n<h1>Compatibility report</h1>\n");
+
+    html.push_str("<table>\n<tr><th>ROM</th><th>Mapper</th><th>CRC32</th><th>Result</th><th>Details</th></tr>\n");
+    for result in results {
+        html.push_str(&format!("<tr style=\"background: {}\"><td>{}</td><td>{}</td><td>{:08X}</td><td>{}</td><td>{}</td></tr>\n",
+            result.outcome.html_color(),
+            html_escape(&result.file),
+            result.mapper.map(|num| num.to_string()).unwrap_or_default(),
+            result.crc32,
+            result.outcome.name(),
+            html_escape(&result.outcome.details())));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Mapper coverage</h2>\n<table>\n<tr><th>Mapper</th><th>Ok</th><th>ROMs</th></tr>\n");
+    for (mapper, ok, total) in mapper_coverage(results) {
+        html.push_str(&format!("<tr><td>{mapper}</td><td>{ok}</td><td>{total}</td></tr>\n"));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+
+    html
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn save_report(path: &str, report: &str) -> Result<(), String> {
+    fs::write(path, report).map_err(|e| format!("Couldn't write report '{path}': {e}"))?;
+
+    println!("Saved report to {path}");
+
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use std::{fs, path::Path};
+
+use super::hash;
+
+/*
+ON HOW ROMS ARE READ FROM ARCHIVES:
+
+ROM sets are usually kept zipped, one game per .zip. Any path given to load a
+game can point at a .zip instead of a .nes, and the .nes file inside it is
+unzipped in memory (nothing is written to disk). When an archive holds more
+than one .nes file, the one to load is picked by adding its name after a '#':
+
+    games.zip#Super Game (USA).nes
+
+and loading the archive without one lists what's in it. Entries are found
+through the zip's central directory, and can be stored or deflated, which is
+everything zip tools make by default. Zip64 archives (for files over 4GiB) and
+encrypted entries aren't supported, no NES game needs them.
+https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+
+7-Zip archives are recognised, but not unpacked: they're LZMA compressed, which
+is a lot of decoder for the few sets that use it, so they're turned away with
+a message asking for the ROM to be extracted first.
+*/
+
+// Separates an archive's path from the name of the ROM to load from it
+const ENTRY_SEPARATOR: char = '#';
+
+const ZIP_LOCAL_HEADER_SIG: u32 = 0x0403_4B50;
+const ZIP_CENTRAL_HEADER_SIG: u32 = 0x0201_4B50;
+const ZIP_END_SIG: u32 = 0x0605_4B50;
+// The end of central directory record, without its comment, and the longest
+// the comment can be
+const ZIP_END_LEN: usize = 22;
+const ZIP_MAX_COMMENT_LEN: usize = 0xFFFF;
+const ZIP_LOCAL_HEADER_LEN: usize = 30;
+const ZIP_CENTRAL_HEADER_LEN: usize = 46;
+
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+const ZIP_ENCRYPTED_FLAG: u16 = 1;
+
+const SEVEN_ZIP_MAGIC: &[u8] = &[b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C];
+
+// Biggest ROM unzipped, so a corrupt or malicious archive can't take all the
+// memory there is
+const MAX_ROM_SIZE: usize = 16 * 1024 * 1024;
+
+/// Reads the bytes of the .nes file at a path, unzipping it first if the path
+/// is to an archive (see "ON HOW ROMS ARE READ FROM ARCHIVES")
+pub fn read_rom(cart_path: &str) -> Result<Vec<u8>, String> {
+    let (file_path, entry) = split_path(cart_path);
+
+    let data = fs::read(file_path)
+        .map_err(|e| format!("Could not read cartridge '{file_path}': {e}"))?;
+
+    if data.starts_with(SEVEN_ZIP_MAGIC) {
+        return Err(format!("'{file_path}' is a 7z archive, which can't be opened yet (extract the ROM, or zip it)"));
+    }
+    if !data.starts_with(&ZIP_LOCAL_HEADER_SIG.to_le_bytes()) {
+        return match entry {
+            Some(_) => Err(format!("'{file_path}' isn't a zip archive")),
+            None => Ok(data),
+        };
+    }
+
+    let entries = zip_entries(&data).map_err(|e| format!("Could not open '{file_path}': {e}"))?;
+    let roms: Vec<&ZipEntry> = entries.iter().filter(|entry| entry.is_rom()).collect();
+
+    let rom = match entry {
+        Some(name) => roms.iter()
+            .find(|entry| entry.name == name || entry.file_name() == name)
+            .ok_or(format!("'{file_path}' has no ROM called '{name}'"))?,
+        None => match roms.as_slice() {
+            [rom] => rom,
+            [] => return Err(format!("'{file_path}' has no .nes file in it")),
+            _ => {
+                let names: Vec<&str> = roms.iter().map(|entry| entry.name.as_str()).collect();
+                return Err(format!(
+                    "'{file_path}' has more than one ROM in it ({}), pick one with '{file_path}{ENTRY_SEPARATOR}<name>'",
+                    names.join(", ")
+                ));
+            }
+        },
+    };
+
+    rom.extract(&data).map_err(|e| format!("Could not unzip '{}' from '{file_path}': {e}", rom.name))
+}
+
+/// The name of the game at a path: the ROM's file name without its extension,
+/// whether it's on its own or in an archive
+pub fn rom_name(cart_path: &str) -> String {
+    let (file_path, entry) = split_path(cart_path);
+
+    Path::new(entry.unwrap_or(file_path))
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The path of the file itself, without any archive entry name
+pub fn file_path(cart_path: &str) -> &str {
+    split_path(cart_path).0
+}
+
+// Splits "games.zip#game.nes" into the archive and the entry. A '#' only
+// counts when what's before it is a file, since ROM names can have one in them.
+fn split_path(cart_path: &str) -> (&str, Option<&str>) {
+    match cart_path.rsplit_once(ENTRY_SEPARATOR) {
+        Some((file_path, entry)) if !Path::new(cart_path).is_file() && Path::new(file_path).is_file() => {
+            (file_path, Some(entry))
+        }
+        _ => (cart_path, None),
+    }
+}
+
+// A file in a zip, from the central directory
+struct ZipEntry {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc32: u32,
+    compressed_size: usize,
+    size: usize,
+    local_header_offset: usize,
+}
+
+impl ZipEntry {
+    fn is_rom(&self) -> bool {
+        !self.name.ends_with('/') && self.name.to_lowercase().ends_with(".nes")
+    }
+
+    // The name without any folders it's in
+    fn file_name(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+
+    fn extract(&self, zip: &[u8]) -> Result<Vec<u8>, String> {
+        if self.flags & ZIP_ENCRYPTED_FLAG != 0 {
+            return Err(String::from("it's encrypted"));
+        }
+        if self.size > MAX_ROM_SIZE {
+            return Err(format!("it's too big to be a ROM ({} bytes)", self.size));
+        }
+
+        // The data comes after the local header, which has its own copy of
+        // the name and extra field (and they can differ from the central
+        // directory's)
+        let header = self.local_header_offset;
+        if read_u32(zip, header)? != ZIP_LOCAL_HEADER_SIG {
+            return Err(String::from("its header is missing"));
+        }
+        let start = header + ZIP_LOCAL_HEADER_LEN + read_u16(zip, header + 26)? as usize + read_u16(zip, header + 28)? as usize;
+        let compressed = zip.get(start..start + self.compressed_size).ok_or("the archive is cut short")?;
+
+        let data = match self.method {
+            ZIP_STORED => compressed.to_vec(),
+            ZIP_DEFLATED => miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, MAX_ROM_SIZE)
+                .map_err(|e| format!("it's corrupt ({e})"))?,
+            method => return Err(format!("it's compressed with a method that isn't supported ({method})")),
+        };
+
+        if data.len() != self.size || hash::crc32(&data) != self.crc32 {
+            return Err(String::from("it's corrupt (its checksum doesn't match)"));
+        }
+
+        Ok(data)
+    }
+}
+
+// Reads the list of files from a zip's central directory
+fn zip_entries(zip: &[u8]) -> Result<Vec<ZipEntry>, String> {
+    // The end of central directory record is the last thing in the file,
+    // followed only by a comment
+    let search_start = zip.len().saturating_sub(ZIP_END_LEN + ZIP_MAX_COMMENT_LEN);
+    let end = (search_start..=zip.len().saturating_sub(ZIP_END_LEN)).rev()
+        .find(|&i| read_u32(zip, i) == Ok(ZIP_END_SIG))
+        .ok_or("it isn't a zip archive, or it's cut short")?;
+
+    let count = read_u16(zip, end + 10)? as usize;
+    let mut offset = read_u32(zip, end + 16)? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if read_u32(zip, offset)? != ZIP_CENTRAL_HEADER_SIG {
+            return Err(String::from("its list of files is corrupt"));
+        }
+
+        let name_len = read_u16(zip, offset + 28)? as usize;
+        let extra_len = read_u16(zip, offset + 30)? as usize;
+        let comment_len = read_u16(zip, offset + 32)? as usize;
+        let name_start = offset + ZIP_CENTRAL_HEADER_LEN;
+        let name = zip.get(name_start..name_start + name_len).ok_or("its list of files is cut short")?;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags: read_u16(zip, offset + 8)?,
+            method: read_u16(zip, offset + 10)?,
+            crc32: read_u32(zip, offset + 16)?,
+            compressed_size: read_u32(zip, offset + 20)? as usize,
+            size: read_u32(zip, offset + 24)? as usize,
+            local_header_offset: read_u32(zip, offset + 42)? as usize,
+        });
+
+        offset = name_start + name_len + extra_len + comment_len;
+    }
+
+    Ok(entries)
+}
+
+// Zips are little endian throughout
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, String> {
+    let bytes = data.get(offset..offset + 2).ok_or("the archive is cut short")?;
+
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    let bytes = data.get(offset..offset + 4).ok_or("the archive is cut short")?;
+
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
@@ -1,3 +1,5 @@
+use super::mapper::NametableMirror;
+
 
 
 
@@ -35,7 +37,7 @@ pub struct Header {
     pub alt_nametables: bool,
     /// Whether the cartridge includes trainer data. Unused in this emulator
     pub has_trainer: bool, // shouldn't matter for our purposes
-    /// Whether there is a battery present. Unused for this emulator
+    /// Whether there is a battery present, keeping PRG RAM between sessions
     pub battery_present: bool,
     /// Determines the kind of mirroring the cartridge has for nametables. See
     /// https://www.nesdev.org/wiki/PPU_nametables for more information.
@@ -174,18 +176,20 @@ impl Cartridge {
         const PRG_ROM_BANK_SIZE: usize = 0x4000; // 16KiB
 
         let prg_rom_start = 0x10 + if header.has_trainer { 0x200 } else { 0 };
-        let prg_rom_banks = Cartridge::rom_size(header.prg_rom_size);
-        let prg_rom_end = prg_rom_start + prg_rom_banks * PRG_ROM_BANK_SIZE;
+        let prg_rom_end = Cartridge::rom_end(prg_rom_start, header.prg_rom_size, PRG_ROM_BANK_SIZE, "PRG", data.len())?;
         
         let prg_rom = if header.prg_rom_size > 0 {
             data[prg_rom_start..prg_rom_end].to_vec()
         } else {
             vec![0; PRG_ROM_BANK_SIZE] // If no prg rom allocated by program, give cpu 1 bank so it doesn't freak out
         };
+        // The banks actually there, so mappers never work out a last bank
+        // from 0 of them
+        let prg_rom_banks = prg_rom.len() / PRG_ROM_BANK_SIZE;
 
         let chr_rom_start = prg_rom_end;
         let chr_rom_banks = Cartridge::rom_size(header.chr_rom_size);
-        let chr_rom_end = chr_rom_start + chr_rom_banks * CHR_ROM_BANK_SIZE;
+        let chr_rom_end = Cartridge::rom_end(chr_rom_start, header.chr_rom_size, CHR_ROM_BANK_SIZE, "CHR", data.len())?;
 
         let chr_rom = if header.chr_rom_size > 0 {
             data[chr_rom_start..chr_rom_end].to_vec()
@@ -213,6 +217,22 @@ impl Cartridge {
         })
     }
 
+    // Where a ROM that starts at an offset in the file ends, given its size
+    // from the header. Fails if the file is too short to hold all of it (a bad
+    // dump, or a header with the wrong size), rather than reading past the end.
+    fn rom_end(start: usize, rom_size: u16, bank_size: usize, what: &str, file_len: usize) -> Result<usize, String> {
+        let len = Cartridge::rom_size(rom_size).checked_mul(bank_size);
+
+        match len.and_then(|len| start.checked_add(len)) {
+            Some(end) if end <= file_len => Ok(end),
+            _ => Err(format!(
+                "Cartridge file is cut short: the header says it has {} banks of {what} ROM, but the file ends {} bytes in",
+                Cartridge::rom_size(rom_size),
+                file_len
+            )),
+        }
+    }
+
     /// Translates from the prg/chr ROM size specified by the header to the
     /// actual number of bytes to read in from the cart file.
     pub fn rom_size(rom_size_bytes: u16) -> usize {
@@ -220,7 +240,9 @@ impl Cartridge {
             let mm = (rom_size_bytes & 0x3) as usize;
             let exp = (rom_size_bytes & 0xFC) >> 2;
 
-            return (1 << exp) * (2 * mm + 1);
+            // Saturates rather than overflowing on a nonsense size, which the
+            // file then won't be long enough for
+            return 1usize.checked_shl(exp as u32).map_or(usize::MAX, |size| size.saturating_mul(2 * mm + 1));
         }
 
         rom_size_bytes as usize
@@ -241,4 +263,82 @@ impl Cartridge {
     pub fn chr_rom_banks(&self) -> usize {
         self.chr_rom_banks
     }
+
+    /// Total PRG RAM size (battery backed or not) given by an NES 2.0 header,
+    /// in bytes. iNES headers don't say, so None is returned for them.
+    /// https://www.nesdev.org/wiki/NES_2.0#PRG-(NV)RAM/EEPROM
+    pub fn prg_ram_size(&self) -> Option<usize> {
+        if self.format != CartFormat::V2NES {
+            return None;
+        }
+
+        let shift_to_size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+
+        Some(shift_to_size(self.header.prg_ram_shift) + shift_to_size(self.header.prg_nv_ram_shift))
+    }
+
+    /// Sums up what the header says about the cartridge
+    pub fn info(&self) -> CartInfo {
+        let nes2 = self.format == CartFormat::V2NES;
+
+        let mirroring = if self.header.alt_nametables {
+            NametableMirror::FourScreen
+        } else if self.header.hardwired_nametable {
+            NametableMirror::Vertical
+        } else {
+            NametableMirror::Horizontal
+        };
+
+        let region = nes2.then_some(match self.header.timing_mode {
+            0 => CartRegion::Ntsc,
+            1 => CartRegion::Pal,
+            2 => CartRegion::MultiRegion,
+            _ => CartRegion::Dendy,
+        });
+
+        CartInfo {
+            nes2,
+            mapper_num: self.header.mapper_num,
+            submapper_num: nes2.then_some(self.header.submapper_num),
+            prg_rom_size: if self.header.prg_rom_size > 0 { self.prg_rom.len() } else { 0 },
+            chr_rom_size: if self.header.chr_rom_size > 0 { self.chr_rom.len() } else { 0 },
+            prg_ram_size: self.prg_ram_size(),
+            mirroring,
+            battery: self.header.battery_present,
+            trainer: self.header.has_trainer,
+            region,
+        }
+    }
+}
+
+/// The console (and TV system) a game was made for, from an NES 2.0 header
+/// https://www.nesdev.org/wiki/NES_2.0#CPU/PPU_Timing
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum CartRegion {
+    Ntsc,
+    Pal,
+    // Works on either
+    MultiRegion,
+    Dendy,
+}
+
+/// What a cartridge's header says about it. The cartridge itself goes to its
+/// mapper when it's loaded, so this is what's kept for showing to the player.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CartInfo {
+    // NES 2.0 header, as opposed to iNES
+    pub nes2: bool,
+    pub mapper_num: u16,
+    // Only NES 2.0 headers have these
+    pub submapper_num: Option<u8>,
+    pub prg_ram_size: Option<usize>,
+    pub region: Option<CartRegion>,
+    // In bytes. A CHR ROM size of 0 means the cart has CHR RAM instead.
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    // The mirroring the header asks for. Mappers that switch mirroring
+    // themselves (like MMC1 and MMC3) ignore it.
+    pub mirroring: NametableMirror,
+    pub battery: bool,
+    pub trainer: bool,
 }
@@ -0,0 +1,191 @@
+use serde::Deserialize;
+
+/*
+ON THE COMPATIBILITY DATABASE:
+
+Some games are known not to work right, and it saves everyone time when the
+emulator says so up front instead of leaving the player to wonder if they did
+something wrong. The compatibility database lists those games and what's wrong
+with them, and the app shows the notes for a game when it's loaded.
+
+The database is a TOML file built into the app (src/app/assets/
+compatibility.toml, which can be swapped out with --assets like the other
+assets). Each game is a [[game]] table:
+
+    [[game]]
+    crc32 = "3FE272FB"
+    name = "Some Game (USA)"
+    status = "major-issues"
+    notes = [
+        "Requires MMC5: unsupported",
+        "Minor audio glitches",
+    ]
+
+    crc32   the CRC-32 of the ROM without its header, the same one the pause
+            menu's Cartridge Info page shows and the per-game settings go by
+    name    only for whoever's reading the file
+    status  how well it runs: "playable", "minor-issues", "major-issues" or
+            "unplayable"
+    notes   what's wrong, a short line each
+
+It's read with the toml crate, so anything that's valid TOML is fine, but a
+key the database doesn't know about is an error (with the line it's on), so a
+typo in a contribution doesn't go unnoticed.
+https://toml.io/en/v1.0.0
+*/
+
+/// How well a game runs
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompatStatus {
+    Playable,
+    MinorIssues,
+    MajorIssues,
+    Unplayable,
+}
+
+impl CompatStatus {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "playable" => Some(Self::Playable),
+            "minor-issues" => Some(Self::MinorIssues),
+            "major-issues" => Some(Self::MajorIssues),
+            "unplayable" => Some(Self::Unplayable),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Playable => "playable",
+            Self::MinorIssues => "minor-issues",
+            Self::MajorIssues => "major-issues",
+            Self::Unplayable => "unplayable",
+        }
+    }
+}
+
+/// What the database says about one game (see "ON THE COMPATIBILITY
+/// DATABASE")
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CompatEntry {
+    pub crc32: u32,
+    pub name: String,
+    pub status: CompatStatus,
+    pub notes: Vec<String>,
+}
+
+/// The known issues of every game in the database
+#[derive(Clone, Default, Debug)]
+pub struct CompatDatabase {
+    entries: Vec<CompatEntry>,
+}
+
+// The file as it's written, before the CRCs are read
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DatabaseFile {
+    #[serde(default)]
+    game: Vec<GameTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GameTable {
+    crc32: String,
+    #[serde(default)]
+    name: String,
+    status: CompatStatus,
+    #[serde(default)]
+    notes: Vec<String>,
+}
+
+impl CompatDatabase {
+    /// Reads the database from the text of its TOML file
+    pub fn parse(toml: &str) -> Result<Self, String> {
+        let file: DatabaseFile = toml::from_str(toml).map_err(|e| e.to_string())?;
+
+        let entries = file.game.into_iter().map(|game| {
+            let crc32 = u32::from_str_radix(&game.crc32, 16)
+                .map_err(|_| format!("'{}' isn't a CRC-32 in hex", game.crc32))?;
+
+            Ok(CompatEntry { crc32, name: game.name, status: game.status, notes: game.notes })
+        }).collect::<Result<_, String>>()?;
+
+        Ok(Self { entries })
+    }
+
+    /// What the database says about the ROM with the given CRC-32 (of the ROM
+    /// without its header), if it's in there
+    pub fn get(&self, crc32: u32) -> Option<&CompatEntry> {
+        self.entries.iter().find(|entry| entry.crc32 == crc32)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_are_found_by_crc() {
+        let db = CompatDatabase::parse(r#"
+            # A comment
+            [[game]]
+            crc32 = "3FE272FB"
+            name = "Some Game # 2"  # with a comment after
+            status = "major-issues"
+            notes = [
+                "Requires MMC5: unsupported",
+                "Minor \"audio\" glitches",
+            ]
+
+            [[game]]
+            crc32 = "00000001"
+            status = "playable"
+        "#).unwrap();
+
+        assert_eq!(db.len(), 2);
+        assert_eq!(db.get(0x3FE272FB), Some(&CompatEntry {
+            crc32: 0x3FE272FB,
+            name: String::from("Some Game # 2"),
+            status: CompatStatus::MajorIssues,
+            notes: vec![String::from("Requires MMC5: unsupported"), String::from("Minor \"audio\" glitches")],
+        }));
+        assert!(db.get(1).unwrap().notes.is_empty());
+        assert_eq!(db.get(2), None);
+    }
+
+    #[test]
+    fn any_toml_will_do() {
+        let db = CompatDatabase::parse(r#"
+            game = [
+                { crc32 = '0000000A', status = "minor-issues", notes = ['Literal \strings'] },
+            ]
+        "#).unwrap();
+
+        assert_eq!(db.get(0xA).unwrap().notes, vec![String::from("Literal \\strings")]);
+    }
+
+    #[test]
+    fn mistakes_say_where_they_are() {
+        let parse_err = |toml: &str| CompatDatabase::parse(toml).unwrap_err();
+
+        assert!(parse_err("[[game]]\ncrc32 = \"00000001\"\nstatus = \"fine\"").contains("line 3"));
+        assert!(parse_err("[[game]]\nstatus = \"playable\"").contains("crc32"));
+        assert!(parse_err("[[game]]\ncrc32 = \"00000001\"\nstatus = \"playable\"\ncrc = \"1\"").contains("unknown field `crc`"));
+        assert!(parse_err("[[game]]\ncrc32 = \"nope\"\nstatus = \"playable\"").contains("isn't a CRC-32"));
+    }
+
+    #[test]
+    fn the_built_in_database_reads() {
+        CompatDatabase::parse(include_str!("../app/assets/compatibility.toml")).unwrap();
+    }
+}
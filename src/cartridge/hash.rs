@@ -0,0 +1,135 @@
+use super::cartridge::Cartridge;
+
+// Lookup table for the standard (IEEE 802.3) CRC-32, built at compile time
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+/// Computes the CRC-32 of some data. This is the checksum ROM databases (and
+/// most other emulators) use to identify games.
+/// https://www.nesdev.org/wiki/NES_2.0_XML_Database
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for byte in data {
+        crc = (crc >> 8) ^ CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize];
+    }
+
+    !crc
+}
+
+/// Computes the SHA-1 of some data. ROM databases list it next to the CRC-32,
+/// since unlike the CRC it's practically impossible for two different dumps
+/// to share one.
+/// https://en.wikipedia.org/wiki/SHA-1
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+    // The data is padded with a 1 bit, then zeros up to 8 bytes short of a
+    // whole block, then the length of the data in bits
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend(((data.len() as u64) * 8).to_be_bytes());
+
+    for block in padded.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (total, part) in h.iter_mut().zip([a, b, c, d, e]) {
+            *total = total.wrapping_add(part);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+
+    digest
+}
+
+/// The hashes that identify a ROM: of the whole thing (everything after the
+/// header, which is what the NES 2.0 database and the per-game settings go
+/// by) and of the PRG and CHR ROM on their own, which stay the same when a
+/// dump only differs in its header or trailing junk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct RomHashes {
+    pub rom_crc32: u32,
+    pub rom_sha1: [u8; 20],
+    pub prg_crc32: u32,
+    pub prg_sha1: [u8; 20],
+    // None for games with CHR RAM instead of CHR ROM
+    pub chr_crc32: Option<u32>,
+    pub chr_sha1: Option<[u8; 20]>,
+}
+
+impl RomHashes {
+    /// Hashes a ROM given the bytes of its .nes file. SHA-1 is slow enough on
+    /// big ROMs that the app does this off the main thread.
+    pub fn of_rom(data: &[u8]) -> Result<Self, String> {
+        let cart = Cartridge::from_bytes(data)?;
+        let rom = &data[Cartridge::HEADER_LEN..];
+        let prg_rom = cart.get_prg_rom();
+        let chr_rom = (cart.chr_rom_banks() > 0).then(|| cart.get_chr_rom());
+
+        Ok(Self {
+            rom_crc32: crc32(rom),
+            rom_sha1: sha1(rom),
+            prg_crc32: crc32(&prg_rom),
+            prg_sha1: sha1(&prg_rom),
+            chr_crc32: chr_rom.as_deref().map(crc32),
+            chr_sha1: chr_rom.as_deref().map(sha1),
+        })
+    }
+
+    /// Writes a SHA-1 out in hex, the way databases list them
+    pub fn sha1_hex(sha1: &[u8; 20]) -> String {
+        sha1.iter().map(|byte| format!("{byte:02X}")).collect()
+    }
+}
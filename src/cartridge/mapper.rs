@@ -4,9 +4,16 @@ use std::{rc::Rc};
 
 use crate::cartridge::mappers::{Mapper2, Mapper4, Mapper9};
 
+use crate::system::savestate::{StateReader, StateWriter};
+
 use super::mappers::{Mapper0, Mapper1, Mapper3};
 use super::{Cartridge};
 
+// Bumped whenever the layout of the mapper state block header changes. Each
+// mapper versions its own registers separately (see Mapper::state_version).
+pub const MAPPER_STATE_MAGIC: &[u8; 4] = b"MAPR";
+const MAPPER_STATE_FORMAT_VERSION: u16 = 1;
+
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub enum NametableMirror {
     #[default]
@@ -17,6 +24,39 @@ pub enum NametableMirror {
     FourScreen,
 }
 
+impl NametableMirror {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            NametableMirror::Vertical => 0,
+            NametableMirror::Horizontal => 1,
+            NametableMirror::SingleScreenLower => 2,
+            NametableMirror::SingleScreenUpper => 3,
+            NametableMirror::FourScreen => 4,
+        }
+    }
+
+    pub fn from_u8(val: u8) -> Result<Self, String> {
+        match val {
+            0 => Ok(NametableMirror::Vertical),
+            1 => Ok(NametableMirror::Horizontal),
+            2 => Ok(NametableMirror::SingleScreenLower),
+            3 => Ok(NametableMirror::SingleScreenUpper),
+            4 => Ok(NametableMirror::FourScreen),
+            _ => Err(format!("Invalid nametable mirroring type {val}")),
+        }
+    }
+}
+
+/// Where on the cartridge the CPU reading an address would read from, as
+/// resolved through the mapper's current banks (see Mapper::cpu_prg_location)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrgLocation {
+    // An offset into PRG ROM
+    Rom(usize),
+    // An offset into PRG RAM
+    Ram(usize),
+}
+
 /*
 ON HOW MAPPERS WORK:
 
@@ -57,16 +97,35 @@ pub trait Mapper {
     /// or RAM, then the memory internal to the cartridge is accessed and the
     /// data is returned. If the mapper doesn't want the address, i.e. the read
     /// is meant for somewhere else in the system, then none is returned instead. 
+    /// Unlike cpu_cart_read, this must never change the mapper's state, so the
+    /// debugger can look at memory without disturbing the game.
     ///
     ///  * `addr` - The CPU address to translate to a cartridge PRG ROM address
-    fn cpu_cart_read(&mut self, addr: u16) -> Option<u8>;
+    fn cpu_cart_peek(&self, addr: u16) -> Option<u8>;
     /// If the mapper maps the address given by the PPU to somewhere in CHR ROM
     /// or RAM, then the memory internal to the cartridge is accessed and the
     /// data is returned. If the mapper doesn't want the address, i.e. the read
     /// is meant for somewhere else in the system, then none is returned instead. 
+    /// Unlike ppu_cart_read, this must never change the mapper's state.
     ///
     ///  * `addr` - The PPU address to translate to a cartridge CHR ROM address
-    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8>;
+    fn ppu_cart_peek(&self, addr: u16) -> Option<u8>;
+    /// The same as cpu_cart_peek, for mappers where the CPU reading an address
+    /// has side effects (like latching a bank). Most mappers don't, so the
+    /// default is to just peek.
+    ///
+    ///  * `addr` - The CPU address to translate to a cartridge PRG ROM address
+    fn cpu_cart_read(&mut self, addr: u16) -> Option<u8> {
+        self.cpu_cart_peek(addr)
+    }
+    /// The same as ppu_cart_peek, for mappers where the PPU reading an address
+    /// has side effects (like MMC2's CHR latches). Most mappers don't, so the
+    /// default is to just peek.
+    ///
+    ///  * `addr` - The PPU address to translate to a cartridge CHR ROM address
+    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8> {
+        self.ppu_cart_peek(addr)
+    }
     /// If the mapper maps the address given by the CPU to somewhere in PRG RAM
     /// then the internal cartridge memory is written to. If the address is mapped
     /// to PRG ROM, then the mapper may use the data to modify its internal registers.
@@ -77,6 +136,45 @@ pub trait Mapper {
     ///  * `addr` - The PPU address to translate to a cartridge PRG ROM address
     ///  * `data` - The data being written to PRG ROM/RAM (may be used to set internal mapper register)
     fn cpu_cart_write(&mut self, addr: u16, data: u8) -> bool;
+    /// The same as cpu_cart_write, for a write on the CPU cycle right after
+    /// another write (the second write of a read-modify-write instruction).
+    /// Only mappers that can tell the difference, like MMC1, need this.
+    ///
+    ///  * `addr` - The CPU address being written to
+    ///  * `data` - The data being written
+    fn cpu_cart_write_consecutive(&mut self, addr: u16, data: u8) -> bool {
+        self.cpu_cart_write(addr, data)
+    }
+    /// Whether the board has anything at a CPU address for a write to land
+    /// in (PRG RAM or a register), for spotting games that write where there's
+    /// nothing (see telemetry). The default is PRG RAM at $6000-$7FFF and
+    /// registers all over $8000-$FFFF.
+    ///
+    ///  * `addr` - The CPU address being written to, from $4020 up
+    fn cpu_write_has_target(&self, addr: u16) -> bool {
+        (0x6000..=0xFFFF).contains(&addr)
+    }
+    /// Where the CPU reading an address would read from on the cartridge,
+    /// through the banks switched in right now, or None if the cartridge
+    /// doesn't answer reads there. Like cpu_cart_peek, this must never change
+    /// the mapper's state.
+    ///
+    ///  * `addr` - The CPU address to resolve, from $4020 up
+    fn cpu_prg_location(&self, addr: u16) -> Option<PrgLocation>;
+    /// The size of the PRG ROM banks the mapper switches between, which is
+    /// what describe_region numbers banks by. The default is 16KiB.
+    fn prg_bank_size(&self) -> usize { 0x4000 }
+    /// A short description of what's at a CPU address in cartridge space,
+    /// like "PRG-ROM bank 3" or "SRAM", for the debugger's memory view. None
+    /// if the cartridge doesn't answer reads there.
+    ///
+    ///  * `addr` - The CPU address to describe, from $4020 up
+    fn describe_region(&self, addr: u16) -> Option<String> {
+        match self.cpu_prg_location(addr)? {
+            PrgLocation::Rom(offset) => Some(format!("PRG-ROM bank {}", offset / self.prg_bank_size())),
+            PrgLocation::Ram(_) => Some(String::from("SRAM")),
+        }
+    }
     /// If the mapper maps the address given by the PPU to somewhere in CHR RAM
     /// then the internal cartridge memory is written to. In this case, true is 
     /// returned to indicate that the mapper has handled the write, otherwise 
@@ -87,20 +185,104 @@ pub trait Mapper {
     fn ppu_cart_write(&mut self, addr: u16, data: u8) -> bool;
     /// Returns the direction addresses should be mirrored.
     fn get_nt_mirror_type(&self) -> NametableMirror;
-    /// Resets the mapper to a known state. This happens whenever the NES system is reset.
-    fn reset(&mut self) {}
+    /// Called when the NES's reset button is pressed. The reset line doesn't
+    /// go to the cartridge connector, so most mappers never find out and keep
+    /// their banks, IRQ counters and PRG RAM just as they were, which is what
+    /// the default does. Boards that do notice (multicarts that switch games
+    /// on reset) override this. Power on is handled by init instead.
+    fn on_reset(&mut self) {}
     /// This function is called every time the PPU completes a scanline. Not all
     /// mappers are sensitive to scanline completions, so the default implementation
     /// is to do nothing at all.
     fn scanline_finished(&mut self) {}
+    /// This function is called every time the PPU finishes fetching the sprite
+    /// patterns for the next scanline (dot 320), which is when PPU A12 falls
+    /// back low if sprites come from $1000. Only mappers that count falling
+    /// edges of A12 care about this.
+    fn sprite_fetches_finished(&mut self) {}
     /// Checks if the mapper is currently requesting an IRQ for the CPU. Not all
     /// mappers have this capability, but MMC3 (mapper 4) does.
     fn irq_requested(&self) -> bool { false }
-    /// Called when the CPU handles a mapper IRQ
-    fn irq_handled(&mut self) {}
+    /// The iNES mapper number of this mapper.
+    fn mapper_num(&self) -> u16;
+    /// The name the mapper's chip or board family goes by, like "MMC1"
+    fn mapper_name(&self) -> &'static str;
+    /// The mapper's most useful internal registers, as (name, value) pairs for
+    /// the debugger's mapper panel. Mappers with nothing worth showing don't
+    /// need to give anything.
+    fn debug_state(&self) -> Vec<(String, String)> { Vec::new() }
+    /// The cartridge's PRG RAM, for boards that have any, so battery backed
+    /// RAM can be kept between sessions. Mappers without PRG RAM don't need
+    /// to give any.
+    fn prg_ram(&self) -> Option<&[u8]> { None }
+    /// The same as prg_ram, for putting battery backed RAM back when the
+    /// game is loaded.
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> { None }
+    /// The version of the layout written by save_state. This must be bumped
+    /// every time a mapper's save_state changes what it writes, and load_state
+    /// must keep being able to load every older version.
+    fn state_version(&self) -> u16 { 1 }
+    /// Writes all of the mapper's internal state (registers, IRQ counters,
+    /// PRG RAM, CHR RAM) needed to restore it later. ROM is not included since
+    /// it is reloaded from the cartridge.
+    fn save_state(&self, state: &mut StateWriter);
+    /// Restores internal state previously written by save_state, which may have
+    /// been written by an older version of the mapper's save_state.
+    ///
+    ///  * `version` - The state_version the state was saved with
+    ///  * `state` - Reader over the saved mapper state
+    fn load_state(&mut self, version: u16, state: &mut StateReader) -> Result<(), String>;
+}
+
+/// Serializes the mapper's state into a versioned block that can be stored in
+/// a save state.
+pub fn save_mapper_state(mapper: &dyn Mapper) -> Vec<u8> {
+    let mut payload = StateWriter::new();
+    payload.write_u16(mapper.mapper_num());
+    payload.write_u16(mapper.state_version());
+    mapper.save_state(&mut payload);
+
+    let mut block = StateWriter::new();
+    block.write_block(MAPPER_STATE_MAGIC, MAPPER_STATE_FORMAT_VERSION, &payload.into_bytes());
+
+    block.into_bytes()
+}
+
+/// Restores a mapper's state from a block written by save_mapper_state. Fails
+/// if the state was saved for a different mapper or by a newer version of the
+/// emulator than this one.
+pub fn load_mapper_state(mapper: &mut dyn Mapper, bytes: &[u8]) -> Result<(), String> {
+    let mut reader = StateReader::new(bytes);
+    let (format_version, mut payload) = reader.read_block(MAPPER_STATE_MAGIC)?;
+
+    match format_version {
+        1 => {
+            let mapper_num = payload.read_u16()?;
+            let state_version = payload.read_u16()?;
+
+            if mapper_num != mapper.mapper_num() {
+                return Err(format!(
+                    "Save state is for mapper {mapper_num}, but the loaded cartridge uses mapper {}",
+                    mapper.mapper_num(),
+                ));
+            }
+            if state_version > mapper.state_version() {
+                return Err(format!(
+                    "Mapper {mapper_num} state version {state_version} is newer than this emulator supports ({})",
+                    mapper.state_version(),
+                ));
+            }
+
+            mapper.load_state(state_version, &mut payload)
+        }
+
+        _ => Err(format!("Unsupported mapper state format version {format_version}")),
+    }
 }
 
-pub fn mapper_from_cart(cart: Cartridge) -> Rc<RefCell<dyn Mapper>> {
+/// Makes the mapper the cartridge asks for. Fails if it isn't one the
+/// emulator has.
+pub fn mapper_from_cart(cart: Cartridge) -> Result<Rc<RefCell<dyn Mapper>>, String> {
     println!("Loading cart with mapper {}", cart.header.mapper_num);
 
     let mapper: Rc<RefCell<dyn Mapper>> = match cart.header.mapper_num {
@@ -110,10 +292,200 @@ pub fn mapper_from_cart(cart: Cartridge) -> Rc<RefCell<dyn Mapper>> {
         3 => Rc::new(RefCell::new(Mapper3::default())),
         4 => Rc::new(RefCell::new(Mapper4::default())),
         9 => Rc::new(RefCell::new(Mapper9::default())),
-        _ => panic!("unimplemented mapper number {}", cart.header.mapper_num),
+        _ => return Err(format!("Unimplemented mapper number {}", cart.header.mapper_num)),
     };
 
     mapper.as_ref().borrow_mut().init(cart);
 
-    mapper
+    Ok(mapper)
+}
+
+/*
+ON HOW OUT OF RANGE BANKS ARE HANDLED:
+
+A bank register can hold a bigger number than the cartridge has banks for: a
+128KiB UxROM game can ask for bank 12, or a bad dump (or a header with the
+wrong sizes in it) can leave the ROM smaller than the game expects. On a real
+board the high bits of the bank number go to address lines the ROM chip
+doesn't have, so the banks just wrap back around to the start. Mappers do the
+same by reading and writing banked memory through read_banked and
+write_banked, which wrap an address past the end of the memory back to the
+start rather than panicking.
+
+That's almost never what the game means to do, so the first time it happens
+each mapper prints a warning (through BankWarning) saying where the bank
+was, which is a good hint the ROM is bad.
+https://www.nesdev.org/wiki/Mirroring#Memory_Mirroring
+*/
+
+/// Reads a byte of banked ROM or RAM, wrapping addresses past the end back to
+/// the start (see "ON HOW OUT OF RANGE BANKS ARE HANDLED")
+pub fn read_banked(mem: &[u8], addr: usize) -> u8 {
+    match mem.len() {
+        0 => 0,
+        len => mem[addr % len],
+    }
+}
+
+/// Where a byte of banked ROM or RAM really is, wrapping addresses past the
+/// end back to the start like read_banked does
+pub fn banked_offset(mem: &[u8], addr: usize) -> usize {
+    match mem.len() {
+        0 => 0,
+        len => addr % len,
+    }
+}
+
+/// Writes a byte of banked RAM, wrapping addresses past the end back to the
+/// start (see "ON HOW OUT OF RANGE BANKS ARE HANDLED")
+pub fn write_banked(mem: &mut [u8], addr: usize, data: u8) {
+    let len = mem.len();
+
+    if len > 0 {
+        mem[addr % len] = data;
+    }
+}
+
+/// Warns the first time a game switches in a bank past the end of the memory
+/// it's in, and then keeps quiet so a game doing it every frame doesn't flood
+/// the log
+#[derive(Debug, Default)]
+pub struct BankWarning {
+    warned: bool,
+}
+
+impl BankWarning {
+    ///  * `what` - The kind of memory, like "PRG ROM"
+    ///  * `offset` - Where the bank starts in the memory
+    ///  * `mem_len` - The size of the memory
+    pub fn check(&mut self, what: &str, offset: usize, mem_len: usize) {
+        if offset < mem_len || self.warned {
+            return;
+        }
+
+        println!(
+            "Warning: the game switched in a {what} bank at ${offset:X}, past the end of the cartridge's {}KiB. \
+            It wraps around, but the ROM may be a bad dump or have the wrong sizes in its header.",
+            mem_len / 1024
+        );
+        self.warned = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPPERS: [u8; 6] = [0, 1, 2, 3, 4, 9];
+
+    // xorshift64, so every run fuzzes the same ROMs
+    struct Fuzzer(u64);
+
+    impl Fuzzer {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn byte(&mut self) -> u8 {
+            (self.next() >> 32) as u8
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() >> 16) as usize % n
+        }
+    }
+
+    // A cartridge file with a header that's random apart from the identifier
+    // and a mapper that exists, and a random amount of data after it (which
+    // often doesn't match the sizes in the header)
+    fn fuzzed_rom(fuzzer: &mut Fuzzer) -> Vec<u8> {
+        let mut rom = b"NES\x1A".to_vec();
+        rom.extend((0..12).map(|_| fuzzer.byte()));
+
+        let mapper = MAPPERS[fuzzer.below(MAPPERS.len())];
+        rom[6] = (rom[6] & 0x0F) | (mapper << 4);
+        rom[7] &= 0x0F;
+        rom[8] &= 0xF0;
+        // Mostly small sizes, so the file is long enough often enough
+        if fuzzer.below(4) != 0 {
+            rom[4] = fuzzer.byte() % 5;
+            rom[5] = fuzzer.byte() % 5;
+            rom[9] = 0;
+        }
+
+        let len = fuzzer.below(5 * 0x4000 + 5 * 0x2000);
+        rom.extend((0..len).map(|_| fuzzer.byte()));
+
+        rom
+    }
+
+    #[test]
+    fn malformed_roms_dont_panic() {
+        let mut fuzzer = Fuzzer(0x2545_F491_4F6C_DD1D);
+        let mut loaded = 0;
+
+        for _ in 0..400 {
+            let rom = fuzzed_rom(&mut fuzzer);
+
+            let Ok(cart) = Cartridge::from_bytes(&rom) else {
+                continue;
+            };
+            let mapper = mapper_from_cart(cart).unwrap();
+            let mut mapper = mapper.as_ref().borrow_mut();
+            loaded += 1;
+
+            // Random bank switches, with reads all over both address spaces
+            // in between
+            for _ in 0..500 {
+                let addr = 0x4020 + fuzzer.below(0x10000 - 0x4020) as u16;
+                mapper.cpu_cart_write(addr, fuzzer.byte());
+
+                for _ in 0..4 {
+                    mapper.cpu_cart_read(0x4020 + fuzzer.below(0x10000 - 0x4020) as u16);
+                    mapper.ppu_cart_read(fuzzer.below(0x2000) as u16);
+                }
+                mapper.ppu_cart_write(fuzzer.below(0x2000) as u16, fuzzer.byte());
+                mapper.scanline_finished();
+            }
+        }
+
+        assert!(loaded > 50, "too few of the fuzzed ROMs loaded ({loaded}) to test the mappers");
+    }
+
+    #[test]
+    fn cut_short_roms_fail_to_load() {
+        // 2 banks of PRG ROM and 1 of CHR in the header, but half the CHR
+        let mut rom = b"NES\x1A\x02\x01".to_vec();
+        rom.resize(16 + 2 * 0x4000 + 0x1000, 0);
+
+        assert!(Cartridge::from_bytes(&rom).is_err());
+
+        // And sizes so big they'd overflow
+        let mut rom = b"NES\x1A\xFF\xFF\x00\x08\x00\xFF".to_vec();
+        rom.resize(16, 0);
+
+        assert!(Cartridge::from_bytes(&rom).is_err());
+    }
+
+    #[test]
+    fn out_of_range_banks_wrap() {
+        // UxROM with 2 PRG banks, each filled with its number
+        let mut rom = b"NES\x1A\x02\x01\x20".to_vec();
+        rom.resize(16, 0);
+        rom.extend([0; 0x4000]);
+        rom.extend([1; 0x4000]);
+        rom.extend([0; 0x2000]);
+
+        let mapper = mapper_from_cart(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+        let mut mapper = mapper.as_ref().borrow_mut();
+
+        // Bank 3 is bank 1, and bank 2 is bank 0
+        mapper.cpu_cart_write(0x8000, 3);
+        assert_eq!(mapper.cpu_cart_peek(0x8000), Some(1));
+        mapper.cpu_cart_write(0x8000, 2);
+        assert_eq!(mapper.cpu_cart_peek(0x8000), Some(0));
+    }
 }
@@ -1,5 +1,6 @@
 use crate::cartridge::{Cartridge};
-use crate::cartridge::mapper::{Mapper, NametableMirror};
+use crate::cartridge::mapper::{banked_offset, read_banked, Mapper, NametableMirror, PrgLocation};
+use crate::system::savestate::{StateReader, StateWriter};
 
 /// The simplest mapper, and the most common.
 /// PRG: 0x8000-BFFF (mirrored 0xC000-FFFF)
@@ -27,29 +28,32 @@ impl Mapper for Mapper0 {
         self.num_chr_banks = cart.chr_rom_banks();
         self.prg_rom = cart.get_prg_rom();
         self.chr_rom = cart.get_chr_rom();
+
+        if self.num_prg_banks > 2 || self.num_chr_banks > 1 {
+            println!("Warning: NROM can only reach 32KiB of PRG ROM and 8KiB of CHR ROM, the rest of the cartridge is never used");
+        }
     }
 
-    fn cpu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_cart_peek(&self, addr: u16) -> Option<u8> {
+        // A single 16KiB bank is mirrored at $C000, which wrapping does
         if 0x8000 <= addr {
-            let mut mapped_addr = addr;
+            return Some( read_banked(&self.prg_rom, (addr & 0x7FFF) as usize) );
+        }
 
-            if self.num_prg_banks == 1 {
-                mapped_addr &= 0x3FFF;
-            } else if self.num_prg_banks == 2 {
-                mapped_addr &= 0x7FFF;
-            } else {
-                panic!("Mapper 0 should have 1 or 2 prg rom banks");
-            };
+        None
+    }
 
-            return Some( self.prg_rom[mapped_addr as usize] );
+    fn cpu_prg_location(&self, addr: u16) -> Option<PrgLocation> {
+        if 0x8000 <= addr {
+            return Some( PrgLocation::Rom(banked_offset(&self.prg_rom, (addr & 0x7FFF) as usize)) );
         }
 
         None
     }
 
-    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn ppu_cart_peek(&self, addr: u16) -> Option<u8> {
         if addr <= 0x1FFF {
-            return Some( self.chr_rom[addr as usize] );
+            return Some( read_banked(&self.chr_rom, addr as usize) );
         }
 
         None
@@ -59,6 +63,11 @@ impl Mapper for Mapper0 {
         false
     }
 
+    // NROM has no registers and no PRG RAM
+    fn cpu_write_has_target(&self, _addr: u16) -> bool {
+        false
+    }
+
     fn ppu_cart_write(&mut self, _addr: u16, _data: u8) -> bool {
         false
     }
@@ -66,4 +75,28 @@ impl Mapper for Mapper0 {
     fn get_nt_mirror_type(&self) -> NametableMirror {
         self.nt_mirror_type
     }
+
+    fn mapper_num(&self) -> u16 {
+        0
+    }
+
+    fn mapper_name(&self) -> &'static str {
+        "NROM"
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nt_mirror_type.to_u8());
+    }
+
+    fn load_state(&mut self, version: u16, state: &mut StateReader) -> Result<(), String> {
+        match version {
+            1 => {
+                self.nt_mirror_type = NametableMirror::from_u8(state.read_u8()?)?;
+            }
+
+            _ => return Err(format!("Unknown mapper 0 state version {version}")),
+        }
+
+        Ok(())
+    }
 }
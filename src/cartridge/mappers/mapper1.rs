@@ -1,10 +1,66 @@
-use crate::cartridge::{mapper::NametableMirror, Cartridge, Mapper};
+use crate::cartridge::mapper::{banked_offset, read_banked, write_banked, BankWarning, NametableMirror, PrgLocation};
+use crate::cartridge::{Cartridge, Mapper};
+use crate::system::savestate::{StateReader, StateWriter};
+
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+// PRG ROM banks (16 KiB each) reachable without the SUROM/SXROM outer bank bit
+const PRG_BANKS_PER_OUTER_BANK: usize = 16;
+
+/// MMC1 boards that need special handling. They only have 8 KiB of CHR RAM, so
+/// the upper bits of the CHR bank registers go unused and the boards wire them
+/// to extra PRG ROM or PRG RAM address lines instead.
+/// https://www.nesdev.org/wiki/MMC1#SNROM,_SOROM,_SUROM_and_SXROM
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mmc1Board {
+    #[default]
+    Standard,
+    /// 16 KiB PRG RAM, CHR bank bit 3 selects the PRG RAM bank
+    Sorom,
+    /// 512 KiB PRG ROM, CHR bank bit 4 selects which 256 KiB half is used
+    Surom,
+    /// Both of the above, with 32 KiB PRG RAM banked by CHR bank bits 2-3
+    Sxrom,
+}
+
+impl Mmc1Board {
+    /// Works out the board from the NES 2.0 submapper if one is given (these
+    /// submappers are deprecated, but some dumps still use them), otherwise from
+    /// the PRG ROM and PRG RAM sizes.
+    /// https://www.nesdev.org/wiki/NES_2.0_submappers#001:_MMC1
+    fn detect(cart: &Cartridge) -> Self {
+        let prg_ram_size = cart.prg_ram_size();
+        let large_prg = cart.prg_rom_banks() > PRG_BANKS_PER_OUTER_BANK;
+
+        if prg_ram_size.is_some() {
+            match cart.header.submapper_num {
+                1 => return Self::Surom,
+                2 => return Self::Sorom,
+                4 => return Self::Sxrom,
+                _ => {}
+            }
+        }
 
-const PRG_RAM_SIZE: usize = 0x2000;
+        match prg_ram_size {
+            Some(0x8000) => Self::Sxrom,
+            Some(0x4000) if !large_prg => Self::Sorom,
+            _ if large_prg => Self::Surom,
+            _ => Self::Standard,
+        }
+    }
+
+    fn prg_ram_size(&self) -> usize {
+        match self {
+            Self::Sorom => 2 * PRG_RAM_BANK_SIZE,
+            Self::Sxrom => 4 * PRG_RAM_BANK_SIZE,
+            _ => PRG_RAM_BANK_SIZE,
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct Mapper1 {
     nt_mirror_type: NametableMirror,
+    board: Mmc1Board,
     num_prg_banks: usize,
     num_chr_banks: usize,
     control: u8,
@@ -15,6 +71,9 @@ pub struct Mapper1 {
     chr_bank_select_lo: usize,
     chr_bank_select_hi: usize,
     chr_bank_select_full: usize,
+    // Last value written to CHR bank register 0, whose upper bits select the
+    // outer PRG ROM bank and PRG RAM bank on the boards that use them
+    chr_bank_0_reg: u8,
 
     prg_bank_select_lo: usize,
     prg_bank_select_hi: usize,
@@ -23,78 +82,61 @@ pub struct Mapper1 {
     prg_rom: Vec<u8>,
     prg_ram: Vec<u8>,
     chr_mem: Vec<u8>,
+
+    bank_warning: BankWarning,
 }
 
 impl Mapper for Mapper1 {
     fn init(&mut self, cart: Cartridge) {
+        self.board = Mmc1Board::detect(&cart);
         self.num_prg_banks = cart.prg_rom_banks();
         self.num_chr_banks = cart.chr_rom_banks();
 
         self.prg_rom = cart.get_prg_rom();
         self.chr_mem = cart.get_chr_rom();
 
-        self.prg_ram = vec![0; PRG_RAM_SIZE];
+        self.prg_ram = vec![0; self.board.prg_ram_size()];
+
+        if self.board != Mmc1Board::Standard {
+            println!("MMC1 board: {:?}", self.board);
+        }
 
         self.reset();
     }
 
-    fn cpu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_cart_peek(&self, addr: u16) -> Option<u8> {
         match addr {
             // Internal PRG RAM
             0x6000..=0x7FFF => {
-                let mapped_addr = (addr & 0x1FFF) as usize;
+                let mapped_addr = self.prg_ram_bank() * PRG_RAM_BANK_SIZE + (addr & 0x1FFF) as usize;
 
-                Some( self.prg_ram[mapped_addr] )
+                Some( read_banked(&self.prg_ram, mapped_addr) )
             }
 
-            // PRG ROM Low
-            0x8000..=0xBFFF => {
-                let mapped_addr = if self.split_prg_bank_mode() {
-                    self.prg_bank_select_lo * 0x4000 + (addr & 0x3FFF) as usize
-                } else {
-                    self.prg_bank_select_full * 0x8000 + (addr & 0x7FFF) as usize
-                };
+            // PRG ROM
+            0x8000..=0xFFFF => Some( read_banked(&self.prg_rom, self.prg_rom_addr(addr)) ),
 
-                Some( self.prg_rom[mapped_addr] )
-            }
+            _ => None,
+        }
+    }
 
-            // PRG ROM High
-            0xC000..=0xFFFF => {
-                let mapped_addr = if self.split_prg_bank_mode() {
-                    self.prg_bank_select_hi * 0x4000 + (addr & 0x3FFF) as usize
-                } else {
-                    self.prg_bank_select_full * 0x8000 + (addr & 0x7FFF) as usize
-                };
+    fn cpu_prg_location(&self, addr: u16) -> Option<PrgLocation> {
+        match addr {
+            0x6000..=0x7FFF => {
+                let mapped_addr = self.prg_ram_bank() * PRG_RAM_BANK_SIZE + (addr & 0x1FFF) as usize;
 
-                Some( self.prg_rom[mapped_addr] )
+                Some( PrgLocation::Ram(banked_offset(&self.prg_ram, mapped_addr)) )
             }
 
+            0x8000..=0xFFFF => Some( PrgLocation::Rom(banked_offset(&self.prg_rom, self.prg_rom_addr(addr))) ),
+
             _ => None,
         }
     }
 
-    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn ppu_cart_peek(&self, addr: u16) -> Option<u8> {
         if addr <= 0x1FFF {
-            // Only one CHR bank to access
-            if self.num_chr_banks == 0 {
-                return Some( self.chr_mem[addr as usize] )
-            }
-
-            let mapped_addr = if self.split_chr_bank_mode() {
-                // CHR Bank Low
-                if addr <= 0x0FFF {
-                    self.chr_bank_select_lo * 0x1000 + addr as usize
-                } 
-                // CHR Bank High
-                else {
-                    self.chr_bank_select_hi * 0x1000 + (addr & 0x0FFF) as usize
-                }
-            } else {
-                // CHR Bank Full
-                self.chr_bank_select_full * 0x2000 + addr as usize
-            };
-
-            return Some( self.chr_mem[mapped_addr] );
+            return Some( read_banked(&self.chr_mem, self.chr_addr(addr)) );
         }
 
         None
@@ -104,9 +146,9 @@ impl Mapper for Mapper1 {
         match addr {
             // Internal PRG RAM
             0x6000..=0x7FFF => {
-                let mapped_addr = (addr & 0x1FFF) as usize;
+                let mapped_addr = self.prg_ram_bank() * PRG_RAM_BANK_SIZE + (addr & 0x1FFF) as usize;
                 
-                self.prg_ram[mapped_addr] = data;
+                write_banked(&mut self.prg_ram, mapped_addr, data);
 
                 true
             }
@@ -129,11 +171,22 @@ impl Mapper for Mapper1 {
         }
     }
 
+    // MMC1 ignores a write to its registers on the cycle after another write,
+    // so a read-modify-write instruction only gets its first write (the
+    // unchanged value) into the shift register
+    // https://www.nesdev.org/wiki/MMC1#Consecutive-cycle_writes
+    fn cpu_cart_write_consecutive(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            0x8000..=0xFFFF => false,
+            _ => self.cpu_cart_write(addr, data),
+        }
+    }
+
     fn ppu_cart_write(&mut self, addr: u16, data: u8) -> bool {
         if addr <= 0x1FFF {
             // If # CHR banks == 0, treat CHR ROM as CHR RAM
             if self.num_chr_banks == 0 {
-                self.chr_mem[addr as usize] = data;
+                write_banked(&mut self.chr_mem, addr as usize, data);
                 return true;
             }
         }
@@ -144,6 +197,113 @@ impl Mapper for Mapper1 {
     fn get_nt_mirror_type(&self) -> NametableMirror {
         self.nt_mirror_type
     }
+
+    fn mapper_num(&self) -> u16 {
+        1
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn mapper_name(&self) -> &'static str {
+        "MMC1"
+    }
+
+    fn debug_state(&self) -> Vec<(String, String)> {
+        let prg_mode = match (self.control >> 2) & 0x03 {
+            0 | 1 => "32K",
+            2 => "16K, $8000 fixed",
+            _ => "16K, $C000 fixed",
+        };
+        let chr_mode = if self.split_chr_bank_mode() { "4K" } else { "8K" };
+
+        vec![
+            (String::from("Board"), format!("{:?}", self.board)),
+            (String::from("Shift"), format!("{:05b} ({} of 5 bits)", self.shift_reg & 0x1F, self.write_count)),
+            (String::from("Control"), format!("${:02X}", self.control)),
+            (String::from("PRG mode"), String::from(prg_mode)),
+            (String::from("CHR mode"), String::from(chr_mode)),
+            (String::from("PRG bank"), if self.split_prg_bank_mode() {
+                format!("{} / {}", self.prg_outer_bank() + self.prg_bank_select_lo, self.prg_outer_bank() + self.prg_bank_select_hi)
+            } else {
+                format!("{}", self.prg_outer_bank() / 2 + self.prg_bank_select_full)
+            }),
+            (String::from("CHR bank"), if self.split_chr_bank_mode() {
+                format!("{} / {}", self.chr_bank_select_lo, self.chr_bank_select_hi)
+            } else {
+                format!("{}", self.chr_bank_select_full)
+            }),
+            (String::from("PRG RAM bank"), format!("{}", self.prg_ram_bank())),
+        ]
+    }
+
+    fn state_version(&self) -> u16 {
+        2
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nt_mirror_type.to_u8());
+        state.write_u8(self.control);
+        state.write_usize(self.write_count);
+        state.write_u8(self.shift_reg);
+
+        state.write_usize(self.chr_bank_select_lo);
+        state.write_usize(self.chr_bank_select_hi);
+        state.write_usize(self.chr_bank_select_full);
+        state.write_u8(self.chr_bank_0_reg);
+
+        state.write_usize(self.prg_bank_select_lo);
+        state.write_usize(self.prg_bank_select_hi);
+        state.write_usize(self.prg_bank_select_full);
+
+        state.write_bytes(&self.prg_ram);
+        // CHR is only state if it's RAM
+        if self.num_chr_banks == 0 {
+            state.write_bytes(&self.chr_mem);
+        }
+    }
+
+    fn load_state(&mut self, version: u16, state: &mut StateReader) -> Result<(), String> {
+        match version {
+            1 | 2 => {
+                self.nt_mirror_type = NametableMirror::from_u8(state.read_u8()?)?;
+                self.control = state.read_u8()?;
+                self.write_count = state.read_usize()?;
+                self.shift_reg = state.read_u8()?;
+
+                self.chr_bank_select_lo = state.read_usize()?;
+                self.chr_bank_select_hi = state.read_usize()?;
+                self.chr_bank_select_full = state.read_usize()?;
+                // Version 1 didn't save the raw register, but it can be rebuilt
+                // from whichever bank was last selected with it
+                self.chr_bank_0_reg = if version >= 2 {
+                    state.read_u8()?
+                } else if self.split_chr_bank_mode() {
+                    self.chr_bank_select_lo as u8
+                } else {
+                    self.chr_bank_select_full as u8
+                };
+
+                self.prg_bank_select_lo = state.read_usize()?;
+                self.prg_bank_select_hi = state.read_usize()?;
+                self.prg_bank_select_full = state.read_usize()?;
+
+                state.read_bytes_into(&mut self.prg_ram)?;
+                if self.num_chr_banks == 0 {
+                    state.read_bytes_into(&mut self.chr_mem)?;
+                }
+            }
+
+            _ => return Err(format!("Unknown mapper 1 state version {version}")),
+        }
+
+        Ok(())
+    }
 }
 
 
@@ -157,9 +317,10 @@ impl Mapper1 {
         self.chr_bank_select_lo = 0;
         self.chr_bank_select_hi = 0;
         self.chr_bank_select_full = 0;
+        self.chr_bank_0_reg = 0;
 
         self.prg_bank_select_lo = 0;
-        self.prg_bank_select_hi = self.num_prg_banks - 1;
+        self.prg_bank_select_hi = self.last_inner_prg_bank();
         self.prg_bank_select_full = 0;
     }
 
@@ -190,6 +351,8 @@ impl Mapper1 {
 
                 // CHR ROM Select Low OR Full
                 1 => {
+                    self.chr_bank_0_reg = shift_val as u8;
+
                     if self.split_chr_bank_mode() {
                         self.chr_bank_select_lo = shift_val;
                     } else {
@@ -210,7 +373,7 @@ impl Mapper1 {
                     match (self.control >> 2) & 0x03 {
                         // Switch Full
                         0 | 1 => {
-                            self.prg_bank_select_full = shift_val >> 1;
+                            self.prg_bank_select_full = (shift_val & 0x0E) >> 1;
                         }
 
                         // Fix Low At Bank 0 & Switch High
@@ -222,7 +385,7 @@ impl Mapper1 {
                         // Switch Low & Fix High At Last Bank
                         3 => {
                             self.prg_bank_select_lo = shift_val & 0x0F;
-                            self.prg_bank_select_hi = self.num_prg_banks - 1;
+                            self.prg_bank_select_hi = self.last_inner_prg_bank();
                         }
 
                         _ => {}
@@ -234,6 +397,77 @@ impl Mapper1 {
         
             self.shift_reg = 0;
             self.write_count = 0;
+
+            self.check_banks();
+        }
+    }
+
+    // Where a CPU address from $8000 up is in PRG ROM
+    fn prg_rom_addr(&self, addr: u16) -> usize {
+        if !self.split_prg_bank_mode() {
+            return self.prg_outer_bank() * 0x4000 + self.prg_bank_select_full * 0x8000 + (addr & 0x7FFF) as usize;
+        }
+
+        let bank = if addr <= 0xBFFF {
+            self.prg_bank_select_lo
+        } else {
+            self.prg_bank_select_hi
+        };
+
+        (self.prg_outer_bank() + bank) * 0x4000 + (addr & 0x3FFF) as usize
+    }
+
+    // Where a PPU address below $2000 is in CHR ROM (or RAM)
+    fn chr_addr(&self, addr: u16) -> usize {
+        // Only one CHR bank to access
+        if self.num_chr_banks == 0 {
+            return addr as usize;
+        }
+
+        if !self.split_chr_bank_mode() {
+            return self.chr_bank_select_full * 0x2000 + addr as usize;
+        }
+
+        if addr <= 0x0FFF {
+            self.chr_bank_select_lo * 0x1000 + addr as usize
+        } else {
+            self.chr_bank_select_hi * 0x1000 + (addr & 0x0FFF) as usize
+        }
+    }
+
+    // Warns if a register write switched in a bank past the end of the ROM
+    fn check_banks(&mut self) {
+        for addr in [0x8000, 0xC000] {
+            self.bank_warning.check("PRG ROM", self.prg_rom_addr(addr), self.prg_rom.len());
+        }
+        for addr in [0x0000, 0x1000] {
+            self.bank_warning.check("CHR ROM", self.chr_addr(addr), self.chr_mem.len());
+        }
+    }
+
+    // The last 16 KiB PRG bank within the current 256 KiB outer bank, which is
+    // what gets fixed at $C000
+    fn last_inner_prg_bank(&self) -> usize {
+        std::cmp::min(self.num_prg_banks, PRG_BANKS_PER_OUTER_BANK) - 1
+    }
+
+    // First 16 KiB PRG bank of the 256 KiB outer bank selected on SUROM/SXROM.
+    // The real boards take this bit from whichever CHR register the PPU is
+    // using at the time, but games always write the same value to both, so
+    // register 0 is used here.
+    fn prg_outer_bank(&self) -> usize {
+        match self.board {
+            Mmc1Board::Surom | Mmc1Board::Sxrom if self.chr_bank_0_reg & 0x10 != 0 => PRG_BANKS_PER_OUTER_BANK,
+            _ => 0,
+        }
+    }
+
+    // The 8 KiB PRG RAM bank mapped at $6000
+    fn prg_ram_bank(&self) -> usize {
+        match self.board {
+            Mmc1Board::Sorom => ((self.chr_bank_0_reg >> 3) & 0x01) as usize,
+            Mmc1Board::Sxrom => ((self.chr_bank_0_reg >> 2) & 0x03) as usize,
+            _ => 0,
         }
     }
 
@@ -1,5 +1,6 @@
 use crate::cartridge::{Cartridge};
-use crate::cartridge::mapper::{Mapper, NametableMirror};
+use crate::cartridge::mapper::{banked_offset, read_banked, write_banked, BankWarning, Mapper, NametableMirror, PrgLocation};
+use crate::system::savestate::{StateReader, StateWriter};
 
 /// In this mapper, the low half of the PRG ROM address space ($8000-$BFFF) is
 /// switchable, while the higher half ($C000-$FFFF) is locked to the highest bank
@@ -19,6 +20,8 @@ pub struct Mapper2 {
 
     prg_bank_select_lo: usize,
     prg_bank_select_hi: usize,
+
+    bank_warning: BankWarning,
 }
 
 impl Mapper for Mapper2 {
@@ -38,29 +41,39 @@ impl Mapper for Mapper2 {
         self.reset();
     }
 
-    fn cpu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_cart_peek(&self, addr: u16) -> Option<u8> {
         match addr {
             // PRG bank low
             0x8000..=0xBFFF => {
                 let mapped_addr = self.prg_bank_select_lo * 0x4000 + (addr & 0x3FFF) as usize;
 
-                Some( self.prg_rom[mapped_addr] )
+                Some( read_banked(&self.prg_rom, mapped_addr) )
             }
 
             // PRG bank high
             0xC000..=0xFFFF => {
                 let mapped_addr = self.prg_bank_select_hi * 0x4000 + (addr & 0x3FFF) as usize;
 
-                Some( self.prg_rom[mapped_addr] )
+                Some( read_banked(&self.prg_rom, mapped_addr) )
             }
 
             _ => None,
         }
     }
 
-    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_prg_location(&self, addr: u16) -> Option<PrgLocation> {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank_select_lo,
+            0xC000..=0xFFFF => self.prg_bank_select_hi,
+            _ => return None,
+        };
+
+        Some( PrgLocation::Rom(banked_offset(&self.prg_rom, bank * 0x4000 + (addr & 0x3FFF) as usize)) )
+    }
+
+    fn ppu_cart_peek(&self, addr: u16) -> Option<u8> {
         if addr <= 0x1FFF {
-            return Some( self.chr_mem[addr as usize] );
+            return Some( read_banked(&self.chr_mem, addr as usize) );
         }
 
         None
@@ -69,15 +82,21 @@ impl Mapper for Mapper2 {
     fn cpu_cart_write(&mut self, addr: u16, data: u8) -> bool {
         if 0x8000 <= addr {
             self.prg_bank_select_lo = (data & 0x0F) as usize;
+            self.bank_warning.check("PRG ROM", self.prg_bank_select_lo * 0x4000, self.prg_rom.len());
         }
         
         false
     }
 
+    // No PRG RAM, only the bank register
+    fn cpu_write_has_target(&self, addr: u16) -> bool {
+        0x8000 <= addr
+    }
+
     fn ppu_cart_write(&mut self, addr: u16, data: u8) -> bool {
         // If # CHR banks == 0, treat CHR ROM as CHR RAM
         if self.num_chr_banks == 0 && addr <= 0x1FFF {
-            self.chr_mem[addr as usize] = data;
+            write_banked(&mut self.chr_mem, addr as usize, data);
             
             return true;
         }
@@ -89,6 +108,54 @@ impl Mapper for Mapper2 {
         self.nt_mirror_type
     }
 
+    fn mapper_num(&self) -> u16 {
+        2
+    }
+
+    fn mapper_name(&self) -> &'static str {
+        "UxROM"
+    }
+
+    fn debug_state(&self) -> Vec<(String, String)> {
+        vec![
+            (String::from("$8000 bank"), format!("{} of {}", self.prg_bank_select_lo, self.num_prg_banks)),
+            (String::from("$C000 bank"), format!("{} (fixed)", self.prg_bank_select_hi)),
+        ]
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nt_mirror_type.to_u8());
+        state.write_usize(self.prg_bank_select_lo);
+        state.write_usize(self.prg_bank_select_hi);
+
+        // CHR is only state if it's RAM
+        if self.num_chr_banks == 0 {
+            state.write_bytes(&self.chr_mem);
+        }
+    }
+
+    fn load_state(&mut self, version: u16, state: &mut StateReader) -> Result<(), String> {
+        match version {
+            1 => {
+                self.nt_mirror_type = NametableMirror::from_u8(state.read_u8()?)?;
+                self.prg_bank_select_lo = state.read_usize()?;
+                self.prg_bank_select_hi = state.read_usize()?;
+
+                if self.num_chr_banks == 0 {
+                    state.read_bytes_into(&mut self.chr_mem)?;
+                }
+            }
+
+            _ => return Err(format!("Unknown mapper 2 state version {version}")),
+        }
+
+        Ok(())
+    }
+}
+
+
+impl Mapper2 {
+    // Power on state: the first bank at $8000 and the last one fixed at $C000
     fn reset(&mut self) {
         self.prg_bank_select_lo = 0;
         self.prg_bank_select_hi = self.num_prg_banks - 1;
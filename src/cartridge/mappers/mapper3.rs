@@ -1,4 +1,6 @@
-use crate::cartridge::{mapper::NametableMirror, Cartridge, Mapper};
+use crate::cartridge::mapper::{banked_offset, read_banked, BankWarning, NametableMirror, PrgLocation};
+use crate::cartridge::{Cartridge, Mapper};
+use crate::system::savestate::{StateReader, StateWriter};
 
 #[derive(Default)]
 pub struct Mapper3 {
@@ -9,6 +11,8 @@ pub struct Mapper3 {
 
     prg_rom: Vec<u8>,
     chr_rom: Vec<u8>,
+
+    bank_warning: BankWarning,
 }
 
 impl Mapper for Mapper3 {
@@ -22,31 +26,34 @@ impl Mapper for Mapper3 {
         self.num_prg_banks = cart.prg_rom_banks();
         self.prg_rom = cart.get_prg_rom();
         self.chr_rom = cart.get_chr_rom();
+
+        if self.num_prg_banks > 2 {
+            println!("Warning: CNROM can only reach 32KiB of PRG ROM, the rest of the cartridge is never used");
+        }
     }
 
-    fn cpu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_cart_peek(&self, addr: u16) -> Option<u8> {
+        // A single 16KiB bank is mirrored at $C000, which wrapping does
         if 0x8000 <= addr {
-            let mut mapped_addr = addr;
+            return Some( read_banked(&self.prg_rom, (addr & 0x7FFF) as usize) );
+        }
 
-            if self.num_prg_banks == 2 {
-                mapped_addr &= 0x7FFF;
-            } else if self.num_prg_banks == 1 {
-                mapped_addr &= 0x3FFF;
-            } else {
-                panic!("Mapper 0 should have 1 or 2 prg rom banks");
-            };
+        None
+    }
 
-            return Some( self.prg_rom[mapped_addr as usize] );
+    fn cpu_prg_location(&self, addr: u16) -> Option<PrgLocation> {
+        if 0x8000 <= addr {
+            return Some( PrgLocation::Rom(banked_offset(&self.prg_rom, (addr & 0x7FFF) as usize)) );
         }
 
         None
     }
 
-    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn ppu_cart_peek(&self, addr: u16) -> Option<u8> {
         if addr <= 0x1FFF {
             let mapped_addr = (self.chr_bank_select * 0x2000) + addr as usize;
 
-            return Some( self.chr_rom[mapped_addr] );
+            return Some( read_banked(&self.chr_rom, mapped_addr) );
         }
 
         None
@@ -55,11 +62,17 @@ impl Mapper for Mapper3 {
     fn cpu_cart_write(&mut self, addr: u16, data: u8) -> bool {
         if 0x8000 <= addr {
             self.chr_bank_select = (data & 3) as usize;
+            self.bank_warning.check("CHR ROM", self.chr_bank_select * 0x2000, self.chr_rom.len());
         }
 
         false
     }
 
+    // No PRG RAM, only the bank register
+    fn cpu_write_has_target(&self, addr: u16) -> bool {
+        0x8000 <= addr
+    }
+
     fn ppu_cart_write(&mut self, _addr: u16, _data: u8) -> bool {
         false
     }
@@ -68,7 +81,33 @@ impl Mapper for Mapper3 {
         self.nt_mirror_type
     }
 
-    fn reset(&mut self) {
-        self.chr_bank_select = 0;
+    fn mapper_num(&self) -> u16 {
+        3
+    }
+
+    fn mapper_name(&self) -> &'static str {
+        "CNROM"
+    }
+
+    fn debug_state(&self) -> Vec<(String, String)> {
+        vec![(String::from("CHR bank"), format!("{}", self.chr_bank_select))]
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nt_mirror_type.to_u8());
+        state.write_usize(self.chr_bank_select);
+    }
+
+    fn load_state(&mut self, version: u16, state: &mut StateReader) -> Result<(), String> {
+        match version {
+            1 => {
+                self.nt_mirror_type = NametableMirror::from_u8(state.read_u8()?)?;
+                self.chr_bank_select = state.read_usize()?;
+            }
+
+            _ => return Err(format!("Unknown mapper 3 state version {version}")),
+        }
+
+        Ok(())
     }
 }
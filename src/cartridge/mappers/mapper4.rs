@@ -1,6 +1,9 @@
-use crate::cartridge::{mapper::NametableMirror, Cartridge, Mapper};
+use crate::cartridge::mapper::{banked_offset, read_banked, BankWarning, NametableMirror, PrgLocation};
+use crate::cartridge::{Cartridge, Mapper};
+use crate::system::savestate::{StateReader, StateWriter};
 
 const PRG_RAM_SIZE: usize = 0x2000;
+const MMC6_PRG_RAM_SIZE: usize = 0x400;
 const PRG_BANK_SIZE: usize = 0x2000;
 const CHR_BANK_SIZE: usize = 0x400;
 
@@ -13,8 +16,43 @@ const CHR_BANK_SIZE: usize = 0x400;
 // Games:
 // - Super Mario Bros 2
 // - Super Mario Bros 3
+//
+// Two relatives of the MMC3 also use mapper 4, and are told apart by the NES 2.0
+// submapper (iNES headers get the plain MMC3):
+// - MMC6 (StarTropics 1 & 2) has 1 KiB of PRG RAM at $7000, split in two halves
+//   that can each be protected from reads and writes.
+//   https://www.nesdev.org/wiki/MMC6
+// - Acclaim's MC-ACC counts falling edges of PPU A12 instead of rising ones, so
+//   its IRQ goes off after the sprite fetches instead of at the start of them.
+//   https://www.nesdev.org/wiki/MMC3#MC-ACC
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mmc3Variant {
+    #[default]
+    Mmc3,
+    Mmc6,
+    McAcc,
+}
+
+impl Mmc3Variant {
+    // https://www.nesdev.org/wiki/NES_2.0_submappers#004:_MMC3
+    fn detect(cart: &Cartridge) -> Self {
+        // Submappers only exist in NES 2.0 headers
+        if cart.prg_ram_size().is_none() {
+            return Self::Mmc3;
+        }
+
+        match cart.header.submapper_num {
+            1 => Self::Mmc6,
+            3 => Self::McAcc,
+            _ => Self::Mmc3,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Mapper4 {
+    variant: Mmc3Variant,
+
     irq_counter: usize,
     irq_latch: usize,
     irq_enabled: bool,
@@ -33,6 +71,11 @@ pub struct Mapper4 {
     chr_rom: Vec<u8>,
 
     prg_ram: Vec<u8>,
+    // Last value written to $A001. Only used by the MMC6, since PRG RAM is
+    // never protected on a regular MMC3 here.
+    prg_ram_protect: u8,
+
+    bank_warning: BankWarning,
 }
 
 impl Mapper for Mapper4 {
@@ -41,17 +84,27 @@ impl Mapper for Mapper4 {
             self.nt_mirror_type = NametableMirror::FourScreen
         }
 
+        self.variant = Mmc3Variant::detect(&cart);
         self.num_prg_banks = cart.prg_rom_banks();
         self.prg_rom = cart.get_prg_rom();
         self.chr_rom = cart.get_chr_rom();
 
-        self.prg_ram = vec![0; PRG_RAM_SIZE];
+        self.prg_ram = match self.variant {
+            Mmc3Variant::Mmc6 => vec![0; MMC6_PRG_RAM_SIZE],
+            _ => vec![0; PRG_RAM_SIZE],
+        };
+
+        if self.variant != Mmc3Variant::Mmc3 {
+            println!("MMC3 variant: {:?}", self.variant);
+        }
 
         self.reset();
     }
 
-    fn cpu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_cart_peek(&self, addr: u16) -> Option<u8> {
         match addr {
+            0x6000..=0x7FFF if self.variant == Mmc3Variant::Mmc6 => self.mmc6_ram_read(addr),
+
             0x6000..=0x7FFF => {
                 let mapped_addr = (addr & 0x1FFF) as usize;
 
@@ -61,79 +114,103 @@ impl Mapper for Mapper4 {
             0x8000..=0x9FFF => {
                 let mapped_addr = self.prg_banks[0] + (addr & 0x1FFF) as usize;
                 
-                Some( self.prg_rom[mapped_addr] )
+                Some( read_banked(&self.prg_rom, mapped_addr) )
             }
 
             0xA000..=0xBFFF => {
                 let mapped_addr = self.prg_banks[1] + (addr & 0x1FFF) as usize;
 
-                Some( self.prg_rom[mapped_addr] )
+                Some( read_banked(&self.prg_rom, mapped_addr) )
             }
 
             0xC000..=0xDFFF => {
                 let mapped_addr = self.prg_banks[2] + (addr & 0x1FFF) as usize;
                                 
-                Some( self.prg_rom[mapped_addr] )
+                Some( read_banked(&self.prg_rom, mapped_addr) )
             }
 
             0xE000..=0xFFFF => {
                 let mapped_addr = self.prg_banks[3] + (addr & 0x1FFF) as usize;
                 
-                Some( self.prg_rom[mapped_addr] )
+                Some( read_banked(&self.prg_rom, mapped_addr) )
             }
         
             _ => None,
         }
     }
 
-    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_prg_location(&self, addr: u16) -> Option<PrgLocation> {
+        match addr {
+            // MMC6's 1KiB is mirrored over $7000-$7FFF, and only there while
+            // it can be read
+            0x6000..=0x7FFF if self.variant == Mmc3Variant::Mmc6 => {
+                self.mmc6_ram_read(addr).map(|_| PrgLocation::Ram((addr & 0x03FF) as usize))
+            }
+
+            0x6000..=0x7FFF => Some( PrgLocation::Ram((addr & 0x1FFF) as usize) ),
+
+            0x8000..=0xFFFF => {
+                let bank = self.prg_banks[((addr - 0x8000) / 0x2000) as usize];
+
+                Some( PrgLocation::Rom(banked_offset(&self.prg_rom, bank + (addr & 0x1FFF) as usize)) )
+            }
+
+            _ => None,
+        }
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        PRG_BANK_SIZE
+    }
+
+    fn ppu_cart_peek(&self, addr: u16) -> Option<u8> {
         match addr {
             0x0000..=0x03FF => {
                 let mapped_addr = self.chr_banks[0] + (addr & 0x3FF) as usize;
                 
-                Some( self.chr_rom[mapped_addr] )
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             0x0400..=0x07FF => {
                 let mapped_addr = self.chr_banks[1] + (addr & 0x3FF) as usize;
                 
-                Some( self.chr_rom[mapped_addr] )
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             0x0800..=0x0BFF => {
                 let mapped_addr = self.chr_banks[2] + (addr & 0x3FF) as usize;
                 
-                Some( self.chr_rom[mapped_addr] )
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             0x0C00..=0x0FFF => {
                 let mapped_addr = self.chr_banks[3] + (addr & 0x3FF) as usize;
                 
-                Some( self.chr_rom[mapped_addr] )
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             0x1000..=0x13FF => {
                 let mapped_addr = self.chr_banks[4] + (addr & 0x3FF) as usize;
                 
-                Some( self.chr_rom[mapped_addr] )
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             0x1400..=0x17FF => {
                 let mapped_addr = self.chr_banks[5] + (addr & 0x3FF) as usize;
                 
-                Some( self.chr_rom[mapped_addr] )
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             0x1800..=0x1BFF => {
                 let mapped_addr = self.chr_banks[6] + (addr & 0x3FF) as usize;
                 
-                Some( self.chr_rom[mapped_addr] )
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             0x1C00..=0x1FFF => {
                 let mapped_addr = self.chr_banks[7] + (addr & 0x3FF) as usize;
                 
-                Some( self.chr_rom[mapped_addr] )
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             _ => None,
@@ -142,6 +219,8 @@ impl Mapper for Mapper4 {
 
     fn cpu_cart_write(&mut self, addr: u16, data: u8) -> bool {
         match addr {
+            0x6000..=0x7FFF if self.variant == Mmc3Variant::Mmc6 => self.mmc6_ram_write(addr, data),
+
             // PRG RAM
             0x6000..=0x7FFF => {
                 let mapped_addr = (addr & 0x1FFF) as usize;
@@ -173,7 +252,7 @@ impl Mapper for Mapper4 {
                 false
             },
 
-            // Mirror (Even), PRG ROM Protect (Odd - unimplemented)
+            // Mirror (Even), PRG RAM Protect (Odd - MMC6 only)
             0xA000..=0xBFFF => {
                 // New Mirror
                 if addr & 0x01 == 0 {
@@ -185,9 +264,9 @@ impl Mapper for Mapper4 {
                         };
                     }
                 }
-                // Prg Rom Protect
-                else {
-                    // unimplemented
+                // Prg Ram Protect. The MMC6 ignores writes while its RAM is disabled
+                else if self.variant == Mmc3Variant::Mmc6 && self.mmc6_ram_enabled() {
+                    self.prg_ram_protect = data;
                 }
 
                 false
@@ -226,6 +305,14 @@ impl Mapper for Mapper4 {
         }
     }
 
+    // MMC6's 1KiB of PRG RAM is only at $7000-$7FFF
+    fn cpu_write_has_target(&self, addr: u16) -> bool {
+        match addr {
+            0x6000..=0x6FFF => self.variant != Mmc3Variant::Mmc6,
+            _ => (0x6000..=0xFFFF).contains(&addr),
+        }
+    }
+
     fn ppu_cart_write(&mut self, _addr: u16, _data: u8) -> bool {
         false
     }
@@ -234,6 +321,116 @@ impl Mapper for Mapper4 {
         self.nt_mirror_type
     }
 
+    fn mapper_num(&self) -> u16 {
+        4
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn mapper_name(&self) -> &'static str {
+        match self.variant {
+            Mmc3Variant::Mmc3 => "MMC3",
+            Mmc3Variant::Mmc6 => "MMC6",
+            Mmc3Variant::McAcc => "MC-ACC",
+        }
+    }
+
+    fn debug_state(&self) -> Vec<(String, String)> {
+        let registers = self.registers.map(|reg| format!("{reg:02X}")).join(" ");
+
+        vec![
+            (String::from("Bank select"), format!("${:02X} (R{})", self.bank_select, self.bank_select & 0x07)),
+            (String::from("R0-R7"), registers),
+            (String::from("PRG mode"), String::from(if self.prg_banks_swapped() { "$C000 swappable" } else { "$8000 swappable" })),
+            (String::from("CHR mode"), String::from(if self.chr_banks_swapped() { "2K at $1000" } else { "2K at $0000" })),
+            (String::from("IRQ counter"), format!("{}", self.irq_counter)),
+            (String::from("IRQ latch"), format!("{}", self.irq_latch)),
+            (String::from("IRQ"), format!("{}{}",
+                if self.irq_enabled { "enabled" } else { "disabled" },
+                if self.irq_request_flag { ", pending" } else { "" })),
+        ]
+    }
+
+    fn state_version(&self) -> u16 {
+        2
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nt_mirror_type.to_u8());
+
+        state.write_usize(self.irq_counter);
+        state.write_usize(self.irq_latch);
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_request_flag);
+
+        state.write_u8(self.bank_select);
+        state.write_bytes(&self.registers);
+        for bank in self.prg_banks {
+            state.write_usize(bank);
+        }
+        for bank in self.chr_banks {
+            state.write_usize(bank);
+        }
+
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(self.prg_ram_protect);
+    }
+
+    fn load_state(&mut self, version: u16, state: &mut StateReader) -> Result<(), String> {
+        match version {
+            1 | 2 => {
+                self.nt_mirror_type = NametableMirror::from_u8(state.read_u8()?)?;
+
+                self.irq_counter = state.read_usize()?;
+                self.irq_latch = state.read_usize()?;
+                self.irq_enabled = state.read_bool()?;
+                self.irq_request_flag = state.read_bool()?;
+
+                self.bank_select = state.read_u8()?;
+                state.read_bytes_into(&mut self.registers)?;
+                for bank in self.prg_banks.iter_mut() {
+                    *bank = state.read_usize()?;
+                }
+                for bank in self.chr_banks.iter_mut() {
+                    *bank = state.read_usize()?;
+                }
+
+                state.read_bytes_into(&mut self.prg_ram)?;
+                self.prg_ram_protect = if version >= 2 { state.read_u8()? } else { 0 };
+            }
+
+            _ => return Err(format!("Unknown mapper 4 state version {version}")),
+        }
+
+        Ok(())
+    }
+
+    fn scanline_finished(&mut self) {
+        if self.variant != Mmc3Variant::McAcc {
+            self.clock_irq_counter();
+        }
+    }
+
+    fn sprite_fetches_finished(&mut self) {
+        if self.variant == Mmc3Variant::McAcc {
+            self.clock_irq_counter();
+        }
+    }
+
+    fn irq_requested(&self) -> bool {
+        self.irq_request_flag
+    }
+}
+
+
+impl Mapper4 {
+    // Power on state. MMC3 has no reset line, so this only happens once.
     fn reset(&mut self) {
         self.bank_select = 0;
 
@@ -246,6 +443,8 @@ impl Mapper for Mapper4 {
         self.irq_counter = 0;
         self.irq_latch = 0;
 
+        self.prg_ram_protect = 0;
+
         self.registers.fill(0);
         self.chr_banks.fill(0);
 
@@ -255,7 +454,7 @@ impl Mapper for Mapper4 {
         self.prg_banks[3] = (self.num_prg_banks * 2 - 1) * PRG_BANK_SIZE;
     }
 
-    fn scanline_finished(&mut self) {
+    fn clock_irq_counter(&mut self) {
         if self.irq_counter == 0 {
             self.irq_counter = self.irq_latch;
         } else {
@@ -267,17 +466,53 @@ impl Mapper for Mapper4 {
         }
     }
 
-    fn irq_requested(&self) -> bool {
-        self.irq_request_flag
+    // The MMC6's PRG RAM is switched on and off with bit 5 of $8000
+    fn mmc6_ram_enabled(&self) -> bool {
+        self.bank_select & 0x20 != 0
     }
 
-    fn irq_handled(&mut self) {
-        self.irq_request_flag = false;
+    // Which half of the MMC6's RAM an address is in, and whether that half can
+    // be read and written (from the protect bits written to $A001)
+    fn mmc6_ram_access(&self, addr: u16) -> (bool, bool) {
+        let upper_half = addr & 0x0200 != 0;
+        let (read_bit, write_bit) = if upper_half { (0x80, 0x40) } else { (0x20, 0x10) };
+
+        (self.prg_ram_protect & read_bit != 0, self.prg_ram_protect & write_bit != 0)
     }
-}
 
+    // The 1 KiB of RAM shows up at $7000-$73FF and is mirrored up to $7FFF.
+    // $6000-$6FFF, and all of it while the RAM is disabled, is open bus.
+    fn mmc6_ram_read(&self, addr: u16) -> Option<u8> {
+        if addr < 0x7000 || !self.mmc6_ram_enabled() {
+            return None;
+        }
+
+        // With neither half readable nothing drives the bus, but if just one
+        // half is readable the other reads back as 0
+        if self.prg_ram_protect & 0xA0 == 0 {
+            return None;
+        }
+
+        let (readable, _) = self.mmc6_ram_access(addr);
+
+        Some( if readable { self.prg_ram[(addr & 0x03FF) as usize] } else { 0 } )
+    }
+
+    fn mmc6_ram_write(&mut self, addr: u16, data: u8) -> bool {
+        if addr < 0x7000 || !self.mmc6_ram_enabled() {
+            return false;
+        }
+
+        // Writes only go through if the half is readable as well as writable
+        let (readable, writable) = self.mmc6_ram_access(addr);
+
+        if readable && writable {
+            self.prg_ram[(addr & 0x03FF) as usize] = data;
+        }
+
+        true
+    }
 
-impl Mapper4 {
     fn prg_banks_swapped(&self) -> bool {
         self.bank_select & 0x40 != 0
     }
@@ -318,5 +553,12 @@ impl Mapper4 {
             self.chr_banks[6] = self.registers[4] as usize * CHR_BANK_SIZE;
             self.chr_banks[7] = self.registers[5] as usize * CHR_BANK_SIZE;
         }
+
+        for bank in self.prg_banks {
+            self.bank_warning.check("PRG ROM", bank, self.prg_rom.len());
+        }
+        for bank in self.chr_banks {
+            self.bank_warning.check("CHR ROM", bank, self.chr_rom.len());
+        }
     }
 }
\ No newline at end of file
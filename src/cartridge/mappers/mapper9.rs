@@ -1,5 +1,6 @@
 use crate::cartridge::Cartridge;
-use crate::cartridge::mapper::{Mapper, NametableMirror};
+use crate::cartridge::mapper::{banked_offset, read_banked, BankWarning, Mapper, NametableMirror, PrgLocation};
+use crate::system::savestate::{StateReader, StateWriter};
 
 const PRG_RAM_SIZE: usize = 0x2000;
 const PRG_BANK_SIZE: usize = 0x2000;
@@ -29,6 +30,8 @@ pub struct Mapper9 {
     chr_rom: Vec<u8>,
 
     prg_ram: Vec<u8>,
+
+    bank_warning: BankWarning,
 }
 
 impl Mapper for Mapper9 {
@@ -48,11 +51,11 @@ impl Mapper for Mapper9 {
         self.prg_ram = vec![0; PRG_RAM_SIZE];
 
         // The PRG ROM is split into 4 8KiB chunks. The highest 3 are fixed to the
-        // last 3 banks in the cartridge.
-        self.prg_bank_select_hi = self.num_prg_banks * 2 - 3;
+        // last 3 banks in the cartridge (or as many as there are).
+        self.prg_bank_select_hi = (self.num_prg_banks * 2).saturating_sub(3);
     }
 
-    fn cpu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_cart_peek(&self, addr: u16) -> Option<u8> {
         match addr {
             // PRG RAM
             0x6000..=0x7FFF => {
@@ -65,21 +68,36 @@ impl Mapper for Mapper9 {
             0x8000..=0x9FFF => {
                 let mapped_addr = self.prg_bank_select_lo * PRG_BANK_SIZE + (addr & 0x1FFF) as usize;
 
-                Some( self.prg_rom[mapped_addr] )
+                Some( read_banked(&self.prg_rom, mapped_addr) )
             }
 
             // 2nd, 3rd, & 4th PRG ROM Banks (Fixed to last 3 banks)
             0xA000..=0xFFFF => {
                 let mapped_addr = self.prg_bank_select_hi * PRG_BANK_SIZE + (addr - 0xA000) as usize;
 
-                Some( self.prg_rom[mapped_addr] )
+                Some( read_banked(&self.prg_rom, mapped_addr) )
             }
 
             _ => None,
         }
     }
 
-    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8> {
+    fn cpu_prg_location(&self, addr: u16) -> Option<PrgLocation> {
+        let mapped_addr = match addr {
+            0x6000..=0x7FFF => return Some( PrgLocation::Ram((addr & 0x1FFF) as usize) ),
+            0x8000..=0x9FFF => self.prg_bank_select_lo * PRG_BANK_SIZE + (addr & 0x1FFF) as usize,
+            0xA000..=0xFFFF => self.prg_bank_select_hi * PRG_BANK_SIZE + (addr - 0xA000) as usize,
+            _ => return None,
+        };
+
+        Some( PrgLocation::Rom(banked_offset(&self.prg_rom, mapped_addr)) )
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        PRG_BANK_SIZE
+    }
+
+    fn ppu_cart_peek(&self, addr: u16) -> Option<u8> {
         match addr {
             // CHR ROM Bank Low (One of two banks depending on state of the low latch)
             0x0000..=0x0FFF => {
@@ -91,16 +109,7 @@ impl Mapper for Mapper9 {
 
                 let mapped_addr = chr_bank_select_lo * CHR_BANK_SIZE + addr as usize;
 
-                let data = Some( self.chr_rom[mapped_addr] );
-
-                // PPU reads from these addresses set the low latch
-                if addr == 0x0FD8 {
-                    self.chr_latch_lo = false;
-                } else if addr == 0x0FE8 {
-                    self.chr_latch_lo = true;
-                }
-
-                data
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
 
             // CHR ROM Bank High (One of two banks depending on state of the high latch)
@@ -113,21 +122,29 @@ impl Mapper for Mapper9 {
 
                 let mapped_addr = chr_bank_select_hi * CHR_BANK_SIZE + (addr - 0x1000) as usize;
 
-                let data = Some( self.chr_rom[mapped_addr] );
-
-                // PPU reads from these addresses set the high latch
-                if 0x1FD8 <= addr && addr <= 0x1FDF {
-                    self.chr_latch_hi = false;
-                } else if 0x1FE8 <= addr && addr <= 0x1FEF {
-                    self.chr_latch_hi = true;
-                }
-
-                data
+                Some( read_banked(&self.chr_rom, mapped_addr) )
             }
             
             _ => None,
         }
     }
+    fn ppu_cart_read(&mut self, addr: u16) -> Option<u8> {
+        let data = self.ppu_cart_peek(addr);
+
+        // PPU reads from these addresses flip the latches, after the read
+        // https://www.nesdev.org/wiki/MMC2#CHR_banking
+        if addr == 0x0FD8 {
+            self.chr_latch_lo = false;
+        } else if addr == 0x0FE8 {
+            self.chr_latch_lo = true;
+        } else if 0x1FD8 <= addr && addr <= 0x1FDF {
+            self.chr_latch_hi = false;
+        } else if 0x1FE8 <= addr && addr <= 0x1FEF {
+            self.chr_latch_hi = true;
+        }
+
+        data
+    }
 
     fn cpu_cart_write(&mut self, addr: u16, data: u8) -> bool {
         match addr {
@@ -141,6 +158,7 @@ impl Mapper for Mapper9 {
             // PRG ROM Bank Select Low
             0xA000..=0xAFFF => {
                 self.prg_bank_select_lo = (data & 0x0F) as usize;
+                self.bank_warning.check("PRG ROM", self.prg_bank_select_lo * PRG_BANK_SIZE, self.prg_rom.len());
 
                 true
             }
@@ -148,6 +166,7 @@ impl Mapper for Mapper9 {
             // CHR ROM Low Bank Select (Latch off)
             0xB000..=0xBFFF => {
                 self.chr_bank_lo_latch_off = (data & 0x1F) as usize;
+                self.bank_warning.check("CHR ROM", self.chr_bank_lo_latch_off * CHR_BANK_SIZE, self.chr_rom.len());
             
                 true
             }
@@ -155,6 +174,7 @@ impl Mapper for Mapper9 {
             // CHR ROM Low Bank Select (Latch on)
             0xC000..=0xCFFF => {
                 self.chr_bank_lo_latch_on = (data & 0x1F) as usize;
+                self.bank_warning.check("CHR ROM", self.chr_bank_lo_latch_on * CHR_BANK_SIZE, self.chr_rom.len());
             
                 true
             }
@@ -162,6 +182,7 @@ impl Mapper for Mapper9 {
             // CHR ROM High Bank Select (Latch off)
             0xD000..=0xDFFF => {
                 self.chr_bank_hi_latch_off = (data & 0x1F) as usize;
+                self.bank_warning.check("CHR ROM", self.chr_bank_hi_latch_off * CHR_BANK_SIZE, self.chr_rom.len());
             
                 true
             }
@@ -169,6 +190,7 @@ impl Mapper for Mapper9 {
             // CHR ROM High Bank Select (Latch on)
             0xE000..=0xEFFF => {
                 self.chr_bank_hi_latch_on = (data & 0x1F) as usize;
+                self.bank_warning.check("CHR ROM", self.chr_bank_hi_latch_on * CHR_BANK_SIZE, self.chr_rom.len());
             
                 true
             }
@@ -188,6 +210,11 @@ impl Mapper for Mapper9 {
         }
     }
 
+    // Nothing is at $8000-$9FFF
+    fn cpu_write_has_target(&self, addr: u16) -> bool {
+        matches!(addr, 0x6000..=0x7FFF | 0xA000..=0xFFFF)
+    }
+
     fn ppu_cart_write(&mut self, _addr: u16, _data: u8) -> bool {
         false
     }
@@ -195,4 +222,68 @@ impl Mapper for Mapper9 {
     fn get_nt_mirror_type(&self) -> NametableMirror {
         self.nt_mirror_type
     }
+
+    fn mapper_num(&self) -> u16 {
+        9
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn mapper_name(&self) -> &'static str {
+        "MMC2"
+    }
+
+    fn debug_state(&self) -> Vec<(String, String)> {
+        let latch = |on: bool| if on { "FE" } else { "FD" };
+
+        vec![
+            (String::from("PRG bank"), format!("{}", self.prg_bank_select_lo)),
+            (String::from("CHR $0000"), format!("FD:{} FE:{} ({})", self.chr_bank_lo_latch_off, self.chr_bank_lo_latch_on, latch(self.chr_latch_lo))),
+            (String::from("CHR $1000"), format!("FD:{} FE:{} ({})", self.chr_bank_hi_latch_off, self.chr_bank_hi_latch_on, latch(self.chr_latch_hi))),
+        ]
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.nt_mirror_type.to_u8());
+
+        state.write_usize(self.prg_bank_select_lo);
+        state.write_usize(self.prg_bank_select_hi);
+        state.write_usize(self.chr_bank_lo_latch_off);
+        state.write_usize(self.chr_bank_lo_latch_on);
+        state.write_usize(self.chr_bank_hi_latch_off);
+        state.write_usize(self.chr_bank_hi_latch_on);
+        state.write_bool(self.chr_latch_lo);
+        state.write_bool(self.chr_latch_hi);
+
+        state.write_bytes(&self.prg_ram);
+    }
+
+    fn load_state(&mut self, version: u16, state: &mut StateReader) -> Result<(), String> {
+        match version {
+            1 => {
+                self.nt_mirror_type = NametableMirror::from_u8(state.read_u8()?)?;
+
+                self.prg_bank_select_lo = state.read_usize()?;
+                self.prg_bank_select_hi = state.read_usize()?;
+                self.chr_bank_lo_latch_off = state.read_usize()?;
+                self.chr_bank_lo_latch_on = state.read_usize()?;
+                self.chr_bank_hi_latch_off = state.read_usize()?;
+                self.chr_bank_hi_latch_on = state.read_usize()?;
+                self.chr_latch_lo = state.read_bool()?;
+                self.chr_latch_hi = state.read_bool()?;
+
+                state.read_bytes_into(&mut self.prg_ram)?;
+            }
+
+            _ => return Err(format!("Unknown mapper 9 state version {version}")),
+        }
+
+        Ok(())
+    }
 }
@@ -1,8 +1,11 @@
+pub mod archive;
 pub mod cartridge;
+pub mod compat;
+pub mod hash;
 pub mod mapper;
 pub mod mappers;
+pub mod patch;
 
 pub use cartridge::Cartridge;
-pub use cartridge::Header;
 pub use mapper::Mapper;
 
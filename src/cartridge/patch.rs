@@ -0,0 +1,259 @@
+use std::{fs, path::Path};
+
+use super::{archive, hash};
+
+/*
+ON HOW SOFTPATCHING WORKS:
+
+Romhacks and fan translations are shared as patches rather than whole ROMs: a
+small file saying which bytes of the original .nes file to change. Rather than
+having to patch a copy of the game with another tool first, the emulator can
+apply a patch as the game is loaded. The file on disk is never touched, so
+taking the patch away (or swapping it for another) gives back the original.
+
+A patch is used when it's given with --patch, or when a file with the ROM's
+name and an .ips or .bps extension sits next to it (game.nes and game.ips).
+Patches apply to the whole .nes file, header included, since that's what
+patching tools work on.
+
+    IPS  "PATCH", then records of a 3 byte offset, a 2 byte length and that
+         many bytes to write there, ending with "EOF". A length of 0 means a
+         run: a 2 byte count and the one byte to repeat. Writing past the end
+         of the ROM makes it bigger, and an optional 3 byte length after "EOF"
+         cuts it down.
+         https://zerosoft.zophar.net/ips.php
+    BPS  "BPS1", the sizes of the source and target, then actions that build
+         the patched ROM out of runs copied from the original, from the patch,
+         or from earlier in the patched ROM itself. It ends with the CRC-32s
+         of the original, the result and the patch, so a BPS patch made for a
+         different dump of the game is turned away rather than making a mess.
+         https://www.romhacking.net/documents/746/
+*/
+
+// The extensions a patch next to a ROM can have, in the order they're looked for
+const PATCH_EXTENSIONS: [&str; 2] = ["ips", "bps"];
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+// Source, target and patch CRC-32s
+const BPS_FOOTER_LEN: usize = 12;
+
+/// Reads the ROM at a path (see archive) and applies the given patch to it,
+/// or with None, one found next to it. Gives back the patch that was applied,
+/// if there was one.
+pub fn read_patched_rom(cart_path: &str, patch_path: Option<&str>) -> Result<(Vec<u8>, Option<String>), String> {
+    let data = archive::read_rom(cart_path)?;
+
+    let Some(patch_path) = patch_path.map(String::from).or_else(|| find_patch(cart_path)) else {
+        return Ok((data, None));
+    };
+
+    let patch_data = fs::read(&patch_path)
+        .map_err(|e| format!("Could not read patch '{patch_path}': {e}"))?;
+    let data = apply_patch(&data, &patch_data)
+        .map_err(|e| format!("Could not apply patch '{patch_path}': {e}"))?;
+
+    Ok((data, Some(patch_path)))
+}
+
+/// Finds a patch sitting next to a ROM (or the archive it's in) with the same
+/// name (see "ON HOW SOFTPATCHING WORKS")
+pub fn find_patch(cart_path: &str) -> Option<String> {
+    let file_path = Path::new(archive::file_path(cart_path));
+    let rom_name = archive::rom_name(cart_path);
+
+    PATCH_EXTENSIONS.iter()
+        .map(|extension| file_path.with_file_name(format!("{rom_name}.{extension}")))
+        .find(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Applies an IPS or BPS patch to the bytes of a ROM, telling which it is from
+/// the start of the patch
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(String::from("Patch isn't an IPS or BPS file"))
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut patched = rom.to_vec();
+    let mut reader = PatchReader::new(&patch[IPS_MAGIC.len()..]);
+
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = usize::from_be_bytes([0, 0, 0, 0, 0, offset[0], offset[1], offset[2]]);
+        let len = reader.u16_be()? as usize;
+
+        let data = match len {
+            0 => {
+                let count = reader.u16_be()? as usize;
+                vec![reader.byte()?; count]
+            }
+            _ => reader.bytes(len)?.to_vec(),
+        };
+
+        if patched.len() < offset + data.len() {
+            patched.resize(offset + data.len(), 0);
+        }
+        patched[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    // Some patches also cut the ROM down to a length
+    if let Ok(len) = reader.bytes(3) {
+        patched.truncate(usize::from_be_bytes([0, 0, 0, 0, 0, len[0], len[1], len[2]]));
+    }
+
+    Ok(patched)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_LEN {
+        return Err(String::from("BPS patch is cut short"));
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - BPS_FOOTER_LEN);
+    let footer_crc = |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+    let (source_crc, target_crc, patch_crc) = (footer_crc(0), footer_crc(4), footer_crc(8));
+
+    if hash::crc32(&patch[..patch.len() - 4]) != patch_crc {
+        return Err(String::from("BPS patch is corrupt (its checksum doesn't match)"));
+    }
+    if hash::crc32(rom) != source_crc {
+        return Err(format!(
+            "BPS patch is for a different dump of the game (expected CRC-32 {source_crc:08X}, the ROM is {:08X})",
+            hash::crc32(rom)
+        ));
+    }
+
+    let mut reader = PatchReader::new(&body[BPS_MAGIC.len()..]);
+
+    let _source_len = reader.number()?;
+    let target_len = reader.number()?;
+    let metadata_len = reader.number()?;
+    reader.bytes(metadata_len)?;
+
+    let mut target = Vec::new();
+    // Where the next SourceCopy and TargetCopy read from
+    let mut source_pos = 0usize;
+    let mut target_pos = 0usize;
+
+    while !reader.at_end() {
+        let action = reader.number()?;
+        let len = (action >> 2) + 1;
+
+        if target.len().saturating_add(len) > target_len {
+            return Err(String::from("BPS patch writes past the end of the ROM it makes"));
+        }
+
+        match action & 3 {
+            // SourceRead: the same bytes as the original, in the same place
+            0 => {
+                let start = target.len();
+                let run = rom.get(start..start + len).ok_or("BPS patch reads past the end of the ROM")?;
+                target.extend_from_slice(run);
+            }
+            // TargetRead: bytes from the patch
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy: bytes from somewhere else in the original
+            2 => {
+                source_pos = reader.offset(source_pos)?;
+                let run = rom.get(source_pos..source_pos.saturating_add(len))
+                    .ok_or("BPS patch reads past the end of the ROM")?;
+                target.extend_from_slice(run);
+                source_pos += len;
+            }
+            // TargetCopy: bytes already written, which can overlap the ones
+            // being written to repeat a pattern
+            _ => {
+                target_pos = reader.offset(target_pos)?;
+                for _ in 0..len {
+                    let byte = *target.get(target_pos).ok_or("BPS patch copies bytes it hasn't written yet")?;
+                    target.push(byte);
+                    target_pos += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_len || hash::crc32(&target) != target_crc {
+        return Err(String::from("BPS patch didn't produce the ROM it was meant to"));
+    }
+
+    Ok(target)
+}
+
+// Reads the fields of a patch in order
+struct PatchReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.pos..self.pos.saturating_add(len)).ok_or("Patch is cut short")?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    // BPS numbers are 7 bits a byte, lowest first, with the top bit set on
+    // the last byte. Each byte after the first also adds one to everything
+    // above it, so no number has two encodings.
+    fn number(&mut self) -> Result<usize, String> {
+        let mut number = 0usize;
+        let mut shift = 1usize;
+
+        loop {
+            let byte = self.byte()?;
+            number = (byte as usize & 0x7F).checked_mul(shift)
+                .and_then(|part| number.checked_add(part))
+                .ok_or("BPS patch has a number too big to use")?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(0x80).ok_or("BPS patch has a number too big to use")?;
+            number = number.checked_add(shift).ok_or("BPS patch has a number too big to use")?;
+        }
+    }
+
+    // A BPS copy offset: a distance to move from pos, with the lowest bit
+    // saying which way
+    fn offset(&mut self, pos: usize) -> Result<usize, String> {
+        let number = self.number()?;
+        let distance = number >> 1;
+
+        let moved = match number & 1 {
+            0 => pos.checked_add(distance),
+            _ => pos.checked_sub(distance),
+        };
+
+        moved.ok_or(String::from("BPS patch copies from outside the ROM"))
+    }
+}
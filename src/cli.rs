@@ -0,0 +1,625 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::RuntimeConfig;
+use crate::system::apu::SampleRate;
+use crate::system::apu_util::{Mixer, Synthesis};
+use crate::system::breakpoints::WriteBreakpoint;
+use crate::system::bus::RamFill;
+use crate::system::ppu::SpriteEvaluation;
+use crate::system::cheats::RamFreeze;
+use crate::system::controller::ExpansionDevice;
+use crate::system::cpu::CpuMode;
+use crate::system::nes_graphics::ColorFilter;
+
+pub const USAGE: &str = "\
+usage: nes_emulator [command] <rom.nes|rom.zip[#name.nes]> [options]
+       nes_emulator [command] --testpattern [options]
+       nes_emulator batch <rom folder> [options]
+
+commands:
+    run <rom>                         play a game (the default if no command is given)
+    test <rom>                        run a blargg-style test ROM and report the result
+    record <rom> --movie <out.fm2>    play a game, recording both players' input to a
+                                      movie file
+    playback <rom> --movie <in.fm2>   play back a recorded movie
+    headless <rom> --frames <n>       run without a window or sound
+    compare <rom> --toggle <option>   run two copies side by side, one with the option
+                                      flipped, and report the first frame they differ
+    compare <rom> --against <log>     check a run against the state checksums another
+                                      run logged with --checksums
+    batch <folder>                    run every ROM in a folder headlessly and report
+                                      which ones crash, jam, or show a blank screen
+
+options:
+    -d,  --debug                 enable the debug view and debug hotkeys
+    -nl, --nolimit               run as fast as possible
+    -ll, --lowlatency            use small, adaptive audio batches
+         --sync <timer|audio>    pace frames with a timer, or by how fast the audio plays
+         --samplerate <44100|48000|96000>
+                                 rate the audio is put out at (default 44100)
+         --synth <bandlimited|naive>
+                                 how the pulse and triangle waves are made (default bandlimited)
+         --mixer <nonlinear|linear>
+                                 how the audio channels are mixed (default nonlinear)
+         --swapduty              swap the 25% and 50% pulse duty cycles, like some Famicom clones
+         --batch <samples>       audio batch size
+         --queue <samples>       number of audio samples to keep queued
+         --dmcglitch             let DMC sample fetches corrupt controller reads
+         --sprites <limited|authentic|unlimited>
+                                 8 sprites a line, 8 with the hardware's overflow bug, or
+                                 no limit (so nothing flickers) (default limited)
+         --cpu <accurate|cached> read every instruction off the bus, or cache decoded ones
+                                 (faster, for fast-forwarding and batch runs) (default accurate)
+         --writeassert           log PPUCTRL/PPUSCROLL/PPUADDR writes made outside vblank
+         --break <addr[&mask][=value][@midframe]>
+                                 pause when the CPU writes a matching value, in hex (like
+                                 2001&18=00@midframe), can be given more than once
+         --hidebg                leave the background out of the picture
+         --hidesprites           leave the sprites out of the picture
+         --ramfill <00|ff|random[:seed]>
+                                 what RAM holds at power on (default 00)
+         --warmup                have the PPU ignore writes for a while after power on/reset
+         --patch <file.ips|bps>  apply a patch to the ROM as it's loaded (by default,
+                                 one with the ROM's name next to it is used)
+         --movie <file.fm2>      movie to record to / play back from
+         --fromstate <in.state>  start the recording from a save state, which is kept
+                                 in the movie (record)
+         --ghost <file.fm2>      race a recorded run, drawn see-through over the game
+         --frames <n>            number of frames to run (headless, test and batch)
+         --screenshot <out.png>  save the last frame as a png (headless)
+         --savestate <out.state> save the state after the last frame (headless)
+         --capture <game|debug|pagetables|nametables|oam>
+                                 what the screenshot is of (default game)
+         --toggle <dmcglitch|warmup|ramfill|sprites|cpu>
+                                 the option flipped in the second copy (compare)
+         --checksums <out.txt>   log a checksum of the state after every frame
+                                 (headless and compare)
+         --against <log.txt>     compare against a checksum log instead of a second copy
+         --report <out.csv|out.html>
+                                 where to write the compatibility report (batch)
+         --region <ntsc|pal|dendy>
+         --palette <file.pal>    use a custom system palette
+         --expansion <none|keyboard|arkanoid>
+                                 plug a Family BASIC keyboard or Arkanoid controller
+                                 into the expansion port
+         --cheat <addr:value>    hold a byte of RAM at a value, in hex (like 0075:09),
+                                 can be given more than once
+         --testpattern           show color bars for every palette, emphasis and
+                                 greyscale setting instead of a game (no ROM needed)
+         --scale <n>             window size as a multiple of the NES resolution
+         --crop <full|left|overscan|safe>
+                                 hide the left column, the top/bottom 8 rows, or both
+         --rotate <0|90|180|270> turn the picture clockwise, for monitors on their side
+         --mirror                flip the picture left to right
+         --colorfilter <none|protan|deutan|protan-sim|deutan-sim>
+                                 adjust (or, with -sim, preview) the game's colors
+                                 for protanopia or deuteranopia
+         --uipalette <default|contrast|colorblind>
+                                 colors for the menus and debug view
+    -f,  --fullscreen            start in fullscreen
+         --assets <dir>          replace the menu's sprites/sounds with files from dir
+    -h,  --help                  show this message";
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    #[default]
+    Run,
+    Test,
+    Record,
+    Playback,
+    Headless,
+    Compare,
+    Batch,
+}
+
+impl Command {
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "run" => Some(Self::Run),
+            "test" => Some(Self::Test),
+            "record" => Some(Self::Record),
+            "playback" => Some(Self::Playback),
+            "headless" => Some(Self::Headless),
+            "compare" => Some(Self::Compare),
+            "batch" => Some(Self::Batch),
+            _ => None,
+        }
+    }
+}
+
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "ntsc" => Some(Self::Ntsc),
+            "pal" => Some(Self::Pal),
+            "dendy" => Some(Self::Dendy),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ntsc => "ntsc",
+            Self::Pal => "pal",
+            Self::Dendy => "dendy",
+        }
+    }
+}
+
+/// How the app decides when to run the next frame
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum SyncMode {
+    // Frames run on a 60 Hz timer, with an extra one whenever the audio
+    // queue runs low
+    #[default]
+    Timer,
+    // Frames run whenever the audio queue drops below its target depth, so
+    // the sound card's clock sets the pace. Nothing has to be skipped or
+    // stretched when the screen isn't exactly 60 Hz, since the video just
+    // shows whichever frame is newest.
+    Audio,
+}
+
+impl SyncMode {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "timer" => Some(Self::Timer),
+            "audio" => Some(Self::Audio),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Timer => "timer",
+            Self::Audio => "audio",
+        }
+    }
+}
+
+/// Which edges of the picture are hidden in the game view. Lots of TVs cut off
+/// the edges of the picture, so plenty of games have garbage there (scrolling
+/// glitches in the left column from the PPUMASK clip, attribute artifacts at
+/// the top and bottom).
+/// https://www.nesdev.org/wiki/Overscan
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum CropMode {
+    #[default]
+    Full,
+    // Hide the left 8 pixels
+    LeftColumn,
+    // Hide the top and bottom 8 rows
+    Overscan,
+    // Both of the above
+    SafeArea,
+}
+
+impl CropMode {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "full" | "none" => Some(Self::Full),
+            "left" => Some(Self::LeftColumn),
+            "overscan" => Some(Self::Overscan),
+            "safe" => Some(Self::SafeArea),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::LeftColumn => "left",
+            Self::Overscan => "overscan",
+            Self::SafeArea => "safe",
+        }
+    }
+
+    /// The next crop mode, wrapping back around to Full
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Full => Self::LeftColumn,
+            Self::LeftColumn => Self::Overscan,
+            Self::Overscan => Self::SafeArea,
+            Self::SafeArea => Self::Full,
+        }
+    }
+
+    pub fn hides_left_column(&self) -> bool {
+        matches!(self, Self::LeftColumn | Self::SafeArea)
+    }
+
+    pub fn hides_overscan(&self) -> bool {
+        matches!(self, Self::Overscan | Self::SafeArea)
+    }
+}
+
+/// How far the game's picture is turned (clockwise) before it's shown, for
+/// monitors turned on their side (TATE) and the homebrew made for them. The
+/// menus and on screen messages aren't turned, so they can still be read.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl Rotation {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "0" => Some(Self::None),
+            "90" => Some(Self::Quarter),
+            "180" => Some(Self::Half),
+            "270" => Some(Self::ThreeQuarters),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "0",
+            Self::Quarter => "90",
+            Self::Half => "180",
+            Self::ThreeQuarters => "270",
+        }
+    }
+
+    /// Another quarter turn, wrapping back around to None
+    pub fn next(&self) -> Self {
+        match self {
+            Self::None => Self::Quarter,
+            Self::Quarter => Self::Half,
+            Self::Half => Self::ThreeQuarters,
+            Self::ThreeQuarters => Self::None,
+        }
+    }
+
+    /// Whether the picture ends up on its side, swapping its width and height
+    pub fn swaps_sides(&self) -> bool {
+        matches!(self, Self::Quarter | Self::ThreeQuarters)
+    }
+}
+
+/// What a screenshot is of: the game's picture, the whole debug view, or one of
+/// the debugger's views of PPU memory (see app::capture)
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum CaptureView {
+    #[default]
+    Game,
+    Debug,
+    Pagetables,
+    Nametables,
+    Oam,
+}
+
+impl CaptureView {
+    /// The views of PPU memory, which can be captured on their own
+    pub const PANELS: [Self; 3] = [Self::Pagetables, Self::Nametables, Self::Oam];
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "game" => Some(Self::Game),
+            "debug" => Some(Self::Debug),
+            "pagetables" => Some(Self::Pagetables),
+            "nametables" => Some(Self::Nametables),
+            "oam" => Some(Self::Oam),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Game => "game",
+            Self::Debug => "debug",
+            Self::Pagetables => "pagetables",
+            Self::Nametables => "nametables",
+            Self::Oam => "oam",
+        }
+    }
+}
+
+/// The set of colors the menus, debug view and HUDs are drawn in (see
+/// draw::DebugPalette)
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum UiPalette {
+    #[default]
+    Default,
+    // White text and bright, saturated highlights
+    HighContrast,
+    // Colors that stay apart with any kind of color blindness (no red/green
+    // pairs for on/off and ok/error)
+    ColorBlind,
+}
+
+impl UiPalette {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "default" => Some(Self::Default),
+            "contrast" => Some(Self::HighContrast),
+            "colorblind" => Some(Self::ColorBlind),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::HighContrast => "contrast",
+            Self::ColorBlind => "colorblind",
+        }
+    }
+}
+
+/// An emulation option that changes how accurate the NES is, which the compare
+/// command flips in one of its two copies
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccuracyOption {
+    DmcGlitch,
+    Warmup,
+    // Flips the power on RAM between $00 and $FF (random goes to $00)
+    RamFill,
+    // Flips sprite evaluation between limited and authentic (unlimited goes
+    // to limited)
+    Sprites,
+    // Flips the CPU between accurate and cached
+    Cpu,
+}
+
+impl AccuracyOption {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "dmcglitch" => Some(Self::DmcGlitch),
+            "warmup" => Some(Self::Warmup),
+            "ramfill" => Some(Self::RamFill),
+            "sprites" => Some(Self::Sprites),
+            "cpu" => Some(Self::Cpu),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DmcGlitch => "dmcglitch",
+            Self::Warmup => "warmup",
+            Self::RamFill => "ramfill",
+            Self::Sprites => "sprites",
+            Self::Cpu => "cpu",
+        }
+    }
+
+    /// Flips the option in a config
+    pub fn toggle(&self, config: &mut RuntimeConfig) {
+        match self {
+            Self::DmcGlitch => config.dmc_input_glitch = !config.dmc_input_glitch,
+            Self::Warmup => config.ppu_warmup = !config.ppu_warmup,
+            Self::RamFill => config.ram_fill = match config.ram_fill {
+                RamFill::Value(value) => RamFill::Value(!value),
+                RamFill::Random(_) => RamFill::Value(0x00),
+            },
+            Self::Sprites => config.sprite_evaluation = match config.sprite_evaluation {
+                SpriteEvaluation::Limited => SpriteEvaluation::Authentic,
+                _ => SpriteEvaluation::Limited,
+            },
+            Self::Cpu => config.cpu_mode = match config.cpu_mode {
+                CpuMode::Accurate => CpuMode::Cached,
+                CpuMode::Cached => CpuMode::Accurate,
+            },
+        }
+    }
+}
+
+/// Parses the command line arguments (not including the program name) into a
+/// RuntimeConfig. Returns Ok(None) if the user just asked for the usage message.
+pub fn parse_args(args: &[String]) -> Result<Option<RuntimeConfig>, String> {
+    let mut config = RuntimeConfig::default();
+
+    let mut args = args.iter();
+    let mut cart_path: Option<String> = None;
+    let mut command_given = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+
+            "-d" | "--debug" => config.can_debug = true,
+            "-nl" | "--nolimit" => config.limit_fps = false,
+            "-f" | "--fullscreen" => config.fullscreen = true,
+            "--dmcglitch" => config.dmc_input_glitch = true,
+            "--writeassert" => config.register_write_assert = true,
+            "--break" => {
+                let value = flag_value(arg, args.next())?;
+                config.write_breakpoints.push(WriteBreakpoint::parse(&value)?);
+            }
+            "--hidebg" => config.hide_bg = true,
+            "--hidesprites" => config.hide_sprites = true,
+            "--swapduty" => config.swap_duty_cycles = true,
+            "--warmup" => config.ppu_warmup = true,
+            "--testpattern" => config.test_pattern = true,
+
+            // Low latency mode uses small, adaptive sample batches and a shallow queue
+            "-ll" | "--lowlatency" => {
+                config.audio_batch_size = 256;
+                config.audio_queue_depth = 512;
+                config.adaptive_audio = true;
+            }
+
+            "--batch" => config.audio_batch_size = parse_num(arg, args.next())?,
+            "--queue" => config.audio_queue_depth = parse_num(arg, args.next())?,
+            "--frames" => config.frames = Some(parse_num(arg, args.next())?),
+            "--scale" => {
+                let scale = parse_num(arg, args.next())?;
+                if scale == 0 {
+                    return Err(String::from("--scale must be at least 1"));
+                }
+                config.scale = Some(scale);
+            }
+
+            "--movie" => config.movie_path = Some(flag_value(arg, args.next())?),
+            "--fromstate" => config.movie_anchor_path = Some(flag_value(arg, args.next())?),
+            "--ghost" => config.ghost_path = Some(flag_value(arg, args.next())?),
+            "--patch" => config.patch_path = Some(flag_value(arg, args.next())?),
+            "--screenshot" => config.screenshot_path = Some(flag_value(arg, args.next())?),
+            "--savestate" => config.state_path = Some(flag_value(arg, args.next())?),
+            "--report" => config.report_path = Some(flag_value(arg, args.next())?),
+            "--checksums" => config.checksum_path = Some(flag_value(arg, args.next())?),
+            "--against" => config.checksum_against = Some(flag_value(arg, args.next())?),
+            "--palette" => config.palette_path = Some(flag_value(arg, args.next())?),
+            "--assets" => config.assets_dir = Some(flag_value(arg, args.next())?),
+            "--crop" => {
+                let value = flag_value(arg, args.next())?;
+                config.crop = CropMode::from_name(&value)
+                    .ok_or(format!("Unknown crop mode '{value}' (expected full, left, overscan, or safe)"))?;
+            }
+            "--rotate" => {
+                let value = flag_value(arg, args.next())?;
+                config.rotation = Rotation::from_name(&value)
+                    .ok_or(format!("Unknown rotation '{value}' (expected 0, 90, 180, or 270)"))?;
+            }
+            "--mirror" => config.mirror = true,
+            "--capture" => {
+                let value = flag_value(arg, args.next())?;
+                config.capture_view = CaptureView::from_name(&value)
+                    .ok_or(format!("Unknown capture view '{value}' (expected game, debug, pagetables, nametables, or oam)"))?;
+            }
+            "--toggle" => {
+                let value = flag_value(arg, args.next())?;
+                config.compare_toggle = Some(AccuracyOption::from_name(&value)
+                    .ok_or(format!("Unknown option to toggle '{value}' (expected dmcglitch, warmup, ramfill, sprites, or cpu)"))?);
+            }
+            "--expansion" => {
+                let value = flag_value(arg, args.next())?;
+                config.expansion_device = ExpansionDevice::from_name(&value)
+                    .ok_or(format!("Unknown expansion device '{value}' (expected none, keyboard, or arkanoid)"))?;
+            }
+            "--cheat" => {
+                let value = flag_value(arg, args.next())?;
+                config.cheats.push(RamFreeze::parse(&value)?);
+            }
+            "--colorfilter" => {
+                let value = flag_value(arg, args.next())?;
+                config.color_filter = ColorFilter::from_name(&value)
+                    .ok_or(format!("Unknown color filter '{value}' (expected none, protan, deutan, protan-sim, or deutan-sim)"))?;
+            }
+            "--uipalette" => {
+                let value = flag_value(arg, args.next())?;
+                config.ui_palette = UiPalette::from_name(&value)
+                    .ok_or(format!("Unknown UI palette '{value}' (expected default, contrast, or colorblind)"))?;
+            }
+            "--sprites" => {
+                let value = flag_value(arg, args.next())?;
+                config.sprite_evaluation = SpriteEvaluation::from_name(&value)
+                    .ok_or(format!("Unknown sprite evaluation '{value}' (expected limited, authentic, or unlimited)"))?;
+            }
+            "--cpu" => {
+                let value = flag_value(arg, args.next())?;
+                config.cpu_mode = CpuMode::from_name(&value)
+                    .ok_or(format!("Unknown CPU mode '{value}' (expected accurate or cached)"))?;
+            }
+            "--ramfill" => {
+                let value = flag_value(arg, args.next())?;
+                config.ram_fill = RamFill::from_name(&value, clock_seed)
+                    .ok_or(format!("Unknown RAM fill '{value}' (expected a hex byte like 00 or ff, random, or random:<seed>)"))?;
+            }
+            "--sync" => {
+                let value = flag_value(arg, args.next())?;
+                config.sync_mode = SyncMode::from_name(&value)
+                    .ok_or(format!("Unknown sync mode '{value}' (expected timer or audio)"))?;
+            }
+            "--samplerate" => {
+                let value = flag_value(arg, args.next())?;
+                config.sample_rate = SampleRate::from_name(&value)
+                    .ok_or(format!("Unknown sample rate '{value}' (expected 44100, 48000, or 96000)"))?;
+            }
+            "--synth" => {
+                let value = flag_value(arg, args.next())?;
+                config.synthesis = Synthesis::from_name(&value)
+                    .ok_or(format!("Unknown synthesis '{value}' (expected bandlimited or naive)"))?;
+            }
+            "--mixer" => {
+                let value = flag_value(arg, args.next())?;
+                config.mixer = Mixer::from_name(&value)
+                    .ok_or(format!("Unknown mixer '{value}' (expected nonlinear or linear)"))?;
+            }
+            "--region" => {
+                let value = flag_value(arg, args.next())?;
+                config.region = Region::from_name(&value)
+                    .ok_or(format!("Unknown region '{value}' (expected ntsc, pal, or dendy)"))?;
+            }
+
+            flag if flag.starts_with('-') => return Err(format!("Unknown option '{flag}'")),
+
+            // The first bare word may be a command, anything after that is the rom
+            word => {
+                if !command_given && cart_path.is_none() {
+                    if let Some(command) = Command::from_str(word) {
+                        config.command = command;
+                        command_given = true;
+                        continue;
+                    }
+                }
+
+                if cart_path.is_some() {
+                    return Err(format!("Unexpected argument '{word}'"));
+                }
+                cart_path = Some(word.to_string());
+            }
+        }
+    }
+
+    config.cart_path = match cart_path {
+        Some(path) => path,
+        None if config.test_pattern => String::new(),
+        None if config.command == Command::Batch => return Err(String::from("No ROM folder given")),
+        None => return Err(String::from("No ROM file given")),
+    };
+
+    match config.command {
+        Command::Record | Command::Playback if config.movie_path.is_none() => {
+            return Err(String::from("record and playback need a movie file (--movie <file.fm2>)"));
+        }
+        _ if config.movie_anchor_path.is_some() && config.command != Command::Record => {
+            return Err(String::from("--fromstate only works when recording a movie"));
+        }
+        Command::Batch if config.patch_path.is_some() => {
+            return Err(String::from("batch can't apply one patch to a whole folder (leave patches next to their ROMs instead)"));
+        }
+        Command::Headless if config.frames.is_none() => {
+            return Err(String::from("headless needs a frame count (--frames <n>)"));
+        }
+        _ => {}
+    }
+
+    if config.region != Region::Ntsc {
+        println!("Warning: only NTSC timing is emulated right now, running as NTSC");
+    }
+
+    Ok(Some(config))
+}
+
+// Gets the value following a flag like "--movie out.fm2"
+fn flag_value(flag: &str, value: Option<&String>) -> Result<String, String> {
+    value.cloned().ok_or(format!("{flag} needs a value"))
+}
+
+// A seed for "--ramfill random" that's different every run
+fn clock_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+}
+
+// Gets and parses the number following a flag like "--batch 512"
+fn parse_num(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    let value = flag_value(flag, value)?;
+
+    value.parse::<usize>()
+        .map_err(|_| format!("Invalid value for {flag}: {value}"))
+}
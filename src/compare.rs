@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::{
+    headless::{headless_nes, save_checksum_log},
+    system::{checksum::StateChecksum, movie::Movie, nes::{Nes, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH}},
+    RuntimeConfig,
+};
+
+/*
+ON HOW A/B COMPARISONS WORK:
+
+The compare command is for checking what an accuracy option (or a change to the
+CPU/PPU cores) actually does to a game. It powers on two NESes with the same
+game, A with the options from the command line and B with the same options but
+one of them flipped (--toggle), and runs them side by side in lockstep. Both get
+the same input every frame: both players' buttons from --movie, or nothing. A
+movie that starts from a save state has it loaded into both first.
+
+After each frame, both pictures, both copies of RAM and both state checksums
+are compared:
+
+    Picture  every pixel of the finished frame
+    RAM      the 2KiB of internal RAM ($0000-$07FF) and the cartridge's work
+             RAM ($6000-$7FFF), read with peek_cpu so nothing is disturbed
+    State    the checksum of each part of the system (see system::checksum),
+             which also covers the CPU and PPU registers, VRAM, OAM, the
+             mapper and the APU
+
+The first frame where anything differs is reported, with how many pixels and
+which RAM addresses changed, which parts of the state differ and where both
+CPUs were, and the run stops there (after that the two usually only drift
+further apart). The state tends to diverge frames before the picture does, so
+it points closer to the cause.
+
+Without --toggle both NESes are set up the same, which checks that a run can be
+repeated at all: if they ever differ, something in the emulator isn't
+deterministic.
+
+With --screenshot, the first differing frame is saved as one png with A on the
+left, B in the middle, and on the right A dimmed with every differing pixel
+lit up in magenta.
+
+The two runs don't have to be in the same process. headless and compare both
+take --checksums to log A's state checksum after every frame, and compare
+--against checks a run against such a log (from another build, another
+machine, or a netplay peer) instead of running B, reporting the first frame
+and the parts of the state that disagree.
+*/
+
+// How long to compare for when no frame count is given (a minute of NES time)
+const DEFAULT_COMPARE_FRAMES: usize = 60 * 60;
+
+// The RAM compared between the two NESes: internal RAM and cartridge work RAM
+const COMPARED_RAM: [std::ops::Range<u16>; 2] = [0x0000..0x0800, 0x6000..0x8000];
+
+// How many differing RAM addresses get printed
+const MAX_LISTED_ADDRESSES: usize = 16;
+
+const DIFF_COLOR: [u8; 4] = [0xFF, 0x00, 0xFF, 0xFF];
+
+/// Where two NESes first disagreed
+struct Divergence {
+    frame: usize,
+    // Indices of the pixels that differ
+    pixels: Vec<usize>,
+    // RAM addresses that differ, with A's value then B's
+    ram: Vec<(u16, u8, u8)>,
+    // The parts of the state whose checksums differ
+    state: Vec<&'static str>,
+}
+
+/// Runs the game on two NESes, the second with the configured option flipped,
+/// until their pictures or RAM differ. Returns an error if they did.
+pub fn run_compare(config: &RuntimeConfig) -> Result<(), String> {
+    if let Some(path) = &config.checksum_against {
+        return run_against_log(config, path);
+    }
+
+    let mut config_b = config.clone();
+    if let Some(option) = config.compare_toggle {
+        option.toggle(&mut config_b);
+    }
+
+    let mut nes_a = headless_nes(config)?;
+    let mut nes_b = headless_nes(&config_b)?;
+
+    let movie = match &config.movie_path {
+        Some(path) => Some(Movie::load(path)?),
+        None => None,
+    };
+    if let Some(movie) = &movie {
+        movie.start(&mut nes_a)?;
+        movie.start(&mut nes_b)?;
+    }
+
+    let frames = config.frames.unwrap_or(DEFAULT_COMPARE_FRAMES);
+
+    match config.compare_toggle {
+        Some(option) => println!("Comparing {frames} frames, with {} flipped in B", option.name()),
+        None => println!("Comparing {frames} frames of two identical NESes"),
+    }
+
+    let mut checksum_log = vec![StateChecksum::log_header()];
+
+    for frame in 0..frames {
+        for nes in [&mut nes_a, &mut nes_b] {
+            let inputs = movie.as_ref().and_then(|m| m.frame(frame))
+                .unwrap_or([nes.controller_state(0), nes.controller_state(1)]);
+
+            nes.advance_frame(inputs);
+            nes.swap_screen_buffers();
+        }
+
+        let checksum_a = nes_a.state_checksum();
+        checksum_log.push(checksum_a.to_log_line(frame));
+
+        if let Some(divergence) = diverged(&nes_a, &nes_b, checksum_a, frame) {
+            report(&nes_a, &nes_b, &divergence);
+
+            if let Some(path) = &config.screenshot_path {
+                save_side_by_side(&nes_a, &nes_b, &divergence, path)?;
+            }
+            if let Some(path) = &config.checksum_path {
+                save_checksum_log(path, &checksum_log)?;
+            }
+
+            return Err(format!("A and B diverged on frame {}", divergence.frame));
+        }
+    }
+
+    println!("A and B matched for all {frames} frames");
+
+    if let Some(path) = &config.checksum_path {
+        save_checksum_log(path, &checksum_log)?;
+    }
+
+    Ok(())
+}
+
+// Runs the game on one NES, checking its state against a checksum log made by
+// another run. Returns an error if they differed.
+fn run_against_log(config: &RuntimeConfig, log_path: &str) -> Result<(), String> {
+    let text = fs::read_to_string(log_path)
+        .map_err(|e| format!("Couldn't read checksum log '{log_path}': {e}"))?;
+    let logged: BTreeMap<usize, StateChecksum> = text.lines().filter_map(StateChecksum::from_log_line).collect();
+
+    let Some(&last_logged) = logged.keys().next_back() else {
+        return Err(format!("No checksums in '{log_path}'"));
+    };
+
+    let mut nes = headless_nes(config)?;
+
+    let movie = match &config.movie_path {
+        Some(path) => Some(Movie::load(path)?),
+        None => None,
+    };
+    if let Some(movie) = &movie {
+        movie.start(&mut nes)?;
+    }
+
+    let frames = config.frames.unwrap_or(last_logged + 1);
+    println!("Checking {frames} frames against {log_path}");
+
+    let mut checksum_log = vec![StateChecksum::log_header()];
+
+    for frame in 0..frames {
+        let inputs = movie.as_ref().and_then(|m| m.frame(frame))
+            .unwrap_or([nes.controller_state(0), nes.controller_state(1)]);
+
+        nes.advance_frame(inputs);
+        nes.swap_screen_buffers();
+
+        let checksum = nes.state_checksum();
+        checksum_log.push(checksum.to_log_line(frame));
+
+        // A log doesn't have to have every frame in it
+        let Some(expected) = logged.get(&frame) else {
+            continue;
+        };
+
+        let differs = expected.diff(&checksum);
+        if differs.is_empty() {
+            continue;
+        }
+
+        println!("Diverged from the log on frame {frame}");
+        println!("  State differs in: {} (log -> this run)", differs.join(", "));
+        for ((name, a), (_, b)) in expected.components().iter().zip(checksum.components()) {
+            if a != &b {
+                println!("    {name:<8}{a:08X} -> {b:08X}");
+            }
+        }
+
+        let cpu = nes.get_cpu_state();
+        println!("  PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} CYC:{}",
+            cpu.pc, cpu.acc, cpu.x, cpu.y, cpu.sp, cpu.total_clocks);
+
+        if let Some(path) = &config.checksum_path {
+            save_checksum_log(path, &checksum_log)?;
+        }
+
+        return Err(format!("Diverged from '{log_path}' on frame {frame}"));
+    }
+
+    println!("Matched the log for all {frames} frames");
+
+    if let Some(path) = &config.checksum_path {
+        save_checksum_log(path, &checksum_log)?;
+    }
+
+    Ok(())
+}
+
+// Compares the last finished frame, RAM and state of the two NESes
+fn diverged(nes_a: &Nes, nes_b: &Nes, checksum_a: StateChecksum, frame: usize) -> Option<Divergence> {
+    let pixels: Vec<usize> = nes_a.screen_buf_slice().chunks_exact(4)
+        .zip(nes_b.screen_buf_slice().chunks_exact(4))
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(i, _)| i)
+        .collect();
+
+    let ram: Vec<(u16, u8, u8)> = COMPARED_RAM.iter().cloned()
+        .flatten()
+        .map(|address| (address, nes_a.peek_cpu(address), nes_b.peek_cpu(address)))
+        .filter(|(_, a, b)| a != b)
+        .collect();
+
+    let state = checksum_a.diff(&nes_b.state_checksum());
+
+    if pixels.is_empty() && ram.is_empty() && state.is_empty() {
+        None
+    } else {
+        Some(Divergence { frame, pixels, ram, state })
+    }
+}
+
+fn report(nes_a: &Nes, nes_b: &Nes, divergence: &Divergence) {
+    println!("A and B diverged on frame {}", divergence.frame);
+
+    if !divergence.pixels.is_empty() {
+        let xs = divergence.pixels.iter().map(|i| i % NES_SCREEN_WIDTH);
+        let ys = divergence.pixels.iter().map(|i| i / NES_SCREEN_WIDTH);
+
+        println!("  {} pixels differ, between ({}, {}) and ({}, {})",
+            divergence.pixels.len(),
+            xs.clone().min().unwrap_or(0), ys.clone().min().unwrap_or(0),
+            xs.max().unwrap_or(0), ys.max().unwrap_or(0));
+    }
+
+    if !divergence.ram.is_empty() {
+        println!("  {} RAM bytes differ (A -> B):", divergence.ram.len());
+
+        for (address, a, b) in divergence.ram.iter().take(MAX_LISTED_ADDRESSES) {
+            println!("    ${address:04X}: {a:02X} -> {b:02X}");
+        }
+        if divergence.ram.len() > MAX_LISTED_ADDRESSES {
+            println!("    ...");
+        }
+    }
+
+    if !divergence.state.is_empty() {
+        println!("  State differs in: {}", divergence.state.join(", "));
+    }
+
+    for (name, nes) in [("A", nes_a), ("B", nes_b)] {
+        let cpu = nes.get_cpu_state();
+        println!("  {name}: PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} CYC:{}",
+            cpu.pc, cpu.acc, cpu.x, cpu.y, cpu.sp, cpu.total_clocks);
+    }
+}
+
+// Saves A, B, and where they differ next to each other
+fn save_side_by_side(nes_a: &Nes, nes_b: &Nes, divergence: &Divergence, path: &str) -> Result<(), String> {
+    let width = NES_SCREEN_WIDTH * 3;
+    let mut pixels = vec![0; width * NES_SCREEN_HEIGHT * 4];
+
+    let mut diff: Vec<u8> = nes_a.screen_buf_slice().iter()
+        .enumerate()
+        .map(|(i, &byte)| if i % 4 == 3 { byte } else { byte / 3 })
+        .collect();
+    for &i in &divergence.pixels {
+        diff[i * 4..i * 4 + 4].copy_from_slice(&DIFF_COLOR);
+    }
+
+    let row_bytes = NES_SCREEN_WIDTH * 4;
+
+    for (panel, screen) in [nes_a.screen_buf_slice(), nes_b.screen_buf_slice(), &diff].iter().enumerate() {
+        for y in 0..NES_SCREEN_HEIGHT {
+            let start = (y * width + panel * NES_SCREEN_WIDTH) * 4;
+            pixels[start..start + row_bytes].copy_from_slice(&screen[y * row_bytes..(y + 1) * row_bytes]);
+        }
+    }
+
+    image::save_buffer(path, &pixels, width as u32, NES_SCREEN_HEIGHT as u32, image::ExtendedColorType::Rgba8)
+        .map_err(|e| format!("Couldn't save comparison '{path}': {e}"))?;
+
+    println!("Saved comparison to {path}");
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+/*
+ON THE PUBLIC API:
+
+This module is everything other crates (frontends, test runners, tools) are
+meant to use. The system and cartridge modules are how the emulator is built
+on the inside, and they're free to change shape between versions, so they
+aren't public. Things here only get removed or changed in a breaking way with
+a new major version; new things can be added whenever.
+
+    Nes         the console. Load a game, run it a frame at a time, reset it.
+    Frame       a finished picture, from Nes::frame
+    Input       the buttons held on one controller, for Nes::advance_frame
+    SaveState   a saved state, from Nes::save_state
+    StateChecksum
+                a checksum of each part of the system, from
+                Nes::state_checksum, for finding where two runs drift apart
+    RomHashes   the CRC-32s and SHA-1s identifying a ROM, for Nes::set_rom_hashes
+    CartInfo    what the loaded cartridge's header says, from Nes::cart_info
+    InputDevice something to plug into a controller port or the expansion
+                port with Nes::plug_device, for devices of your own (like a
+                controller driven by a script)
+    NesEvent    something that happened inside the NES (a frame finishing,
+                vblank, sprite 0 hit, a mapper IRQ...), for subscribers from
+                Nes::subscribe to pick up with Nes::take_events
+    WriteBreakpoint
+                a write to stop on, for Nes::add_write_breakpoint, which
+                comes back as a NesEvent::WriteBreak when it's hit
+    MemoryRegion
+                a part of memory (PRG RAM, CHR RAM, VRAM, OAM, palettes)
+                to copy out with Nes::dump_memory or overwrite with
+                Nes::restore_memory
+    CpuRegion   what's at a CPU address (RAM, a mirror, a PRG ROM bank...),
+                from Nes::cpu_region
+    UnsupportedUse
+                something the game did that isn't emulated (an unstable
+                opcode, a write where the cartridge has nothing...), from
+                Nes::unsupported_uses
+    FilterChain the video filters (NTSC, scanlines, CRT curve...) a frame
+                can go through before it's shown, and VideoFilter for
+                adding filters of your own
+    AgentEnv    a game as a Gym-style environment for an agent to play, with
+                RewardHooks watching memory for its rewards (see "ON THE
+                AGENT INTERFACE" in agent)
+
+Audio comes out through a SampleQueue, given to Nes::set_sample_queue. It's
+kept when games are swapped, so the audio output only needs setting up once.
+
+A minimal frontend loop looks like:
+
+    let mut nes = Nes::default();
+    nes.set_sample_queue(Arc::clone(&audio_queue));
+    nes.load_cart_bytes(&rom, "game")?;
+
+    loop {
+        nes.advance_frame([buttons_held(), Input::default()]);
+        nes.swap_screen_buffers();
+        show(nes.frame().pixels());
+    }
+*/
+
+pub use crate::cartridge::cartridge::{CartInfo, CartRegion};
+pub use crate::cartridge::compat::{CompatDatabase, CompatEntry, CompatStatus};
+pub use crate::cartridge::hash::RomHashes;
+pub use crate::cartridge::mapper::NametableMirror;
+pub use crate::system::agent::{AgentEnv, AgentStep, MemoryCondition, MemoryTest, RewardHook};
+pub use crate::system::apu::SampleRate;
+pub use crate::system::apu_util::{Mixer, NesChannel, Synthesis};
+pub use crate::system::breakpoints::{WriteBreak, WriteBreakpoint};
+pub use crate::system::bus::RamFill;
+pub use crate::system::cheats::RamFreeze;
+pub use crate::system::checksum::StateChecksum;
+pub use crate::system::controller::{
+    ControllerButton as Button, DeviceSlot, ExpansionDevice, ExpansionInput, InputDevice, NesController as Input,
+    SharedDevice, StandardController, Unplugged,
+};
+pub use crate::system::cpu::CpuMode;
+pub use crate::system::events::{EventKind, NesEvent, Subscription};
+pub use crate::system::memory_region::{CpuRegion, MemoryRegion};
+pub use crate::system::movie::Movie;
+pub use crate::system::nes_graphics::ColorFilter;
+pub use crate::system::nes::{Frame, Nes, NES_SCREEN_BUF_SIZE, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH};
+pub use crate::system::ppu::SpriteEvaluation;
+pub use crate::system::sample_ring::{SampleQueue, SampleRing, SAMPLE_RING_CAPACITY};
+pub use crate::system::savestate::SaveState;
+pub use crate::system::telemetry::{UnsupportedFeature, UnsupportedUse};
+pub use crate::system::trace::{TraceEntry, TRACE_LOG_SIZE};
+pub use crate::system::video_filter::{FilterChain, FilterSetting, VideoFilter, VideoFrame};
@@ -0,0 +1,261 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    app::capture::{self, CaptureSettings},
+    cartridge::hash,
+    cli::CaptureView,
+    system::{agent::AgentEnv, checksum::StateChecksum, movie::Movie, nes::{Nes, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH}},
+    RuntimeConfig,
+};
+
+// How long a test ROM is allowed to run before we give up on it (a minute of
+// NES time)
+const DEFAULT_TEST_FRAMES: usize = 60 * 60;
+
+// Blargg's test ROMs report their status through cartridge RAM
+// https://github.com/christopherpow/nes-test-roms/blob/master/instr_test-v5/readme.txt
+const TEST_STATUS_ADDR: u16 = 0x6000;
+const TEST_SIGNATURE_ADDR: u16 = 0x6001;
+const TEST_TEXT_ADDR: u16 = 0x6004;
+const TEST_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const TEST_STATUS_RUNNING: u8 = 0x80;
+const TEST_STATUS_NEEDS_RESET: u8 = 0x81;
+// The test asks to be reset at least 100ms after it requests it
+const TEST_RESET_DELAY_FRAMES: usize = 8;
+
+/// Creates an NES with the config's cart and palette loaded. Audio samples are
+/// thrown away since there's nobody to listen to them.
+pub(crate) fn headless_nes(config: &RuntimeConfig) -> Result<Nes, String> {
+    let mut nes = Nes::default();
+
+    nes.set_ram_fill(config.ram_fill);
+    nes.set_expansion_device(config.expansion_device);
+    nes.set_ppu_warmup(config.ppu_warmup);
+    if config.test_pattern {
+        nes.load_test_pattern()?;
+    } else {
+        nes.load_cart_patched(&config.cart_path, config.patch_path.as_deref())?;
+    }
+    for &freeze in &config.cheats {
+        nes.add_freeze(freeze);
+    }
+    nes.set_block_audio_samples(true);
+    nes.set_audio_sample_rate(config.sample_rate);
+    nes.set_audio_synthesis(config.synthesis);
+    nes.set_audio_mixer(config.mixer);
+    nes.set_duty_cycles_swapped(config.swap_duty_cycles);
+    nes.set_dmc_input_glitch(config.dmc_input_glitch);
+    nes.set_sprite_evaluation(config.sprite_evaluation);
+    nes.set_cpu_mode(config.cpu_mode);
+    nes.set_register_write_assert(config.register_write_assert);
+    nes.set_bg_hidden(config.hide_bg);
+    nes.set_sprites_hidden(config.hide_sprites);
+    nes.set_color_filter(config.color_filter);
+
+    if let Some(path) = &config.palette_path {
+        nes.load_palette(path)?;
+    }
+
+    Ok(nes)
+}
+
+/// Makes an environment for an agent to play the config's game in, set up
+/// the same way as a headless run (see "ON THE AGENT INTERFACE")
+pub fn agent_env(config: &RuntimeConfig) -> Result<AgentEnv, String> {
+    AgentEnv::new(headless_nes(config)?)
+}
+
+/// Runs the NES for the configured number of frames without opening a window,
+/// optionally playing back a movie, then saves a screenshot of the last frame
+/// and the state the NES was left in, if asked to. Every run with the same
+/// options ends on the same frame, so these can be compared between builds
+/// (and with --checksums, so can every frame along the way).
+pub fn run_headless(config: &RuntimeConfig) -> Result<(), String> {
+    let mut nes = headless_nes(config)?;
+
+    let movie = match &config.movie_path {
+        Some(path) => Some(Movie::load(path)?),
+        None => None,
+    };
+    if let Some(movie) = &movie {
+        movie.start(&mut nes)?;
+    }
+
+    let frames = config.frames.unwrap_or(0);
+    let mut checksum_log = config.checksum_path.as_ref().map(|_| vec![StateChecksum::log_header()]);
+
+    for frame in 0..frames {
+        // Past the end of the movie the last input stays held
+        let inputs = movie.as_ref().and_then(|m| m.frame(frame))
+            .unwrap_or([nes.controller_state(0), nes.controller_state(1)]);
+
+        nes.advance_frame(inputs);
+        nes.swap_screen_buffers();
+
+        if let Some(log) = &mut checksum_log {
+            log.push(nes.state_checksum().to_log_line(frame));
+        }
+    }
+
+    println!("Ran {frames} frames");
+
+    if let (Some(path), Some(log)) = (&config.checksum_path, &checksum_log) {
+        save_checksum_log(path, log)?;
+    }
+
+    if let Some(path) = &config.screenshot_path {
+        save_capture(&mut nes, config.capture_view, path)?;
+    }
+
+    if let Some(path) = &config.state_path {
+        fs::write(path, nes.save_state().as_bytes())
+            .map_err(|e| format!("Couldn't save state '{path}': {e}"))?;
+
+        println!("Saved state to {path}");
+    }
+
+    Ok(())
+}
+
+/// Runs a test ROM until it reports a result. Returns an error if the test
+/// failed or never finished.
+pub fn run_test(config: &RuntimeConfig) -> Result<(), String> {
+    let mut nes = headless_nes(config)?;
+    let frames = config.frames.unwrap_or(DEFAULT_TEST_FRAMES);
+
+    let mut reset_countdown: Option<usize> = None;
+    let mut result: Option<u8> = None;
+
+    for _ in 0..frames {
+        nes.cycle_until_frame();
+
+        if let Some(address) = nes.cpu_jammed_at() {
+            return Err(format!("CPU jammed at ${address:04X}"));
+        }
+
+        if !test_signature_present(&nes) {
+            continue;
+        }
+
+        match nes.peek_cpu(TEST_STATUS_ADDR) {
+            TEST_STATUS_RUNNING => {}
+            TEST_STATUS_NEEDS_RESET => {
+                match reset_countdown {
+                    Some(0) => {
+                        nes.reset();
+                        reset_countdown = None;
+                    }
+                    Some(n) => reset_countdown = Some(n - 1),
+                    None => reset_countdown = Some(TEST_RESET_DELAY_FRAMES),
+                }
+            }
+            code => {
+                result = Some(code);
+                break;
+            }
+        }
+    }
+
+    nes.swap_screen_buffers();
+
+    if let Some(path) = &config.screenshot_path {
+        save_capture(&mut nes, config.capture_view, path)?;
+    }
+
+    match result {
+        Some(0) => {
+            println!("{}", test_text(&nes));
+            println!("PASSED");
+            Ok(())
+        }
+        Some(code) => {
+            println!("{}", test_text(&nes));
+            Err(format!("Test failed with code {code}"))
+        }
+        None => Err(format!("Test didn't report a result within {frames} frames")),
+    }
+}
+
+fn test_signature_present(nes: &Nes) -> bool {
+    (0..3).all(|i| nes.peek_cpu(TEST_SIGNATURE_ADDR + i) == TEST_SIGNATURE[i as usize])
+}
+
+// Reads the null terminated string the test writes its output to
+fn test_text(nes: &Nes) -> String {
+    (TEST_TEXT_ADDR..0x8000)
+        .map(|addr| nes.peek_cpu(addr))
+        .take_while(|&c| c != 0)
+        .map(|c| c as char)
+        .collect()
+}
+
+/// Runs a ROM with no input until its picture settles down, and returns that
+/// frame (RGBA). The first `min_frames` frames are skipped, since most games
+/// show a black screen or a logo first. After that the first frame identical to
+/// the one before it is used, or the last frame if nothing settles by
+/// `max_frames`.
+pub fn render_stable_frame(cart_path: &str, min_frames: usize, max_frames: usize) -> Result<Vec<u8>, String> {
+    let config = RuntimeConfig { cart_path: String::from(cart_path), ..Default::default() };
+    let mut nes = headless_nes(&config)?;
+
+    let mut last_hash = None;
+
+    for frame in 0..max_frames {
+        nes.cycle_until_frame();
+        nes.swap_screen_buffers();
+
+        if frame < min_frames {
+            continue;
+        }
+
+        let hash = hash::crc32(nes.screen_buf_slice());
+
+        if last_hash == Some(hash) {
+            break;
+        }
+        last_hash = Some(hash);
+    }
+
+    Ok(nes.screen_buf_slice().to_vec())
+}
+
+/// Saves a picture of one of the views (see app::capture) as a png. The game
+/// view is the most recently finished frame.
+pub fn save_capture(nes: &mut Nes, view: CaptureView, path: &str) -> Result<(), String> {
+    if view == CaptureView::Game {
+        return save_screenshot(nes, path);
+    }
+
+    capture::capture(nes, view, CaptureSettings::default()).save(Path::new(path))?;
+
+    println!("Saved {} capture to {path}", view.name());
+
+    Ok(())
+}
+
+/// Writes the lines of a state checksum log (see system::checksum)
+pub fn save_checksum_log(path: &str, lines: &[String]) -> Result<(), String> {
+    fs::write(path, lines.join("\n") + "\n")
+        .map_err(|e| format!("Couldn't write checksum log '{path}': {e}"))?;
+
+    println!("Saved checksums to {path}");
+
+    Ok(())
+}
+
+/// Saves the most recently finished frame as a png
+pub fn save_screenshot(nes: &Nes, path: &str) -> Result<(), String> {
+    image::save_buffer(
+        path,
+        nes.screen_buf_slice(),
+        NES_SCREEN_WIDTH as u32,
+        NES_SCREEN_HEIGHT as u32,
+        image::ExtendedColorType::Rgba8,
+    )
+    .map_err(|e| format!("Couldn't save screenshot '{path}': {e}"))?;
+
+    println!("Saved screenshot to {path}");
+
+    Ok(())
+}
@@ -1,41 +1,234 @@
+// The emulator core (system and cartridge) only needs std, so it builds for
+// wasm32 too. Everything that needs a window, audio device, or gamepad is
+// behind the "frontend" feature.
+//
+// Other crates should only use what's in core (see core.rs), the rest of the
+// emulator's insides aren't public.
+#[cfg(feature = "frontend")]
 pub mod app;
-pub mod cartridge;
-pub mod system;
+#[cfg(feature = "frontend")]
+pub mod batch;
+pub(crate) mod cartridge;
+pub mod cli;
+pub mod core;
+#[cfg(feature = "frontend")]
+pub mod compare;
+#[cfg(feature = "frontend")]
+pub mod headless;
+pub(crate) mod system;
 
 
+use cli::{AccuracyOption, CaptureView, Command, CropMode, Region, Rotation, SyncMode, UiPalette};
+use system::apu::SampleRate;
+use system::apu_util::{Mixer, Synthesis};
+use system::breakpoints::WriteBreakpoint;
+use system::bus::RamFill;
+use system::cheats::RamFreeze;
+use system::controller::ExpansionDevice;
+use system::cpu::CpuMode;
+use system::nes_graphics::ColorFilter;
+use system::ppu::SpriteEvaluation;
+#[cfg(feature = "frontend")]
 use rodio::{OutputStream, Sink};
+#[cfg(feature = "frontend")]
 use system::apu_util::NesAudioStream;
 // use system::audio::NesAudioHandler;
+#[cfg(feature = "frontend")]
 use winit::event_loop::{ControlFlow, EventLoop};
 
 
-#[derive(Default)]
+#[derive(Clone)]
 pub struct RuntimeConfig {
+    pub command: Command,
     pub cart_path: String,
+    // IPS or BPS patch to apply to the cart (see cartridge::patch)
+    pub patch_path: Option<String>,
     pub limit_fps: bool,
     pub can_debug: bool,
+
+    // Audio batching settings (see Apu2A03::set_batch_config)
+    pub audio_batch_size: usize,
+    pub audio_queue_depth: usize,
+    pub adaptive_audio: bool,
+    // Whether frames are paced by a timer or by the audio queue
+    pub sync_mode: SyncMode,
+    // Rate the audio is put out at, and how the pulse and triangle waves are
+    // generated
+    pub sample_rate: SampleRate,
+    pub synthesis: Synthesis,
+    // How the channels are mixed, and whether the pulse duty cycles are
+    // swapped like on some Famicom clones
+    pub mixer: Mixer,
+    pub swap_duty_cycles: bool,
+
+    // Emulate DMC DMA corrupting controller reads
+    pub dmc_input_glitch: bool,
+    // How sprites are picked for each scanline: the 8 sprite limit, the
+    // overflow bug, or no limit (see ppu::SpriteEvaluation)
+    pub sprite_evaluation: SpriteEvaluation,
+    // Read every instruction off the bus, or cache decoded ones for speed
+    // (see cpu::CpuMode)
+    pub cpu_mode: CpuMode,
+
+    // Flag PPU register writes made outside vblank (see ppu::RasterWrite)
+    pub register_write_assert: bool,
+    // Stop when the CPU makes a matching write (see system::breakpoints)
+    pub write_breakpoints: Vec<WriteBreakpoint>,
+
+    // Leave the background or sprites out of the picture
+    pub hide_bg: bool,
+    pub hide_sprites: bool,
+
+    // Power on state: what RAM starts out holding, and whether the PPU
+    // ignores writes for a while after power on and reset
+    pub ram_fill: RamFill,
+    pub ppu_warmup: bool,
+
+    // Movie to record to or play back from, and the save state a recording
+    // starts from (kept in the movie)
+    pub movie_path: Option<String>,
+    pub movie_anchor_path: Option<String>,
+    // Movie of a past run to race against
+    pub ghost_path: Option<String>,
+    // Headless / test settings
+    pub frames: Option<usize>,
+    pub screenshot_path: Option<String>,
+    // Where headless saves the state the NES ends up in
+    pub state_path: Option<String>,
+    // Where batch writes its compatibility report (.csv or .html)
+    pub report_path: Option<String>,
+    // What the screenshot is of
+    pub capture_view: CaptureView,
+    // The option flipped in the second NES when comparing
+    pub compare_toggle: Option<AccuracyOption>,
+    // Where headless and compare write a state checksum for every frame, and
+    // a log of them for compare to check against instead of a second NES
+    pub checksum_path: Option<String>,
+    pub checksum_against: Option<String>,
+
+    // Display settings
+    pub region: Region,
+    pub palette_path: Option<String>,
+    // Show the built-in test pattern instead of a game
+    pub test_pattern: bool,
+
+    // What's plugged into the Famicom expansion port
+    pub expansion_device: ExpansionDevice,
+    // RAM to hold at a value (see system::cheats)
+    pub cheats: Vec<RamFreeze>,
+    pub scale: Option<usize>,
+    pub fullscreen: bool,
+    pub crop: CropMode,
+    // How the game's picture is turned and flipped (see cli::Rotation)
+    pub rotation: Rotation,
+    pub mirror: bool,
+    // Accessibility: a filter for the game's colors, and the colors the UI
+    // is drawn in
+    pub color_filter: ColorFilter,
+    pub ui_palette: UiPalette,
+
+    // Folder with menu sprites/sounds to use in place of the built-in ones
+    pub assets_dir: Option<String>,
 }
 
-pub fn run(config: RuntimeConfig) {
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            command: Command::Run,
+            cart_path: String::new(),
+            patch_path: None,
+            limit_fps: true,
+            can_debug: false,
+
+            audio_batch_size: system::apu::DEFAULT_SAMPLE_BATCH_SIZE,
+            audio_queue_depth: system::apu::DEFAULT_TARGET_QUEUE_DEPTH,
+            adaptive_audio: false,
+            sync_mode: SyncMode::Timer,
+            sample_rate: SampleRate::default(),
+            synthesis: Synthesis::default(),
+            mixer: Mixer::default(),
+            swap_duty_cycles: false,
+
+            dmc_input_glitch: false,
+            sprite_evaluation: SpriteEvaluation::default(),
+            cpu_mode: CpuMode::default(),
+
+            register_write_assert: false,
+            write_breakpoints: Vec::new(),
+
+            hide_bg: false,
+            hide_sprites: false,
+
+            ram_fill: RamFill::default(),
+            ppu_warmup: false,
+
+            movie_path: None,
+            movie_anchor_path: None,
+            ghost_path: None,
+            frames: None,
+            screenshot_path: None,
+            state_path: None,
+            report_path: None,
+            capture_view: CaptureView::Game,
+            compare_toggle: None,
+            checksum_path: None,
+            checksum_against: None,
+
+            region: Region::Ntsc,
+            palette_path: None,
+            test_pattern: false,
+
+            expansion_device: ExpansionDevice::None,
+            cheats: Vec::new(),
+            scale: None,
+            fullscreen: false,
+            crop: CropMode::Full,
+            rotation: Rotation::None,
+            mirror: false,
+            color_filter: ColorFilter::None,
+            ui_palette: UiPalette::Default,
+
+            assets_dir: None,
+        }
+    }
+}
+
+#[cfg(feature = "frontend")]
+pub fn run(config: RuntimeConfig) -> Result<(), String> {
     env_logger::init();
 
+    match config.command {
+        Command::Test => return headless::run_test(&config),
+        Command::Headless => return headless::run_headless(&config),
+        Command::Compare => return compare::run_compare(&config),
+        Command::Batch => return batch::run_batch(&config),
+        Command::Run | Command::Record | Command::Playback => {}
+    }
+
     let (_output_stream, output_handle) = OutputStream::try_default().unwrap();
     let sink = Sink::try_new(&output_handle).unwrap();
-    let (sound_stream, sample_queue) = NesAudioStream::new();
+    let (sound_stream, sample_queue) = NesAudioStream::new(config.sample_rate);
 
     let event_loop = EventLoop::new().unwrap();
     let mut nes_app = app::NesApp::new();
 
+    // Ctrl+C closes the app the same way the close button does
+    app::shutdown::install_interrupt_handler(event_loop.create_proxy());
+    // Panics while emulating get a crash report instead of closing the app
+    app::crash::install_panic_hook();
+
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    nes_app.init(config, sample_queue);
+    nes_app.init(config, sample_queue)?;
     
-    // Start the sound system
+    // Start the sound system. The app starts the sink once it's set the volume.
+    sink.pause();
     sink.append(sound_stream);
-    sink.play();
 
     nes_app.attatch_sound_sink(sink);
 
     // Run the application
     event_loop.run_app(&mut nes_app).unwrap();
+
+    Ok(())
 }
\ No newline at end of file
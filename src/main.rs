@@ -16,24 +16,27 @@
 */
 use std::env;
 
-use nes_emulator::{self, RuntimeConfig};
+use nes_emulator::{self, cli};
 
 pub fn main() -> Result<(), String> {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    if args.len() < 2 {
-        println!("usage: {} <filename>", args[0]);
+    if args.is_empty() {
+        println!("{}", cli::USAGE);
         return Ok(());
     }
 
-    let mut config = RuntimeConfig::default();
-
-    config.cart_path = args[1].clone();
-    config.limit_fps = !args.contains(&String::from("--nolimit")) && !args.contains(&String::from("-nl"));
-    config.can_debug = args.contains(&String::from("--debug")) || args.contains(&String::from("-d"));
-
-    nes_emulator::run(config);
-
-    Ok(())
+    let config = match cli::parse_args(&args) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Err(e) => {
+            println!("{}", cli::USAGE);
+            return Err(e);
+        }
+    };
+
+    nes_emulator::run(config)
 }
-
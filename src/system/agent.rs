@@ -0,0 +1,307 @@
+use std::ops::Range;
+
+use super::controller::NesController;
+use super::nes::Nes;
+use super::savestate::SaveState;
+
+/*
+ON THE AGENT INTERFACE:
+
+Reinforcement learning experiments (and other programs that play games) want
+the emulator as an environment in the style of OpenAI Gym: reset it to the
+start, then step it over and over with an action, getting back what the agent
+can see and how well it's doing after each step. AgentEnv wraps a Nes with a
+game loaded to work like that, so an agent can be written straight against
+the crate instead of screen-scraping a window or going through FFI.
+
+    reset   puts the NES back in the state it was in when the environment was
+            made, so every episode starts the same
+    step    holds the buttons in the action for a frame (or frame_skip
+            frames), then gives back an AgentStep: the finished picture, a
+            slice of RAM, what each reward hook gave and their total, and
+            whether the episode is done
+
+An action is the buttons on controller 1 as a byte, one bit each in the order
+the NES reads them: A, B, Select, Start, Up, Down, Left, Right from bit 0 up
+(the same bits as Input). step_players takes both controllers.
+
+Rewards come from reward hooks, which watch bytes of memory. The memory
+conditions they test are checked after every frame, against the byte's value
+now and its value after the frame before:
+
+    When    gives its reward on every frame its condition holds. When it's
+            marked done, the condition holding also ends the episode (the
+            lives counter hitting 0, a "level cleared" flag being set...).
+    Delta   gives how much the byte changed, times a scale. For a score or the
+            player's X position, it rewards progress as it happens.
+
+Reads are done with peek_cpu, which has no side effects, so watching memory
+doesn't change what the game does. An episode is also done when the CPU
+jams, since nothing the agent does can matter after that.
+*/
+
+/// What a memory condition tests a byte for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryTest {
+    Equals(u8),
+    NotEquals(u8),
+    Above(u8),
+    Below(u8),
+    // Different from the frame before, in any direction
+    Changed,
+    Increased,
+    Decreased,
+}
+
+/// A test of one byte in CPU address space (see "ON THE AGENT INTERFACE")
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryCondition {
+    pub address: u16,
+    pub test: MemoryTest,
+}
+
+impl MemoryCondition {
+    /// Whether the condition holds, given the byte's value the frame before
+    /// and now
+    pub fn holds(&self, before: u8, now: u8) -> bool {
+        match self.test {
+            MemoryTest::Equals(value) => now == value,
+            MemoryTest::NotEquals(value) => now != value,
+            MemoryTest::Above(value) => now > value,
+            MemoryTest::Below(value) => now < value,
+            MemoryTest::Changed => now != before,
+            MemoryTest::Increased => now > before,
+            MemoryTest::Decreased => now < before,
+        }
+    }
+}
+
+/// Where an agent's rewards come from (see "ON THE AGENT INTERFACE")
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RewardHook {
+    When { condition: MemoryCondition, reward: f32, done: bool },
+    Delta { address: u16, scale: f32 },
+}
+
+impl RewardHook {
+    pub fn address(&self) -> u16 {
+        match self {
+            Self::When { condition, .. } => condition.address,
+            Self::Delta { address, .. } => *address,
+        }
+    }
+
+    // What the hook gives for a frame, and whether it ends the episode
+    fn output(&self, before: u8, now: u8) -> (f32, bool) {
+        match *self {
+            Self::When { condition, reward, done } => match condition.holds(before, now) {
+                true => (reward, done),
+                false => (0.0, false),
+            },
+            Self::Delta { scale, .. } => ((now as f32 - before as f32) * scale, false),
+        }
+    }
+}
+
+/// What the agent gets back after a step (or a reset)
+#[derive(Clone, PartialEq, Debug)]
+pub struct AgentStep {
+    // The last finished picture, RGBA from the top left (see Frame)
+    pub frame: Vec<u8>,
+    // The bytes in the environment's RAM range
+    pub ram: Vec<u8>,
+    // What each reward hook gave, in the order they were added
+    pub hook_outputs: Vec<f32>,
+    pub reward: f32,
+    pub done: bool,
+    // How many frames have been run since the episode started
+    pub episode_frame: u64,
+}
+
+/// A game as an environment for an agent to play (see "ON THE AGENT
+/// INTERFACE")
+pub struct AgentEnv {
+    nes: Nes,
+    // What reset goes back to. The picture isn't part of a save state, so
+    // it's kept on its own.
+    start: SaveState,
+    start_frame: Vec<u8>,
+    hooks: Vec<RewardHook>,
+    // Each hook's byte as of the last frame
+    watched: Vec<u8>,
+    ram_range: Range<u16>,
+    frame_skip: usize,
+    episode_frame: u64,
+}
+
+impl AgentEnv {
+    /// Makes an environment out of an NES with a game loaded, starting from
+    /// the state it's in now. Audio is thrown away, since nobody's listening.
+    /// The RAM in each AgentStep is the 2KiB of internal RAM until
+    /// set_ram_range says otherwise.
+    pub fn new(mut nes: Nes) -> Result<Self, String> {
+        if !nes.cart_loaded() {
+            return Err(String::from("The agent environment needs a game loaded"));
+        }
+        nes.set_block_audio_samples(true);
+
+        Ok(Self {
+            start: nes.save_state(),
+            start_frame: nes.frame().pixels().to_vec(),
+            nes,
+            hooks: Vec::new(),
+            watched: Vec::new(),
+            ram_range: 0x0000..0x0800,
+            frame_skip: 1,
+            episode_frame: 0,
+        })
+    }
+
+    /// Adds a reward hook. Its output comes after those of the hooks added
+    /// before it.
+    pub fn add_hook(&mut self, hook: RewardHook) {
+        self.watched.push(self.nes.peek_cpu(hook.address()));
+        self.hooks.push(hook);
+    }
+
+    pub fn hooks(&self) -> &[RewardHook] {
+        &self.hooks
+    }
+
+    /// Sets the CPU addresses whose bytes come back in each AgentStep
+    pub fn set_ram_range(&mut self, range: Range<u16>) {
+        self.ram_range = range;
+    }
+
+    /// Sets how many frames each step holds its action for (at least 1). The
+    /// hooks' outputs are added up over them, and the step stops early if
+    /// the episode ends partway.
+    pub fn set_frame_skip(&mut self, frames: usize) {
+        self.frame_skip = frames.max(1);
+    }
+
+    /// Starts a new episode from the state the environment was made in
+    pub fn reset(&mut self) -> Result<AgentStep, String> {
+        self.nes.load_state(&self.start)?;
+        self.episode_frame = 0;
+        self.watch();
+
+        Ok(self.observe(self.start_frame.clone(), vec![0.0; self.hooks.len()], false))
+    }
+
+    /// Runs a step with the action's buttons held on controller 1 (see "ON
+    /// THE AGENT INTERFACE")
+    pub fn step(&mut self, action: u8) -> AgentStep {
+        self.step_players([action, 0])
+    }
+
+    /// Runs a step with each controller's buttons held
+    pub fn step_players(&mut self, actions: [u8; 2]) -> AgentStep {
+        let inputs = actions.map(NesController::from_bits);
+        let mut outputs = vec![0.0; self.hooks.len()];
+        let mut done = false;
+
+        for _ in 0..self.frame_skip {
+            self.nes.advance_frame(inputs);
+            self.nes.swap_screen_buffers();
+            self.episode_frame += 1;
+
+            for (i, hook) in self.hooks.iter().enumerate() {
+                let now = self.nes.peek_cpu(hook.address());
+                let (output, ends) = hook.output(self.watched[i], now);
+
+                outputs[i] += output;
+                done |= ends;
+                self.watched[i] = now;
+            }
+
+            done |= self.nes.cpu_jammed_at().is_some();
+            if done {
+                break;
+            }
+        }
+
+        self.observe(self.nes.frame().pixels().to_vec(), outputs, done)
+    }
+
+    pub fn nes(&self) -> &Nes {
+        &self.nes
+    }
+
+    /// The NES inside, for anything the environment doesn't cover. Loading a
+    /// state or poking memory through it won't be seen by the hooks until
+    /// the next step.
+    pub fn nes_mut(&mut self) -> &mut Nes {
+        &mut self.nes
+    }
+
+    // Takes each hook's byte as it is now
+    fn watch(&mut self) {
+        self.watched = self.hooks.iter().map(|hook| self.nes.peek_cpu(hook.address())).collect();
+    }
+
+    fn observe(&self, frame: Vec<u8>, hook_outputs: Vec<f32>, done: bool) -> AgentStep {
+        AgentStep {
+            frame,
+            ram: self.ram_range.clone().map(|address| self.nes.peek_cpu(address)).collect(),
+            reward: hook_outputs.iter().sum(),
+            hook_outputs,
+            done,
+            episode_frame: self.episode_frame,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::cheats::RamFreeze;
+
+    fn test_env() -> AgentEnv {
+        let mut nes = Nes::default();
+        nes.load_test_pattern().unwrap();
+
+        AgentEnv::new(nes).unwrap()
+    }
+
+    #[test]
+    fn reset_starts_every_episode_the_same() {
+        let mut env = test_env();
+
+        let first = env.reset().unwrap();
+        let run = |env: &mut AgentEnv| (0..3).map(|_| env.step(0b1000_0001)).last().unwrap();
+        let stepped = run(&mut env);
+        assert_eq!((first.episode_frame, stepped.episode_frame), (0, 3));
+        assert_eq!(stepped.ram.len(), 0x800);
+
+        assert_eq!(env.reset().unwrap(), first);
+        assert_eq!(run(&mut env), stepped);
+    }
+
+    #[test]
+    fn hooks_watch_memory() {
+        let mut env = test_env();
+        env.add_hook(RewardHook::Delta { address: 0x0010, scale: 0.5 });
+        env.add_hook(RewardHook::When {
+            condition: MemoryCondition { address: 0x0010, test: MemoryTest::Equals(0x08) },
+            reward: 10.0,
+            done: true,
+        });
+        env.reset().unwrap();
+
+        env.nes_mut().add_freeze(RamFreeze { address: 0x0010, value: 0x04 });
+        let step = env.step(0);
+        assert_eq!(step.hook_outputs, vec![2.0, 0.0]);
+        assert!(!step.done);
+
+        let step = env.step(0);
+        assert_eq!(step.reward, 0.0);
+
+        env.nes_mut().add_freeze(RamFreeze { address: 0x0010, value: 0x08 });
+        env.set_frame_skip(4);
+        let step = env.step(0);
+        assert_eq!(step.hook_outputs, vec![2.0, 10.0]);
+        assert_eq!(step.reward, 12.0);
+        assert!(step.done);
+    }
+}
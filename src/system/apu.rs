@@ -1,23 +1,38 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::time::Instant;
 use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-use biquad::{Biquad, Coefficients, DirectForm1, ToHertz, Type};
+use biquad::{Biquad, Coefficients, DirectForm2Transposed, ToHertz, Type};
 
 use crate::cartridge::Mapper;
 
+use super::events::{NesEvent, SharedEventBus};
+use super::sample_ring::SampleQueue;
+use super::savestate::{StateReader, StateWriter};
 use super::apu_util::{
-    DmcChannel, NesChannel, NoiseChannel, PulseChannel, TriangleChannel
+    ChannelMask, DmcChannel, Mixer, NesChannel, NoiseChannel, PulseChannel, Synthesis, TriangleChannel
 };
 
-pub const NES_AUDIO_FREQUENCY: u32 = 44100; // 44.1 KiHz
 pub const CPU_FREQ: f64 = 1_789_773f64; // For NTSC systems
 pub const CPU_CYCLE_PERIOD: f64 = 1.0 / CPU_FREQ;
 
-const SAMPLE_PERIOD: f64 = 1.0 / NES_AUDIO_FREQUENCY as f64;
-const SAMPLE_BATCH_SIZE: usize = 2048;
+// Number of samples collected before they're handed to the audio queue. Every
+// batch adds its length in latency (2048 samples is ~46ms at 44.1kHz), so the
+// app can lower this for a low-latency mode.
+pub const DEFAULT_SAMPLE_BATCH_SIZE: usize = 2048;
+// Number of samples the app tries to keep in the audio queue at all times
+pub const DEFAULT_TARGET_QUEUE_DEPTH: usize = 600;
+// Smallest batch the adaptive batching mode will shrink down to
+const MIN_SAMPLE_BATCH_SIZE: usize = 64;
+// Size of the chunks of audio that get dropped or repeated when the emulator
+// isn't running at 100% speed (~12ms)
+const SPEED_GRAIN_SIZE: usize = 512;
+// Samples at the start of each grain that are crossfaded with the audio that
+// would have come next, so skipping or repeating a grain doesn't click (~1.5ms)
+const GRAIN_CROSSFADE_SIZE: usize = 64;
+// How long the audio takes to fade out when it's ducked, and back in after
+const DUCK_FADE_SECS: f64 = 0.015;
 // The number of clocks in each denomination of a frame (in CPU clocks)
 const QUARTER_FRAME_CLOCKS: usize = 3729;
 const HALF_FRAME_CLOCKS: usize = 7457;
@@ -32,15 +47,129 @@ const LOW_PASS_CUTOFF_FREQ: f32 = 14000.0;
 // Quality factor ( 1/sqrt(2) is customary )
 const Q_VAL: f32 = 0.7071067811865475244008443622;
 
+// Save state block holding the APU's state (see savestate)
+pub const APU_STATE_MAGIC: &[u8; 4] = b"APU ";
+const APU_STATE_VERSION: u16 = 1;
+
+// How many register writes & $4011 values are kept around when debug logging
+pub const APU_WRITE_LOG_SIZE: usize = 32;
+pub const PCM_LOG_SIZE: usize = 200;
+
+/// The rate audio samples are put out at. 44.1kHz is the default, but sound
+/// cards that run at 48kHz natively would otherwise have to resample it, which
+/// can be heard as a slight grittiness on the high notes.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleRate {
+    #[default]
+    Hz44100,
+    Hz48000,
+    Hz96000,
+}
+
+impl SampleRate {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "44100" | "44.1k" => Some(Self::Hz44100),
+            "48000" | "48k" => Some(Self::Hz48000),
+            "96000" | "96k" => Some(Self::Hz96000),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Hz44100 => "44100",
+            Self::Hz48000 => "48000",
+            Self::Hz96000 => "96000",
+        }
+    }
+
+    pub fn hz(&self) -> u32 {
+        match self {
+            Self::Hz44100 => 44100,
+            Self::Hz48000 => 48000,
+            Self::Hz96000 => 96000,
+        }
+    }
+}
+
+// Builds one of the mixer's lookup tables, table[n] = scale / (divisor / n + 100)
+// (and 0 for n = 0)
+const fn mixer_table<const N: usize>(scale: f32, divisor: f32) -> [f32; N] {
+    let mut table = [0.0; N];
+
+    let mut n = 1;
+    while n < N {
+        table[n] = scale / (divisor / n as f32 + 100.0);
+        n += 1;
+    }
+
+    table
+}
+
+// The output filters (two high passes and a low pass), with their
+// coefficients worked out for the given sample rate
+// https://www.nesdev.org/wiki/APU_Mixer
+fn output_filters(sample_rate: SampleRate) -> [DirectForm2Transposed<f32>; 3] {
+    [
+        (Type::HighPass, HIGH_PASS1_CUTOFF_FREQ),
+        (Type::HighPass, HIGH_PASS2_CUTOFF_FREQ),
+        (Type::LowPass, LOW_PASS_CUTOFF_FREQ),
+    ].map(|(filter_type, cutoff)| {
+        let coeffs = Coefficients::<f32>::from_params(filter_type, sample_rate.hz().hz(), 
+            cutoff.hz(), Q_VAL).unwrap();
+
+        DirectForm2Transposed::<f32>::new(coeffs)
+    })
+}
+
+/// A single CPU write to one of the APU registers, timestamped with the number
+/// of CPU clocks the APU had run for when it happened.
+#[derive(Clone, Copy, Debug, Default, Hash)]
+pub struct ApuWrite {
+    pub clock: u64,
+    pub address: u16,
+    pub data: u8,
+}
+
 pub struct Apu2A03 {
-    sample_queue: Arc<Mutex<VecDeque<f32>>>,
+    sample_queue: SampleQueue,
     sample_batch: Vec<f32>,
-
-    mapper: Rc<RefCell<dyn Mapper>>,
+    // Largest batch size we're allowed to use & the one currently being used.
+    // These only differ when adaptive batching is turned on.
+    max_batch_size: usize,
+    batch_size: usize,
+    adaptive_batching: bool,
+    target_queue_depth: usize,
+
+    // Emulation speed as a multiple of normal speed. To keep the pitch right
+    // at other speeds, samples are collected into small grains which are then
+    // skipped (faster) or played more than once (slower). Each grain is held
+    // back until the next one is finished, so the start of whatever plays
+    // after it can be crossfaded with how it carried on.
+    speed: f64,
+    speed_grain: Vec<f32>,
+    grain_credit: f64,
+    held_grain: Vec<f32>,
+    held_plays: usize,
+    // The start of the grain after the last one played
+    grain_continuation: [f32; GRAIN_CROSSFADE_SIZE],
+
+    // The cartridge, for DMC sample fetches. None when there's no cartridge in.
+    mapper: Option<Rc<RefCell<dyn Mapper>>>,
     
     clocks: u64,
     frame_clocks: usize,
     clocks_since_sampled: usize,
+    // The rate samples are put out at, and the time between them in seconds
+    sample_rate: SampleRate,
+    sample_period: f64,
+    // How the pulse and triangle waves are generated
+    synthesis: Synthesis,
+    // How the channels are mixed, and whether the pulse duty cycles are
+    // swapped like on some clones (see duty_cycle)
+    mixer: Mixer,
+    swap_duty_cycles: bool,
 
     pulse1_channel: PulseChannel,
     pulse2_channel: PulseChannel,
@@ -48,21 +177,39 @@ pub struct Apu2A03 {
     noise_channel: NoiseChannel,
     dmc_channel: DmcChannel,
 
-    high_pass1: DirectForm1<f32>,
-    _high_pass2: DirectForm1<f32>,
-    _low_pass: DirectForm1<f32>,
+    // Direct form 2 transposed, because its history (s1, s2) is public and
+    // can be put in save states
+    high_pass1: DirectForm2Transposed<f32>,
+    _high_pass2: DirectForm2Transposed<f32>,
+    _low_pass: DirectForm2Transposed<f32>,
 
     frame_update_counter: usize,
     frame_update_mode1: bool,
 
-    last_output: Instant,
     batches_sent: usize,
+    // Where ApuBatchReady is published
+    events: SharedEventBus,
 
     irq_request_flag: bool,
     trigger_irq: bool,
+    // Set when the DMC fetched a sample byte this cycle, which stalls the CPU
+    dmc_dma: bool,
 
     // The app can block the APU from adding samples to the queue
     block_samples: bool,
+    // Or duck the audio (for fast-forward), which fades it out and then stops
+    // adding samples. The gain moves a step towards its target every sample.
+    ducked: bool,
+    duck_gain: f32,
+
+    // Channels the user has muted from the mixer output
+    muted_channels: ChannelMask,
+
+    // Debug logging of register writes. Off by default since it's only used
+    // by the debug view.
+    debug_logging: bool,
+    write_log: VecDeque<ApuWrite>,
+    pcm_log: VecDeque<u8>,
 }
 
 impl Apu2A03 {
@@ -70,78 +217,45 @@ impl Apu2A03 {
     // PULSE_LOOKUP[n] = 95.52 / (8128.0 / n + 100)
     // indexed as PULSE_LOOKUP[ pulse1 + pulse2 ] (max values of 15 + 15 == 30)
     // from the nesdev wiki: https://www.nesdev.org/wiki/APU_Mixer
-    const PULSE_LOOKUP: [f32; 31] = [
-        1.175196850393700787401574803, 1.186948818897637795275590551, 1.198700787401574803149606299, 1.210452755905511811023622047, 1.222204724409448818897637795, 1.233956692913385826771653543, 1.245708661417322834645669291, 1.257460629921259842519685039,
-        1.269212598425196850393700787, 1.280964566929133858267716536, 1.292716535433070866141732283, 1.304468503937007874015748031, 1.316220472440944881889763780, 1.327972440944881889763779528, 1.339724409448818897637795276, 1.351476377952755905511811024,
-        1.363228346456692913385826772, 1.374980314960629921259842520, 1.386732283464566929133858268, 1.398484251968503937007874016, 1.410236220472440944881889764, 1.421988188976377952755905512, 1.433740157480314960629921260, 1.445492125984251968503937008,
-        1.457244094488188976377952756, 1.468996062992125984251968504, 1.480748031496062992125984252, 1.492500000000000000000000000, 1.504251968503937007874015748, 1.516003937007874015748031496, 1.527755905511811023622047244, 
-    ];
+    const PULSE_LOOKUP: [f32; 31] = mixer_table(95.52, 8128.0);
 
     // Sample lookup table for the DMC + Triangle + Noise channels. All terms calculated using the formula
     // TND_LOOKUP[n] = 163.67 / (24329.0 / n + 100)
     // indexed as TND_LOOKUP[ 3*triangle + 2*noise + dmc ] (max values of 3*15 + 2*15 + 128 == 203)
     // from the nesdev wiki: https://www.nesdev.org/wiki/APU_Mixer
-    const TND_LOOKUP: [f32; 204] = [
-        0.672736240700398701138558922, 0.679463603107402688149944511, 0.686190965514406675161330100, 0.692918327921410662172715689, 0.699645690328414649184101278, 0.706373052735418636195486868, 0.713100415142422623206872457, 0.719827777549426610218258046,
-        0.726555139956430597229643635, 0.733282502363434584241029224, 0.740009864770438571252414814, 0.746737227177442558263800403, 0.753464589584446545275185992, 0.760191951991450532286571581, 0.766919314398454519297957170, 0.773646676805458506309342760,
-        0.780374039212462493320728349, 0.787101401619466480332113938, 0.793828764026470467343499528, 0.800556126433474454354885116, 0.807283488840478441366270706, 0.814010851247482428377656295, 0.820738213654486415389041884, 0.827465576061490402400427473,
-        0.834192938468494389411813063, 0.840920300875498376423198652, 0.847647663282502363434584241, 0.854375025689506350445969830, 0.861102388096510337457355420, 0.867829750503514324468741009, 0.874557112910518311480126598, 0.881284475317522298491512187,
-        0.888011837724526285502897776, 0.894739200131530272514283366, 0.901466562538534259525668955, 0.908193924945538246537054544, 0.914921287352542233548440133, 0.921648649759546220559825722, 0.928376012166550207571211312, 0.935103374573554194582596901,
-        0.941830736980558181593982490, 0.948558099387562168605368079, 0.955285461794566155616753669, 0.962012824201570142628139258, 0.968740186608574129639524847, 0.975467549015578116650910436, 0.982194911422582103662296026, 0.988922273829586090673681615,
-        0.995649636236590077685067204, 1.002376998643594064696452793, 1.009104361050598051707838382, 1.015831723457602038719223971, 1.022559085864606025730609561, 1.029286448271610012741995150, 1.036013810678613999753380739, 1.042741173085617986764766328,
-        1.049468535492621973776151917, 1.056195897899625960787537506, 1.062923260306629947798923096, 1.069650622713633934810308685, 1.076377985120637921821694274, 1.083105347527641908833079863, 1.089832709934645895844465453, 1.096560072341649882855851042,
-        1.103287434748653869867236631, 1.110014797155657856878622221, 1.116742159562661843890007809, 1.123469521969665830901393398, 1.130196884376669817912778988, 1.136924246783673804924164577, 1.143651609190677791935550166, 1.150378971597681778946935756,
-        1.157106334004685765958321345, 1.163833696411689752969706934, 1.170561058818693739981092524, 1.177288421225697726992478113, 1.184015783632701714003863702, 1.190743146039705701015249291, 1.197470508446709688026634880, 1.204197870853713675038020470,
-        1.210925233260717662049406059, 1.217652595667721649060791648, 1.224379958074725636072177237, 1.231107320481729623083562826, 1.237834682888733610094948415, 1.244562045295737597106334005, 1.251289407702741584117719594, 1.258016770109745571129105183,
-        1.264744132516749558140490772, 1.271471494923753545151876362, 1.278198857330757532163261951, 1.284926219737761519174647540, 1.291653582144765506186033129, 1.298380944551769493197418718, 1.305108306958773480208804308, 1.311835669365777467220189896,
-        1.318563031772781454231575487, 1.325290394179785441242961075, 1.332017756586789428254346664, 1.338745118993793415265732254, 1.345472481400797402277117843, 1.352199843807801389288503432, 1.358927206214805376299889022, 1.365654568621809363311274611,
-        1.372381931028813350322660199, 1.379109293435817337334045789, 1.385836655842821324345431378, 1.392564018249825311356816968, 1.399291380656829298368202556, 1.406018743063833285379588145, 1.412746105470837272390973734, 1.419473467877841259402359324,
-        1.426200830284845246413744914, 1.432928192691849233425130503, 1.439655555098853220436516092, 1.446382917505857207447901682, 1.453110279912861194459287270, 1.459837642319865181470672859, 1.466565004726869168482058449, 1.473292367133873155493444038,
-        1.480019729540877142504829628, 1.486747091947881129516215217, 1.493474454354885116527600805, 1.500201816761889103538986395, 1.506929179168893090550371985, 1.513656541575897077561757573, 1.520383903982901064573143162, 1.527111266389905051584528751,
-        1.533838628796909038595914340, 1.540565991203913025607299930, 1.547293353610917012618685520, 1.554020716017920999630071109, 1.560748078424924986641456697, 1.567475440831928973652842287, 1.574202803238932960664227876, 1.580930165645936947675613466,
-        1.587657528052940934686999054, 1.594384890459944921698384643, 1.601112252866948908709770234, 1.607839615273952895721155822, 1.614566977680956882732541412, 1.621294340087960869743927001, 1.628021702494964856755312590, 1.634749064901968843766698179,
-        1.641476427308972830778083768, 1.648203789715976817789469358, 1.654931152122980804800854947, 1.661658514529984791812240536, 1.668385876936988778823626125, 1.675113239343992765835011714, 1.681840601750996752846397304, 1.688567964158000739857782893,
-        1.695295326565004726869168482, 1.702022688972008713880554071, 1.708750051379012700891939660, 1.715477413786016687903325250, 1.722204776193020674914710839, 1.728932138600024661926096428, 1.735659501007028648937482017, 1.742386863414032635948867607,
-        1.749114225821036622960253196, 1.755841588228040609971638785, 1.762568950635044596983024374, 1.769296313042048583994409963, 1.776023675449052571005795553, 1.782751037856056558017181142, 1.789478400263060545028566731, 1.796205762670064532039952320,
-        1.802933125077068519051337909, 1.809660487484072506062723499, 1.816387849891076493074109088, 1.823115212298080480085494677, 1.829842574705084467096880266, 1.836569937112088454108265856, 1.843297299519092441119651445, 1.850024661926096428131037034,
-        1.856752024333100415142422623, 1.863479386740104402153808212, 1.870206749147108389165193802, 1.876934111554112376176579391, 1.883661473961116363187964980, 1.890388836368120350199350569, 1.897116198775124337210736159, 1.903843561182128324222121748,
-        1.910570923589132311233507337, 1.917298285996136298244892926, 1.924025648403140285256278515, 1.930753010810144272267664105, 1.937480373217148259279049694, 1.944207735624152246290435283, 1.950935098031156233301820872, 1.957662460438160220313206461,
-        1.964389822845164207324592051, 1.971117185252168194335977640, 1.977844547659172181347363229, 1.984571910066176168358748818, 1.991299272473180155370134407, 1.998026634880184142381519997, 2.004753997287188129392905586, 2.011481359694192116404291175,
-        2.018208722101196103415676764, 2.024936084508200090427062353, 2.031663446915204077438447943, 2.038390809322208064449833532,
-    ];
+    const TND_LOOKUP: [f32; 204] = mixer_table(163.67, 24329.0);
 
 
-    pub fn new(sample_queue: Arc<Mutex<VecDeque<f32>>>, mapper: Rc<RefCell<dyn Mapper>>) -> Self {
-        let high_pass1_coeffs: Coefficients<f32> = Coefficients::<f32>::from_params(
-            Type::HighPass,
-            NES_AUDIO_FREQUENCY.hz(),
-            HIGH_PASS1_CUTOFF_FREQ.hz(),
-            Q_VAL,
-        ).unwrap();
-    
-        let high_pass2_coeffs: Coefficients<f32> = Coefficients::<f32>::from_params(
-            Type::HighPass,
-            NES_AUDIO_FREQUENCY.hz(),
-            HIGH_PASS2_CUTOFF_FREQ.hz(),
-            Q_VAL,
-        ).unwrap();
-    
-        let low_pass_coeffs: Coefficients<f32> = Coefficients::<f32>::from_params(
-            Type::LowPass,
-            NES_AUDIO_FREQUENCY.hz(),
-            LOW_PASS_CUTOFF_FREQ.hz(),
-            Q_VAL,
-        ).unwrap();
+    /// Create a new APU with no cartridge connected (see power_on)
+    ///  * `sample_queue` - Where finished audio samples are sent
+    pub fn new(sample_queue: SampleQueue) -> Self {
+        let [high_pass1, high_pass2, low_pass] = output_filters(SampleRate::default());
 
         Self {
             sample_queue,
-            sample_batch: Vec::with_capacity(SAMPLE_BATCH_SIZE),
+            sample_batch: Vec::with_capacity(DEFAULT_SAMPLE_BATCH_SIZE),
+            max_batch_size: DEFAULT_SAMPLE_BATCH_SIZE,
+            batch_size: DEFAULT_SAMPLE_BATCH_SIZE,
+            adaptive_batching: false,
+            target_queue_depth: DEFAULT_TARGET_QUEUE_DEPTH,
+
+            speed: 1.0,
+            speed_grain: Vec::with_capacity(SPEED_GRAIN_SIZE),
+            grain_credit: 0.0,
+            held_grain: Vec::with_capacity(SPEED_GRAIN_SIZE),
+            held_plays: 0,
+            grain_continuation: [0.0; GRAIN_CROSSFADE_SIZE],
 
-            mapper,
+            mapper: None,
 
             clocks: 0,
             frame_clocks: 0,
             clocks_since_sampled: 0,
+            sample_rate: SampleRate::default(),
+            sample_period: 1.0 / SampleRate::default().hz() as f64,
+            synthesis: Synthesis::default(),
+            mixer: Mixer::default(),
+            swap_duty_cycles: false,
 
             pulse1_channel: PulseChannel::new(NesChannel::Pulse1),
             pulse2_channel: PulseChannel::new(NesChannel::Pulse2),
@@ -149,20 +263,29 @@ impl Apu2A03 {
             noise_channel: NoiseChannel::new(),
             dmc_channel: DmcChannel::new(),
 
-            high_pass1: DirectForm1::<f32>::new(high_pass1_coeffs),
-            _high_pass2: DirectForm1::<f32>::new(high_pass2_coeffs),
-            _low_pass: DirectForm1::<f32>::new(low_pass_coeffs),
+            high_pass1,
+            _high_pass2: high_pass2,
+            _low_pass: low_pass,
 
             frame_update_counter: 0,
             frame_update_mode1: false,
 
-            last_output: Instant::now(),
             batches_sent: 0,
+            events: SharedEventBus::default(),
 
             irq_request_flag: false,
             trigger_irq: false,
+            dmc_dma: false,
 
             block_samples: false,
+            ducked: false,
+            duck_gain: 1.0,
+
+            muted_channels: ChannelMask::new(),
+
+            debug_logging: false,
+            write_log: VecDeque::with_capacity(APU_WRITE_LOG_SIZE),
+            pcm_log: VecDeque::with_capacity(PCM_LOG_SIZE),
         }
     }
 
@@ -179,12 +302,13 @@ impl Apu2A03 {
 
             // Audio clips start at $C000, which should always be 
             // accessing valid cartridge memory.
-            let next_clip_byte = self.mapper.as_ref()
-                                                .borrow_mut()
-                                                .cpu_cart_read(addr)
-                                                .unwrap();
+            let next_clip_byte = match &self.mapper {
+                Some(mapper) => mapper.borrow_mut().cpu_cart_read(addr).unwrap(),
+                None => 0,
+            };
 
             self.dmc_channel.update_timer(Some(next_clip_byte));
+            self.dmc_dma = true;
 
         } else {
             self.dmc_channel.update_timer(None);
@@ -204,7 +328,7 @@ impl Apu2A03 {
 
         let time_since_sampled = self.clocks_since_sampled as f64 * CPU_CYCLE_PERIOD;
 
-        if time_since_sampled > SAMPLE_PERIOD {
+        if time_since_sampled > self.sample_period {
             let sample = self.generate_sample();
 
             self.push_sample(sample);
@@ -214,16 +338,31 @@ impl Apu2A03 {
     }
 
     pub fn cpu_read(&mut self, address: u16) -> u8 {
+        let data = self.peek_cpu(address);
+
+        // Reading the status acknowledges the frame interrupt
+        if address == 0x4015 {
+            self.trigger_irq = false;
+        }
+
+        data
+    }
+
+    /// What the CPU reading the given address would get, without
+    /// acknowledging the frame interrupt the way a real read does
+    pub fn peek_cpu(&self, address: u16) -> u8 {
         match address {
             0x4015 => {
                 // DMC interrupt (I), frame interrupt (F), DMC active (D), length counter > 0 (N/T/2/1) 
+                let i = if self.dmc_channel.irq_triggered() { 1 } else { 0 };
+                let f = if self.trigger_irq { 1 } else { 0 };
                 let d = if self.dmc_channel.dmc_active() { 1 } else { 0 };
-                let n = if self.pulse1_channel.length_counter.is_zero() { 0 } else { 1 };
-                let t = if self.pulse1_channel.length_counter.is_zero() { 0 } else { 1 };
+                let n = if self.noise_channel.length_counter.is_zero() { 0 } else { 1 };
+                let t = if self.triangle_channel.length_counter.is_zero() { 0 } else { 1 };
+                let p2 = if self.pulse2_channel.length_counter.is_zero() { 0 } else { 1 };
                 let p1 = if self.pulse1_channel.length_counter.is_zero() { 0 } else { 1 };
-                let p2 = if self.pulse1_channel.length_counter.is_zero() { 0 } else { 1 };
 
-                let data = (d << 4) | (n << 3) | (t << 2) | (p2 << 1) | (p1 << 0);
+                let data = (i << 7) | (f << 6) | (d << 4) | (n << 3) | (t << 2) | (p2 << 1) | p1;
 
                 data as u8
             },
@@ -233,21 +372,18 @@ impl Apu2A03 {
     }
 
     pub fn cpu_write(&mut self, address: u16, data: u8) {
+        if self.debug_logging {
+            self.log_write(address, data);
+        }
+
         match address {
             // Pulse 1 Registers
             0x4000 => {
-                let new_duty_cycle = (data >> 6) & 3;
                 let new_halt = (data & 0x20) != 0; // Also envelope's loop flag
                 let new_const_volume = (data & 0x10) != 0;
                 let new_volume = (data & 0x0F) as usize;
 
-                self.pulse1_channel.duty_cycle_percent = match new_duty_cycle {
-                    0 => 0.125,
-                    1 => 0.25,
-                    2 => 0.50,
-                    3 => 0.75,
-                    _ => { unreachable!("Holy wack unlyrical lyrics, Batman!"); }
-                };
+                self.pulse1_channel.duty_cycle_percent = self.duty_cycle(data);
                 self.pulse1_channel.length_counter.set_halted(new_halt);
                 self.pulse1_channel.envelope.set_loop_flag(new_halt);
                 self.pulse1_channel.envelope.set_const_volume(new_const_volume);
@@ -293,18 +429,11 @@ impl Apu2A03 {
 
             // Pulse 2 Registers
             0x4004 => {
-                let new_duty_cycle = (data >> 6) & 3;
                 let new_halt = (data & 0x20) != 0;
                 let new_const_volume = (data & 0x10) != 0;
                 let new_volume = (data & 0x0F) as usize;
 
-                self.pulse2_channel.duty_cycle_percent = match new_duty_cycle {
-                    0 => 0.125,
-                    1 => 0.25,
-                    2 => 0.50,
-                    3 => 0.75,
-                    _ => { unreachable!("Holy wack unlyrical lyrics, Batman!"); }
-                };
+                self.pulse2_channel.duty_cycle_percent = self.duty_cycle(data);
                 self.pulse2_channel.length_counter.set_halted(new_halt);
                 self.pulse2_channel.envelope.set_loop_flag(new_halt);
                 self.pulse2_channel.envelope.set_const_volume(new_const_volume);
@@ -437,7 +566,10 @@ impl Apu2A03 {
                 self.dmc_channel.set_clip_length(new_clip_length);
             }
 
-            // Channel enable register
+            // Channel enable register. Takes effect immediately: disabled
+            // channels have their length counters zeroed (see
+            // LengthCounter::set_channel_enabled) and the DMC starts or stops
+            // fetching its sample.
             0x4015 => {
                 let pulse1_enabled = (data & 0x01) != 0;
                 let pulse2_enabled = (data & 0x02) != 0;
@@ -463,24 +595,64 @@ impl Apu2A03 {
                     self.frame_update()
                 }
                 self.irq_request_flag = new_irq_flag;
+                // Setting the inhibit flag also acknowledges the frame interrupt
+                if !new_irq_flag {
+                    self.trigger_irq = false;
+                }
             }
 
             _ => {}
         }
     }
 
+    // The duty cycle set by a write to $4000 or $4004. Some Famicom clones
+    // have the two duty bits the wrong way round, which swaps 25% and 50%.
+    // https://www.nesdev.org/wiki/APU_Pulse
+    fn duty_cycle(&self, data: u8) -> f64 {
+        let duty_bits = if self.swap_duty_cycles {
+            ((data >> 7) & 1) | ((data >> 5) & 2)
+        } else {
+            (data >> 6) & 3
+        };
+
+        match duty_bits {
+            0 => 0.125,
+            1 => 0.25,
+            2 => 0.50,
+            3 => 0.75,
+            _ => { unreachable!("Holy wack unlyrical lyrics, Batman!"); }
+        }
+    }
+
     fn generate_sample(&mut self) -> f32 {
-        let pulse1_sample = self.pulse1_channel.sample(self.clocks);
-        let pulse2_sample = self.pulse2_channel.sample(self.clocks);
-        let triangle_sample = self.triangle_channel.sample(self.clocks);
+        let pulse1_sample = self.pulse1_channel.sample(self.clocks, self.synthesis, self.sample_period);
+        let pulse2_sample = self.pulse2_channel.sample(self.clocks, self.synthesis, self.sample_period);
+        let triangle_sample = self.triangle_channel.sample(self.clocks, self.synthesis, self.sample_period);
         let noise_sample = self.noise_channel.sample();
         let dmc_sample = self.dmc_channel.sample();
 
-        let pulse_idx = (pulse1_sample + pulse2_sample) as usize;
-        let tnd_idx = (3.0*triangle_sample + 2.0*noise_sample + dmc_sample) as usize;
-
-        let pulse_out = Self::PULSE_LOOKUP[pulse_idx];
-        let tnd_out = Self::TND_LOOKUP[tnd_idx];
+        // Channels still get sampled when muted so their internal state keeps
+        // moving along exactly as it would otherwise.
+        let mask = self.muted_channels;
+        let pulse1_sample = if mask.pulse1() { 0.0 } else { pulse1_sample };
+        let pulse2_sample = if mask.pulse2() { 0.0 } else { pulse2_sample };
+        let triangle_sample = if mask.triangle() { 0.0 } else { triangle_sample };
+        let noise_sample = if mask.noise() { 0.0 } else { noise_sample };
+        let dmc_sample = if mask.dmc() { 0.0 } else { dmc_sample };
+
+        let (pulse_out, tnd_out) = match self.mixer {
+            Mixer::Nonlinear => {
+                let pulse_idx = pulse1_sample + pulse2_sample;
+                let tnd_idx = 3.0*triangle_sample + 2.0*noise_sample + dmc_sample;
+
+                (Self::mix_lookup(&Self::PULSE_LOOKUP, pulse_idx), Self::mix_lookup(&Self::TND_LOOKUP, tnd_idx))
+            }
+            // The linear approximation from the nesdev wiki
+            Mixer::Linear => (
+                0.00752 * (pulse1_sample + pulse2_sample),
+                0.00851 * triangle_sample + 0.00494 * noise_sample + 0.00335 * dmc_sample,
+            ),
+        };
 
         let sample = pulse_out + tnd_out;
 
@@ -491,26 +663,370 @@ impl Apu2A03 {
         sample
     }
 
+    // Reads a mixer lookup table, interpolating between entries. Band-limited
+    // synthesis rounds off the edges of the waves, so the channels don't
+    // always come out at whole numbers.
+    fn mix_lookup(table: &[f32], idx: f32) -> f32 {
+        let idx = idx.clamp(0.0, (table.len() - 1) as f32);
+        let lo = idx as usize;
+        let hi = std::cmp::min(lo + 1, table.len() - 1);
+
+        table[lo] + (table[hi] - table[lo]) * idx.fract()
+    }
+
     fn push_sample(&mut self, sample: f32) {
-        if !self.block_samples {
-            self.sample_batch.push(sample);
-    
-            if self.sample_batch.len() >= SAMPLE_BATCH_SIZE {
-                self.send_sample_batch();
+        if self.block_samples {
+            return;
+        }
+
+        if self.speed == 1.0 {
+            self.batch_sample(sample);
+            return;
+        }
+
+        self.speed_grain.push(sample);
+
+        if self.speed_grain.len() >= SPEED_GRAIN_SIZE {
+            // Now that it's known how the held grain carries on, it can be
+            // played (a grain that's skipped doesn't change what the next one
+            // played is crossfaded with)
+            let mut held = std::mem::take(&mut self.held_grain);
+            for _ in 0..self.held_plays {
+                self.play_grain(&held);
+                self.grain_continuation.copy_from_slice(&self.speed_grain[..GRAIN_CROSSFADE_SIZE]);
             }
+
+            // Each finished grain earns 1/speed plays, so at 200% every other
+            // grain is played and at 50% every grain is played twice.
+            self.grain_credit += 1.0 / self.speed;
+            self.held_plays = self.grain_credit as usize;
+            self.grain_credit -= self.held_plays as f64;
+
+            held.clear();
+            self.held_grain = std::mem::replace(&mut self.speed_grain, held);
+        }
+    }
+
+    // Plays a grain, crossfading its start with how the last grain played
+    // would have carried on. When the grain is the one that came next anyway
+    // (the audio isn't skipping or repeating here) they're the same samples,
+    // so nothing changes.
+    fn play_grain(&mut self, grain: &[f32]) {
+        for (i, &sample) in grain.iter().enumerate() {
+            let sample = match self.grain_continuation.get(i) {
+                Some(&carried_on) => {
+                    let mix = (i + 1) as f32 / (GRAIN_CROSSFADE_SIZE + 1) as f32;
+                    carried_on * (1.0 - mix) + sample * mix
+                }
+                None => sample,
+            };
+            self.batch_sample(sample);
+        }
+    }
+
+    fn batch_sample(&mut self, sample: f32) {
+        let target_gain = if self.ducked { 0.0 } else { 1.0 };
+
+        if self.duck_gain != target_gain {
+            let step = (self.sample_period / DUCK_FADE_SECS) as f32;
+            self.duck_gain = match self.ducked {
+                true => f32::max(self.duck_gain - step, 0.0),
+                false => f32::min(self.duck_gain + step, 1.0),
+            };
+        } else if self.ducked {
+            return;
+        }
+
+        self.sample_batch.push(sample * self.duck_gain);
+
+        // Once it's faded all the way out, the end of the fade goes out
+        // without waiting for the batch to fill
+        let faded_out = self.ducked && self.duck_gain == 0.0;
+
+        if self.sample_batch.len() >= self.batch_size || faded_out {
+            self.send_sample_batch();
         }
     }
 
     fn send_sample_batch(&mut self) {
-        self.sample_queue.lock().unwrap()
-            .extend(self.sample_batch.drain(..));
+        // If the ring is full the rest of the batch is dropped, which only
+        // happens if the audio thread has stopped pulling samples
+        self.sample_queue.push_slice(&self.sample_batch);
+        self.events.borrow_mut().publish(NesEvent::ApuBatchReady { samples: self.sample_batch.len() });
+        self.sample_batch.clear();
+
+        let queued = self.sample_queue.len();
+
+        if self.adaptive_batching {
+            self.adapt_batch_size(queued);
+        }
 
-        self.last_output = Instant::now();
         self.batches_sent += 1;
     }
 
+    /// Adjusts the batch size based on how full the audio queue is. If the
+    /// queue is already past its target depth there's plenty of audio buffered,
+    /// so batches get smaller to cut down on latency. If the queue is close to
+    /// running dry, batches grow back towards the max batch size.
+    fn adapt_batch_size(&mut self, queued: usize) {
+        if queued > self.target_queue_depth {
+            self.batch_size = std::cmp::max(self.batch_size / 2, MIN_SAMPLE_BATCH_SIZE);
+        } else if queued < self.batch_size {
+            self.batch_size = std::cmp::min(self.batch_size * 2, self.max_batch_size);
+        }
+    }
+
+    /// Puts the APU back in its power on state, connected to a new cartridge
+    /// (or to none, when the cartridge is ejected). Settings like batching,
+    /// speed and muted channels are kept, and so is the sample queue, so the
+    /// audio output keeps playing from the same queue. Samples left over from
+    /// the last game are thrown away.
+    pub fn power_on(&mut self, mapper: Option<Rc<RefCell<dyn Mapper>>>) {
+        let mut fresh = Self::new(Arc::clone(&self.sample_queue));
+
+        fresh.mapper = mapper;
+
+        fresh.max_batch_size = self.max_batch_size;
+        fresh.batch_size = self.max_batch_size;
+        fresh.adaptive_batching = self.adaptive_batching;
+        fresh.target_queue_depth = self.target_queue_depth;
+        fresh.speed = self.speed;
+        fresh.block_samples = self.block_samples;
+        fresh.ducked = self.ducked;
+        fresh.muted_channels = self.muted_channels;
+        fresh.debug_logging = self.debug_logging;
+        fresh.events = Rc::clone(&self.events);
+
+        *self = fresh;
+        self.sample_queue.clear();
+    }
+
+    /// What happens to the APU when the reset button is pressed. Every channel
+    /// is silenced as if $4015 was written with 0, a DMC fetch that hadn't
+    /// stalled the CPU yet is dropped, and the frame counter starts over in
+    /// the mode last written to $4017. Nothing else changes (the channels'
+    /// registers and the noise LFSR keep their values), and unlike power_on
+    /// the audio already queued is left to play.
+    /// https://www.nesdev.org/wiki/CPU_power_up_state
+    pub fn reset(&mut self) {
+        self.pulse1_channel.set_enable(false);
+        self.pulse2_channel.set_enable(false);
+        self.triangle_channel.set_enable(false);
+        self.noise_channel.set_enable(false);
+        self.dmc_channel.set_enable(false);
+        self.dmc_channel.reset_output();
+        self.dmc_dma = false;
+
+        self.frame_clocks = 0;
+        self.frame_update_counter = 0;
+        if self.frame_update_mode1 {
+            self.frame_update();
+        }
+        self.trigger_irq = false;
+    }
+
+    /// Serializes everything that affects the sound the APU makes next: the
+    /// channels (timers, envelopes, sweep units, length and linear counters,
+    /// the noise LFSR, the DMC's shifter and sample position), the frame
+    /// counter, the clock (which the pulse and triangle waveforms are timed
+    /// from) and the filter history. Settings like the speed, batching and
+    /// muted channels aren't part of it, they belong to the user.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut payload = StateWriter::new();
+
+        payload.write_u64(self.clocks);
+        payload.write_usize(self.frame_clocks);
+        payload.write_usize(self.clocks_since_sampled);
+
+        self.pulse1_channel.save_state(&mut payload);
+        self.pulse2_channel.save_state(&mut payload);
+        self.triangle_channel.save_state(&mut payload);
+        self.noise_channel.save_state(&mut payload);
+        self.dmc_channel.save_state(&mut payload);
+
+        for filter in [&self.high_pass1, &self._high_pass2, &self._low_pass] {
+            payload.write_f32(filter.s1);
+            payload.write_f32(filter.s2);
+        }
+
+        payload.write_usize(self.frame_update_counter);
+        payload.write_bool(self.frame_update_mode1);
+
+        payload.write_bool(self.irq_request_flag);
+        payload.write_bool(self.trigger_irq);
+        payload.write_bool(self.dmc_dma);
+
+        let mut block = StateWriter::new();
+        block.write_block(APU_STATE_MAGIC, APU_STATE_VERSION, &payload.into_bytes());
+
+        block.into_bytes()
+    }
+
+    /// Restores a state written by save_state. Samples from before the state
+    /// was loaded that haven't been sent yet are thrown away, so they don't
+    /// get mixed in with the restored audio.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut reader = StateReader::new(bytes);
+        let (version, mut payload) = reader.read_block(APU_STATE_MAGIC)?;
+
+        if version > APU_STATE_VERSION {
+            return Err(format!(
+                "APU state version {version} is newer than this emulator supports ({APU_STATE_VERSION})"
+            ));
+        }
+
+        self.clocks = payload.read_u64()?;
+        self.frame_clocks = payload.read_usize()?;
+        self.clocks_since_sampled = payload.read_usize()?;
+
+        self.pulse1_channel.load_state(&mut payload)?;
+        self.pulse2_channel.load_state(&mut payload)?;
+        self.triangle_channel.load_state(&mut payload)?;
+        self.noise_channel.load_state(&mut payload)?;
+        self.dmc_channel.load_state(&mut payload)?;
+
+        for filter in [&mut self.high_pass1, &mut self._high_pass2, &mut self._low_pass] {
+            filter.s1 = payload.read_f32()?;
+            filter.s2 = payload.read_f32()?;
+        }
+
+        self.frame_update_counter = payload.read_usize()?;
+        self.frame_update_mode1 = payload.read_bool()?;
+
+        self.irq_request_flag = payload.read_bool()?;
+        self.trigger_irq = payload.read_bool()?;
+        self.dmc_dma = payload.read_bool()?;
+
+        self.sample_batch.clear();
+        self.speed_grain.clear();
+        self.grain_credit = 0.0;
+        self.held_grain.clear();
+        self.held_plays = 0;
+
+        Ok(())
+    }
+
+    /// Sends samples to a different queue from now on
+    pub fn set_sample_queue(&mut self, sample_queue: SampleQueue) {
+        self.sample_batch.clear();
+        self.sample_queue = sample_queue;
+    }
+
+    /// Set the event bus to publish on (see events)
+    pub fn set_event_bus(&mut self, events: SharedEventBus) {
+        self.events = events;
+    }
+
     pub fn audio_samples_queued(&self) -> usize {
-        self.sample_queue.lock().unwrap().len()
+        self.sample_queue.len()
+    }
+
+    /// Number of times the audio output has run out of samples
+    pub fn audio_underruns(&self) -> u64 {
+        self.sample_queue.underruns()
+    }
+
+    /// Number of samples the audio output has had to make up because it ran
+    /// out (see "ON AUDIO UNDERRUNS" in apu_util.rs)
+    pub fn audio_missed_samples(&self) -> u64 {
+        self.sample_queue.missed_samples()
+    }
+
+    /// Estimated time (in milliseconds) between a sample being generated and
+    /// it being played, measured from the samples waiting in the current batch
+    /// and in the audio queue.
+    pub fn audio_latency_ms(&self) -> f64 {
+        let pending = self.audio_samples_queued() + self.sample_batch.len();
+
+        pending as f64 * 1000.0 / self.sample_rate.hz() as f64
+    }
+
+    pub fn synthesis(&self) -> Synthesis {
+        self.synthesis
+    }
+
+    /// Switches between band-limited and naive generation of the pulse and
+    /// triangle waves (see "ON HOW BAND-LIMITED SYNTHESIS WORKS")
+    pub fn set_synthesis(&mut self, synthesis: Synthesis) {
+        self.synthesis = synthesis;
+    }
+
+    pub fn mixer(&self) -> Mixer {
+        self.mixer
+    }
+
+    pub fn set_mixer(&mut self, mixer: Mixer) {
+        self.mixer = mixer;
+    }
+
+    pub fn swap_duty_cycles(&self) -> bool {
+        self.swap_duty_cycles
+    }
+
+    /// Swaps the 25% and 50% pulse duty cycles, like some Famicom clones do.
+    /// Takes effect from the next write to the duty cycle.
+    pub fn set_swap_duty_cycles(&mut self, val: bool) {
+        self.swap_duty_cycles = val;
+    }
+
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// Changes the rate samples are put out at. The audio output has to be
+    /// playing them back at the same rate (see NesAudioStream::new).
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        let [high_pass1, high_pass2, low_pass] = output_filters(sample_rate);
+
+        self.sample_rate = sample_rate;
+        self.sample_period = 1.0 / sample_rate.hz() as f64;
+        self.high_pass1 = high_pass1;
+        self._high_pass2 = high_pass2;
+        self._low_pass = low_pass;
+    }
+
+    /// Sets how the APU batches samples before sending them to the audio queue.
+    /// batch_size is the largest batch that will be sent, and target_queue_depth
+    /// is how many samples adaptive batching tries to keep queued up.
+    pub fn set_batch_config(&mut self, batch_size: usize, target_queue_depth: usize, adaptive: bool) {
+        let batch_size = std::cmp::max(batch_size, MIN_SAMPLE_BATCH_SIZE);
+
+        self.max_batch_size = batch_size;
+        self.batch_size = batch_size;
+        self.adaptive_batching = adaptive;
+        self.target_queue_depth = target_queue_depth;
+
+        if self.sample_batch.len() >= self.batch_size {
+            self.send_sample_batch();
+        }
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Sets the emulation speed (1.0 is normal speed) so the audio can be
+    /// stretched to match. The audio dips for a moment around the change,
+    /// so the jump from the old stretch to the new one doesn't click.
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed == self.speed {
+            return;
+        }
+
+        self.speed = speed;
+        self.speed_grain.clear();
+        self.grain_credit = 0.0;
+        self.held_grain.clear();
+        self.held_plays = 0;
+        self.grain_continuation = [0.0; GRAIN_CROSSFADE_SIZE];
+
+        // Fade out what's been batched but not sent yet, and fade back in
+        let fade_len = std::cmp::min(self.sample_batch.len(), GRAIN_CROSSFADE_SIZE);
+        let fade_start = self.sample_batch.len() - fade_len;
+        for (i, sample) in self.sample_batch[fade_start..].iter_mut().enumerate() {
+            *sample *= (fade_len - i) as f32 / (fade_len + 1) as f32;
+        }
+        self.duck_gain = 0.0;
     }
 
     fn frame_update(&mut self) {
@@ -599,16 +1115,28 @@ impl Apu2A03 {
         self.trigger_irq
     }
 
+    /// Whether the DMC fetched a sample byte since the last call. Calling this
+    /// clears it.
+    pub fn take_dmc_dma(&mut self) -> bool {
+        std::mem::take(&mut self.dmc_dma)
+    }
+
     pub fn dmc_trigger_irq(&self) -> bool {
         self.dmc_channel.irq_triggered()
     }
 
-    pub fn set_trigger_irq(&mut self, val: bool) {
-        self.trigger_irq = val;
+    /// Fades the audio out and stops adding samples to the queue (or fades it
+    /// back in). Unlike blocking samples, the sound doesn't cut off mid-wave.
+    pub fn set_ducked(&mut self, ducked: bool) {
+        self.ducked = ducked;
     }
 
-    pub fn set_dmc_irq_flag(&mut self, val: bool) {
-        self.dmc_channel.set_irq_flag(val);
+    /// Sends out the samples waiting for the batch to fill up, so everything
+    /// made so far is in the queue
+    pub fn flush_samples(&mut self) {
+        if !self.block_samples && !self.sample_batch.is_empty() {
+            self.send_sample_batch();
+        }
     }
 
     pub fn set_block_samples(&mut self, val: bool) {
@@ -618,4 +1146,288 @@ impl Apu2A03 {
             self.send_sample_batch();
         }
     }
+
+    fn log_write(&mut self, address: u16, data: u8) {
+        if self.write_log.len() >= APU_WRITE_LOG_SIZE {
+            self.write_log.pop_front();
+        }
+        self.write_log.push_back(ApuWrite { clock: self.clocks, address, data });
+
+        // Games that play sampled audio (drums, voices, etc.) do it by writing
+        // straight to the DMC output level, so keep those around to be plotted
+        if address == 0x4011 {
+            if self.pcm_log.len() >= PCM_LOG_SIZE {
+                self.pcm_log.pop_front();
+            }
+            self.pcm_log.push_back(data & 0x7F);
+        }
+    }
+
+    pub fn debug_logging(&self) -> bool {
+        self.debug_logging
+    }
+
+    pub fn set_debug_logging(&mut self, val: bool) {
+        self.debug_logging = val;
+
+        if !self.debug_logging {
+            self.write_log.clear();
+            self.pcm_log.clear();
+        }
+    }
+
+    pub fn write_log(&self) -> &VecDeque<ApuWrite> {
+        &self.write_log
+    }
+
+    pub fn pcm_log(&self) -> &VecDeque<u8> {
+        &self.pcm_log
+    }
+
+    pub fn muted_channels(&self) -> ChannelMask {
+        self.muted_channels
+    }
+
+    pub fn set_channel_muted(&mut self, channel: NesChannel, muted: bool) {
+        self.muted_channels.set_muted(channel, muted);
+    }
+
+    pub fn toggle_channel_muted(&mut self, channel: NesChannel) {
+        let muted = self.muted_channels.is_muted(channel);
+        self.muted_channels.set_muted(channel, !muted);
+    }
+
+    pub fn solo_channel(&mut self, channel: NesChannel) {
+        self.muted_channels.solo(channel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::sample_ring::{SampleRing, SAMPLE_RING_CAPACITY};
+
+    // Register writes that get every channel going: pulse 1 with a decaying
+    // envelope and a sweep, pulse 2 looping its envelope, the triangle, the
+    // noise channel in short mode, and a looping DMC sample (which reads 0s
+    // with no cartridge in)
+    const SETUP_WRITES: [(u16, u8); 19] = [
+        (0x4015, 0x1F),
+        (0x4000, 0x83), (0x4001, 0xA9), (0x4002, 0x80), (0x4003, 0x09),
+        (0x4004, 0x66), (0x4006, 0x40), (0x4007, 0x0A),
+        (0x4008, 0x40), (0x400A, 0x30), (0x400B, 0x0B),
+        (0x400C, 0x25), (0x400E, 0x84), (0x400F, 0x08),
+        (0x4010, 0x4F), (0x4011, 0x40), (0x4012, 0x00), (0x4013, 0x04),
+        (0x4017, 0x00),
+    ];
+
+    fn apu() -> Apu2A03 {
+        let mut apu = Apu2A03::new(Arc::new(SampleRing::new(SAMPLE_RING_CAPACITY)));
+        apu.set_block_samples(true);
+
+        apu
+    }
+
+    // Runs the APU for the given number of clocks, mixing a sample every so
+    // often, and returns the samples
+    fn run(apu: &mut Apu2A03, clocks: usize) -> Vec<f32> {
+        (0..clocks)
+            .filter_map(|i| {
+                apu.cycle();
+                (i % 40 == 0).then(|| apu.generate_sample())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_restores_state() {
+        let mut original = apu();
+        for (address, data) in SETUP_WRITES {
+            original.cpu_write(address, data);
+        }
+        run(&mut original, 20_000);
+
+        let state = original.save_state();
+
+        let mut restored = apu();
+        restored.load_state(&state).unwrap();
+
+        assert_eq!(restored.save_state(), state);
+    }
+
+    #[test]
+    fn loaded_state_sounds_the_same() {
+        let mut original = apu();
+        for (address, data) in SETUP_WRITES {
+            original.cpu_write(address, data);
+        }
+        run(&mut original, 30_000);
+
+        let state = original.save_state();
+        let mut restored = apu();
+        restored.load_state(&state).unwrap();
+
+        // Long enough for envelopes to decay, sweeps to move the pulse's
+        // period and the DMC to loop its sample a few times
+        let clocks = 4 * WHOLE_FRAME_CLOCKS * 10;
+        let expected = run(&mut original, clocks);
+        let actual = run(&mut restored, clocks);
+
+        assert!(expected.iter().any(|&sample| sample != expected[0]), "the channels should be making sound");
+        assert_eq!(actual, expected);
+        assert_eq!(restored.save_state(), original.save_state());
+    }
+
+    #[test]
+    fn loading_keeps_settings() {
+        let mut apu = apu();
+        apu.set_channel_muted(NesChannel::Noise, true);
+        apu.set_speed(2.0);
+
+        let state = Apu2A03::new(Arc::new(SampleRing::new(SAMPLE_RING_CAPACITY))).save_state();
+        apu.load_state(&state).unwrap();
+
+        assert!(apu.muted_channels().is_muted(NesChannel::Noise));
+        assert_eq!(apu.speed, 2.0);
+    }
+
+    // Everything the APU has put out, sent to the queue or not
+    fn output(apu: &Apu2A03, queue: &SampleRing) -> Vec<f32> {
+        std::iter::from_fn(|| queue.pop()).chain(apu.sample_batch.iter().copied()).collect()
+    }
+
+    #[test]
+    fn ducking_fades_out_and_back_in() {
+        let queue = Arc::new(SampleRing::new(SAMPLE_RING_CAPACITY));
+        let mut apu = Apu2A03::new(Arc::clone(&queue));
+        let fade_len = (DUCK_FADE_SECS / apu.sample_period).ceil() as usize;
+
+        apu.set_ducked(true);
+        for _ in 0..fade_len * 2 {
+            apu.push_sample(1.0);
+        }
+
+        // Faded out a step at a time, then nothing more, with the end of the
+        // fade sent rather than left waiting for the batch to fill
+        let faded = output(&apu, &queue);
+        assert_eq!(faded.len(), fade_len);
+        assert!(faded.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(faded[0] > 0.9 && faded[fade_len - 1] == 0.0);
+        assert!(apu.sample_batch.is_empty());
+
+        apu.set_ducked(false);
+        for _ in 0..fade_len * 2 {
+            apu.push_sample(1.0);
+        }
+
+        let back = output(&apu, &queue);
+        assert_eq!(back.len(), fade_len * 2);
+        assert!(back.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!(back[0] < 0.1 && back[fade_len] == 1.0);
+    }
+
+    #[test]
+    fn stretching_crossfades_grains() {
+        let queue = Arc::new(SampleRing::new(SAMPLE_RING_CAPACITY));
+        let mut apu = Apu2A03::new(Arc::clone(&queue));
+        apu.set_speed(0.5);
+
+        // A ramp, so a repeated grain would jump from its end back to its start
+        for i in 0..SPEED_GRAIN_SIZE * 4 {
+            apu.push_sample(i as f32 / SPEED_GRAIN_SIZE as f32);
+        }
+
+        // At 50% the grains before the last are each played twice, and the
+        // joins between them never jump
+        let stretched = output(&apu, &queue);
+        assert_eq!(stretched.len(), SPEED_GRAIN_SIZE * 6);
+        assert!(stretched.windows(2).all(|pair| (pair[1] - pair[0]).abs() < 0.05));
+    }
+
+    #[cfg(feature = "frontend")]
+    #[test]
+    fn underruns_fade_out_and_blend_back_in() {
+        use crate::system::apu_util::NesAudioStream;
+
+        let (mut stream, queue) = NesAudioStream::new(SampleRate::default());
+        queue.push_slice(&[1.0; 4]);
+        let played: Vec<f32> = stream.by_ref().take(4).collect();
+        assert_eq!(played, [1.0; 4]);
+
+        // Running dry fades the last sample out rather than cutting to 0, and
+        // counts one underrun for the whole dry spell
+        let made_up: Vec<f32> = stream.by_ref().take(100).collect();
+        assert!(made_up[0] > 0.9 && made_up[99] < 0.5);
+        assert!(made_up.windows(2).all(|pair| pair[1] < pair[0]));
+        assert_eq!(queue.underruns(), 1);
+        assert_eq!(queue.missed_samples(), 100);
+
+        // Then ramps over to the real samples instead of jumping to them
+        queue.push_slice(&[1.0; 200]);
+        let back: Vec<f32> = stream.by_ref().take(200).collect();
+        assert!(back.windows(2).all(|pair| (pair[1] - pair[0]).abs() < 0.05));
+        assert_eq!(back[199], 1.0);
+    }
+
+    #[test]
+    fn reset_silences_every_channel() {
+        let mut apu = apu();
+        for (address, data) in SETUP_WRITES {
+            apu.cpu_write(address, data);
+        }
+        run(&mut apu, 20_000);
+
+        apu.reset();
+
+        assert!(apu.pulse1_channel.length_counter.is_zero());
+        assert!(apu.pulse2_channel.length_counter.is_zero());
+        assert!(!apu.triangle_channel.enabled);
+        assert!(apu.noise_channel.length_counter.is_zero());
+        assert!(!apu.dmc_channel.dmc_active());
+        assert!(!apu.take_dmc_dma());
+
+        // Nothing starts back up until the game turns the channels on again
+        run(&mut apu, 4 * WHOLE_FRAME_CLOCKS);
+        assert!(!apu.dmc_channel.dmc_active());
+        assert!(!apu.take_dmc_dma());
+    }
+
+    #[test]
+    fn reset_restarts_the_frame_counter() {
+        let mut apu = apu();
+        apu.cpu_write(0x4017, 0x00);
+        run(&mut apu, WHOLE_FRAME_CLOCKS * 3 + HALF_FRAME_CLOCKS);
+
+        apu.reset();
+        assert_eq!(apu.frame_clocks, 0);
+        assert_eq!(apu.frame_update_counter, 0);
+        assert!(!apu.trigger_irq());
+
+        // 5 step mode (and the IRQ being inhibited) is kept, and the first
+        // step gets clocked right away like a write to $4017 would
+        apu.cpu_write(0x4017, 0xC0);
+        apu.reset();
+        assert!(apu.frame_update_mode1);
+        assert!(!apu.irq_request_flag);
+        assert_eq!(apu.frame_update_counter, 1);
+    }
+
+    #[test]
+    fn rejects_bad_states() {
+        let state = apu().save_state();
+        let mut apu = apu();
+
+        // Cut short
+        assert!(apu.load_state(&state[..state.len() - 4]).is_err());
+
+        // Some other block
+        let mut other = StateWriter::new();
+        other.write_block(b"MAPR", 1, &[0; 8]);
+        assert!(apu.load_state(&other.into_bytes()).is_err());
+
+        // From a newer version
+        let mut newer = state.clone();
+        newer[4..6].copy_from_slice(&(APU_STATE_VERSION + 1).to_le_bytes());
+        assert!(apu.load_state(&newer).is_err());
+    }
 }
@@ -1,30 +1,94 @@
-use std::{collections::VecDeque, sync::{Arc, Mutex}, time::Duration};
+#[cfg(feature = "frontend")]
+use std::sync::Arc;
 
+use bitfield_struct::bitfield;
+#[cfg(feature = "frontend")]
 use rodio::Source;
 
-use super::apu::{CPU_CYCLE_PERIOD, CPU_FREQ, NES_AUDIO_FREQUENCY};
+use super::apu::{CPU_CYCLE_PERIOD, CPU_FREQ};
+use super::savestate::{StateReader, StateWriter};
+#[cfg(feature = "frontend")]
+use super::apu::SampleRate;
+#[cfg(feature = "frontend")]
+use super::sample_ring::{SampleQueue, SampleRing, SAMPLE_RING_CAPACITY};
 
+#[cfg(feature = "frontend")]
 #[derive(Debug, Default, Clone)]
 pub struct NesAudioStream {
-    // Using an arc mutex vecdeque allows us to directly queue up samples within
+    // Using a shared queue allows us to directly queue up samples within
     // this source. I would prefer to use rodio's SourcesInputQueue, but there
     // are weird popping artifacts in between sources if we do it that way.
     // Appending samples directly to a single source gets rid of this popping as
     // long as we always have samples in the queue.
-    sample_queue: Arc<Mutex<VecDeque<f32>>>,
+    sample_queue: SampleQueue,
+    // Has to match the rate the APU puts samples out at (see
+    // Apu2A03::set_sample_rate)
+    sample_rate: SampleRate,
+    // The last sample handed out, which is what gets faded out when the queue
+    // runs dry, and number of samples left in the ramp back to the real
+    // samples afterwards (see "ON AUDIO UNDERRUNS")
+    last_sample: f32,
+    recovering: usize,
 }
 
+/*
+ON AUDIO UNDERRUNS:
+
+If the emulator falls behind (the window being dragged, the OS not scheduling
+the app for a while) the sound card drains the sample queue and asks for samples
+that aren't there yet. Going straight to silence makes the speaker cone jump
+from wherever the wave was to 0, which is the pop you hear, and jumping back to
+the wave when samples come in again pops a second time.
+
+So the stream makes up samples itself while the queue is dry: the last real
+sample, fading towards 0 a bit more each sample, which is quiet enough to not
+be noticed for the few milliseconds a hiccup usually lasts. When samples come
+back, the first few are blended with the made up ones, ramping over to the real
+wave instead of jumping to it.
+
+That only hides the gap. The sample ring counts each dry spell (an underrun)
+and every sample made up, and the app looks at the underrun count after each
+frame; if it went up, the app runs an extra frame right away to put samples in
+the queue instead of waiting for the next redraw (see NesApp::window_event).
+Both counts and the extra frames are shown on the performance HUD.
+*/
+
+// How much of the last sample is kept for each made up one. At 44.1kHz this
+// gets to about a tenth of the way in 5ms.
+#[cfg(feature = "frontend")]
+const UNDERRUN_FADE: f32 = 0.99;
+
+// Number of samples it takes to blend back into the real samples after an
+// underrun, about 1.5ms at 44.1kHz
+#[cfg(feature = "frontend")]
+const UNDERRUN_RECOVERY_SAMPLES: usize = 64;
+
+#[cfg(feature = "frontend")]
 impl Iterator for NesAudioStream {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut sample_queue = self.sample_queue.lock().unwrap();
+        let sample = match self.sample_queue.pop() {
+            Some(sample) if self.recovering > 0 => {
+                let made_up = self.recovering as f32 / (UNDERRUN_RECOVERY_SAMPLES + 1) as f32;
+                self.recovering -= 1;
+
+                sample * (1.0 - made_up) + self.last_sample * made_up
+            }
+            Some(sample) => sample,
+            None => {
+                self.recovering = UNDERRUN_RECOVERY_SAMPLES;
+                self.last_sample * UNDERRUN_FADE
+            }
+        };
+        self.last_sample = sample;
 
         // Always return some so the source is never destroyed, even if it gets ahead.
-        Some(sample_queue.pop_front().unwrap_or(0.0))
+        Some(sample)
     }
 }
 
+#[cfg(feature = "frontend")]
 impl Source for NesAudioStream {
     fn current_frame_len(&self) -> Option<usize> {
         None
@@ -33,33 +97,29 @@ impl Source for NesAudioStream {
         1
     }
     fn sample_rate(&self) -> u32 {
-        NES_AUDIO_FREQUENCY
+        self.sample_rate.hz()
     }
-    fn total_duration(&self) -> Option<Duration> {
+    fn total_duration(&self) -> Option<std::time::Duration> {
         None
     }
 }
 
+#[cfg(feature = "frontend")]
 impl NesAudioStream {
-    pub fn new() -> (Self, Arc<Mutex<VecDeque<f32>>>) {
-        let sample_queue = Arc::new(Mutex::new(VecDeque::new()));
+    pub fn new(sample_rate: SampleRate) -> (Self, SampleQueue) {
+        let sample_queue = Arc::new(SampleRing::new(SAMPLE_RING_CAPACITY));
         let stream = Self { 
-            sample_queue: Arc::clone(&sample_queue) 
+            sample_queue: Arc::clone(&sample_queue),
+            sample_rate,
+            last_sample: 0.0,
+            recovering: 0,
         };
 
         (stream, sample_queue)
     }
-
-    pub fn push_sample(&mut self, sample: f32) {
-        self.sample_queue.lock().unwrap().push_back(sample);
-    }
-
-    pub fn push_sample_batch(&mut self, sample_batch: Vec<f32>) {
-        self.sample_queue.lock().unwrap().extend(sample_batch.into_iter());
-    }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
 pub enum NesChannel {
     #[default]
     Pulse1,
@@ -67,6 +127,84 @@ pub enum NesChannel {
     Triangle,
     Noise,
     DMC,
+    // Cartridge audio (VRC6, MMC5, etc.). None of the supported mappers have
+    // any yet, but the mask is here so it can be muted once they do.
+    Expansion,
+}
+
+impl NesChannel {
+    pub const ALL: [NesChannel; 6] = [
+        NesChannel::Pulse1,
+        NesChannel::Pulse2,
+        NesChannel::Triangle,
+        NesChannel::Noise,
+        NesChannel::DMC,
+        NesChannel::Expansion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NesChannel::Pulse1 => "Pulse 1",
+            NesChannel::Pulse2 => "Pulse 2",
+            NesChannel::Triangle => "Triangle",
+            NesChannel::Noise => "Noise",
+            NesChannel::DMC => "DMC",
+            NesChannel::Expansion => "Expansion",
+        }
+    }
+}
+
+/// Set of channels muted by the user. This is purely an emulator feature (the
+/// real APU has no such thing), so a muted channel keeps running as normal and
+/// only has its output replaced with silence in the mixer.
+#[bitfield(u8)]
+#[derive(PartialEq, Eq)]
+pub struct ChannelMask {
+    pub pulse1: bool,
+    pub pulse2: bool,
+    pub triangle: bool,
+    pub noise: bool,
+    pub dmc: bool,
+    pub expansion: bool,
+    #[bits(2)]
+    __: u8,
+}
+
+impl ChannelMask {
+    pub fn is_muted(&self, channel: NesChannel) -> bool {
+        match channel {
+            NesChannel::Pulse1 => self.pulse1(),
+            NesChannel::Pulse2 => self.pulse2(),
+            NesChannel::Triangle => self.triangle(),
+            NesChannel::Noise => self.noise(),
+            NesChannel::DMC => self.dmc(),
+            NesChannel::Expansion => self.expansion(),
+        }
+    }
+
+    pub fn set_muted(&mut self, channel: NesChannel, muted: bool) {
+        match channel {
+            NesChannel::Pulse1 => self.set_pulse1(muted),
+            NesChannel::Pulse2 => self.set_pulse2(muted),
+            NesChannel::Triangle => self.set_triangle(muted),
+            NesChannel::Noise => self.set_noise(muted),
+            NesChannel::DMC => self.set_dmc(muted),
+            NesChannel::Expansion => self.set_expansion(muted),
+        }
+    }
+
+    /// Mutes every channel except the given one. Soloing a channel that is
+    /// already soloed unmutes everything again.
+    pub fn solo(&mut self, channel: NesChannel) {
+        let mut soloed = ChannelMask::from(0x3F);
+        soloed.set_muted(channel, false);
+
+        if *self == soloed {
+            *self = ChannelMask::new();
+        } else {
+            *self = soloed;
+        }
+    }
 }
 
 
@@ -76,7 +214,6 @@ pub struct LengthCounter {
     counter: usize,
 
     channel_enabled: bool,
-    silence_channel: bool,
 }
 
 impl LengthCounter {
@@ -99,10 +236,13 @@ impl LengthCounter {
                 }
             }
         }
-
-        self.silence_channel = self.is_zero();
     }
 
+    /// Enables or disables the channel through $4015. Disabling a channel
+    /// clears its length counter right away (silencing it on the spot), and
+    /// while disabled, writes to the length counter are ignored. Enabling it
+    /// doesn't reload anything, the game has to write a new length.
+    /// https://www.nesdev.org/wiki/APU#Status_($4015)
     pub fn set_channel_enabled(&mut self, val: bool) {
         self.channel_enabled = val;
         if !self.channel_enabled {
@@ -121,12 +261,26 @@ impl LengthCounter {
     }
 
     pub fn is_silencing_channel(&self) -> bool {
-        self.silence_channel
+        self.is_zero()
     }
 
     pub fn is_zero(&self) -> bool {
         self.counter == 0
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.halted);
+        state.write_usize(self.counter);
+        state.write_bool(self.channel_enabled);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.halted = state.read_bool()?;
+        self.counter = state.read_usize()?;
+        self.channel_enabled = state.read_bool()?;
+
+        Ok(())
+    }
 }
 
 #[derive(Default)]
@@ -167,6 +321,22 @@ impl LinearCounter {
     pub fn set_control_flag(&mut self, val: bool) {
         self.control = val;
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.control);
+        state.write_bool(self.reload_flag);
+        state.write_usize(self.reload_value);
+        state.write_usize(self.counter);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.control = state.read_bool()?;
+        self.reload_flag = state.read_bool()?;
+        self.reload_value = state.read_usize()?;
+        self.counter = state.read_usize()?;
+
+        Ok(())
+    }
 }
 
 #[derive(Default)]
@@ -226,9 +396,123 @@ impl VolumeEnvelope {
     pub fn set_loop_flag(&mut self, val: bool) {
         self.loop_flag = val;
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.start);
+        state.write_bool(self.const_volume);
+        state.write_usize(self.divider);
+        state.write_usize(self.decay);
+        state.write_usize(self.volume);
+        state.write_bool(self.loop_flag);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.start = state.read_bool()?;
+        self.const_volume = state.read_bool()?;
+        self.divider = state.read_usize()?;
+        self.decay = state.read_usize()?;
+        self.volume = state.read_usize()?;
+        self.loop_flag = state.read_bool()?;
+
+        Ok(())
+    }
 }
 
 
+/*
+ON HOW BAND-LIMITED SYNTHESIS WORKS:
+
+The pulse and triangle channels are worked out from where the current sample
+falls in the wave's period. Just reading off the wave at that point (naive
+synthesis) makes every jump in the wave land on a whole sample, which is the
+same as adding in harmonics far above what the sample rate can hold. Those fold
+back down as aliasing: inharmonic whistles that get worse the higher the note.
+
+Band-limited synthesis rounds off each jump over the samples either side of it
+with a small polynomial correction (a polyBLEP, for band-limited step), sized
+by how big the jump is and placed by where it really happened between the two
+samples. That takes out most of the aliasing for a few extra multiplies.
+
+The triangle channel is a 32 step staircase, so each of its steps gets the
+same treatment. Once the steps come faster than every other sample, their
+harmonics are all above the highest frequency the sample rate can hold anyway,
+and what's left is a plain triangle wave, which barely aliases at all.
+
+Naive synthesis is still there for slow machines, since it skips the
+corrections entirely.
+https://www.nesdev.org/wiki/APU_Mixer
+*/
+
+/// How the pulse and triangle waves are generated (see "ON HOW BAND-LIMITED
+/// SYNTHESIS WORKS")
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Synthesis {
+    #[default]
+    BandLimited,
+    Naive,
+}
+
+impl Synthesis {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "bandlimited" => Some(Self::BandLimited),
+            "naive" => Some(Self::Naive),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::BandLimited => "bandlimited",
+            Self::Naive => "naive",
+        }
+    }
+}
+
+/// How the channels are mixed together. The real APU's output isn't linear:
+/// the louder the other channels, the quieter each one gets. A linear mix is
+/// there for comparing against.
+/// https://www.nesdev.org/wiki/APU_Mixer
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mixer {
+    #[default]
+    Nonlinear,
+    Linear,
+}
+
+impl Mixer {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "nonlinear" => Some(Self::Nonlinear),
+            "linear" => Some(Self::Linear),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Nonlinear => "nonlinear",
+            Self::Linear => "linear",
+        }
+    }
+}
+
+// The correction that rounds off a jump from -1 to 1 at phase 0 (which is also
+// phase 1), given where in the period the sample is and how far through the
+// period one sample goes. Anything more than a sample away from the jump is
+// left alone.
+fn poly_blep(phase: f64, phase_step: f64) -> f64 {
+    if phase < phase_step {
+        let x = phase / phase_step;
+        2.0 * x - x * x - 1.0
+    } else if phase > 1.0 - phase_step {
+        let x = (phase - 1.0) / phase_step;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
 #[derive(Default)]
 pub struct PulseChannel {
     sweep_negate_offset: isize,
@@ -268,7 +552,9 @@ impl PulseChannel {
         }
     }
 
-    pub fn sample(&mut self, total_clocks: u64) -> f32 {
+    /// Samples the channel at the given time. sample_period is the time
+    /// between samples (in seconds), which band-limited synthesis needs.
+    pub fn sample(&mut self, total_clocks: u64, synthesis: Synthesis, sample_period: f64) -> f32 {
         let mut sample = 0.0;
 
         if self.enabled {
@@ -284,11 +570,20 @@ impl PulseChannel {
                 } else {
                     sample = 0.0;
                 }
+
+                // The wave jumps up at the start of the period and back down
+                // at the end of the duty cycle
+                if synthesis == Synthesis::BandLimited {
+                    let phase_step = self.freq * sample_period;
+                    let fall_phase = (remainder - self.duty_cycle_percent).rem_euclid(1.0);
+
+                    sample += 0.5 * (poly_blep(remainder, phase_step) - poly_blep(fall_phase, phase_step));
+                }
             }
         }
 
         // The sample is scaled by the envelope to be a value in the range [0.0, 15.0]
-        sample * self.envelope.output() as f32
+        sample as f32 * self.envelope.output() as f32
     }
 
     // https://www.nesdev.org/wiki/APU_Sweep
@@ -365,6 +660,42 @@ impl PulseChannel {
         self.sweep_reload_flag = val;
     }
 
+    // The channel's frequency isn't saved, it's worked out again from the
+    // timer. Where the channel is in its waveform comes from the APU's clock.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_usize(self.timer_reload);
+        state.write_bool(self.enabled);
+        state.write_f64(self.duty_cycle_percent);
+
+        state.write_bool(self.sweep_enabled);
+        state.write_bool(self.sweep_negate);
+        state.write_usize(self.sweep_divider);
+        state.write_bool(self.sweep_reload_flag);
+        state.write_usize(self.sweep_reload_value);
+        state.write_usize(self.sweep_shift);
+
+        self.length_counter.save_state(state);
+        self.envelope.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        let timer_reload = state.read_usize()?;
+        self.enabled = state.read_bool()?;
+        self.duty_cycle_percent = state.read_f64()?;
+
+        self.sweep_enabled = state.read_bool()?;
+        self.sweep_negate = state.read_bool()?;
+        self.sweep_divider = state.read_usize()?;
+        self.sweep_reload_flag = state.read_bool()?;
+        self.sweep_reload_value = state.read_usize()?;
+        self.sweep_shift = state.read_usize()?;
+
+        // After the sweep settings, since the target period depends on them
+        self.set_timer_reload(timer_reload);
+
+        self.length_counter.load_state(state)?;
+        self.envelope.load_state(state)
+    }
 }
 
 
@@ -387,11 +718,9 @@ impl TriangleChannel {
          8.0,  9.0, 10.0, 11.0, 12.0, 13.0, 14.0, 15.0,
     ];
 
-    pub fn new() -> Self {
-        Self::default()
-    }
-
-    pub fn sample(&mut self, total_clocks: u64) -> f32 {
+    /// Samples the channel at the given time. sample_period is the time
+    /// between samples (in seconds), which band-limited synthesis needs.
+    pub fn sample(&mut self, total_clocks: u64, synthesis: Synthesis, sample_period: f64) -> f32 {
         // This is not how the NES works, but some games set the triangle timer
         // to 0 to "silence" the channel. This doesn't actually silence it, however,
         // and instead an extremely high frequency wave is produced. At the cost
@@ -409,8 +738,26 @@ impl TriangleChannel {
             let remainder = (time * self.freq).fract();
 
             let sequencer_idx = (32.0 * remainder) as usize;
+            let mut sample = Self::SEQUENCER_LOOKUP[sequencer_idx] as f64;
+
+            if synthesis == Synthesis::BandLimited {
+                // Where the sample is within the current step, and how far
+                // through a step one sample goes
+                let step_phase = (32.0 * remainder).fract();
+                let phase_step = 32.0 * self.freq * sample_period;
+
+                if phase_step >= 0.5 {
+                    sample = 15.0 * (1.0 - 2.0 * remainder).abs();
+                } else if step_phase < phase_step {
+                    let jump = Self::SEQUENCER_LOOKUP[sequencer_idx] - Self::SEQUENCER_LOOKUP[(sequencer_idx + 31) % 32];
+                    sample += 0.5 * jump as f64 * poly_blep(step_phase, phase_step);
+                } else if step_phase > 1.0 - phase_step {
+                    let jump = Self::SEQUENCER_LOOKUP[(sequencer_idx + 1) % 32] - Self::SEQUENCER_LOOKUP[sequencer_idx];
+                    sample += 0.5 * jump as f64 * poly_blep(step_phase, phase_step);
+                }
+            }
 
-            return Self::SEQUENCER_LOOKUP[sequencer_idx];
+            return sample as f32;
         }
 
         0.0
@@ -433,6 +780,23 @@ impl TriangleChannel {
         self.enabled = val;
         self.length_counter.set_channel_enabled(val);
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_usize(self.timer_reload);
+        state.write_bool(self.enabled);
+
+        self.length_counter.save_state(state);
+        self.linear_counter.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        let timer_reload = state.read_usize()?;
+        self.set_timer_reload(timer_reload);
+        self.enabled = state.read_bool()?;
+
+        self.length_counter.load_state(state)?;
+        self.linear_counter.load_state(state)
+    }
 }
 
 
@@ -519,6 +883,28 @@ impl NoiseChannel {
         self.enabled = val;
         self.length_counter.set_channel_enabled(val);
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.rand_shifter);
+        state.write_usize(self.period_reload);
+        state.write_usize(self.period);
+        state.write_bool(self.enabled);
+        state.write_bool(self.mode);
+
+        self.length_counter.save_state(state);
+        self.envelope.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.rand_shifter = state.read_u16()?;
+        self.period_reload = state.read_usize()?;
+        self.period = state.read_usize()?;
+        self.enabled = state.read_bool()?;
+        self.mode = state.read_bool()?;
+
+        self.length_counter.load_state(state)?;
+        self.envelope.load_state(state)
+    }
 }
 
 
@@ -558,6 +944,9 @@ impl DmcChannel {
         Self {
             sample_start_addr: 0xC000,
             current_addr: 0xC000,
+            // The sample buffer starts out empty
+            need_next_byte: true,
+            silenced: true,
             ..Default::default()
         }
     }
@@ -624,8 +1013,10 @@ impl DmcChannel {
         self.bytes_remaining = self.sample_len;
     }
 
+    /// Whether the channel wants to fetch another byte of its sample (the
+    /// sample buffer is empty and there are bytes left to play)
     pub fn need_next_clip_byte(&self) -> bool {
-        self.need_next_byte
+        self.need_next_byte && self.bytes_remaining > 0
     }
 
     pub fn current_sample_addr(&self) -> u16 {
@@ -640,10 +1031,13 @@ impl DmcChannel {
         self.irq_requested
     }
 
-    pub fn set_irq_flag(&mut self, val: bool) {
-        self.irq_requested = val;
-    }
-
+    /// Enables or disables the channel through $4015. Disabling it stops any
+    /// more sample bytes from being fetched, but whatever is already in the
+    /// sample buffer and shift register still plays out. Enabling it only
+    /// restarts the sample if the last one had finished, otherwise it carries
+    /// on from where it was. If the sample buffer is empty, the first byte is
+    /// fetched right away.
+    /// https://www.nesdev.org/wiki/APU_DMC
     pub fn set_enable(&mut self, val: bool) {
         self.enabled = val;
 
@@ -655,11 +1049,16 @@ impl DmcChannel {
             self.bytes_remaining = 0;
         }
         
-        self.irq_enabled = false;
+        // Any write to $4015 acknowledges the DMC interrupt
+        self.irq_requested = false;
     }
 
     pub fn set_irq_enable(&mut self, val: bool) {
         self.irq_enabled = val;
+
+        if !val {
+            self.irq_requested = false;
+        }
     }
 
     pub fn set_loop_flag(&mut self, val: bool) {
@@ -674,6 +1073,12 @@ impl DmcChannel {
         self.output = data;
     }
 
+    /// Resetting the NES clears all but the lowest bit of the output level
+    /// https://www.nesdev.org/wiki/CPU_power_up_state
+    pub fn reset_output(&mut self) {
+        self.output &= 1;
+    }
+
     pub fn set_clip_address(&mut self, addr: u16) {
         self.sample_start_addr = addr;
     }
@@ -681,4 +1086,54 @@ impl DmcChannel {
     pub fn set_clip_length(&mut self, data: usize) {
         self.sample_len = data;
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_usize(self.divider);
+        state.write_usize(self.divider_reload_value);
+        state.write_bool(self.enabled);
+        state.write_bool(self.loop_flag);
+
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_requested);
+
+        state.write_u8(self.next_byte);
+        state.write_bool(self.need_next_byte);
+
+        state.write_usize(self.bytes_remaining);
+        state.write_usize(self.sample_len);
+        state.write_u16(self.sample_start_addr);
+        state.write_u16(self.current_addr);
+
+        state.write_usize(self.bits_remaining);
+        state.write_u8(self.dmc_shifter);
+        state.write_bool(self.silenced);
+
+        state.write_u8(self.output);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.divider = state.read_usize()?;
+        self.divider_reload_value = state.read_usize()?;
+        self.enabled = state.read_bool()?;
+        self.loop_flag = state.read_bool()?;
+
+        self.irq_enabled = state.read_bool()?;
+        self.irq_requested = state.read_bool()?;
+
+        self.next_byte = state.read_u8()?;
+        self.need_next_byte = state.read_bool()?;
+
+        self.bytes_remaining = state.read_usize()?;
+        self.sample_len = state.read_usize()?;
+        self.sample_start_addr = state.read_u16()?;
+        self.current_addr = state.read_u16()?;
+
+        self.bits_remaining = state.read_usize()?;
+        self.dmc_shifter = state.read_u8()?;
+        self.silenced = state.read_bool()?;
+
+        self.output = state.read_u8()?;
+
+        Ok(())
+    }
 }
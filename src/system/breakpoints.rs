@@ -0,0 +1,128 @@
+/*
+ON HOW WRITE BREAKPOINTS WORK:
+
+A write breakpoint stops the emulator when the CPU writes a particular value
+(or bit pattern) to an address. They're meant for the PPU and APU registers,
+where the interesting bugs are a write that lands at the wrong time, like a
+$2001 write turning rendering off halfway down the screen.
+
+Breakpoints are written as the address in hex, optionally followed by a mask
+and a value the masked bits have to equal, and "@midframe" to only break while
+the PPU is drawing the picture (scanlines 0-239):
+
+    2001                 any write to PPUMASK
+    2001=1E              a write of $1E
+    2001&18=00@midframe  a write turning off both the background and sprites,
+                         while the picture is being drawn
+    4015&10=00           a write stopping the DMC
+
+The PPU's registers repeat every 8 bytes from $2008 to $3FFF, so a breakpoint
+on $2001 also catches writes to $2009, $3FF9 and so on.
+
+The check happens in the CPU's write path, and when no breakpoints are set
+it's a single check of an empty list, so having the feature costs nothing
+until it's used. A write that matches is published on the event bus as a
+WriteBreak (see events), and the NES stops running at the end of the
+instruction that made it. The mid-frame condition is checked by the Nes,
+which knows where the PPU is.
+https://www.nesdev.org/wiki/PPU_registers
+*/
+
+/// A condition on the value written to an address (see "ON HOW WRITE
+/// BREAKPOINTS WORK")
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WriteBreakpoint {
+    pub address: u16,
+    // The write matches when (data & mask) == value
+    pub mask: u8,
+    pub value: u8,
+    // Only while the PPU is drawing the picture
+    pub mid_frame: bool,
+}
+
+impl WriteBreakpoint {
+    /// Reads a breakpoint written as "address[&mask][=value][@midframe]" in
+    /// hex (see "ON HOW WRITE BREAKPOINTS WORK")
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let (rest, mid_frame) = match code.trim().strip_suffix("@midframe") {
+            Some(rest) => (rest, true),
+            None => (code.trim(), false),
+        };
+
+        let (rest, value) = match rest.split_once('=') {
+            Some((rest, value)) => (rest, Some(value)),
+            None => (rest, None),
+        };
+        let (address, mask) = match rest.split_once('&') {
+            Some((address, mask)) => (address, Some(mask)),
+            None => (rest, None),
+        };
+
+        let hex_byte = |text: &str, what: &str| u8::from_str_radix(text.trim().trim_start_matches('$'), 16)
+            .map_err(|_| format!("Breakpoint '{code}' has a bad {what} '{text}'"));
+
+        let address = u16::from_str_radix(address.trim().trim_start_matches('$'), 16)
+            .map_err(|_| format!("Breakpoint '{code}' has a bad address '{address}'"))?;
+        let mask = match mask {
+            Some(mask) => hex_byte(mask, "mask")?,
+            None if value.is_some() => 0xFF,
+            None => 0,
+        };
+        let value = match value {
+            Some(value) => hex_byte(value, "value")?,
+            None if mask != 0 => return Err(format!("Breakpoint '{code}' has a mask but no value, like 2001&18=00")),
+            None => 0,
+        };
+
+        if value & !mask != 0 {
+            return Err(format!("Breakpoint '{code}' can never match, the value has bits outside the mask"));
+        }
+
+        Ok(Self { address: register_address(address), mask, value, mid_frame })
+    }
+
+    /// The breakpoint written the way parse reads it
+    pub fn code(&self) -> String {
+        let mut code = format!("{:04X}", self.address);
+
+        if self.mask != 0 && self.mask != 0xFF {
+            code += &format!("&{:02X}", self.mask);
+        }
+        if self.mask != 0 {
+            code += &format!("={:02X}", self.value);
+        }
+        if self.mid_frame {
+            code += "@midframe";
+        }
+
+        code
+    }
+
+    /// Whether writing data to address matches the breakpoint (leaving out
+    /// the mid-frame condition)
+    pub fn matches(&self, address: u16, data: u8) -> bool {
+        register_address(address) == self.address && data & self.mask == self.value
+    }
+}
+
+/// A write that matched a breakpoint
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WriteBreak {
+    pub breakpoint: WriteBreakpoint,
+    // The address as written, before the PPU's mirrors are folded in
+    pub address: u16,
+    pub data: u8,
+    // Address of the instruction that made the write
+    pub pc: u16,
+    // Where the PPU was, filled in by the Nes
+    pub scanline: usize,
+    pub dot: usize,
+}
+
+// The PPU's 8 registers are mirrored all the way up to $3FFF
+fn register_address(address: u16) -> u16 {
+    match address {
+        0x2000..=0x3FFF => 0x2000 | (address & 7),
+        _ => address,
+    }
+}
@@ -0,0 +1,528 @@
+use std::cell::{Cell, RefCell, RefMut};
+use std::rc::Rc;
+
+use crate::cartridge::mapper::Mapper;
+
+use super::apu::Apu2A03;
+use super::controller::{DeviceSlot, SharedDevice, StandardController, Unplugged};
+use super::ppu::Ppu2C02;
+use super::savestate::{StateReader, StateWriter};
+use super::telemetry::{Telemetry, UnsupportedFeature, UnsupportedUse};
+
+// NES has 2KiB of internal memory that only the CPU can access
+const SYS_RAM_SIZE: usize = 0x800;
+
+/// What's in the internal RAM when the NES is turned on. On real hardware it's
+/// whatever the RAM chip happens to power up with, which is different between
+/// consoles (and sometimes between boots), so this lets games and test ROMs
+/// that care be tried with a few different ones. Resetting doesn't touch RAM.
+/// https://www.nesdev.org/wiki/CPU_power_up_state
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RamFill {
+    // Every byte set to the same value, usually $00 or $FF
+    Value(u8),
+    // Random bytes, from the given seed so a run can be repeated
+    Random(u64),
+}
+
+impl Default for RamFill {
+    fn default() -> Self {
+        Self::Value(0x00)
+    }
+}
+
+impl RamFill {
+    /// Parses "00", "ff" (or any other byte in hex), "random", or
+    /// "random:<seed>". Without a seed, one comes from new_seed. The core
+    /// doesn't read the clock itself, since it can't on every target.
+    pub fn from_name(s: &str, new_seed: impl FnOnce() -> u64) -> Option<Self> {
+        let s = s.to_lowercase();
+
+        if s == "random" {
+            return Some(Self::Random(new_seed()));
+        }
+        if let Some(seed) = s.strip_prefix("random:") {
+            return seed.parse().ok().map(Self::Random);
+        }
+
+        u8::from_str_radix(s.trim_start_matches('$'), 16).ok().map(Self::Value)
+    }
+
+    pub fn fill(&self, ram: &mut [u8]) {
+        match *self {
+            Self::Value(value) => ram.fill(value),
+            Self::Random(seed) => {
+                // xorshift64, which just needs a seed that isn't 0
+                let mut state = seed | 1;
+
+                for byte in ram.iter_mut() {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = (state >> 32) as u8;
+                }
+            }
+        }
+    }
+}
+
+/*
+ON HOW THE BUS WORKS:
+
+The 6502 doesn't know anything about the NES. All it does is put addresses on
+the bus and read or write whatever's there, so everything it can see (RAM, the
+PPU and APU registers, controllers, and the cartridge) lives behind the Bus
+trait instead of inside Cpu6502. NesBus is the real NES memory map:
+
+  $0000-$07FF  2KiB of internal RAM, mirrored up to $1FFF
+  $2000-$2007  PPU registers, mirrored up to $3FFF
+  $4000-$4017  APU and I/O registers
+  $4018-$401F  Normally disabled test registers
+  $4020-$FFFF  Cartridge space (PRG RAM, PRG ROM, mapper registers)
+
+The mapper gets first dibs on every access, the same way it does on the real
+console where the cartridge can see the whole bus. Reads from addresses nothing
+answers to (and the undriven bits of some registers) return "open bus", which
+is just whatever value was last on the data bus, since the bus lines hold onto
+it for a little while. Other buses can be swapped in without touching the CPU
+core, like RamBus, which is just 64KiB of RAM for running CPU tests that don't
+expect an NES around them.
+https://www.nesdev.org/wiki/Open_bus_behavior
+https://www.nesdev.org/wiki/CPU_memory_map
+*/
+
+/// Everything the CPU can read from or write to
+pub trait Bus {
+    /// Read a single byte from the given address. Takes &self so the debugger
+    /// can read memory, but reads can still have side effects (like advancing
+    /// the controller shift registers), so buses use interior mutability.
+    fn read(&self, address: u16) -> u8;
+    /// Read a single byte from the given address without any of the side
+    /// effects a real read has, for the debugger. The default just reads, so
+    /// buses where reading changes anything have to provide their own.
+    fn peek(&self, address: u16) -> u8 {
+        self.read(address)
+    }
+    /// Write a single byte to the given address
+    fn write(&mut self, address: u16, data: u8);
+    /// Write a single byte to the given address on the cycle right after
+    /// another write, which only the second write of a read-modify-write
+    /// instruction does. Some hardware (MMC1) ignores writes like these.
+    fn write_consecutive(&mut self, address: u16, data: u8) {
+        self.write(address, data);
+    }
+    /// Number of cycles the CPU has to stall for because of the last write
+    /// (e.g. while an OAM DMA copies sprite data). Calling this clears it.
+    ///
+    ///  * `cpu_clock` - The CPU clock the write happened on
+    fn take_stall_cycles(&mut self, _cpu_clock: u64) -> usize { 0 }
+    /// Plugs an input device into one of the console's slots (see "ON HOW
+    /// INPUT DEVICES WORK"). Buses without input ports ignore it.
+    fn plug_device(&mut self, _slot: DeviceSlot, _device: SharedDevice) {}
+    /// Repeats the most recent read, the way the CPU does when a DMA halts it
+    /// partway through one. Only matters for registers where reading has side
+    /// effects.
+    fn repeat_last_read(&self) {}
+    /// Number of times the game has latched the controllers' buttons (by
+    /// clearing the strobe bit). Buses without controller ports never do.
+    fn controller_polls(&self) -> u64 { 0 }
+    /// Counts the game doing something that isn't emulated (see telemetry).
+    /// Only the NES's bus keeps count.
+    fn record_unsupported(&mut self, _feature: UnsupportedFeature) {}
+    /// The unsupported things counted so far
+    fn unsupported_uses(&self) -> &[UnsupportedUse] { &[] }
+    /// Called as the CPU starts each instruction (or interrupt), so the bus
+    /// can tell which cycle of it each access happens on. Only the NES's bus
+    /// needs to, to keep the PPU in step (see "ON HOW THE CPU AND PPU KEEP IN
+    /// STEP").
+    fn instruction_started(&mut self) {}
+    /// Counts accesses the CPU skipped as if they'd been made, for when the
+    /// cached CPU mode leaves out an instruction's fetches (see "ON THE CACHED
+    /// CPU MODE")
+    fn skip_accesses(&mut self, _count: usize) {}
+    /// Adds whatever the bus itself keeps (like RAM) to the CPU's save state
+    /// (see Cpu6502::save_state). Buses with nothing worth keeping write
+    /// nothing.
+    fn save_state(&self, _state: &mut StateWriter) {}
+    /// Reads back what save_state wrote
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), String> { Ok(()) }
+}
+
+/// The NES's CPU memory map
+pub struct NesBus {
+    // Memory accessable only by the CPU
+    sys_ram: [u8; SYS_RAM_SIZE],
+
+    // What's plugged into the two controller ports and the expansion port,
+    // indexed by DeviceSlot
+    devices: [SharedDevice; 3],
+    // The last value written to the strobe bit
+    strobe: bool,
+    // Number of times the strobe bit has been cleared, for measuring input lag
+    controller_polls: u64,
+
+    // Set when an OAM DMA starts, so the CPU knows to stall for it
+    oam_dma_started: bool,
+    // Address of the most recent read, for repeat_last_read
+    last_read_addr: Cell<u16>,
+    // Number of reads and writes the current instruction has made, which is
+    // the cycle of it the latest one happened on
+    instr_accesses: Cell<usize>,
+    // Last value that was on the data bus, returned by reads nothing answers
+    open_bus: Cell<u8>,
+
+    // What the game has done that isn't emulated (see telemetry)
+    telemetry: Telemetry,
+
+    mapper: Rc<RefCell<dyn Mapper>>,
+    ppu: Rc<RefCell<Ppu2C02>>,
+    apu: Rc<RefCell<Apu2A03>>,
+}
+
+impl NesBus {
+    pub fn new(ppu: Rc<RefCell<Ppu2C02>>,
+            apu: Rc<RefCell<Apu2A03>>,
+            mapper: Rc<RefCell<dyn Mapper>>
+        ) -> Self {
+
+        Self {
+            sys_ram: [0; SYS_RAM_SIZE],
+
+            devices: [
+                Rc::new(RefCell::new(StandardController::default())),
+                Rc::new(RefCell::new(StandardController::default())),
+                Rc::new(RefCell::new(Unplugged)),
+            ],
+            strobe: true,
+            controller_polls: 0,
+
+            oam_dma_started: false,
+            last_read_addr: Cell::new(0),
+            instr_accesses: Cell::new(0),
+            open_bus: Cell::new(0),
+
+            telemetry: Telemetry::default(),
+
+            mapper,
+            ppu,
+            apu,
+        }
+    }
+
+    /// Sets the internal RAM to what it should be at power on
+    pub fn fill_ram(&mut self, fill: RamFill) {
+        fill.fill(&mut self.sys_ram);
+    }
+
+    // Reads anything the cartridge didn't answer to
+    fn read_internal(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => {
+                // First 2KiB of memory (0x0800) are mirrored until 0x2000
+                self.sys_ram[(address & 0x07FF) as usize]
+            },
+            0x2000..=0x3FFF => {
+                // PPU Registers mirrored over 8KiB
+                self.ppu_in_step().cpu_read(address)
+            }
+            0x4015 => {
+                self.apu.as_ref().borrow_mut().cpu_read(address)
+            }
+            0x4016 | 0x4017 => {
+                // Controller ports, shared with the expansion port
+                let port = (address - 0x4016) as usize;
+
+                self.input_open_bus()
+                    | self.devices[port].borrow_mut().read(port)
+                    | self.devices[DeviceSlot::Expansion as usize].borrow_mut().read(port)
+            }
+            // Write-only APU registers, the disabled test registers, and any
+            // cartridge space the mapper doesn't answer to
+            _ => self.open_bus.get(),
+        }
+    }
+
+    /// The same as read_internal, leaving the PPU, APU and controller ports as
+    /// they were
+    fn peek_internal(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.sys_ram[(address & 0x07FF) as usize],
+            0x2000..=0x3FFF => self.ppu.as_ref().borrow().peek_cpu(address),
+            0x4015 => self.apu.as_ref().borrow().peek_cpu(address),
+            0x4016 | 0x4017 => {
+                let port = (address - 0x4016) as usize;
+
+                self.input_open_bus()
+                    | self.devices[port].borrow().peek(port)
+                    | self.devices[DeviceSlot::Expansion as usize].borrow().peek(port)
+            }
+            _ => self.open_bus.get(),
+        }
+    }
+
+    // Runs the PPU up to the dot the current access happens on (see "ON HOW
+    // THE CPU AND PPU KEEP IN STEP")
+    fn ppu_in_step(&self) -> RefMut<'_, Ppu2C02> {
+        let mut ppu = self.ppu.as_ref().borrow_mut();
+        ppu.catch_up(3 * self.instr_accesses.get().saturating_sub(1));
+
+        ppu
+    }
+
+    // Counts a read or write made by the current instruction
+    fn count_access(&self) {
+        self.instr_accesses.set(self.instr_accesses.get() + 1);
+    }
+
+    // The bits of a controller port read that no input device drives
+    // https://www.nesdev.org/wiki/Open_bus_behavior
+    fn input_open_bus(&self) -> u8 {
+        self.open_bus.get() & 0xE0
+    }
+
+    // Counts writes to things that aren't emulated (see telemetry)
+    fn check_unsupported_write(&mut self, address: u16, data: u8) {
+        let feature = match address {
+            0x2000..=0x3FFF if address & 7 == 0 && data & 0x40 != 0 => UnsupportedFeature::PpuExtOutput,
+            0x4009 | 0x400D => UnsupportedFeature::UnusedApuRegister { address },
+            0x4018..=0x401F => UnsupportedFeature::CpuTestRegister { address },
+            0x4020..=0xFFFF if !self.mapper.borrow().cpu_write_has_target(address) => {
+                UnsupportedFeature::CartWrite { window: address & 0xF000 }
+            }
+            _ => return,
+        };
+
+        self.record_unsupported(feature);
+    }
+
+    fn write_internal(&mut self, address: u16, data: u8) {
+        match address {
+            // CPU RAM
+            0x0000..=0x1FFF => {
+                // First 2KiB of memory (0x0800) are mirrored until 0x2000
+                self.sys_ram[(address & 0x07FF) as usize] = data;
+            },
+
+            // PPU Internal Registers
+            0x2000..=0x3FFF => {
+                // PPU Registers mirrored over 8KiB
+                self.ppu_in_step().cpu_write(address, data);
+            },
+
+            // APU Addresses
+            0x4000..=0x4013 => {
+                self.apu.as_ref().borrow_mut().cpu_write(address, data);
+            },
+
+            // PPU OAM DMA Register
+            0x4014 => {
+                // The page being copied can be anywhere in CPU memory, not just RAM.
+                // The DMA's reads aren't the instruction's, so they aren't counted.
+                let source_addr = (data as u16) << 8;
+                let accesses = self.instr_accesses.get();
+                let oam_dma_source: Vec<u8> = (0..256)
+                    .map(|i| self.read(source_addr + i))
+                    .collect();
+                self.instr_accesses.set(accesses);
+
+                self.ppu.as_ref().borrow_mut().full_oam_dma_transfer(&oam_dma_source);
+                self.oam_dma_started = true;
+            },
+
+            0x4015 => {
+                self.apu.as_ref().borrow_mut().cpu_write(address, data);
+            }
+
+            // Controller strobe. Every input device shares the same strobe
+            // line, and the expansion port gets the whole write.
+            0x4016 => {
+                let strobe = data & 1 == 1;
+
+                if self.strobe && !strobe {
+                    self.controller_polls += 1;
+                }
+                self.strobe = strobe;
+
+                for device in &self.devices {
+                    device.borrow_mut().write(data);
+                }
+            },
+
+            // APU Register
+            0x4017 => {
+                self.apu.as_ref().borrow_mut().cpu_write(address, data);
+            },
+
+            _ => {},
+        };
+    }
+}
+
+impl Bus for NesBus {
+    fn read(&self, address: u16) -> u8 {
+        self.last_read_addr.set(address);
+        self.count_access();
+
+        // Kept out of the if let, so the mapper isn't still borrowed when
+        // reading PPUDATA has the PPU read from it
+        let cart_data = self.mapper.borrow_mut().cpu_cart_read(address);
+
+        let data = if let Some(data) = cart_data {
+            data
+        } else {
+            self.read_internal(address)
+        };
+
+        // $4015 is inside the CPU, so reading it doesn't drive the external
+        // data bus (and bit 5 of it is open bus itself)
+        if address == 0x4015 {
+            return data | (self.open_bus.get() & 0x20);
+        }
+
+        self.open_bus.set(data);
+
+        data
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let cart_data = self.mapper.borrow().cpu_cart_peek(address);
+
+        let data = if let Some(data) = cart_data {
+            data
+        } else {
+            self.peek_internal(address)
+        };
+
+        if address == 0x4015 {
+            return data | (self.open_bus.get() & 0x20);
+        }
+
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.count_access();
+        self.open_bus.set(data);
+        self.check_unsupported_write(address, data);
+
+        if self.mapper.borrow_mut().cpu_cart_write(address, data) {
+            return;
+        }
+
+        self.write_internal(address, data);
+    }
+
+    fn write_consecutive(&mut self, address: u16, data: u8) {
+        self.count_access();
+        self.open_bus.set(data);
+        self.check_unsupported_write(address, data);
+
+        if self.mapper.borrow_mut().cpu_cart_write_consecutive(address, data) {
+            return;
+        }
+
+        self.write_internal(address, data);
+    }
+
+    fn take_stall_cycles(&mut self, cpu_clock: u64) -> usize {
+        if !self.oam_dma_started {
+            return 0;
+        }
+
+        self.oam_dma_started = false;
+
+        // One extra cycle to line up with the APU's get/put cycles if the DMA
+        // starts on an odd cycle
+        // https://www.nesdev.org/wiki/PPU_registers#OAMDMA
+        513 + (cpu_clock & 1) as usize
+    }
+
+    fn plug_device(&mut self, slot: DeviceSlot, device: SharedDevice) {
+        self.devices[slot as usize] = device;
+    }
+
+    // Only the controller ports are affected here. A repeated read of one of
+    // them clocks its shift register an extra time, so the game misses a bit.
+    fn repeat_last_read(&self) {
+        let address = self.last_read_addr.get();
+
+        if address == 0x4016 || address == 0x4017 {
+            self.read(address);
+        }
+    }
+
+    fn controller_polls(&self) -> u64 {
+        self.controller_polls
+    }
+
+    fn record_unsupported(&mut self, feature: UnsupportedFeature) {
+        let frame = self.ppu.as_ref().borrow().frame_count();
+
+        self.telemetry.record(feature, frame);
+    }
+
+    fn unsupported_uses(&self) -> &[UnsupportedUse] {
+        self.telemetry.uses()
+    }
+
+    fn instruction_started(&mut self) {
+        self.instr_accesses.set(0);
+    }
+
+    fn skip_accesses(&mut self, count: usize) {
+        self.instr_accesses.set(self.instr_accesses.get() + count);
+    }
+
+    // The input devices are left out, since states are saved between frames,
+    // when games aren't partway through reading the controllers
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.sys_ram);
+        state.write_bool(self.strobe);
+        state.write_u64(self.controller_polls);
+        state.write_u8(self.open_bus.get());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_bytes_into(&mut self.sys_ram)?;
+        self.strobe = state.read_bool()?;
+        self.controller_polls = state.read_u64()?;
+        self.open_bus.set(state.read_u8()?);
+
+        Ok(())
+    }
+}
+
+/// A bus that's nothing but 64KiB of RAM. Handy for running CPU tests that
+/// expect to own the whole address space.
+#[cfg(test)]
+pub struct RamBus {
+    ram: Box<[u8; 0x10000]>,
+}
+
+#[cfg(test)]
+impl Default for RamBus {
+    fn default() -> Self {
+        Self { ram: Box::new([0; 0x10000]) }
+    }
+}
+
+#[cfg(test)]
+impl RamBus {
+    /// Copies data into RAM starting at the given address
+    pub fn load(&mut self, address: u16, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.ram[address.wrapping_add(i as u16) as usize] = *byte;
+        }
+    }
+}
+
+#[cfg(test)]
+impl Bus for RamBus {
+    fn read(&self, address: u16) -> u8 {
+        self.ram[address as usize]
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.ram[address as usize] = data;
+    }
+}
@@ -0,0 +1,54 @@
+/*
+ON HOW CHEATS WORK:
+
+The simplest kind of cheat is a RAM freeze: one byte of RAM that's held at a
+value the player picks, like the number of lives. Games keep writing their own
+values to it, so the freeze is put back after every frame, once the CPU has
+run, and the game sees the frozen value the next time it reads it.
+
+Freezes are written as the address and the value in hex, split by a colon,
+like "0075:09" (the same as FCEUX's cheat list, minus the name). Only RAM can
+be frozen: the 2KiB of internal RAM ($0000-$07FF) and the cartridge's work RAM
+($6000-$7FFF). Anything else is a register, where writing every frame would do
+more than hold a value.
+
+Freezes belong to the game they were made for, so they're cleared whenever the
+cartridge is taken out.
+https://www.nesdev.org/wiki/CPU_memory_map
+*/
+
+/// One byte of RAM held at a value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RamFreeze {
+    pub address: u16,
+    pub value: u8,
+}
+
+impl RamFreeze {
+    /// Reads a freeze written as "address:value" in hex (see "ON HOW CHEATS
+    /// WORK")
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let (address, value) = code.split_once(':')
+            .ok_or(format!("Cheat '{code}' should be address:value, like 0075:09"))?;
+
+        let address = u16::from_str_radix(address.trim().trim_start_matches('$'), 16)
+            .map_err(|_| format!("Cheat '{code}' has a bad address '{address}'"))?;
+        let value = u8::from_str_radix(value.trim().trim_start_matches('$'), 16)
+            .map_err(|_| format!("Cheat '{code}' has a bad value '{value}'"))?;
+
+        if !Self::is_ram(address) {
+            return Err(format!("Cheat '{code}' isn't in RAM ($0000-$07FF or $6000-$7FFF)"));
+        }
+
+        Ok(Self { address, value })
+    }
+
+    /// The freeze written the way parse reads it
+    pub fn code(&self) -> String {
+        format!("{:04X}:{:02X}", self.address, self.value)
+    }
+
+    fn is_ram(address: u16) -> bool {
+        matches!(address, 0x0000..=0x07FF | 0x6000..=0x7FFF)
+    }
+}
@@ -0,0 +1,106 @@
+use crate::cartridge::hash;
+
+/*
+ON HOW STATE CHECKSUMS WORK:
+
+Netplay and rollback both rely on two copies of the emulator (two players'
+machines, or one run and its re-run after a rollback) staying exactly the same
+given the same input. When they don't, the picture usually only shows it many
+frames later, long after whatever caused it. A state checksum is a CRC-32 of
+each part of the system, taken between frames, so two copies can swap a few
+bytes a frame and notice the very frame they stop agreeing, and which part of
+the system went first:
+
+    cpu      the registers, and how many cycles have run
+    ram      the 2KiB of internal RAM
+    ppu      the PPU's registers (control, status, the VRAM address and
+             scroll) and where it is in the frame
+    vram     the nametables, as the PPU sees them through the mapper
+    oam      the sprites
+    palette  palette RAM
+    mapper   the mapper's registers, with its PRG RAM and CHR RAM
+    apu      every APU channel, the frame counter and the DMC
+
+Everything is read without side effects (the same peeks the debugger uses),
+so taking a checksum never changes how the game runs. A whole checksum is 32
+bytes, and total() folds it into one number for when that's all there's room
+to send.
+*/
+
+/// A CRC-32 of each part of the system's state, from Nes::state_checksum (see
+/// "ON HOW STATE CHECKSUMS WORK")
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct StateChecksum {
+    pub cpu: u32,
+    pub ram: u32,
+    pub ppu: u32,
+    pub vram: u32,
+    pub oam: u32,
+    pub palette: u32,
+    pub mapper: u32,
+    pub apu: u32,
+}
+
+impl StateChecksum {
+    /// The names of the components, in the order components() gives them
+    pub const COMPONENTS: [&'static str; 8] = ["cpu", "ram", "ppu", "vram", "oam", "palette", "mapper", "apu"];
+
+    /// Each component with its name
+    pub fn components(&self) -> [(&'static str, u32); 8] {
+        let values = [self.cpu, self.ram, self.ppu, self.vram, self.oam, self.palette, self.mapper, self.apu];
+
+        std::array::from_fn(|i| (Self::COMPONENTS[i], values[i]))
+    }
+
+    /// Builds a checksum from the components in the order of COMPONENTS
+    pub fn from_components(values: [u32; 8]) -> Self {
+        let [cpu, ram, ppu, vram, oam, palette, mapper, apu] = values;
+
+        Self { cpu, ram, ppu, vram, oam, palette, mapper, apu }
+    }
+
+    /// One CRC-32 covering every component
+    pub fn total(&self) -> u32 {
+        let bytes: Vec<u8> = self.components().iter()
+            .flat_map(|(_, value)| value.to_le_bytes())
+            .collect();
+
+        hash::crc32(&bytes)
+    }
+
+    /// The names of the components that differ between two checksums
+    pub fn diff(&self, other: &StateChecksum) -> Vec<&'static str> {
+        self.components().iter()
+            .zip(other.components())
+            .filter(|((_, a), (_, b))| a != b)
+            .map(|((name, _), _)| *name)
+            .collect()
+    }
+
+    /// A line for a checksum log: the frame, the total, then each component,
+    /// all in hex
+    pub fn to_log_line(&self, frame: usize) -> String {
+        let components: Vec<String> = self.components().iter().map(|(_, value)| format!("{value:08X}")).collect();
+
+        format!("{frame} {:08X} {}", self.total(), components.join(" "))
+    }
+
+    /// Reads a line written by to_log_line, returning the frame and checksum.
+    /// Returns None for anything else, like the header or a blank line.
+    pub fn from_log_line(line: &str) -> Option<(usize, StateChecksum)> {
+        let mut fields = line.split_whitespace();
+
+        let frame = fields.next()?.parse().ok()?;
+        let _total = fields.next()?;
+
+        let values: Vec<u32> = fields.map(|field| u32::from_str_radix(field, 16)).collect::<Result<_, _>>().ok()?;
+        let values: [u32; 8] = values.try_into().ok()?;
+
+        Some((frame, Self::from_components(values)))
+    }
+
+    /// The first line of a checksum log, naming the columns
+    pub fn log_header() -> String {
+        format!("# frame total {}", Self::COMPONENTS.join(" "))
+    }
+}
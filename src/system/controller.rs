@@ -1,4 +1,6 @@
 
+use std::{cell::RefCell, rc::Rc};
+
 use bitfield_struct::bitfield;
 
 #[derive(Clone, Copy, Debug, PartialEq, Default)]
@@ -87,6 +89,10 @@ impl NesController {
         }
     }
 
+    pub fn is_pressed(&self, button: ControllerButton) -> bool {
+        self.read_button(ControllerReadState { button, finished: false }) == 1
+    }
+
     pub fn set_button(&mut self, button: ControllerButton, val: bool) {
         match button {
             ControllerButton::A => self.set_a(val),
@@ -99,4 +105,341 @@ impl NesController {
             ControllerButton::Right => self.set_right(val),
         }
     }
-}
\ No newline at end of file
+}
+
+/*
+ON HOW INPUT DEVICES WORK:
+
+Anything the player holds is an InputDevice plugged into one of three slots:
+the two controller ports, or the Famicom's expansion port. The CPU talks to all
+of them through the same two registers. A write to $4016 goes to every device
+(bit 0 is the strobe line they all share), and a read of $4016 or $4017 gets
+bits 0-4 from whatever is wired to that register: the device in port 1 or port
+2, ORed together with the expansion port device. Bits 5-7 aren't driven by any
+of them, so they keep whatever was last on the data bus (open bus), usually
+$40, the high byte of the address.
+https://www.nesdev.org/wiki/Input_devices
+
+Devices are shared between the Nes and the bus, so the Nes can hand them the
+player's input whenever it changes, and they stay plugged in (with whatever
+state they're in) when a new game is loaded, like they would on the console.
+Swapping one for another is just plugging a new one into the slot.
+
+    StandardController  the 8 button controller
+    Unplugged           an empty port, which reads as all 0s
+    FamilyKeyboard      the Family BASIC keyboard (expansion port)
+    ArkanoidPaddle      the Arkanoid controller's knob and button (expansion
+                        port)
+
+Other devices (the Zapper, the Four Score, controllers driven by a script) are
+new InputDevices, and don't need the bus or the Nes to change.
+*/
+
+/// Something plugged into one of the console's input slots
+pub trait InputDevice {
+    /// Takes a write to $4016. Bit 0 is the strobe, expansion port devices
+    /// use the other bits too.
+    fn write(&mut self, data: u8);
+    /// The bits 0-4 the device puts on a read of $4016 (port 0) or $4017
+    /// (port 1). Reading can shift the device's data along.
+    fn read(&mut self, port: usize) -> u8;
+    /// The same as read, without changing anything, for the debugger
+    fn peek(&self, port: usize) -> u8;
+
+    /// Gives the device the buttons held on the player's controller, for
+    /// devices that have them
+    fn set_buttons(&mut self, _buttons: NesController) {}
+    /// Gives the device the player's keyboard and mouse input, for expansion
+    /// port devices
+    fn set_expansion_input(&mut self, _input: ExpansionInput) {}
+}
+
+/// An input device the Nes and the bus both hold on to
+pub type SharedDevice = Rc<RefCell<dyn InputDevice>>;
+
+/// The slots input devices plug into
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceSlot {
+    Port1,
+    Port2,
+    Expansion,
+}
+
+impl DeviceSlot {
+    pub const ALL: [DeviceSlot; 3] = [Self::Port1, Self::Port2, Self::Expansion];
+}
+
+/// The standard controller. Its buttons are copied into a shift register
+/// while the strobe bit is set, then each read of the port shifts out the next
+/// one, in the order ControllerReadState goes through them.
+/// https://www.nesdev.org/wiki/Standard_controller
+pub struct StandardController {
+    buttons: NesController,
+    latched: NesController,
+    read_state: ControllerReadState,
+    strobe: bool,
+}
+
+impl Default for StandardController {
+    fn default() -> Self {
+        Self {
+            buttons: NesController::default(),
+            latched: NesController::default(),
+            read_state: ControllerReadState::new(),
+            strobe: true,
+        }
+    }
+}
+
+impl InputDevice for StandardController {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+
+        if self.strobe {
+            self.latched = self.buttons;
+            self.read_state = ControllerReadState::new();
+        }
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        let data = self.peek(port);
+
+        if !self.strobe {
+            self.read_state = self.read_state.next();
+        }
+
+        data
+    }
+
+    fn peek(&self, _port: usize) -> u8 {
+        self.latched.read_button(self.read_state)
+    }
+
+    // While the strobe bit is set, the controller keeps reloading its shift
+    // register, so reads keep returning the A button as it is right now
+    fn set_buttons(&mut self, buttons: NesController) {
+        self.buttons = buttons;
+
+        if self.strobe {
+            self.latched = buttons;
+        }
+    }
+}
+
+/// Nothing plugged in
+#[derive(Default)]
+pub struct Unplugged;
+
+impl InputDevice for Unplugged {
+    fn write(&mut self, _data: u8) {}
+
+    fn read(&mut self, _port: usize) -> u8 {
+        0
+    }
+
+    fn peek(&self, _port: usize) -> u8 {
+        0
+    }
+}
+
+/*
+ON HOW EXPANSION PORT DEVICES WORK:
+
+Besides its two controllers, the Famicom has an expansion port on the front
+that other devices plug into. They're wired to the same registers as the
+controllers, but to the other data bits: writes to $4016 reach them (all three
+low bits, not just the strobe), and reads of $4016/$4017 get their bits 1-4.
+Only one device can be plugged in at a time, and the standard controllers keep
+working next to it.
+
+The Family BASIC keyboard has 72 keys in 9 rows of 2 columns of 4 keys. A write
+to $4016 with bit 2 set turns it on, bit 0 goes back to the first row, and bit
+1 picks the column, moving on to the next row every time it goes from 1 to 0.
+Reading $4017 gives the 4 keys of the current row and column in bits 4-1, as 0
+for held down and 1 for not. Keys are numbered here in the order the keyboard
+puts them out: row by row, column 0 then column 1, bit 4 down to bit 1.
+https://www.nesdev.org/wiki/Family_BASIC_Keyboard
+
+The Arkanoid controller (Vaus) has a knob and a fire button. The knob's
+position is an 8 bit number that gets latched when the strobe bit is written
+with 1, then read one bit at a time out of bit 1 of $4017, highest bit first
+and inverted. The fire button is bit 1 of $4016. Only part of the 8 bit range
+is used by the real controller, so positions are scaled into it.
+https://www.nesdev.org/wiki/Arkanoid_controller
+
+The frontend decides where input for these comes from (the host's keyboard and
+mouse), and hands it over as an ExpansionInput whenever it changes.
+*/
+
+/// The devices that can be plugged into the expansion port
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum ExpansionDevice {
+    #[default]
+    None,
+    FamilyKeyboard,
+    ArkanoidPaddle,
+}
+
+impl ExpansionDevice {
+    pub const ALL: [ExpansionDevice; 3] = [Self::None, Self::FamilyKeyboard, Self::ArkanoidPaddle];
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "keyboard" => Some(Self::FamilyKeyboard),
+            "arkanoid" => Some(Self::ArkanoidPaddle),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::FamilyKeyboard => "keyboard",
+            Self::ArkanoidPaddle => "arkanoid",
+        }
+    }
+
+    /// A new device of this kind, ready to be plugged in
+    pub fn create(&self) -> SharedDevice {
+        match self {
+            Self::None => Rc::new(RefCell::new(Unplugged)),
+            Self::FamilyKeyboard => Rc::new(RefCell::new(FamilyKeyboard::default())),
+            Self::ArkanoidPaddle => Rc::new(RefCell::new(ArkanoidPaddle::default())),
+        }
+    }
+}
+
+/// How many keys the Family BASIC keyboard has
+pub const FAMILY_KEYBOARD_KEYS: usize = 72;
+
+// The knob values the Arkanoid controller puts out from all the way left to all
+// the way right
+const PADDLE_MIN: u8 = 0x54;
+const PADDLE_MAX: u8 = 0xF4;
+
+/// What the player is doing with the expansion port device
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct ExpansionInput {
+    // One bit for each Family BASIC key held down (see "ON HOW EXPANSION PORT
+    // DEVICES WORK" for the numbering)
+    pub keys: u128,
+    // Where the Arkanoid knob is, from 0 (left) to 255 (right)
+    pub paddle_position: u8,
+    pub paddle_button: bool,
+}
+
+impl ExpansionInput {
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        if pressed {
+            self.keys |= 1 << key;
+        } else {
+            self.keys &= !(1 << key);
+        }
+    }
+
+    pub fn key_pressed(&self, key: usize) -> bool {
+        self.keys & (1 << key) != 0
+    }
+}
+
+/// The Family BASIC keyboard
+#[derive(Default)]
+pub struct FamilyKeyboard {
+    keys: u128,
+    enabled: bool,
+    row: usize,
+    column: usize,
+}
+
+impl InputDevice for FamilyKeyboard {
+    fn write(&mut self, data: u8) {
+        let column = ((data >> 1) & 1) as usize;
+
+        if data & 1 != 0 {
+            self.row = 0;
+        } else if self.column == 1 && column == 0 {
+            self.row += 1;
+        }
+
+        self.column = column;
+        self.enabled = data & 0x04 != 0;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        self.peek(port)
+    }
+
+    fn peek(&self, port: usize) -> u8 {
+        if port != 1 || !self.enabled {
+            return 0;
+        }
+
+        let first_key = self.row * 8 + self.column * 4;
+        let pressed = |key: usize| key < FAMILY_KEYBOARD_KEYS && self.keys & (1 << key) != 0;
+
+        (0..4)
+            .filter(|i| !pressed(first_key + i))
+            .fold(0, |bits, i| bits | (1 << (4 - i)))
+    }
+
+    fn set_expansion_input(&mut self, input: ExpansionInput) {
+        self.keys = input.keys;
+    }
+}
+
+/// The Arkanoid controller
+#[derive(Default)]
+pub struct ArkanoidPaddle {
+    input: ExpansionInput,
+
+    // The knob position being shifted out, and the button, as latched by the
+    // last strobe
+    shift: u8,
+    button: bool,
+    strobe: bool,
+}
+
+impl ArkanoidPaddle {
+    fn latch(&mut self) {
+        let range = (PADDLE_MAX - PADDLE_MIN) as usize;
+
+        self.shift = PADDLE_MIN + (self.input.paddle_position as usize * range / 255) as u8;
+        self.button = self.input.paddle_button;
+    }
+}
+
+impl InputDevice for ArkanoidPaddle {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 1 != 0;
+
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        let data = self.peek(port);
+
+        if port == 1 && !self.strobe {
+            self.shift <<= 1;
+        }
+
+        data
+    }
+
+    fn peek(&self, port: usize) -> u8 {
+        match port {
+            0 => (self.button as u8) << 1,
+            _ => (!self.shift >> 7) << 1,
+        }
+    }
+
+    fn set_expansion_input(&mut self, input: ExpansionInput) {
+        self.input = input;
+
+        if self.strobe {
+            self.latch();
+        }
+    }
+}
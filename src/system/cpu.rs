@@ -1,20 +1,25 @@
-use std::rc::Rc;
-use std::cell::{Cell, RefCell};
+use std::{fmt, sync::OnceLock};
 
 use bitfield_struct::bitfield;
 
-use crate::cartridge::mapper::Mapper;
-
-use super::apu::Apu2A03;
-use super::controller::{ControllerReadState, NesController};
+use super::breakpoints::{WriteBreak, WriteBreakpoint};
+use super::bus::Bus;
+use super::savestate::{StateReader, StateWriter};
+use super::telemetry::UnsupportedFeature;
+use super::trace::{TraceEntry, TraceLog};
 use super::instructions::{AddressingMode, Instruction, OpcodeData, INSTRUCTION_TABLE, DEFAULT_ILLEGAL_OP};
 
-use super::ppu::Ppu2C02;
+pub const CPU_STATE_MAGIC: &[u8; 4] = b"CPU ";
+const CPU_STATE_VERSION: u16 = 1;
 
-// NES has 2KiB of internal memory that only the CPU can access
-const SYS_RAM_SIZE: usize = 0x800;
+const NMI_PC_VECTOR: u16 = 0xFFFA;
+const IRQ_PC_VECTOR: u16 = 0xFFFE;
 
-// NVUBDIZC
+/// The processor status register, NVUBDIZC. Only six of the bits really exist
+/// in the CPU: B and the unused bit are made up when the status is pushed to
+/// the stack (see CpuStatus::pushed) and ignored when it's pulled back off, so
+/// B is always 0 here and the unused bit is always 1.
+/// https://www.nesdev.org/wiki/Status_flags
 #[bitfield(u8)]
 pub struct CpuStatus {
     pub carry: bool,
@@ -27,6 +32,116 @@ pub struct CpuStatus {
     pub negative: bool,
 }
 
+impl CpuStatus {
+    /// The byte pushed to the stack for this status. The unused bit is always
+    /// pushed as 1, and B is 1 when the push came from an instruction (PHP or
+    /// BRK) and 0 when it came from an IRQ or NMI.
+    pub fn pushed(self, b: bool) -> u8 {
+        self.with_b(b).with_unused(true).0
+    }
+
+    /// The status after pulling a byte off the stack (PLP or RTI). The B and
+    /// unused bits pulled are ignored.
+    pub fn pulled(self, data: u8) -> Self {
+        Self::from_bits((data & 0xCF) | (self.0 & 0x20))
+    }
+}
+
+/// The ways the CPU can be interrupted. They all run the same sequence, but
+/// jump through different vectors and push different B flags.
+/// https://www.nesdev.org/wiki/CPU_interrupts
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interrupt {
+    // The BRK instruction
+    Brk,
+    Irq,
+    Nmi,
+}
+
+impl Interrupt {
+    /// The address the new PC is read from
+    pub fn vector(self) -> u16 {
+        match self {
+            Interrupt::Brk | Interrupt::Irq => IRQ_PC_VECTOR,
+            Interrupt::Nmi => NMI_PC_VECTOR,
+        }
+    }
+}
+
+/*
+ON THE CACHED CPU MODE:
+
+Every instruction starts by reading its opcode and operand bytes off the bus,
+which goes through the mapper for code in ROM. That's most of the CPU's time
+when nothing else is slow, so for fast-forwarding and batch runs there's a mode
+that remembers what it decoded at each address and skips those reads the next
+time the PC lands there. The address the operand points at is still worked
+out every time (it depends on X, Y and pointers in RAM), but from the cached
+operand (see Instruction::resolve_address).
+
+Decoded instructions are kept one per address rather than in whole blocks,
+since the PPU and APU still have to be clocked between every instruction, so
+there's nothing to gain by running a block at once. What makes them go stale:
+
+    writes to RAM      forget the instructions that cover the byte written,
+                       in all four mirrors (self-modifying code, which lots of
+                       games run from RAM)
+    writes anywhere    forget everything, since a write to $6000 or above
+    in the cartridge   can switch PRG banks (or change PRG RAM, which can be
+                       mirrored in ways only the mapper knows)
+    anything else      changing memory behind the CPU's back (save states,
+                       freezes, the debugger) forgets everything too
+
+Forgetting everything just bumps a generation number that every decoded
+instruction is stamped with, so it's cheap enough to do on every bank switch.
+Code isn't cached from $2000-$5FFF, where reads can have side effects.
+
+Skipping the fetch reads means the bus doesn't see them, so open bus and the
+DMC's repeated read (see "ON HOW DMC DMA STALLS THE CPU") can come out
+differently than on the real console. That's why it isn't the default. PPU
+timing would too, since the PPU is run up to each access by counting the
+accesses before it (see "ON HOW THE CPU AND PPU KEEP IN STEP"), so the bus is
+still told how many reads were skipped, and a $2002 read in a vblank wait lands
+on the same dot either way.
+*/
+
+/// How the CPU gets its instructions (see "ON THE CACHED CPU MODE")
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum CpuMode {
+    // Reads every opcode and operand off the bus, like the real CPU
+    #[default]
+    Accurate,
+    // Remembers decoded instructions, and skips the reads when it can
+    Cached,
+}
+
+impl CpuMode {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "accurate" => Some(Self::Accurate),
+            "cached" => Some(Self::Cached),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Accurate => "accurate",
+            Self::Cached => "cached",
+        }
+    }
+}
+
+// An instruction decoded by the cached mode
+#[derive(Clone, Copy)]
+struct DecodedInstr {
+    opcode: u8,
+    // The bytes after the opcode, little-endian
+    operand: u16,
+    // The decode generation it was made in. It's stale once that's moved on.
+    generation: u32,
+}
+
 #[derive(Default)]
 pub struct CpuState {
     pub acc: u8,
@@ -51,47 +166,57 @@ pub struct Cpu6502 {
     pc: u16,
     pub status: CpuStatus,
 
-    // Flag used to keep track of when the PPU triggers an NMI
-    nmi_flag: bool,
-    // Flag used to keep track of when the APU triggers an IRQ
-    irq_flag: bool,
-
-    // Memory accessable only by the CPU
-    sys_ram: [u8; SYS_RAM_SIZE],
-
-    // Last polled states of each controller
-    polled_p1_controller: NesController,
-    polled_p2_controller: NesController,
-    // Current reading status of each controller
-    p1_read_state: Cell<ControllerReadState>,
-    p2_read_state: Cell<ControllerReadState>,
-    // Flags dictating whether to update the polled controller values
-    poll_p1: Cell<bool>,
-    poll_p2: Cell<bool>,
-
-    // References to the cartridge mapper and PPU are required so the CPU can
-    // map addresses & read/write data to and from the PPU
-    mapper: Rc<RefCell<dyn Mapper>>,
-    ppu: Rc<RefCell<Ppu2C02>>,
-    apu: Rc<RefCell<Apu2A03>>,
+    // See "ON HOW INTERRUPTS ARE POLLED" below
+    nmi_line: bool,
+    nmi_pending: bool,
+    irq_line: bool,
+    irq_inhibit_polled: bool,
+    // CPU clock a BRK or IRQ reads its vector on, until which an NMI can
+    // hijack it (see "ON HOW INTERRUPTS HIJACK EACH OTHER" below)
+    vector_fetch_clock: Option<u64>,
+    // The IRQ or NMI the CPU last started running, until it's taken by
+    // take_serviced_interrupt (for the interrupt log)
+    serviced_interrupt: Option<Interrupt>,
+
+    // Address of the JAM opcode that locked up the CPU, if one has
+    jammed_at: Option<u16>,
+
+    // Checked on every write (see breakpoints). The first write to match
+    // one is kept until take_write_break.
+    write_breakpoints: Vec<WriteBreakpoint>,
+    write_break: Option<WriteBreak>,
+    // Address of the instruction being run
+    instr_pc: u16,
+    // See "ON THE INSTRUCTION TRACE" in trace
+    trace: TraceLog,
+
+    // Everything the CPU reads from and writes to (RAM, the PPU and APU,
+    // controllers, and the cartridge) is behind the bus
+    bus: Box<dyn Bus>,
 
     oam_data: u8,
     oam_address: u16,
     dma_in_progress: bool,
+    // CPU clock the current OAM DMA stall ends on (in the past if there isn't one)
+    oam_dma_end: u64,
 
     cycles_remaining: usize, // Number of CPU clocks before next instruction
     total_clocks: u64, // Total number of clocks since CPU started running
 
     current_instr: Instruction,
     _instr_data: OpcodeData,
+
+    // See "ON THE CACHED CPU MODE". Empty in the accurate mode, otherwise
+    // one entry for every address.
+    mode: CpuMode,
+    decoded: Vec<Option<DecodedInstr>>,
+    decode_generation: u32,
 }
 
 impl Cpu6502 {
-    /// Make a new CPU with access to given program memory and PPU Registers and a given mapper
-    pub fn new(ppu: Rc<RefCell<Ppu2C02>>, 
-            apu: Rc<RefCell<Apu2A03>>, 
-            mapper: Rc<RefCell<dyn Mapper>>
-        ) -> Self {
+    /// Make a new CPU connected to the given bus. The CPU is reset right away,
+    /// so the reset vector must already be readable.
+    pub fn new(bus: Box<dyn Bus>) -> Self {
 
         let mut new_cpu = Cpu6502{
             acc: 0,
@@ -101,25 +226,26 @@ impl Cpu6502 {
             pc: 0,
             status: CpuStatus::from_bits(0x20), // start w/ unused flag on cuz why not ig (fixes nesdev tests)
 
-            nmi_flag: false,
-            irq_flag: false,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: false,
+            irq_inhibit_polled: true,
+            vector_fetch_clock: None,
+            serviced_interrupt: None,
 
-            sys_ram: [0; SYS_RAM_SIZE],
+            jammed_at: None,
 
-            polled_p1_controller: NesController::default(),
-            polled_p2_controller: NesController::default(),
-            p1_read_state: Cell::new(ControllerReadState::new()),
-            p2_read_state: Cell::new(ControllerReadState::new()),
-            poll_p1: Cell::new(true),
-            poll_p2: Cell::new(true),
+            write_breakpoints: Vec::new(),
+            write_break: None,
+            instr_pc: 0,
+            trace: TraceLog::default(),
 
-            mapper,
-            ppu,
-            apu,
+            bus,
 
             oam_data: 0,
             oam_address: 0,
             dma_in_progress: false,
+            oam_dma_end: 0,
 
             cycles_remaining: 0,
             total_clocks: 0,
@@ -130,6 +256,10 @@ impl Cpu6502 {
                 address: None,
                 offset: None,
             },
+
+            mode: CpuMode::Accurate,
+            decoded: Vec::new(),
+            decode_generation: 0,
         };
 
         new_cpu.reset();
@@ -141,40 +271,89 @@ impl Cpu6502 {
     /// cycles as that instruction requires. This function encapsulates all of
     /// the fetch, decode, and execute stages of the CPU. Returns a bool 
     /// reporting whether an instruction was excecuted.
-    pub fn cycle(&mut self, p1_controller_state: NesController, p2_controller_state: NesController) -> bool {
+    pub fn cycle(&mut self) -> bool {
         let mut excecuted = false;
 
-        // Update controllers
-        if self.poll_p1.get() { self.polled_p1_controller = p1_controller_state; }
-        if self.poll_p2.get() { self.polled_p2_controller = p2_controller_state; }
+        // A jammed CPU doesn't fetch anything or respond to interrupts, it just
+        // sits there until it's reset
+        if self.jammed_at.is_some() {
+            self.cycles_remaining = 0;
+            self.total_clocks += 1;
+            return false;
+        }
 
         if self.cycles_remaining == 0 {
-            if self.nmi_flag {
-                self.nmi();
-                self.nmi_flag = false;
+            self.bus.instruction_started();
 
+            if self.nmi_pending {
+                self.nmi_pending = false;
+                self.interrupt(Interrupt::Nmi);
+
+                // Interrupts take 7 clock cycles
+                self.cycles_remaining += 7;
+                self.cycles_remaining -= 1;
+                self.total_clocks += 1;
                 return false;
             }
 
-            if self.irq_flag {
-                self.irq();
-                self.irq_flag = false;
+            if self.irq_line && !self.irq_inhibit_polled {
+                self.interrupt(Interrupt::Irq);
 
+                self.cycles_remaining += 7;
+                self.cycles_remaining -= 1;
+                self.total_clocks += 1;
                 return false;
             }
 
             excecuted = true;
 
-            let opcode = self.read(self.pc);
-    
-            // fetch - get the opcode we are running
-            let instr = &INSTRUCTION_TABLE[opcode as usize];
-    
-            // decode - retrieve the neccesary data for the instruction
-            let (opcode_data, fetch_cycles) = (instr.addr_func)(self);
+            // CLI, SEI and PLP change the interrupt disable flag after the
+            // interrupt lines have been polled, so their effect is delayed
+            let interrupt_flag_before = self.status.interrupt();
+
+            self.instr_pc = self.pc;
+
+            // fetch & decode - get the opcode we are running and retrieve the
+            // neccesary data for the instruction
+            let (instr, (opcode_data, fetch_cycles)) = match self.mode {
+                CpuMode::Accurate => {
+                    let instr = &INSTRUCTION_TABLE[self.read(self.pc) as usize];
+
+                    (instr, (instr.addr_func)(self))
+                }
+                CpuMode::Cached => {
+                    let decoded = self.decode_cached();
+                    let instr = &INSTRUCTION_TABLE[decoded.opcode as usize];
+
+                    (instr, instr.resolve_address(self, decoded.operand))
+                }
+            };
+
+            if instr.is_unstable() {
+                self.bus.record_unsupported(UnsupportedFeature::UnstableOpcode { opcode: instr.opcode_num });
+            }
+
+            if let Some(address) = instr.dummy_read_address(opcode_data, fetch_cycles) {
+                self.read(address);
+            }
 
             // store the instruction (for debugging)
             self.current_instr = instr.clone();
+            self.trace.push(TraceEntry {
+                pc: self.instr_pc,
+                opcode: instr.opcode_num,
+                name: instr.name,
+                address: match instr.addr_mode {
+                    AddressingMode::Accumulator | AddressingMode::Implied | AddressingMode::Immediate => None,
+                    _ => Some(opcode_data),
+                },
+                acc: self.acc,
+                x: self.x,
+                y: self.y,
+                sp: self.sp,
+                status: self.status.0,
+                clock: self.total_clocks,
+            });
     
             // Increment pc before instruction execution
             self.pc += instr.bytes as u16;
@@ -183,11 +362,12 @@ impl Cpu6502 {
             //           as defined by the instruction
             let execute_cycles = (instr.func)(self, opcode_data);
     
-            self.cycles_remaining += execute_cycles + instr.base_clocks;
-    
-            if instr.has_extra_fetch_cycles {
-                self.cycles_remaining += fetch_cycles;
-            }
+            self.cycles_remaining += execute_cycles + instr.base_clocks + instr.page_cross_cycles(fetch_cycles);
+
+            self.irq_inhibit_polled = match instr.name {
+                "CLI" | "SEI" | "PLP" => interrupt_flag_before,
+                _ => self.status.interrupt(),
+            };
         }
 
         self.cycles_remaining -= 1;
@@ -195,119 +375,236 @@ impl Cpu6502 {
         
         excecuted
     }
-}
 
-// Internal & Helper functionality
-impl Cpu6502 {
-    // HELPER FUNCTIONS
+    /// Switches between reading every instruction off the bus and caching
+    /// decoded ones (see "ON THE CACHED CPU MODE"). It can be switched at any
+    /// time, and the cache starts out empty.
+    pub fn set_mode(&mut self, mode: CpuMode) {
+        self.mode = mode;
+        self.decoded = match mode {
+            CpuMode::Accurate => Vec::new(),
+            CpuMode::Cached => vec![None; 0x10000],
+        };
+    }
 
-    /// Read a single byte from a given address off the bus
-    pub fn read(&self, address: u16) -> u8 {
-        if let Some(data) = self.mapper.borrow_mut().cpu_cart_read(address) {
-            return data;
-        }
+    pub fn mode(&self) -> CpuMode {
+        self.mode
+    }
 
-        match address {
-            0x0000..=0x1FFF => {
-                // First 2KiB of memory (0x0800) are mirrored until 0x2000
-                self.sys_ram[(address & 0x07FF) as usize]
-            },
-            0x2000..=0x3FFF => {
-                // PPU Registers mirrored over 8KiB
-                self.ppu.as_ref().borrow_mut().cpu_read(address)
-            }
-            0x4015 => {
-                self.apu.as_ref().borrow_mut().cpu_read(address)
-            }
-            0x4016 => {
-                // Player 1 controller port
-                let data = self.polled_p1_controller.read_button(self.p1_read_state.get());
+    /// Forgets every decoded instruction, for when memory might have changed
+    /// without the CPU writing to it
+    pub fn forget_decoded(&mut self) {
+        self.decode_generation = self.decode_generation.wrapping_add(1);
+    }
 
-                if !self.poll_p1.get() {
-                    self.p1_read_state.set( self.p1_read_state.get().next() );
-                }
+    // The instruction at the PC, from the cache if it's still good, otherwise
+    // read off the bus (and cached if it's somewhere code can be cached)
+    fn decode_cached(&mut self) -> DecodedInstr {
+        let pc = self.pc;
 
-                data
+        if let Some(decoded) = self.decoded[pc as usize] {
+            if decoded.generation == self.decode_generation {
+                self.bus.skip_accesses(INSTRUCTION_TABLE[decoded.opcode as usize].bytes);
+                return decoded;
             }
-            0x4017 => {
-                // Player 2 controller port
-                let data = self.polled_p2_controller.read_button(self.p2_read_state.get());
-
-                if !self.poll_p2.get() {
-                    self.p2_read_state.set( self.p2_read_state.get().next() );
-                }
+        }
 
-                data
-            }
-            0x4000..=0x401F => { 0xEE },
+        let opcode = self.read(pc);
+        let operand = match INSTRUCTION_TABLE[opcode as usize].bytes {
+            2 => self.read(pc.wrapping_add(1)) as u16,
+            3 => self.read(pc.wrapping_add(1)) as u16 | (self.read(pc.wrapping_add(2)) as u16) << 8,
+            _ => 0,
+        };
+        let decoded = DecodedInstr { opcode, operand, generation: self.decode_generation };
 
-            _ => 0x00,
+        let last_byte = pc.checked_add(INSTRUCTION_TABLE[opcode as usize].bytes as u16 - 1);
+        let cacheable = |address: u16| !(0x2000..0x6000).contains(&address);
+        if cacheable(pc) && last_byte.is_some_and(cacheable) {
+            self.decoded[pc as usize] = Some(decoded);
         }
+
+        decoded
     }
-    /// Write a single byte to the bus at a given address
-    pub fn write(&mut self, address: u16, data: u8) {
-        if self.mapper.borrow_mut().cpu_cart_write(address, data) {
+
+    // Forgets the decoded instructions a write could have changed (see "ON
+    // THE CACHED CPU MODE")
+    fn forget_decoded_at(&mut self, address: u16) {
+        if self.decoded.is_empty() {
             return;
         }
 
         match address {
-            // CPU RAM
+            // An instruction is up to 3 bytes long, so the ones starting at
+            // the byte written and the two before it in every mirror
             0x0000..=0x1FFF => {
-                // First 2KiB of memory (0x0800) are mirrored until 0x2000
-                self.sys_ram[(address & 0x07FF) as usize] = data;
-            },
+                for mirror in 0..4 {
+                    let address = (address & 0x07FF) | (mirror << 11);
 
-            // PPU Internal Registers
-            0x2000..=0x3FFF => {
-                // PPU Registers mirrored over 8KiB
-                self.ppu.as_ref().borrow_mut().cpu_write(address, data);
-            },
+                    for start in address.saturating_sub(2)..=address {
+                        self.decoded[start as usize] = None;
+                    }
+                }
+            }
+            0x2000..=0x401F => {}
+            _ => self.forget_decoded(),
+        }
+    }
+}
 
-            // APU Addresses
-            0x4000..=0x4013 => {
-                self.apu.as_ref().borrow_mut().cpu_write(address, data);
-            },
+// Save states
+impl Cpu6502 {
+    /// Saves the CPU's registers and interrupt state, along with whatever the
+    /// bus keeps (the NES's bus has the internal RAM), as a save state block
+    /// (see savestate.rs)
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut payload = StateWriter::new();
+
+        payload.write_u8(self.acc);
+        payload.write_u8(self.x);
+        payload.write_u8(self.y);
+        payload.write_u8(self.sp);
+        payload.write_u16(self.pc);
+        payload.write_u8(self.status.into());
+
+        payload.write_bool(self.nmi_line);
+        payload.write_bool(self.nmi_pending);
+        payload.write_bool(self.irq_line);
+        payload.write_bool(self.irq_inhibit_polled);
+        payload.write_bool(self.vector_fetch_clock.is_some());
+        payload.write_u64(self.vector_fetch_clock.unwrap_or(0));
+        payload.write_bool(self.jammed_at.is_some());
+        payload.write_u16(self.jammed_at.unwrap_or(0));
+
+        payload.write_u8(self.oam_data);
+        payload.write_u16(self.oam_address);
+        payload.write_bool(self.dma_in_progress);
+        payload.write_u64(self.oam_dma_end);
+
+        payload.write_usize(self.cycles_remaining);
+        payload.write_u64(self.total_clocks);
+
+        self.bus.save_state(&mut payload);
+
+        let mut block = StateWriter::new();
+        block.write_block(CPU_STATE_MAGIC, CPU_STATE_VERSION, &payload.into_bytes());
+
+        block.into_bytes()
+    }
 
-            // PPU OAM DMA Register
-            0x4014 => {
-                // self.oam_address = (data as u16) << 8;
-                // self.dma_in_progress = true;
-                let source_addr = ((data as u16) << 8) as usize;
-                let oam_dma_source = &self.sys_ram[source_addr..source_addr+256];
-                self.ppu.as_ref().borrow_mut().full_oam_dma_transfer(oam_dma_source);
-                // self.dma_in_progress = true;
-                self.cycles_remaining += (513 + self.total_clocks & 1) as usize;
-            },
+    /// Restores a block written by save_state
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut reader = StateReader::new(bytes);
+        let (version, mut payload) = reader.read_block(CPU_STATE_MAGIC)?;
 
-            0x4015 => {
-                self.apu.as_ref().borrow_mut().cpu_write(address, data);
-            }
+        if version > CPU_STATE_VERSION {
+            return Err(format!(
+                "CPU state version {version} is newer than this emulator supports ({CPU_STATE_VERSION})"
+            ));
+        }
 
-            // Player 1 Controller Port
-            0x4016 => {
-                self.poll_p1.set(data & 1 == 1);
-                self.p1_read_state.set(ControllerReadState::new());
-                self.poll_p2.set(data & 1 == 1);
-                self.p2_read_state.set(ControllerReadState::new());
-            },
+        self.acc = payload.read_u8()?;
+        self.x = payload.read_u8()?;
+        self.y = payload.read_u8()?;
+        self.sp = payload.read_u8()?;
+        self.pc = payload.read_u16()?;
+        self.status = payload.read_u8()?.into();
+
+        self.nmi_line = payload.read_bool()?;
+        self.nmi_pending = payload.read_bool()?;
+        self.irq_line = payload.read_bool()?;
+        self.irq_inhibit_polled = payload.read_bool()?;
+        let vector_fetching = payload.read_bool()?;
+        let vector_fetch_clock = payload.read_u64()?;
+        self.vector_fetch_clock = vector_fetching.then_some(vector_fetch_clock);
+        let jammed = payload.read_bool()?;
+        let jammed_at = payload.read_u16()?;
+        self.jammed_at = jammed.then_some(jammed_at);
+
+        self.oam_data = payload.read_u8()?;
+        self.oam_address = payload.read_u16()?;
+        self.dma_in_progress = payload.read_bool()?;
+        self.oam_dma_end = payload.read_u64()?;
+
+        self.cycles_remaining = payload.read_usize()?;
+        self.total_clocks = payload.read_u64()?;
+
+        self.bus.load_state(&mut payload)?;
+        self.serviced_interrupt = None;
+        self.write_break = None;
+        self.forget_decoded();
+        // The instructions that led up to the state weren't run here
+        self.trace.clear();
+
+        Ok(())
+    }
+}
 
-            // APU Register
-            0x4017 => {
-                self.apu.as_ref().borrow_mut().cpu_write(address, data);
-            },
+// Internal & Helper functionality
+impl Cpu6502 {
+    // HELPER FUNCTIONS
 
-            0x4018 | 0x4019 => {},
-            
-            // Program ROM
-            // 0x4020..=0xFFFF => {
-            //     // Write to program ROM through mapper
-            //     if let Some(mapped_addr) = self.mapper.borrow_mut().get_cpu_write_addr(address, data) {
-            //         self.prg_rom[mapped_addr as usize] = data;
-            //     }
-            // },
-
-            _ => {},
-        };
+    /// Read a single byte from a given address off the bus
+    pub fn read(&self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+    /// Read a single byte off the bus without any side effects, for the
+    /// debugger (see Bus::peek)
+    pub fn peek_cpu(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+    /// Write a single byte to the bus at a given address
+    pub fn write(&mut self, address: u16, data: u8) {
+        self.bus.write(address, data);
+        self.take_stall_cycles();
+        self.forget_decoded_at(address);
+
+        if !self.write_breakpoints.is_empty() {
+            self.check_write_breakpoints(address, data);
+        }
+    }
+    // Keeps the write if it matches a breakpoint and there isn't one kept
+    // already
+    fn check_write_breakpoints(&mut self, address: u16, data: u8) {
+        if self.write_break.is_some() {
+            return;
+        }
+
+        if let Some(&breakpoint) = self.write_breakpoints.iter().find(|breakpoint| breakpoint.matches(address, data)) {
+            self.write_break = Some(WriteBreak { breakpoint, address, data, pc: self.instr_pc, scanline: 0, dot: 0 });
+        }
+    }
+    // Stalls the CPU if the last write started something that holds it up
+    // (an OAM DMA)
+    fn take_stall_cycles(&mut self) {
+        let stall = self.bus.take_stall_cycles(self.total_clocks);
+
+        if stall > 0 {
+            self.cycles_remaining += stall;
+            self.oam_dma_end = self.total_clocks + self.cycles_remaining as u64;
+        }
+    }
+    /// Reads a byte, works out a new value for it, and writes it back, the way
+    /// read-modify-write instructions (INC, ASL...) do. While the new value is
+    /// being worked out the CPU writes the old value back, so the address sees
+    /// two writes in a row. Returns the new value.
+    /// https://www.nesdev.org/wiki/CPU#Read-modify-write
+    ///
+    ///  * `modify` - Takes the byte read and returns the value to write
+    pub fn read_modify_write(&mut self, address: u16, modify: impl FnOnce(&mut Self, u8) -> u8) -> u8 {
+        let data = self.read(address);
+        self.write(address, data);
+
+        let result = modify(self, data);
+
+        self.bus.write_consecutive(address, result);
+        self.take_stall_cycles();
+        self.forget_decoded_at(address);
+
+        if !self.write_breakpoints.is_empty() {
+            self.check_write_breakpoints(address, result);
+        }
+
+        result
     }
     /// Read a 2 byte value starting at the given address in LLHH (little-endian) form
     pub fn read_word(&self, address: u16) -> u16 {
@@ -370,10 +667,49 @@ impl Cpu6502 {
         data
     }
 
+    /*
+    ON HOW DMC DMA STALLS THE CPU:
+
+    When the APU's DMC channel needs another sample byte, it halts the CPU and
+    reads the byte itself, which normally takes the CPU 4 cycles. If an OAM DMA
+    is already running, the DMC fetch slots in between its reads and only costs
+    2 extra cycles.
+
+    The CPU can only be halted on a read cycle, and the read it was halted on
+    gets repeated. Usually nobody notices, but reading a controller port clocks
+    its shift register, so a DMA landing on a $4016 read makes the game skip a
+    button. Games that play DMC samples read the controllers twice and keep
+    reading until the results agree to work around this. Since each instruction
+    here runs all at once on its first cycle, a DMA that lands on the last cycle
+    of an instruction (where loads do their read) is treated as halting it.
+    https://www.nesdev.org/wiki/DMA#DMC_DMA_during_OAM_DMA
+    https://www.nesdev.org/wiki/Standard_controller#Hardware
+    */
+
+    /// Stalls the CPU for a DMC sample fetch.
+    ///
+    ///  * `repeat_halted_read` - Whether to repeat the read the CPU was halted
+    ///    on (which can corrupt controller reads, like the real console)
+    pub fn dmc_dma(&mut self, repeat_halted_read: bool) {
+        if self.total_clocks < self.oam_dma_end {
+            self.cycles_remaining += 2;
+            self.oam_dma_end += 2;
+            return;
+        }
+
+        if repeat_halted_read && self.cycles_remaining == 1 {
+            self.bus.repeat_last_read();
+        }
+
+        self.cycles_remaining += 4;
+    }
+
     // RESET FUNCTION
 
     /// Runs the defined reset sequence of the 6502, detailed here:
     /// https://www.nesdev.org/wiki/CPU_power_up_state
+    /// Whatever the CPU was in the middle of (an instruction, or being held up
+    /// by an OAM DMA) is dropped, and the sequence starts right away.
     pub fn reset(&mut self) {
         const RESET_PC_VECTOR: u16 = 0xFFFC;
 
@@ -391,67 +727,150 @@ impl Cpu6502 {
         // leave unused flag alone
         self.status.set_overflow(false);
         self.status.set_negative(false);
+        self.irq_inhibit_polled = true;
+        self.nmi_pending = false;
+        self.vector_fetch_clock = None;
+        self.jammed_at = None;
+
+        self.dma_in_progress = false;
+        self.oam_dma_end = self.total_clocks;
 
         self.pc = self.read_word(RESET_PC_VECTOR);
 
-        self.cycles_remaining += 7;
+        self.cycles_remaining = 7;
     }
 
     // INTERRUPTS
 
-    /// Make an interrupt request to the CPU. Only interrupts if the interrupt
-    /// disable flag is set to 0. The interrupt sequence is detailed here:
-    /// https://www.nesdev.org/wiki/CPU_interrupts
-    pub fn irq(&mut self) {
-        // Check interrupt disable flag
-        if !self.status.interrupt() {
-            const IRQ_PC_VECTOR: u16 = 0xFFFE;
-
-            // Store PC
-            let lo = self.pc as u8;
-            let hi = (self.pc >> 8) as u8;
-            self.push_to_stack(hi);
-            self.push_to_stack(lo);
-
-            // Set flags and store status
-            self.status.set_b(true);
-            self.status.set_unused(true);
-            self.push_to_stack(self.get_status());
-            
-            self.status.set_interrupt(true);
+    /*
+    ON HOW INTERRUPTS ARE POLLED:
+
+    The NMI and IRQ inputs are wires, not messages, so the rest of the system
+    just sets their levels (set_nmi_line & set_irq_line) and the CPU decides
+    what to do with them.
+
+    NMI is edge sensitive. The CPU remembers that the line went from off to on
+    (nmi_pending) and handles it at the next instruction boundary, even if the
+    line has already gone off again by then. Holding the line on doesn't cause
+    a second NMI.
+
+    IRQ is level sensitive. Nothing is remembered, so if the device stops
+    asserting it before the CPU gets around to checking, no interrupt happens,
+    and if it keeps asserting it the CPU will be interrupted again as soon as
+    the interrupt disable flag is cleared (which is why handlers have to
+    acknowledge the IRQ at its source).
+
+    The CPU checks for interrupts at the end of each instruction, before the
+    last cycle. CLI, SEI and PLP change the interrupt disable flag during their
+    last cycle, after that check has already happened, so an IRQ that's waiting
+    when CLI runs doesn't get serviced until after the *next* instruction, and
+    one that's waiting when SEI runs still gets serviced right after it.
+    irq_inhibit_polled holds the flag as it was when the check happened. RTI
+    restores the flag before the check, so it takes effect immediately.
+    https://www.nesdev.org/wiki/CPU_interrupts
+    */
+
+    /*
+    ON HOW INTERRUPTS HIJACK EACH OTHER:
+
+    BRK, IRQ and NMI all run the same 7 cycle sequence: two reads that go
+    nowhere, three pushes (PC high, PC low, then the status), and two reads of
+    the vector. Which vector gets read isn't decided until the reads happen, so
+    if an NMI comes along during the first four cycles of a BRK or IRQ, that
+    sequence jumps to the NMI handler instead, and the NMI doesn't happen again
+    on its own. The status was already pushed, so a hijacked BRK still pushes
+    B=1, and the NMI handler has to check it to notice the BRK (which is lost
+    otherwise). An IRQ during a BRK goes through the same vector anyway, so it
+    changes nothing that can be seen.
+
+    The whole sequence happens at once here, on its first cycle, so a hijack
+    goes back and fixes the PC: vector_fetch_clock remembers when the vector
+    would have been read, and an NMI edge before then reads the NMI vector into
+    the PC instead of being left pending. Nothing else has run in between, so
+    that's the same as having read it in the first place.
+    https://www.nesdev.org/wiki/CPU_interrupts#Interrupt_hijacking
+    */
+
+    /// Sets the level of the NMI input. An NMI happens at the next instruction
+    /// boundary whenever it goes from off to on, unless it hijacks a BRK or
+    /// IRQ that's still getting started.
+    pub fn set_nmi_line(&mut self, level: bool) {
+        if level && !self.nmi_line {
+            match self.vector_fetch_clock.take() {
+                Some(clock) if self.total_clocks <= clock => {
+                    self.pc = self.read_word(NMI_PC_VECTOR);
+                    self.serviced_interrupt = Some(Interrupt::Nmi);
+                }
+                _ => self.nmi_pending = true,
+            }
+        }
 
-            // Set PC to whatever is at addr 0xFFFE
-            self.pc = self.read_word(IRQ_PC_VECTOR);
+        self.nmi_line = level;
+    }
 
-            // Interrupts take 7 clock cycles
-            self.cycles_remaining += 7;
-        }
+    /// Sets the level of the IRQ input (the OR of every device that can
+    /// request an IRQ). An IRQ happens at the next instruction boundary if the
+    /// line is on and interrupts weren't disabled when the CPU polled them.
+    pub fn set_irq_line(&mut self, level: bool) {
+        self.irq_line = level;
+    }
+
+    /// Replaces the write breakpoints (see breakpoints)
+    pub fn set_write_breakpoints(&mut self, breakpoints: Vec<WriteBreakpoint>) {
+        self.write_breakpoints = breakpoints;
+        self.write_break = None;
+    }
+
+    /// Returns the first write to match a breakpoint since this was last
+    /// called, if there was one. The PPU position is left for the caller.
+    pub fn take_write_break(&mut self) -> Option<WriteBreak> {
+        self.write_break.take()
     }
-    /// Send a non-maskable interrupt to the CPU, which executes the defined
-    /// 6502 interrupt sequence regardless of the state of the interrupt disable
-    /// flag. The interrupt sequence is detailed here:
-    /// https://www.nesdev.org/wiki/CPU_interrupts
-    pub fn nmi(&mut self) {
-        const NMI_PC_VECTOR: u16 = 0xFFFA;
 
+    /// The last instructions the CPU ran, oldest first (see "ON THE
+    /// INSTRUCTION TRACE" in trace)
+    pub fn trace(&self) -> Vec<TraceEntry> {
+        self.trace.entries()
+    }
+
+    /// Returns the IRQ or NMI the CPU started running since this was last
+    /// called, if it did
+    pub fn take_serviced_interrupt(&mut self) -> Option<Interrupt> {
+        self.serviced_interrupt.take()
+    }
+
+    /// Runs the interrupt sequence for a BRK instruction, which happens
+    /// whatever the interrupt disable flag is. The PC should already be past
+    /// BRK's padding byte. The 7 cycles it takes are BRK's own.
+    pub fn brk(&mut self) {
+        self.interrupt(Interrupt::Brk);
+    }
+
+    // Pushes the PC and status and jumps through the interrupt's vector. Takes
+    // 7 cycles, which the caller adds.
+    fn interrupt(&mut self, kind: Interrupt) {
         // Store PC
         let lo = self.pc as u8;
         let hi = (self.pc >> 8) as u8;
         self.push_to_stack(hi);
         self.push_to_stack(lo);
 
-        // Set flags and store status
-        self.status.set_b(true);
-        self.status.set_unused(true);
-        self.push_to_stack(self.get_status());
+        self.push_to_stack(self.status.pushed(kind == Interrupt::Brk));
 
         self.status.set_interrupt(true);
+        self.irq_inhibit_polled = true;
 
-        // Set PC to whatever is at addr 0xFFFE
-        self.pc = self.read_word(NMI_PC_VECTOR);
+        // Set PC to whatever is in the vector
+        self.pc = self.read_word(kind.vector());
+
+        if kind != Interrupt::Brk {
+            self.serviced_interrupt = Some(kind);
+        }
 
-        // Interrupts take 7 clock cycles
-        self.cycles_remaining += 7;
+        self.vector_fetch_clock = match kind {
+            Interrupt::Brk | Interrupt::Irq => Some(self.total_clocks + 4),
+            Interrupt::Nmi => None,
+        };
     }
 }
 
@@ -459,11 +878,35 @@ impl Cpu6502 {
 impl Cpu6502 {
     // GETTER/SETTER FUNCTIONS
 
+    /// Get a reference to the bus the CPU is connected to
+    pub fn bus(&self) -> &dyn Bus {
+        self.bus.as_ref()
+    }
+
+    /// Get a mutable reference to the bus the CPU is connected to. Whatever's
+    /// written through it is out of the CPU's sight, so the cached mode
+    /// forgets what it's decoded.
+    pub fn bus_mut(&mut self) -> &mut dyn Bus {
+        self.forget_decoded();
+        self.bus.as_mut()
+    }
+
     /// Get the current number of clock cycles since turn-on
     pub fn total_clocks(&self) -> u64 {
         self.total_clocks
     }
 
+    /// Address of the JAM opcode the CPU locked up on, or None if it's running.
+    /// Only a reset gets it going again.
+    pub fn jammed_at(&self) -> Option<u16> {
+        self.jammed_at
+    }
+
+    /// Locks up the CPU, as if it just ran the JAM opcode at the given address
+    pub fn jam(&mut self, address: u16) {
+        self.jammed_at = Some(address);
+    }
+
     /// Manually set the number of clock cycles since turn-on
     pub fn set_total_clocks(&mut self, clks: u64) {
         self.total_clocks = clks;
@@ -543,56 +986,26 @@ impl Cpu6502 {
         self.pc = val;
     }
 
-    pub fn trigger_ppu_nmi(&mut self) {
-        self.nmi_flag = true;
-    }
-
-    pub fn trigger_apu_irq(&mut self) {
-        self.irq_flag = true;
-    }
-
     pub fn increment_clock(&mut self) {
         self.total_clocks += 1;
     }
 
 
     /// Get the instruction just executed as a string of 6502 assembly for
-    /// debugging purposes.
-    pub fn current_instr_str(&self) -> String {
-        let mut out_str = format!("0x{:02X}", self.current_instr.opcode_num);
-        out_str.push(' ');
-        out_str.push_str(self.current_instr.name);
-
-        out_str.push(' ');
-        let temp = match self.current_instr.addr_mode {
-            AddressingMode::Accumulator => String::from(": [acc]"),
-
-            AddressingMode::Implied => String::from(": [imp]"),
-
-            AddressingMode::Immediate => String::from(": [imm]"),
-
-            AddressingMode::Absolute => String::from(": [abs]"),
-            AddressingMode::AbsoluteX => String::from(": [abs x]"),
-            AddressingMode::AbsoluteY => String::from(": [abs y]"),
-
-            AddressingMode::ZeroPage => String::from(": [zpage]"),
-            AddressingMode::ZeroPageX => String::from(": [zpage x]"),
-            AddressingMode::ZeroPageY => String::from(": [zpage y]"),
-
-            // Don't know the original data from the instruction, it's somewhere in memory
-            AddressingMode::Indirect => String::from(": [ind]"),
-            AddressingMode::IndirectX => String::from(": [ind x]"),
-            AddressingMode::IndirectY => String::from(": [ind y]"),
-
-            AddressingMode::Relative => String::from(": [rel]"),
-        };
-        out_str.push_str(&temp);
-
-        if self.current_instr.is_illegal {
-            out_str.push_str(" <ILLEGAL>");
+    /// debugging purposes. The text only depends on the opcode, so it comes
+    /// from a table built the first time it's asked for, and the debug view
+    /// can ask every frame without allocating.
+    pub fn current_instr_str(&self) -> &'static str {
+        let strs = instr_strs();
+        let opcode = self.current_instr.opcode_num as usize;
+
+        // Before the first instruction runs, the CPU holds a placeholder
+        // that isn't in the instruction table
+        if self.current_instr.name != INSTRUCTION_TABLE[opcode].name {
+            return &strs[INSTRUCTION_TABLE.len()];
         }
 
-        out_str
+        &strs[opcode]
     }
 
     pub fn get_state(&self) -> CpuState {
@@ -619,3 +1032,515 @@ impl Cpu6502 {
     }
 }
 
+// The text for every instruction in the instruction table, in opcode order,
+// followed by the placeholder instruction's
+fn instr_strs() -> &'static [String] {
+    static INSTR_STRS: OnceLock<Vec<String>> = OnceLock::new();
+
+    INSTR_STRS.get_or_init(|| {
+        INSTRUCTION_TABLE.iter()
+            .chain(std::iter::once(&DEFAULT_ILLEGAL_OP))
+            .map(|instr| {
+                let mut text = String::new();
+                // Writing to a String can't fail
+                write_instr_str(instr, &mut text).unwrap();
+                text
+            })
+            .collect()
+    })
+}
+
+/// Writes an instruction as a string of 6502 assembly, straight into the
+/// given buffer
+pub fn write_instr_str(instr: &Instruction, out: &mut impl fmt::Write) -> fmt::Result {
+    let mode = match instr.addr_mode {
+        AddressingMode::Accumulator => "acc",
+
+        AddressingMode::Implied => "imp",
+
+        AddressingMode::Immediate => "imm",
+
+        AddressingMode::Absolute => "abs",
+        AddressingMode::AbsoluteX => "abs x",
+        AddressingMode::AbsoluteY => "abs y",
+
+        AddressingMode::ZeroPage => "zpage",
+        AddressingMode::ZeroPageX => "zpage x",
+        AddressingMode::ZeroPageY => "zpage y",
+
+        // Don't know the original data from the instruction, it's somewhere in memory
+        AddressingMode::Indirect => "ind",
+        AddressingMode::IndirectX => "ind x",
+        AddressingMode::IndirectY => "ind y",
+
+        AddressingMode::Relative => "rel",
+    };
+
+    write!(out, "0x{:02X} {} : [{mode}]", instr.opcode_num, instr.name)?;
+
+    if instr.is_illegal {
+        out.write_str(" <ILLEGAL>")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, fs, rc::Rc};
+
+    use super::*;
+    use crate::system::bus::RamBus;
+
+    const BRK: u8 = 0x00;
+    const PHP: u8 = 0x08;
+    const PLP: u8 = 0x28;
+    const RTI: u8 = 0x40;
+    const NOP: u8 = 0xEA;
+
+    // Powers on a CPU running the program from $0200, with the IRQ/BRK
+    // handler at $0300 and the NMI handler at $0400. The stack bytes go at
+    // $01FB, just above the stack pointer.
+    fn cpu_with(program: &[u8], stack: &[u8]) -> Cpu6502 {
+        let mut bus = RamBus::default();
+        bus.load(0xFFFA, &[0x00, 0x04, 0x00, 0x02, 0x00, 0x03]);
+        bus.load(0x0200, program);
+        bus.load(0x0300, &[NOP; 4]);
+        bus.load(0x0400, &[NOP; 4]);
+        bus.load(0x01FB, stack);
+
+        let mut cpu = Cpu6502::new(Box::new(bus));
+        cpu.set_sp(0xFA);
+
+        // Let the reset sequence finish
+        while cpu.cycles_remaining > 0 {
+            cpu.cycle();
+        }
+
+        cpu
+    }
+
+    struct InterruptCase {
+        name: &'static str,
+        program: &'static [u8],
+        interrupts_disabled: bool,
+        irq_line: bool,
+        // Cycles into the sequence the NMI line goes on, if it does
+        nmi_at: Option<u64>,
+
+        pc: u16,
+        cycles: u64,
+        // The PC and status pushed, if anything was
+        pushed: Option<(u16, u8)>,
+        nmi_still_pending: bool,
+    }
+
+    const INTERRUPT_CASES: &[InterruptCase] = &[
+        InterruptCase {
+            name: "BRK pushes B set and skips its padding byte",
+            program: &[BRK, NOP], interrupts_disabled: false, irq_line: false, nmi_at: None,
+            pc: 0x0300, cycles: 7, pushed: Some((0x0202, 0x30)), nmi_still_pending: false,
+        },
+        InterruptCase {
+            name: "BRK ignores the interrupt disable flag",
+            program: &[BRK, NOP], interrupts_disabled: true, irq_line: false, nmi_at: None,
+            pc: 0x0300, cycles: 7, pushed: Some((0x0202, 0x34)), nmi_still_pending: false,
+        },
+        InterruptCase {
+            name: "IRQ pushes B clear",
+            program: &[NOP], interrupts_disabled: false, irq_line: true, nmi_at: None,
+            pc: 0x0300, cycles: 7, pushed: Some((0x0200, 0x20)), nmi_still_pending: false,
+        },
+        InterruptCase {
+            name: "IRQ waits while interrupts are disabled",
+            program: &[NOP], interrupts_disabled: true, irq_line: true, nmi_at: None,
+            pc: 0x0201, cycles: 2, pushed: None, nmi_still_pending: false,
+        },
+        InterruptCase {
+            name: "NMI pushes B clear, even with interrupts disabled",
+            program: &[NOP], interrupts_disabled: true, irq_line: false, nmi_at: Some(0),
+            pc: 0x0400, cycles: 7, pushed: Some((0x0200, 0x24)), nmi_still_pending: false,
+        },
+        InterruptCase {
+            name: "NMI hijacks BRK, which still pushes B set",
+            program: &[BRK, NOP], interrupts_disabled: false, irq_line: false, nmi_at: Some(2),
+            pc: 0x0400, cycles: 7, pushed: Some((0x0202, 0x30)), nmi_still_pending: false,
+        },
+        InterruptCase {
+            name: "NMI hijacks IRQ",
+            program: &[NOP], interrupts_disabled: false, irq_line: true, nmi_at: Some(4),
+            pc: 0x0400, cycles: 7, pushed: Some((0x0200, 0x20)), nmi_still_pending: false,
+        },
+        InterruptCase {
+            name: "NMI after BRK reads its vector waits its turn",
+            program: &[BRK, NOP], interrupts_disabled: false, irq_line: false, nmi_at: Some(5),
+            pc: 0x0300, cycles: 7, pushed: Some((0x0202, 0x30)), nmi_still_pending: true,
+        },
+    ];
+
+    #[test]
+    fn interrupt_sequences() {
+        for case in INTERRUPT_CASES {
+            let mut cpu = cpu_with(case.program, &[]);
+            cpu.status.set_interrupt(case.interrupts_disabled);
+            cpu.irq_inhibit_polled = case.interrupts_disabled;
+            cpu.set_irq_line(case.irq_line);
+
+            // Run one instruction or interrupt sequence, putting the NMI line
+            // on between cycles the way the NES does
+            let start = cpu.total_clocks();
+            loop {
+                if case.nmi_at == Some(cpu.total_clocks() - start) {
+                    cpu.set_nmi_line(true);
+                }
+
+                cpu.cycle();
+
+                if cpu.cycles_remaining == 0 {
+                    break;
+                }
+            }
+
+            assert_eq!(cpu.get_pc(), case.pc, "{}: PC", case.name);
+            assert_eq!(cpu.total_clocks() - start, case.cycles, "{}: cycles", case.name);
+            assert_eq!(cpu.nmi_pending, case.nmi_still_pending, "{}: NMI pending", case.name);
+            assert!(!cpu.status.b(), "{}: B left set in the status register", case.name);
+
+            match case.pushed {
+                Some((pc, status)) => {
+                    let pushed_pc = (cpu.read(0x01FA) as u16) << 8 | cpu.read(0x01F9) as u16;
+                    assert_eq!(pushed_pc, pc, "{}: pushed PC", case.name);
+                    assert_eq!(cpu.read(0x01F8), status, "{}: pushed status", case.name);
+                    assert_eq!(cpu.get_sp(), 0xF7, "{}: SP", case.name);
+                    assert!(cpu.status.interrupt(), "{}: interrupt disable flag", case.name);
+                }
+                None => assert_eq!(cpu.get_sp(), 0xFA, "{}: SP", case.name),
+            }
+        }
+    }
+
+    struct StatusStackCase {
+        name: &'static str,
+        program: &'static [u8],
+        status: u8,
+        stack: &'static [u8],
+
+        status_after: u8,
+        pushed: Option<u8>,
+    }
+
+    const STATUS_STACK_CASES: &[StatusStackCase] = &[
+        StatusStackCase {
+            name: "PHP pushes B and the unused bit set",
+            program: &[PHP], status: 0xC3, stack: &[],
+            status_after: 0xC3, pushed: Some(0xF3),
+        },
+        StatusStackCase {
+            name: "PLP ignores B and the unused bit",
+            program: &[PLP], status: 0x20, stack: &[0xFF],
+            status_after: 0xEF, pushed: None,
+        },
+        StatusStackCase {
+            name: "PLP keeps the unused bit set",
+            program: &[PLP], status: 0x20, stack: &[0x00],
+            status_after: 0x20, pushed: None,
+        },
+        StatusStackCase {
+            name: "RTI ignores B and the unused bit",
+            program: &[RTI], status: 0x24, stack: &[0x10, 0x00, 0x03],
+            status_after: 0x20, pushed: None,
+        },
+    ];
+
+    #[test]
+    fn status_stack_round_trips() {
+        for case in STATUS_STACK_CASES {
+            let mut cpu = cpu_with(case.program, case.stack);
+            cpu.set_status(case.status);
+
+            cpu.cycle();
+
+            assert_eq!(cpu.get_status(), case.status_after, "{}: status", case.name);
+            if let Some(pushed) = case.pushed {
+                assert_eq!(cpu.read(0x01FA), pushed, "{}: pushed status", case.name);
+            }
+        }
+    }
+
+    // RAM, where a write to $4014 holds the CPU up like an OAM DMA does
+    #[derive(Default)]
+    struct DmaBus {
+        ram: RamBus,
+        dma_started: bool,
+    }
+
+    impl Bus for DmaBus {
+        fn read(&self, address: u16) -> u8 {
+            self.ram.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.dma_started |= address == 0x4014;
+            self.ram.write(address, data);
+        }
+
+        fn take_stall_cycles(&mut self, _cpu_clock: u64) -> usize {
+            if std::mem::take(&mut self.dma_started) { 513 } else { 0 }
+        }
+    }
+
+    #[test]
+    fn reset_drops_an_oam_dma_stall() {
+        const STA_ABS: u8 = 0x8D;
+
+        let mut bus = DmaBus::default();
+        bus.ram.load(0xFFFC, &[0x00, 0x02]);
+        bus.ram.load(0x0200, &[STA_ABS, 0x14, 0x40, NOP]);
+
+        let mut cpu = Cpu6502::new(Box::new(bus));
+        while !cpu.cycle() {}
+        assert!(cpu.cycles_remaining > 513, "the DMA should be stalling the CPU");
+
+        cpu.reset();
+        assert_eq!(cpu.cycles_remaining, 7);
+        assert_eq!(cpu.get_pc(), 0x0200);
+
+        // A DMC fetch now takes its usual 4 cycles, rather than the 2 it takes
+        // when it lands during an OAM DMA
+        cpu.dmc_dma(false);
+        assert_eq!(cpu.cycles_remaining, 11);
+    }
+
+    #[test]
+    fn cached_mode_sees_self_modifying_code() {
+        const LDX_IMM: u8 = 0xA2;
+        const INX: u8 = 0xE8;
+        const DEX: u8 = 0xCA;
+        const LDA_IMM: u8 = 0xA9;
+        const STA_ABS: u8 = 0x8D;
+        const JMP_ABS: u8 = 0x4C;
+
+        // Runs the INX at $0202 once, then writes a DEX over it and goes back
+        let program = [
+            LDX_IMM, 0x00,
+            INX,
+            LDA_IMM, DEX,
+            STA_ABS, 0x02, 0x02,
+            JMP_ABS, 0x02, 0x02,
+        ];
+
+        for mode in [CpuMode::Accurate, CpuMode::Cached] {
+            let mut cpu = cpu_with(&program, &[]);
+            cpu.set_mode(mode);
+
+            for _ in 0..6 {
+                while !cpu.cycle() {}
+            }
+
+            assert_eq!(cpu.get_x_reg(), 0, "{mode:?}: the DEX should have run the second time");
+        }
+    }
+
+    /*
+    The single step tests (https://github.com/SingleStepTests/ProcessorTests)
+    are 10,000 runs of every opcode, each giving the registers and the RAM the
+    instruction touches before and after, and what was on the bus every cycle.
+    The nes6502 set is the one for the 2A03 (a 6502 without decimal mode).
+    They're far too big to keep here, so a few written out by hand in the same
+    format always run, and the whole set runs when NES_CPU_TESTS points at the
+    folder with 00.json to ff.json in it:
+
+        NES_CPU_TESTS=ProcessorTests/nes6502/v1 cargo test single_step
+
+    Instructions here run all at once rather than a cycle at a time, so of the
+    bus activity only the number of cycles and the writes (in order) are
+    checked, not which read happened on which cycle. JAM and the unstable
+    opcodes (see Instruction::is_unstable) are skipped, since what they do
+    isn't the same from one CPU to the next.
+    */
+    const SINGLE_STEP_TESTS_DIR: &str = "NES_CPU_TESTS";
+
+    const SINGLE_STEP_SAMPLES: &str = r#"[
+        {"name": "a9 00 LDA # sets Z",
+         "initial": {"pc": 512, "s": 253, "a": 85, "x": 0, "y": 0, "p": 36, "ram": [[512, 169], [513, 0]]},
+         "final": {"pc": 514, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38, "ram": [[512, 169], [513, 0]]},
+         "cycles": [[512, 169, "read"], [513, 0, "read"]]},
+        {"name": "69 50 ADC # overflows into N",
+         "initial": {"pc": 512, "s": 253, "a": 80, "x": 0, "y": 0, "p": 36, "ram": [[512, 105], [513, 80]]},
+         "final": {"pc": 514, "s": 253, "a": 160, "x": 0, "y": 0, "p": 228, "ram": [[512, 105], [513, 80]]},
+         "cycles": [[512, 105, "read"], [513, 80, "read"]]},
+        {"name": "69 01 ADC # ignores decimal mode",
+         "initial": {"pc": 512, "s": 253, "a": 9, "x": 0, "y": 0, "p": 44, "ram": [[512, 105], [513, 1]]},
+         "final": {"pc": 514, "s": 253, "a": 10, "x": 0, "y": 0, "p": 44, "ram": [[512, 105], [513, 1]]},
+         "cycles": [[512, 105, "read"], [513, 1, "read"]]},
+        {"name": "e9 01 SBC # borrows",
+         "initial": {"pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 37, "ram": [[512, 233], [513, 1]]},
+         "final": {"pc": 514, "s": 253, "a": 255, "x": 0, "y": 0, "p": 164, "ram": [[512, 233], [513, 1]]},
+         "cycles": [[512, 233, "read"], [513, 1, "read"]]},
+        {"name": "e6 10 INC zp writes the old value back first",
+         "initial": {"pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [[512, 230], [513, 16], [16, 255]]},
+         "final": {"pc": 514, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38, "ram": [[512, 230], [513, 16], [16, 0]]},
+         "cycles": [[512, 230, "read"], [513, 16, "read"], [16, 255, "read"], [16, 255, "write"], [16, 0, "write"]]},
+        {"name": "bd ff 10 LDA abs,X crosses a page",
+         "initial": {"pc": 512, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36, "ram": [[512, 189], [513, 255], [514, 16], [4096, 0], [4352, 128]]},
+         "final": {"pc": 515, "s": 253, "a": 128, "x": 1, "y": 0, "p": 164, "ram": [[512, 189], [513, 255], [514, 16], [4096, 0], [4352, 128]]},
+         "cycles": [[512, 189, "read"], [513, 255, "read"], [514, 16, "read"], [4096, 0, "read"], [4352, 128, "read"]]},
+        {"name": "d0 20 BNE taken across a page",
+         "initial": {"pc": 752, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [[752, 208], [753, 32], [754, 0], [530, 0]]},
+         "final": {"pc": 786, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [[752, 208], [753, 32], [754, 0], [530, 0]]},
+         "cycles": [[752, 208, "read"], [753, 32, "read"], [754, 0, "read"], [530, 0, "read"]]},
+        {"name": "20 34 12 JSR pushes the address of its last byte",
+         "initial": {"pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [[512, 32], [513, 52], [514, 18], [509, 0], [508, 0]]},
+         "final": {"pc": 4660, "s": 251, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [[512, 32], [513, 52], [514, 18], [509, 2], [508, 2]]},
+         "cycles": [[512, 32, "read"], [513, 52, "read"], [509, 0, "read"], [509, 2, "write"], [508, 2, "write"], [514, 18, "read"]]},
+        {"name": "48 PHA",
+         "initial": {"pc": 512, "s": 253, "a": 119, "x": 0, "y": 0, "p": 36, "ram": [[512, 72], [513, 0], [509, 0]]},
+         "final": {"pc": 513, "s": 252, "a": 119, "x": 0, "y": 0, "p": 36, "ram": [[512, 72], [513, 0], [509, 119]]},
+         "cycles": [[512, 72, "read"], [513, 0, "read"], [509, 119, "write"]]}
+    ]"#;
+
+    // RAM that keeps a list of the writes made to it
+    struct WriteLogBus {
+        ram: RamBus,
+        writes: Rc<RefCell<Vec<(u16, u8)>>>,
+    }
+
+    impl Bus for WriteLogBus {
+        fn read(&self, address: u16) -> u8 {
+            self.ram.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.writes.borrow_mut().push((address, data));
+            self.ram.write(address, data);
+        }
+    }
+
+    // Runs one single step test, describing everything that came out wrong
+    fn run_single_step(case: &serde_json::Value) -> Result<(), String> {
+        let number = |value: &serde_json::Value| value.as_u64().unwrap_or(0);
+        let (initial, expected) = (&case["initial"], &case["final"]);
+        let ram_of = |state: &serde_json::Value| -> Vec<(u16, u8)> {
+            state["ram"].as_array().into_iter().flatten()
+                .map(|entry| (number(&entry[0]) as u16, number(&entry[1]) as u8))
+                .collect()
+        };
+
+        let mut ram = RamBus::default();
+        for (address, data) in ram_of(initial) {
+            ram.load(address, &[data]);
+        }
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = Cpu6502::new(Box::new(WriteLogBus { ram, writes: Rc::clone(&writes) }));
+
+        cpu.set_remaining_cycles(0);
+        cpu.set_pc(number(&initial["pc"]) as u16);
+        cpu.set_sp(number(&initial["s"]) as u8);
+        cpu.set_acc(number(&initial["a"]) as u8);
+        cpu.set_x_reg(number(&initial["x"]) as u8);
+        cpu.set_y_reg(number(&initial["y"]) as u8);
+        cpu.set_status(number(&initial["p"]) as u8);
+
+        let start = cpu.total_clocks();
+        loop {
+            cpu.cycle();
+            if cpu.cycles_remaining == 0 {
+                break;
+            }
+        }
+
+        let mut wrong = Vec::new();
+        let mut check = |what: &str, actual: u64, expected: u64| {
+            if actual != expected {
+                wrong.push(format!("{what} {actual:X} (expected {expected:X})"));
+            }
+        };
+
+        check("PC", cpu.get_pc() as u64, number(&expected["pc"]));
+        check("S", cpu.get_sp() as u64, number(&expected["s"]));
+        check("A", cpu.get_acc() as u64, number(&expected["a"]));
+        check("X", cpu.get_x_reg() as u64, number(&expected["x"]));
+        check("Y", cpu.get_y_reg() as u64, number(&expected["y"]));
+        // B and the unused bit aren't really flags, they only exist in the
+        // copies of P pushed to the stack (which the RAM checks cover)
+        check("P", (cpu.get_status() & 0xCF) as u64, number(&expected["p"]) & 0xCF);
+        for (address, data) in ram_of(expected) {
+            check(&format!("${address:04X}"), cpu.read(address) as u64, data as u64);
+        }
+
+        let bus_cycles = case["cycles"].as_array().cloned().unwrap_or_default();
+        check("cycles", cpu.total_clocks() - start, bus_cycles.len() as u64);
+
+        let expected_writes: Vec<(u16, u8)> = bus_cycles.iter()
+            .filter(|cycle| cycle[2] == "write")
+            .map(|cycle| (number(&cycle[0]) as u16, number(&cycle[1]) as u8))
+            .collect();
+        if *writes.borrow() != expected_writes {
+            wrong.push(format!("writes {:X?} (expected {expected_writes:X?})", writes.borrow()));
+        }
+
+        match wrong.is_empty() {
+            true => Ok(()),
+            false => Err(format!("{}: {}", case["name"].as_str().unwrap_or("?"), wrong.join(", "))),
+        }
+    }
+
+    // Runs a list of single step tests, skipping the opcodes that can't pass
+    // and giving back the failures
+    fn run_single_steps(cases: &[serde_json::Value]) -> Vec<String> {
+        cases.iter()
+            .filter(|case| {
+                let opcode = case["initial"]["ram"].as_array().into_iter().flatten()
+                    .find(|entry| entry[0] == case["initial"]["pc"])
+                    .and_then(|entry| entry[1].as_u64())
+                    .unwrap_or(0);
+                let instr = &INSTRUCTION_TABLE[opcode as usize];
+
+                instr.name != "JAM" && !instr.is_unstable()
+            })
+            .filter_map(|case| run_single_step(case).err())
+            .collect()
+    }
+
+    #[test]
+    fn single_step_samples() {
+        let cases: Vec<serde_json::Value> = serde_json::from_str(SINGLE_STEP_SAMPLES).unwrap();
+
+        let failures = run_single_steps(&cases);
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn single_step_tests() {
+        let Ok(dir) = std::env::var(SINGLE_STEP_TESTS_DIR) else {
+            println!("{SINGLE_STEP_TESTS_DIR} isn't set, skipping the full single step tests");
+            return;
+        };
+
+        let mut failures = Vec::new();
+        let mut opcodes_failed = 0;
+        let mut files_loaded = 0;
+
+        for opcode in 0..=0xFFu8 {
+            let path = format!("{dir}/{opcode:02x}.json");
+            let Ok(json) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let cases: Vec<serde_json::Value> = serde_json::from_str(&json)
+                .unwrap_or_else(|e| panic!("Couldn't parse '{path}': {e}"));
+            files_loaded += 1;
+
+            let opcode_failures = run_single_steps(&cases);
+            if !opcode_failures.is_empty() {
+                opcodes_failed += 1;
+                // The first few of each opcode are plenty to go on
+                failures.push(format!("${opcode:02X} {}: {} of {} failed", INSTRUCTION_TABLE[opcode as usize].name, opcode_failures.len(), cases.len()));
+                failures.extend(opcode_failures.into_iter().take(3).map(|failure| format!("    {failure}")));
+            }
+        }
+
+        // A mistyped folder shouldn't pass by running nothing
+        assert!(files_loaded > 0, "No test files found in '{dir}' ({SINGLE_STEP_TESTS_DIR} is set)");
+        assert!(failures.is_empty(), "{opcodes_failed} opcodes failed:\n{}", failures.join("\n"));
+    }
+}
@@ -0,0 +1,213 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use super::breakpoints::WriteBreak;
+
+/*
+ON HOW THE EVENT BUS WORKS:
+
+Things happen inside the NES that the rest of the emulator wants to hear about:
+the PPU finishing a frame, vblank starting, sprite 0 hitting, the mapper
+raising an IRQ, the APU sending a batch of samples off. Rather than each of
+these being a flag that someone has to remember to poll (and clear) at the
+right time, the part of the system it happens in publishes a NesEvent on the
+event bus, and anything that cares subscribes to the kinds it wants.
+
+Each subscriber gets its own queue, so they never steal events from each
+other: the NES's own frame loop, the renderer, the debugger and a script can
+all be told about the same FrameReady. Subscribers drain their queue with
+Nes::take_events whenever suits them (usually once a frame), which keeps the
+emulation itself from ever calling out into the frontend partway through a
+clock. A queue that's never drained stops growing at MAX_QUEUED_EVENTS, losing
+its oldest events first.
+
+The bus belongs to the Nes and is shared with the PPU and APU. It outlives the
+cartridge, so subscriptions carry on when another game is loaded.
+*/
+
+// The most events a subscriber's queue holds before the oldest are dropped
+const MAX_QUEUED_EVENTS: usize = 4096;
+
+/// Something that happened inside the NES (see "ON HOW THE EVENT BUS WORKS")
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NesEvent {
+    /// The PPU finished a frame, and the picture is ready to be shown
+    FrameReady,
+    /// The PPU set the vblank flag, at dot 1 of scanline 241
+    /// https://www.nesdev.org/wiki/PPU_frame_timing#VBL_Flag_Timing
+    VblankStart,
+    /// Sprite 0 hit the background for the first time this frame
+    /// https://www.nesdev.org/wiki/PPU_OAM#Sprite_zero_hits
+    Sprite0Hit { scanline: usize, dot: usize },
+    /// The CPU started running the NMI handler
+    Nmi,
+    /// The mapper raised its IRQ line
+    MapperIrq,
+    /// The APU sent a batch of samples to the sample queue
+    ApuBatchReady { samples: usize },
+    /// The reset button was pressed
+    Reset,
+    /// The CPU made a write matching a write breakpoint (see breakpoints)
+    WriteBreak(WriteBreak),
+}
+
+impl NesEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::FrameReady => EventKind::FrameReady,
+            Self::VblankStart => EventKind::VblankStart,
+            Self::Sprite0Hit { .. } => EventKind::Sprite0Hit,
+            Self::Nmi => EventKind::Nmi,
+            Self::MapperIrq => EventKind::MapperIrq,
+            Self::ApuBatchReady { .. } => EventKind::ApuBatchReady,
+            Self::Reset => EventKind::Reset,
+            Self::WriteBreak(_) => EventKind::WriteBreak,
+        }
+    }
+}
+
+/// The kinds of NesEvent, for choosing which ones to subscribe to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    FrameReady,
+    VblankStart,
+    Sprite0Hit,
+    Nmi,
+    MapperIrq,
+    ApuBatchReady,
+    Reset,
+    WriteBreak,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 8] = [
+        Self::FrameReady, Self::VblankStart, Self::Sprite0Hit, Self::Nmi, Self::MapperIrq, Self::ApuBatchReady,
+        Self::Reset, Self::WriteBreak,
+    ];
+}
+
+/// A subscriber's place on the event bus, from Nes::subscribe. Spots are
+/// reused once unsubscribed, so it also says which of the spot's subscribers
+/// it's for, and one kept after unsubscribing matches nobody.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Subscription {
+    index: usize,
+    generation: u32,
+}
+
+struct Subscriber {
+    kinds: Vec<EventKind>,
+    queue: VecDeque<NesEvent>,
+}
+
+#[derive(Default)]
+struct Slot {
+    // How many subscribers have had this spot before the current one
+    generation: u32,
+    // None once unsubscribed
+    subscriber: Option<Subscriber>,
+}
+
+/// Passes events from the parts of the NES to whoever subscribed to them (see
+/// "ON HOW THE EVENT BUS WORKS")
+#[derive(Default)]
+pub struct EventBus {
+    // Indexed by Subscription
+    slots: Vec<Slot>,
+}
+
+pub type SharedEventBus = Rc<RefCell<EventBus>>;
+
+impl EventBus {
+    /// Starts queueing events of the given kinds for a new subscriber
+    pub fn subscribe(&mut self, kinds: &[EventKind]) -> Subscription {
+        let subscriber = Subscriber { kinds: kinds.to_vec(), queue: VecDeque::new() };
+
+        // Reuse the spot of one that's unsubscribed
+        let index = match self.slots.iter().position(|slot| slot.subscriber.is_none()) {
+            Some(i) => i,
+            None => {
+                self.slots.push(Slot::default());
+                self.slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        slot.subscriber = Some(subscriber);
+
+        Subscription { index, generation: slot.generation }
+    }
+
+    /// Stops queueing events for a subscriber, throwing away any it hasn't
+    /// taken
+    pub fn unsubscribe(&mut self, subscription: Subscription) {
+        if self.subscriber(subscription).is_some() {
+            let slot = &mut self.slots[subscription.index];
+            slot.subscriber = None;
+            slot.generation = slot.generation.wrapping_add(1);
+        }
+    }
+
+    /// Queues the event for everyone subscribed to its kind
+    pub fn publish(&mut self, event: NesEvent) {
+        let kind = event.kind();
+
+        for subscriber in self.slots.iter_mut().filter_map(|slot| slot.subscriber.as_mut()) {
+            if subscriber.kinds.contains(&kind) {
+                if subscriber.queue.len() >= MAX_QUEUED_EVENTS {
+                    subscriber.queue.pop_front();
+                }
+                subscriber.queue.push_back(event);
+            }
+        }
+    }
+
+    /// Whether a subscriber has any events waiting
+    pub fn has_events(&self, subscription: Subscription) -> bool {
+        self.subscriber(subscription)
+            .is_some_and(|subscriber| !subscriber.queue.is_empty())
+    }
+
+    /// Takes every event waiting for a subscriber, oldest first
+    pub fn take(&mut self, subscription: Subscription) -> Vec<NesEvent> {
+        match self.subscriber_mut(subscription) {
+            Some(subscriber) => subscriber.queue.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // The subscriber the subscription is for, if it's still subscribed
+    fn subscriber(&self, subscription: Subscription) -> Option<&Subscriber> {
+        self.slots.get(subscription.index)
+            .filter(|slot| slot.generation == subscription.generation)
+            .and_then(|slot| slot.subscriber.as_ref())
+    }
+
+    fn subscriber_mut(&mut self, subscription: Subscription) -> Option<&mut Subscriber> {
+        self.slots.get_mut(subscription.index)
+            .filter(|slot| slot.generation == subscription.generation)
+            .and_then(|slot| slot.subscriber.as_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_subscriptions_dont_reach_new_subscribers() {
+        let mut bus = EventBus::default();
+
+        let old = bus.subscribe(&[EventKind::FrameReady]);
+        bus.unsubscribe(old);
+        let new = bus.subscribe(&[EventKind::FrameReady]);
+        assert_ne!(old, new);
+
+        bus.publish(NesEvent::FrameReady);
+        assert!(!bus.has_events(old));
+        assert!(bus.take(old).is_empty());
+
+        // Unsubscribing twice doesn't take the new subscriber's spot either
+        bus.unsubscribe(old);
+        assert_eq!(bus.take(new), vec![NesEvent::FrameReady]);
+    }
+}
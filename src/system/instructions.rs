@@ -1,7 +1,3 @@
-// KNOWN ISSUES:
-//  - Not all instructions that use abs_x, abs_y, etc. should add the extra 
-//    clock cycle if page boundary crossed. (e.g. SBC does but STA does not)
-
 use crate::system::cpu::Cpu6502;
 
 // Generic unimplemented opcode instr with given opcode (for differentiating instr calls)
@@ -15,7 +11,7 @@ macro_rules! illegal_op {
             func: xxx,
             base_clocks: 2,
             bytes: 1,
-            has_extra_fetch_cycles: false, 
+            class: InstrClass::Other,
             is_illegal: true,
         }
     };
@@ -30,268 +26,262 @@ pub const DEFAULT_ILLEGAL_OP: Instruction = illegal_op!(0x00);
 /// instruction's opcode. The full table can be viewed here:
 /// https://www.masswerk.at/6502/6502_instruction_set.html
 pub const INSTRUCTION_TABLE: [Instruction; 256] = [
-    Instruction{name: "BRK", opcode_num: 0x00, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: brk, base_clocks: 7, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ORA", opcode_num: 0x01, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: ora, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0x02), // JAM
-    Instruction{name: "SLO", opcode_num: 0x03, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: slo, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x04, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: nop, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "ORA", opcode_num: 0x05, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: ora, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ASL", opcode_num: 0x06, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: asl_mem, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SLO", opcode_num: 0x07, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: slo, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "PHP", opcode_num: 0x08, addr_mode: AddressingMode::Implied, addr_func: implied, func: php, base_clocks: 3, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ORA", opcode_num: 0x09, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: ora, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ASL", opcode_num: 0x0A, addr_mode: AddressingMode::Accumulator, addr_func: accumulator, func: asl_acc, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ANC", opcode_num: 0x0B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: anc, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x0C, addr_mode: AddressingMode::Absolute, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "ORA", opcode_num: 0x0D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: ora, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ASL", opcode_num: 0x0E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: asl_mem, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SLO", opcode_num: 0x0F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: slo, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BPL", opcode_num: 0x10, addr_mode: AddressingMode::Relative, addr_func: relative, func: bpl, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "ORA", opcode_num: 0x11, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: ora, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    illegal_op!(0x12), // JAM
-    Instruction{name: "SLO", opcode_num: 0x13, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: slo, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x14, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "ORA", opcode_num: 0x15, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: ora, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ASL", opcode_num: 0x16, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: asl_mem, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SLO", opcode_num: 0x17, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: slo, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CLC", opcode_num: 0x18, addr_mode: AddressingMode::Implied, addr_func: implied, func: clc, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ORA", opcode_num: 0x19, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: ora, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0x1A, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "SLO", opcode_num: 0x1B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: slo, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x1C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: true},
-    Instruction{name: "ORA", opcode_num: 0x1D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: ora, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "ASL", opcode_num: 0x1E, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: asl_mem, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SLO", opcode_num: 0x1F, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: slo, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "JSR", opcode_num: 0x20, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: jsr, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "AND", opcode_num: 0x21, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: and, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0x22), // JAM
-    Instruction{name: "RLA", opcode_num: 0x23, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: rla, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BIT", opcode_num: 0x24, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: bit, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "AND", opcode_num: 0x25, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: and, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ROL", opcode_num: 0x26, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: rol_mem, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "RLA", opcode_num: 0x27, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: rla, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "PLP", opcode_num: 0x28, addr_mode: AddressingMode::Implied, addr_func: implied, func: plp, base_clocks: 4, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "AND", opcode_num: 0x29, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: and, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ROL", opcode_num: 0x2A, addr_mode: AddressingMode::Accumulator, addr_func: accumulator, func: rol_acc, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ANC", opcode_num: 0x2B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: anc, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BIT", opcode_num: 0x2C, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: bit, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "AND", opcode_num: 0x2D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: and, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ROL", opcode_num: 0x2E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: rol_mem, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "RLA", opcode_num: 0x2F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: rla, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BMI", opcode_num: 0x30, addr_mode: AddressingMode::Relative, addr_func: relative, func: bmi, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "AND", opcode_num: 0x31, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: and, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    illegal_op!(0x32), // JAM
-    Instruction{name: "RLA", opcode_num: 0x2F, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: rla, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x34, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "AND", opcode_num: 0x35, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: and, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ROL", opcode_num: 0x36, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: rol_mem, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "RLA", opcode_num: 0x2F, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: rla, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "SEC", opcode_num: 0x38, addr_mode: AddressingMode::Implied, addr_func: implied, func: sec, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "AND", opcode_num: 0x39, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: and, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0x3A, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "RLA", opcode_num: 0x3B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: rla, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x3C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: true},
-    Instruction{name: "AND", opcode_num: 0x3D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: and, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "ROL", opcode_num: 0x3E, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: rol_mem, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "RLA", opcode_num: 0x3F, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: rla, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "RTI", opcode_num: 0x40, addr_mode: AddressingMode::Implied, addr_func: implied, func: rti, base_clocks: 6, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "EOR", opcode_num: 0x41, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: eor, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0x42), // JAM
-    Instruction{name: "SRE", opcode_num: 0x43, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: sre, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x44, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: nop, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "EOR", opcode_num: 0x45, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: eor, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LSR", opcode_num: 0x46, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: lsr_mem, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SRE", opcode_num: 0x47, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sre, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "PHA", opcode_num: 0x48, addr_mode: AddressingMode::Implied, addr_func: implied, func: pha, base_clocks: 3, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "EOR", opcode_num: 0x49, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: eor, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LSR", opcode_num: 0x4A, addr_mode: AddressingMode::Accumulator, addr_func: accumulator, func: lsr_acc, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ASR", opcode_num: 0x4B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: asr, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "JMP", opcode_num: 0x4C, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: jmp, base_clocks: 3, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "EOR", opcode_num: 0x4D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: eor, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LSR", opcode_num: 0x4E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: lsr_mem, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SRE", opcode_num: 0x4F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sre, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BVC", opcode_num: 0x50, addr_mode: AddressingMode::Relative, addr_func: relative, func: bvc, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "EOR", opcode_num: 0x51, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: eor, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    illegal_op!(0x52), // JAM
-    Instruction{name: "SRE", opcode_num: 0x4F, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: sre, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x54, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "EOR", opcode_num: 0x55, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: eor, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LSR", opcode_num: 0x56, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: lsr_mem, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SRE", opcode_num: 0x57, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: sre, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CLI", opcode_num: 0x58, addr_mode: AddressingMode::Implied, addr_func: implied, func: cli, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "EOR", opcode_num: 0x59, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: eor, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0x5A, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "SRE", opcode_num: 0x5B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: sre, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x5C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: true},
-    Instruction{name: "EOR", opcode_num: 0x5D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: eor, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "LSR", opcode_num: 0x5E, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: lsr_mem, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SRE", opcode_num: 0x5F, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: sre, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "RTS", opcode_num: 0x60, addr_mode: AddressingMode::Implied, addr_func: implied, func: rts, base_clocks: 6, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ADC", opcode_num: 0x61, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: adc, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0x62), // JAM
-    Instruction{name: "RRA", opcode_num: 0x63, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: rra, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x64, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: nop, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "ADC", opcode_num: 0x65, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: adc, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ROR", opcode_num: 0x66, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: ror_mem, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "RRA", opcode_num: 0x67, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: rra, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "PLA", opcode_num: 0x68, addr_mode: AddressingMode::Implied, addr_func: implied, func: pla, base_clocks: 4, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ADC", opcode_num: 0x69, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: adc, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ROR", opcode_num: 0x6A, addr_mode: AddressingMode::Accumulator, addr_func: accumulator, func: ror_acc, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ARR", opcode_num: 0x6B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: arr, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "JMP", opcode_num: 0x6C, addr_mode: AddressingMode::Indirect, addr_func: indirect, func: jmp, base_clocks: 5, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ADC", opcode_num: 0x6D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: adc, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ROR", opcode_num: 0x6E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: ror_mem, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "RRA", opcode_num: 0x6F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: rra, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BVS", opcode_num: 0x70, addr_mode: AddressingMode::Relative, addr_func: relative, func: bvs, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "ADC", opcode_num: 0x71, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: adc, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    illegal_op!(0x72), // JAM
-    Instruction{name: "RRA", opcode_num: 0x73, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: rra, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x74, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "ADC", opcode_num: 0x75, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: adc, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ROR", opcode_num: 0x76, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: ror_mem, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "RRA", opcode_num: 0x77, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: rra, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "SEI", opcode_num: 0x78, addr_mode: AddressingMode::Implied, addr_func: implied, func: sei, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ADC", opcode_num: 0x79, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: adc, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0x7A, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "RRA", opcode_num: 0x7B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: rra, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x7C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: true},
-    Instruction{name: "ADC", opcode_num: 0x7D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: adc, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "ROR", opcode_num: 0x7E, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: ror_mem, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "RRA", opcode_num: 0x7F, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: rra, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0x80, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "STA", opcode_num: 0x81, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: sta, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0x82, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "SAX", opcode_num: 0x83, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: sax, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "STY", opcode_num: 0x84, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sty, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "STA", opcode_num: 0x85, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sta, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "STX", opcode_num: 0x86, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: stx, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SAX", opcode_num: 0x87, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sax, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "DEY", opcode_num: 0x88, addr_mode: AddressingMode::Implied, addr_func: implied, func: dey, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0x89, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "TXA", opcode_num: 0x8A, addr_mode: AddressingMode::Implied, addr_func: implied, func: txa, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0x8B), // XAA - non-deterministic, highly unstable, do not use
-    Instruction{name: "STY", opcode_num: 0x8C, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sty, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "STA", opcode_num: 0x8D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sta, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "STX", opcode_num: 0x8E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: stx, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SAX", opcode_num: 0x8F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sax, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BCC", opcode_num: 0x90, addr_mode: AddressingMode::Relative, addr_func: relative, func: bcc, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "STA", opcode_num: 0x91, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: sta, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0x92), // JAM
-    illegal_op!(0x93), // SHA
-    Instruction{name: "STY", opcode_num: 0x94, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: sty, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "STA", opcode_num: 0x95, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: sta, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "STX", opcode_num: 0x96, addr_mode: AddressingMode::ZeroPageY, addr_func: zpage_y, func: stx, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SAX", opcode_num: 0x97, addr_mode: AddressingMode::ZeroPageY, addr_func: zpage_y, func: sax, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "TYA", opcode_num: 0x98, addr_mode: AddressingMode::Implied, addr_func: implied, func: tya, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "STA", opcode_num: 0x99, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: sta, base_clocks: 5, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "TXS", opcode_num: 0x9A, addr_mode: AddressingMode::Implied, addr_func: implied, func: txs, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0x9B), // SHA - IndirectY
-    Instruction{name: "SHY", opcode_num: 0x9C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: shy, base_clocks: 5, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-
-    Instruction{name: "STA", opcode_num: 0x9D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: sta, base_clocks: 5, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0x9E), // SHX
-    Instruction{name: "SHY", opcode_num: 0x9E, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: shx, base_clocks: 5, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-
-    Instruction{name: "LDY", opcode_num: 0xA0, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: ldy, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDA", opcode_num: 0xA1, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: lda, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDX", opcode_num: 0xA2, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: ldx, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LAX", opcode_num: 0xA3, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: lax, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "LDY", opcode_num: 0xA4, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: ldy, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDA", opcode_num: 0xA5, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: lda, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDX", opcode_num: 0xA6, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: ldx, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LAX", opcode_num: 0xA7, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: lax, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "TAY", opcode_num: 0xA8, addr_mode: AddressingMode::Implied, addr_func: implied, func: tay, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDA", opcode_num: 0xA9, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: lda, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "TAX", opcode_num: 0xAA, addr_mode: AddressingMode::Implied, addr_func: implied, func: tax, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    
-    
-    //Instruction{name: "LXA", opcode_num: 0xAB, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: lxa, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "LXA", opcode_num: 0xAB, addr_mode: AddressingMode::Immediate, addr_func: absolute, func: lax, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    
-    Instruction{name: "LDY", opcode_num: 0xAC, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: ldy, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDA", opcode_num: 0xAD, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: lda, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDX", opcode_num: 0xAE, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: ldx, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LAX", opcode_num: 0xAF, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: lax, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BCS", opcode_num: 0xB0, addr_mode: AddressingMode::Relative, addr_func: relative, func: bcs, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "LDA", opcode_num: 0xB1, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: lda, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    illegal_op!(0xB2), // JAM
-    Instruction{name: "LAX", opcode_num: 0xB3, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: lax, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: true, is_illegal: true},
-    Instruction{name: "LDY", opcode_num: 0xB4, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: ldy, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDA", opcode_num: 0xB5, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: lda, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDX", opcode_num: 0xB6, addr_mode: AddressingMode::ZeroPageY, addr_func: zpage_y, func: ldx, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LAX", opcode_num: 0xB7, addr_mode: AddressingMode::ZeroPageY, addr_func: zpage_y, func: lax, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CLV", opcode_num: 0xB8, addr_mode: AddressingMode::Implied, addr_func: implied, func: clv, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "LDA", opcode_num: 0xB9, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: lda, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "TSX", opcode_num: 0xBA, addr_mode: AddressingMode::Implied, addr_func: implied, func: tsx, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0xBB), // LAS
-    Instruction{name: "LDY", opcode_num: 0xBC, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: ldy, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "LDA", opcode_num: 0xBD, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: lda, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "LDX", opcode_num: 0xBE, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: ldx, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "LAX", opcode_num: 0xBF, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: lax, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: true},
-    Instruction{name: "CPY", opcode_num: 0xC0, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: cpy, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "CMP", opcode_num: 0xC1, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: cmp, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0xC2, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "DCP", opcode_num: 0xC3, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: dcp, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CPY", opcode_num: 0xC4, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: cpy, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "CMP", opcode_num: 0xC5, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: cmp, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DEC", opcode_num: 0xC6, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: dec, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DCP", opcode_num: 0xC7, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: dcp, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "INY", opcode_num: 0xC8, addr_mode: AddressingMode::Implied, addr_func: implied, func: iny, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "CMP", opcode_num: 0xC9, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: cmp, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DEX", opcode_num: 0xCA, addr_mode: AddressingMode::Implied, addr_func: implied, func: dex, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    illegal_op!(0xCB), // SBX
-    Instruction{name: "CPY", opcode_num: 0xCC, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: cpy, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "CMP", opcode_num: 0xCD, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: cmp, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DEC", opcode_num: 0xCE, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: dec, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DCP", opcode_num: 0xCF, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: dcp, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BNE", opcode_num: 0xD0, addr_mode: AddressingMode::Relative, addr_func: relative, func: bne, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "CMP", opcode_num: 0xD1, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: cmp, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    illegal_op!(0xD2), // JAM
-    Instruction{name: "DCP", opcode_num: 0xD3, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: dcp, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0xD4, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CMP", opcode_num: 0xD5, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: cmp, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DEC", opcode_num: 0xD6, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: dec, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DCP", opcode_num: 0xD7, addr_mode: AddressingMode::Absolute, addr_func: zpage_x, func: dcp, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CLD", opcode_num: 0xD8, addr_mode: AddressingMode::Implied, addr_func: implied, func: cld, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "CMP", opcode_num: 0xD9, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: cmp, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0xDA, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DCP", opcode_num: 0xDB, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: dcp, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0xDC, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: true},
-    Instruction{name: "CMP", opcode_num: 0xDD, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: cmp, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "DEC", opcode_num: 0xDE, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: dec, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "DCP", opcode_num: 0xDF, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: dcp, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CPX", opcode_num: 0xE0, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: cpx, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SBC", opcode_num: 0xE1, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: sbc, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0xE2, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "ISC", opcode_num: 0xE3, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: isc, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CPX", opcode_num: 0xE4, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: cpx, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SBC", opcode_num: 0xE5, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sbc, base_clocks: 3, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "INC", opcode_num: 0xE6, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: inc, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ISC", opcode_num: 0xE7, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: isc, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "INX", opcode_num: 0xE8, addr_mode: AddressingMode::Implied, addr_func: implied, func: inx, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SBC", opcode_num: 0xE9, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: sbc, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0xEA, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "USBC", opcode_num: 0xEB, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: sbc, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "CPX", opcode_num: 0xEC, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: cpx, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SBC", opcode_num: 0xED, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sbc, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "INC", opcode_num: 0xEE, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: inc, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ISC", opcode_num: 0xEF, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: isc, base_clocks: 6, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "BEQ", opcode_num: 0xF0, addr_mode: AddressingMode::Relative, addr_func: relative, func: beq, base_clocks: 2, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "SBC", opcode_num: 0xF1, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: sbc, base_clocks: 5, bytes: 2, has_extra_fetch_cycles: true, is_illegal: false},
-    illegal_op!(0xF2), // JAM
-    Instruction{name: "ISC", opcode_num: 0xF3, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: isc, base_clocks: 8, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0xF4, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "SBC", opcode_num: 0xF5, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: sbc, base_clocks: 4, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "INC", opcode_num: 0xF6, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: inc, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ISC", opcode_num: 0xF7, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: isc, base_clocks: 6, bytes: 2, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "SED", opcode_num: 0xF8, addr_mode: AddressingMode::Implied, addr_func: implied, func: sed, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "SBC", opcode_num: 0xF9, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: sbc, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "NOP", opcode_num: 0xFA, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ISC", opcode_num: 0xFB, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: isc, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
-    Instruction{name: "NOP", opcode_num: 0xFC, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: true},
-    Instruction{name: "SBC", opcode_num: 0xFD, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: sbc, base_clocks: 4, bytes: 3, has_extra_fetch_cycles: true, is_illegal: false},
-    Instruction{name: "INC", opcode_num: 0xFE, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: inc, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: false},
-    Instruction{name: "ISC", opcode_num: 0xFF, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: isc, base_clocks: 7, bytes: 3, has_extra_fetch_cycles: false, is_illegal: true},
+    Instruction{name: "BRK", opcode_num: 0x00, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: brk, base_clocks: 7, bytes: 2, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ORA", opcode_num: 0x01, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: ora, base_clocks: 6, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x02, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "SLO", opcode_num: 0x03, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: slo, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x04, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: nop, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "ORA", opcode_num: 0x05, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: ora, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ASL", opcode_num: 0x06, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: asl_mem, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "SLO", opcode_num: 0x07, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: slo, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "PHP", opcode_num: 0x08, addr_mode: AddressingMode::Implied, addr_func: implied, func: php, base_clocks: 3, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ORA", opcode_num: 0x09, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: ora, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ASL", opcode_num: 0x0A, addr_mode: AddressingMode::Accumulator, addr_func: accumulator, func: asl_acc, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ANC", opcode_num: 0x0B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: anc, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x0C, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: nop, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "ORA", opcode_num: 0x0D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: ora, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ASL", opcode_num: 0x0E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: asl_mem, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "SLO", opcode_num: 0x0F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: slo, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "BPL", opcode_num: 0x10, addr_mode: AddressingMode::Relative, addr_func: relative, func: bpl, base_clocks: 2, bytes: 2, class: InstrClass::Branch, is_illegal: false},
+    Instruction{name: "ORA", opcode_num: 0x11, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: ora, base_clocks: 5, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x12, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "SLO", opcode_num: 0x13, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: slo, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x14, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "ORA", opcode_num: 0x15, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: ora, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ASL", opcode_num: 0x16, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: asl_mem, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "SLO", opcode_num: 0x17, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: slo, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "CLC", opcode_num: 0x18, addr_mode: AddressingMode::Implied, addr_func: implied, func: clc, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ORA", opcode_num: 0x19, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: ora, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0x1A, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "SLO", opcode_num: 0x1B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: slo, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x1C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "ORA", opcode_num: 0x1D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: ora, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ASL", opcode_num: 0x1E, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: asl_mem, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "SLO", opcode_num: 0x1F, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: slo, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "JSR", opcode_num: 0x20, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: jsr, base_clocks: 6, bytes: 3, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "AND", opcode_num: 0x21, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: and, base_clocks: 6, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x22, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "RLA", opcode_num: 0x23, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: rla, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "BIT", opcode_num: 0x24, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: bit, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "AND", opcode_num: 0x25, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: and, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROL", opcode_num: 0x26, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: rol_mem, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "RLA", opcode_num: 0x27, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: rla, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "PLP", opcode_num: 0x28, addr_mode: AddressingMode::Implied, addr_func: implied, func: plp, base_clocks: 4, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "AND", opcode_num: 0x29, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: and, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROL", opcode_num: 0x2A, addr_mode: AddressingMode::Accumulator, addr_func: accumulator, func: rol_acc, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ANC", opcode_num: 0x2B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: anc, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "BIT", opcode_num: 0x2C, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: bit, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "AND", opcode_num: 0x2D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: and, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROL", opcode_num: 0x2E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: rol_mem, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "RLA", opcode_num: 0x2F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: rla, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "BMI", opcode_num: 0x30, addr_mode: AddressingMode::Relative, addr_func: relative, func: bmi, base_clocks: 2, bytes: 2, class: InstrClass::Branch, is_illegal: false},
+    Instruction{name: "AND", opcode_num: 0x31, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: and, base_clocks: 5, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x32, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "RLA", opcode_num: 0x33, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: rla, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x34, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "AND", opcode_num: 0x35, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: and, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROL", opcode_num: 0x36, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: rol_mem, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "RLA", opcode_num: 0x37, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: rla, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "SEC", opcode_num: 0x38, addr_mode: AddressingMode::Implied, addr_func: implied, func: sec, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "AND", opcode_num: 0x39, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: and, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0x3A, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "RLA", opcode_num: 0x3B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: rla, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x3C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "AND", opcode_num: 0x3D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: and, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROL", opcode_num: 0x3E, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: rol_mem, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "RLA", opcode_num: 0x3F, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: rla, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "RTI", opcode_num: 0x40, addr_mode: AddressingMode::Implied, addr_func: implied, func: rti, base_clocks: 6, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "EOR", opcode_num: 0x41, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: eor, base_clocks: 6, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x42, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "SRE", opcode_num: 0x43, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: sre, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x44, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: nop, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "EOR", opcode_num: 0x45, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: eor, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LSR", opcode_num: 0x46, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: lsr_mem, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "SRE", opcode_num: 0x47, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sre, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "PHA", opcode_num: 0x48, addr_mode: AddressingMode::Implied, addr_func: implied, func: pha, base_clocks: 3, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "EOR", opcode_num: 0x49, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: eor, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LSR", opcode_num: 0x4A, addr_mode: AddressingMode::Accumulator, addr_func: accumulator, func: lsr_acc, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ASR", opcode_num: 0x4B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: asr, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "JMP", opcode_num: 0x4C, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: jmp, base_clocks: 3, bytes: 3, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "EOR", opcode_num: 0x4D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: eor, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LSR", opcode_num: 0x4E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: lsr_mem, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "SRE", opcode_num: 0x4F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sre, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "BVC", opcode_num: 0x50, addr_mode: AddressingMode::Relative, addr_func: relative, func: bvc, base_clocks: 2, bytes: 2, class: InstrClass::Branch, is_illegal: false},
+    Instruction{name: "EOR", opcode_num: 0x51, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: eor, base_clocks: 5, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x52, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "SRE", opcode_num: 0x53, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: sre, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x54, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "EOR", opcode_num: 0x55, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: eor, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LSR", opcode_num: 0x56, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: lsr_mem, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "SRE", opcode_num: 0x57, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: sre, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "CLI", opcode_num: 0x58, addr_mode: AddressingMode::Implied, addr_func: implied, func: cli, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "EOR", opcode_num: 0x59, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: eor, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0x5A, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "SRE", opcode_num: 0x5B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: sre, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x5C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "EOR", opcode_num: 0x5D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: eor, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LSR", opcode_num: 0x5E, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: lsr_mem, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "SRE", opcode_num: 0x5F, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: sre, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "RTS", opcode_num: 0x60, addr_mode: AddressingMode::Implied, addr_func: implied, func: rts, base_clocks: 6, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ADC", opcode_num: 0x61, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: adc, base_clocks: 6, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x62, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "RRA", opcode_num: 0x63, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: rra, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x64, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: nop, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "ADC", opcode_num: 0x65, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: adc, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROR", opcode_num: 0x66, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: ror_mem, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "RRA", opcode_num: 0x67, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: rra, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "PLA", opcode_num: 0x68, addr_mode: AddressingMode::Implied, addr_func: implied, func: pla, base_clocks: 4, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ADC", opcode_num: 0x69, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: adc, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROR", opcode_num: 0x6A, addr_mode: AddressingMode::Accumulator, addr_func: accumulator, func: ror_acc, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ARR", opcode_num: 0x6B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: arr, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "JMP", opcode_num: 0x6C, addr_mode: AddressingMode::Indirect, addr_func: indirect, func: jmp, base_clocks: 5, bytes: 3, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ADC", opcode_num: 0x6D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: adc, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROR", opcode_num: 0x6E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: ror_mem, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "RRA", opcode_num: 0x6F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: rra, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "BVS", opcode_num: 0x70, addr_mode: AddressingMode::Relative, addr_func: relative, func: bvs, base_clocks: 2, bytes: 2, class: InstrClass::Branch, is_illegal: false},
+    Instruction{name: "ADC", opcode_num: 0x71, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: adc, base_clocks: 5, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x72, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "RRA", opcode_num: 0x73, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: rra, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x74, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "ADC", opcode_num: 0x75, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: adc, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROR", opcode_num: 0x76, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: ror_mem, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "RRA", opcode_num: 0x77, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: rra, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "SEI", opcode_num: 0x78, addr_mode: AddressingMode::Implied, addr_func: implied, func: sei, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ADC", opcode_num: 0x79, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: adc, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0x7A, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "RRA", opcode_num: 0x7B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: rra, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x7C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "ADC", opcode_num: 0x7D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: adc, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "ROR", opcode_num: 0x7E, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: ror_mem, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "RRA", opcode_num: 0x7F, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: rra, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0x80, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "STA", opcode_num: 0x81, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: sta, base_clocks: 6, bytes: 2, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0x82, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "SAX", opcode_num: 0x83, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: sax, base_clocks: 6, bytes: 2, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "STY", opcode_num: 0x84, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sty, base_clocks: 3, bytes: 2, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "STA", opcode_num: 0x85, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sta, base_clocks: 3, bytes: 2, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "STX", opcode_num: 0x86, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: stx, base_clocks: 3, bytes: 2, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "SAX", opcode_num: 0x87, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sax, base_clocks: 3, bytes: 2, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "DEY", opcode_num: 0x88, addr_mode: AddressingMode::Implied, addr_func: implied, func: dey, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0x89, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "TXA", opcode_num: 0x8A, addr_mode: AddressingMode::Implied, addr_func: implied, func: txa, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "XAA", opcode_num: 0x8B, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: xaa, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "STY", opcode_num: 0x8C, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sty, base_clocks: 4, bytes: 3, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "STA", opcode_num: 0x8D, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sta, base_clocks: 4, bytes: 3, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "STX", opcode_num: 0x8E, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: stx, base_clocks: 4, bytes: 3, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "SAX", opcode_num: 0x8F, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sax, base_clocks: 4, bytes: 3, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "BCC", opcode_num: 0x90, addr_mode: AddressingMode::Relative, addr_func: relative, func: bcc, base_clocks: 2, bytes: 2, class: InstrClass::Branch, is_illegal: false},
+    Instruction{name: "STA", opcode_num: 0x91, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: sta, base_clocks: 6, bytes: 2, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0x92, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "SHA", opcode_num: 0x93, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: sha, base_clocks: 6, bytes: 2, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "STY", opcode_num: 0x94, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: sty, base_clocks: 4, bytes: 2, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "STA", opcode_num: 0x95, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: sta, base_clocks: 4, bytes: 2, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "STX", opcode_num: 0x96, addr_mode: AddressingMode::ZeroPageY, addr_func: zpage_y, func: stx, base_clocks: 4, bytes: 2, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "SAX", opcode_num: 0x97, addr_mode: AddressingMode::ZeroPageY, addr_func: zpage_y, func: sax, base_clocks: 4, bytes: 2, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "TYA", opcode_num: 0x98, addr_mode: AddressingMode::Implied, addr_func: implied, func: tya, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "STA", opcode_num: 0x99, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: sta, base_clocks: 5, bytes: 3, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "TXS", opcode_num: 0x9A, addr_mode: AddressingMode::Implied, addr_func: implied, func: txs, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "TAS", opcode_num: 0x9B, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: tas, base_clocks: 5, bytes: 3, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "SHY", opcode_num: 0x9C, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: shy, base_clocks: 5, bytes: 3, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "STA", opcode_num: 0x9D, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: sta, base_clocks: 5, bytes: 3, class: InstrClass::Write, is_illegal: false},
+    Instruction{name: "SHX", opcode_num: 0x9E, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: shx, base_clocks: 5, bytes: 3, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "SHA", opcode_num: 0x9F, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: sha, base_clocks: 5, bytes: 3, class: InstrClass::Write, is_illegal: true},
+    Instruction{name: "LDY", opcode_num: 0xA0, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: ldy, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDA", opcode_num: 0xA1, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: lda, base_clocks: 6, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDX", opcode_num: 0xA2, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: ldx, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LAX", opcode_num: 0xA3, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: lax, base_clocks: 6, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "LDY", opcode_num: 0xA4, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: ldy, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDA", opcode_num: 0xA5, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: lda, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDX", opcode_num: 0xA6, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: ldx, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LAX", opcode_num: 0xA7, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: lax, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "TAY", opcode_num: 0xA8, addr_mode: AddressingMode::Implied, addr_func: implied, func: tay, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "LDA", opcode_num: 0xA9, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: lda, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "TAX", opcode_num: 0xAA, addr_mode: AddressingMode::Implied, addr_func: implied, func: tax, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "LXA", opcode_num: 0xAB, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: lxa, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "LDY", opcode_num: 0xAC, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: ldy, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDA", opcode_num: 0xAD, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: lda, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDX", opcode_num: 0xAE, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: ldx, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LAX", opcode_num: 0xAF, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: lax, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "BCS", opcode_num: 0xB0, addr_mode: AddressingMode::Relative, addr_func: relative, func: bcs, base_clocks: 2, bytes: 2, class: InstrClass::Branch, is_illegal: false},
+    Instruction{name: "LDA", opcode_num: 0xB1, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: lda, base_clocks: 5, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0xB2, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "LAX", opcode_num: 0xB3, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: lax, base_clocks: 5, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "LDY", opcode_num: 0xB4, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: ldy, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDA", opcode_num: 0xB5, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: lda, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDX", opcode_num: 0xB6, addr_mode: AddressingMode::ZeroPageY, addr_func: zpage_y, func: ldx, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LAX", opcode_num: 0xB7, addr_mode: AddressingMode::ZeroPageY, addr_func: zpage_y, func: lax, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "CLV", opcode_num: 0xB8, addr_mode: AddressingMode::Implied, addr_func: implied, func: clv, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "LDA", opcode_num: 0xB9, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: lda, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "TSX", opcode_num: 0xBA, addr_mode: AddressingMode::Implied, addr_func: implied, func: tsx, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "LAS", opcode_num: 0xBB, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: las, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "LDY", opcode_num: 0xBC, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: ldy, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDA", opcode_num: 0xBD, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: lda, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LDX", opcode_num: 0xBE, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: ldx, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "LAX", opcode_num: 0xBF, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: lax, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "CPY", opcode_num: 0xC0, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: cpy, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "CMP", opcode_num: 0xC1, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: cmp, base_clocks: 6, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0xC2, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "DCP", opcode_num: 0xC3, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: dcp, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "CPY", opcode_num: 0xC4, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: cpy, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "CMP", opcode_num: 0xC5, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: cmp, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "DEC", opcode_num: 0xC6, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: dec, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "DCP", opcode_num: 0xC7, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: dcp, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "INY", opcode_num: 0xC8, addr_mode: AddressingMode::Implied, addr_func: implied, func: iny, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "CMP", opcode_num: 0xC9, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: cmp, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "DEX", opcode_num: 0xCA, addr_mode: AddressingMode::Implied, addr_func: implied, func: dex, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "SBX", opcode_num: 0xCB, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: sbx, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "CPY", opcode_num: 0xCC, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: cpy, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "CMP", opcode_num: 0xCD, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: cmp, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "DEC", opcode_num: 0xCE, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: dec, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "DCP", opcode_num: 0xCF, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: dcp, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "BNE", opcode_num: 0xD0, addr_mode: AddressingMode::Relative, addr_func: relative, func: bne, base_clocks: 2, bytes: 2, class: InstrClass::Branch, is_illegal: false},
+    Instruction{name: "CMP", opcode_num: 0xD1, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: cmp, base_clocks: 5, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0xD2, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "DCP", opcode_num: 0xD3, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: dcp, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0xD4, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "CMP", opcode_num: 0xD5, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: cmp, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "DEC", opcode_num: 0xD6, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: dec, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "DCP", opcode_num: 0xD7, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: dcp, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "CLD", opcode_num: 0xD8, addr_mode: AddressingMode::Implied, addr_func: implied, func: cld, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "CMP", opcode_num: 0xD9, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: cmp, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0xDA, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "DCP", opcode_num: 0xDB, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: dcp, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0xDC, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "CMP", opcode_num: 0xDD, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: cmp, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "DEC", opcode_num: 0xDE, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: dec, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "DCP", opcode_num: 0xDF, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: dcp, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "CPX", opcode_num: 0xE0, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: cpx, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "SBC", opcode_num: 0xE1, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: sbc, base_clocks: 6, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0xE2, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: nop, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "ISC", opcode_num: 0xE3, addr_mode: AddressingMode::IndirectX, addr_func: indirect_x, func: isc, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "CPX", opcode_num: 0xE4, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: cpx, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "SBC", opcode_num: 0xE5, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: sbc, base_clocks: 3, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "INC", opcode_num: 0xE6, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: inc, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "ISC", opcode_num: 0xE7, addr_mode: AddressingMode::ZeroPage, addr_func: zero_page, func: isc, base_clocks: 5, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "INX", opcode_num: 0xE8, addr_mode: AddressingMode::Implied, addr_func: implied, func: inx, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "SBC", opcode_num: 0xE9, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: sbc, base_clocks: 2, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0xEA, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "USBC", opcode_num: 0xEB, addr_mode: AddressingMode::Immediate, addr_func: immediate, func: sbc, base_clocks: 2, bytes: 2, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "CPX", opcode_num: 0xEC, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: cpx, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "SBC", opcode_num: 0xED, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: sbc, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "INC", opcode_num: 0xEE, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: inc, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "ISC", opcode_num: 0xEF, addr_mode: AddressingMode::Absolute, addr_func: absolute, func: isc, base_clocks: 6, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "BEQ", opcode_num: 0xF0, addr_mode: AddressingMode::Relative, addr_func: relative, func: beq, base_clocks: 2, bytes: 2, class: InstrClass::Branch, is_illegal: false},
+    Instruction{name: "SBC", opcode_num: 0xF1, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: sbc, base_clocks: 5, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "JAM", opcode_num: 0xF2, addr_mode: AddressingMode::Implied, addr_func: implied, func: jam, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: true},
+    Instruction{name: "ISC", opcode_num: 0xF3, addr_mode: AddressingMode::IndirectY, addr_func: indirect_y, func: isc, base_clocks: 8, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0xF4, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: nop, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "SBC", opcode_num: 0xF5, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: sbc, base_clocks: 4, bytes: 2, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "INC", opcode_num: 0xF6, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: inc, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "ISC", opcode_num: 0xF7, addr_mode: AddressingMode::ZeroPageX, addr_func: zpage_x, func: isc, base_clocks: 6, bytes: 2, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "SED", opcode_num: 0xF8, addr_mode: AddressingMode::Implied, addr_func: implied, func: sed, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "SBC", opcode_num: 0xF9, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: sbc, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "NOP", opcode_num: 0xFA, addr_mode: AddressingMode::Implied, addr_func: implied, func: nop, base_clocks: 2, bytes: 1, class: InstrClass::Other, is_illegal: false},
+    Instruction{name: "ISC", opcode_num: 0xFB, addr_mode: AddressingMode::AbsoluteY, addr_func: absolute_y, func: isc, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
+    Instruction{name: "NOP", opcode_num: 0xFC, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: nop, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: true},
+    Instruction{name: "SBC", opcode_num: 0xFD, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: sbc, base_clocks: 4, bytes: 3, class: InstrClass::Read, is_illegal: false},
+    Instruction{name: "INC", opcode_num: 0xFE, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: inc, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: false},
+    Instruction{name: "ISC", opcode_num: 0xFF, addr_mode: AddressingMode::AbsoluteX, addr_func: absolute_x, func: isc, base_clocks: 7, bytes: 3, class: InstrClass::ReadModifyWrite, is_illegal: true},
 ];
 
 #[derive(Clone, Copy)]
@@ -311,6 +301,28 @@ pub enum AddressingMode {
     Relative,
 }
 
+/// What an instruction does with the memory its addressing mode points at,
+/// which decides how its timing works with the indexed addressing modes.
+///
+/// Adding an index to an address can carry into the high byte. The CPU reads
+/// from the address before fixing the high byte up, so a read instruction can
+/// skip the extra cycle when nothing carried and the read already got the
+/// right byte. Writes and read-modify-writes can't take back a write to the
+/// wrong address, so they always spend the cycle, and it's counted in their
+/// base clocks. Branches work out their own extra cycles.
+/// https://www.nesdev.org/wiki/CPU_addressing_modes
+/// https://www.nesdev.org/6502_cpu.txt
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InstrClass {
+    Read,
+    Write,
+    ReadModifyWrite,
+    Branch,
+    // Instructions that don't go through memory at an address (implied,
+    // accumulator, stack and jump instructions)
+    Other,
+}
+
 #[derive(Clone, Copy)]
 /// A struct containing all of the data any instruction may need for its
 /// execution. Some instructions need data, some need addresses, some need both.
@@ -334,10 +346,77 @@ pub struct Instruction {
     pub func: fn(cpu: &mut Cpu6502, address: u16) -> usize,
     pub base_clocks: usize,
     pub bytes: usize,
-    pub has_extra_fetch_cycles: bool,
+    pub class: InstrClass,
     pub is_illegal: bool,
 }
 
+impl Instruction {
+    /// Where the instruction reads from while its indexed address is still
+    /// being worked out, if it does. The CPU adds the index to the low byte
+    /// first and reads from there, before the carry (if any) reaches the high
+    /// byte. Read instructions only do this when there's a carry (otherwise
+    /// the read got the right byte and they're done), but writes and
+    /// read-modify-writes always do. These reads are thrown away, but reading
+    /// some registers (PPUSTATUS, PPUDATA, the controller ports) has side
+    /// effects that games can run into.
+    /// https://www.nesdev.org/wiki/CPU_addressing_modes
+    ///
+    ///  * `address` - The finished address, from the addressing mode
+    ///  * `fetch_cycles` - The extra cycles from the addressing mode (1 when
+    ///    the index carried into the high byte)
+    pub fn dummy_read_address(&self, address: u16, fetch_cycles: usize) -> Option<u16> {
+        if !matches!(self.addr_mode, AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectY) {
+            return None;
+        }
+
+        // The index can carry at most one into the high byte
+        let partial_address = if fetch_cycles > 0 { address.wrapping_sub(0x100) } else { address };
+
+        match self.class {
+            InstrClass::Read if fetch_cycles > 0 => Some(partial_address),
+            InstrClass::Write | InstrClass::ReadModifyWrite => Some(partial_address),
+            _ => None,
+        }
+    }
+
+    /// How many extra cycles the instruction takes when its addressing mode
+    /// had to fix up the high byte of the address (see InstrClass)
+    pub fn page_cross_cycles(&self, fetch_cycles: usize) -> usize {
+        match self.class {
+            InstrClass::Read => fetch_cycles,
+            _ => 0,
+        }
+    }
+
+    /// Works out the address the instruction's addressing mode points at,
+    /// like addr_func does, but from operand bytes that have already been
+    /// read (see "ON THE CACHED CPU MODE" in cpu.rs). Pointers in memory
+    /// (the indirect modes) are still read, since they can change any time.
+    ///
+    ///  * `operand` - The bytes after the opcode, little-endian
+    pub fn resolve_address(&self, cpu: &Cpu6502, operand: u16) -> (u16, usize) {
+        match self.addr_mode {
+            AddressingMode::Accumulator | AddressingMode::Implied => (0, 0),
+            AddressingMode::Immediate | AddressingMode::Relative => (cpu.get_pc() + 1, 0),
+            AddressingMode::Absolute => (operand, 0),
+            AddressingMode::AbsoluteX => indexed(operand, cpu.get_x_reg()),
+            AddressingMode::AbsoluteY => indexed(operand, cpu.get_y_reg()),
+            AddressingMode::ZeroPage => (operand & 0xFF, 0),
+            AddressingMode::ZeroPageX => ((operand as u8).wrapping_add(cpu.get_x_reg()) as u16, 0),
+            AddressingMode::ZeroPageY => ((operand as u8).wrapping_add(cpu.get_y_reg()) as u16, 0),
+            AddressingMode::Indirect => indirect_from(cpu, operand),
+            AddressingMode::IndirectX => (cpu.read_zpage_word((operand as u8).wrapping_add(cpu.get_x_reg())), 0),
+            AddressingMode::IndirectY => indexed(cpu.read_zpage_word(operand as u8), cpu.get_y_reg()),
+        }
+    }
+
+    /// Whether this is one of the unofficial opcodes whose result depends on
+    /// the CPU it runs on, so it's only roughly emulated (see telemetry)
+    pub fn is_unstable(&self) -> bool {
+        self.is_illegal && matches!(self.name, "XAA" | "LXA" | "SHA" | "SHX" | "SHY" | "TAS")
+    }
+}
+
 
 // ADDRESSING MODES - Fetches data from the bus
 // Returns:
@@ -369,17 +448,20 @@ fn absolute(cpu: &Cpu6502) -> (u16, usize) {
 // Also known as Absolute X addressing.
 fn absolute_x(cpu: &Cpu6502) -> (u16, usize) {
     let abs_address = cpu.read_word(cpu.get_pc() + 1);
-    let effective_address = abs_address.wrapping_add(cpu.get_x_reg() as u16);
-
-    let page_boundary_crossed: bool = (abs_address & 0xFF00) != (effective_address & 0xFF00);
 
-    (effective_address, if page_boundary_crossed { 1 } else { 0 })
+    indexed(abs_address, cpu.get_x_reg())
 }
 // Indexed Addressing (Y) - Like same as Indexed x, but used the y register instead.
 // Also known as Absolute Y addressing.
 fn absolute_y(cpu: &Cpu6502) -> (u16, usize) {
     let abs_address = cpu.read_word(cpu.get_pc() + 1);
-    let effective_address = abs_address.wrapping_add(cpu.get_y_reg() as u16);
+
+    indexed(abs_address, cpu.get_y_reg())
+}
+// Adds an index register to an address, along with the extra cycle taken
+// when that carries into the high byte
+fn indexed(abs_address: u16, index: u8) -> (u16, usize) {
+    let effective_address = abs_address.wrapping_add(index as u16);
 
     let page_boundary_crossed: bool = (abs_address & 0xFF00) != (effective_address & 0xFF00);
 
@@ -418,6 +500,10 @@ fn zpage_y(cpu: &Cpu6502) -> (u16, usize) {
 fn indirect(cpu: &Cpu6502) -> (u16, usize) {
     let abs_address = cpu.read_word(cpu.get_pc() + 1);
 
+    indirect_from(cpu, abs_address)
+}
+// Reads the address an indirect JMP goes to from the pointer at abs_address
+fn indirect_from(cpu: &Cpu6502, abs_address: u16) -> (u16, usize) {
     let effective_lo = cpu.read(abs_address) as u16;
     let effective_hi = if abs_address & 0xFF == 0xFF {
         cpu.read(abs_address & 0xFF00)
@@ -445,11 +531,8 @@ fn indirect_x(cpu: &Cpu6502) -> (u16, usize) {
 fn indirect_y(cpu: &Cpu6502) -> (u16, usize) {
     let zpage_address = cpu.read(cpu.get_pc() + 1);
     let abs_address = cpu.read_zpage_word(zpage_address);
-    let effective_address = abs_address.wrapping_add(cpu.get_y_reg() as u16);
-    
-    let page_boundary_crossed = (abs_address & 0xFF00) != (effective_address & 0xFF00);
 
-    (effective_address, if page_boundary_crossed { 1 } else { 0 })
+    indexed(abs_address, cpu.get_y_reg())
 }
 // Relative Addressing - Data used is next byte
 fn relative(cpu: &Cpu6502) -> (u16, usize) {
@@ -507,12 +590,13 @@ fn asl_acc(cpu: &mut Cpu6502, _address: u16) -> usize {
 }
 // ASL - Shift Left One Bit (Memory version)
 fn asl_mem(cpu: &mut Cpu6502, address: u16) -> usize {
-    let data = cpu.read(address);
-    let result = data << 1;
-    cpu.status.set_carry((data & 0x80) != 0);
-    cpu.status.set_zero(result == 0);
-    cpu.status.set_negative(result & 0x80 != 0);
-    cpu.write(address, result);
+    cpu.read_modify_write(address, |cpu, data| {
+        let result = data << 1;
+        cpu.status.set_carry((data & 0x80) != 0);
+        cpu.status.set_zero(result == 0);
+        cpu.status.set_negative(result & 0x80 != 0);
+        result
+    });
     0
 }
 // BCC - Branch on Carry Clear
@@ -636,7 +720,7 @@ fn bpl(cpu: &mut Cpu6502, address: u16) -> usize {
 }
 // BRK - Force Break (Initiate interrupt)
 fn brk(cpu: &mut Cpu6502, _address: u16) -> usize {
-    cpu.irq();
+    cpu.brk();
     0
 }
 // BVC - Branch on Overflow clear
@@ -727,12 +811,12 @@ fn cpy(cpu: &mut Cpu6502, address: u16) -> usize {
 }
 // DEC - Decrement Memory
 fn dec(cpu: &mut Cpu6502, address: u16) -> usize {
-    let data = cpu.read(address);
-
-    let result = data.wrapping_sub(1);
-    cpu.status.set_zero(result == 0);
-    cpu.status.set_negative(result & 0x80 != 0);
-    cpu.write(address, result);
+    cpu.read_modify_write(address, |cpu, data| {
+        let result = data.wrapping_sub(1);
+        cpu.status.set_zero(result == 0);
+        cpu.status.set_negative(result & 0x80 != 0);
+        result
+    });
     0
 }
 // DEX - Decrement X Register
@@ -764,16 +848,15 @@ fn eor(cpu: &mut Cpu6502, address: u16) -> usize {
 }
 // INC - Increment Memory
 fn inc(cpu: &mut Cpu6502, address: u16) -> usize {
-    let data = cpu.read(address);
-
-    // NOTE: The nesdev page on MMC1 (mapper 1) notes that the inc instr writes to
-    // memory twice. Once before the increment (with the unchanged data), and once after.
-    // This may be a source of error with mapper 1, as we aren't doing that rn.
-
-    let result = data.wrapping_add(1);
-    cpu.status.set_zero(result == 0);
-    cpu.status.set_negative(result & 0x80 != 0);
-    cpu.write(address, result);
+    // Like every read-modify-write instruction, this writes to memory twice:
+    // once with the unchanged data, and once after the increment. MMC1 games
+    // rely on this to reset the mapper with INC on a byte of ROM.
+    cpu.read_modify_write(address, |cpu, data| {
+        let result = data.wrapping_add(1);
+        cpu.status.set_zero(result == 0);
+        cpu.status.set_negative(result & 0x80 != 0);
+        result
+    });
     0
 }
 // INX - Increment X Register
@@ -848,12 +931,13 @@ fn lsr_acc(cpu: &mut Cpu6502, _address: u16) -> usize {
 }
 // LSR - Logical Shift Right (Memory version)
 fn lsr_mem(cpu: &mut Cpu6502, address: u16) -> usize {
-    let data = cpu.read(address);
-    let result = data >> 1;
-    cpu.status.set_carry(data & 0x01 == 1);
-    cpu.status.set_zero(result == 0);
-    cpu.status.set_negative(false); // result will always have bit 7 == 0
-    cpu.write(address, result);
+    cpu.read_modify_write(address, |cpu, data| {
+        let result = data >> 1;
+        cpu.status.set_carry(data & 0x01 == 1);
+        cpu.status.set_zero(result == 0);
+        cpu.status.set_negative(false); // result will always have bit 7 == 0
+        result
+    });
     0
 }
 // NOP - No Operation
@@ -875,9 +959,8 @@ fn pha(cpu: &mut Cpu6502, _address: u16) -> usize {
 }
 // PHP - Push Processor Status
 fn php(cpu: &mut Cpu6502, _address: u16) -> usize {
-    // Bit 5 (unused flag) is always set to 1 when status pushed to stack
-    // Bit 4 (break flag) is set when push to stk caused by php or brk
-    cpu.push_to_stack(cpu.get_status() | 0x30);
+    // The break flag is pushed as 1, since the push came from an instruction
+    cpu.push_to_stack(cpu.status.pushed(true));
     0
 }
 // PLA - Pull Accumulator
@@ -890,10 +973,8 @@ fn pla(cpu: &mut Cpu6502, _address: u16) -> usize {
 }
 // PLP - Pull Processor Status
 fn plp(cpu: &mut Cpu6502, _address: u16) -> usize {
-    // Bit 5 is ignored when pulling into processor status
-    // Bit 4 is cleared
-    let data = cpu.pop_from_stack() & 0xCF;
-    cpu.set_status(data | (cpu.get_status() & 0x20));
+    let data = cpu.pop_from_stack();
+    cpu.status = cpu.status.pulled(data);
     0
 }
 // ROL - Rotate Left (Accumulator version)
@@ -907,12 +988,13 @@ fn rol_acc(cpu: &mut Cpu6502, _address: u16) -> usize {
 }
 // ROL - Rotate Left (Memory version)
 fn rol_mem(cpu: &mut Cpu6502, address: u16) -> usize {
-    let data = cpu.read(address);
-    let result = (data << 1) | if cpu.status.carry() { 1 } else { 0 };
-    cpu.status.set_carry(data >> 7 == 1); // old bit 7 becomes new carry
-    cpu.status.set_zero(result == 0);
-    cpu.status.set_negative(result & 0x80 != 0);
-    cpu.write(address, result);
+    cpu.read_modify_write(address, |cpu, data| {
+        let result = (data << 1) | if cpu.status.carry() { 1 } else { 0 };
+        cpu.status.set_carry(data >> 7 == 1); // old bit 7 becomes new carry
+        cpu.status.set_zero(result == 0);
+        cpu.status.set_negative(result & 0x80 != 0);
+        result
+    });
     0
 }
 // ROR - Rotate Right (Accumulator version)
@@ -926,19 +1008,20 @@ fn ror_acc(cpu: &mut Cpu6502, _address: u16) -> usize {
 }
 // ROR - Rotate Right (Memory version)
 fn ror_mem(cpu: &mut Cpu6502, address: u16) -> usize {
-    let data = cpu.read(address);
-    let result = (if cpu.status.carry() { 1 } else { 0 } << 7) | (data >> 1);
-    cpu.status.set_carry(data & 0x01 == 1); // old bit 0 becomes new carry
-    cpu.status.set_zero(result == 0);
-    cpu.status.set_negative(result & 0x80 != 0);
-    cpu.write(address, result);
+    cpu.read_modify_write(address, |cpu, data| {
+        let result = (if cpu.status.carry() { 1 } else { 0 } << 7) | (data >> 1);
+        cpu.status.set_carry(data & 0x01 == 1); // old bit 0 becomes new carry
+        cpu.status.set_zero(result == 0);
+        cpu.status.set_negative(result & 0x80 != 0);
+        result
+    });
     0
 }
 // RTI - Return from Interrupt
 fn rti(cpu: &mut Cpu6502, _address: u16) -> usize {
-    // Restore processer status (Bit 5 ignored, bit 4 cleared)
-    let prev_status = cpu.pop_from_stack() & 0xCF;
-    cpu.set_status(prev_status | (cpu.get_status() & 0x20));
+    // Restore processer status
+    let prev_status = cpu.pop_from_stack();
+    cpu.status = cpu.status.pulled(prev_status);
     // Return to previous PC
     let lo = cpu.pop_from_stack() as u16;
     let hi = cpu.pop_from_stack() as u16;
@@ -1059,6 +1142,15 @@ fn tya(cpu: &mut Cpu6502, _address: u16) -> usize {
 //                  Placeholder for all unimplemented illegal opcodes.
 fn xxx(_: &mut Cpu6502, _address: u16) -> usize { 0 }
 
+// JAM - Locks up the CPU until it's reset. The real 6502 gets stuck with
+// $FFFF on the address bus and won't even respond to interrupts.
+// https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+fn jam(cpu: &mut Cpu6502, _address: u16) -> usize {
+    // PC has already moved past the opcode
+    cpu.jam(cpu.get_pc().wrapping_sub(1));
+    0
+}
+
 
 // LAX - Load Accumulator and X Register
 fn lax(cpu: &mut Cpu6502, address: u16) -> usize {
@@ -1147,39 +1239,110 @@ fn arr(cpu: &mut Cpu6502, address: u16) -> usize {
     0
 }
 
-// LXA - Load Accumulator and Index Register X From Memory
+/*
+ON HOW THE UNSTABLE OPCODES WORK:
+
+A handful of illegal opcodes mix the registers together on the bus in ways that
+depend on analog details of the chip, so in theory they aren't deterministic.
+In practice the NES's 2A03 behaves the same way almost all the time, and that's
+what test ROMs (and the few games that use them) expect:
+
+  XAA/LXA - AND the immediate with (A | magic), where magic depends on the chip.
+            $EE for XAA and $FF for LXA are the values tests accept.
+  SHA/SHX/SHY/TAS - The value stored is also ANDed with the high byte of the
+            base address plus one (H+1). If indexing crossed a page, the high
+            byte of the address that gets written to is replaced by that value.
+https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+https://www.nesdev.org/wiki/Programming_with_unofficial_opcodes
+*/
+
+// LXA - AND Immediate with Accumulator then Load Into Accumulator and X
 fn lxa(cpu: &mut Cpu6502, address: u16) -> usize {
-    // const RAND_CONST: u8 = 0xEE; // This instruction is highly unstable, and
-                                 // this constant may take on a value of 00, FF, 
-                                 // EE, etc. depending on the state of the
-                                 // device (like temperature)   
+    const MAGIC: u8 = 0xFF;
 
-    // let result = (cpu.get_acc() | RAND_CONST) & cpu.get_x_reg();
-    // cpu.status.set_zero(result == 0);
-    // cpu.status.set_negative(result & 0x80 != 0);
-    // cpu.set_acc(result);
+    let result = (cpu.get_acc() | MAGIC) & cpu.read(address);
 
+    cpu.status.set_zero(result == 0);
+    cpu.status.set_negative(result & 0x80 != 0);
+    cpu.set_acc(result);
+    cpu.set_x_reg(result);
     0
 }
 
-// SHY - Store Index Register Y Bitwise AND Value
-fn shy(cpu: &mut Cpu6502, address: u16) -> usize {
-    let data = (address.wrapping_sub(cpu.get_x_reg() as u16) >> 8) as u8; // undo shift from x offset
+// XAA - Transfer X to Accumulator then AND with Immediate
+fn xaa(cpu: &mut Cpu6502, address: u16) -> usize {
+    const MAGIC: u8 = 0xEE;
 
-    let result = cpu.get_y_reg() & data.wrapping_add(1);
+    let result = (cpu.get_acc() | MAGIC) & cpu.get_x_reg() & cpu.read(address);
+
+    cpu.status.set_zero(result == 0);
+    cpu.status.set_negative(result & 0x80 != 0);
+    cpu.set_acc(result);
+    0
+}
+
+// LAS - AND Memory with Stack Pointer then Load Into Accumulator, X, and Stack Pointer
+fn las(cpu: &mut Cpu6502, address: u16) -> usize {
+    let result = cpu.read(address) & cpu.get_sp();
+
+    cpu.status.set_zero(result == 0);
+    cpu.status.set_negative(result & 0x80 != 0);
+    cpu.set_acc(result);
+    cpu.set_x_reg(result);
+    cpu.set_sp(result);
+    0
+}
+
+// SBX - AND Accumulator with X then Subtract Immediate (without borrow) Into X
+fn sbx(cpu: &mut Cpu6502, address: u16) -> usize {
+    let data = cpu.read(address);
+    let and_result = cpu.get_acc() & cpu.get_x_reg();
+    let result = and_result.wrapping_sub(data);
+
+    // Flags are set like CMP, the old carry doesn't affect the result
+    cpu.status.set_carry(and_result >= data);
+    cpu.status.set_zero(result == 0);
+    cpu.status.set_negative(result & 0x80 != 0);
+    cpu.set_x_reg(result);
+    0
+}
+
+// Shared by the SH* stores. `index` is the register that was added to the base
+// address, so the base's high byte can be recovered from the effective address.
+fn unstable_store(cpu: &mut Cpu6502, address: u16, index: u8, value: u8) {
+    let base = address.wrapping_sub(index as u16);
+    let result = value & ((base >> 8) as u8).wrapping_add(1);
+
+    let address = if (base & 0xFF00) != (address & 0xFF00) {
+        ((result as u16) << 8) | (address & 0x00FF)
+    } else {
+        address
+    };
 
     cpu.write(address, result);
+}
 
+// SHY - Store Index Register Y Bitwise AND Value
+fn shy(cpu: &mut Cpu6502, address: u16) -> usize {
+    unstable_store(cpu, address, cpu.get_x_reg(), cpu.get_y_reg());
     0
 }
 
 // SHX - Store Index Register X Bitwise AND Value
 fn shx(cpu: &mut Cpu6502, address: u16) -> usize {
-    let data = (address.wrapping_sub(cpu.get_y_reg() as u16) >> 8) as u8; // undo shift from x offset
-
-    let result = cpu.get_x_reg() & data.wrapping_add(1);
+    unstable_store(cpu, address, cpu.get_y_reg(), cpu.get_x_reg());
+    0
+}
 
-    cpu.write(address, result);
+// SHA - Store Accumulator AND X AND Value
+fn sha(cpu: &mut Cpu6502, address: u16) -> usize {
+    unstable_store(cpu, address, cpu.get_y_reg(), cpu.get_acc() & cpu.get_x_reg());
+    0
+}
 
+// TAS - Transfer Accumulator AND X to Stack Pointer, then Store Like SHA
+fn tas(cpu: &mut Cpu6502, address: u16) -> usize {
+    cpu.set_sp(cpu.get_acc() & cpu.get_x_reg());
+    unstable_store(cpu, address, cpu.get_y_reg(), cpu.get_sp());
     0
 }
\ No newline at end of file
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+
+use super::cpu::Interrupt;
+
+// How many interrupts the log keeps around
+pub const INTERRUPT_LOG_SIZE: usize = 64;
+
+/*
+ON HOW INTERRUPTS ARE LOGGED
+
+Four things can interrupt the CPU: the PPU's NMI at the start of vblank, and
+IRQs from the mapper (MMC3's scanline counter), the APU frame counter and the
+DMC. The IRQs all share one wire, so from the CPU's side there's no telling
+them apart, which is exactly what makes raster effects hard to debug.
+
+The log watches each source's output on its own. An interrupt is logged when a
+source's output goes from off to on, along with where the PPU was at the time.
+It stays pending until either the CPU runs the interrupt sequence for it
+(serviced), or the source stops requesting it first (ignored). An IRQ is
+ignored when the game has interrupts disabled and acknowledges the IRQ by
+polling the device's registers instead, or when a game turns on an IRQ it
+never meant to take. An NMI is only ignored when it ends before the CPU gets
+to it, which happens when the CPU has jammed.
+https://www.nesdev.org/wiki/CPU_interrupts
+*/
+
+/// The devices that can interrupt the CPU
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum InterruptSource {
+    // The PPU's vblank NMI
+    Nmi,
+    MapperIrq,
+    // The APU frame counter's IRQ
+    FrameIrq,
+    DmcIrq,
+}
+
+impl InterruptSource {
+    pub const ALL: [InterruptSource; 4] = [
+        InterruptSource::Nmi, InterruptSource::MapperIrq,
+        InterruptSource::FrameIrq, InterruptSource::DmcIrq,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InterruptSource::Nmi => "NMI",
+            InterruptSource::MapperIrq => "Mapper IRQ",
+            InterruptSource::FrameIrq => "Frame IRQ",
+            InterruptSource::DmcIrq => "DMC IRQ",
+        }
+    }
+
+    // The kind of interrupt the CPU runs for this source
+    fn interrupt(self) -> Interrupt {
+        match self {
+            InterruptSource::Nmi => Interrupt::Nmi,
+            _ => Interrupt::Irq,
+        }
+    }
+}
+
+/// What became of a logged interrupt
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum InterruptOutcome {
+    // Still being requested, and the CPU hasn't taken it yet
+    Pending,
+    Serviced,
+    // The source stopped requesting it before the CPU took it
+    Ignored,
+}
+
+impl InterruptOutcome {
+    pub fn name(self) -> &'static str {
+        match self {
+            InterruptOutcome::Pending => "pending",
+            InterruptOutcome::Serviced => "serviced",
+            InterruptOutcome::Ignored => "ignored",
+        }
+    }
+}
+
+/// An interrupt request, with the frame, scanline and dot it started on
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct InterruptEvent {
+    pub source: InterruptSource,
+    pub frame: u64,
+    pub scanline: usize,
+    pub dot: usize,
+    pub outcome: InterruptOutcome,
+}
+
+/// The most recent interrupts (see "ON HOW INTERRUPTS ARE LOGGED")
+#[derive(Default)]
+pub struct InterruptLog {
+    events: VecDeque<InterruptEvent>,
+    // Each source's output as of the last update, indexed like
+    // InterruptSource::ALL
+    lines: [bool; 4],
+}
+
+impl InterruptLog {
+    /// Updates the log with each source's output (in the order of
+    /// InterruptSource::ALL) and the interrupt the CPU started running since
+    /// the last update, if it did.
+    pub fn update(&mut self, lines: [bool; 4], serviced: Option<Interrupt>,
+            frame: u64, (dot, scanline): (usize, usize)) {
+        for (i, source) in InterruptSource::ALL.into_iter().enumerate() {
+            if lines[i] && !self.lines[i] {
+                if self.events.len() >= INTERRUPT_LOG_SIZE {
+                    self.events.pop_front();
+                }
+                self.events.push_back(InterruptEvent {
+                    source, frame, scanline, dot, outcome: InterruptOutcome::Pending,
+                });
+            }
+        }
+
+        // An NMI can be taken on the same clock it starts (when it hijacks a
+        // BRK or IRQ), so this goes after the new ones are logged
+        if let Some(interrupt) = serviced {
+            for event in self.events.iter_mut() {
+                if event.outcome == InterruptOutcome::Pending && event.source.interrupt() == interrupt {
+                    event.outcome = InterruptOutcome::Serviced;
+                }
+            }
+        }
+
+        for (i, source) in InterruptSource::ALL.into_iter().enumerate() {
+            if !lines[i] && self.lines[i] {
+                for event in self.events.iter_mut().filter(|event| event.source == source) {
+                    if event.outcome == InterruptOutcome::Pending {
+                        event.outcome = InterruptOutcome::Ignored;
+                    }
+                }
+            }
+        }
+
+        self.lines = lines;
+    }
+
+    /// The logged interrupts, oldest first
+    pub fn events(&self) -> &VecDeque<InterruptEvent> {
+        &self.events
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.lines = [false; 4];
+    }
+}
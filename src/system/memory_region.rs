@@ -0,0 +1,116 @@
+use crate::cartridge::Mapper;
+
+/*
+ON HOW MEMORY DUMPS WORK:
+
+The debugger can write the memory behind the picture and the game's save data
+out to plain binary files, and load them back in while the game is running.
+That makes it easy to look at graphics in a tile editor, diff a nametable
+between two frames, or patch a palette and see what it does without touching
+the ROM. Each region is dumped as the raw bytes, with nothing added:
+
+    prgram   $6000-$7FFF as the CPU sees it (8KiB of cartridge work RAM)
+    chrram   $0000-$1FFF as the PPU sees it (8KiB of CHR RAM, through the
+             mapper's banks). Only for carts with CHR RAM, CHR ROM can't be
+             written.
+    vram     the console's 2KiB of nametable RAM, before mirroring
+    oam      the 256 bytes of sprite memory
+    palette  the 32 bytes of palette RAM
+
+PRG RAM and CHR RAM go through the mapper, so RAM the mapper has switched off
+(or write-protected) can't be dumped (or restored), and only the banks switched
+in right now are seen. A file has to be exactly the size of the region to be
+loaded back.
+https://www.nesdev.org/wiki/CPU_memory_map
+https://www.nesdev.org/wiki/PPU_memory_map
+*/
+
+/// A part of the NES's memory that can be dumped and restored (see "ON HOW
+/// MEMORY DUMPS WORK")
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryRegion {
+    PrgRam,
+    ChrRam,
+    Vram,
+    Oam,
+    Palette,
+}
+
+impl MemoryRegion {
+    pub const ALL: [MemoryRegion; 5] = [Self::PrgRam, Self::ChrRam, Self::Vram, Self::Oam, Self::Palette];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|region| region.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PrgRam => "prgram",
+            Self::ChrRam => "chrram",
+            Self::Vram => "vram",
+            Self::Oam => "oam",
+            Self::Palette => "palette",
+        }
+    }
+
+    /// The size of the region in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::PrgRam | Self::ChrRam => 0x2000,
+            Self::Vram => 0x800,
+            Self::Oam => 0x100,
+            Self::Palette => 0x20,
+        }
+    }
+}
+
+/*
+ON THE CPU MEMORY MAP:
+
+The debugger's memory view labels each part of the CPU's address space with
+what's really there, and greys out the parts that are only mirrors:
+
+    $0000-$07FF  RAM             the console's 2KiB of internal RAM
+    $0800-$1FFF  RAM mirror      the same 2KiB three more times, since the
+                                 RAM only looks at 11 address lines
+    $2000-$2007  PPU regs        the PPU's eight registers
+    $2008-$3FFF  PPU reg mirror  the same registers every 8 bytes
+    $4000-$401F  APU/IO          the APU, OAM DMA and the controller ports
+    $4020-$FFFF  cartridge       whatever the mapper says (see
+                                 Mapper::describe_region): usually SRAM at
+                                 $6000 and PRG ROM banks from $8000, or open
+                                 bus where the board has nothing
+https://www.nesdev.org/wiki/CPU_memory_map
+*/
+
+/// What's at an address in the CPU's memory map, for the debugger's memory
+/// view (see "ON THE CPU MEMORY MAP")
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CpuRegion {
+    pub name: String,
+    // Whether the address is a mirror of memory that's also lower down
+    pub mirror: bool,
+}
+
+impl CpuRegion {
+    /// Describes the address, asking the mapper about cartridge space (or
+    /// calling it open bus when there's no cartridge)
+    pub fn at(address: u16, mapper: Option<&dyn Mapper>) -> Self {
+        let (name, mirror) = match address {
+            0x0000..=0x07FF => ("RAM", false),
+            0x0800..=0x1FFF => ("RAM mirror", true),
+            0x2000..=0x2007 => ("PPU regs", false),
+            0x2008..=0x3FFF => ("PPU reg mirror", true),
+            0x4000..=0x401F => ("APU/IO", false),
+            _ => {
+                let name = mapper
+                    .and_then(|mapper| mapper.describe_region(address))
+                    .unwrap_or_else(|| String::from("Open bus"));
+
+                return Self { name, mirror: false };
+            }
+        };
+
+        Self { name: String::from(name), mirror }
+    }
+}
@@ -1,9 +1,25 @@
+pub mod agent;
+pub mod breakpoints;
+pub mod bus;
 pub mod cpu;
 pub mod ppu;
 mod ppu_util;
 pub mod nes;
-mod nes_graphics;
+pub mod nes_graphics;
 mod instructions;
 pub mod controller;
 pub mod apu;
-pub mod apu_util;
\ No newline at end of file
+pub mod apu_util;
+pub mod sample_ring;
+pub mod savestate;
+pub mod memory_region;
+pub mod movie;
+pub mod cheats;
+pub mod checksum;
+pub mod events;
+pub mod interrupt_log;
+pub mod telemetry;
+pub mod trace;
+pub mod video_filter;
+mod test_pattern;
+
@@ -0,0 +1,300 @@
+use std::fs;
+
+use super::{controller::NesController, nes::Nes, savestate::SaveState};
+
+// Order the buttons are written in each port of an input log line
+// https://fceux.com/web/help/fm2.html
+const FM2_BUTTON_ORDER: [char; 8] = ['R', 'L', 'D', 'U', 'T', 'S', 'B', 'A'];
+
+// How a savestate anchor is marked in the savestate header line
+const FM2_BASE64_PREFIX: &str = "base64:";
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/*
+ON HOW MOVIES WORK:
+
+A movie is the buttons held on both controllers for every frame, starting from
+power on, or from a save state kept in the movie (its "anchor"). Playing one
+back from the same start gives the same run, frame for frame, since the NES
+only ever changes because of its input.
+
+Movies are FCEUX .fm2 files: a header of "key value" lines, then one line per
+frame with both ports' buttons. A movie with an anchor has a savestate line,
+with the state base64 encoded the way FCEUX does it. The state is this
+emulator's own though, so FCEUX can't play movies that start from one (and
+this can't play FCEUX's).
+https://fceux.com/web/help/fm2.html
+
+Recording can be picked back up partway through playing a movie back (read+
+write mode): the rest of the movie is thrown away, and recording carries on
+from the frame it's on. Each time counts as a rerecord, which is kept in the
+header like in FCEUX.
+*/
+
+/// An input movie (see "ON HOW MOVIES WORK")
+#[derive(Debug, Default, Clone)]
+pub struct Movie {
+    pub rom_name: String,
+    // Both controllers' buttons for each frame
+    frames: Vec<[NesController; 2]>,
+    // The state the movie starts from, when it doesn't start from power on
+    anchor: Option<SaveState>,
+    rerecords: u32,
+}
+
+impl Movie {
+    pub fn new(rom_name: &str) -> Self {
+        Self {
+            rom_name: String::from(rom_name),
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Adds both controllers' state for the next frame to the end of the movie
+    pub fn push_frame(&mut self, inputs: [NesController; 2]) {
+        self.frames.push(inputs);
+    }
+
+    /// Gets both controllers' state for the given frame, or None if the movie
+    /// has ended.
+    pub fn frame(&self, frame: usize) -> Option<[NesController; 2]> {
+        self.frames.get(frame).copied()
+    }
+
+    /// The state the movie starts from, if it doesn't start from power on
+    pub fn anchor(&self) -> Option<&SaveState> {
+        self.anchor.as_ref()
+    }
+
+    /// Has the movie start from a state instead of from power on
+    pub fn set_anchor(&mut self, state: SaveState) {
+        self.anchor = Some(state);
+    }
+
+    /// Number of times recording was picked back up partway through
+    pub fn rerecords(&self) -> u32 {
+        self.rerecords
+    }
+
+    /// Throws away everything from the given frame on, so recording can
+    /// carry on from there (read+write mode)
+    pub fn resume_recording(&mut self, frame: usize) {
+        self.frames.truncate(frame);
+        self.rerecords += 1;
+    }
+
+    /// Gets a freshly powered on NES to where the movie starts, by loading
+    /// the anchor if it has one
+    pub fn start(&self, nes: &mut Nes) -> Result<(), String> {
+        match &self.anchor {
+            Some(state) => nes.load_state(state).map_err(|e| format!("Couldn't load the movie's save state: {e}")),
+            None => Ok(()),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read movie '{path}': {e}"))?;
+
+        Self::from_fm2(&text)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_fm2())
+            .map_err(|e| format!("Couldn't write movie '{path}': {e}"))
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::with_capacity(200 + self.frames.len() * 24);
+
+        text.push_str("version 3\n");
+        text.push_str("emuVersion 0\n");
+        text.push_str(&format!("rerecordCount {}\n", self.rerecords));
+        text.push_str("palFlag 0\n");
+        text.push_str(&format!("romFilename {}\n", self.rom_name));
+        text.push_str("fourscore 0\n");
+        text.push_str("port0 1\n");
+        text.push_str("port1 1\n");
+        text.push_str("port2 0\n");
+        if let Some(state) = &self.anchor {
+            text.push_str(&format!("savestate {FM2_BASE64_PREFIX}{}\n", base64_encode(state.as_bytes())));
+        }
+
+        for [port0, port1] in &self.frames {
+            text.push_str("|0|");
+            text.push_str(&Self::port_str(*port0));
+            text.push('|');
+            text.push_str(&Self::port_str(*port1));
+            text.push_str("||\n");
+        }
+
+        text
+    }
+
+    pub fn from_fm2(text: &str) -> Result<Self, String> {
+        let mut movie = Movie::default();
+
+        for (line_num, line) in text.lines().enumerate() {
+            let bad_line = || format!("Bad input on line {} of movie: '{line}'", line_num + 1);
+
+            // Input log lines start with a pipe, everything else is a header
+            if let Some(fields) = line.strip_prefix('|') {
+                let mut fields = fields.split('|');
+                let _commands = fields.next();
+                let port0 = Self::parse_port(fields.next().unwrap_or("")).ok_or_else(bad_line)?;
+                let port1 = Self::parse_port(fields.next().unwrap_or("")).ok_or_else(bad_line)?;
+
+                movie.frames.push([port0, port1]);
+            } else if let Some(name) = line.strip_prefix("romFilename ") {
+                movie.rom_name = String::from(name);
+            } else if let Some(count) = line.strip_prefix("rerecordCount ") {
+                movie.rerecords = count.trim().parse().unwrap_or(0);
+            } else if let Some(state) = line.strip_prefix("savestate ") {
+                // FCEUX can write hex states too, which are its own format
+                // anyway, so only base64 ones are looked at
+                let encoded = state.trim().strip_prefix(FM2_BASE64_PREFIX)
+                    .ok_or("Movie's save state isn't base64 (it may be from another emulator)")?;
+                let bytes = base64_decode(encoded).ok_or("Movie's save state is corrupt")?;
+
+                movie.anchor = Some(SaveState::from_bytes(bytes));
+            }
+        }
+
+        Ok(movie)
+    }
+
+    fn port_str(controller: NesController) -> String {
+        let pressed = [
+            controller.right(), controller.left(), controller.down(), controller.up(),
+            controller.start(), controller.select(), controller.b(), controller.a(),
+        ];
+
+        FM2_BUTTON_ORDER.iter()
+            .zip(pressed)
+            .map(|(c, p)| if p { *c } else { '.' })
+            .collect()
+    }
+
+    fn parse_port(port: &str) -> Option<NesController> {
+        // Ports that aren't plugged in are left empty
+        if port.is_empty() {
+            return Some(NesController::new());
+        }
+        if port.chars().count() != 8 {
+            return None;
+        }
+
+        let mut pressed = port.chars().map(|c| c != '.' && c != ' ');
+
+        Some(NesController::new()
+            .with_right(pressed.next()?)
+            .with_left(pressed.next()?)
+            .with_down(pressed.next()?)
+            .with_up(pressed.next()?)
+            .with_start(pressed.next()?)
+            .with_select(pressed.next()?)
+            .with_b(pressed.next()?)
+            .with_a(pressed.next()?))
+    }
+}
+
+// Standard base64, with padding
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64_ALPHABET[(group >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+
+    for chunk in text.chunks(4) {
+        // A lone character can't make up a whole byte
+        if chunk.len() == 1 {
+            return None;
+        }
+
+        let mut group = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+            group |= value << (18 - 6 * i);
+        }
+
+        bytes.extend_from_slice(&group.to_be_bytes()[1..chunk.len()]);
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_round_trips() {
+        for len in 0..10 {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 37 + 200) as u8).collect();
+
+            assert_eq!(base64_decode(&base64_encode(&bytes)), Some(bytes));
+        }
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE=");
+        assert_eq!(base64_decode("TQ=="), Some(b"M".to_vec()));
+        assert_eq!(base64_decode("T!=="), None);
+    }
+
+    #[test]
+    fn both_players_and_the_anchor_are_saved() {
+        let mut movie = Movie::new("game.nes");
+        movie.set_anchor(SaveState::from_bytes(vec![1, 2, 3, 4, 5]));
+        movie.push_frame([NesController::new().with_a(true), NesController::new().with_left(true)]);
+        movie.push_frame([NesController::new(), NesController::new().with_start(true)]);
+
+        let text = movie.to_fm2();
+        assert!(text.contains("|0|.......A|.L......||\n"));
+
+        let loaded = Movie::from_fm2(&text).unwrap();
+        assert_eq!(loaded.rom_name, "game.nes");
+        assert_eq!(loaded.anchor(), Some(&SaveState::from_bytes(vec![1, 2, 3, 4, 5])));
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.frame(1).unwrap()[1].start());
+        assert!(!loaded.frame(1).unwrap()[0].start());
+    }
+
+    #[test]
+    fn resuming_recording_drops_the_rest_of_the_movie() {
+        let mut movie = Movie::new("game.nes");
+        for _ in 0..10 {
+            movie.push_frame([NesController::new(); 2]);
+        }
+
+        movie.resume_recording(4);
+        movie.push_frame([NesController::new().with_b(true), NesController::new()]);
+
+        let loaded = Movie::from_fm2(&movie.to_fm2()).unwrap();
+        assert_eq!(loaded.len(), 5);
+        assert!(loaded.frame(4).unwrap()[0].b());
+        assert_eq!(loaded.rerecords(), 1);
+    }
+}
@@ -1,106 +1,326 @@
 use std::{
-    borrow::Borrow, cell::{Ref, RefCell, RefMut}, collections::VecDeque, fs, io::Read, rc::Rc, sync::{Arc, Mutex}
+    borrow::Borrow, cell::{Ref, RefCell, RefMut}, fs, rc::Rc, sync::Arc
 };
 
-use crate::cartridge::{cartridge::Cartridge, mapper::{self, Mapper, NametableMirror}};
+use crate::cartridge::{archive, cartridge::{CartInfo, Cartridge}, hash::{self, RomHashes}, mapper::{self, Mapper, NametableMirror}, patch};
 
 use super::{
-    apu::Apu2A03,
-    controller::{ControllerButton, ControllerUpdate, NesController},
-    cpu::{Cpu6502, CpuState},
-    ppu::Ppu2C02,
+    apu::{Apu2A03, ApuWrite, SampleRate, APU_STATE_MAGIC},
+    apu_util::{ChannelMask, Mixer, NesChannel, Synthesis},
+    breakpoints::WriteBreakpoint,
+    bus::{Bus, NesBus, RamFill},
+    cheats::RamFreeze,
+    checksum::StateChecksum,
+    controller::{ControllerButton, ControllerUpdate, DeviceSlot, ExpansionDevice, ExpansionInput, NesController, SharedDevice, StandardController},
+    cpu::{Cpu6502, CpuMode, CpuState, Interrupt, CPU_STATE_MAGIC},
+    events::{EventKind, NesEvent, SharedEventBus, Subscription},
+    interrupt_log::{InterruptEvent, InterruptLog},
+    memory_region::{CpuRegion, MemoryRegion},
+    nes_graphics::{self, ColorFilter, NesColor, SystemPalette, DEFAULT_PALETTE},
+    ppu_util::ScrollSplit,
+    ppu::{Ppu2C02, RasterWrite, SpriteEvaluation, PPU_STATE_MAGIC},
+    sample_ring::{SampleQueue, SampleRing, SAMPLE_RING_CAPACITY},
+    savestate::{SaveState, StateReader, StateWriter},
+    telemetry::UnsupportedUse,
+    test_pattern,
+    trace::TraceEntry,
 };
 
+const NES_STATE_MAGIC: &[u8; 4] = b"NES ";
+const NES_STATE_VERSION: u16 = 1;
+
 pub const NES_SCREEN_WIDTH: usize = 256;
 pub const NES_SCREEN_HEIGHT: usize = 240;
 
 // times 4 bc there are 4 colors per pixel: R, G, B, A
 pub const NES_SCREEN_BUF_SIZE: usize = NES_SCREEN_WIDTH * NES_SCREEN_HEIGHT * 4;
 
+/// A finished frame: NES_SCREEN_WIDTH x NES_SCREEN_HEIGHT pixels of 4 bytes
+/// each (R, G, B, A), row by row starting from the top left
+#[derive(Clone, Copy)]
+pub struct Frame<'a> {
+    pixels: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    pub const WIDTH: usize = NES_SCREEN_WIDTH;
+    pub const HEIGHT: usize = NES_SCREEN_HEIGHT;
+
+    pub fn pixels(&self) -> &'a [u8] {
+        self.pixels
+    }
+
+    /// The color of the pixel at (x, y) as [R, G, B, A]
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * Self::WIDTH + x) * 4;
+
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+}
+
 pub struct Nes {
     cpu: Option<Cpu6502>,
-    apu: Option<Rc<RefCell<Apu2A03>>>,
+    // The APU sticks around between carts so its settings and sample queue
+    // do too (see Apu2A03::power_on)
+    apu: Rc<RefCell<Apu2A03>>,
     ppu: Option<Rc<RefCell<Ppu2C02>>>,
     mapper: Option<Rc<RefCell<dyn Mapper>>>,
 
     p1_controller: NesController,
     p2_controller: NesController,
-
-    // The screen buffer currently being drawn to by the ppu
-    screen_buf1: Box<[u8; NES_SCREEN_BUF_SIZE]>,
-    // The screen buffer currently being rendered by the app
-    screen_buf2: Box<[u8; NES_SCREEN_BUF_SIZE]>,
+    // What's plugged into each slot (indexed by DeviceSlot), and the input
+    // for the expansion port. Kept here so they survive loading another game.
+    devices: [SharedDevice; 3],
+    expansion_device: ExpansionDevice,
+    expansion_input: ExpansionInput,
+
+    // RAM held at a value after every frame (see cheats)
+    freezes: Vec<RamFreeze>,
+
+    // Shared with the PPU and APU (see events). frame_events is the NES's
+    // own subscription, for finding the end of the frame.
+    events: SharedEventBus,
+    frame_events: Subscription,
+    // The mapper's IRQ output as of the last clock, so MapperIrq is only
+    // published when it's raised
+    mapper_irq_line: bool,
+
+    // Only kept while someone is looking at it (see interrupt_log)
+    interrupt_log: InterruptLog,
+    interrupt_logging: bool,
+
+    // The last frame the PPU finished, the one being rendered by the app (the
+    // PPU has the one it's drawing)
+    screen_buf: Box<[u8; NES_SCREEN_BUF_SIZE]>,
 
     clocks: u64,
+    // Number of frames run since the cart was loaded
+    frames: u64,
+
+    // Accuracy option: let DMC DMA corrupt controller reads like it does on
+    // the real console (see Cpu6502::dmc_dma)
+    dmc_input_glitch: bool,
+    // Accuracy option: how sprites are picked for each scanline (see
+    // SpriteEvaluation). Kept here so it survives loading another game.
+    sprite_evaluation: SpriteEvaluation,
+    // Performance option: cache decoded instructions (see CpuMode). Kept
+    // here too so it survives loading another game.
+    cpu_mode: CpuMode,
+
+    // Developer option: flag PPU register writes made mid-frame (see
+    // RasterWrite). Kept here too so it survives loading another game.
+    register_write_assert: bool,
+
+    // Debug option: stop when the CPU writes certain values (see
+    // breakpoints). Kept here so they survive loading another game.
+    // break_events is the NES's own subscription, for stopping when one
+    // is hit.
+    write_breakpoints: Vec<WriteBreakpoint>,
+    break_events: Subscription,
+
+    // Debug options: leave the background or sprites out of the picture (see
+    // Ppu2C02::set_bg_hidden). Kept here so they survive loading another game.
+    bg_hidden: bool,
+    sprites_hidden: bool,
+
+    // Power on state, used whenever a cart is loaded (see RamFill and
+    // Ppu2C02::set_warmup)
+    ram_fill: RamFill,
+    ppu_warmup: bool,
+
+    // The system palette (see load_palette) and the filter the PPU gets it
+    // through. Kept here so they survive loading another game.
+    system_palette: SystemPalette,
+    color_filter: ColorFilter,
 
     cart_loaded: bool,
+    // Name of the loaded game, taken from the ROM's filename
+    rom_name: String,
+    // CRC-32 of the ROM (everything after the header), used to identify games
+    rom_crc32: u32,
+    // Filled in by whoever hashed the ROM, see set_rom_hashes
+    rom_hashes: Option<RomHashes>,
+    cart_info: Option<CartInfo>,
 }
 
 impl Default for Nes {
     fn default() -> Self {
+        let events = SharedEventBus::default();
+        let frame_events = events.borrow_mut().subscribe(&[EventKind::FrameReady]);
+        let break_events = events.borrow_mut().subscribe(&[EventKind::WriteBreak]);
+
+        let mut apu = Apu2A03::new(Arc::new(SampleRing::new(SAMPLE_RING_CAPACITY)));
+        apu.set_event_bus(Rc::clone(&events));
+
         Nes {
             cpu: None,
-            apu: None,
+            apu: Rc::new(RefCell::new(apu)),
             ppu: None,
             mapper: None,
 
             p1_controller: NesController::default(),
             p2_controller: NesController::default(),
+            devices: [
+                Rc::new(RefCell::new(StandardController::default())),
+                Rc::new(RefCell::new(StandardController::default())),
+                ExpansionDevice::None.create(),
+            ],
+            expansion_device: ExpansionDevice::None,
+            expansion_input: ExpansionInput::default(),
+
+            freezes: Vec::new(),
 
-            screen_buf1: Box::new([0; NES_SCREEN_BUF_SIZE]),
-            screen_buf2: Box::new([0; NES_SCREEN_BUF_SIZE]),
+            events,
+            frame_events,
+            mapper_irq_line: false,
+
+            interrupt_log: InterruptLog::default(),
+            interrupt_logging: false,
+
+            screen_buf: Box::new([0; NES_SCREEN_BUF_SIZE]),
 
             clocks: 0,
+            frames: 0,
+
+            dmc_input_glitch: false,
+
+            register_write_assert: false,
+            sprite_evaluation: SpriteEvaluation::default(),
+            cpu_mode: CpuMode::default(),
+
+            write_breakpoints: Vec::new(),
+            break_events,
+
+            bg_hidden: false,
+            sprites_hidden: false,
+
+            ram_fill: RamFill::default(),
+            ppu_warmup: false,
+
+            system_palette: nes_graphics::with_emphasis(&DEFAULT_PALETTE),
+            color_filter: ColorFilter::None,
 
             cart_loaded: false,
+            rom_name: String::new(),
+            rom_crc32: 0,
+            rom_hashes: None,
+            cart_info: None,
         }
     }
 }
 
 impl Nes {
-    /// Load a new cart into this NES object
-    pub fn load_cart(&mut self, cart_path_str: &str, sample_queue: Arc<Mutex<VecDeque<f32>>>) {
-        let mut cart_file = match fs::File::open(cart_path_str) {
-            Ok(v) => v,
-            Err(..) => panic!("Could not find file '{cart_path_str}'"),
-        };
+    /// Sends the audio to a different queue. The queue is kept when carts
+    /// are swapped, so this only needs doing once.
+    pub fn set_sample_queue(&mut self, sample_queue: SampleQueue) {
+        self.get_apu_mut().set_sample_queue(sample_queue);
+    }
 
-        let mut data = Vec::new();
+    /// Load a new cart into this NES object, taking out the one that was in
+    /// it (if any). The NES starts up as if it was just turned on. The cart
+    /// can be a .nes file or a zip with one in it (see archive), and an .ips
+    /// or .bps patch next to it is applied to it (see patch).
+    pub fn load_cart(&mut self, cart_path_str: &str) -> Result<(), String> {
+        self.load_cart_patched(cart_path_str, None)
+    }
 
-        if let Err(..) = cart_file.read_to_end(&mut data) {
-            panic!("Failed to read cartridge from '{cart_path_str}' to buffer");
+    /// Load a new cart like load_cart, applying the given IPS or BPS patch
+    /// to it, or with None, one found next to the cart
+    pub fn load_cart_patched(&mut self, cart_path_str: &str, patch_path: Option<&str>) -> Result<(), String> {
+        let (data, applied_patch) = patch::read_patched_rom(cart_path_str, patch_path)?;
+        if let Some(patch_path) = applied_patch {
+            println!("Applied patch {patch_path}");
         }
 
+        self.load_cart_bytes(&data, &archive::rom_name(cart_path_str))
+    }
+
+    /// Load a new cart into this NES object from the bytes of a .nes file.
+    /// Used where there's no file system to load from, like in a browser. If
+    /// the cart can't be loaded, the one already in is left running.
+    pub fn load_cart_bytes(&mut self, data: &[u8], rom_name: &str) -> Result<(), String> {
         // Parse cartridge from file bytes
-        let cart = Cartridge::from_bytes(data.as_slice()).unwrap();
+        let cart = Cartridge::from_bytes(data)?;
+        let cart_info = cart.info();
+        let mapper = mapper::mapper_from_cart(cart)?;
+
+        self.eject();
 
-        let mapper = mapper::mapper_from_cart(cart);
+        self.cart_info = Some(cart_info);
 
-        let apu = Apu2A03::new(sample_queue, Rc::clone(&mapper));
-        let apu = Rc::new(RefCell::new(apu));
+        self.rom_crc32 = hash::crc32(&data[Cartridge::HEADER_LEN..]);
 
-        let ppu = Ppu2C02::new(Rc::clone(&mapper));
+        self.get_apu_mut().power_on(Some(Rc::clone(&mapper)));
+
+        let mut ppu = Ppu2C02::new(Rc::clone(&mapper));
+        ppu.set_write_assert(self.register_write_assert);
+        ppu.set_bg_hidden(self.bg_hidden);
+        ppu.set_sprites_hidden(self.sprites_hidden);
+        ppu.set_warmup(self.ppu_warmup);
+        ppu.set_sprite_evaluation(self.sprite_evaluation);
+        ppu.start_warmup();
+        ppu.set_system_palette(self.color_filter.apply(&self.system_palette));
+        ppu.set_event_bus(Rc::clone(&self.events));
         let ppu = Rc::new(RefCell::new(ppu));
 
-        let cpu = Cpu6502::new(
+        let mut bus = NesBus::new(
             Rc::clone(&ppu), 
-            Rc::clone(&apu),
+            Rc::clone(&self.apu),
             Rc::clone(&mapper));
+        bus.fill_ram(self.ram_fill);
+        for slot in DeviceSlot::ALL {
+            bus.plug_device(slot, Rc::clone(&self.devices[slot as usize]));
+        }
+
+        let mut cpu = Cpu6502::new(Box::new(bus));
+        cpu.set_write_breakpoints(self.write_breakpoints.clone());
+        cpu.set_mode(self.cpu_mode);
 
         self.cpu = Some(cpu);
-        self.apu = Some(apu);
         self.ppu = Some(ppu);
         self.mapper = Some(mapper);
 
         self.cart_loaded = true;
+        self.rom_name = String::from(rom_name);
+
+        Ok(())
+    }
+
+    /// Loads the built-in test pattern cart in place of a game, which draws
+    /// every color the PPU can put out (see test_pattern)
+    pub fn load_test_pattern(&mut self) -> Result<(), String> {
+        self.load_cart_bytes(&test_pattern::rom(), test_pattern::ROM_NAME)?;
+        self.get_ppu_mut().set_test_pattern(true);
+
+        Ok(())
     }
 
-    /// Remove the loaded cartridge from this NES
-    pub fn remove_cart(&mut self) {
+    /// Takes the cartridge out. Everything that came with it (the CPU, PPU and
+    /// mapper state) is thrown away and the screen goes black, but settings
+    /// and the audio queue stay, so another cart can be loaded straight after.
+    pub fn eject(&mut self) {
         self.cpu = None;
         self.ppu = None;
         self.mapper = None;
+        self.get_apu_mut().power_on(None);
+
+        self.screen_buf.fill(0);
+        self.clocks = 0;
+        self.frames = 0;
+
         self.cart_loaded = false;
+        self.rom_name.clear();
+        self.rom_crc32 = 0;
+        self.rom_hashes = None;
+        self.cart_info = None;
+        self.freezes.clear();
+        self.interrupt_log.clear();
+        self.events.borrow_mut().take(self.frame_events);
+        self.mapper_irq_line = false;
+    }
+
+    pub fn cart_loaded(&self) -> bool {
+        self.cart_loaded
     }
 
     /// Manually set the state of the CPU
@@ -127,12 +347,28 @@ impl Nes {
         }
     }
 
+    /// Presses or releases one button right away. Frontends that run a frame
+    /// at a time should hand over the whole frame's input with advance_frame
+    /// instead.
     pub fn update_controllers(&mut self, update: ControllerUpdate) {
-        match update.player_id {
+        let player_id = update.player_id;
+
+        match player_id {
             0 => Nes::update_controller_state(&mut self.p1_controller, update),
             1 => Nes::update_controller_state(&mut self.p2_controller, update),
             _ => {}
         }
+
+        self.send_buttons(player_id);
+    }
+
+    // Hands a player's buttons to whatever's plugged into their port
+    fn send_buttons(&mut self, player_id: usize) {
+        let buttons = self.controller_state(player_id);
+
+        if let Some(device) = self.devices.get(player_id) {
+            device.borrow_mut().set_buttons(buttons);
+        }
     }
 
     fn update_controller_state(controller: &mut NesController, update: ControllerUpdate) {
@@ -168,20 +404,26 @@ impl Nes {
         self.ppu.as_ref().unwrap().as_ref().borrow_mut()
     }
 
-    // Get a reference to the APU. Does not check if a cart is loaded.
+    // Get a reference to the APU. There always is one, even with no cart in.
     pub fn get_apu(&self) -> Ref<Apu2A03> {
-        self.apu.as_ref().unwrap().as_ref().borrow()
+        self.apu.as_ref().borrow()
     }
-    // Get a mutable reference to the APU. Does not check if a cart is loaded.
+    // Get a mutable reference to the APU. There always is one, even with no cart in.
     pub fn get_apu_mut(&mut self) -> RefMut<Apu2A03> {
-        self.apu.as_ref().unwrap().as_ref().borrow_mut()
+        self.apu.as_ref().borrow_mut()
     }
 
     pub fn get_mapper(&self) -> Ref<dyn Mapper> {
         self.mapper.as_ref().unwrap().as_ref().borrow()
     }
 
+    /// Get a mutable reference to the mapper. Anything could be changed
+    /// through it (like the PRG banks), so the CPU forgets what it's decoded.
     pub fn get_mapper_mut(&mut self) -> RefMut<dyn Mapper> {
+        if let Some(cpu) = self.cpu.as_mut() {
+            cpu.forget_decoded();
+        }
+
         self.mapper.as_ref().unwrap().as_ref().borrow_mut()
     }
 
@@ -194,22 +436,38 @@ impl Nes {
         }
     }
 
+    /// Address of the JAM opcode the CPU locked up on, if it has. The NES
+    /// won't do anything useful again until it's reset.
+    pub fn cpu_jammed_at(&self) -> Option<u16> {
+        self.cpu.as_ref().and_then(|cpu| cpu.jammed_at())
+    }
+
+    /// Presses the reset button. Unlike loading a cart (power on), RAM and
+    /// most of the mapper and APU state are left as they were, so this is what
+    /// games expect from a soft reset:
+    ///  * The CPU drops what it was doing, including any DMA stall, and jumps
+    ///    through the reset vector
+    ///  * The APU is silenced as if $4015 was written with 0, and its frame
+    ///    counter starts over (see Apu2A03::reset)
+    ///  * The PPU's control registers are cleared and its warm-up period
+    ///    starts over, if that's turned on (see Ppu2C02::reset)
+    ///  * The mapper gets told through Mapper::on_reset
+    ///
+    /// https://www.nesdev.org/wiki/CPU_power_up_state
     pub fn reset(&mut self) {
         self.get_cpu_mut().reset();
+        self.get_apu_mut().reset();
         self.get_ppu_mut().reset();
-        self.get_mapper_mut().reset();
+        self.get_mapper_mut().on_reset();
+
+        self.events.borrow_mut().publish(NesEvent::Reset);
     }
 
     // Cycles the system through one system clock. The PPU will cycle, the CPU
     // might cycle (CPU cycles every 3 PPU cycles). Returns a bool reporting
     // whether the CPU was cycled.
     pub fn cycle(&mut self) -> bool {
-        self.ppu
-            .as_ref()
-            .unwrap()
-            .as_ref()
-            .borrow_mut()
-            .cycle(self.screen_buf1.as_mut_slice());
+        self.get_ppu_mut().cycle();
 
         let mut cpu_cycled = false;
 
@@ -217,12 +475,21 @@ impl Nes {
             // APU cycles with CPU clock
             self.get_apu_mut().cycle();
 
-            let p1_controller_state = self.p1_controller;
-            let p2_controller_state = self.p2_controller;
+            if self.get_apu_mut().take_dmc_dma() {
+                let dmc_input_glitch = self.dmc_input_glitch;
+                self.get_cpu_mut().dmc_dma(dmc_input_glitch);
+            }
+
+            if self.register_write_assert {
+                let pc = self.get_cpu().get_pc();
+                self.get_ppu_mut().set_cpu_pc(pc);
+            }
+
+            cpu_cycled = self.get_cpu_mut().cycle();
 
-            cpu_cycled = self
-                .get_cpu_mut()
-                .cycle(p1_controller_state, p2_controller_state);
+            if !self.write_breakpoints.is_empty() {
+                self.publish_write_break();
+            }
         }
 
         self.handle_cpu_interrupts();
@@ -235,29 +502,83 @@ impl Nes {
     /// Cycle the CPU until a new instruction in executed (if cart is loaded).
     /// Also cycles the PPU.
     pub fn cycle_instr(&mut self) {
+        // A jammed CPU never runs another instruction, so don't wait for one
+        if self.cpu_jammed_at().is_some() {
+            self.cycle();
+            return;
+        }
+
         // loop while cycle returns false => loop until cpu cycled
         while !self.cycle() {}
+
+        // Running one instruction is as far as it would have gone anyway
+        self.take_write_break();
     }
 
-    fn handle_cpu_interrupts(&mut self) {
-        if self.get_mapper_mut().irq_requested() {
-            self.get_cpu_mut().irq();
-            self.get_mapper_mut().irq_handled();
+    // Publishes the write that matched a breakpoint (if one did), unless it's
+    // a mid-frame breakpoint and the PPU isn't drawing the picture
+    fn publish_write_break(&mut self) {
+        let Some(mut hit) = self.get_cpu_mut().take_write_break() else {
+            return;
+        };
+
+        (hit.dot, hit.scanline) = self.ppu_position();
+
+        if !hit.breakpoint.mid_frame || hit.scanline < NES_SCREEN_HEIGHT {
+            self.events.borrow_mut().publish(NesEvent::WriteBreak(hit));
         }
+    }
+
+    // Whether a write breakpoint has been hit since this was last called
+    fn take_write_break(&mut self) -> bool {
+        if self.write_breakpoints.is_empty() {
+            return false;
+        }
+
+        let mut events = self.events.borrow_mut();
+
+        if !events.has_events(self.break_events) {
+            return false;
+        }
+
+        events.take(self.break_events);
+        true
+    }
 
-        if self.get_ppu().cpu_nmi_flag() {
-            self.cpu.as_mut().unwrap().trigger_ppu_nmi();
-            self.get_ppu_mut().set_cpu_nmi_flag(false);
+    // Passes the interrupt lines on to the CPU. Every device that can request
+    // an IRQ shares the same line, and each one keeps requesting it until the
+    // game acknowledges it through that device's registers.
+    fn handle_cpu_interrupts(&mut self) {
+        let nmi_raised = self.get_ppu_mut().take_nmi_raised();
+        let nmi_line = self.get_ppu().nmi_line();
+        let mapper_irq = self.get_mapper().irq_requested();
+        let frame_irq = self.get_apu().trigger_irq();
+        let dmc_irq = self.get_apu().dmc_trigger_irq();
+
+        let cpu = self.get_cpu_mut();
+        cpu.set_irq_line(mapper_irq || frame_irq || dmc_irq);
+        // The PPU may have run ahead of the clock and raised and dropped NMI
+        // since last time (see "ON HOW THE CPU AND PPU KEEP IN STEP")
+        if nmi_raised {
+            cpu.set_nmi_line(false);
+            cpu.set_nmi_line(true);
         }
+        cpu.set_nmi_line(nmi_line);
+        let serviced = cpu.take_serviced_interrupt();
 
-        if self.get_apu().trigger_irq() {
-            self.get_cpu_mut().trigger_apu_irq();
-            self.get_apu_mut().set_trigger_irq(false);
+        if mapper_irq && !self.mapper_irq_line {
+            self.events.borrow_mut().publish(NesEvent::MapperIrq);
         }
+        if serviced == Some(Interrupt::Nmi) {
+            self.events.borrow_mut().publish(NesEvent::Nmi);
+        }
+        self.mapper_irq_line = mapper_irq;
+
+        if self.interrupt_logging {
+            let (frame, position) = (self.frames, self.ppu_position());
 
-        if self.get_apu().dmc_trigger_irq() {
-            self.get_cpu_mut().trigger_apu_irq();
-            self.get_apu_mut().set_dmc_irq_flag(false);
+            self.interrupt_log.update([nmi_line, mapper_irq, frame_irq, dmc_irq], serviced, 
+                frame, position);
         }
     }
 
@@ -285,29 +606,285 @@ impl Nes {
         }
     }
 
+    /// The colors of palette 0-7 as they are in palette RAM right now
+    pub fn palette_colors(&self, palette: u8) -> [NesColor; 4] {
+        if let Some(ppu) = &self.ppu {
+            ppu.as_ref().borrow().palette_colors(palette)
+        } else {
+            [NesColor { r: 0, g: 0, b: 0 }; 4]
+        }
+    }
+
+    /// Runs one frame with the given buttons held on each controller. The
+    /// buttons are handed to the controllers before the frame starts and stay
+    /// that way until it's over, so the same inputs from the same state always
+    /// give the same frame, which is what movie playback, rollback and
+    /// headless runs rely on. Returns whether the frame finished (a write
+    /// breakpoint can stop it partway). The picture is left for
+    /// swap_screen_buffers, like with cycle_until_frame.
+    pub fn advance_frame(&mut self, inputs: [NesController; 2]) -> bool {
+        for (player_id, input) in inputs.into_iter().enumerate() {
+            self.set_controller_state(player_id, input);
+        }
+
+        let frames_before = self.frames;
+        self.cycle_until_frame();
+
+        self.frames > frames_before
+    }
+
+    /// Runs the NES to the end of the frame, or until a write breakpoint is
+    /// hit (see breakpoints)
     pub fn cycle_until_frame(&mut self) {
         if self.cart_loaded {
-            while !self.get_ppu().frame_finished() {
+            while !self.take_frame_ready() {
                 self.cycle();
+
+                // The rest of the frame is run by the next call
+                if self.take_write_break() {
+                    return;
+                }
             }
 
-            self.get_ppu_mut().set_frame_finished(false);
+            self.frames += 1;
+            self.apply_freezes();
+        }
+    }
+
+    /// Runs the NES until the PPU gets to the given dot of the given scanline,
+    /// running a whole frame if it's already there. Scanlines go from 0 to
+    /// 261 (the pre-render line) and dots from 0 to 340. Returns false if it
+    /// never got there, either because the position doesn't exist or because
+    /// it's the dot skipped on odd frames, or because a write breakpoint was
+    /// hit first.
+    /// https://www.nesdev.org/wiki/PPU_rendering
+    pub fn cycle_until_scanline(&mut self, scanline: usize, dot: usize) -> bool {
+        // Two frames is enough to find any dot, even one only on even frames
+        const MAX_CLOCKS: usize = 341 * 262 * 2;
+
+        if !self.cart_loaded || scanline > 261 || dot > 340 {
+            return false;
+        }
+
+        for _ in 0..MAX_CLOCKS {
+            self.step_clock();
+
+            if self.ppu_position() == (dot, scanline) {
+                return true;
+            }
+            if self.take_write_break() {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// Runs the NES until the CPU is about to run the instruction at the given
+    /// address, running at least one instruction first. Gives up after
+    /// max_frames frames in case it never gets there (or when a write
+    /// breakpoint is hit), and returns whether it did.
+    pub fn cycle_until_pc(&mut self, address: u16, max_frames: u64) -> bool {
+        if !self.cart_loaded {
+            return false;
+        }
+
+        let give_up_at = self.frames + max_frames;
+
+        self.step_instr();
+
+        while self.frames < give_up_at {
+            self.step_clock();
+
+            // The whole instruction runs on its first cycle, so the CPU is
+            // about to run the one at PC once the cycles of the last one are
+            // used up
+            let cpu = self.get_cpu();
+            if cpu.get_remaining_cycles() == 0 && cpu.get_pc() == address {
+                return true;
+            }
+
+            if self.take_write_break() {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// Runs the NES for the given number of CPU cycles, stopping early if a
+    /// write breakpoint is hit
+    pub fn cycle_cpu(&mut self, cycles: u64) {
+        if !self.cart_loaded {
+            return;
+        }
+
+        let stop_at = self.get_cpu().total_clocks() + cycles;
+
+        while self.get_cpu().total_clocks() < stop_at {
+            self.step_clock();
+
+            if self.take_write_break() {
+                return;
+            }
+        }
+    }
+
+    // One clock of the stepping functions above. They can run past the end of
+    // a frame, which is finished off here the same way cycle_until_frame and
+    // the app would (so the debugger shows the frame that was just drawn).
+    // Returns whether an instruction was run, like cycle.
+    fn step_clock(&mut self) -> bool {
+        let instr_run = self.cycle();
+
+        if self.take_frame_ready() {
+            self.frames += 1;
+            self.apply_freezes();
+            self.swap_screen_buffers();
+        }
+
+        instr_run
+    }
+
+    // Whether the PPU has finished a frame since this was last called
+    fn take_frame_ready(&mut self) -> bool {
+        let mut events = self.events.borrow_mut();
+
+        if !events.has_events(self.frame_events) {
+            return false;
+        }
+
+        events.take(self.frame_events);
+        true
+    }
+
+    // cycle_instr, going through step_clock
+    fn step_instr(&mut self) {
+        if self.cpu_jammed_at().is_some() {
+            self.step_clock();
+            return;
+        }
+
+        while !self.step_clock() {}
+    }
+
+    /// Number of frames run by cycle_until_frame since the cart was loaded
+    pub fn frames_run(&self) -> u64 {
+        self.frames
+    }
+
+    /// Number of times the game has latched the controllers since the cart
+    /// was loaded. A frame where this goes up is one that saw the input the
+    /// player was holding at the time.
+    pub fn controller_polls(&self) -> u64 {
+        self.cpu.as_ref().map_or(0, |cpu| cpu.bus().controller_polls())
+    }
+
+    /// Everything the loaded game has done that isn't emulated (or is only
+    /// roughly emulated), in the order each first happened (see telemetry)
+    pub fn unsupported_uses(&self) -> Vec<UnsupportedUse> {
+        self.cpu.as_ref().map_or(Vec::new(), |cpu| cpu.bus().unsupported_uses().to_vec())
+    }
+
+    /// Reads a byte from CPU address space without disturbing anything, the
+    /// way the debugger should. Reading PPUSTATUS this way doesn't clear
+    /// vblank, reading PPUDATA doesn't move the VRAM address, and reading the
+    /// controller ports doesn't shift their buttons out.
+    pub fn peek_cpu(&self, address: u16) -> u8 {
+        self.cpu.as_ref().map_or(0xEE, |cpu| cpu.peek_cpu(address))
+    }
+
+    /// What's at an address in CPU address space, for labelling the memory
+    /// view (see "ON THE CPU MEMORY MAP")
+    pub fn cpu_region(&self, address: u16) -> CpuRegion {
+        match &self.mapper {
+            Some(mapper) => CpuRegion::at(address, Some(&*mapper.as_ref().borrow())),
+            None => CpuRegion::at(address, None),
+        }
+    }
+
+    /// Reads a byte from PPU address space (pattern tables, nametables and
+    /// palettes) without disturbing anything, including mapper latches
+    pub fn peek_ppu(&self, address: u16) -> u8 {
+        self.ppu.as_ref().map_or(0xEE, |ppu| ppu.as_ref().borrow().peek_ppu(address))
+    }
+
+    /// Copies a region of memory out, for saving to a file (see
+    /// memory_region). Fails if there's no cart in or it doesn't have the
+    /// region.
+    pub fn dump_memory(&self, region: MemoryRegion) -> Result<Vec<u8>, String> {
+        self.check_memory_region(region)?;
+
+        let bytes = match region {
+            MemoryRegion::PrgRam => {
+                let mapper = self.get_mapper();
+                (0x6000..0x8000).map(|address| mapper.cpu_cart_peek(address).unwrap_or(0)).collect()
+            }
+            MemoryRegion::ChrRam => (0x0000..0x2000).map(|address| self.peek_ppu(address)).collect(),
+            MemoryRegion::Vram => self.get_ppu().vram().to_vec(),
+            MemoryRegion::Oam => self.get_ppu().oam().to_vec(),
+            MemoryRegion::Palette => self.get_ppu().palette_mem().to_vec(),
+        };
+
+        Ok(bytes)
+    }
+
+    /// Overwrites a region of memory with bytes from dump_memory (or anywhere
+    /// else, as long as it's the right size)
+    pub fn restore_memory(&mut self, region: MemoryRegion, bytes: &[u8]) -> Result<(), String> {
+        self.check_memory_region(region)?;
+
+        if bytes.len() != region.size() {
+            return Err(format!("A {} dump should be {} bytes, not {}", region.name(), region.size(), bytes.len()));
+        }
+
+        match region {
+            MemoryRegion::PrgRam => {
+                let mut mapper = self.get_mapper_mut();
+                for (address, &data) in (0x6000..0x8000).zip(bytes) {
+                    mapper.cpu_cart_write(address, data);
+                }
+            }
+            MemoryRegion::ChrRam => {
+                let mut ppu = self.get_ppu_mut();
+                for (address, &data) in (0x0000..0x2000).zip(bytes) {
+                    ppu.ppu_write(address, data);
+                }
+            }
+            MemoryRegion::Vram => self.get_ppu_mut().vram_mut().copy_from_slice(bytes),
+            MemoryRegion::Oam => self.get_ppu_mut().oam_mut().copy_from_slice(bytes),
+            MemoryRegion::Palette => self.get_ppu_mut().palette_mem_mut().copy_from_slice(bytes),
+        }
+
+        Ok(())
+    }
+
+    // Whether the region can be dumped and restored with the cart that's in
+    fn check_memory_region(&self, region: MemoryRegion) -> Result<(), String> {
+        if !self.cart_loaded {
+            return Err(String::from("No cartridge is loaded"));
+        }
+
+        match region {
+            MemoryRegion::PrgRam if self.get_mapper().cpu_cart_peek(0x6000).is_none() => {
+                Err(String::from("The cartridge has no PRG RAM, or it's switched off"))
+            }
+            MemoryRegion::ChrRam if self.cart_info.as_ref().is_some_and(|info| info.chr_rom_size != 0) => {
+                Err(String::from("The cartridge has CHR ROM, not CHR RAM"))
+            }
+            _ => Ok(()),
         }
     }
 
     /// Get a string showing the contents of the Zero Page of system ram
-    pub fn zpage_str(&mut self) -> String {
+    pub fn zpage_str(&self) -> String {
         let mut mem_str: String = String::from("");
 
         for i in 0..16 {
             let prefix = format!("${:04X}:", i * 16);
             mem_str.push_str(&prefix);
             for j in 0..16 {
-                let mem_val = if let Some(cpu) = &mut self.cpu {
-                    cpu.read(i * 16 + j)
-                } else {
-                    0xEE
-                };
+                let mem_val = self.peek_cpu(i * 16 + j);
                 let val_str = format!(" {mem_val:02X}");
                 mem_str.push_str(&val_str);
             }
@@ -319,29 +896,845 @@ impl Nes {
     }
 
     pub fn swap_screen_buffers(&mut self) {
-        let buf_ptr1 = self.screen_buf1.as_mut_ptr() as *mut [u8; NES_SCREEN_BUF_SIZE];
-        let buf_ptr2 = self.screen_buf2.as_mut_ptr() as *mut [u8; NES_SCREEN_BUF_SIZE];
-
-        unsafe { std::ptr::swap(buf_ptr1, buf_ptr2); }
+        if let Some(ppu) = &self.ppu {
+            ppu.as_ref().borrow_mut().swap_frame(&mut self.screen_buf);
+        }
     }
 
     pub fn screen_buf_slice(&self) -> &[u8] {
-        self.screen_buf2.as_slice()
+        self.screen_buf.as_slice()
+    }
+
+    /// The last finished frame (the one swap_screen_buffers moved out of the
+    /// PPU's way)
+    pub fn frame(&self) -> Frame<'_> {
+        Frame { pixels: self.screen_buf_slice() }
     }
 
     pub fn audio_samples_queued(&self) -> usize {
         self.get_apu().audio_samples_queued()
     }
 
+    /// Get the PPU's background shift registers (see Ppu2C02::bg_shift_regs)
+    pub fn ppu_bg_shift_regs(&self) -> [u16; 4] {
+        self.get_ppu().bg_shift_regs()
+    }
+
+    /// Get the PPU's next background tile latches (see Ppu2C02::bg_next_tile_latches)
+    pub fn ppu_bg_next_tile_latches(&self) -> [u8; 4] {
+        self.get_ppu().bg_next_tile_latches()
+    }
+
+    pub fn ppu_fine_x(&self) -> u8 {
+        self.get_ppu().fine_x()
+    }
+
+    /// Get the dot & scanline the PPU is on
+    pub fn ppu_position(&self) -> (usize, usize) {
+        self.get_ppu().position()
+    }
+
+    /// The sprites in OAM, 4 bytes each (Y, tile, attributes, X)
+    /// https://www.nesdev.org/wiki/PPU_OAM
+    pub fn oam(&self) -> [u8; 256] {
+        *self.get_ppu().oam()
+    }
+
+    /// The value last written to PPUCTRL
+    pub fn ppu_ctrl(&self) -> u8 {
+        self.get_ppu().ctrl_val()
+    }
+
     pub fn large_sprites(&self) -> bool {
         self.get_ppu().using_large_sprites()
     }
 
+    /// How recently the nametable/attribute bytes behind each pixel of the
+    /// last frame were written (see Ppu2C02::bg_write_heat)
+    pub fn bg_write_heat(&self, fade_frames: u64) -> Vec<u8> {
+        self.get_ppu().bg_write_heat(fade_frames)
+    }
+
     pub fn current_mirror_type(&self) -> NametableMirror {
         self.get_mapper().get_nt_mirror_type()
     }
 
+    /// The loaded mapper's number and name
+    pub fn mapper_info(&self) -> (u16, &'static str) {
+        let mapper = self.get_mapper();
+
+        (mapper.mapper_num(), mapper.mapper_name())
+    }
+
+    /// The loaded mapper's internal registers, for the debugger (see
+    /// Mapper::debug_state)
+    pub fn mapper_debug_state(&self) -> Vec<(String, String)> {
+        self.get_mapper().debug_state()
+    }
+
+    pub fn bg_hidden(&self) -> bool {
+        self.bg_hidden
+    }
+
+    /// Leaves the background out of the picture (see Ppu2C02::set_bg_hidden)
+    pub fn set_bg_hidden(&mut self, val: bool) {
+        self.bg_hidden = val;
+
+        if self.ppu.is_some() {
+            self.get_ppu_mut().set_bg_hidden(val);
+        }
+    }
+
+    pub fn sprites_hidden(&self) -> bool {
+        self.sprites_hidden
+    }
+
+    /// Leaves the sprites out of the picture (see Ppu2C02::set_sprites_hidden)
+    pub fn set_sprites_hidden(&mut self, val: bool) {
+        self.sprites_hidden = val;
+
+        if self.ppu.is_some() {
+            self.get_ppu_mut().set_sprites_hidden(val);
+        }
+    }
+
+    /// Stops the NES when the CPU makes a write matching the breakpoint, and
+    /// publishes it as a WriteBreak event (see breakpoints)
+    pub fn add_write_breakpoint(&mut self, breakpoint: WriteBreakpoint) {
+        self.write_breakpoints.push(breakpoint);
+
+        if self.cpu.is_some() {
+            let breakpoints = self.write_breakpoints.clone();
+            self.get_cpu_mut().set_write_breakpoints(breakpoints);
+        }
+    }
+
+    pub fn write_breakpoints(&self) -> &[WriteBreakpoint] {
+        &self.write_breakpoints
+    }
+
+    pub fn clear_write_breakpoints(&mut self) {
+        self.write_breakpoints.clear();
+
+        if self.cpu.is_some() {
+            self.get_cpu_mut().set_write_breakpoints(Vec::new());
+        }
+    }
+
+    pub fn register_write_assert(&self) -> bool {
+        self.register_write_assert
+    }
+
+    /// Turns on flagging writes to PPUCTRL, PPUSCROLL and PPUADDR that happen
+    /// outside of vblank. Each one is printed as it happens, and the last
+    /// frame's writes are available from raster_writes.
+    pub fn set_register_write_assert(&mut self, val: bool) {
+        self.register_write_assert = val;
+
+        if self.ppu.is_some() {
+            self.get_ppu_mut().set_write_assert(val);
+        }
+    }
+
+    /// What changed about the scroll on each scanline of the last frame (see
+    /// Ppu2C02::scroll_splits)
+    pub fn scroll_splits(&self) -> [ScrollSplit; 240] {
+        *self.get_ppu().scroll_splits()
+    }
+
+    /// The mid-frame PPU register writes flagged during the last frame
+    pub fn raster_writes(&self) -> Vec<RasterWrite> {
+        self.get_ppu().raster_writes().to_vec()
+    }
+
+    /// Sets what the internal RAM holds at power on. Takes effect the next
+    /// time a cart is loaded, since resetting doesn't clear RAM.
+    pub fn set_ram_fill(&mut self, fill: RamFill) {
+        self.ram_fill = fill;
+    }
+
+    /// Turns the PPU's warm-up period after power on and reset on or off (see
+    /// Ppu2C02::set_warmup). Takes effect the next time a cart is loaded or
+    /// the NES is reset.
+    pub fn set_ppu_warmup(&mut self, val: bool) {
+        self.ppu_warmup = val;
+
+        if self.ppu.is_some() {
+            self.get_ppu_mut().set_warmup(val);
+        }
+    }
+
+    /// Sets how sprites are picked for each scanline, which decides whether
+    /// they flicker and how the sprite overflow flag is set (see
+    /// SpriteEvaluation)
+    pub fn set_sprite_evaluation(&mut self, evaluation: SpriteEvaluation) {
+        self.sprite_evaluation = evaluation;
+
+        if self.ppu.is_some() {
+            self.get_ppu_mut().set_sprite_evaluation(evaluation);
+        }
+    }
+
+    pub fn sprite_evaluation(&self) -> SpriteEvaluation {
+        self.sprite_evaluation
+    }
+
+    /// Sets whether the CPU reads every instruction off the bus or caches
+    /// decoded ones, which is faster but less exact (see CpuMode). It can be
+    /// switched while a game is running.
+    pub fn set_cpu_mode(&mut self, mode: CpuMode) {
+        self.cpu_mode = mode;
+
+        if let Some(cpu) = self.cpu.as_mut() {
+            cpu.set_mode(mode);
+        }
+    }
+
+    pub fn cpu_mode(&self) -> CpuMode {
+        self.cpu_mode
+    }
+
+    /// Sets whether DMC sample fetches can corrupt controller reads
+    pub fn set_dmc_input_glitch(&mut self, val: bool) {
+        self.dmc_input_glitch = val;
+    }
+
+    /// Puts all the sound from the frames run so far into the sample queue,
+    /// instead of holding the end of it back until there's a whole batch.
+    /// For when the next frame might not come for a while, like when going
+    /// frame by frame.
+    pub fn flush_audio(&mut self) {
+        self.get_apu_mut().flush_samples();
+    }
+
     pub fn set_block_audio_samples(&mut self, val: bool) {
         self.get_apu_mut().set_block_samples(val);
     }
-}
\ No newline at end of file
+
+    /// Fades the audio out while ducked (for fast-forward) and back in after,
+    /// rather than cutting it off
+    pub fn set_audio_ducked(&mut self, ducked: bool) {
+        self.get_apu_mut().set_ducked(ducked);
+    }
+
+    pub fn audio_underruns(&self) -> u64 {
+        self.get_apu().audio_underruns()
+    }
+
+    pub fn audio_missed_samples(&self) -> u64 {
+        self.get_apu().audio_missed_samples()
+    }
+
+    pub fn audio_latency_ms(&self) -> f64 {
+        self.get_apu().audio_latency_ms()
+    }
+
+    pub fn audio_batch_size(&self) -> usize {
+        self.get_apu().batch_size()
+    }
+
+    pub fn set_audio_batch_config(&mut self, batch_size: usize, target_queue_depth: usize, adaptive: bool) {
+        self.get_apu_mut().set_batch_config(batch_size, target_queue_depth, adaptive);
+    }
+
+    /// Lets the APU know how fast the emulation is running (1.0 is normal
+    /// speed) so audio pitch stays correct
+    pub fn set_emulation_speed(&mut self, speed: f64) {
+        self.get_apu_mut().set_speed(speed);
+    }
+
+    /// Serializes the state of the cartridge mapper. See cartridge::mapper for
+    /// the format.
+    pub fn save_mapper_state(&self) -> Vec<u8> {
+        mapper::save_mapper_state(&*self.get_mapper())
+    }
+
+    pub fn load_mapper_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        mapper::load_mapper_state(&mut *self.get_mapper_mut(), bytes)
+    }
+
+    /// Saves the state of the system so it can be restored with load_state:
+    /// the cartridge's state (mapper registers, PRG RAM and CHR RAM), the
+    /// APU's, the CPU's (with the internal RAM) and the PPU's. Settings (like
+    /// the muted channels or cheats) and the debug views' history aren't part
+    /// of it.
+    pub fn save_state(&self) -> SaveState {
+        let mut bytes = self.save_mapper_state();
+        bytes.extend(self.get_apu().save_state());
+        bytes.extend(self.get_cpu().save_state());
+        bytes.extend(self.get_ppu().save_state());
+
+        let mut payload = StateWriter::new();
+        payload.write_u64(self.clocks);
+        payload.write_bool(self.mapper_irq_line);
+
+        let mut block = StateWriter::new();
+        block.write_block(NES_STATE_MAGIC, NES_STATE_VERSION, &payload.into_bytes());
+        bytes.extend(block.into_bytes());
+
+        SaveState::from_bytes(bytes)
+    }
+
+    /// Restores a state made by save_state. The same game has to be loaded.
+    /// States saved before the APU, CPU and PPU were part of them leave those
+    /// as they are.
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), String> {
+        let mapper_state = state.block(mapper::MAPPER_STATE_MAGIC)
+            .ok_or("Save state has no mapper state in it")?;
+        self.load_mapper_state(mapper_state)?;
+
+        if let Some(apu_state) = state.block(APU_STATE_MAGIC) {
+            self.get_apu_mut().load_state(apu_state)?;
+        }
+        if let Some(cpu_state) = state.block(CPU_STATE_MAGIC) {
+            self.get_cpu_mut().load_state(cpu_state)?;
+        }
+        if let Some(ppu_state) = state.block(PPU_STATE_MAGIC) {
+            self.get_ppu_mut().load_state(ppu_state)?;
+        }
+        if let Some(nes_state) = state.block(NES_STATE_MAGIC) {
+            let (version, mut payload) = StateReader::new(nes_state).read_block(NES_STATE_MAGIC)?;
+            if version > NES_STATE_VERSION {
+                return Err(format!(
+                    "NES state version {version} is newer than this emulator supports ({NES_STATE_VERSION})"
+                ));
+            }
+
+            self.clocks = payload.read_u64()?;
+            self.mapper_irq_line = payload.read_bool()?;
+        }
+
+        Ok(())
+    }
+
+    /// Checksums each part of the system's state (see checksum.rs), for
+    /// spotting where two runs that should be identical drift apart
+    pub fn state_checksum(&self) -> StateChecksum {
+        let cpu = self.get_cpu_state();
+        let mut cpu_bytes = vec![cpu.acc, cpu.x, cpu.y, cpu.sp, u8::from(cpu.status)];
+        cpu_bytes.extend(cpu.pc.to_le_bytes());
+        cpu_bytes.extend(cpu.total_clocks.to_le_bytes());
+
+        let ram: Vec<u8> = (0x0000..0x0800).map(|address| self.peek_cpu(address)).collect();
+        let vram: Vec<u8> = (0x2000..0x3000).map(|address| self.peek_ppu(address)).collect();
+        let palette: Vec<u8> = (0x3F00..0x3F20).map(|address| self.peek_ppu(address)).collect();
+
+        let (ppu_bytes, oam) = match &self.ppu {
+            Some(_) => {
+                let ppu = self.get_ppu();
+                let (dot, scanline) = ppu.position();
+
+                let mut ppu_bytes = vec![ppu.ctrl_val(), ppu.status_val(), ppu.fine_x()];
+                ppu_bytes.extend(ppu.v_val().to_le_bytes());
+                ppu_bytes.extend(ppu.t_val().to_le_bytes());
+                ppu_bytes.extend((scanline as u16).to_le_bytes());
+                ppu_bytes.extend((dot as u16).to_le_bytes());
+
+                (ppu_bytes, ppu.oam().to_vec())
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+        let mapper = if self.mapper.is_some() { self.save_mapper_state() } else { Vec::new() };
+
+        StateChecksum {
+            cpu: hash::crc32(&cpu_bytes),
+            ram: hash::crc32(&ram),
+            ppu: hash::crc32(&ppu_bytes),
+            vram: hash::crc32(&vram),
+            oam: hash::crc32(&oam),
+            palette: hash::crc32(&palette),
+            mapper: hash::crc32(&mapper),
+            apu: hash::crc32(&self.get_apu().save_state()),
+        }
+    }
+
+    pub fn apu_debug_enabled(&self) -> bool {
+        self.get_apu().debug_logging()
+    }
+
+    pub fn set_apu_debug(&mut self, val: bool) {
+        self.get_apu_mut().set_debug_logging(val);
+    }
+
+    pub fn interrupt_logging(&self) -> bool {
+        self.interrupt_logging
+    }
+
+    /// Turns the interrupt log on or off. It's cleared when turned off.
+    pub fn set_interrupt_logging(&mut self, val: bool) {
+        self.interrupt_logging = val;
+
+        if !self.interrupt_logging {
+            self.interrupt_log.clear();
+        }
+    }
+
+    /// Returns the most recent interrupts, oldest first (see interrupt_log)
+    pub fn interrupt_log(&self) -> Vec<InterruptEvent> {
+        self.interrupt_log.events().iter().copied().collect()
+    }
+
+    /// The last instructions the CPU ran, oldest first, or none if no cart is
+    /// loaded (see trace)
+    pub fn cpu_trace(&self) -> Vec<TraceEntry> {
+        self.cpu.as_ref().map_or_else(Vec::new, Cpu6502::trace)
+    }
+
+    /// Starts queueing the given kinds of event for a new subscriber, to be
+    /// picked up with take_events (see events)
+    pub fn subscribe(&mut self, kinds: &[EventKind]) -> Subscription {
+        self.events.borrow_mut().subscribe(kinds)
+    }
+
+    pub fn unsubscribe(&mut self, subscription: Subscription) {
+        self.events.borrow_mut().unsubscribe(subscription);
+    }
+
+    /// Takes the events published for a subscriber since the last call,
+    /// oldest first
+    pub fn take_events(&mut self, subscription: Subscription) -> Vec<NesEvent> {
+        self.events.borrow_mut().take(subscription)
+    }
+
+    /// Returns the most recent APU register writes, oldest first
+    pub fn apu_write_log(&self) -> Vec<ApuWrite> {
+        self.get_apu().write_log().iter().copied().collect()
+    }
+
+    /// Returns the most recent values written to the DMC direct load register
+    /// ($4011), oldest first
+    pub fn apu_pcm_log(&self) -> Vec<u8> {
+        self.get_apu().pcm_log().iter().copied().collect()
+    }
+
+    pub fn audio_sample_rate(&self) -> SampleRate {
+        self.get_apu().sample_rate()
+    }
+
+    /// Changes the rate audio samples are put out at. Whatever plays the
+    /// samples from the queue has to play them at the same rate.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.get_apu_mut().set_sample_rate(sample_rate);
+    }
+
+    pub fn audio_synthesis(&self) -> Synthesis {
+        self.get_apu().synthesis()
+    }
+
+    /// Switches between band-limited and naive generation of the pulse and
+    /// triangle waves. Naive is a little faster, but aliases on high notes.
+    pub fn set_audio_synthesis(&mut self, synthesis: Synthesis) {
+        self.get_apu_mut().set_synthesis(synthesis);
+    }
+
+    pub fn audio_mixer(&self) -> Mixer {
+        self.get_apu().mixer()
+    }
+
+    /// Switches between the APU's real, nonlinear mixing and a linear mix
+    pub fn set_audio_mixer(&mut self, mixer: Mixer) {
+        self.get_apu_mut().set_mixer(mixer);
+    }
+
+    pub fn duty_cycles_swapped(&self) -> bool {
+        self.get_apu().swap_duty_cycles()
+    }
+
+    /// Swaps the 25% and 50% pulse duty cycles, which some Famicom clones do
+    /// (and some games made for them expect)
+    pub fn set_duty_cycles_swapped(&mut self, val: bool) {
+        self.get_apu_mut().set_swap_duty_cycles(val);
+    }
+
+    pub fn muted_audio_channels(&self) -> ChannelMask {
+        self.get_apu().muted_channels()
+    }
+
+    pub fn toggle_audio_channel(&mut self, channel: NesChannel) {
+        self.get_apu_mut().toggle_channel_muted(channel);
+    }
+
+    pub fn solo_audio_channel(&mut self, channel: NesChannel) {
+        self.get_apu_mut().solo_channel(channel);
+    }
+
+    /// Name of the loaded game (empty if no cart is loaded)
+    pub fn rom_name(&self) -> &str {
+        &self.rom_name
+    }
+
+    /// CRC-32 of the loaded ROM, not including the header
+    pub fn rom_crc32(&self) -> u32 {
+        self.rom_crc32
+    }
+
+    /// The cartridge's battery backed PRG RAM, or None if there's no cart in
+    /// or its header says it has no battery
+    pub fn battery_ram(&self) -> Option<Vec<u8>> {
+        if !self.cart_info?.battery {
+            return None;
+        }
+
+        self.get_mapper().prg_ram().map(<[u8]>::to_vec)
+    }
+
+    /// Puts back battery backed PRG RAM saved from battery_ram, like when
+    /// the game is loaded. Fails if the cartridge has no battery, or the RAM
+    /// is a different size (it was saved for a different game).
+    pub fn load_battery_ram(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if !self.cart_info.is_some_and(|info| info.battery) {
+            return Err(String::from("The cartridge has no battery backed RAM"));
+        }
+
+        let mut mapper = self.get_mapper_mut();
+        let ram = mapper.prg_ram_mut().ok_or("The cartridge has no battery backed RAM")?;
+        if ram.len() != bytes.len() {
+            return Err(format!("Battery save is {} bytes, but the cartridge has {} bytes of RAM", bytes.len(), ram.len()));
+        }
+        ram.copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    /// What the loaded cartridge's header says about it
+    pub fn cart_info(&self) -> Option<CartInfo> {
+        self.cart_info
+    }
+
+    /// The loaded ROM's full set of hashes, once they've been given with
+    /// set_rom_hashes
+    pub fn rom_hashes(&self) -> Option<&RomHashes> {
+        self.rom_hashes.as_ref()
+    }
+
+    /// Gives the NES the hashes of the loaded ROM. Hashing isn't done when the
+    /// cart is loaded since SHA-1 takes a while on big ROMs, so it's up to the
+    /// frontend to do it (see RomHashes::of_rom). Hashes of a different ROM
+    /// than the one loaded are ignored.
+    pub fn set_rom_hashes(&mut self, hashes: RomHashes) {
+        if self.cart_loaded && hashes.rom_crc32 == self.rom_crc32 {
+            self.rom_hashes = Some(hashes);
+        }
+    }
+
+    /// Gets the current state of a player's controller (0 for player 1, 1 for
+    /// player 2)
+    pub fn controller_state(&self, player_id: usize) -> NesController {
+        match player_id {
+            0 => self.p1_controller,
+            1 => self.p2_controller,
+            _ => NesController::default(),
+        }
+    }
+
+    /// Overwrites the state of a player's controller all at once (used for
+    /// movie playback)
+    pub fn set_controller_state(&mut self, player_id: usize, state: NesController) {
+        match player_id {
+            0 => self.p1_controller = state,
+            1 => self.p2_controller = state,
+            _ => return,
+        }
+
+        self.send_buttons(player_id);
+    }
+
+    /// Plugs a device into one of the console's input slots, replacing
+    /// whatever was there (see "ON HOW INPUT DEVICES WORK"). The player's
+    /// current input is handed to it straight away.
+    pub fn plug_device(&mut self, slot: DeviceSlot, device: SharedDevice) {
+        self.devices[slot as usize] = Rc::clone(&device);
+
+        if let Some(cpu) = self.cpu.as_mut() {
+            cpu.bus_mut().plug_device(slot, device);
+        }
+
+        match slot {
+            DeviceSlot::Port1 => self.send_buttons(0),
+            DeviceSlot::Port2 => self.send_buttons(1),
+            DeviceSlot::Expansion => {
+                let input = self.expansion_input;
+                self.devices[slot as usize].borrow_mut().set_expansion_input(input);
+            }
+        }
+    }
+
+    /// The device plugged into one of the console's input slots
+    pub fn device(&self, slot: DeviceSlot) -> SharedDevice {
+        Rc::clone(&self.devices[slot as usize])
+    }
+
+    /// Plugs a new device of the given kind into the Famicom expansion port
+    pub fn set_expansion_device(&mut self, device: ExpansionDevice) {
+        self.expansion_device = device;
+        self.expansion_input = ExpansionInput::default();
+
+        self.plug_device(DeviceSlot::Expansion, device.create());
+    }
+
+    pub fn expansion_device(&self) -> ExpansionDevice {
+        self.expansion_device
+    }
+
+    /// Gives the expansion port device the player's latest input
+    pub fn set_expansion_input(&mut self, input: ExpansionInput) {
+        self.expansion_input = input;
+
+        self.devices[DeviceSlot::Expansion as usize].borrow_mut().set_expansion_input(input);
+    }
+
+    pub fn expansion_input(&self) -> ExpansionInput {
+        self.expansion_input
+    }
+
+    /// Holds a byte of RAM at a value from now on (see cheats). A freeze on an
+    /// address that's already frozen replaces it.
+    pub fn add_freeze(&mut self, freeze: RamFreeze) {
+        self.remove_freeze(freeze.address);
+        self.freezes.push(freeze);
+    }
+
+    /// Lets the game change a frozen byte of RAM again
+    pub fn remove_freeze(&mut self, address: u16) {
+        self.freezes.retain(|freeze| freeze.address != address);
+    }
+
+    pub fn freezes(&self) -> &[RamFreeze] {
+        &self.freezes
+    }
+
+    // Puts every frozen byte back, after the CPU's done with the frame
+    fn apply_freezes(&mut self) {
+        for i in 0..self.freezes.len() {
+            let RamFreeze { address, value } = self.freezes[i];
+            self.get_cpu_mut().bus_mut().write(address, value);
+        }
+    }
+
+    /// Loads a .pal palette file (64 RGB triplets) and uses it for all future
+    /// frames. Files with emphasis variants (512 colors, in the same order as
+    /// SystemPalette) have them used too, otherwise they're worked out from
+    /// the 64 colors.
+    pub fn load_palette(&mut self, path: &str) -> Result<(), String> {
+        let bytes = fs::read(path).map_err(|e| format!("Couldn't read palette '{path}': {e}"))?;
+
+        if bytes.len() < 64 * 3 {
+            return Err(format!("Palette '{path}' is too small ({} bytes, expected at least 192)", bytes.len()));
+        }
+
+        let mut palette = [NesColor { r: 0, g: 0, b: 0 }; 64 * 8];
+
+        for (color, rgb) in palette.iter_mut().zip(bytes.chunks_exact(3)) {
+            *color = NesColor { r: rgb[0], g: rgb[1], b: rgb[2] };
+        }
+
+        let mut system_palette = nes_graphics::with_emphasis(&palette[..64].try_into().unwrap());
+
+        if bytes.len() >= 64 * 8 * 3 {
+            for (variant, colors) in system_palette.iter_mut().zip(palette.chunks_exact(64)) {
+                variant.copy_from_slice(colors);
+            }
+        }
+
+        self.system_palette = system_palette;
+        self.update_system_palette();
+
+        Ok(())
+    }
+
+    /// Runs the game's picture through a color filter, for color vision
+    /// deficiencies (see ColorFilter)
+    pub fn set_color_filter(&mut self, filter: ColorFilter) {
+        self.color_filter = filter;
+        self.update_system_palette();
+    }
+
+    pub fn color_filter(&self) -> ColorFilter {
+        self.color_filter
+    }
+
+    // Gives the PPU the system palette with the color filter applied
+    fn update_system_palette(&mut self) {
+        if self.ppu.is_some() {
+            let palette = self.color_filter.apply(&self.system_palette);
+            self.get_ppu_mut().set_system_palette(palette);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::trace::TRACE_LOG_SIZE;
+
+    const NOP: u8 = 0xEA;
+
+    #[test]
+    fn reset_leaves_the_mapper_alone() {
+        // The test pattern cart on an MMC3 instead, so its first 8 KiB bank
+        // (where the program is) and its second (all NOPs) can be swapped
+        let mut rom = test_pattern::rom();
+        rom[6] = 0x40;
+
+        let mut nes = Nes::default();
+        nes.load_cart_bytes(&rom, "mmc3").unwrap();
+        assert_ne!(nes.peek_cpu(0x8000), NOP);
+
+        // The second bank at $8000
+        nes.get_mapper_mut().cpu_cart_write(0x8000, 6);
+        nes.get_mapper_mut().cpu_cart_write(0x8001, 1);
+        assert_eq!(nes.peek_cpu(0x8000), NOP);
+
+        // The MMC3 can't see the reset button, so the bank stays switched
+        nes.reset();
+        assert_eq!(nes.peek_cpu(0x8000), NOP);
+    }
+
+    #[test]
+    fn cpu_regions_follow_mirrors_and_banks() {
+        let mut rom = test_pattern::rom();
+        rom[6] = 0x40;
+
+        let mut nes = Nes::default();
+        nes.load_cart_bytes(&rom, "mmc3").unwrap();
+
+        assert_eq!(nes.cpu_region(0x0123), CpuRegion { name: String::from("RAM"), mirror: false });
+        assert_eq!(nes.cpu_region(0x0923), CpuRegion { name: String::from("RAM mirror"), mirror: true });
+        assert_eq!(nes.cpu_region(0x2008), CpuRegion { name: String::from("PPU reg mirror"), mirror: true });
+        assert_eq!(nes.cpu_region(0x6000).name, "SRAM");
+        assert_eq!(nes.cpu_region(0x8000).name, "PRG-ROM bank 0");
+
+        // Looking doesn't switch anything, but switching shows up
+        nes.get_mapper_mut().cpu_cart_write(0x8000, 6);
+        nes.get_mapper_mut().cpu_cart_write(0x8001, 1);
+        assert_eq!(nes.cpu_region(0x8000).name, "PRG-ROM bank 1");
+    }
+
+    #[test]
+    fn cached_cpu_runs_like_the_accurate_one() {
+        let run = |mode: CpuMode| {
+            let mut nes = Nes::default();
+            nes.set_cpu_mode(mode);
+            nes.load_test_pattern().unwrap();
+
+            for _ in 0..5 {
+                nes.advance_frame([NesController::default(); 2]);
+            }
+
+            (nes.state_checksum(), nes.get_cpu_state().total_clocks)
+        };
+
+        assert_eq!(run(CpuMode::Cached), run(CpuMode::Accurate));
+    }
+
+    #[test]
+    fn cached_cpu_reads_ppu_registers_on_the_same_dot() {
+        // The test pattern cart running a loop that counts how long each
+        // frame's wait for vblank takes instead. The wait is in ROM, where the
+        // cached CPU skips the fetches, and which dot $2002 is read on decides
+        // which time round the loop sees the flag.
+        let program = [
+            0xA2, 0x00,       // LDX #$00
+            0xE8,             // INX
+            0xAD, 0x02, 0x20, // LDA $2002
+            0x10, 0xFA,       // BPL $C002
+            0x86, 0x00,       // STX $00
+            0x4C, 0x00, 0xC0, // JMP $C000
+        ];
+        let mut rom = test_pattern::rom();
+        rom[16..16 + program.len()].copy_from_slice(&program);
+
+        let run = |mode: CpuMode| {
+            let mut nes = Nes::default();
+            nes.set_cpu_mode(mode);
+            nes.load_cart_bytes(&rom, "vblank wait").unwrap();
+
+            (0..20).map(|_| {
+                nes.advance_frame([NesController::default(); 2]);
+                nes.peek_cpu(0x0000)
+            }).collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(CpuMode::Cached), run(CpuMode::Accurate));
+    }
+
+    #[test]
+    fn trace_keeps_the_last_instructions_run() {
+        let mut nes = Nes::default();
+        assert!(nes.cpu_trace().is_empty());
+
+        nes.load_test_pattern().unwrap();
+        let start = nes.get_cpu_state();
+        nes.cycle_instr();
+        let first = nes.cpu_trace();
+        assert_eq!(first.len(), 1);
+        assert_eq!((first[0].pc, first[0].sp), (start.pc, start.sp));
+
+        nes.advance_frame([NesController::default(); 2]);
+        let trace = nes.cpu_trace();
+        assert_eq!(trace.len(), TRACE_LOG_SIZE);
+        assert!(trace.windows(2).all(|pair| pair[0].clock < pair[1].clock));
+    }
+
+    #[test]
+    fn flushing_queues_the_end_of_the_frames_sound() {
+        let mut nes = Nes::default();
+        nes.load_test_pattern().unwrap();
+        // A batch bigger than a frame's worth, so none of it goes out on its own
+        nes.set_audio_batch_config(4096, 8192, false);
+
+        nes.advance_frame([NesController::default(); 2]);
+        assert_eq!(nes.audio_samples_queued(), 0);
+
+        nes.flush_audio();
+        let queued = nes.audio_samples_queued();
+        assert!(queued > 0 && queued < 4096);
+
+        // Nothing's left to flush a second time
+        nes.flush_audio();
+        assert_eq!(nes.audio_samples_queued(), queued);
+    }
+
+    #[test]
+    fn battery_ram_is_only_kept_for_carts_with_a_battery() {
+        let mut nes = Nes::default();
+        nes.load_cart_bytes(&test_pattern::rom(), "test pattern").unwrap();
+        assert_eq!(nes.battery_ram(), None);
+        assert!(nes.load_battery_ram(&[0; 8]).is_err());
+
+        // On an MMC3 with a battery instead
+        let mut rom = test_pattern::rom();
+        rom[6] = 0x42;
+        nes.load_cart_bytes(&rom, "mmc3").unwrap();
+
+        let mut saved = nes.battery_ram().unwrap();
+        saved[0x123] = 0x45;
+        assert!(nes.load_battery_ram(&saved[..0x100]).is_err());
+        nes.load_battery_ram(&saved).unwrap();
+        assert_eq!(nes.battery_ram(), Some(saved));
+    }
+
+    #[test]
+    fn loading_a_state_picks_up_where_it_was_saved() {
+        let mut nes = Nes::default();
+        nes.load_cart_bytes(&test_pattern::rom(), "test pattern").unwrap();
+
+        let run = |nes: &mut Nes| for _ in 0..3 {
+            nes.advance_frame([NesController::default(); 2]);
+        };
+
+        run(&mut nes);
+        let state = nes.save_state();
+        run(&mut nes);
+        let checksum = nes.state_checksum();
+
+        // Somewhere else entirely first, so nothing is left over by chance
+        nes.reset();
+        run(&mut nes);
+
+        nes.load_state(&state).unwrap();
+        run(&mut nes);
+        assert_eq!(nes.state_checksum(), checksum);
+    }
+}
@@ -73,4 +73,173 @@ pub struct NesColor {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+}
+
+/// Every color the PPU can put out: the 64 colors once for each combination of
+/// the emphasis bits in PPUMASK (red = 1, green = 2, blue = 4), so a color is
+/// looked up with palette[emphasis][index]
+/// https://www.nesdev.org/wiki/PPU_palettes
+pub type SystemPalette = [[NesColor; 64]; 8];
+
+// How much emphasis darkens the colors it doesn't emphasize
+// https://www.nesdev.org/wiki/NTSC_video#Color_Tint_Bits
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/// Works out the emphasized versions of a 64 color palette, for palettes that
+/// don't come with their own. Each emphasis bit dims the two color channels it
+/// doesn't emphasize, and with all three set everything is dimmed. The blacks
+/// in columns $xE and $xF aren't affected.
+pub fn with_emphasis(palette: &[NesColor; 64]) -> SystemPalette {
+    std::array::from_fn(|emphasis| std::array::from_fn(|index| {
+        let color = palette[index];
+
+        if emphasis == 0 || index & 0x0E == 0x0E {
+            return color;
+        }
+
+        let dim = |channel: u8, bit: usize| {
+            if emphasis & bit == 0 || emphasis == 0b111 {
+                (channel as f32 * EMPHASIS_ATTENUATION) as u8
+            } else {
+                channel
+            }
+        };
+
+        NesColor { r: dim(color.r, 1), g: dim(color.g, 2), b: dim(color.b, 4) }
+    }))
+}
+
+/*
+ON HOW THE COLOR FILTERS WORK:
+
+Every pixel the PPU puts out is one of the colors in the system palette (64,
+times 8 emphasis settings), so a color filter doesn't need to touch the picture
+at all. It's applied to the
+palette once, whenever the palette or the filter changes, and the filtered
+palette works as a lookup table for the whole picture.
+
+The simulation filters show what the picture looks like to someone with
+protanopia (no red-sensitive cones) or deuteranopia (no green-sensitive cones),
+for checking whether a game can be played that way. The colors are converted
+into how strongly they excite each kind of cone (LMS space), the missing cone's
+response is rebuilt out of the other two, and the result is converted back.
+
+The compensation filters (daltonization) are for playing with those
+deficiencies. The simulation shows which part of each color can't be seen, and
+that lost part is moved into differences that still can be (more green and
+blue), so colors that would look the same end up looking different. The numbers are
+the ones from Fidaner, Lin & Ozguven's "Analysis of Color Blindness".
+*/
+
+type ColorMatrix = [[f32; 3]; 3];
+
+const RGB_TO_LMS: ColorMatrix = [
+    [17.8824, 43.5161, 4.11935],
+    [3.45565, 27.1554, 3.86714],
+    [0.0299566, 0.184309, 1.46709],
+];
+const LMS_TO_RGB: ColorMatrix = [
+    [0.08094445, -0.13050441, 0.11672107],
+    [-0.010248534, 0.05401933, -0.11361471],
+    [-0.00036529694, -0.0041216147, 0.6935114],
+];
+// Rebuild the missing cone's response out of the other two
+const PROTANOPIA_LMS: ColorMatrix = [
+    [0.0, 2.02344, -2.52581],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0],
+];
+const DEUTERANOPIA_LMS: ColorMatrix = [
+    [1.0, 0.0, 0.0],
+    [0.494207, 0.0, 1.24827],
+    [0.0, 0.0, 1.0],
+];
+// Where the part of a color that can't be seen gets moved to
+const ERROR_SHIFT: ColorMatrix = [
+    [0.0, 0.0, 0.0],
+    [0.7, 1.0, 0.0],
+    [0.7, 0.0, 1.0],
+];
+
+/// A transform applied to the colors of the game's picture, to help with (or
+/// preview) color vision deficiencies. See "ON HOW THE COLOR FILTERS WORK".
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum ColorFilter {
+    #[default]
+    None,
+    // Compensate for protanopia/deuteranopia
+    Protanopia,
+    Deuteranopia,
+    // Show what protanopia/deuteranopia looks like
+    ProtanopiaSim,
+    DeuteranopiaSim,
+}
+
+impl ColorFilter {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "protan" => Some(Self::Protanopia),
+            "deutan" => Some(Self::Deuteranopia),
+            "protan-sim" => Some(Self::ProtanopiaSim),
+            "deutan-sim" => Some(Self::DeuteranopiaSim),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Protanopia => "protan",
+            Self::Deuteranopia => "deutan",
+            Self::ProtanopiaSim => "protan-sim",
+            Self::DeuteranopiaSim => "deutan-sim",
+        }
+    }
+
+    /// The next filter, wrapping back around to None
+    pub fn next(&self) -> Self {
+        match self {
+            Self::None => Self::Protanopia,
+            Self::Protanopia => Self::Deuteranopia,
+            Self::Deuteranopia => Self::ProtanopiaSim,
+            Self::ProtanopiaSim => Self::DeuteranopiaSim,
+            Self::DeuteranopiaSim => Self::None,
+        }
+    }
+
+    /// Runs every color in a system palette through the filter
+    pub fn apply(&self, palette: &SystemPalette) -> SystemPalette {
+        palette.map(|colors| colors.map(|color| self.filter_color(color)))
+    }
+
+    fn filter_color(&self, color: NesColor) -> NesColor {
+        let (deficiency, compensate) = match self {
+            Self::None => return color,
+            Self::Protanopia => (PROTANOPIA_LMS, true),
+            Self::Deuteranopia => (DEUTERANOPIA_LMS, true),
+            Self::ProtanopiaSim => (PROTANOPIA_LMS, false),
+            Self::DeuteranopiaSim => (DEUTERANOPIA_LMS, false),
+        };
+
+        let rgb = [color.r as f32, color.g as f32, color.b as f32];
+        let simulated = transform(LMS_TO_RGB, transform(deficiency, transform(RGB_TO_LMS, rgb)));
+
+        let filtered = if compensate {
+            let lost = [rgb[0] - simulated[0], rgb[1] - simulated[1], rgb[2] - simulated[2]];
+            let shift = transform(ERROR_SHIFT, lost);
+
+            [rgb[0] + shift[0], rgb[1] + shift[1], rgb[2] + shift[2]]
+        } else {
+            simulated
+        };
+
+        let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+
+        NesColor { r: channel(filtered[0]), g: channel(filtered[1]), b: channel(filtered[2]) }
+    }
+}
+
+fn transform(matrix: ColorMatrix, v: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
 }
\ No newline at end of file
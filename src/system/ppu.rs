@@ -2,12 +2,131 @@ use std::{cell::RefCell, rc::Rc};
 
 use crate::cartridge::{mapper::NametableMirror, Mapper};
 
-use super::{nes_graphics::{NesColor, DEFAULT_PALETTE}, ppu_util::{PpuCtrl, PpuMask, PpuScrollReg, PpuStatus}};
+use super::{events::{NesEvent, SharedEventBus}, savestate::{StateReader, StateWriter}, nes::{NES_SCREEN_BUF_SIZE, NES_SCREEN_HEIGHT, NES_SCREEN_WIDTH}, nes_graphics::{self, NesColor, SystemPalette, DEFAULT_PALETTE}, ppu_util::{PpuCtrl, PpuMask, PpuScrollReg, PpuStatus, ScrollSplit}, test_pattern};
+
+pub const PPU_STATE_MAGIC: &[u8; 4] = b"PPU ";
+const PPU_STATE_VERSION: u16 = 1;
 
 const VRAM_SIZE: usize = 0x800;
 const PALETTE_MEM_SIZE: usize = 32;
 const PRIMARY_OAM_SIZE: usize = 256;
 const SECONDARY_OAM_SIZE: usize = 32;
+// How long writes are ignored for after power on/reset with warm-up on:
+// 29658 CPU cycles, 3 dots each
+const WARMUP_DOTS: usize = 29658 * 3;
+
+/*
+ON HOW THE CPU AND PPU KEEP IN STEP:
+
+The PPU runs 3 dots for every CPU cycle, and the Nes clocks them together one
+dot at a time. The CPU, though, runs each instruction all at once on its first
+cycle and then sits out the rest, so without help every register access an
+instruction makes would see the PPU where it was when the instruction started,
+up to 21 dots early. That's enough to miss a sprite 0 hit, or to read the
+vblank flag a whole instruction late.
+
+So before the CPU touches a PPU register, the bus has the PPU catch up to the
+cycle the access really happens on: access k of an instruction happens on its
+k-th cycle, 3 * (k - 1) dots after it started (see catch_up). The dots run
+early are then skipped as the Nes clock gets to them. The PPU never runs past
+the end of a frame this way, so frames still finish (and get swapped out) on
+the clock they should.
+
+Being on the right dot matters most around the start of vblank, where reading
+PPUSTATUS races the flag being set:
+  - Reading it on the dot before the flag is set returns it clear, and keeps it
+    from being set at all that frame (so there's no NMI either).
+  - Reading it on the dot it's set, or the one after, returns it set and clears
+    it, and the NMI it would have caused never happens.
+  - Reading it any later returns it set and clears it, but the NMI still
+    happens, since the CPU has already seen it.
+The NMI output is passed to the CPU as an edge (see take_nmi_raised) as well as
+a level, since the flag can be set and cleared again while the PPU is ahead.
+https://www.nesdev.org/wiki/PPU_frame_timing#VBL_Flag_Timing
+https://www.nesdev.org/wiki/NMI#Race_condition
+*/
+
+/*
+ON SPRITE EVALUATION:
+
+Before each scanline the PPU looks through OAM for the sprites on the next one
+and copies the first 8 it finds into secondary OAM, which is all it can draw
+on a line. Any more are left out, which is why games that put lots of sprites
+side by side cycle their order in OAM every frame: each sprite gets left out
+some of the time instead of one going missing altogether, and they flicker.
+
+A 9th sprite on the line is meant to set the sprite overflow flag in PPUSTATUS,
+but the hardware's search for it is broken. Once secondary OAM is full, it
+moves to the next sprite and the next byte within it at the same time, so it
+reads X positions, tiles and attributes as if they were Y positions. The flag
+ends up set for lines without 9 sprites and missed for lines with them, and a
+few games (and test ROMs) depend on exactly how.
+
+SpriteEvaluation picks how much of this is emulated:
+    Limited     8 sprites a line, with the flag set when there's a 9th. Not
+                quite the hardware, but what most games expect.
+    Authentic   8 sprites a line, with the flag coming from the broken search
+                the hardware does
+    Unlimited   every sprite on the line is drawn, so nothing flickers. The
+                flag is set the same as Limited so games still run the same;
+                only the picture changes.
+https://www.nesdev.org/wiki/PPU_sprite_evaluation
+https://www.nesdev.org/wiki/PPU_OAM#Sprite_overflow_bug
+*/
+
+/// How sprites are picked for each scanline, and how the sprite overflow
+/// flag is set (see "ON SPRITE EVALUATION")
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpriteEvaluation {
+    #[default]
+    Limited,
+    Authentic,
+    Unlimited,
+}
+
+impl SpriteEvaluation {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "limited" => Some(Self::Limited),
+            "authentic" => Some(Self::Authentic),
+            "unlimited" => Some(Self::Unlimited),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Limited => "limited",
+            Self::Authentic => "authentic",
+            Self::Unlimited => "unlimited",
+        }
+    }
+}
+
+/// A write to PPUCTRL, PPUSCROLL or PPUADDR made while the PPU was drawing the
+/// picture, recorded by the register write assert mode. These are how games do
+/// raster splits (status bars, parallax), but a write that lands on the wrong
+/// scanline is also the classic sign of a CPU/PPU timing bug.
+/// https://www.nesdev.org/wiki/PPU_scrolling#Split_X/Y_scroll
+#[derive(Clone, Copy, Debug)]
+pub struct RasterWrite {
+    pub address: u16,
+    pub data: u8,
+    pub scanline: usize,
+    pub dot: usize,
+    // Address of the instruction that made the write
+    pub pc: u16,
+}
+
+impl RasterWrite {
+    pub fn register_name(&self) -> &'static str {
+        match self.address & 0x0007 {
+            0 => "PPUCTRL",
+            5 => "PPUSCROLL",
+            _ => "PPUADDR",
+        }
+    }
+}
 
 /// Representation of the NES Picture Processing Unit. Details on how the PPU
 /// works can be found here: https://www.nesdev.org/wiki/PPU_registers
@@ -44,12 +163,14 @@ pub struct Ppu2C02 {
 
     // Flag to keep track of if sprite 0 made it into secondary OAM during the last sprite evaluation phase
     spr_0_in_secondary_oam: bool,
+    // Sprites on the line past the first 8, only filled in with unlimited
+    // sprite evaluation. Drawn after the ones in secondary OAM.
+    extra_sprites: [u8; PRIMARY_OAM_SIZE - SECONDARY_OAM_SIZE],
+    sprite_evaluation: SpriteEvaluation,
 
     // Mapper used by the cartridge, Rc because both the CPU and PPU access to it
     mapper: Rc<RefCell<dyn Mapper>>,
 
-    // Flag to signal the NES to trigger a CPU NMI
-    cpu_nmi_flag: bool,
     // Flag to signal the NES to suspend the CPU due to OAM DMA transfer
     initiate_dma: bool,
 
@@ -70,13 +191,67 @@ pub struct Ppu2C02 {
     pgtbl1: Box<[u8; 0x1000]>,
     pgtbl2: Box<[u8; 0x1000]>,
 
-    // flag set when the ppu finishes rendering a frame
-    frame_finished: bool,
+    // Where FrameReady, VblankStart and Sprite0Hit are published
+    events: SharedEventBus,
     // flag to keep track of when the ppu is rendering an odd or even number frame
     odd_frame: bool,
 
-    // Keeps track of how many sprites were loaded into secondary OAM last sprite evaluation
+    // Keeps track of how many sprites were loaded into secondary OAM (and
+    // extra_sprites) last sprite evaluation
     sprites_found: usize,
+
+    // The system colors the PPU outputs, with their emphasized versions. Can
+    // be swapped out for a .pal file
+    // https://www.nesdev.org/wiki/PPU_palettes
+    system_palette: SystemPalette,
+
+    // Draws the built-in test pattern over every frame (see test_pattern)
+    test_pattern: bool,
+
+    // For the write heatmap (see bg_write_heat). The frame each VRAM byte was
+    // last written on plus one (0 means never), and the v register and fine x
+    // scroll each visible scanline started with.
+    frame_count: u64,
+    vram_write_frames: Box<[u64; VRAM_SIZE]>,
+    line_scroll: Box<[(PpuScrollReg, u8); 240]>,
+
+    // Register write assert mode (see RasterWrite). Off by default since it's
+    // a developer tool. The PPU doesn't know what the CPU is doing, so the Nes
+    // tells it the PC of each instruction before it runs.
+    write_assert: bool,
+    cpu_pc: u16,
+    current_raster_writes: Vec<RasterWrite>,
+    last_raster_writes: Vec<RasterWrite>,
+
+    // Whether writes are ignored for a while after power on and reset, like
+    // the real PPU does (see set_warmup), and how many dots are left until
+    // they aren't
+    warmup: bool,
+    warmup_dots_left: usize,
+
+    // Debug overrides that keep the background or sprites out of the picture
+    // whatever PPUMASK says (see draw_dot)
+    hide_bg: bool,
+    hide_sprites: bool,
+
+    // For the scroll split view (see scroll_splits). What each visible
+    // scanline's register writes changed about the scroll, this frame and
+    // last frame.
+    current_scroll_splits: Box<[ScrollSplit; 240]>,
+    last_scroll_splits: Box<[ScrollSplit; 240]>,
+
+    // The picture being drawn. Once it's finished the Nes swaps it for the
+    // one it's showing (see swap_frame).
+    frame: Box<[u8; NES_SCREEN_BUF_SIZE]>,
+
+    // How many dots have been run ahead of the system clock, and whether the
+    // NMI output has turned on since the Nes last looked (see "ON HOW THE CPU
+    // AND PPU KEEP IN STEP")
+    dots_ahead: usize,
+    nmi_raised: bool,
+    // Set by a PPUSTATUS read on the dot before vblank starts, which keeps the
+    // vblank flag from being set that frame
+    vblank_suppressed: bool,
 }
 
 // Main functionality
@@ -92,7 +267,6 @@ impl Ppu2C02 {
             dot: 0,
             scanline: 0,
 
-            cpu_nmi_flag: false,
             initiate_dma: false,
 
             ctrl: 0.into(),
@@ -112,6 +286,8 @@ impl Ppu2C02 {
             secondary_oam: [0; SECONDARY_OAM_SIZE],
 
             spr_0_in_secondary_oam: false,
+            extra_sprites: [0xFF; PRIMARY_OAM_SIZE - SECONDARY_OAM_SIZE],
+            sprite_evaluation: SpriteEvaluation::default(),
 
             mapper: Rc::clone(&mapper),
 
@@ -128,10 +304,38 @@ impl Ppu2C02 {
             pgtbl1: Box::new([0; 0x1000]),
             pgtbl2: Box::new([0; 0x1000]),
 
-            frame_finished: false,
+            system_palette: nes_graphics::with_emphasis(&DEFAULT_PALETTE),
+
+            test_pattern: false,
+
+            events: SharedEventBus::default(),
             odd_frame: false,
 
             sprites_found: 0,
+
+            frame_count: 0,
+            vram_write_frames: Box::new([0; VRAM_SIZE]),
+            line_scroll: Box::new([(0.into(), 0); 240]),
+
+            write_assert: false,
+            cpu_pc: 0,
+            current_raster_writes: Vec::new(),
+            last_raster_writes: Vec::new(),
+
+            warmup: false,
+            warmup_dots_left: 0,
+
+            hide_bg: false,
+            hide_sprites: false,
+
+            current_scroll_splits: Box::new([ScrollSplit::new(); 240]),
+            last_scroll_splits: Box::new([ScrollSplit::new(); 240]),
+
+            frame: Box::new([0; NES_SCREEN_BUF_SIZE]),
+
+            dots_ahead: 0,
+            nmi_raised: false,
+            vblank_suppressed: false,
         };
 
         // Read pagetable memories into arrays for debug view
@@ -153,12 +357,37 @@ impl Ppu2C02 {
         ppu
     }
 
-    /// Cycle the PPU through the rendering/execution of a single pixel/dot.
-    pub fn cycle(&mut self, frame: &mut [u8]) {        
-        self.frame_finished = false;
+    /// Cycle the PPU through the rendering/execution of a single pixel/dot,
+    /// unless that dot was already run early (see catch_up).
+    pub fn cycle(&mut self) {
+        if self.dots_ahead > 0 {
+            self.dots_ahead -= 1;
+        } else {
+            self.run_dot();
+        }
+    }
+
+    /// Runs the PPU ahead of the system clock until it's the given number of
+    /// dots past it, for a register access partway through a CPU instruction
+    /// (see "ON HOW THE CPU AND PPU KEEP IN STEP"). Stops at the last dot of
+    /// the frame, which is always left for the clock to run.
+    pub fn catch_up(&mut self, dots: usize) {
+        while self.dots_ahead < dots && !(self.scanline == 261 && self.dot == 340) {
+            self.run_dot();
+            self.dots_ahead += 1;
+        }
+    }
+
+    // Renders/executes a single dot
+    fn run_dot(&mut self) {
+        self.warmup_dots_left = self.warmup_dots_left.saturating_sub(1);
 
         match self.scanline {
             0..=239 => { // Visible cycles
+                if self.dot == 1 {
+                    self.line_scroll[self.scanline] = (self.v_reg, self.fine_x);
+                }
+
                 self.visible_scanline_cycle();
 
                 if self.dot == 257 {
@@ -167,15 +396,19 @@ impl Ppu2C02 {
                     self.sprite_evaluation();
                 }
             }
+            // The test pattern goes over the picture once it's been drawn
+            240 if self.test_pattern && self.dot == 0 => self.draw_test_pattern(),
             240 => {}, // Idle scanline (technically the start of vblank, but 
                        // the vblank flag isn't set until dot 1 of scanline 241)
             241 => { // Start of vblank
                 if self.dot == 1 {
-                    self.status.set_in_vblank(1);
-                
-                    if self.ctrl.vblank_nmi() == 1 {
-                        self.trigger_nmi();
+                    if self.vblank_suppressed {
+                        self.vblank_suppressed = false;
+                    } else {
+                        self.status.set_in_vblank(1);
+                        self.nmi_raised |= self.nmi_line();
                     }
+                    self.events.borrow_mut().publish(NesEvent::VblankStart);
                 }
             }
             242..=260 => {}, // Idle cycles
@@ -205,8 +438,12 @@ impl Ppu2C02 {
             self.mapper.borrow_mut().scanline_finished();
         }
 
+        if self.scanline < 240 && self.dot == 320 && self.rendering_enabled() {
+            self.mapper.borrow_mut().sprite_fetches_finished();
+        }
+
         if self.scanline < 240 && 0 < self.dot && self.dot <= 256 {
-            self.draw_dot(frame);
+            self.draw_dot();
         }
 
         self.finish_dot();
@@ -259,6 +496,8 @@ impl Ppu2C02 {
         }
     }
 
+    /// Finds the sprites on the next scanline and sets the sprite overflow
+    /// flag (see "ON SPRITE EVALUATION")
     fn sprite_evaluation(&mut self) {
         // Clear secondary OAM to 0xFF
         self.secondary_oam.fill(0xFF);
@@ -270,46 +509,73 @@ impl Ppu2C02 {
 
         let next_scanline = self.scanline + 1;
 
-        let sprite_height: u8 = if self.ctrl.spr_size() == 0 { 8 } else { 16 };
+        let sprite_height: usize = if self.ctrl.spr_size() == 0 { 8 } else { 16 };
+        let in_range = |sprite_y: u8| (sprite_y as usize <= next_scanline) && (next_scanline < sprite_y as usize + sprite_height);
+
         let mut sprites_found = 0;
         let mut sprite_index = 0;
-        
-        // 64 sprites in primary oam, each of 4 bytes
-        while sprites_found < 9 && sprite_index < 64 {
-            let sprite_y = self.primary_oam[sprite_index*4 + 0];
+        let mut overflow = false;
 
-            // Always copy first byte (might implement spr overflow bug later, likely not)
-            // if sprites_found < 8 {
-            //     self.secondary_oam[sprites_found*4 + 0] = sprite_y;
-            // }
+        // 64 sprites in primary oam, each of 4 bytes
+        while sprite_index < 64 {
+            let sprite = &self.primary_oam[sprite_index*4..sprite_index*4 + 4];
 
-            if (sprite_y as usize <= next_scanline) && 
-               (next_scanline < (sprite_y as usize + sprite_height as usize)) {
-                
+            if in_range(sprite[0]) {
                 if sprites_found < 8 {
                     if sprite_index == 0 {
                         self.spr_0_in_secondary_oam = true;
                     }
 
-                    self.secondary_oam[sprites_found*4 + 0] = sprite_y;
-                    self.secondary_oam[sprites_found*4 + 1] = self.primary_oam[sprite_index*4 + 1];
-                    self.secondary_oam[sprites_found*4 + 2] = self.primary_oam[sprite_index*4 + 2];
-                    self.secondary_oam[sprites_found*4 + 3] = self.primary_oam[sprite_index*4 + 3];
+                    self.secondary_oam[sprites_found*4..sprites_found*4 + 4].copy_from_slice(sprite);
+                } else if self.sprite_evaluation == SpriteEvaluation::Unlimited {
+                    let extra = (sprites_found - 8) * 4;
+                    self.extra_sprites[extra..extra + 4].copy_from_slice(sprite);
+                } else {
+                    overflow = true;
+                    break;
                 }
 
                 sprites_found += 1;
             }
 
             sprite_index += 1;
+
+            if sprites_found == 8 && self.sprite_evaluation == SpriteEvaluation::Authentic {
+                overflow = self.buggy_overflow_search(sprite_index, in_range);
+                break;
+            }
         }
 
         self.sprites_found = sprites_found;
+        if overflow || sprites_found > 8 {
+            self.status.set_spr_overflow(1);
+        }
+    }
+
+    /// The hardware's search for a 9th sprite once secondary OAM is full,
+    /// starting from the given sprite. Moving to the next sprite also moves
+    /// to the next byte in it, so after the first sprite it's checking the
+    /// wrong bytes as Y positions.
+    /// https://www.nesdev.org/wiki/PPU_sprite_evaluation
+    fn buggy_overflow_search(&self, mut sprite_index: usize, in_range: impl Fn(u8) -> bool) -> bool {
+        let mut byte = 0;
+
+        while sprite_index < 64 {
+            if in_range(self.primary_oam[sprite_index*4 + byte]) {
+                return true;
+            }
+
+            sprite_index += 1;
+            byte = (byte + 1) & 3;
+        }
+
+        false
     }
 
-    /// Draw to the given frame buffer at the current scanline and dot. This function
+    /// Draw to the frame buffer at the current scanline and dot. This function
     /// does not internally check to ensure the scanline and dot are within the bounds
     /// of the screen.
-    fn draw_dot(&mut self, frame: &mut [u8]) {
+    fn draw_dot(&mut self) {
         let mut bg_pix: u16 = 0;
         let mut bg_pal: u16 = 0;
 
@@ -351,7 +617,8 @@ impl Ppu2C02 {
                 let large_sprites = self.ctrl.spr_size() == 1;
                 let sprite_height: u16 = if large_sprites { 16 } else { 8 };
 
-                for (sprite_idx, sprite_data) in self.secondary_oam.chunks(4).enumerate() {                    
+                let sprites = self.secondary_oam.chunks(4).chain(self.extra_sprites.chunks(4));
+                for (sprite_idx, sprite_data) in sprites.enumerate() {
                     // No more sprites to check, so there won't be any spr_pix this dot
                     if sprite_idx >= self.sprites_found { break; }
 
@@ -426,6 +693,25 @@ impl Ppu2C02 {
             }
         }
 
+        // If we are drawing sprite 0 over an opaque background pixel, and we
+        // pass the sprite 0 hit check, we need to set the ppu status bit to
+        // alert the CPU of a spr 0 hit
+        if bg_pix > 0 && spr_pix > 0 && drawing_spr_0 && self.sprite_0_hit_check() && self.status.spr_0_hit() == 0 {
+            self.status.set_spr_0_hit(1);
+
+            let (scanline, dot) = (self.scanline, self.dot);
+            self.events.borrow_mut().publish(NesEvent::Sprite0Hit { scanline, dot });
+        }
+
+        // Hidden layers are only left out of the picture, after sprite 0 hit
+        // has been checked, so the game runs the same with them hidden
+        if self.hide_bg {
+            bg_pix = 0;
+        }
+        if self.hide_sprites {
+            spr_pix = 0;
+        }
+
         if bg_pix == 0 && spr_pix == 0 {
             // pixel and palette stay 0 bc we are drawing the universal bg color 
         } 
@@ -451,21 +737,15 @@ impl Ppu2C02 {
                 pixel = bg_pix;
                 palette = bg_pal;
             }
-
-            // If we are drawing sprite 0, and we pass the sprite 0 hit check,
-            // we need to set the ppu status bit to alert the CPU of a spr 0 hit
-            if drawing_spr_0 && self.sprite_0_hit_check() {
-                self.status.set_spr_0_hit(1);
-            }
         }
 
         let col = self.color_from_tile_data(palette, pixel);
         let pix_idx = (self.scanline * 256 + screen_pixel_x)*4;
 
-        frame[pix_idx + 0] = col.r;
-        frame[pix_idx + 1] = col.g;
-        frame[pix_idx + 2] = col.b;
-        frame[pix_idx + 3] = 0xFF;
+        self.frame[pix_idx + 0] = col.r;
+        self.frame[pix_idx + 1] = col.g;
+        self.frame[pix_idx + 2] = col.b;
+        self.frame[pix_idx + 3] = 0xFF;
     }
 }
 
@@ -494,7 +774,32 @@ impl Ppu2C02 {
     /// Takes in a 2 bit palette value and 2 bit pixel value and returns the
     /// color of the pixel as a NesColor
     fn color_from_tile_data(&self, palette: u16, pixel: u16) -> NesColor {
-        DEFAULT_PALETTE[self.ppu_read(0x3F00 | (palette << 2) | pixel) as usize & 0x3F]
+        self.output_color(self.ppu_read(0x3F00 | (palette << 2) | pixel), self.mask)
+    }
+
+    /// Turns a palette index into the color that comes out of the PPU with
+    /// the given PPUMASK settings: greyscale drops the hue, leaving only the
+    /// grey column ($x0), and the emphasis bits pick which version of the
+    /// system palette the color comes from.
+    /// https://www.nesdev.org/wiki/PPU_registers#PPUMASK
+    fn output_color(&self, index: u8, mask: PpuMask) -> NesColor {
+        let index = if mask.greyscale() == 1 { index & 0x30 } else { index & 0x3F };
+
+        self.system_palette[mask.emphasis()][index as usize]
+    }
+
+    /// Draws the test pattern over the whole picture, each pixel going
+    /// through output_color with the PPUMASK settings the pattern asks for
+    fn draw_test_pattern(&mut self) {
+        for y in 0..NES_SCREEN_HEIGHT {
+            for x in 0..NES_SCREEN_WIDTH {
+                let (index, mask) = test_pattern::pixel(x, y);
+                let col = self.output_color(index, PpuMask::from_bits(mask));
+                let pix_idx = (y * NES_SCREEN_WIDTH + x) * 4;
+
+                self.frame[pix_idx..pix_idx + 4].copy_from_slice(&[col.r, col.g, col.b, 0xFF]);
+            }
+        }
     }
 
     /// PPU reads a single byte from a given address. The ram/rom accessed 
@@ -510,10 +815,33 @@ impl Ppu2C02 {
     ///
     ///  * `address` - 16 bit address used to access data
     pub fn ppu_read(&self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+
         if let Some(data) = self.mapper.borrow_mut().ppu_cart_read(address) {
             return data;
         }
 
+        self.internal_read(address)
+    }
+
+    /// Reads a byte from PPU address space the same way ppu_read does, but
+    /// without any side effects (like MMC2's CHR latches flipping), for the
+    /// debugger to use.
+    ///
+    ///  * `address` - 16 bit address used to access data
+    pub fn peek_ppu(&self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+
+        if let Some(data) = self.mapper.borrow().ppu_cart_peek(address) {
+            return data;
+        }
+
+        self.internal_read(address)
+    }
+
+    /// Reads from the memory inside the PPU (nametables and palettes), for
+    /// addresses the mapper doesn't take
+    fn internal_read(&self, address: u16) -> u8 {
         match address & 0x3FFF {
             0x0000..=0x1FFF => {
                 0x00 // Mapper should take care of this address range
@@ -551,6 +879,8 @@ impl Ppu2C02 {
     ///  * `address` - 16 bit address used to access data
     ///  * `data` - Single byte of data to write
     pub fn ppu_write(&mut self, address: u16, data: u8) {
+        let address = address & 0x3FFF;
+
         if self.mapper.borrow_mut().ppu_cart_write(address, data) {
             return;
         }
@@ -562,6 +892,7 @@ impl Ppu2C02 {
             0x2000..=0x3EFF => {
                 let mirrored_addr = self.get_nt_mirrored_address(address);
                 self.vram[mirrored_addr as usize] = data;
+                self.vram_write_frames[mirrored_addr as usize] = self.frame_count + 1;
             },
             0x3F00..=0x3FFF => {
                 let mirrored_addr = address & 0x1F;
@@ -608,6 +939,14 @@ impl Ppu2C02 {
 
             // PPUSTATUS
             2 => {
+                // Reading right as vblank starts races the flag being set (see
+                // "ON HOW THE CPU AND PPU KEEP IN STEP")
+                match (self.scanline, self.dot) {
+                    (241, 1) => self.vblank_suppressed = true,
+                    (241, 2..=3) => self.nmi_raised = false,
+                    _ => {}
+                }
+
                 let data = self.status_val();
                 // Reads from $2002 reset write latch and vblank flag (after the read occurs)
                 self.write_latch = 0;
@@ -640,21 +979,23 @@ impl Ppu2C02 {
                 // Reads are too slow for the PPU to respond immediatly, so they
                 // go to a read buffer. With the exception (because of course
                 // there's an exception) of palette memory, which responds
-                // immediatly and still updates the read buffer, discarding the
-                // old read buffer data.
-                let mut data = self.read_buffer;
-                self.read_buffer = self.ppu_read(self.v_val());
-
-                if self.v_val() >= 0x3F00 {
-                    data = self.read_buffer;
-                }
+                // immediatly. The read buffer still gets filled, but with the
+                // nametable byte "underneath" the palette ($2F00-$2FFF, which
+                // mirrors whichever nametable the cart maps there), since the
+                // palette isn't on the PPU's address bus.
+                // https://www.nesdev.org/wiki/PPU_registers#The_PPUDATA_read_buffer
+                let address = self.vram_address();
+
+                let data = if address >= 0x3F00 {
+                    self.read_buffer = self.ppu_read(address - 0x1000);
+                    self.ppu_read(address)
+                } else {
+                    let data = self.read_buffer;
+                    self.read_buffer = self.ppu_read(address);
+                    data
+                };
 
-                // if self.status().in_vblank() == 1 {
-                //     self.inc_coarse_x();
-                //     self.inc_coarse_y();
-                // } else {
-                self.set_v_reg(self.v_val() + if self.ctrl.vram_addr_inc() == 0 { 1 } else { 32 });
-                // }
+                self.increment_vram_address();
 
                 data
             },
@@ -664,18 +1005,66 @@ impl Ppu2C02 {
         }
     }
 
+    /// What reading a PPU register from the CPU would return right now, without
+    /// any of the side effects of reading it (PPUSTATUS clearing vblank and the
+    /// write latch, PPUDATA filling the read buffer and incrementing v)
+    pub fn peek_cpu(&self, address: u16) -> u8 {
+        match address & 0x0007 {
+            // PPUSTATUS
+            2 => self.status_val(),
+
+            // OAMDATA
+            4 => {
+                if self.oam_address & 3 == 2 {
+                    self.primary_oam[self.oam_address as usize] & 0xE3
+                } else {
+                    self.primary_oam[self.oam_address as usize]
+                }
+            },
+
+            // PPUDATA (palette reads skip the read buffer)
+            7 => {
+                if self.vram_address() >= 0x3F00 {
+                    self.peek_ppu(self.vram_address())
+                } else {
+                    self.read_buffer
+                }
+            },
+
+            // Write-only registers
+            _ => 0xEE,
+        }
+    }
+
     // READS/WRITES FROM CPU:
 
     /// Write a single byte to the PPU Registers. Internal Registers cannot be
     /// written to, and some registers depend on the internal write latch to
     /// determine which byte is being written.
     pub fn cpu_write(&mut self, address: u16, data: u8) {
+        // PPUCTRL, PPUMASK, PPUSCROLL and PPUADDR don't take writes while the
+        // PPU is warming up (and the write latch doesn't flip either)
+        if self.warmup_dots_left > 0 && matches!(address & 0x0007, 0 | 1 | 5 | 6) {
+            return;
+        }
+
+        if self.write_assert && matches!(address & 0x0007, 0 | 5 | 6) {
+            self.check_raster_write(address, data);
+        }
+
+        let t_before = (self.t_reg, self.fine_x);
+        let v_before = (self.v_reg, self.fine_x);
+
         match address & 0x0007 {
             // PPUCTRL
             0 => {
+                // Turning NMIs on partway through vblank causes one straight away
+                let nmi_before = self.nmi_line();
+
                 // t: ...GH.. ........ <- d: ......GH
                 //    <used elsewhere> <- d: ABCDEF..
                 self.set_ctrl(data);
+                self.nmi_raised |= self.nmi_line() && !nmi_before;
                 self.t_reg.set_nt_select((data & 3) as usize);
             },
 
@@ -754,17 +1143,21 @@ impl Ppu2C02 {
 
             // PPUDATA
             7 => {
-                self.ppu_write(self.v_val(), data);
-
-                // if self.status().in_vblank() == 1 {
-                //     self.inc_coarse_x();
-                //     self.inc_coarse_y();
-                // } else {
-                self.set_v_reg(self.v_val() + if self.ctrl.vram_addr_inc() == 0 { 1 } else { 32 });
-                // }
+                self.ppu_write(self.vram_address(), data);
+                self.increment_vram_address();
             },
             _ => unreachable!("Well done. Here are the test results: \"You are a horrible person.\" I'm serious, that's what it says: \"A horrible person.\" We weren't even testing for that."),
         };
+
+        // Both t and v are checked since PPUCTRL/PPUSCROLL only change t (which
+        // gets copied into v later in the line), while PPUADDR changes v too
+        if matches!(address & 0x0007, 0 | 5 | 6) && self.scanline < 240 && self.rendering_enabled() {
+            let t_split = ScrollSplit::between(t_before, (self.t_reg, self.fine_x));
+            let v_split = ScrollSplit::between(v_before, (self.v_reg, self.fine_x));
+
+            let line = &mut self.current_scroll_splits[self.scanline];
+            *line = ScrollSplit::from_bits(line.into_bits() | t_split.into_bits() | v_split.into_bits());
+        }
     }
 
     // pub fn oam_dma_write(&mut self, oam_data: u8, addr: u8) {
@@ -785,6 +1178,37 @@ impl Ppu2C02 {
         }
     }
 
+    /// The address PPUDATA reads and writes. The PPU's address bus is only 14
+    /// bits wide, so the top bit of v (which holds part of fine Y while
+    /// rendering) doesn't reach it, and everything above $3FFF mirrors
+    /// $0000-$3FFF.
+    /// https://www.nesdev.org/wiki/PPU_memory_map
+    fn vram_address(&self) -> u16 {
+        self.v_val() & 0x3FFF
+    }
+
+    /// Moves v on after a PPUDATA read or write, by 1 or 32 depending on
+    /// PPUCTRL. While the PPU is fetching tiles it's using v itself, and the
+    /// access bumps coarse X and Y at the same time (as if both of rendering's
+    /// increments happened at once) instead, which is what games that touch
+    /// PPUDATA mid-frame by accident actually get.
+    /// https://www.nesdev.org/wiki/PPU_scrolling#$2007_reads_and_writes
+    fn increment_vram_address(&mut self) {
+        if self.fetching() {
+            self.inc_coarse_x();
+            self.inc_coarse_y();
+        } else {
+            let step = if self.ctrl.vram_addr_inc() == 0 { 1 } else { 32 };
+            self.set_v_reg(self.v_val().wrapping_add(step) & 0x7FFF);
+        }
+    }
+
+    /// Whether the PPU is fetching tiles, which it does on the visible
+    /// scanlines and the pre-render line while rendering is enabled
+    fn fetching(&self) -> bool {
+        (self.scanline < 240 || self.scanline == 261) && self.rendering_enabled()
+    }
+
     /// Increment the coarse x value in the v register. Also handles wrap around
     /// cases when the value of coarse x overflows. For more details, visit
     /// https://www.nesdev.org/wiki/PPU_scrolling#Wrapping_around
@@ -890,11 +1314,6 @@ impl Ppu2C02 {
             + (fine_y as u16 + 8)); // fixed: was setting tile_lsb instead of tile_msb
     }
 
-    /// Trigger a NMI within the CPU
-    fn trigger_nmi(&mut self) {
-        self.cpu_nmi_flag = true;
-    }
-
     fn sprite_0_hit_check(&self) -> bool {
         // Spr 0 hit cannot happen if bg or spr rendering disabled
         if self.mask.draw_bg() == 0 || self.mask.draw_sprites() == 0 {
@@ -931,6 +1350,29 @@ impl Ppu2C02 {
         }
     }
 
+    // Logs writes to PPUCTRL/PPUSCROLL/PPUADDR that happen while the PPU is
+    // drawing (the visible scanlines and the pre-render line). Writes made
+    // with rendering turned off are left alone, since nothing is being drawn
+    // for them to mess up.
+    fn check_raster_write(&mut self, address: u16, data: u8) {
+        if !self.fetching() {
+            return;
+        }
+
+        let write = RasterWrite {
+            address: 0x2000 | (address & 0x0007),
+            data,
+            scanline: self.scanline,
+            dot: self.dot,
+            pc: self.cpu_pc,
+        };
+
+        println!("PPU write outside vblank: {} (${:04X}) <- ${:02X} at scanline {}, dot {} (PC ${:04X})",
+            write.register_name(), write.address, write.data, write.scanline, write.dot, write.pc);
+
+        self.current_raster_writes.push(write);
+    }
+
     fn finish_dot(&mut self) {
         self.dot += 1;
         if self.dot > 340 {
@@ -939,7 +1381,14 @@ impl Ppu2C02 {
             self.scanline += 1;
             if self.scanline > 261 {
                 self.scanline = 0;
-                self.frame_finished = true;
+                self.events.borrow_mut().publish(NesEvent::FrameReady);
+                self.frame_count += 1;
+
+                std::mem::swap(&mut self.current_raster_writes, &mut self.last_raster_writes);
+                self.current_raster_writes.clear();
+
+                std::mem::swap(&mut self.current_scroll_splits, &mut self.last_scroll_splits);
+                self.current_scroll_splits.fill(ScrollSplit::new());
 
                 if self.odd_frame && self.rendering_enabled() {
                     self.dot = 1; // Skip one cycle on odd frames if rendering
@@ -951,18 +1400,125 @@ impl Ppu2C02 {
     }
 }
 
+// Save states
+impl Ppu2C02 {
+    /// Saves the PPU's registers, memory and rendering state as a save state
+    /// block (see savestate.rs). The picture being drawn and the debug views'
+    /// history aren't kept, since states are saved between frames.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut payload = StateWriter::new();
+
+        payload.write_usize(self.dot);
+        payload.write_usize(self.scanline);
+        payload.write_bool(self.odd_frame);
+        payload.write_u64(self.frame_count);
+
+        payload.write_u8(self.ctrl.into());
+        payload.write_u8(self.mask.into());
+        payload.write_u8(self.status.into());
+        payload.write_u8(self.oam_address);
+        payload.write_u16(self.v_reg.into());
+        payload.write_u16(self.t_reg.into());
+        payload.write_u8(self.fine_x);
+        payload.write_u8(self.read_buffer);
+        payload.write_u8(self.write_latch);
+
+        payload.write_bytes(&self.vram);
+        payload.write_bytes(&self.palette_mem);
+        payload.write_bytes(&self.primary_oam);
+        payload.write_bytes(&self.secondary_oam);
+        payload.write_bool(self.spr_0_in_secondary_oam);
+        payload.write_usize(self.sprites_found);
+
+        for latch in [self.bg_next_tile_nt_addr, self.bg_next_tile_attrib, self.bg_next_tile_lsb, self.bg_next_tile_msb] {
+            payload.write_u8(latch);
+        }
+        for shift_reg in [self.bg_tile_nt_hi, self.bg_tile_nt_lo, self.bg_tile_attrib_hi, self.bg_tile_attrib_lo] {
+            payload.write_u16(shift_reg);
+        }
+
+        payload.write_usize(self.warmup_dots_left);
+        payload.write_usize(self.dots_ahead);
+        payload.write_bool(self.nmi_raised);
+        payload.write_bool(self.vblank_suppressed);
+
+        let mut block = StateWriter::new();
+        block.write_block(PPU_STATE_MAGIC, PPU_STATE_VERSION, &payload.into_bytes());
+
+        block.into_bytes()
+    }
+
+    /// Restores a block written by save_state
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut reader = StateReader::new(bytes);
+        let (version, mut payload) = reader.read_block(PPU_STATE_MAGIC)?;
+
+        if version > PPU_STATE_VERSION {
+            return Err(format!(
+                "PPU state version {version} is newer than this emulator supports ({PPU_STATE_VERSION})"
+            ));
+        }
+
+        self.dot = payload.read_usize()?;
+        self.scanline = payload.read_usize()?;
+        if self.dot > 340 || self.scanline > 261 {
+            return Err(format!("PPU state is on a dot that doesn't exist ({}, {})", self.scanline, self.dot));
+        }
+        self.odd_frame = payload.read_bool()?;
+        self.frame_count = payload.read_u64()?;
+
+        self.ctrl = payload.read_u8()?.into();
+        self.mask = payload.read_u8()?.into();
+        self.status = payload.read_u8()?.into();
+        self.oam_address = payload.read_u8()?;
+        self.v_reg = payload.read_u16()?.into();
+        self.t_reg = payload.read_u16()?.into();
+        self.fine_x = payload.read_u8()?;
+        self.read_buffer = payload.read_u8()?;
+        self.write_latch = payload.read_u8()?;
+
+        payload.read_bytes_into(&mut self.vram)?;
+        payload.read_bytes_into(&mut self.palette_mem)?;
+        payload.read_bytes_into(&mut self.primary_oam)?;
+        payload.read_bytes_into(&mut self.secondary_oam)?;
+        self.spr_0_in_secondary_oam = payload.read_bool()?;
+        // Unlimited sprite evaluation's extra sprites aren't kept, but states
+        // are saved between frames, when there are none to draw
+        self.sprites_found = payload.read_usize()?.min(8);
+
+        self.bg_next_tile_nt_addr = payload.read_u8()?;
+        self.bg_next_tile_attrib = payload.read_u8()?;
+        self.bg_next_tile_lsb = payload.read_u8()?;
+        self.bg_next_tile_msb = payload.read_u8()?;
+        self.bg_tile_nt_hi = payload.read_u16()?;
+        self.bg_tile_nt_lo = payload.read_u16()?;
+        self.bg_tile_attrib_hi = payload.read_u16()?;
+        self.bg_tile_attrib_lo = payload.read_u16()?;
+
+        self.warmup_dots_left = payload.read_usize()?;
+        self.dots_ahead = payload.read_usize()?;
+        self.nmi_raised = payload.read_bool()?;
+        self.vblank_suppressed = payload.read_bool()?;
+
+        Ok(())
+    }
+}
+
 // Public functionality
 impl Ppu2C02 {
+    /// What happens to the PPU when the reset button is pressed. PPUCTRL,
+    /// PPUMASK, the scroll and the write latch are cleared, but PPUSTATUS,
+    /// OAMADDR and the VRAM address (along with all of the PPU's memory) are
+    /// left alone.
+    /// https://www.nesdev.org/wiki/PPU_power_up_state
     pub fn reset(&mut self) {
         self.dot = 0;
         self.scanline = 0;
+        self.odd_frame = false;
 
         self.set_ctrl(0);
         self.set_mask(0);
-        self.set_status(0);
-        self.oam_address = 0;
-        
-        self.set_v_reg(0);
+
         self.set_t_reg(0);
         self.fine_x = 0;
         self.read_buffer = 0;
@@ -977,6 +1533,33 @@ impl Ppu2C02 {
         self.bg_tile_nt_lo = 0;
         self.bg_tile_attrib_hi = 0;
         self.bg_tile_attrib_lo = 0;
+
+        self.dots_ahead = 0;
+        self.vblank_suppressed = false;
+
+        self.start_warmup();
+    }
+
+    /// Turns the PPU's warm-up period on or off. For the first ~29658 CPU
+    /// cycles after power on or reset, the real PPU ignores writes to
+    /// PPUCTRL, PPUMASK, PPUSCROLL and PPUADDR, and games are supposed to wait
+    /// for two vblanks before touching it. Some games and test ROMs break (or
+    /// check for this) if the writes go through. Once on, the period starts
+    /// with the next reset or start_warmup.
+    /// https://www.nesdev.org/wiki/PPU_power_up_state
+    pub fn set_warmup(&mut self, val: bool) {
+        self.warmup = val;
+
+        if !val {
+            self.warmup_dots_left = 0;
+        }
+    }
+
+    /// Starts the warm-up period over, if it's turned on. Called at power on.
+    pub fn start_warmup(&mut self) {
+        if self.warmup {
+            self.warmup_dots_left = WARMUP_DOTS;
+        }
     }
 
     // GETTER / SETTER FUNCTIONS
@@ -988,32 +1571,49 @@ impl Ppu2C02 {
     pub fn get_scanline(&self) -> usize {
         self.scanline
     }
-    /// Get the current state of the frame finished flag
-    pub fn frame_finished(&self) -> bool {
-        self.frame_finished
-    }
     /// Return a bool reporting whether either sprites or background tiles are
     /// currently being rendered by the PPU.
     pub fn rendering_enabled(&self) -> bool {
         self.mask.draw_bg() == 1 || self.mask.draw_sprites() == 1
     }
-    pub fn cpu_nmi_flag(&self) -> bool {
-        self.cpu_nmi_flag
+    /// The state of the PPU's NMI output. It's held for as long as the vblank
+    /// flag is set with NMIs enabled, so enabling NMIs partway through vblank
+    /// (or reading $2002 right as it starts) does the same thing it does on
+    /// the real console. The CPU only reacts when this goes from off to on.
+    /// https://www.nesdev.org/wiki/NMI
+    pub fn nmi_line(&self) -> bool {
+        self.status.in_vblank() == 1 && self.ctrl.vblank_nmi() == 1
+    }
+    /// Whether the NMI output has gone from off to on since this was last
+    /// called. It can go on and back off while the PPU is running ahead of the
+    /// clock, where nmi_line alone would miss it.
+    pub fn take_nmi_raised(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_raised)
+    }
+    /// Swaps the picture being drawn for another buffer, giving back the
+    /// finished one
+    pub fn swap_frame(&mut self, frame: &mut Box<[u8; NES_SCREEN_BUF_SIZE]>) {
+        std::mem::swap(&mut self.frame, frame);
     }
     pub fn initiate_dma(&self) -> bool {
         self.initiate_dma
     }
     
-    /// Set the value of the frame finished flag
-    pub fn set_frame_finished(&mut self, val: bool) {
-        self.frame_finished = val;
-    }
-    pub fn set_cpu_nmi_flag(&mut self, val: bool) {
-        self.cpu_nmi_flag = val;
+    /// Set the event bus to publish on (see events)
+    pub fn set_event_bus(&mut self, events: SharedEventBus) {
+        self.events = events;
     }
     pub fn set_initiate_dma(&mut self, val: bool) {
         self.initiate_dma = val;
     }
+    /// Replace the system palette used to turn palette indices into colors
+    pub fn set_system_palette(&mut self, palette: SystemPalette) {
+        self.system_palette = palette;
+    }
+    /// Draw the test pattern in place of the picture
+    pub fn set_test_pattern(&mut self, val: bool) {
+        self.test_pattern = val;
+    }
 }
 
 // Getters & Setters (more helper functionality)
@@ -1021,6 +1621,27 @@ impl Ppu2C02 {
     // GETTER / SETTER FUNCTIONS FOR PPU USE
 
     /// Get a copy of the value of PPUCTRL as a u8
+    /// The sprites in OAM, 4 bytes each (Y, tile, attributes, X)
+    pub fn oam(&self) -> &[u8; PRIMARY_OAM_SIZE] {
+        &self.primary_oam
+    }
+    pub fn oam_mut(&mut self) -> &mut [u8; PRIMARY_OAM_SIZE] {
+        &mut self.primary_oam
+    }
+    /// The 2KiB of nametable RAM inside the console, before mirroring
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+    pub fn vram_mut(&mut self) -> &mut [u8] {
+        &mut self.vram
+    }
+    /// Palette RAM as it's stored, without the greyscale bit applied
+    pub fn palette_mem(&self) -> &[u8] {
+        &self.palette_mem
+    }
+    pub fn palette_mem_mut(&mut self) -> &mut [u8] {
+        &mut self.palette_mem
+    }
     pub fn ctrl_val(&self) -> u8 {
         self.ctrl.clone().into_bits()
     }
@@ -1058,25 +1679,423 @@ impl Ppu2C02 {
         self.t_reg = val.into();
     }
 
+    /// Get the background shift registers, in the order pattern low, pattern
+    /// high, attribute low, attribute high. The upper byte of each is the tile
+    /// being drawn and the lower byte is the next tile.
+    pub fn bg_shift_regs(&self) -> [u16; 4] {
+        [self.bg_tile_nt_lo, self.bg_tile_nt_hi, self.bg_tile_attrib_lo, self.bg_tile_attrib_hi]
+    }
+    /// Get the latches holding the next background tile before it's loaded into
+    /// the shift registers, in the order nametable byte, attribute, pattern
+    /// low, pattern high.
+    pub fn bg_next_tile_latches(&self) -> [u8; 4] {
+        [self.bg_next_tile_nt_addr, self.bg_next_tile_attrib, self.bg_next_tile_lsb, self.bg_next_tile_msb]
+    }
+    /// Get the fine x scroll, which picks which bit of the shift registers is
+    /// drawn
+    pub fn fine_x(&self) -> u8 {
+        self.fine_x
+    }
+    /// Get the dot & scanline the PPU is currently on
+    pub fn position(&self) -> (usize, usize) {
+        (self.dot, self.scanline)
+    }
+    /// Get the number of frames the PPU has finished since power on
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn write_assert(&self) -> bool {
+        self.write_assert
+    }
+    pub fn set_write_assert(&mut self, val: bool) {
+        self.write_assert = val;
+
+        if !self.write_assert {
+            self.current_raster_writes.clear();
+            self.last_raster_writes.clear();
+        }
+    }
+    /// Keeps the background out of the picture, whatever PPUMASK says. Only
+    /// the picture changes, the game can't tell.
+    pub fn set_bg_hidden(&mut self, val: bool) {
+        self.hide_bg = val;
+    }
+    /// Keeps the sprites out of the picture, whatever PPUMASK says. Only the
+    /// picture changes, the game can't tell.
+    pub fn set_sprites_hidden(&mut self, val: bool) {
+        self.hide_sprites = val;
+    }
+    /// Sets how sprites are picked for each scanline (see "ON SPRITE
+    /// EVALUATION"). Takes effect from the next scanline.
+    pub fn set_sprite_evaluation(&mut self, evaluation: SpriteEvaluation) {
+        self.sprite_evaluation = evaluation;
+    }
+    /// Tells the PPU which instruction the CPU is about to run, so writes it
+    /// flags can say where they came from
+    pub fn set_cpu_pc(&mut self, pc: u16) {
+        self.cpu_pc = pc;
+    }
+    /// What the game changed about the scroll position on each visible
+    /// scanline of the last frame, through writes to PPUCTRL, PPUSCROLL or
+    /// PPUADDR. Status bars and parallax show up as changes partway down.
+    /// https://www.nesdev.org/wiki/PPU_scrolling#Split_X_scroll
+    pub fn scroll_splits(&self) -> &[ScrollSplit; 240] {
+        &self.last_scroll_splits
+    }
+    /// The register writes flagged during the last full frame
+    pub fn raster_writes(&self) -> &[RasterWrite] {
+        &self.last_raster_writes
+    }
+
     pub fn using_large_sprites(&self) -> bool {
         self.ctrl.spr_size() == 1
     }
 
     pub fn get_pgtbl1(&mut self) -> Box<[u8; 0x1000]> {
-        // Read pagetable memories into arrays for debug view
+        // Read pagetable memories into arrays for debug view. Peeked, since
+        // reading some addresses flips MMC2's latches.
         for i in 0..0x1000 {
-            self.pgtbl1[i as usize] = self.ppu_read(i);
+            self.pgtbl1[i as usize] = self.peek_ppu(i);
         }
 
         self.pgtbl1.clone()
     }
 
+    /// How recently the nametable and attribute bytes behind each background
+    /// pixel of the last frame were written, from 255 (written this frame) down
+    /// to 0 (written `fade_frames` or more frames ago, or never). One byte per
+    /// pixel, row by row. Uses the scroll each scanline started with, so
+    /// mid-frame scroll splits are followed.
+    pub fn bg_write_heat(&self, fade_frames: u64) -> Vec<u8> {
+        let mut heat = vec![0; NES_SCREEN_WIDTH * NES_SCREEN_HEIGHT];
+
+        let heat_of = |address: u16| -> u64 {
+            let mirrored_addr = self.get_nt_mirrored_address(address) as usize;
+            let written = self.vram_write_frames[mirrored_addr];
+
+            if written == 0 {
+                return 0;
+            }
+
+            let age = self.frame_count.saturating_sub(written - 1);
+            fade_frames.saturating_sub(age) * 255 / fade_frames.max(1)
+        };
+
+        for (y, (v, fine_x)) in self.line_scroll.iter().enumerate() {
+            // By the time a scanline starts, the first two tiles have already
+            // been fetched, so v is two tiles ahead of the left edge
+            let start_x = (v.coarse_x() * 8 + v.nt_x() * 256 + *fine_x as usize + 512 - 16) % 512;
+
+            for x in 0..NES_SCREEN_WIDTH {
+                let scroll_x = (start_x + x) % 512;
+                let tile_x = (scroll_x % 256) / 8;
+                let nt_select = ((v.nt_y() << 1) | (scroll_x / 256)) as u16;
+                let coarse_y = v.coarse_y();
+
+                let nt_addr = 0x2000 | (nt_select << 10) | ((coarse_y as u16) << 5) | tile_x as u16;
+                let attrib_addr = 0x23C0 | (nt_select << 10) | (((coarse_y >> 2) << 3) | (tile_x >> 2)) as u16;
+
+                heat[y * NES_SCREEN_WIDTH + x] = heat_of(nt_addr).max(heat_of(attrib_addr)) as u8;
+            }
+        }
+
+        heat
+    }
+
+    /// The four colors of one of the palettes in palette RAM, 0-3 being the
+    /// background palettes and 4-7 the sprite palettes. Color 0 of every
+    /// palette is the shared backdrop color.
+    /// https://www.nesdev.org/wiki/PPU_palettes
+    pub fn palette_colors(&self, palette: u8) -> [NesColor; 4] {
+        [0, 1, 2, 3].map(|pixel| self.color_from_tile_data((palette & 7) as u16, pixel))
+    }
+
     pub fn get_pgtbl2(&mut self) -> Box<[u8; 0x1000]> {
-        // Read pagetable memories into arrays for debug view
+        // Read pagetable memories into arrays for debug view. Peeked, since
+        // reading some addresses flips MMC2's latches.
         for i in 0..0x1000 {
-            self.pgtbl2[i as usize] = self.ppu_read(i + 0x1000);
+            self.pgtbl2[i as usize] = self.peek_ppu(i + 0x1000);
         }
 
         self.pgtbl2.clone()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{cartridge::Cartridge, mapper};
+    use crate::system::test_pattern;
+
+    // A PPU with the test pattern cart (NROM, with a blank CHR ROM bank)
+    // plugged in
+    fn ppu() -> Ppu2C02 {
+        let cart = Cartridge::from_bytes(&test_pattern::rom()).unwrap();
+
+        Ppu2C02::new(mapper::mapper_from_cart(cart).unwrap())
+    }
+
+    fn set_address(ppu: &mut Ppu2C02, address: u16) {
+        ppu.cpu_write(0x2006, (address >> 8) as u8);
+        ppu.cpu_write(0x2006, address as u8);
+    }
+
+    fn write(ppu: &mut Ppu2C02, address: u16, data: u8) {
+        set_address(ppu, address);
+        ppu.cpu_write(0x2007, data);
+    }
+
+    fn read(ppu: &mut Ppu2C02, address: u16) -> u8 {
+        set_address(ppu, address);
+        ppu.cpu_read(0x2007)
+    }
+
+    #[test]
+    fn reads_below_the_palette_are_buffered() {
+        let mut ppu = ppu();
+        write(&mut ppu, 0x2005, 0x11);
+        write(&mut ppu, 0x2006, 0x22);
+
+        set_address(&mut ppu, 0x2005);
+        ppu.cpu_read(0x2007);
+
+        assert_eq!(ppu.cpu_read(0x2007), 0x11);
+        assert_eq!(ppu.cpu_read(0x2007), 0x22);
+    }
+
+    #[test]
+    fn palette_reads_are_immediate() {
+        let mut ppu = ppu();
+        write(&mut ppu, 0x3F05, 0x2A);
+        write(&mut ppu, 0x3F11, 0x16);
+
+        assert_eq!(read(&mut ppu, 0x3F05), 0x2A);
+        assert_eq!(read(&mut ppu, 0x3F11), 0x16);
+        // Mirrored every 32 bytes up to $3FFF
+        assert_eq!(read(&mut ppu, 0x3FE5), 0x2A);
+    }
+
+    #[test]
+    fn palette_reads_fill_the_buffer_from_the_nametable_underneath() {
+        let mut ppu = ppu();
+        write(&mut ppu, 0x2F05, 0xAB);
+        write(&mut ppu, 0x2F25, 0xCD);
+        write(&mut ppu, 0x3F05, 0x2A);
+
+        assert_eq!(read(&mut ppu, 0x3F05), 0x2A);
+        // The next read gets the buffer, which should hold the byte at $2F05
+        // and not the palette entry
+        assert_eq!(read(&mut ppu, 0x2000), 0xAB);
+
+        // The whole $3F00-$3FFF range sits over $2F00-$2FFF, not just the
+        // first 32 bytes
+        read(&mut ppu, 0x3F25);
+        assert_eq!(read(&mut ppu, 0x2000), 0xCD);
+    }
+
+    #[test]
+    fn palette_reads_ignore_the_old_buffer() {
+        let mut ppu = ppu();
+        write(&mut ppu, 0x2100, 0x77);
+        write(&mut ppu, 0x3F00, 0x0F);
+
+        // Leaves $77 in the buffer, which the palette read shouldn't return
+        read(&mut ppu, 0x2100);
+        assert_eq!(read(&mut ppu, 0x3F00), 0x0F);
+    }
+
+    #[test]
+    fn addresses_above_3fff_mirror_down() {
+        let mut ppu = ppu();
+        write(&mut ppu, 0x2005, 0x5A);
+        read(&mut ppu, 0x2100);
+
+        // v with fine Y in its top bits still only reaches $2005, so this is
+        // a buffered nametable read and not a palette read
+        ppu.set_v_reg(0x6005);
+        assert_eq!(ppu.cpu_read(0x2007), 0x00);
+        assert_eq!(ppu.cpu_read(0x2007), 0x5A);
+
+        ppu.set_v_reg(0x7F05);
+        ppu.cpu_write(0x2007, 0x21);
+        assert_eq!(read(&mut ppu, 0x3F05), 0x21);
+    }
+
+    #[test]
+    fn accesses_outside_rendering_increment_normally() {
+        let mut ppu = ppu();
+
+        set_address(&mut ppu, 0x2000);
+        ppu.cpu_write(0x2007, 0);
+        assert_eq!(ppu.v_val(), 0x2001);
+
+        ppu.cpu_write(0x2000, 0x04);
+        ppu.cpu_read(0x2007);
+        assert_eq!(ppu.v_val(), 0x2021);
+    }
+
+    #[test]
+    fn accesses_while_rendering_increment_coarse_x_and_y() {
+        let mut ppu = ppu();
+        set_address(&mut ppu, 0x2000);
+
+        // Rendering on, and the PPU starts out on scanline 0
+        ppu.cpu_write(0x2001, 0x08);
+        ppu.cpu_write(0x2007, 0);
+        // Coarse X 0 -> 1 and fine Y 0 -> 1, whatever PPUCTRL says
+        assert_eq!(ppu.v_val(), 0x3001);
+
+        ppu.cpu_write(0x2000, 0x04);
+        ppu.cpu_read(0x2007);
+        assert_eq!(ppu.v_val(), 0x4002);
+    }
+
+    #[test]
+    fn reset_clears_control_but_keeps_the_address() {
+        let mut ppu = ppu();
+        ppu.cpu_write(0x2000, 0x80);
+        ppu.cpu_write(0x2001, 0x1E);
+        ppu.set_status(0x80);
+        ppu.cpu_write(0x2003, 0x40);
+        set_address(&mut ppu, 0x2345);
+        // Half of a PPUADDR write, left hanging
+        ppu.cpu_write(0x2006, 0x3F);
+
+        ppu.reset();
+
+        assert_eq!(ppu.ctrl_val(), 0);
+        assert_eq!(ppu.mask.clone().into_bits(), 0);
+        assert_eq!(ppu.status_val() & 0x80, 0x80);
+        assert_eq!(ppu.oam_address, 0x40);
+        assert_eq!(ppu.v_val(), 0x2345);
+        assert_eq!(ppu.t_val(), 0);
+
+        // The write latch was cleared, so this is a whole new address
+        set_address(&mut ppu, 0x2100);
+        assert_eq!(ppu.v_val(), 0x2100);
+    }
+
+    #[test]
+    fn reset_restarts_the_warmup() {
+        let mut ppu = ppu();
+        ppu.set_warmup(true);
+
+        ppu.reset();
+        ppu.cpu_write(0x2000, 0x80);
+        assert_eq!(ppu.ctrl_val(), 0);
+
+        // Without the warm-up, writes go through straight after a reset
+        ppu.set_warmup(false);
+        ppu.reset();
+        ppu.cpu_write(0x2000, 0x80);
+        assert_eq!(ppu.ctrl_val(), 0x80);
+    }
+
+    // Runs the PPU until the given dot is the next one it'll run
+    fn run_to(ppu: &mut Ppu2C02, scanline: usize, dot: usize) {
+        while (ppu.get_scanline(), ppu.get_dot()) != (scanline, dot) {
+            ppu.cycle();
+        }
+    }
+
+    // Reads PPUSTATUS on the given dot of vblank's first scanline, returning
+    // whether the flag read back set and whether NMI happened by the end of
+    // the scanline
+    fn read_status_at(dot: usize) -> (bool, bool) {
+        let mut ppu = ppu();
+        ppu.cpu_write(0x2000, 0x80);
+
+        run_to(&mut ppu, 241, dot);
+        let in_vblank = ppu.cpu_read(0x2002) & 0x80 != 0;
+        let mut nmi = ppu.take_nmi_raised();
+
+        run_to(&mut ppu, 242, 0);
+        nmi |= ppu.take_nmi_raised();
+
+        (in_vblank, nmi)
+    }
+
+    #[test]
+    fn reading_status_as_vblank_starts_suppresses_nmi() {
+        assert_eq!(read_status_at(0), (false, true), "2 dots before");
+        assert_eq!(read_status_at(1), (false, false), "1 dot before");
+        assert_eq!(read_status_at(2), (true, false), "on the dot");
+        assert_eq!(read_status_at(3), (true, false), "1 dot after");
+        assert_eq!(read_status_at(4), (true, true), "2 dots after");
+    }
+
+    #[test]
+    fn dots_run_early_are_skipped() {
+        let mut ppu = ppu();
+        run_to(&mut ppu, 100, 10);
+
+        ppu.catch_up(9);
+        assert_eq!((ppu.get_scanline(), ppu.get_dot()), (100, 19));
+
+        // Catching up to where it already is does nothing, and the clock
+        // doesn't move it until it's caught up
+        ppu.catch_up(6);
+        for _ in 0..9 {
+            ppu.cycle();
+        }
+        assert_eq!((ppu.get_scanline(), ppu.get_dot()), (100, 19));
+        ppu.cycle();
+        assert_eq!((ppu.get_scanline(), ppu.get_dot()), (100, 20));
+    }
+
+    #[test]
+    fn catching_up_stops_at_the_end_of_the_frame() {
+        let mut ppu = ppu();
+        run_to(&mut ppu, 261, 335);
+
+        ppu.catch_up(9);
+        assert_eq!((ppu.get_scanline(), ppu.get_dot()), (261, 340));
+    }
+
+    // Runs sprite evaluation for scanline 100 with sprites at the given Y
+    // positions (and everything else in OAM off screen), and returns how many
+    // sprites it found and whether the overflow flag got set
+    fn evaluate(evaluation: SpriteEvaluation, oam: &[(usize, u8)]) -> (usize, bool) {
+        let mut ppu = ppu();
+        ppu.set_mask(0x18);
+        ppu.set_sprite_evaluation(evaluation);
+        ppu.primary_oam.fill(0xF0);
+        for &(address, value) in oam {
+            ppu.primary_oam[address] = value;
+        }
+
+        ppu.scanline = 99;
+        ppu.sprite_evaluation();
+
+        (ppu.sprites_found, ppu.status.spr_overflow() == 1)
+    }
+
+    #[test]
+    fn nine_sprites_on_a_line_overflow() {
+        let ten_sprites: Vec<(usize, u8)> = (0..10).map(|i| (i * 4, 100)).collect();
+
+        assert_eq!(evaluate(SpriteEvaluation::Limited, &ten_sprites), (8, true));
+        assert_eq!(evaluate(SpriteEvaluation::Authentic, &ten_sprites), (8, true));
+        // Every sprite is drawn, but the game still sees the flag
+        assert_eq!(evaluate(SpriteEvaluation::Unlimited, &ten_sprites), (10, true));
+        assert_eq!(evaluate(SpriteEvaluation::Limited, &ten_sprites[..8]), (8, false));
+    }
+
+    #[test]
+    fn authentic_overflow_checks_the_wrong_bytes() {
+        let mut oam: Vec<(usize, u8)> = (0..8).map(|i| (i * 4, 100)).collect();
+
+        // Sprite 9's tile number looks like a Y on the line to the broken
+        // search, though there are only 8 sprites on it
+        oam.push((9 * 4 + 1, 100));
+        assert_eq!(evaluate(SpriteEvaluation::Limited, &oam), (8, false));
+        assert_eq!(evaluate(SpriteEvaluation::Authentic, &oam), (8, true));
+
+        // And a real 9th sprite gets missed when the search is looking at the
+        // wrong byte of it
+        oam.pop();
+        oam.push((9 * 4, 100));
+        assert_eq!(evaluate(SpriteEvaluation::Limited, &oam), (8, true));
+        assert_eq!(evaluate(SpriteEvaluation::Authentic, &oam), (8, false));
+    }
+}
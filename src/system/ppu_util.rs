@@ -68,6 +68,14 @@ pub struct PpuMask {
     pub emph_blu: usize,
 }
 
+impl PpuMask {
+    /// The three emphasis bits together (red = 1, green = 2, blue = 4), for
+    /// picking colors out of a SystemPalette
+    pub fn emphasis(&self) -> usize {
+        self.emph_red() | (self.emph_grn() << 1) | (self.emph_blu() << 2)
+    }
+}
+
 // PPUSTATUS Register (read only)
 //     7  bit  0
 //     ---- ----
@@ -142,4 +150,33 @@ impl PpuScrollReg {
     pub fn with_nt_y(self, value: usize) -> PpuScrollReg {
         PpuScrollReg(((self.0 as usize & 0xF7FF) | ((value & 1) << 11)) as u16)
     }
-}
\ No newline at end of file
+}
+// What a game changed about the scroll position partway through drawing a
+// scanline, for the scroll split view (see Ppu2C02::scroll_splits)
+#[bitfield(u8)]
+#[derive(PartialEq)]
+pub struct ScrollSplit {
+    pub scroll_x: bool,
+    pub scroll_y: bool,
+    pub nametable: bool,
+    #[bits(5)]
+    __: u8,
+}
+
+impl ScrollSplit {
+    pub fn any(&self) -> bool {
+        self.0 != 0
+    }
+
+    // Which parts of the scroll position are different between two copies of
+    // the v/t register (and fine x)
+    pub fn between(before: (PpuScrollReg, u8), after: (PpuScrollReg, u8)) -> Self {
+        let (before, before_fine_x) = before;
+        let (after, after_fine_x) = after;
+
+        ScrollSplit::new()
+            .with_scroll_x(before.coarse_x() != after.coarse_x() || before_fine_x != after_fine_x)
+            .with_scroll_y(before.coarse_y() != after.coarse_y() || before.fine_y() != after.fine_y())
+            .with_nametable(before.nt_select() != after.nt_select())
+    }
+}
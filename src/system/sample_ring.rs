@@ -0,0 +1,172 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    Arc,
+};
+
+// Enough room for about 1.5 seconds of audio, which covers the biggest batches
+// plus the menu sound effects (which are pushed all at once)
+pub const SAMPLE_RING_CAPACITY: usize = 1 << 16;
+
+/// The queue shared between the emulator (which pushes samples) and the audio
+/// thread (which pops them), as handed out by NesAudioStream::new.
+pub type SampleQueue = Arc<SampleRing>;
+
+/*
+ON THE SAMPLE RING:
+
+The audio thread asks for samples one at a time, tens of thousands of times a
+second. It used to lock a Mutex<VecDeque> for every one of them, which meant the
+emulator thread would occasionally stall on the lock (and vice versa) right in
+the middle of a batch. This is a single-producer single-consumer ring buffer that
+needs no lock at all.
+
+`write` and `read` are counters that only ever go up, and a slot's index is
+the counter modulo the capacity. They're u64s rather than usizes, since a
+32-bit usize (like on wasm32) would wrap after half a day of audio at 96kHz,
+and a u64 won't in the lifetime of the program. The emulator is the only one that moves `write`, and the audio thread
+is the only one that moves `read`. Samples are stored as their f32 bit patterns
+in AtomicU32s, so the whole thing is safe code: a slot is written before `write`
+is published with Release ordering, and the reader loads `write` with Acquire
+before reading slots, so it always sees the finished samples.
+
+Clearing the queue (which the app does when pausing) is a bit tricky, since only
+the reader is allowed to move `read`. Instead the writer publishes a `clear_to`
+counter and the reader skips ahead to it the next time it reads. Anything pushed
+after the clear is still played, so menu sounds can be queued right after one.
+*/
+pub struct SampleRing {
+    slots: Box<[AtomicU32]>,
+    write: AtomicU64,
+    read: AtomicU64,
+    clear_to: AtomicU64,
+
+    // Number of times the audio thread has run out of samples, and whether it
+    // is out of samples right now (so a long dry spell only counts once)
+    underruns: AtomicU64,
+    starved: AtomicBool,
+    // Number of samples asked for while the ring was empty
+    missed: AtomicU64,
+}
+
+impl Default for SampleRing {
+    fn default() -> Self {
+        Self::new(SAMPLE_RING_CAPACITY)
+    }
+}
+
+impl std::fmt::Debug for SampleRing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SampleRing")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .field("underruns", &self.underruns())
+            .field("missed_samples", &self.missed_samples())
+            .finish()
+    }
+}
+
+impl SampleRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            write: AtomicU64::new(0),
+            read: AtomicU64::new(0),
+            clear_to: AtomicU64::new(0),
+
+            underruns: AtomicU64::new(0),
+            starved: AtomicBool::new(false),
+            missed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    // The oldest sample that's still waiting to be played
+    fn effective_read(&self) -> u64 {
+        std::cmp::max(self.read.load(Ordering::Acquire), self.clear_to.load(Ordering::Acquire))
+    }
+
+    // Where the sample a counter points at goes
+    fn slot(&self, counter: u64) -> usize {
+        (counter % self.capacity() as u64) as usize
+    }
+
+    /// Number of samples waiting to be played
+    pub fn len(&self) -> usize {
+        self.write.load(Ordering::Acquire).saturating_sub(self.effective_read()) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queues a sample. Returns false (and drops the sample) if the ring is full.
+    /// Must only be called from the producer side.
+    pub fn push(&self, sample: f32) -> bool {
+        let write = self.write.load(Ordering::Relaxed);
+
+        if (write - self.effective_read()) as usize >= self.capacity() {
+            return false;
+        }
+
+        self.slots[self.slot(write)].store(sample.to_bits(), Ordering::Relaxed);
+        self.write.store(write + 1, Ordering::Release);
+
+        true
+    }
+
+    /// Queues as many of the samples as fit. Returns how many were queued.
+    /// Must only be called from the producer side.
+    pub fn push_slice(&self, samples: &[f32]) -> usize {
+        let write = self.write.load(Ordering::Relaxed);
+        let free = self.capacity() - (write - self.effective_read()) as usize;
+        let count = std::cmp::min(free, samples.len());
+
+        for (i, sample) in samples[..count].iter().enumerate() {
+            self.slots[self.slot(write + i as u64)].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.write.store(write + count as u64, Ordering::Release);
+
+        count
+    }
+
+    /// Takes the oldest sample out of the ring, or None if it's empty. Must only
+    /// be called from the consumer side.
+    pub fn pop(&self) -> Option<f32> {
+        let read = self.effective_read();
+        let write = self.write.load(Ordering::Acquire);
+
+        if read >= write {
+            if !self.starved.swap(true, Ordering::Relaxed) {
+                self.underruns.fetch_add(1, Ordering::Relaxed);
+            }
+            self.missed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let sample = f32::from_bits(self.slots[self.slot(read)].load(Ordering::Relaxed));
+        self.read.store(read + 1, Ordering::Release);
+        self.starved.store(false, Ordering::Relaxed);
+
+        Some(sample)
+    }
+
+    /// Throws away every sample currently queued. Called from the producer side.
+    pub fn clear(&self) {
+        self.clear_to.store(self.write.load(Ordering::Relaxed), Ordering::Release);
+    }
+
+    /// Number of times the audio thread has asked for a sample and found the
+    /// ring empty (counting each dry spell once)
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Number of samples the audio thread has asked for and found the ring
+    /// empty, which it had to make up (see NesAudioStream)
+    pub fn missed_samples(&self) -> u64 {
+        self.missed.load(Ordering::Relaxed)
+    }
+}
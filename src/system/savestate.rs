@@ -0,0 +1,234 @@
+/*
+ON SAVE STATE SERIALIZATION:
+
+Save states are stored in a small hand-rolled binary format instead of pulling
+in serde + a binary encoder. Every piece of the system writes its own state as a
+"block", which looks like this:
+
+    magic    [u8; 4]   identifies what kind of block it is, e.g. b"MAPR"
+    version  u16       the layout version of the block's payload
+    length   u32       number of payload bytes that follow
+    payload  [u8]      the state itself, written with StateWriter
+
+All numbers are little endian. The version is bumped whenever the payload layout
+changes. Loaders must keep handling every older version they know of (migrating
+the old layout into the current struct fields), so save states made by older
+builds keep working. Because every block stores its own length, a reader can
+always skip over a block it doesn't care about.
+*/
+
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.bytes.push(val);
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.bytes.push(val as u8);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// usizes are always stored as 64 bits so states are portable between
+    /// 32 and 64 bit builds.
+    pub fn write_usize(&mut self, val: usize) {
+        self.write_u64(val as u64);
+    }
+
+    pub fn write_f32(&mut self, val: f32) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, val: f64) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// Writes a length-prefixed run of bytes (e.g. a RAM chip)
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Writes a complete block with the given magic, version, and payload.
+    pub fn write_block(&mut self, magic: &[u8; 4], version: u16, payload: &[u8]) {
+        self.bytes.extend_from_slice(magic);
+        self.write_u16(version);
+        self.write_bytes(payload);
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.pos + count > self.bytes.len() {
+            return Err(format!(
+                "Save state ended early (wanted {count} bytes at offset {}, only {} left)",
+                self.pos, self.bytes.len() - self.pos,
+            ));
+        }
+
+        let taken = &self.bytes[self.pos..self.pos + count];
+        self.pos += count;
+
+        Ok(taken)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, String> {
+        let val = self.read_u64()?;
+
+        usize::try_from(val).map_err(|_| format!("Value {val} is too big for this platform"))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, String> {
+        let bytes = self.take(4)?;
+        Ok(f32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, String> {
+        let bytes = self.take(8)?;
+        Ok(f64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Reads a length-prefixed run of bytes
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    /// Reads a length-prefixed run of bytes into an existing buffer, which
+    /// must be exactly the right size.
+    pub fn read_bytes_into(&mut self, dest: &mut [u8]) -> Result<(), String> {
+        let bytes = self.read_bytes()?;
+
+        if bytes.len() != dest.len() {
+            return Err(format!(
+                "Save state memory size mismatch (expected {} bytes, found {})",
+                dest.len(), bytes.len(),
+            ));
+        }
+
+        dest.copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    /// Reads a block header and payload, checking that the magic matches.
+    /// Returns the version and a reader over the block's payload.
+    pub fn read_block(&mut self, magic: &[u8; 4]) -> Result<(u16, StateReader<'a>), String> {
+        let found = self.take(4)?;
+        if found != magic {
+            return Err(format!(
+                "Expected a {} block in save state, found {}",
+                String::from_utf8_lossy(magic), String::from_utf8_lossy(found),
+            ));
+        }
+
+        let version = self.read_u16()?;
+        let payload = self.read_bytes()?;
+
+        Ok((version, StateReader::new(payload)))
+    }
+}
+
+/// A saved state of the whole system, made by Nes::save_state. It's a list of
+/// blocks in the format above, so it can be written to a file as is and read
+/// back with from_bytes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SaveState {
+    bytes: Vec<u8>,
+}
+
+impl SaveState {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The whole block (header included) with the given magic, if the state
+    /// has one. States made by older builds can be missing blocks that were
+    /// added later.
+    pub fn block(&self, magic: &[u8; 4]) -> Option<&[u8]> {
+        let mut start = 0;
+
+        // Each block's header is its magic, version and payload length
+        while start + 10 <= self.bytes.len() {
+            let length = u32::from_le_bytes(self.bytes[start + 6..start + 10].try_into().unwrap()) as usize;
+            let end = (start + 10).checked_add(length)?;
+
+            if end > self.bytes.len() {
+                return None;
+            }
+            if &self.bytes[start..start + 4] == magic {
+                return Some(&self.bytes[start..end]);
+            }
+
+            start = end;
+        }
+
+        None
+    }
+}
@@ -0,0 +1,88 @@
+use super::instructions::INSTRUCTION_TABLE;
+
+/*
+ON HOW UNSUPPORTED-FEATURE TELEMETRY WORKS:
+
+Some things a game can do aren't emulated, or are only roughly emulated, and
+when a game breaks it's rarely obvious whether one of them is to blame. The
+bus keeps a tally of every time the running game touches one, so the debug
+view can show a report for the game (and so it's easy to see which missing
+pieces real games actually run into, and work on those first):
+
+    unstable opcode   XAA, LXA, SHA, SHX, SHY and TAS. What they do depends on
+                      the CPU (and even its temperature), and they're emulated
+                      the way most NES CPUs behave.
+                      https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+    PPU EXT output    PPUCTRL bit 6 set, which has the PPU drive its EXT pins.
+                      Nothing is connected to them on an NES, so it isn't
+                      emulated (a real PPU set up this way can be damaged).
+                      https://www.nesdev.org/wiki/PPU_registers#PPUCTRL
+    unused APU reg    writes to $4009 and $400D, which no channel has a use for
+    CPU test reg      writes to $4018-$401F, the CPU's test mode registers,
+                      which are turned off on every retail console
+                      https://www.nesdev.org/wiki/CPU_test_mode
+    cart write        writes to cartridge space ($4020-$FFFF) the mapper has
+                      nothing at, like PRG RAM on a board without it, or the
+                      registers of a mapper other than the one in the header
+
+Uses are counted by opcode, by register, or for cart writes by 4KiB window
+(so a game using $6000-$7FFF as RAM that isn't there is two lines, not eight
+thousand), along with the frame each first happened on. The tally starts over
+when a game is loaded.
+*/
+
+/// Something a game did that isn't emulated (or is only roughly emulated)
+/// (see "ON HOW UNSUPPORTED-FEATURE TELEMETRY WORKS")
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum UnsupportedFeature {
+    UnstableOpcode { opcode: u8 },
+    PpuExtOutput,
+    UnusedApuRegister { address: u16 },
+    CpuTestRegister { address: u16 },
+    // The 4KiB window of cartridge space, like $6000 for $6000-$6FFF
+    CartWrite { window: u16 },
+}
+
+impl UnsupportedFeature {
+    /// A short description, like "cart write $6xxx" or "unstable opcode $8B XAA"
+    pub fn describe(&self) -> String {
+        match self {
+            Self::UnstableOpcode { opcode } => {
+                format!("unstable opcode ${opcode:02X} {}", INSTRUCTION_TABLE[*opcode as usize].name)
+            }
+            Self::PpuExtOutput => String::from("PPU EXT output"),
+            Self::UnusedApuRegister { address } => format!("unused APU reg ${address:04X}"),
+            Self::CpuTestRegister { address } => format!("CPU test reg ${address:04X}"),
+            Self::CartWrite { window } => format!("cart write ${:X}xxx", window >> 12),
+        }
+    }
+}
+
+/// How often the game did something unsupported
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct UnsupportedUse {
+    pub feature: UnsupportedFeature,
+    pub count: u64,
+    pub first_frame: u64,
+}
+
+/// The tally of unsupported things the running game has done
+#[derive(Default)]
+pub struct Telemetry {
+    // In the order they first happened
+    uses: Vec<UnsupportedUse>,
+}
+
+impl Telemetry {
+    /// Counts one use of a feature, on the given frame
+    pub fn record(&mut self, feature: UnsupportedFeature, frame: u64) {
+        match self.uses.iter_mut().find(|used| used.feature == feature) {
+            Some(used) => used.count += 1,
+            None => self.uses.push(UnsupportedUse { feature, count: 1, first_frame: frame }),
+        }
+    }
+
+    pub fn uses(&self) -> &[UnsupportedUse] {
+        &self.uses
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ON HOW THE TEST PATTERN WORKS:
+
+The test pattern is for checking the PPU's color output without needing a game
+that happens to use the right colors: a custom palette file, the color
+filters, and the emphasis and greyscale bits of PPUMASK all show up in it.
+
+There's no ROM to load, so the NES gets a tiny built-in cartridge instead (an
+NROM board whose program just loops forever and never turns rendering on), and
+the PPU is told to draw the pattern over each frame once the visible scanlines
+are done. Every pixel of the pattern is a palette index and a PPUMASK value,
+turned into a color by the same code the PPU uses for the game's pixels. From
+the top down:
+
+    rows 0-95     all 64 colors, 16 hues across and 4 brightness levels down,
+                  with no emphasis ($00-$0F at the top, $30-$3F at the bottom)
+    rows 96-191   the same 64 colors squashed into 8 bands, one for each
+                  combination of the emphasis bits: none, R, G, RG, B, RB, GB,
+                  RGB
+    rows 192-239  the 64 colors again with greyscale on, which should leave
+                  just the grey column's 4 levels repeated across
+
+https://www.nesdev.org/wiki/PPU_palettes
+https://www.nesdev.org/wiki/PPU_registers#PPUMASK
+*/
+
+/// The name the test pattern's cartridge goes by
+pub const ROM_NAME: &str = "testpattern";
+
+// Where things go in the 16KiB of PRG ROM, which is seen at $C000
+const PROGRAM: [u8; 4] = [
+    0x4C, 0x00, 0xC0, // JMP $C000
+    0x40,             // RTI, for the NMI and IRQ vectors
+];
+const PROGRAM_START: u16 = 0xC000;
+const RTI_ADDR: u16 = 0xC003;
+
+const PRG_SIZE: usize = 0x4000;
+const CHR_SIZE: usize = 0x2000;
+
+// Where each part of the pattern starts, and how tall its rows of colors are
+const BARS_TOP: usize = 0;
+const BARS_ROW_HEIGHT: usize = 24;
+const EMPHASIS_TOP: usize = 96;
+const EMPHASIS_ROW_HEIGHT: usize = 3;
+const GREYSCALE_TOP: usize = 192;
+const GREYSCALE_ROW_HEIGHT: usize = 12;
+
+// PPUMASK bits the pattern sets
+const MASK_GREYSCALE: u8 = 0x01;
+const MASK_EMPHASIS_SHIFT: u8 = 5;
+
+/// The .nes file for the cartridge the test pattern runs on: one 16KiB PRG
+/// bank holding a program that does nothing, and a blank CHR bank
+pub fn rom() -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1];
+    rom.resize(16, 0);
+
+    let mut prg = vec![0xEA; PRG_SIZE];
+    prg[..PROGRAM.len()].copy_from_slice(&PROGRAM);
+
+    // NMI, RESET and IRQ vectors at $FFFA
+    let vectors = [RTI_ADDR, PROGRAM_START, RTI_ADDR];
+    for (i, vector) in vectors.iter().enumerate() {
+        prg[PRG_SIZE - 6 + i * 2..PRG_SIZE - 4 + i * 2].copy_from_slice(&vector.to_le_bytes());
+    }
+
+    rom.extend(prg);
+    rom.extend(vec![0; CHR_SIZE]);
+
+    rom
+}
+
+/// The palette index and PPUMASK value a pixel of the test pattern is drawn
+/// with (see "ON HOW THE TEST PATTERN WORKS")
+pub fn pixel(x: usize, y: usize) -> (u8, u8) {
+    let hue = (x / 16) as u8;
+
+    if y < EMPHASIS_TOP {
+        let brightness = ((y - BARS_TOP) / BARS_ROW_HEIGHT) as u8;
+
+        ((brightness << 4) | hue, 0)
+    } else if y < GREYSCALE_TOP {
+        let band_height = 4 * EMPHASIS_ROW_HEIGHT;
+        let emphasis = ((y - EMPHASIS_TOP) / band_height) as u8;
+        let brightness = (((y - EMPHASIS_TOP) % band_height) / EMPHASIS_ROW_HEIGHT) as u8;
+
+        ((brightness << 4) | hue, emphasis << MASK_EMPHASIS_SHIFT)
+    } else {
+        let brightness = ((y - GREYSCALE_TOP) / GREYSCALE_ROW_HEIGHT) as u8;
+
+        ((brightness << 4) | hue, MASK_GREYSCALE)
+    }
+}
@@ -0,0 +1,84 @@
+// How many instructions the trace keeps around
+pub const TRACE_LOG_SIZE: usize = 100;
+
+/*
+ON THE INSTRUCTION TRACE
+
+The CPU keeps the last few instructions it ran, so that when something goes
+wrong (the emulator panics, or a game crashes into a JAM) there's a record of
+how it got there. Unlike the interrupt log it's always on: an entry is a
+handful of bytes written into a ring that never grows, which costs next to
+nothing next to running the instruction itself.
+
+Each entry is taken after the instruction is decoded but before it runs, so
+the registers are the ones it started with, the same as nestest.log and most
+other emulators' trace logs. The address is the one the addressing mode worked
+out (the branch target for branches), and is left out for instructions that
+don't touch memory.
+https://www.nesdev.org/wiki/Emulator_tests
+*/
+
+/// One instruction the CPU ran, with the registers from before it ran
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u8,
+    pub name: &'static str,
+    pub address: Option<u16>,
+    pub acc: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub status: u8,
+    // CPU clock the instruction started on
+    pub clock: u64,
+}
+
+impl TraceEntry {
+    /// The entry as one line of a trace log, like
+    /// "C5F5  A2  LDX        A:00 X:00 Y:00 P:24 SP:FD CYC:7"
+    pub fn line(&self) -> String {
+        let address = match self.address {
+            Some(address) => format!("${address:04X}"),
+            None => String::new(),
+        };
+
+        format!("{:04X}  {:02X}  {:<4} {:<5}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.pc, self.opcode, self.name, address, self.acc, self.x, self.y, self.status, self.sp, self.clock)
+    }
+}
+
+/// The most recent instructions (see "ON THE INSTRUCTION TRACE")
+pub struct TraceLog {
+    entries: [TraceEntry; TRACE_LOG_SIZE],
+    // Where the next entry goes, and how many of the entries are real
+    next: usize,
+    len: usize,
+}
+
+impl Default for TraceLog {
+    fn default() -> Self {
+        Self { entries: [TraceEntry::default(); TRACE_LOG_SIZE], next: 0, len: 0 }
+    }
+}
+
+impl TraceLog {
+    /// Adds an entry, pushing out the oldest one once the log is full
+    pub fn push(&mut self, entry: TraceEntry) {
+        self.entries[self.next] = entry;
+        self.next = (self.next + 1) % TRACE_LOG_SIZE;
+        self.len = (self.len + 1).min(TRACE_LOG_SIZE);
+    }
+
+    /// The logged instructions, oldest first
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        let start = (self.next + TRACE_LOG_SIZE - self.len) % TRACE_LOG_SIZE;
+
+        (0..self.len).map(|i| self.entries[(start + i) % TRACE_LOG_SIZE]).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+}
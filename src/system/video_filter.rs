@@ -0,0 +1,453 @@
+/*
+ON HOW VIDEO FILTERS WORK:
+
+The game view (the NES's 256x240 picture, with the ghost and overlays drawn on
+it) can go through a chain of video filters before it's shown. Each filter
+takes the RGBA frame the one before it made and makes a new one, which can be
+a different size: the scalers double it. The window's pixel buffer is resized
+to whatever comes out of the end of the chain, and scaled up to fit the window
+from there like always.
+
+The built-in filters, in the order they're applied:
+
+    NTSC          smears color sideways, like the NES's composite video
+                  signal does on a TV (an approximation, the signal itself
+                  isn't emulated)
+    Frame Blend   mixes each frame with the one before it, so sprites that a
+                  game flickers every other frame show up as see-through
+                  instead of flashing
+    Scale2x       doubles the picture, rounding off diagonal edges
+    Scanlines     doubles the picture, darkening every other line like the
+                  gaps between a CRT's scanlines
+    CRT Curve     bends the picture outwards like the glass of a CRT
+
+Filters are turned on and off on their own, and every setting is a percentage
+so the pause menu can make a page of sliders for any filter. Anything that
+implements VideoFilter can be added to a chain with FilterChain::push.
+*/
+
+/// A frame of RGBA pixels
+#[derive(Clone, PartialEq, Debug)]
+pub struct VideoFrame {
+    pub pixels: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// One of a filter's settings
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FilterSetting {
+    // The setting's key in the config file
+    pub id: &'static str,
+    pub name: &'static str,
+    // A percentage, from 0 to 100
+    pub value: usize,
+}
+
+/// A step in the video filter chain (see "ON HOW VIDEO FILTERS WORK")
+pub trait VideoFilter {
+    /// The filter's key in the config file
+    fn id(&self) -> &'static str;
+    /// The name shown in the menu
+    fn name(&self) -> &'static str;
+    /// The size of the frame that comes out for a frame of the given size
+    fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        (width, height)
+    }
+    /// Filters an RGBA frame of the given size
+    fn process(&mut self, input: &[u8], width: usize, height: usize) -> VideoFrame;
+    /// The filter's settings, in the order they're shown
+    fn settings(&self) -> Vec<FilterSetting> {
+        Vec::new()
+    }
+    /// Changes one of the settings (an index into settings) to a percentage
+    fn set_setting(&mut self, _index: usize, _value: usize) {}
+}
+
+/// The video filters the game view goes through, each of which can be on or
+/// off
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<(Box<dyn VideoFilter>, bool)>,
+}
+
+impl FilterChain {
+    /// Every built-in filter, in order and turned off
+    pub fn standard() -> Self {
+        let mut chain = Self::default();
+
+        chain.push(Box::new(NtscFilter::default()), false);
+        chain.push(Box::new(FrameBlendFilter::default()), false);
+        chain.push(Box::new(Scale2xFilter), false);
+        chain.push(Box::new(ScanlinesFilter::default()), false);
+        chain.push(Box::new(CrtCurveFilter::default()), false);
+
+        chain
+    }
+
+    /// Adds a filter to the end of the chain
+    pub fn push(&mut self, filter: Box<dyn VideoFilter>, enabled: bool) {
+        self.filters.push((filter, enabled));
+    }
+
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn filter(&self, index: usize) -> &dyn VideoFilter {
+        self.filters[index].0.as_ref()
+    }
+
+    pub fn filter_mut(&mut self, index: usize) -> &mut dyn VideoFilter {
+        self.filters[index].0.as_mut()
+    }
+
+    pub fn enabled(&self, index: usize) -> bool {
+        self.filters[index].1
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.filters[index].1 = enabled;
+    }
+
+    /// Whether any of the filters are on
+    pub fn is_active(&self) -> bool {
+        self.filters.iter().any(|(_, enabled)| *enabled)
+    }
+
+    /// The size of the frame that comes out of the chain for a frame of the
+    /// given size
+    pub fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        self.filters.iter()
+            .filter(|(_, enabled)| *enabled)
+            .fold((width, height), |(width, height), (filter, _)| filter.output_size(width, height))
+    }
+
+    /// Runs a frame through every filter that's on, in order
+    pub fn process(&mut self, input: &[u8], width: usize, height: usize) -> VideoFrame {
+        let mut frame = VideoFrame { pixels: input.to_vec(), width, height };
+
+        for (filter, _) in self.filters.iter_mut().filter(|(_, enabled)| *enabled) {
+            frame = filter.process(&frame.pixels, frame.width, frame.height);
+        }
+
+        frame
+    }
+}
+
+// RGB to YIQ and back, the color space NTSC sends its signal in
+// https://en.wikipedia.org/wiki/YIQ
+fn rgb_to_yiq(px: &[u8]) -> [f32; 3] {
+    let (r, g, b) = (px[0] as f32, px[1] as f32, px[2] as f32);
+
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        0.596 * r - 0.274 * g - 0.322 * b,
+        0.211 * r - 0.523 * g + 0.312 * b,
+    ]
+}
+
+fn yiq_to_rgb([y, i, q]: [f32; 3]) -> [u8; 3] {
+    [
+        (y + 0.956 * i + 0.621 * q).round().clamp(0.0, 255.0) as u8,
+        (y - 0.272 * i - 0.647 * q).round().clamp(0.0, 255.0) as u8,
+        (y - 1.106 * i + 1.703 * q).round().clamp(0.0, 255.0) as u8,
+    ]
+}
+
+/// Blurs the color (but barely the brightness) of each line sideways, since
+/// a composite signal has much less room for color than for brightness. The
+/// strength sets how far the color spreads, up to 4 pixels each way.
+pub struct NtscFilter {
+    strength: usize,
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        Self { strength: 50 }
+    }
+}
+
+impl VideoFilter for NtscFilter {
+    fn id(&self) -> &'static str {
+        "ntsc"
+    }
+
+    fn name(&self) -> &'static str {
+        "NTSC"
+    }
+
+    fn process(&mut self, input: &[u8], width: usize, height: usize) -> VideoFrame {
+        let radius = (self.strength + 12) / 25;
+        // How much of the brightness comes from the pixels either side
+        let luma_bleed = self.strength as f32 / 400.0;
+
+        let mut pixels = input.to_vec();
+        let mut line = vec![[0.0; 3]; width];
+
+        for y in 0..height {
+            let row = &input[y * width * 4..(y + 1) * width * 4];
+            for (x, yiq) in line.iter_mut().enumerate() {
+                *yiq = rgb_to_yiq(&row[x * 4..x * 4 + 4]);
+            }
+
+            for x in 0..width {
+                let left = x.saturating_sub(radius);
+                let right = (x + radius).min(width - 1);
+                let count = (right - left + 1) as f32;
+
+                let i = line[left..=right].iter().map(|yiq| yiq[1]).sum::<f32>() / count;
+                let q = line[left..=right].iter().map(|yiq| yiq[2]).sum::<f32>() / count;
+
+                let neighbors = line[x.saturating_sub(1)][0] + line[(x + 1).min(width - 1)][0];
+                let luma = line[x][0] * (1.0 - 2.0 * luma_bleed) + neighbors * luma_bleed;
+
+                let start = (y * width + x) * 4;
+                pixels[start..start + 3].copy_from_slice(&yiq_to_rgb([luma, i, q]));
+            }
+        }
+
+        VideoFrame { pixels, width, height }
+    }
+
+    fn settings(&self) -> Vec<FilterSetting> {
+        vec![FilterSetting { id: "strength", name: "Strength", value: self.strength }]
+    }
+
+    fn set_setting(&mut self, _index: usize, value: usize) {
+        self.strength = value.min(100);
+    }
+}
+
+/// Mixes each frame with the one before it. At 50% both count the same, so a
+/// sprite that's only drawn every other frame looks half see-through.
+pub struct FrameBlendFilter {
+    blend: usize,
+    last_frame: Vec<u8>,
+}
+
+impl Default for FrameBlendFilter {
+    fn default() -> Self {
+        Self { blend: 50, last_frame: Vec::new() }
+    }
+}
+
+impl VideoFilter for FrameBlendFilter {
+    fn id(&self) -> &'static str {
+        "frame_blend"
+    }
+
+    fn name(&self) -> &'static str {
+        "Frame Blend"
+    }
+
+    fn process(&mut self, input: &[u8], width: usize, height: usize) -> VideoFrame {
+        // Nothing to blend with on the first frame, or after the size changes
+        if self.last_frame.len() != input.len() {
+            self.last_frame = input.to_vec();
+        }
+
+        let last_weight = self.blend as u32;
+        let pixels = input.iter()
+            .zip(&self.last_frame)
+            .map(|(&now, &last)| ((now as u32 * (100 - last_weight) + last as u32 * last_weight + 50) / 100) as u8)
+            .collect();
+
+        self.last_frame.copy_from_slice(input);
+
+        VideoFrame { pixels, width, height }
+    }
+
+    fn settings(&self) -> Vec<FilterSetting> {
+        vec![FilterSetting { id: "blend", name: "Last Frame", value: self.blend }]
+    }
+
+    fn set_setting(&mut self, _index: usize, value: usize) {
+        self.blend = value.min(100);
+    }
+}
+
+/// Doubles the picture with the Scale2x algorithm, which fills in the corners
+/// of diagonal edges rather than making them stairs of big pixels
+/// https://www.scale2x.it/algorithm
+pub struct Scale2xFilter;
+
+impl VideoFilter for Scale2xFilter {
+    fn id(&self) -> &'static str {
+        "scale2x"
+    }
+
+    fn name(&self) -> &'static str {
+        "Scale2x"
+    }
+
+    fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        (width * 2, height * 2)
+    }
+
+    fn process(&mut self, input: &[u8], width: usize, height: usize) -> VideoFrame {
+        let pixel = |x: usize, y: usize| -> [u8; 4] {
+            let start = (y * width + x) * 4;
+            input[start..start + 4].try_into().unwrap()
+        };
+
+        let out_width = width * 2;
+        let mut pixels = vec![0; out_width * height * 2 * 4];
+
+        for y in 0..height {
+            for x in 0..width {
+                // The pixel, and the ones above, left, right and below it
+                let e = pixel(x, y);
+                let b = pixel(x, y.saturating_sub(1));
+                let d = pixel(x.saturating_sub(1), y);
+                let f = pixel((x + 1).min(width - 1), y);
+                let h = pixel(x, (y + 1).min(height - 1));
+
+                let corners = if b != h && d != f {
+                    [
+                        if d == b { d } else { e },
+                        if b == f { f } else { e },
+                        if d == h { d } else { e },
+                        if h == f { f } else { e },
+                    ]
+                } else {
+                    [e; 4]
+                };
+
+                for (i, corner) in corners.iter().enumerate() {
+                    let start = ((y * 2 + i / 2) * out_width + x * 2 + i % 2) * 4;
+                    pixels[start..start + 4].copy_from_slice(corner);
+                }
+            }
+        }
+
+        VideoFrame { pixels, width: out_width, height: height * 2 }
+    }
+}
+
+/// Doubles the picture, and darkens every second line by the intensity
+pub struct ScanlinesFilter {
+    intensity: usize,
+}
+
+impl Default for ScanlinesFilter {
+    fn default() -> Self {
+        Self { intensity: 40 }
+    }
+}
+
+impl VideoFilter for ScanlinesFilter {
+    fn id(&self) -> &'static str {
+        "scanlines"
+    }
+
+    fn name(&self) -> &'static str {
+        "Scanlines"
+    }
+
+    fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        (width * 2, height * 2)
+    }
+
+    fn process(&mut self, input: &[u8], width: usize, height: usize) -> VideoFrame {
+        let out_width = width * 2;
+        let mut pixels = vec![0; out_width * height * 2 * 4];
+        let brightness = (100 - self.intensity) as u32;
+
+        for y in 0..height {
+            for x in 0..width {
+                let px = &input[(y * width + x) * 4..(y * width + x) * 4 + 4];
+                let dark = [
+                    (px[0] as u32 * brightness / 100) as u8,
+                    (px[1] as u32 * brightness / 100) as u8,
+                    (px[2] as u32 * brightness / 100) as u8,
+                    px[3],
+                ];
+
+                for (line, color) in [(y * 2, px), (y * 2 + 1, &dark[..])] {
+                    let start = (line * out_width + x * 2) * 4;
+                    pixels[start..start + 4].copy_from_slice(color);
+                    pixels[start + 4..start + 8].copy_from_slice(color);
+                }
+            }
+        }
+
+        VideoFrame { pixels, width: out_width, height: height * 2 }
+    }
+
+    fn settings(&self) -> Vec<FilterSetting> {
+        vec![FilterSetting { id: "intensity", name: "Intensity", value: self.intensity }]
+    }
+
+    fn set_setting(&mut self, _index: usize, value: usize) {
+        self.intensity = value.min(100);
+    }
+}
+
+/// Bends the picture outwards from the middle, leaving the corners black like
+/// the rounded edges of a CRT
+pub struct CrtCurveFilter {
+    curvature: usize,
+}
+
+impl Default for CrtCurveFilter {
+    fn default() -> Self {
+        Self { curvature: 30 }
+    }
+}
+
+impl VideoFilter for CrtCurveFilter {
+    fn id(&self) -> &'static str {
+        "crt_curve"
+    }
+
+    fn name(&self) -> &'static str {
+        "CRT Curve"
+    }
+
+    fn process(&mut self, input: &[u8], width: usize, height: usize) -> VideoFrame {
+        // At 100%, the middle of each edge is pulled in by a tenth of the
+        // picture and the corners by a fifth
+        let k = self.curvature as f32 / 500.0;
+
+        let mut pixels = vec![0; input.len()];
+
+        for y in 0..height {
+            // Where the pixel is, from -1 to 1 across the picture
+            let v = (y as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+
+            for x in 0..width {
+                let u = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+
+                let src_u = u * (1.0 + k * v * v) * (1.0 + k);
+                let src_v = v * (1.0 + k * u * u) * (1.0 + k);
+
+                let start = (y * width + x) * 4;
+                if src_u.abs() >= 1.0 || src_v.abs() >= 1.0 {
+                    pixels[start + 3] = 0xFF;
+                    continue;
+                }
+
+                let src_x = ((src_u + 1.0) / 2.0 * width as f32) as usize;
+                let src_y = ((src_v + 1.0) / 2.0 * height as f32) as usize;
+                let src = (src_y * width + src_x) * 4;
+
+                pixels[start..start + 4].copy_from_slice(&input[src..src + 4]);
+            }
+        }
+
+        VideoFrame { pixels, width, height }
+    }
+
+    fn settings(&self) -> Vec<FilterSetting> {
+        vec![FilterSetting { id: "curvature", name: "Curvature", value: self.curvature }]
+    }
+
+    fn set_setting(&mut self, _index: usize, value: usize) {
+        self.curvature = value.min(100);
+    }
+}